@@ -1,5 +1,6 @@
 #[cfg(windows)]
 fn main() {
+	println!("cargo:rustc-check-cfg=cfg(tarpaulin)");
 	let mut res = winres::WindowsResource::new();
 	res.set_icon("./res/windows/application/icon_polaris_512.ico");
 	res.compile().unwrap();
@@ -7,4 +8,6 @@ fn main() {
 }
 
 #[cfg(unix)]
-fn main() {}
+fn main() {
+	println!("cargo:rustc-check-cfg=cfg(tarpaulin)");
+}
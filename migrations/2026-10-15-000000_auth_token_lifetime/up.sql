@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN auth_token_lifetime_seconds INTEGER NOT NULL DEFAULT 2592000;
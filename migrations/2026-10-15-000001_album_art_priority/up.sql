@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN index_album_art_priority TEXT NOT NULL DEFAULT 'embedded';
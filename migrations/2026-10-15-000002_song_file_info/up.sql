@@ -0,0 +1,3 @@
+ALTER TABLE songs ADD COLUMN file_modified INTEGER;
+ALTER TABLE songs ADD COLUMN file_size INTEGER;
+ALTER TABLE songs ADD COLUMN has_embedded_artwork INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE mount_points ADD COLUMN watch INTEGER NOT NULL DEFAULT 0;
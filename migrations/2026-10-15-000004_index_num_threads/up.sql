@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN index_num_threads INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+CREATE TEMPORARY TABLE misc_settings_backup(id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads);
+INSERT INTO misc_settings_backup SELECT id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads FROM misc_settings;
+DROP TABLE misc_settings;
+CREATE TABLE misc_settings (
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+	auth_secret BLOB NOT NULL DEFAULT (randomblob(32)),
+	index_sleep_duration_seconds INTEGER NOT NULL,
+	index_album_art_pattern TEXT NOT NULL,
+	auth_token_lifetime_seconds INTEGER NOT NULL DEFAULT 2592000,
+	index_album_art_priority TEXT NOT NULL DEFAULT 'embedded',
+	index_num_threads INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO misc_settings SELECT * FROM misc_settings_backup;
+DROP TABLE misc_settings_backup;
@@ -0,0 +1,12 @@
+ALTER TABLE users ADD COLUMN transcode_format TEXT;
+ALTER TABLE users ADD COLUMN transcode_max_bitrate INTEGER;
+
+CREATE TABLE clients (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	transcode_format TEXT,
+	transcode_max_bitrate INTEGER,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(owner, name) ON CONFLICT REPLACE
+);
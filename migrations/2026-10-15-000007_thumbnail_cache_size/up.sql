@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN thumbnail_cache_size_mb INTEGER NOT NULL DEFAULT 0;
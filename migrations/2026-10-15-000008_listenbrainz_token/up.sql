@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN listenbrainz_token TEXT;
@@ -0,0 +1,9 @@
+CREATE TABLE listens (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER NOT NULL,
+	path TEXT NOT NULL,
+	timestamp BIGINT NOT NULL,
+	completion INTEGER,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX listens_user_timestamp ON listens(user, timestamp);
@@ -0,0 +1,7 @@
+CREATE TABLE favorites (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER NOT NULL,
+	path TEXT NOT NULL,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(user, path) ON CONFLICT IGNORE
+);
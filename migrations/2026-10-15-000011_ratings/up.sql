@@ -0,0 +1,8 @@
+CREATE TABLE ratings (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER NOT NULL,
+	path TEXT NOT NULL,
+	rating INTEGER NOT NULL,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(user, path) ON CONFLICT REPLACE
+);
@@ -0,0 +1,8 @@
+CREATE TABLE smart_playlists (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	rules TEXT NOT NULL,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(owner, name) ON CONFLICT REPLACE
+);
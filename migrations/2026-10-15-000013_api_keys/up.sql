@@ -0,0 +1,11 @@
+CREATE TABLE api_keys (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	key_id TEXT NOT NULL,
+	key_hash TEXT NOT NULL,
+	creation_time BIGINT NOT NULL,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(owner, name),
+	UNIQUE(key_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN visible_mounts TEXT;
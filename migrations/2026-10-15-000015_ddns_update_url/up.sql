@@ -0,0 +1,2 @@
+ALTER TABLE ddns_config ADD COLUMN update_url TEXT NOT NULL DEFAULT '';
+ALTER TABLE ddns_config ADD COLUMN update_interval_seconds INTEGER NOT NULL DEFAULT 1800;
@@ -0,0 +1,13 @@
+CREATE TEMPORARY TABLE ddns_config_backup(id, host, username, password, update_url, update_interval_seconds);
+INSERT INTO ddns_config_backup SELECT id, host, username, password, update_url, update_interval_seconds FROM ddns_config;
+DROP TABLE ddns_config;
+CREATE TABLE ddns_config (
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+	host TEXT NOT NULL,
+	username TEXT NOT NULL,
+	password TEXT NOT NULL,
+	update_url TEXT NOT NULL DEFAULT '',
+	update_interval_seconds INTEGER NOT NULL DEFAULT 1800
+);
+INSERT INTO ddns_config SELECT * FROM ddns_config_backup;
+DROP TABLE ddns_config_backup;
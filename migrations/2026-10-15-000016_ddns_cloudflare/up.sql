@@ -0,0 +1,3 @@
+ALTER TABLE ddns_config ADD COLUMN cloudflare_api_token TEXT;
+ALTER TABLE ddns_config ADD COLUMN cloudflare_zone TEXT;
+ALTER TABLE ddns_config ADD COLUMN cloudflare_record TEXT;
@@ -0,0 +1,16 @@
+CREATE TEMPORARY TABLE ddns_config_backup(id, host, username, password, update_url, update_interval_seconds, cloudflare_api_token, cloudflare_zone, cloudflare_record);
+INSERT INTO ddns_config_backup SELECT id, host, username, password, update_url, update_interval_seconds, cloudflare_api_token, cloudflare_zone, cloudflare_record FROM ddns_config;
+DROP TABLE ddns_config;
+CREATE TABLE ddns_config (
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+	host TEXT NOT NULL,
+	username TEXT NOT NULL,
+	password TEXT NOT NULL,
+	update_url TEXT NOT NULL DEFAULT '',
+	update_interval_seconds INTEGER NOT NULL DEFAULT 1800,
+	cloudflare_api_token TEXT,
+	cloudflare_zone TEXT,
+	cloudflare_record TEXT
+);
+INSERT INTO ddns_config SELECT * FROM ddns_config_backup;
+DROP TABLE ddns_config_backup;
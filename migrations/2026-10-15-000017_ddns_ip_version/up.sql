@@ -0,0 +1 @@
+ALTER TABLE ddns_config ADD COLUMN ip_version TEXT NOT NULL DEFAULT 'v4';
@@ -0,0 +1 @@
+ALTER TABLE mount_points ADD COLUMN follow_symlinks INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+ALTER TABLE songs ADD COLUMN cue_source TEXT;
+ALTER TABLE songs ADD COLUMN cue_start INTEGER;
+ALTER TABLE songs ADD COLUMN cue_end INTEGER;
@@ -0,0 +1,48 @@
+CREATE TEMPORARY TABLE songs_backup(id, path, parent, track_number, disc_number, title, artist, album_artist, year, album, artwork, duration, lyricist, composer, genre, label, file_modified, file_size, has_embedded_artwork, cue_source, cue_start, cue_end, disc_total);
+INSERT INTO songs_backup SELECT id, path, parent, track_number, disc_number, title, artist, album_artist, year, album, artwork, duration, lyricist, composer, genre, label, file_modified, file_size, has_embedded_artwork, cue_source, cue_start, cue_end, disc_total FROM songs;
+DROP TABLE songs;
+CREATE TABLE songs (
+	id INTEGER PRIMARY KEY NOT NULL,
+	path TEXT NOT NULL,
+	parent TEXT NOT NULL,
+	track_number INTEGER,
+	disc_number INTEGER,
+	title TEXT,
+	artist TEXT,
+	album_artist TEXT,
+	year INTEGER,
+	album TEXT,
+	artwork TEXT,
+	duration INTEGER,
+	lyricist TEXT,
+	composer TEXT,
+	genre TEXT,
+	label TEXT,
+	file_modified INTEGER,
+	file_size INTEGER,
+	has_embedded_artwork INTEGER,
+	cue_source TEXT,
+	cue_start INTEGER,
+	cue_end INTEGER,
+	disc_total INTEGER,
+	UNIQUE(path) ON CONFLICT REPLACE
+);
+INSERT INTO songs SELECT * FROM songs_backup;
+DROP TABLE songs_backup;
+
+CREATE TEMPORARY TABLE misc_settings_backup(id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads, index_ignore_patterns, thumbnail_cache_size_mb);
+INSERT INTO misc_settings_backup SELECT id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads, index_ignore_patterns, thumbnail_cache_size_mb FROM misc_settings;
+DROP TABLE misc_settings;
+CREATE TABLE misc_settings (
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+	auth_secret BLOB NOT NULL DEFAULT (randomblob(32)),
+	index_sleep_duration_seconds INTEGER NOT NULL,
+	index_album_art_pattern TEXT NOT NULL,
+	auth_token_lifetime_seconds INTEGER NOT NULL DEFAULT 2592000,
+	index_album_art_priority TEXT NOT NULL DEFAULT 'embedded',
+	index_num_threads INTEGER NOT NULL DEFAULT 0,
+	index_ignore_patterns TEXT NOT NULL DEFAULT '[]',
+	thumbnail_cache_size_mb INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO misc_settings SELECT * FROM misc_settings_backup;
+DROP TABLE misc_settings_backup;
@@ -0,0 +1,3 @@
+ALTER TABLE songs ADD COLUMN compilation BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE songs ADD COLUMN compilation_tag BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE misc_settings ADD COLUMN index_group_compilations BOOLEAN NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+ALTER TABLE songs ADD COLUMN artist_sort TEXT;
+ALTER TABLE songs ADD COLUMN album_artist_sort TEXT;
+ALTER TABLE songs ADD COLUMN album_sort TEXT;
+ALTER TABLE songs ADD COLUMN title_sort TEXT;
@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN collation_locale TEXT NOT NULL DEFAULT '';
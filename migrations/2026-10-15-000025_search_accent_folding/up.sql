@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN search_accent_folding BOOLEAN NOT NULL DEFAULT 1;
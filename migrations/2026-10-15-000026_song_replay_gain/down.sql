@@ -0,0 +1,38 @@
+CREATE TEMPORARY TABLE songs_backup(id, path, parent, track_number, disc_number, title, artist, album_artist, year, album, artwork, duration, lyricist, composer, genre, label, file_modified, file_size, has_embedded_artwork, cue_source, cue_start, cue_end, disc_total, compilation, compilation_tag, conductor, artist_sort, album_artist_sort, album_sort, title_sort);
+INSERT INTO songs_backup SELECT id, path, parent, track_number, disc_number, title, artist, album_artist, year, album, artwork, duration, lyricist, composer, genre, label, file_modified, file_size, has_embedded_artwork, cue_source, cue_start, cue_end, disc_total, compilation, compilation_tag, conductor, artist_sort, album_artist_sort, album_sort, title_sort FROM songs;
+DROP TABLE songs;
+CREATE TABLE songs (
+	id INTEGER PRIMARY KEY NOT NULL,
+	path TEXT NOT NULL,
+	parent TEXT NOT NULL,
+	track_number INTEGER,
+	disc_number INTEGER,
+	title TEXT,
+	artist TEXT,
+	album_artist TEXT,
+	year INTEGER,
+	album TEXT,
+	artwork TEXT,
+	duration INTEGER,
+	lyricist TEXT,
+	composer TEXT,
+	genre TEXT,
+	label TEXT,
+	file_modified INTEGER,
+	file_size INTEGER,
+	has_embedded_artwork INTEGER,
+	cue_source TEXT,
+	cue_start INTEGER,
+	cue_end INTEGER,
+	disc_total INTEGER,
+	compilation BOOLEAN NOT NULL DEFAULT 0,
+	compilation_tag BOOLEAN NOT NULL DEFAULT 0,
+	conductor TEXT,
+	artist_sort TEXT,
+	album_artist_sort TEXT,
+	album_sort TEXT,
+	title_sort TEXT,
+	UNIQUE(path) ON CONFLICT REPLACE
+);
+INSERT INTO songs SELECT * FROM songs_backup;
+DROP TABLE songs_backup;
@@ -0,0 +1,4 @@
+ALTER TABLE songs ADD COLUMN track_gain REAL;
+ALTER TABLE songs ADD COLUMN track_peak REAL;
+ALTER TABLE songs ADD COLUMN album_gain REAL;
+ALTER TABLE songs ADD COLUMN album_peak REAL;
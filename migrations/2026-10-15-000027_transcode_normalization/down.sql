@@ -0,0 +1,55 @@
+CREATE TEMPORARY TABLE misc_settings_backup(id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads, index_ignore_patterns, thumbnail_cache_size_mb, index_group_compilations, collation_locale, search_accent_folding);
+INSERT INTO misc_settings_backup SELECT id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads, index_ignore_patterns, thumbnail_cache_size_mb, index_group_compilations, collation_locale, search_accent_folding FROM misc_settings;
+DROP TABLE misc_settings;
+CREATE TABLE misc_settings (
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+	auth_secret BLOB NOT NULL DEFAULT (randomblob(32)),
+	index_sleep_duration_seconds INTEGER NOT NULL,
+	index_album_art_pattern TEXT NOT NULL,
+	auth_token_lifetime_seconds INTEGER NOT NULL DEFAULT 2592000,
+	index_album_art_priority TEXT NOT NULL DEFAULT 'embedded',
+	index_num_threads INTEGER NOT NULL DEFAULT 0,
+	index_ignore_patterns TEXT NOT NULL DEFAULT '[]',
+	thumbnail_cache_size_mb INTEGER NOT NULL DEFAULT 0,
+	index_group_compilations BOOLEAN NOT NULL DEFAULT 1,
+	collation_locale TEXT NOT NULL DEFAULT '',
+	search_accent_folding BOOLEAN NOT NULL DEFAULT 1
+);
+INSERT INTO misc_settings SELECT * FROM misc_settings_backup;
+DROP TABLE misc_settings_backup;
+
+CREATE TEMPORARY TABLE clients_backup(id, owner, name, transcode_format, transcode_max_bitrate);
+INSERT INTO clients_backup SELECT id, owner, name, transcode_format, transcode_max_bitrate FROM clients;
+DROP TABLE clients;
+CREATE TABLE clients (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	transcode_format TEXT,
+	transcode_max_bitrate INTEGER,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(owner, name) ON CONFLICT REPLACE
+);
+INSERT INTO clients SELECT * FROM clients_backup;
+DROP TABLE clients_backup;
+
+CREATE TEMPORARY TABLE users_backup(id, name, password_hash, admin, lastfm_username, lastfm_session_key, web_theme_base, web_theme_accent, transcode_format, transcode_max_bitrate, listenbrainz_token, visible_mounts);
+INSERT INTO users_backup SELECT id, name, password_hash, admin, lastfm_username, lastfm_session_key, web_theme_base, web_theme_accent, transcode_format, transcode_max_bitrate, listenbrainz_token, visible_mounts FROM users;
+DROP TABLE users;
+CREATE TABLE users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL,
+	password_hash TEXT NOT NULL,
+	admin INTEGER NOT NULL,
+	lastfm_username TEXT,
+	lastfm_session_key TEXT,
+	web_theme_base TEXT,
+	web_theme_accent TEXT,
+	transcode_format TEXT,
+	transcode_max_bitrate INTEGER,
+	listenbrainz_token TEXT,
+	visible_mounts TEXT,
+	UNIQUE(name)
+);
+INSERT INTO users SELECT * FROM users_backup;
+DROP TABLE users_backup;
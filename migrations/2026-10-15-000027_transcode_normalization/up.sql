@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN transcode_normalization TEXT;
+ALTER TABLE clients ADD COLUMN transcode_normalization TEXT;
+ALTER TABLE misc_settings ADD COLUMN transcode_loudness_target INTEGER NOT NULL DEFAULT -18;
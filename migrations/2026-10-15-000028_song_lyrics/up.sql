@@ -0,0 +1,2 @@
+ALTER TABLE songs ADD COLUMN lyrics TEXT;
+ALTER TABLE songs ADD COLUMN has_embedded_lyrics BOOLEAN;
@@ -0,0 +1,5 @@
+ALTER TABLE songs ADD COLUMN musicbrainz_recording_id TEXT;
+ALTER TABLE songs ADD COLUMN musicbrainz_release_id TEXT;
+ALTER TABLE songs ADD COLUMN musicbrainz_artist_id TEXT;
+ALTER TABLE songs ADD COLUMN musicbrainz_album_artist_id TEXT;
+UPDATE songs SET file_modified = NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE metadata_lookups (
+	id INTEGER PRIMARY KEY NOT NULL,
+	path TEXT NOT NULL,
+	title TEXT,
+	artist TEXT,
+	album TEXT,
+	year INTEGER,
+	musicbrainz_recording_id TEXT,
+	musicbrainz_release_id TEXT,
+	musicbrainz_artist_id TEXT,
+	UNIQUE(path) ON CONFLICT REPLACE
+);
+ALTER TABLE misc_settings ADD COLUMN index_metadata_lookup BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE misc_settings ADD COLUMN acoustid_api_key TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,7 @@
+CREATE TABLE song_fingerprints (
+	id INTEGER PRIMARY KEY NOT NULL,
+	path TEXT NOT NULL,
+	file_modified INTEGER NOT NULL,
+	fingerprint BLOB NOT NULL,
+	UNIQUE(path) ON CONFLICT REPLACE
+);
@@ -0,0 +1,10 @@
+CREATE TABLE missing_songs (
+	id INTEGER PRIMARY KEY NOT NULL,
+	path TEXT NOT NULL,
+	title TEXT,
+	artist TEXT,
+	album TEXT,
+	missing_since BIGINT NOT NULL,
+	UNIQUE(path) ON CONFLICT IGNORE
+);
+ALTER TABLE misc_settings ADD COLUMN index_missing_grace_period_days INTEGER NOT NULL DEFAULT 30;
@@ -0,0 +1,2 @@
+ALTER TABLE misc_settings ADD COLUMN index_max_files_per_second INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE misc_settings ADD COLUMN index_pause_while_streaming BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN search_fuzzy BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+DROP TABLE play_queue_songs;
+DROP TABLE play_queues;
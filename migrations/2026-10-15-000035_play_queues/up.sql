@@ -0,0 +1,14 @@
+CREATE TABLE play_queues (
+	user INTEGER PRIMARY KEY NOT NULL,
+	current_track INTEGER,
+	position_seconds INTEGER NOT NULL DEFAULT 0,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE play_queue_songs (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER NOT NULL,
+	path TEXT NOT NULL,
+	ordering INTEGER NOT NULL,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,14 @@
+CREATE TABLE shares (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	token TEXT NOT NULL,
+	path TEXT,
+	playlist TEXT,
+	creation_time BIGINT NOT NULL,
+	expiration_time BIGINT,
+	allow_download BOOLEAN NOT NULL DEFAULT 0,
+	max_plays INTEGER,
+	play_count INTEGER NOT NULL DEFAULT 0,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(token)
+);
@@ -0,0 +1,28 @@
+CREATE TABLE podcasts (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	url TEXT NOT NULL,
+	title TEXT NOT NULL,
+	description TEXT,
+	artwork_url TEXT,
+	auto_download BOOLEAN NOT NULL DEFAULT 0,
+	last_refresh BIGINT,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(owner, url)
+);
+
+CREATE TABLE podcast_episodes (
+	id INTEGER PRIMARY KEY NOT NULL,
+	podcast INTEGER NOT NULL,
+	guid TEXT NOT NULL,
+	title TEXT NOT NULL,
+	description TEXT,
+	audio_url TEXT NOT NULL,
+	mime_type TEXT,
+	publication_date BIGINT,
+	duration INTEGER,
+	position_seconds INTEGER NOT NULL DEFAULT 0,
+	completed BOOLEAN NOT NULL DEFAULT 0,
+	FOREIGN KEY(podcast) REFERENCES podcasts(id) ON DELETE CASCADE,
+	UNIQUE(podcast, guid)
+);
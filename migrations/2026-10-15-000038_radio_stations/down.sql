@@ -0,0 +1 @@
+DROP TABLE radio_stations;
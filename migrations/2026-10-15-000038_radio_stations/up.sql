@@ -0,0 +1,8 @@
+CREATE TABLE radio_stations (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER,
+	name TEXT NOT NULL,
+	stream_url TEXT NOT NULL,
+	homepage_url TEXT,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+ALTER TABLE mount_points ADD COLUMN content_type TEXT NOT NULL DEFAULT 'music';
+
+CREATE TABLE audiobook_positions (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user INTEGER NOT NULL,
+	path TEXT NOT NULL,
+	position_seconds INTEGER NOT NULL,
+	update_time INTEGER NOT NULL,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(user, path)
+);
@@ -0,0 +1,11 @@
+CREATE TEMPORARY TABLE play_queues_backup(user, current_track, position_seconds);
+INSERT INTO play_queues_backup SELECT user, current_track, position_seconds FROM play_queues;
+DROP TABLE play_queues;
+CREATE TABLE play_queues (
+	user INTEGER PRIMARY KEY NOT NULL,
+	current_track INTEGER,
+	position_seconds INTEGER NOT NULL DEFAULT 0,
+	FOREIGN KEY(user) REFERENCES users(id) ON DELETE CASCADE
+);
+INSERT INTO play_queues SELECT * FROM play_queues_backup;
+DROP TABLE play_queues_backup;
@@ -0,0 +1,3 @@
+ALTER TABLE play_queues ADD COLUMN device TEXT;
+ALTER TABLE play_queues ADD COLUMN playing INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE play_queues ADD COLUMN update_time INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+DROP TABLE sync_playlists;
+DROP TABLE sync_songs;
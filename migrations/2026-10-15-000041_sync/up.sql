@@ -0,0 +1,20 @@
+CREATE TABLE sync_songs (
+	path TEXT PRIMARY KEY NOT NULL,
+	signature BIGINT NOT NULL,
+	created_revision BIGINT NOT NULL,
+	revision BIGINT NOT NULL,
+	removed INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX sync_songs_revision ON sync_songs(revision);
+
+CREATE TABLE sync_playlists (
+	id INTEGER PRIMARY KEY NOT NULL,
+	owner INTEGER NOT NULL,
+	name TEXT NOT NULL,
+	signature BIGINT NOT NULL,
+	created_revision BIGINT NOT NULL,
+	revision BIGINT NOT NULL,
+	removed INTEGER NOT NULL DEFAULT 0,
+	FOREIGN KEY(owner) REFERENCES users(id) ON DELETE CASCADE,
+	UNIQUE(owner, name)
+);
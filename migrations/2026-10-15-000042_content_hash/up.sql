@@ -0,0 +1,4 @@
+ALTER TABLE songs ADD COLUMN content_hash TEXT;
+ALTER TABLE missing_songs ADD COLUMN content_hash TEXT;
+CREATE INDEX songs_content_hash ON songs(content_hash);
+UPDATE songs SET file_modified = NULL;
@@ -0,0 +1,50 @@
+CREATE TEMPORARY TABLE songs_backup(id, path, parent, track_number, disc_number, title, artist, album_artist, year, album, artwork, duration, lyricist, composer, genre, label, file_modified, file_size, has_embedded_artwork, cue_source, cue_start, cue_end, disc_total, compilation, compilation_tag, conductor, artist_sort, album_artist_sort, album_sort, title_sort, track_gain, track_peak, album_gain, album_peak, lyrics, has_embedded_lyrics, musicbrainz_recording_id, musicbrainz_release_id, musicbrainz_artist_id, musicbrainz_album_artist_id, content_hash);
+INSERT INTO songs_backup SELECT id, path, parent, track_number, disc_number, title, artist, album_artist, year, album, artwork, duration, lyricist, composer, genre, label, file_modified, file_size, has_embedded_artwork, cue_source, cue_start, cue_end, disc_total, compilation, compilation_tag, conductor, artist_sort, album_artist_sort, album_sort, title_sort, track_gain, track_peak, album_gain, album_peak, lyrics, has_embedded_lyrics, musicbrainz_recording_id, musicbrainz_release_id, musicbrainz_artist_id, musicbrainz_album_artist_id, content_hash FROM songs;
+DROP TABLE songs;
+CREATE TABLE songs (
+	id INTEGER PRIMARY KEY NOT NULL,
+	path TEXT NOT NULL,
+	parent TEXT NOT NULL,
+	track_number INTEGER,
+	disc_number INTEGER,
+	title TEXT,
+	artist TEXT,
+	album_artist TEXT,
+	year INTEGER,
+	album TEXT,
+	artwork TEXT,
+	duration INTEGER,
+	lyricist TEXT,
+	composer TEXT,
+	genre TEXT,
+	label TEXT,
+	file_modified INTEGER,
+	file_size INTEGER,
+	has_embedded_artwork INTEGER,
+	cue_source TEXT,
+	cue_start INTEGER,
+	cue_end INTEGER,
+	disc_total INTEGER,
+	compilation BOOLEAN NOT NULL DEFAULT 0,
+	compilation_tag BOOLEAN NOT NULL DEFAULT 0,
+	conductor TEXT,
+	artist_sort TEXT,
+	album_artist_sort TEXT,
+	album_sort TEXT,
+	title_sort TEXT,
+	track_gain REAL,
+	track_peak REAL,
+	album_gain REAL,
+	album_peak REAL,
+	lyrics TEXT,
+	has_embedded_lyrics BOOLEAN,
+	musicbrainz_recording_id TEXT,
+	musicbrainz_release_id TEXT,
+	musicbrainz_artist_id TEXT,
+	musicbrainz_album_artist_id TEXT,
+	content_hash TEXT,
+	UNIQUE(path) ON CONFLICT REPLACE
+);
+INSERT INTO songs SELECT * FROM songs_backup;
+DROP TABLE songs_backup;
+CREATE INDEX songs_content_hash ON songs(content_hash);
@@ -0,0 +1,5 @@
+ALTER TABLE songs ADD COLUMN sample_rate INTEGER;
+ALTER TABLE songs ADD COLUMN encoder_delay INTEGER;
+ALTER TABLE songs ADD COLUMN encoder_padding INTEGER;
+ALTER TABLE songs ADD COLUMN sample_count BIGINT;
+UPDATE songs SET file_modified = NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE misc_settings ADD COLUMN bandwidth_limit_kbps INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE misc_settings ADD COLUMN bandwidth_user_limit_kbps INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE misc_settings ADD COLUMN bandwidth_stream_limit_kbps INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE misc_settings ADD COLUMN index_pregenerate_thumbnails BOOLEAN NOT NULL DEFAULT 0;
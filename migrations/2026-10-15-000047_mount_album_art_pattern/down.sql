@@ -0,0 +1,14 @@
+CREATE TEMPORARY TABLE mount_points_backup(id, source, name, watch, follow_symlinks, content_type);
+INSERT INTO mount_points_backup SELECT id, source, name, watch, follow_symlinks, content_type FROM mount_points;
+DROP TABLE mount_points;
+CREATE TABLE mount_points (
+	id INTEGER PRIMARY KEY NOT NULL,
+	source TEXT NOT NULL,
+	name TEXT NOT NULL,
+	watch INTEGER NOT NULL DEFAULT 0,
+	follow_symlinks INTEGER NOT NULL DEFAULT 0,
+	content_type TEXT NOT NULL DEFAULT 'music',
+	UNIQUE(name)
+);
+INSERT INTO mount_points SELECT * FROM mount_points_backup;
+DROP TABLE mount_points_backup;
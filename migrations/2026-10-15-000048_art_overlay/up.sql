@@ -0,0 +1 @@
+ALTER TABLE mount_points ADD COLUMN art_overlay TEXT;
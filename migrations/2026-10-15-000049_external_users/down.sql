@@ -0,0 +1,21 @@
+CREATE TEMPORARY TABLE users_backup(id, name, password_hash, admin, lastfm_username, lastfm_session_key, web_theme_base, web_theme_accent, transcode_format, transcode_max_bitrate, listenbrainz_token, visible_mounts, transcode_normalization);
+INSERT INTO users_backup SELECT id, name, password_hash, admin, lastfm_username, lastfm_session_key, web_theme_base, web_theme_accent, transcode_format, transcode_max_bitrate, listenbrainz_token, visible_mounts, transcode_normalization FROM users;
+DROP TABLE users;
+CREATE TABLE users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL,
+	password_hash TEXT NOT NULL,
+	admin INTEGER NOT NULL,
+	lastfm_username TEXT,
+	lastfm_session_key TEXT,
+	web_theme_base TEXT,
+	web_theme_accent TEXT,
+	transcode_format TEXT,
+	transcode_max_bitrate INTEGER,
+	listenbrainz_token TEXT,
+	visible_mounts TEXT,
+	transcode_normalization TEXT,
+	UNIQUE(name)
+);
+INSERT INTO users SELECT * FROM users_backup;
+DROP TABLE users_backup;
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN external BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,31 @@
+CREATE TEMPORARY TABLE misc_settings_backup(id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads, index_ignore_patterns, thumbnail_cache_size_mb, index_group_compilations, collation_locale, search_accent_folding, transcode_loudness_target, index_metadata_lookup, acoustid_api_key, index_missing_grace_period_days, index_max_files_per_second, index_pause_while_streaming, search_fuzzy, bandwidth_limit_kbps, bandwidth_user_limit_kbps, bandwidth_stream_limit_kbps, max_streams_per_user, index_pregenerate_thumbnails);
+INSERT INTO misc_settings_backup SELECT id, auth_secret, index_sleep_duration_seconds, index_album_art_pattern, auth_token_lifetime_seconds, index_album_art_priority, index_num_threads, index_ignore_patterns, thumbnail_cache_size_mb, index_group_compilations, collation_locale, search_accent_folding, transcode_loudness_target, index_metadata_lookup, acoustid_api_key, index_missing_grace_period_days, index_max_files_per_second, index_pause_while_streaming, search_fuzzy, bandwidth_limit_kbps, bandwidth_user_limit_kbps, bandwidth_stream_limit_kbps, max_streams_per_user, index_pregenerate_thumbnails FROM misc_settings;
+DROP TABLE misc_settings;
+CREATE TABLE misc_settings (
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 0),
+	auth_secret BLOB NOT NULL DEFAULT (randomblob(32)),
+	index_sleep_duration_seconds INTEGER NOT NULL,
+	index_album_art_pattern TEXT NOT NULL,
+	auth_token_lifetime_seconds INTEGER NOT NULL DEFAULT 2592000,
+	index_album_art_priority TEXT NOT NULL DEFAULT 'embedded',
+	index_num_threads INTEGER NOT NULL DEFAULT 0,
+	index_ignore_patterns TEXT NOT NULL DEFAULT '[]',
+	thumbnail_cache_size_mb INTEGER NOT NULL DEFAULT 0,
+	index_group_compilations BOOLEAN NOT NULL DEFAULT 1,
+	collation_locale TEXT NOT NULL DEFAULT '',
+	search_accent_folding BOOLEAN NOT NULL DEFAULT 1,
+	transcode_loudness_target INTEGER NOT NULL DEFAULT -18,
+	index_metadata_lookup BOOLEAN NOT NULL DEFAULT 0,
+	acoustid_api_key TEXT NOT NULL DEFAULT '',
+	index_missing_grace_period_days INTEGER NOT NULL DEFAULT 30,
+	index_max_files_per_second INTEGER NOT NULL DEFAULT 0,
+	index_pause_while_streaming BOOLEAN NOT NULL DEFAULT 0,
+	search_fuzzy BOOLEAN NOT NULL DEFAULT 0,
+	bandwidth_limit_kbps INTEGER NOT NULL DEFAULT 0,
+	bandwidth_user_limit_kbps INTEGER NOT NULL DEFAULT 0,
+	bandwidth_stream_limit_kbps INTEGER NOT NULL DEFAULT 0,
+	max_streams_per_user INTEGER NOT NULL DEFAULT 0,
+	index_pregenerate_thumbnails BOOLEAN NOT NULL DEFAULT 0
+);
+INSERT INTO misc_settings SELECT * FROM misc_settings_backup;
+DROP TABLE misc_settings_backup;
@@ -1,16 +1,47 @@
+use log::{info, warn};
 use std::fs;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::db::{self, DB};
 use crate::paths::Paths;
 
+pub mod artist_info;
+pub mod audiobook;
+pub mod bandwidth;
 pub mod config;
 pub mod ddns;
+pub mod dlna;
+pub mod download;
+pub mod duplicate;
+pub mod events;
+pub mod favorite;
+pub mod fingerprint;
+pub mod history;
+pub mod hls;
 pub mod index;
+pub mod jukebox;
 pub mod lastfm;
+pub mod ldap;
+pub mod listenbrainz;
+pub mod mpd;
+pub mod outbound;
+pub mod peaks;
 pub mod playlist;
+pub mod podcast;
+pub mod proxy_auth;
+pub mod queue;
+pub mod radio;
+pub mod rating;
+pub mod session;
 pub mod settings;
+pub mod share;
+pub mod smart_playlist;
+pub mod sync;
+pub mod throttle;
 pub mod thumbnail;
+pub mod tls;
+pub mod transcode;
 pub mod user;
 pub mod vfs;
 
@@ -26,23 +57,54 @@ pub enum Error {
 	#[error("Filesystem error for `{0}`: `{1}`")]
 	Io(PathBuf, std::io::Error),
 	#[error(transparent)]
+	ProxyAuth(#[from] proxy_auth::Error),
+	#[error(transparent)]
 	Settings(#[from] settings::Error),
 }
 
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
 #[derive(Clone)]
 pub struct App {
 	pub port: u16,
-	pub auth_secret: settings::AuthSecret,
+	pub shutdown_grace_period: Duration,
+	pub server_config: config::ServerConfig,
+	pub tls_config: Option<tls::Config>,
+	pub url_base: String,
+	pub config_file_path: Option<PathBuf>,
 	pub web_dir_path: PathBuf,
 	pub swagger_dir_path: PathBuf,
 	pub db: DB,
 	pub index: index::Index,
+	pub artist_info_manager: artist_info::Manager,
+	pub audiobook_manager: audiobook::Manager,
+	pub bandwidth_manager: bandwidth::Manager,
 	pub config_manager: config::Manager,
 	pub ddns_manager: ddns::Manager,
+	pub dlna_manager: dlna::Manager,
+	pub duplicate_manager: duplicate::Manager,
+	pub events_manager: events::Manager,
+	pub favorite_manager: favorite::Manager,
+	pub history_manager: history::Manager,
+	pub jukebox_manager: jukebox::Manager,
 	pub lastfm_manager: lastfm::Manager,
+	pub listenbrainz_manager: listenbrainz::Manager,
+	pub mpd_manager: mpd::Manager,
+	pub peaks_manager: peaks::Manager,
 	pub playlist_manager: playlist::Manager,
+	pub podcast_manager: podcast::Manager,
+	pub proxy_auth_manager: proxy_auth::Manager,
+	pub queue_manager: queue::Manager,
+	pub radio_manager: radio::Manager,
+	pub rating_manager: rating::Manager,
+	pub session_manager: session::Manager,
+	pub smart_playlist_manager: smart_playlist::Manager,
 	pub settings_manager: settings::Manager,
+	pub share_manager: share::Manager,
+	pub sync_manager: sync::Manager,
 	pub thumbnail_manager: thumbnail::Manager,
+	pub throttle_manager: throttle::Manager,
+	pub transcode_manager: transcode::Manager,
 	pub user_manager: user::Manager,
 	pub vfs_manager: vfs::Manager,
 }
@@ -59,44 +121,183 @@ impl App {
 		fs::create_dir_all(&thumbnails_dir_path)
 			.map_err(|e| Error::Io(thumbnails_dir_path.clone(), e))?;
 
+		let peaks_dir_path = paths.cache_dir_path.join("peaks");
+		fs::create_dir_all(&peaks_dir_path).map_err(|e| Error::Io(peaks_dir_path.clone(), e))?;
+
+		let artists_dir_path = paths.cache_dir_path.join("artists");
+		fs::create_dir_all(&artists_dir_path)
+			.map_err(|e| Error::Io(artists_dir_path.clone(), e))?;
+
+		let podcasts_dir_path = paths.cache_dir_path.join("podcasts");
+		fs::create_dir_all(&podcasts_dir_path)
+			.map_err(|e| Error::Io(podcasts_dir_path.clone(), e))?;
+
+		let transcodes_dir_path = paths.cache_dir_path.join("transcodes");
+		fs::create_dir_all(&transcodes_dir_path)
+			.map_err(|e| Error::Io(transcodes_dir_path.clone(), e))?;
+
 		let vfs_manager = vfs::Manager::new(db.clone());
 		let settings_manager = settings::Manager::new(db.clone());
 		let auth_secret = settings_manager.get_auth_secret()?;
 		let ddns_manager = ddns::Manager::new(db.clone());
-		let user_manager = user::Manager::new(db.clone(), auth_secret);
-		let index = index::Index::new(db.clone(), vfs_manager.clone(), settings_manager.clone());
+		let ldap_manager = ldap::Manager::new();
+		let user_manager = user::Manager::new(
+			db.clone(),
+			auth_secret.clone(),
+			settings_manager.clone(),
+			ldap_manager.clone(),
+		);
+		let events_manager = events::Manager::new();
+		let thumbnail_manager =
+			thumbnail::Manager::new(thumbnails_dir_path, settings_manager.clone());
+		let index = index::Index::new(
+			db.clone(),
+			vfs_manager.clone(),
+			settings_manager.clone(),
+			events_manager.clone(),
+			thumbnail_manager.clone(),
+		);
 		let config_manager = config::Manager::new(
 			settings_manager.clone(),
 			user_manager.clone(),
 			vfs_manager.clone(),
 			ddns_manager.clone(),
+			ldap_manager.clone(),
 		);
 		let playlist_manager = playlist::Manager::new(db.clone(), vfs_manager.clone());
-		let thumbnail_manager = thumbnail::Manager::new(thumbnails_dir_path);
+		let podcast_manager = podcast::Manager::new(db.clone(), podcasts_dir_path);
+		let history_manager = history::Manager::new(db.clone(), vfs_manager.clone());
+		let favorite_manager = favorite::Manager::new(db.clone(), vfs_manager.clone());
+		let queue_manager = queue::Manager::new(db.clone(), vfs_manager.clone());
+		let audiobook_manager = audiobook::Manager::new(
+			db.clone(),
+			index.clone(),
+			vfs_manager.clone(),
+			queue_manager.clone(),
+		);
+		let duplicate_manager = duplicate::Manager::new(db.clone(), vfs_manager.clone());
+		let rating_manager = rating::Manager::new(db.clone(), vfs_manager.clone());
+		let smart_playlist_manager = smart_playlist::Manager::new(db.clone(), vfs_manager.clone());
+		let share_manager = share::Manager::new(
+			db.clone(),
+			index.clone(),
+			vfs_manager.clone(),
+			playlist_manager.clone(),
+		);
+		let sync_manager = sync::Manager::new(db.clone(), vfs_manager.clone());
+		let peaks_manager = peaks::Manager::new(peaks_dir_path);
+		let throttle_manager = throttle::Manager::new();
+		let bandwidth_manager = bandwidth::Manager::new(settings_manager.clone());
+		let session_manager = session::Manager::new(settings_manager.clone());
+		let transcode_manager =
+			transcode::Manager::new(transcodes_dir_path, settings_manager.clone());
 		let lastfm_manager = lastfm::Manager::new(index.clone(), user_manager.clone());
+		let listenbrainz_manager = listenbrainz::Manager::new(index.clone(), user_manager.clone());
 
-		if let Some(config_path) = paths.config_file_path {
-			let config = config::Config::from_path(&config_path)?;
-			config_manager.apply(&config)?;
+		let mut proxy_auth_manager = proxy_auth::Manager::default();
+		let mut tls_config = None;
+		let mut jukebox_config = None;
+		let mut dlna_config = None;
+		let mut mpd_config = None;
+		let mut radio_config = None;
+		let mut artist_info_config = None;
+		let mut url_base = String::new();
+		let mut shutdown_grace_period = DEFAULT_SHUTDOWN_GRACE_PERIOD;
+		let mut server_config = config::ServerConfig::default();
+		if let Some(config_path) = &paths.config_file_path {
+			let config = config::Config::from_path(config_path)?;
+			config_manager.apply_file(&config)?;
+			url_base = config.url_base();
+			proxy_auth_manager = proxy_auth::Manager::new(config.proxy_auth.as_ref())?;
+			tls_config = config.tls;
+			jukebox_config = config.jukebox;
+			dlna_config = config.dlna;
+			mpd_config = config.mpd;
+			radio_config = config.radio;
+			artist_info_config = config.artist_info;
+			if let Some(seconds) = config.shutdown_grace_period {
+				shutdown_grace_period = Duration::from_secs(seconds);
+			}
+			server_config = config.server.unwrap_or_default();
+			let mut raw_config = config::Config::from_path_raw(config_path)?;
+			if raw_config.has_plaintext_passwords() {
+				info!("Replacing plaintext passwords in config file with password hashes");
+				raw_config.hash_passwords()?;
+				if let Err(e) = raw_config.write_to_path(config_path) {
+					warn!(
+						"Could not remove plaintext passwords from config file: {}",
+						e
+					);
+				}
+			}
 		}
 
-		let auth_secret = settings_manager.get_auth_secret()?;
+		let jukebox_manager = jukebox::Manager::new(
+			jukebox_config,
+			queue_manager.clone(),
+			vfs_manager.clone(),
+			events_manager.clone(),
+		);
+		let dlna_manager = dlna::Manager::new(
+			dlna_config,
+			&auth_secret,
+			port,
+			url_base.clone(),
+			index.clone(),
+		);
+		let radio_manager = radio::Manager::new(db.clone(), user_manager.clone(), radio_config);
+		let artist_info_manager =
+			artist_info::Manager::new(artists_dir_path, db.clone(), artist_info_config);
+		let mpd_manager = mpd::Manager::new(
+			mpd_config,
+			index.clone(),
+			queue_manager.clone(),
+			jukebox_manager.clone(),
+			events_manager.clone(),
+		);
 
 		Ok(Self {
 			port,
-			auth_secret,
+			shutdown_grace_period,
+			server_config,
+			tls_config,
+			url_base,
+			config_file_path: paths.config_file_path,
 			web_dir_path: paths.web_dir_path,
 			swagger_dir_path: paths.swagger_dir_path,
+			db,
 			index,
+			artist_info_manager,
+			audiobook_manager,
+			bandwidth_manager,
 			config_manager,
 			ddns_manager,
+			dlna_manager,
+			duplicate_manager,
+			events_manager,
+			favorite_manager,
+			history_manager,
+			jukebox_manager,
 			lastfm_manager,
+			listenbrainz_manager,
+			mpd_manager,
+			peaks_manager,
 			playlist_manager,
+			podcast_manager,
+			proxy_auth_manager,
+			queue_manager,
+			radio_manager,
+			rating_manager,
+			session_manager,
+			smart_playlist_manager,
 			settings_manager,
+			share_manager,
+			sync_manager,
 			thumbnail_manager,
+			throttle_manager,
+			transcode_manager,
 			user_manager,
 			vfs_manager,
-			db,
 		})
 	}
 }
@@ -0,0 +1,396 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::db::{self, songs, DB};
+
+const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+const FANART_API_URL: &str = "https://webservice.fanart.tv/v3/music";
+// Artists which could not be found are not looked up again for this long
+const MISS_RETRY_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+// Past this many remembered misses, the oldest ones are forgotten early
+const MAX_MISSES: usize = 10_000;
+const MAX_IMAGE_SIZE: u64 = 16 * 1024 * 1024;
+const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "png", "gif", "webp"];
+
+/// API keys of the services artist biographies and images are fetched from.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	pub lastfm_api_key: Option<String>,
+	pub fanart_api_key: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("No biography was found for this artist")]
+	BiographyNotFound,
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("No image was found for this artist")]
+	ImageNotFound,
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("No API key is configured to look up artists")]
+	NotConfigured,
+	#[error("Artist lookup failed with HTTP status code `{0}`")]
+	RequestFailed(u16),
+	#[error("Artist lookup failed due to a transport error")]
+	RequestTransport,
+}
+
+impl From<ureq::Error> for Error {
+	fn from(error: ureq::Error) -> Self {
+		match error {
+			ureq::Error::Status(code, _) => Error::RequestFailed(code),
+			ureq::Error::Transport(_) => Error::RequestTransport,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Biography {
+	// Both are HTML, as written on Last.fm
+	pub summary: String,
+	pub content: String,
+	pub url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LastFmResponse {
+	artist: Option<LastFmArtist>,
+}
+
+#[derive(Deserialize)]
+struct LastFmArtist {
+	mbid: Option<String>,
+	url: Option<String>,
+	bio: Option<LastFmBio>,
+}
+
+#[derive(Deserialize)]
+struct LastFmBio {
+	summary: String,
+	content: String,
+}
+
+#[derive(Deserialize)]
+struct FanartResponse {
+	#[serde(default)]
+	artistthumb: Vec<FanartImage>,
+}
+
+#[derive(Deserialize)]
+struct FanartImage {
+	url: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Lookup {
+	Biography,
+	Image,
+}
+
+// Fetches portraits and biographies of artists, and keeps them in the cache directory
+#[derive(Clone)]
+pub struct Manager {
+	artists_dir_path: PathBuf,
+	db: DB,
+	config: Config,
+	misses: Arc<Mutex<HashMap<(Lookup, String), Instant>>>,
+}
+
+impl Manager {
+	pub fn new(artists_dir_path: PathBuf, db: DB, config: Option<Config>) -> Self {
+		Self {
+			artists_dir_path,
+			db,
+			config: config.unwrap_or_default(),
+			misses: Arc::default(),
+		}
+	}
+
+	pub fn get_biography(&self, artist: &str) -> Result<Biography, Error> {
+		let path = self.get_cache_path(artist, "json");
+		if let Some(biography) = fs::read(&path)
+			.ok()
+			.and_then(|b| serde_json::from_slice(&b).ok())
+		{
+			return Ok(biography);
+		}
+		if self.config.lastfm_api_key.is_none() {
+			return Err(Error::NotConfigured);
+		}
+		self.check_miss(Lookup::Biography, artist, Error::BiographyNotFound)?;
+
+		let biography = self
+			.fetch_lastfm_artist(artist)?
+			.and_then(|a| {
+				let bio = a.bio?;
+				Some(Biography {
+					summary: bio.summary,
+					content: bio.content,
+					url: a.url,
+				})
+			})
+			.filter(|b| !b.content.trim().is_empty());
+		let Some(biography) = biography else {
+			self.record_miss(Lookup::Biography, artist);
+			return Err(Error::BiographyNotFound);
+		};
+		let json = serde_json::to_vec(&biography).unwrap_or_default();
+		self.write_cache_file(&path, &json)?;
+		Ok(biography)
+	}
+
+	/// Returns the path of a cached portrait of the artist, which is downloaded if needed.
+	pub fn get_image(&self, artist: &str) -> Result<PathBuf, Error> {
+		for extension in IMAGE_EXTENSIONS {
+			let path = self.get_cache_path(artist, extension);
+			if path.exists() {
+				return Ok(path);
+			}
+		}
+		if self.config.fanart_api_key.is_none() {
+			return Err(Error::NotConfigured);
+		}
+		self.check_miss(Lookup::Image, artist, Error::ImageNotFound)?;
+
+		let image = match self.find_musicbrainz_id(artist)? {
+			Some(mbid) => self.fetch_fanart_image(&mbid)?,
+			None => None,
+		};
+		let Some(image) = image else {
+			self.record_miss(Lookup::Image, artist);
+			return Err(Error::ImageNotFound);
+		};
+		let extension = match image::guess_format(&image) {
+			Ok(image::ImageFormat::Png) => "png",
+			Ok(image::ImageFormat::Gif) => "gif",
+			Ok(image::ImageFormat::WebP) => "webp",
+			_ => "jpg",
+		};
+		let path = self.get_cache_path(artist, extension);
+		self.write_cache_file(&path, &image)?;
+		Ok(path)
+	}
+
+	// fanart.tv only knows artists by their MusicBrainz ID, which is read from tags when
+	// possible and asked to Last.fm otherwise
+	fn find_musicbrainz_id(&self, artist: &str) -> Result<Option<String>, Error> {
+		let mut connection = self.db.connect()?;
+		let tagged_id = songs::table
+			.filter(songs::artist.eq(artist))
+			.filter(songs::musicbrainz_artist_id.is_not_null())
+			.select(songs::musicbrainz_artist_id)
+			.first::<Option<String>>(&mut connection)
+			.optional()?
+			.flatten();
+		if tagged_id.is_some() {
+			return Ok(tagged_id);
+		}
+		if self.config.lastfm_api_key.is_none() {
+			return Ok(None);
+		}
+		Ok(self
+			.fetch_lastfm_artist(artist)?
+			.and_then(|a| a.mbid)
+			.filter(|id| !id.is_empty()))
+	}
+
+	fn fetch_lastfm_artist(&self, artist: &str) -> Result<Option<LastFmArtist>, Error> {
+		let api_key = self.config.lastfm_api_key.as_deref().unwrap_or_default();
+		let response = ureq::get(LASTFM_API_URL)
+			.query("method", "artist.getinfo")
+			.query("artist", artist)
+			.query("autocorrect", "1")
+			.query("api_key", api_key)
+			.query("format", "json")
+			.call();
+		let response = match response {
+			// Unknown artists are reported as errors in the response body
+			Err(ureq::Error::Status(400 | 404, _)) => return Ok(None),
+			response => response?,
+		};
+		let response: LastFmResponse = response
+			.into_string()
+			.ok()
+			.and_then(|s| serde_json::from_str(&s).ok())
+			.ok_or(Error::RequestTransport)?;
+		Ok(response.artist)
+	}
+
+	fn fetch_fanart_image(&self, mbid: &str) -> Result<Option<Vec<u8>>, Error> {
+		let api_key = self.config.fanart_api_key.as_deref().unwrap_or_default();
+		let response = ureq::get(&format!("{}/{}", FANART_API_URL, mbid))
+			.query("api_key", api_key)
+			.call();
+		let response = match response {
+			Err(ureq::Error::Status(404, _)) => return Ok(None),
+			response => response?,
+		};
+		let response: FanartResponse = response
+			.into_string()
+			.ok()
+			.and_then(|s| serde_json::from_str(&s).ok())
+			.ok_or(Error::RequestTransport)?;
+		let Some(thumb) = response.artistthumb.first() else {
+			return Ok(None);
+		};
+		let mut image = Vec::new();
+		ureq::get(&thumb.url)
+			.call()?
+			.into_reader()
+			.take(MAX_IMAGE_SIZE)
+			.read_to_end(&mut image)
+			.map_err(|_| Error::RequestTransport)?;
+		Ok(Some(image))
+	}
+
+	fn check_miss(&self, lookup: Lookup, artist: &str, error: Error) -> Result<(), Error> {
+		let misses = self.misses.lock().unwrap();
+		match misses.get(&(lookup, normalize(artist))) {
+			Some(at) if at.elapsed() < MISS_RETRY_DELAY => Err(error),
+			_ => Ok(()),
+		}
+	}
+
+	fn record_miss(&self, lookup: Lookup, artist: &str) {
+		let mut misses = self.misses.lock().unwrap();
+		misses.retain(|_, at| at.elapsed() < MISS_RETRY_DELAY);
+		if misses.len() >= MAX_MISSES {
+			let oldest = misses
+				.iter()
+				.min_by_key(|(_, at)| **at)
+				.map(|(key, _)| key.clone());
+			if let Some(oldest) = oldest {
+				misses.remove(&oldest);
+			}
+		}
+		misses.insert((lookup, normalize(artist)), Instant::now());
+	}
+
+	fn get_cache_path(&self, artist: &str, extension: &str) -> PathBuf {
+		let mut hasher = DefaultHasher::new();
+		normalize(artist).hash(&mut hasher);
+		self.artists_dir_path
+			.join(format!("{:016x}.{}", hasher.finish(), extension))
+	}
+
+	fn write_cache_file(&self, path: &Path, content: &[u8]) -> Result<(), Error> {
+		fs::create_dir_all(&self.artists_dir_path)
+			.map_err(|e| Error::Io(self.artists_dir_path.clone(), e))?;
+		fs::write(path, content).map_err(|e| Error::Io(path.to_owned(), e))
+	}
+}
+
+fn normalize(artist: &str) -> String {
+	artist.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	fn build_manager(test_name: &str, config: Option<Config>) -> Manager {
+		let ctx = test::ContextBuilder::new(test_name.to_owned()).build();
+		let artists_dir_path = ctx.test_directory.join("artists");
+		Manager::new(artists_dir_path, ctx.db, config)
+	}
+
+	#[test]
+	fn requires_api_keys() {
+		let manager = build_manager(&test_name!(), None);
+		assert!(matches!(
+			manager.get_biography("Khemmis"),
+			Err(Error::NotConfigured)
+		));
+		assert!(matches!(
+			manager.get_image("Khemmis"),
+			Err(Error::NotConfigured)
+		));
+	}
+
+	#[test]
+	fn serves_cached_results() {
+		let manager = build_manager(&test_name!(), None);
+		let biography = Biography {
+			summary: "Doom metal band".to_owned(),
+			content: "Doom metal band from Denver".to_owned(),
+			url: None,
+		};
+		let json = serde_json::to_vec(&biography).unwrap();
+		let path = manager.get_cache_path("Khemmis", "json");
+		manager.write_cache_file(&path, &json).unwrap();
+		let image_path = manager.get_cache_path("Khemmis", "png");
+		manager.write_cache_file(&image_path, b"image").unwrap();
+
+		// Names are matched regardless of case
+		assert_eq!(manager.get_biography("khemmis ").unwrap(), biography);
+		assert_eq!(manager.get_image("KHEMMIS").unwrap(), image_path);
+	}
+
+	#[test]
+	fn misses_are_remembered() {
+		let manager = build_manager(&test_name!(), None);
+		manager.record_miss(Lookup::Image, "Khemmis");
+		assert!(manager
+			.check_miss(Lookup::Image, "khemmis", Error::ImageNotFound)
+			.is_err());
+		assert!(manager
+			.check_miss(Lookup::Biography, "Khemmis", Error::BiographyNotFound)
+			.is_ok());
+	}
+
+	#[test]
+	fn misses_are_bounded() {
+		let manager = build_manager(&test_name!(), None);
+		for i in 0..MAX_MISSES + 10 {
+			manager.record_miss(Lookup::Image, &format!("Artist {i}"));
+		}
+		assert_eq!(manager.misses.lock().unwrap().len(), MAX_MISSES);
+		assert!(manager
+			.check_miss(Lookup::Image, "Artist 0", Error::ImageNotFound)
+			.is_ok());
+		assert!(manager
+			.check_miss(
+				Lookup::Image,
+				&format!("Artist {}", MAX_MISSES + 9),
+				Error::ImageNotFound
+			)
+			.is_err());
+	}
+
+	#[test]
+	fn parses_service_responses() {
+		let lastfm: LastFmResponse = serde_json::from_str(
+			r#"{"artist":{"name":"Khemmis","mbid":"abc","url":"https://www.last.fm/music/Khemmis",
+			"bio":{"summary":"Short","content":"Long"}}}"#,
+		)
+		.unwrap();
+		let artist = lastfm.artist.unwrap();
+		assert_eq!(artist.mbid.as_deref(), Some("abc"));
+		assert_eq!(artist.bio.unwrap().content, "Long");
+
+		let not_found: LastFmResponse =
+			serde_json::from_str(r#"{"error":6,"message":"The artist could not be found"}"#)
+				.unwrap();
+		assert!(not_found.artist.is_none());
+
+		let fanart: FanartResponse = serde_json::from_str(
+			r#"{"name":"Khemmis","artistthumb":[{"id":"1","url":"https://example.com/a.jpg"}]}"#,
+		)
+		.unwrap();
+		assert_eq!(fanart.artistthumb[0].url, "https://example.com/a.jpg");
+	}
+}
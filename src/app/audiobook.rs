@@ -0,0 +1,311 @@
+use diesel::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::index::{Chapter, Index, QueryError};
+use crate::app::{queue, vfs};
+use crate::db::{self, audiobook_positions, users, DB};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("`{0}` is not in an audiobook mount")]
+	NotAnAudiobook(PathBuf),
+	#[error(transparent)]
+	Query(#[from] QueryError),
+	#[error(transparent)]
+	Queue(#[from] queue::Error),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+/// Where a user stopped listening to an audiobook file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+	pub path: PathBuf,
+	pub position_seconds: u32,
+	pub update_time: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Audiobook {
+	pub path: PathBuf,
+	pub position_seconds: u32,
+	pub chapters: Vec<Chapter>,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	index: Index,
+	vfs_manager: vfs::Manager,
+	queue_manager: queue::Manager,
+}
+
+impl Manager {
+	pub fn new(
+		db: DB,
+		index: Index,
+		vfs_manager: vfs::Manager,
+		queue_manager: queue::Manager,
+	) -> Self {
+		Self {
+			db,
+			index,
+			vfs_manager,
+			queue_manager,
+		}
+	}
+
+	pub fn get_audiobook(&self, username: &str, virtual_path: &Path) -> Result<Audiobook, Error> {
+		let real_path = self.get_real_path(username, virtual_path)?;
+		let chapters = self.index.get_chapters(&real_path)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let position_seconds: Option<i32> = audiobook_positions::table
+			.filter(audiobook_positions::user.eq(user_id))
+			.filter(audiobook_positions::path.eq(real_path.to_string_lossy()))
+			.select(audiobook_positions::position_seconds)
+			.first(&mut connection)
+			.optional()?;
+		Ok(Audiobook {
+			path: virtual_path.to_owned(),
+			position_seconds: position_seconds.unwrap_or_default() as u32,
+			chapters,
+		})
+	}
+
+	pub fn set_position(
+		&self,
+		username: &str,
+		virtual_path: &Path,
+		position_seconds: u32,
+	) -> Result<(), Error> {
+		let real_path = self.get_real_path(username, virtual_path)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		diesel::replace_into(audiobook_positions::table)
+			.values((
+				audiobook_positions::user.eq(user_id),
+				audiobook_positions::path.eq(real_path.to_string_lossy()),
+				audiobook_positions::position_seconds.eq(position_seconds as i32),
+				audiobook_positions::update_time.eq(now()),
+			))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
+	/// Audiobook files a user has started, most recently listened first.
+	pub fn list_positions(&self, username: &str) -> Result<Vec<Position>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let rows: Vec<(String, i32, i64)> = audiobook_positions::table
+			.filter(audiobook_positions::user.eq(user_id))
+			.order(audiobook_positions::update_time.desc())
+			.select((
+				audiobook_positions::path,
+				audiobook_positions::position_seconds,
+				audiobook_positions::update_time,
+			))
+			.load(&mut connection)?;
+		// Positions in files that are no longer reachable are kept, in case the mount comes back
+		Ok(rows
+			.into_iter()
+			.filter_map(|(path, position_seconds, update_time)| {
+				let path = vfs.real_to_virtual(Path::new(&path)).ok()?;
+				vfs.is_audiobook(&path).then_some(Position {
+					path,
+					position_seconds: position_seconds as u32,
+					update_time,
+				})
+			})
+			.collect())
+	}
+
+	/// Bookmarks the current track of a user's play queue, if it is an audiobook.
+	pub fn record_queue_position(&self, username: &str) -> Result<(), Error> {
+		let queue = self.queue_manager.get_queue(username)?;
+		let Some(song) = queue.current_track.and_then(|i| queue.songs.get(i)) else {
+			return Ok(());
+		};
+		match self.set_position(username, Path::new(&song.path), queue.position_seconds) {
+			Err(Error::NotAnAudiobook(_)) => Ok(()),
+			r => r,
+		}
+	}
+
+	fn get_real_path(&self, username: &str, virtual_path: &Path) -> Result<PathBuf, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		if !vfs.is_audiobook(virtual_path) {
+			return Err(Error::NotAnAudiobook(virtual_path.to_owned()));
+		}
+		Ok(vfs.virtual_to_real(virtual_path)?)
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+fn now() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use id3::TagLike;
+	use std::fs;
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const BOOKS_MOUNT_NAME: &str = "books";
+	const MUSIC_MOUNT_NAME: &str = "music";
+
+	fn build_context(test_name: String) -> (test::Context, Manager) {
+		let builder = test::ContextBuilder::new(test_name);
+		let books_directory = builder.test_directory.join("books");
+		fs::create_dir_all(&books_directory).unwrap();
+		let book_path = books_directory.join("book.mp3");
+		fs::copy(
+			"test-data/small-collection/Khemmis/Hunted/05 - Hunted.mp3",
+			&book_path,
+		)
+		.unwrap();
+		let mut tag = id3::Tag::read_from_path(&book_path).unwrap();
+		for (id, title, start, end) in [
+			("ch1", "Part Two", 60_000, 120_000),
+			("ch0", "Part One", 0, 60_000),
+		] {
+			let mut chapter = id3::frame::Chapter {
+				element_id: id.to_owned(),
+				start_time: start,
+				end_time: end,
+				start_offset: 0xffffffff,
+				end_offset: 0xffffffff,
+				frames: Vec::new(),
+			};
+			chapter.set_title(title);
+			tag.add_frame(chapter);
+		}
+		tag.write_to_path(&book_path, id3::Version::Id3v24).unwrap();
+
+		let ctx = builder
+			.user(TEST_USER, "password", false)
+			.mount_dir(vfs::MountDir {
+				name: BOOKS_MOUNT_NAME.to_owned(),
+				source: books_directory.to_string_lossy().into_owned(),
+				content_type: vfs::ContentType::Audiobook,
+				..Default::default()
+			})
+			.mount(MUSIC_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+		let manager = Manager::new(
+			ctx.db.clone(),
+			ctx.index.clone(),
+			ctx.vfs_manager.clone(),
+			ctx.queue_manager.clone(),
+		);
+		(ctx, manager)
+	}
+
+	fn book_path() -> PathBuf {
+		[BOOKS_MOUNT_NAME, "book.mp3"].iter().collect()
+	}
+
+	#[test]
+	fn reads_chapters() {
+		let (_ctx, manager) = build_context(test_name!());
+		let audiobook = manager.get_audiobook(TEST_USER, &book_path()).unwrap();
+		assert_eq!(audiobook.position_seconds, 0);
+		assert_eq!(
+			audiobook.chapters,
+			vec![
+				Chapter {
+					title: Some("Part One".to_owned()),
+					start: 0,
+					end: Some(60_000),
+				},
+				Chapter {
+					title: Some("Part Two".to_owned()),
+					start: 60_000,
+					end: Some(120_000),
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn positions_are_saved_per_file() {
+		let (_ctx, manager) = build_context(test_name!());
+		manager.set_position(TEST_USER, &book_path(), 10).unwrap();
+		manager.set_position(TEST_USER, &book_path(), 42).unwrap();
+		assert_eq!(
+			manager
+				.get_audiobook(TEST_USER, &book_path())
+				.unwrap()
+				.position_seconds,
+			42
+		);
+		let positions = manager.list_positions(TEST_USER).unwrap();
+		assert_eq!(positions.len(), 1);
+		assert_eq!(positions[0].path, book_path());
+		assert_eq!(positions[0].position_seconds, 42);
+	}
+
+	#[test]
+	fn music_is_not_bookmarked() {
+		let (ctx, manager) = build_context(test_name!());
+		let song: PathBuf = [MUSIC_MOUNT_NAME, "Khemmis", "Hunted", "05 - Hunted.mp3"]
+			.iter()
+			.collect();
+		assert!(matches!(
+			manager.set_position(TEST_USER, &song, 10),
+			Err(Error::NotAnAudiobook(_))
+		));
+
+		ctx.queue_manager
+			.append(TEST_USER, &[song, book_path()])
+			.unwrap();
+		ctx.queue_manager
+			.set_current_track(TEST_USER, Some(0), 10)
+			.unwrap();
+		manager.record_queue_position(TEST_USER).unwrap();
+		assert!(manager.list_positions(TEST_USER).unwrap().is_empty());
+
+		ctx.queue_manager
+			.set_current_track(TEST_USER, Some(1), 25)
+			.unwrap();
+		manager.record_queue_position(TEST_USER).unwrap();
+		let positions = manager.list_positions(TEST_USER).unwrap();
+		assert_eq!(positions[0].path, book_path());
+		assert_eq!(positions[0].position_seconds, 25);
+	}
+
+	#[test]
+	fn random_albums_exclude_audiobooks() {
+		let (ctx, _manager) = build_context(test_name!());
+		let albums = ctx.index.get_random_albums(TEST_USER, 100).unwrap();
+		assert!(!albums.is_empty());
+		assert!(albums
+			.iter()
+			.all(|a| !Path::new(&a.path).starts_with(BOOKS_MOUNT_NAME)));
+	}
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::app::settings::{self, BandwidthLimits};
+
+// Streams may get ahead of their budget by this much, so players can fill their buffer quickly
+// when playback starts or resumes
+const BURST_DURATION: Duration = Duration::from_secs(2);
+
+struct Limiter {
+	kbps: u32,
+	// Sending more data is allowed from this instant
+	next_send: Mutex<Instant>,
+}
+
+impl Limiter {
+	fn new(kbps: u32, now: Instant) -> Self {
+		Self {
+			kbps,
+			next_send: Mutex::new(now.checked_sub(BURST_DURATION).unwrap_or(now)),
+		}
+	}
+
+	fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+		let bytes_per_second = self.kbps as f64 * 1000.0 / 8.0;
+		let mut next_send = self.next_send.lock().unwrap();
+		// Time spent idle only carries over up to the burst duration
+		let earliest = now.checked_sub(BURST_DURATION).unwrap_or(now);
+		let send_at = (*next_send).max(earliest);
+		*next_send = send_at + Duration::from_secs_f64(bytes as f64 / bytes_per_second);
+		send_at.saturating_duration_since(now)
+	}
+
+	// Whether the limiter has its full burst available again
+	fn is_idle(&self, now: Instant) -> bool {
+		let earliest = now.checked_sub(BURST_DURATION).unwrap_or(now);
+		*self.next_send.lock().unwrap() <= earliest
+	}
+}
+
+#[derive(Default)]
+struct State {
+	total: Option<Arc<Limiter>>,
+	users: HashMap<String, Arc<Limiter>>,
+}
+
+impl State {
+	// Limiters are replaced when their rate changes in the settings
+	fn get_total(&mut self, kbps: Option<u32>, now: Instant) -> Option<Arc<Limiter>> {
+		let Some(kbps) = kbps else {
+			self.total = None;
+			return None;
+		};
+		let limiter = match &self.total {
+			Some(limiter) if limiter.kbps == kbps => limiter.clone(),
+			_ => Arc::new(Limiter::new(kbps, now)),
+		};
+		self.total = Some(limiter.clone());
+		Some(limiter)
+	}
+
+	fn get_user(
+		&mut self,
+		username: &str,
+		kbps: Option<u32>,
+		now: Instant,
+	) -> Option<Arc<Limiter>> {
+		// Users without streams in progress are only referenced from here, and can be forgotten
+		// once they used up none of their budget
+		self.users
+			.retain(|_, limiter| Arc::strong_count(limiter) > 1 || !limiter.is_idle(now));
+		let Some(kbps) = kbps else {
+			self.users.remove(username);
+			return None;
+		};
+		let limiter = match self.users.get(username) {
+			Some(limiter) if limiter.kbps == kbps => limiter.clone(),
+			_ => Arc::new(Limiter::new(kbps, now)),
+		};
+		self.users.insert(username.to_owned(), limiter.clone());
+		Some(limiter)
+	}
+}
+
+/// Budget of a single response, shared with the other streams of the same user and with all
+/// other streams.
+pub struct Stream {
+	limiters: Vec<Arc<Limiter>>,
+}
+
+impl Stream {
+	/// Accounts for bytes about to be sent, and returns how long to wait before sending them.
+	pub fn reserve(&self, bytes: usize) -> Duration {
+		self.reserve_at(bytes, Instant::now())
+	}
+
+	fn reserve_at(&self, bytes: usize, now: Instant) -> Duration {
+		self.limiters
+			.iter()
+			.map(|l| l.reserve(bytes as u64, now))
+			.max()
+			.unwrap_or_default()
+	}
+}
+
+// Keeps listeners on fast connections from using up all the upload bandwidth of the server
+#[derive(Clone)]
+pub struct Manager {
+	settings_manager: settings::Manager,
+	state: Arc<Mutex<State>>,
+}
+
+impl Manager {
+	pub fn new(settings_manager: settings::Manager) -> Self {
+		Self {
+			settings_manager,
+			state: Arc::default(),
+		}
+	}
+
+	/// Returns the budget of a new stream, or `None` when streams are not limited. Streams
+	/// which do not belong to a user, like those of shared links, only count towards the total.
+	pub fn open_stream(&self, username: Option<&str>) -> Result<Option<Stream>, settings::Error> {
+		let limits = self.settings_manager.get_bandwidth_limits()?;
+		Ok(self.open_stream_at(username, limits, Instant::now()))
+	}
+
+	fn open_stream_at(
+		&self,
+		username: Option<&str>,
+		limits: BandwidthLimits,
+		now: Instant,
+	) -> Option<Stream> {
+		let mut state = self.state.lock().unwrap();
+		let total = state.get_total(limits.total, now);
+		let user = username.and_then(|u| state.get_user(u, limits.per_user, now));
+		let stream = limits
+			.per_stream
+			.map(|kbps| Arc::new(Limiter::new(kbps, now)));
+		let limiters: Vec<_> = [total, user, stream].into_iter().flatten().collect();
+		(!limiters.is_empty()).then_some(Stream { limiters })
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	// 8 kbps, so that a second of budget is 1000 bytes
+	const KBPS: u32 = 8;
+
+	fn manager() -> Manager {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		Manager::new(ctx.settings_manager)
+	}
+
+	#[test]
+	fn unlimited_streams_have_no_budget() {
+		let manager = manager();
+		let stream =
+			manager.open_stream_at(Some("alice"), BandwidthLimits::default(), Instant::now());
+		assert!(stream.is_none());
+	}
+
+	#[test]
+	fn streams_are_limited() {
+		let manager = manager();
+		let limits = BandwidthLimits {
+			per_stream: Some(KBPS),
+			..Default::default()
+		};
+		let now = Instant::now();
+		let stream = manager.open_stream_at(Some("alice"), limits, now).unwrap();
+		assert_eq!(stream.reserve_at(2000, now), Duration::ZERO);
+		assert_eq!(stream.reserve_at(1000, now), Duration::ZERO);
+		assert_eq!(stream.reserve_at(1000, now), Duration::from_secs(1));
+
+		// Other streams have their own budget
+		let other_stream = manager.open_stream_at(Some("alice"), limits, now).unwrap();
+		assert_eq!(other_stream.reserve_at(2000, now), Duration::ZERO);
+	}
+
+	#[test]
+	fn streams_of_a_user_share_their_budget() {
+		let manager = manager();
+		let limits = BandwidthLimits {
+			per_user: Some(KBPS),
+			..Default::default()
+		};
+		let now = Instant::now();
+		let first = manager.open_stream_at(Some("alice"), limits, now).unwrap();
+		assert_eq!(first.reserve_at(3000, now), Duration::ZERO);
+		drop(first);
+
+		// The budget used by streams which ended still counts
+		let second = manager.open_stream_at(Some("alice"), limits, now).unwrap();
+		let other_user = manager.open_stream_at(Some("bob"), limits, now).unwrap();
+		assert_eq!(second.reserve_at(1000, now), Duration::from_secs(1));
+		assert_eq!(other_user.reserve_at(2000, now), Duration::ZERO);
+
+		// Shared links do not belong to any user
+		assert!(manager.open_stream_at(None, limits, now).is_none());
+	}
+
+	#[test]
+	fn all_streams_share_the_total_budget() {
+		let manager = manager();
+		let limits = BandwidthLimits {
+			total: Some(KBPS),
+			..Default::default()
+		};
+		let now = Instant::now();
+		let alice = manager.open_stream_at(Some("alice"), limits, now).unwrap();
+		let shared_link = manager.open_stream_at(None, limits, now).unwrap();
+		assert_eq!(alice.reserve_at(3000, now), Duration::ZERO);
+		assert_eq!(shared_link.reserve_at(1000, now), Duration::from_secs(1));
+	}
+
+	#[test]
+	fn idle_time_only_carries_over_a_burst() {
+		let manager = manager();
+		let limits = BandwidthLimits {
+			per_stream: Some(KBPS),
+			..Default::default()
+		};
+		let now = Instant::now();
+		let stream = manager.open_stream_at(Some("alice"), limits, now).unwrap();
+		let later = now + Duration::from_secs(60);
+		assert_eq!(stream.reserve_at(2500, later), Duration::ZERO);
+		assert_eq!(stream.reserve_at(500, later), Duration::from_millis(500));
+	}
+}
@@ -1,8 +1,17 @@
+use log::{error, info, LevelFilter};
+use regex::Regex;
 use serde::Deserialize;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use crate::app::{ddns, settings, user, vfs};
+use crate::app::{
+	artist_info, ddns, dlna, index::Index, jukebox, ldap, mpd, proxy_auth, radio, settings, tls,
+	user, vfs,
+};
+
+const CONFIG_FILE_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -11,10 +20,16 @@ pub enum Error {
 	#[error("Filesystem error for `{0}`: `{1}`")]
 	Io(PathBuf, std::io::Error),
 	#[error(transparent)]
+	Ldap(#[from] ldap::Error),
+	#[error("Environment variable `{0}` referenced in config file is not set")]
+	MissingEnvironmentVariable(String),
+	#[error(transparent)]
 	Settings(#[from] settings::Error),
 	#[error(transparent)]
 	Toml(#[from] toml::de::Error),
 	#[error(transparent)]
+	TomlEdit(#[from] toml_edit::TomlError),
+	#[error(transparent)]
 	User(#[from] user::Error),
 	#[error(transparent)]
 	Vfs(#[from] vfs::Error),
@@ -24,21 +39,230 @@ pub enum Error {
 pub struct Config {
 	pub settings: Option<settings::NewSettings>,
 	pub mount_dirs: Option<Vec<vfs::MountDir>>,
+	#[serde(alias = "ddns")]
 	pub ydns: Option<ddns::Config>,
 	pub users: Option<Vec<user::NewUser>>,
+	// Logins are checked against this directory first, then against local users
+	pub ldap: Option<ldap::Config>,
+	// Only read on startup, renewed certificates are picked up without a restart
+	pub tls: Option<tls::Config>,
+	// Only read on startup
+	pub proxy_auth: Option<proxy_auth::Config>,
+	// Only read on startup
+	pub jukebox: Option<jukebox::Config>,
+	// Only read on startup
+	pub dlna: Option<dlna::Config>,
+	// Only read on startup
+	pub mpd: Option<mpd::Config>,
+	// Only read on startup
+	pub radio: Option<radio::Config>,
+	// Only read on startup
+	pub artist_info: Option<artist_info::Config>,
+	// Path prefix under which all routes are served, only read on startup
+	pub url_base: Option<String>,
+	// Only read on startup, command line arguments take precedence
+	pub logging: Option<LoggingConfig>,
+	// Seconds given to in-flight requests and index updates to complete on shutdown
+	pub shutdown_grace_period: Option<u64>,
+	// Only read on startup
+	pub server: Option<ServerConfig>,
+}
+
+// Tuning of the HTTP server, unset values keep the defaults of actix-web
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct ServerConfig {
+	// Seconds an idle connection is kept open for more requests, 0 to close it after each response
+	pub keep_alive: Option<u64>,
+	// Seconds given to clients to send the headers of a request, 0 to wait forever
+	pub request_timeout: Option<u64>,
+	// Seconds given to clients to acknowledge the end of a connection, 0 to wait forever
+	pub disconnect_timeout: Option<u64>,
+	pub max_body_size_mb: Option<usize>,
+	pub max_connections: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct LoggingConfig {
+	pub level: Option<LevelFilter>,
+	// Only log messages whose target starts with one of these, e.g. `polaris::app::index`
+	#[serde(default)]
+	pub targets: Vec<String>,
+	pub file_path: Option<PathBuf>,
 }
 
 impl Config {
 	pub fn from_path(path: &Path) -> Result<Config, Error> {
-		let mut config_file =
-			std::fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
-		let mut config_file_content = String::new();
-		config_file
-			.read_to_string(&mut config_file_content)
-			.map_err(|e| Error::Io(path.to_owned(), e))?;
-		let config = toml::de::from_str::<Self>(&config_file_content)?;
+		let mut value = read_toml(path)?;
+		substitute_env_vars(&mut value)?;
+		let mut config: Config = value.try_into()?;
+		if let Some(config_dir) = path.parent() {
+			config.resolve_mount_sources(config_dir);
+		}
 		Ok(config)
 	}
+
+	// Reads the config file without substituting `${ENV_VAR}` references
+	pub fn from_path_raw(path: &Path) -> Result<Config, Error> {
+		Ok(read_toml(path)?.try_into()?)
+	}
+
+	// Relative mount sources and art overlays are relative to the config file, so a config file
+	// and music sharing a removable drive keep working wherever that drive is mounted
+	fn resolve_mount_sources(&mut self, config_dir: &Path) {
+		for mount_dir in self.mount_dirs.iter_mut().flatten() {
+			if Path::new(&mount_dir.source).is_relative() {
+				mount_dir.source = config_dir.join(&mount_dir.source).to_string_lossy().into();
+			}
+			if let Some(art_overlay) = &mut mount_dir.art_overlay {
+				if Path::new(art_overlay).is_relative() {
+					*art_overlay = config_dir.join(&*art_overlay).to_string_lossy().into();
+				}
+			}
+		}
+	}
+
+	// Writes the password hashes of this config in place of the plaintext passwords of the file
+	// it was read from. Everything else in the file, comments and formatting included, is kept.
+	pub fn write_to_path(&self, path: &Path) -> Result<(), Error> {
+		let content = std::fs::read_to_string(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+		let mut document = content.parse::<toml_edit::Document>()?;
+		let users = self.users.iter().flatten();
+		match document.get_mut("users") {
+			Some(toml_edit::Item::ArrayOfTables(tables)) => {
+				for (table, user) in tables.iter_mut().zip(users) {
+					replace_password(table, user);
+				}
+			}
+			Some(toml_edit::Item::Value(toml_edit::Value::Array(array))) => {
+				for (value, user) in array.iter_mut().zip(users) {
+					if let Some(table) = value.as_inline_table_mut() {
+						replace_password(table, user);
+					}
+				}
+			}
+			_ => (),
+		}
+		std::fs::write(path, document.to_string()).map_err(|e| Error::Io(path.to_owned(), e))
+	}
+
+	// Returns the URL base with a leading slash and no trailing slash, or an empty string
+	pub fn url_base(&self) -> String {
+		let url_base = self
+			.url_base
+			.as_deref()
+			.unwrap_or_default()
+			.trim_matches('/');
+		if url_base.is_empty() {
+			String::new()
+		} else {
+			format!("/{}", url_base)
+		}
+	}
+
+	pub fn has_plaintext_passwords(&self) -> bool {
+		self.users
+			.iter()
+			.flatten()
+			.any(|u| is_plaintext_password(&u.password))
+	}
+
+	// Replaces plaintext passwords with their hashes, so they can be written back to disk.
+	// Passwords injected via environment variables are left untouched.
+	pub fn hash_passwords(&mut self) -> Result<(), Error> {
+		for user in self.users.iter_mut().flatten() {
+			if is_plaintext_password(&user.password) {
+				user.hash_password()?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read_toml(path: &Path) -> Result<toml::Value, Error> {
+	let mut config_file = std::fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+	let mut config_file_content = String::new();
+	config_file
+		.read_to_string(&mut config_file_content)
+		.map_err(|e| Error::Io(path.to_owned(), e))?;
+	Ok(toml::de::from_str::<toml::Value>(&config_file_content)?)
+}
+
+fn env_var_regex() -> Regex {
+	Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap()
+}
+
+fn replace_password(table: &mut dyn toml_edit::TableLike, user: &user::NewUser) {
+	let (true, Some(password_hash)) = (user.password.is_empty(), &user.password_hash) else {
+		return;
+	};
+	if table.get("name").and_then(|n| n.as_str()) != Some(user.name.as_str()) {
+		return;
+	}
+	table.remove("password");
+	table.insert("password_hash", toml_edit::value(password_hash.as_str()));
+}
+
+fn is_plaintext_password(password: &str) -> bool {
+	!password.is_empty() && !env_var_regex().is_match(password)
+}
+
+fn substitute_env_vars(value: &mut toml::Value) -> Result<(), Error> {
+	match value {
+		toml::Value::String(s) => {
+			let regex = env_var_regex();
+			let mut substituted = String::new();
+			let mut last_match_end = 0;
+			for captures in regex.captures_iter(s) {
+				let whole_match = captures.get(0).unwrap();
+				let var_name = &captures[1];
+				let var_value = std::env::var(var_name)
+					.map_err(|_| Error::MissingEnvironmentVariable(var_name.to_owned()))?;
+				substituted.push_str(&s[last_match_end..whole_match.start()]);
+				substituted.push_str(&var_value);
+				last_match_end = whole_match.end();
+			}
+			substituted.push_str(&s[last_match_end..]);
+			*s = substituted;
+		}
+		toml::Value::Array(values) => {
+			for v in values {
+				substitute_env_vars(v)?;
+			}
+		}
+		toml::Value::Table(table) => {
+			for (_, v) in table.iter_mut() {
+				substitute_env_vars(v)?;
+			}
+		}
+		_ => (),
+	}
+	Ok(())
+}
+
+// Sections of the config file which apply while the server is running
+#[derive(Clone, Copy, Default)]
+struct FileSections {
+	settings: bool,
+	mount_dirs: bool,
+	ydns: bool,
+	users: bool,
+	ldap: bool,
+}
+
+impl FileSections {
+	fn of(config: &Config) -> Self {
+		Self {
+			settings: config.settings.is_some(),
+			mount_dirs: config.mount_dirs.is_some(),
+			ydns: config.ydns.is_some(),
+			users: config.users.is_some(),
+			ldap: config.ldap.is_some(),
+		}
+	}
 }
 
 #[derive(Clone)]
@@ -47,6 +271,8 @@ pub struct Manager {
 	user_manager: user::Manager,
 	vfs_manager: vfs::Manager,
 	ddns_manager: ddns::Manager,
+	ldap_manager: ldap::Manager,
+	file_sections: Arc<Mutex<FileSections>>,
 }
 
 impl Manager {
@@ -55,15 +281,85 @@ impl Manager {
 		user_manager: user::Manager,
 		vfs_manager: vfs::Manager,
 		ddns_manager: ddns::Manager,
+		ldap_manager: ldap::Manager,
 	) -> Self {
 		Self {
 			settings_manager,
 			user_manager,
 			vfs_manager,
 			ddns_manager,
+			ldap_manager,
+			file_sections: Arc::default(),
+		}
+	}
+
+	pub fn begin_watching(&self, config_path: PathBuf, index: Index) {
+		let watcher = self.clone();
+		std::thread::spawn(move || {
+			watcher.watch(&config_path, &index);
+		});
+	}
+
+	fn watch(&self, config_path: &Path, index: &Index) {
+		let mut last_modified = get_modified_time(config_path);
+		loop {
+			std::thread::sleep(CONFIG_FILE_POLL_INTERVAL);
+			let modified = get_modified_time(config_path);
+			if modified == last_modified {
+				continue;
+			}
+			last_modified = modified;
+			info!("Reloading config file `{}`", config_path.display());
+			if let Err(e) = self.reload(config_path, index) {
+				error!("Could not reload config file: {}", e);
+			}
 		}
 	}
 
+	pub fn reload(&self, config_path: &Path, index: &Index) -> Result<(), Error> {
+		let config = Config::from_path(config_path)?;
+		let old_mount_dirs = self.vfs_manager.mount_dirs()?;
+		let old_settings = self.settings_manager.read()?;
+		self.apply_file(&config)?;
+		let new_mount_dirs = self.vfs_manager.mount_dirs()?;
+		let new_settings = self.settings_manager.read()?;
+		if old_mount_dirs != new_mount_dirs
+			|| old_settings.index_album_art_pattern != new_settings.index_album_art_pattern
+			|| old_settings.index_album_art_priority != new_settings.index_album_art_priority
+			|| old_settings.index_ignore_patterns != new_settings.index_ignore_patterns
+			|| old_settings.index_group_compilations != new_settings.index_group_compilations
+		{
+			index.trigger_reindex();
+		}
+		Ok(())
+	}
+
+	/// Applies the content of the config file. Sections which were removed from the file since it
+	/// was last applied are reset, while sections it never had keep the values set from the API.
+	pub fn apply_file(&self, config: &Config) -> Result<(), Error> {
+		let mut file_sections = self.file_sections.lock().unwrap();
+		let new_sections = FileSections::of(config);
+		if file_sections.settings && !new_sections.settings {
+			self.settings_manager
+				.amend(&settings::NewSettings::defaults())?;
+		}
+		if file_sections.mount_dirs && !new_sections.mount_dirs {
+			self.vfs_manager.set_mount_dirs(&[])?;
+		}
+		if file_sections.ydns && !new_sections.ydns {
+			self.ddns_manager.set_config(&ddns::Config::default())?;
+		}
+		if file_sections.users && !new_sections.users {
+			self.set_users(&[])?;
+		}
+		if file_sections.ldap && !new_sections.ldap {
+			self.ldap_manager.clear_config();
+		}
+		self.apply(config)?;
+		*file_sections = new_sections;
+		Ok(())
+	}
+
 	pub fn apply(&self, config: &Config) -> Result<(), Error> {
 		if let Some(new_settings) = &config.settings {
 			self.settings_manager.amend(new_settings)?;
@@ -77,30 +373,45 @@ impl Manager {
 			self.ddns_manager.set_config(ddns_config)?;
 		}
 
-		if let Some(ref users) = config.users {
-			let old_users: Vec<user::User> = self.user_manager.list()?;
+		if let Some(ldap_config) = &config.ldap {
+			self.ldap_manager.set_config(ldap_config)?;
+		}
 
-			// Delete users that are not in new list
-			for old_user in old_users
-				.iter()
-				.filter(|old_user| !users.iter().any(|u| u.name == old_user.name))
-			{
-				self.user_manager.delete(&old_user.name)?;
-			}
+		if let Some(users) = &config.users {
+			self.set_users(users)?;
+		}
 
-			// Insert new users
-			for new_user in users
-				.iter()
-				.filter(|u| !old_users.iter().any(|old_user| old_user.name == u.name))
-			{
-				self.user_manager.create(new_user)?;
-			}
+		Ok(())
+	}
+
+	fn set_users(&self, users: &[user::NewUser]) -> Result<(), Error> {
+		let old_users: Vec<user::User> = self.user_manager.list()?;
+
+		// Delete users that are not in new list, except those created by LDAP or proxy logins
+		for old_user in old_users
+			.iter()
+			.filter(|old_user| !old_user.external && !users.iter().any(|u| u.name == old_user.name))
+		{
+			self.user_manager.delete(&old_user.name)?;
+		}
 
-			// Update users
-			for user in users {
-				self.user_manager.set_password(&user.name, &user.password)?;
-				self.user_manager.set_is_admin(&user.name, user.admin)?;
+		// Insert new users
+		for new_user in users
+			.iter()
+			.filter(|u| !old_users.iter().any(|old_user| old_user.name == u.name))
+		{
+			self.user_manager.create(new_user)?;
+		}
+
+		// Update users
+		for user in users {
+			match &user.password_hash {
+				Some(hash) => self.user_manager.set_password_hash(&user.name, hash)?,
+				None => self.user_manager.set_password(&user.name, &user.password)?,
 			}
+			self.user_manager.set_is_admin(&user.name, user.admin)?;
+			self.user_manager
+				.set_visible_mounts(&user.name, user.mounts.as_deref())?;
 		}
 
 		Ok(())
@@ -121,6 +432,7 @@ mod test {
 			settings: Some(settings::NewSettings {
 				album_art_pattern: Some("🖼️\\.jpg".into()),
 				reindex_every_n_seconds: Some(100),
+				..Default::default()
 			}),
 			..Default::default()
 		};
@@ -146,6 +458,11 @@ mod test {
 			mount_dirs: Some(vec![vfs::MountDir {
 				source: "/home/music".into(),
 				name: "🎵📁".into(),
+				watch: true,
+				follow_symlinks: true,
+				content_type: vfs::ContentType::Audiobook,
+				album_art_pattern: None,
+				art_overlay: None,
 			}]),
 			..Default::default()
 		};
@@ -164,6 +481,14 @@ mod test {
 				host: "🐸🐸🐸.ydns.eu".into(),
 				username: "kfr🐸g".into(),
 				password: "tasty🐞".into(),
+				update_url: "https://dyn.example.com/update?hostname={host}&myip={ip}".into(),
+				update_interval_seconds: 600,
+				cloudflare: Some(ddns::cloudflare::Config {
+					api_token: "t0k3n".into(),
+					zone: "example.com".into(),
+					record: "music.example.com".into(),
+				}),
+				ip_version: ddns::IpVersion::Both,
 			}),
 			..Default::default()
 		};
@@ -185,11 +510,432 @@ mod test {
 			users: Some(vec![user::NewUser {
 				name: "Walter".into(),
 				password: "Tasty🍖".into(),
+				password_hash: None,
 				admin: false,
+				mounts: None,
+			}]),
+			..Default::default()
+		};
+		ctx.config_manager.apply(&new_config).unwrap();
+		assert!(!ctx.user_manager.list().unwrap()[0].is_admin());
+	}
+
+	#[test]
+	fn apply_keeps_ldap_users() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user("Walter", "Tasty🍖", true)
+			.build();
+		let ldap_config = Config {
+			ldap: Some(ldap::Config {
+				url: test::start_ldap_server(),
+				bind_dn_template: "uid={username},ou=people,dc=example,dc=com".into(),
+				group_filter: None,
+			}),
+			..Default::default()
+		};
+		ctx.config_manager.apply(&ldap_config).unwrap();
+		ctx.user_manager.login("Jesse", "Science🧪").unwrap();
+		ctx.playlist_manager
+			.save_playlist("Chill", "Jesse", &[])
+			.unwrap();
+
+		let new_config = Config {
+			users: Some(vec![user::NewUser {
+				name: "Walter".into(),
+				password: "Tasty🍖".into(),
+				password_hash: None,
+				admin: true,
+				mounts: None,
 			}]),
 			..Default::default()
 		};
 		ctx.config_manager.apply(&new_config).unwrap();
+
+		assert!(ctx.user_manager.exists("Jesse").unwrap());
+		assert_eq!(
+			ctx.playlist_manager.list_playlists("Jesse").unwrap(),
+			vec!["Chill".to_owned()]
+		);
+	}
+
+	#[test]
+	fn hash_passwords_removes_plaintext() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		let original_content = r#"
+			# Music on the NAS
+			[[mount_dirs]]
+			name = 'root'
+			source = 'test-data/small-collection' # mounted at boot
+
+			[[users]]
+			name = 'Walter'
+			password = 'Tasty🍖' # changed on first start
+			admin = false
+
+			[[users]]
+			name = 'Jesse'
+			password = '${POLARIS_TEST_UNSET_VARIABLE}'
+			admin = false
+			"#;
+		std::fs::write(&config_path, original_content).unwrap();
+
+		let mut config = Config::from_path_raw(&config_path).unwrap();
+		assert!(config.has_plaintext_passwords());
+
+		config.hash_passwords().unwrap();
+		assert!(!config.has_plaintext_passwords());
+
+		config.write_to_path(&config_path).unwrap();
+		let content = std::fs::read_to_string(&config_path).unwrap();
+		assert!(!content.contains("Tasty"));
+		assert!(content.contains("# Music on the NAS"));
+		assert!(content.contains("source = 'test-data/small-collection' # mounted at boot"));
+		assert!(content.contains("password = '${POLARIS_TEST_UNSET_VARIABLE}'"));
+		assert_eq!(content.matches("password_hash = ").count(), 1);
+
+		let config = Config::from_path_raw(&config_path).unwrap();
+		assert!(!config.has_plaintext_passwords());
+		let users = config.users.unwrap();
+		assert!(users[0].password_hash.is_some());
+		assert!(users[1].password_hash.is_none());
+
+		let config = Config {
+			users: Some(users.into_iter().take(1).collect()),
+			..Default::default()
+		};
+		ctx.config_manager.apply(&config).unwrap();
+		assert!(ctx.user_manager.login("Walter", "Tasty🍖").is_ok());
+	}
+
+	#[test]
+	fn write_to_path_supports_inline_tables() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			"users = [{ name = 'Walter', password = 'Tasty🍖', admin = false }]\n",
+		)
+		.unwrap();
+
+		let mut config = Config::from_path_raw(&config_path).unwrap();
+		config.hash_passwords().unwrap();
+		config.write_to_path(&config_path).unwrap();
+
+		let config = Config::from_path_raw(&config_path).unwrap();
+		assert!(!config.has_plaintext_passwords());
+		assert!(config.users.unwrap()[0].password_hash.is_some());
+	}
+
+	#[test]
+	fn from_path_substitutes_env_vars() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		std::env::set_var("POLARIS_TEST_DDNS_HOST", "🐸🐸🐸");
+		std::env::set_var("POLARIS_TEST_DDNS_PASSWORD", "tasty🐞");
+
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[ydns]
+			host = '${POLARIS_TEST_DDNS_HOST}.ydns.eu'
+			username = 'kfr🐸g'
+			password = '${POLARIS_TEST_DDNS_PASSWORD}'
+			"#,
+		)
+		.unwrap();
+
+		let config = Config::from_path(&config_path).unwrap();
+		let ddns_config = config.ydns.unwrap();
+		assert_eq!(ddns_config.host, "🐸🐸🐸.ydns.eu");
+		assert_eq!(ddns_config.password, "tasty🐞");
+
+		let raw_config = Config::from_path_raw(&config_path).unwrap();
+		assert_eq!(
+			raw_config.ydns.unwrap().password,
+			"${POLARIS_TEST_DDNS_PASSWORD}"
+		);
+	}
+
+	#[test]
+	fn from_path_rejects_missing_env_vars() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[[users]]
+			name = 'Walter'
+			password = '${POLARIS_TEST_UNSET_VARIABLE}'
+			admin = false
+			"#,
+		)
+		.unwrap();
+
+		assert!(matches!(
+			Config::from_path(&config_path),
+			Err(Error::MissingEnvironmentVariable(_))
+		));
+		assert!(!Config::from_path_raw(&config_path)
+			.unwrap()
+			.has_plaintext_passwords());
+	}
+
+	#[test]
+	fn from_path_resolves_relative_mount_sources() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		let absolute_source = std::env::current_dir().unwrap().join("music");
+		std::fs::write(
+			&config_path,
+			format!(
+				r#"
+				[[mount_dirs]]
+				name = 'portable'
+				source = 'Music/Albums'
+				art_overlay = 'Music/Covers'
+
+				[[mount_dirs]]
+				name = 'fixed'
+				source = '{}'
+				"#,
+				absolute_source.display()
+			),
+		)
+		.unwrap();
+
+		let config = Config::from_path(&config_path).unwrap();
+		let mount_dirs = config.mount_dirs.unwrap();
+		let sources: Vec<PathBuf> = mount_dirs
+			.iter()
+			.map(|m| PathBuf::from(&m.source))
+			.collect();
+		assert_eq!(
+			sources,
+			vec![
+				ctx.test_directory.join("Music").join("Albums"),
+				absolute_source
+			]
+		);
+		assert_eq!(
+			mount_dirs[0].art_overlay.as_ref().map(PathBuf::from),
+			Some(ctx.test_directory.join("Music").join("Covers"))
+		);
+
+		let raw_config = Config::from_path_raw(&config_path).unwrap();
+		assert_eq!(raw_config.mount_dirs.unwrap()[0].source, "Music/Albums");
+	}
+
+	#[test]
+	fn users_are_not_admin_by_default() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[[users]]
+			name = 'Walter'
+			password = 'Tasty🍖'
+			"#,
+		)
+		.unwrap();
+
+		let config = Config::from_path(&config_path).unwrap();
+		ctx.config_manager.apply(&config).unwrap();
 		assert!(!ctx.user_manager.list().unwrap()[0].is_admin());
 	}
+
+	#[test]
+	fn from_path_reads_tls_section() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[tls]
+			cert_path = 'cert.pem'
+			key_path = 'key.pem'
+			"#,
+		)
+		.unwrap();
+
+		let config = Config::from_path(&config_path).unwrap();
+		assert_eq!(
+			config.tls,
+			Some(tls::Config {
+				cert_path: PathBuf::from("cert.pem"),
+				key_path: PathBuf::from("key.pem"),
+			})
+		);
+	}
+
+	#[test]
+	fn from_path_reads_logging_section() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[logging]
+			level = 'debug'
+			targets = ['polaris::app::index']
+			file_path = 'polaris.log'
+			"#,
+		)
+		.unwrap();
+
+		let config = Config::from_path(&config_path).unwrap();
+		assert_eq!(
+			config.logging,
+			Some(LoggingConfig {
+				level: Some(LevelFilter::Debug),
+				targets: vec!["polaris::app::index".to_owned()],
+				file_path: Some(PathBuf::from("polaris.log")),
+			})
+		);
+	}
+
+	#[test]
+	fn from_path_reads_server_section() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[server]
+			keep_alive = 0
+			request_timeout = 10
+			max_connections = 200
+			"#,
+		)
+		.unwrap();
+
+		let config = Config::from_path(&config_path).unwrap();
+		assert_eq!(
+			config.server,
+			Some(ServerConfig {
+				keep_alive: Some(0),
+				request_timeout: Some(10),
+				disconnect_timeout: None,
+				max_body_size_mb: None,
+				max_connections: Some(200),
+			})
+		);
+	}
+
+	#[test]
+	fn url_base_is_normalized() {
+		for (input, expected) in [
+			(None, ""),
+			(Some("/"), ""),
+			(Some("polaris"), "/polaris"),
+			(Some("/polaris/"), "/polaris"),
+			(Some("/music/polaris"), "/music/polaris"),
+		] {
+			let config = Config {
+				url_base: input.map(|s| s.to_owned()),
+				..Default::default()
+			};
+			assert_eq!(config.url_base(), expected);
+		}
+	}
+
+	#[test]
+	fn reload_applies_config_file_changes() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user("Walter", "Tasty🍖", true)
+			.build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(
+			&config_path,
+			r#"
+			[[mount_dirs]]
+			name = '🎵📁'
+			source = '/home/music'
+
+			[[users]]
+			name = 'Jesse'
+			password = 'Tasty🍖'
+			admin = true
+			"#,
+		)
+		.unwrap();
+
+		ctx.config_manager.reload(&config_path, &ctx.index).unwrap();
+
+		let mount_dirs = ctx.vfs_manager.mount_dirs().unwrap();
+		assert_eq!(mount_dirs.len(), 1);
+		assert_eq!(mount_dirs[0].name, "🎵📁");
+
+		let users = ctx.user_manager.list().unwrap();
+		assert_eq!(users.len(), 1);
+		assert_eq!(users[0].name, "Jesse");
+	}
+
+	#[test]
+	fn reload_resets_removed_sections() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user("Walter", "Tasty🍖", true)
+			.build();
+		let initial_settings = format!("{:?}", ctx.settings_manager.read().unwrap());
+		let config_path = ctx.test_directory.join("polaris.toml");
+		let users = r#"
+			[[users]]
+			name = 'Walter'
+			password = 'Tasty🍖'
+			admin = true
+			"#;
+		std::fs::write(
+			&config_path,
+			format!(
+				r#"
+				[settings]
+				reindex_every_n_seconds = 100
+
+				[ldap]
+				url = 'ldap://ldap.example.com'
+				bind_dn_template = 'uid={{username}},ou=people,dc=example,dc=com'
+
+				[[mount_dirs]]
+				name = 'root'
+				source = 'test-data/small-collection'
+				{users}"#
+			),
+		)
+		.unwrap();
+		ctx.config_manager.reload(&config_path, &ctx.index).unwrap();
+		assert!(ctx.config_manager.ldap_manager.is_enabled());
+		assert_eq!(
+			ctx.settings_manager
+				.read()
+				.unwrap()
+				.index_sleep_duration_seconds,
+			100
+		);
+		assert_eq!(ctx.vfs_manager.mount_dirs().unwrap().len(), 1);
+
+		std::fs::write(&config_path, users).unwrap();
+		ctx.config_manager.reload(&config_path, &ctx.index).unwrap();
+		assert!(!ctx.config_manager.ldap_manager.is_enabled());
+		assert!(ctx.vfs_manager.mount_dirs().unwrap().is_empty());
+		assert_eq!(
+			format!("{:?}", ctx.settings_manager.read().unwrap()),
+			initial_settings
+		);
+	}
+
+	#[test]
+	fn reload_keeps_sections_the_file_never_had() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user("Walter", "Tasty🍖", true)
+			.mount("root", "test-data/small-collection")
+			.build();
+		let config_path = ctx.test_directory.join("polaris.toml");
+		std::fs::write(&config_path, "[settings]\nreindex_every_n_seconds = 100\n").unwrap();
+
+		ctx.config_manager.reload(&config_path, &ctx.index).unwrap();
+
+		assert_eq!(ctx.vfs_manager.mount_dirs().unwrap().len(), 1);
+		assert_eq!(ctx.user_manager.list().unwrap().len(), 1);
+	}
 }
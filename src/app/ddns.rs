@@ -2,12 +2,26 @@ use base64::prelude::*;
 use diesel::prelude::*;
 use log::{debug, error};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use std::thread;
-use std::time;
+use std::time::Duration;
 
-use crate::db::{self, ddns_config, DB};
+use crate::db::{self, DB};
 
-const DDNS_UPDATE_URL: &str = "https://ydns.io/api/v1/update/";
+pub mod cloudflare;
+
+const YDNS_UPDATE_URL: &str = "https://ydns.io/api/v1/update/?host={host}";
+const YDNS_EXPLICIT_IP_UPDATE_URL: &str = "https://ydns.io/api/v1/update/?host={host}&ip={ip}";
+const PUBLIC_IPV4_URL: &str = "https://api.ipify.org";
+const PUBLIC_IPV6_URL: &str = "https://api6.ipify.org";
+const DEFAULT_UPDATE_INTERVAL_SECONDS: i32 = 60 * 30;
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Responses of dyndns-compatible services which signal a failure with a successful HTTP status
+const REJECTION_RESPONSES: [&str; 8] = [
+	"ko", "badauth", "badagent", "nohost", "notfqdn", "numhost", "abuse", "911",
+];
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -15,18 +29,170 @@ pub enum Error {
 	UpdateQueryFailed(u16),
 	#[error("DDNS update query failed due to a transport error")]
 	UpdateQueryTransport,
+	#[error("DDNS update was rejected: `{0}`")]
+	UpdateRejected(String),
+	#[error("Could not determine public IP address")]
+	PublicIpLookup,
+	#[error("Unknown IP version `{0}`")]
+	UnknownIpVersion(String),
 	#[error(transparent)]
 	DatabaseConnection(#[from] db::Error),
 	#[error(transparent)]
 	Database(#[from] diesel::result::Error),
 }
 
-#[derive(Clone, Debug, Deserialize, Insertable, PartialEq, Eq, Queryable, Serialize)]
-#[diesel(table_name = ddns_config)]
+// Columns of the ddns_config table, Cloudflare settings are only set together
+type Row = (
+	String,
+	String,
+	String,
+	String,
+	i32,
+	Option<String>,
+	Option<String>,
+	Option<String>,
+	String,
+);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpVersion {
+	#[default]
+	V4,
+	V6,
+	Both,
+}
+
+impl IpVersion {
+	fn as_str(&self) -> &'static str {
+		match self {
+			IpVersion::V4 => "v4",
+			IpVersion::V6 => "v6",
+			IpVersion::Both => "both",
+		}
+	}
+
+	fn parse(value: &str) -> Result<Self, Error> {
+		match value {
+			"v4" => Ok(IpVersion::V4),
+			"v6" => Ok(IpVersion::V6),
+			"both" => Ok(IpVersion::Both),
+			_ => Err(Error::UnknownIpVersion(value.to_owned())),
+		}
+	}
+
+	fn lookup_urls(&self) -> &'static [&'static str] {
+		match self {
+			IpVersion::V4 => &[PUBLIC_IPV4_URL],
+			IpVersion::V6 => &[PUBLIC_IPV6_URL],
+			IpVersion::Both => &[PUBLIC_IPV4_URL, PUBLIC_IPV6_URL],
+		}
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub struct Config {
 	pub host: String,
 	pub username: String,
 	pub password: String,
+	// Update URL of any provider, where `{host}` and `{ip}` are substituted. YDNS when empty.
+	#[serde(default)]
+	pub update_url: String,
+	#[serde(default = "default_update_interval_seconds")]
+	pub update_interval_seconds: i32,
+	// Updates a Cloudflare DNS record instead of calling the update URL
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cloudflare: Option<cloudflare::Config>,
+	// Which public addresses to publish, as A and/or AAAA records
+	#[serde(default)]
+	pub ip_version: IpVersion,
+}
+
+fn default_update_interval_seconds() -> i32 {
+	DEFAULT_UPDATE_INTERVAL_SECONDS
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			host: String::new(),
+			username: String::new(),
+			password: String::new(),
+			update_url: String::new(),
+			update_interval_seconds: DEFAULT_UPDATE_INTERVAL_SECONDS,
+			cloudflare: None,
+			ip_version: IpVersion::default(),
+		}
+	}
+}
+
+impl Config {
+	fn is_enabled(&self) -> bool {
+		self.cloudflare.is_some()
+			|| !self.update_url.is_empty()
+			|| !(self.host.is_empty() || self.username.is_empty())
+	}
+
+	fn update_url_template(&self) -> &str {
+		match (self.update_url.is_empty(), self.ip_version) {
+			(false, _) => &self.update_url,
+			// YDNS detects the IPv4 address requests come from by itself
+			(true, IpVersion::V4) => YDNS_UPDATE_URL,
+			(true, _) => YDNS_EXPLICIT_IP_UPDATE_URL,
+		}
+	}
+
+	fn update_interval(&self) -> Duration {
+		Duration::from_secs(self.update_interval_seconds.max(0) as u64).max(MIN_UPDATE_INTERVAL)
+	}
+}
+
+fn make_update_url(template: &str, host: &str, ip: Option<IpAddr>) -> String {
+	let url = template.replace("{host}", host);
+	match ip {
+		Some(ip) => url.replace("{ip}", &ip.to_string()),
+		None => url,
+	}
+}
+
+fn check_update_response(body: &str) -> Result<(), Error> {
+	let body = body.trim();
+	let status = body.split_whitespace().next().unwrap_or_default();
+	if REJECTION_RESPONSES.contains(&status.to_lowercase().as_str()) {
+		return Err(Error::UpdateRejected(body.to_owned()));
+	}
+	Ok(())
+}
+
+// Retries start quickly and slow down until they reach the regular update interval
+fn next_retry_delay(previous_delay: Option<Duration>, update_interval: Duration) -> Duration {
+	previous_delay
+		.map(|d| d * 2)
+		.unwrap_or(FIRST_RETRY_DELAY)
+		.min(update_interval)
+}
+
+fn query_error(error: ureq::Error) -> Error {
+	match error {
+		ureq::Error::Status(code, _) => Error::UpdateQueryFailed(code),
+		ureq::Error::Transport(_) => Error::UpdateQueryTransport,
+	}
+}
+
+fn get_public_ip(lookup_url: &str) -> Result<IpAddr, Error> {
+	let response = ureq::get(lookup_url)
+		.call()
+		.map_err(|_| Error::PublicIpLookup)?;
+	let ip = response.into_string().map_err(|_| Error::PublicIpLookup)?;
+	ip.trim().parse().map_err(|_| Error::PublicIpLookup)
+}
+
+fn get_public_ips(ip_version: IpVersion) -> Result<Vec<IpAddr>, Error> {
+	ip_version
+		.lookup_urls()
+		.iter()
+		.map(|url| get_public_ip(url))
+		.collect()
 }
 
 #[derive(Clone)]
@@ -39,35 +205,87 @@ impl Manager {
 		Self { db }
 	}
 
-	fn update_my_ip(&self) -> Result<(), Error> {
-		let config = self.config()?;
-		if config.host.is_empty() || config.username.is_empty() {
-			debug!("Skipping DDNS update because credentials are missing");
+	fn update_my_ip(&self, config: &Config) -> Result<(), Error> {
+		if !config.is_enabled() {
+			debug!("Skipping DDNS update because it is not configured");
+			return Ok(());
+		}
+
+		if let Some(cloudflare_config) = &config.cloudflare {
+			for ip in get_public_ips(config.ip_version)? {
+				cloudflare::update_record(cloudflare_config, ip)?;
+			}
 			return Ok(());
 		}
 
-		let full_url = format!("{}?host={}", DDNS_UPDATE_URL, &config.host);
-		let credentials = format!("{}:{}", &config.username, &config.password);
-		let response = ureq::get(full_url.as_str())
-			.set(
+		// Each address is sent in its own request, the provider infers the record type from it
+		let template = config.update_url_template();
+		if !template.contains("{ip}") {
+			return self.send_update(config, &make_update_url(template, &config.host, None));
+		}
+		for ip in get_public_ips(config.ip_version)? {
+			self.send_update(config, &make_update_url(template, &config.host, Some(ip)))?;
+		}
+		Ok(())
+	}
+
+	fn send_update(&self, config: &Config, full_url: &str) -> Result<(), Error> {
+		let mut request = ureq::get(full_url);
+		if !config.username.is_empty() {
+			let credentials = format!("{}:{}", &config.username, &config.password);
+			request = request.set(
 				"Authorization",
 				&format!("Basic {}", BASE64_STANDARD_NO_PAD.encode(credentials)),
-			)
-			.call();
-
-		match response {
-			Ok(_) => Ok(()),
-			Err(ureq::Error::Status(code, _)) => Err(Error::UpdateQueryFailed(code)),
-			Err(ureq::Error::Transport(_)) => Err(Error::UpdateQueryTransport),
+			);
 		}
+
+		let response = request.call().map_err(query_error)?;
+		check_update_response(&response.into_string().unwrap_or_default())
 	}
 
 	pub fn config(&self) -> Result<Config, Error> {
 		use crate::db::ddns_config::dsl::*;
 		let mut connection = self.db.connect()?;
-		Ok(ddns_config
-			.select((host, username, password))
-			.get_result(&mut connection)?)
+		let (
+			host_value,
+			username_value,
+			password_value,
+			update_url_value,
+			update_interval_seconds_value,
+			api_token,
+			zone,
+			record,
+			ip_version_value,
+		): Row = ddns_config
+			.select((
+				host,
+				username,
+				password,
+				update_url,
+				update_interval_seconds,
+				cloudflare_api_token,
+				cloudflare_zone,
+				cloudflare_record,
+				ip_version,
+			))
+			.get_result(&mut connection)?;
+		let cloudflare = match (api_token, zone, record) {
+			(Some(api_token), Some(zone), Some(record)) => Some(cloudflare::Config {
+				api_token,
+				zone,
+				record,
+			}),
+			_ => None,
+		};
+		Ok(Config {
+			host: host_value,
+			username: username_value,
+			password: password_value,
+			update_url: update_url_value,
+			update_interval_seconds: update_interval_seconds_value,
+			cloudflare,
+			ip_version: IpVersion::parse(&ip_version_value)?,
+		})
 	}
 
 	pub fn set_config(&self, new_config: &Config) -> Result<(), Error> {
@@ -78,6 +296,12 @@ impl Manager {
 				host.eq(&new_config.host),
 				username.eq(&new_config.username),
 				password.eq(&new_config.password),
+				update_url.eq(&new_config.update_url),
+				update_interval_seconds.eq(new_config.update_interval_seconds),
+				cloudflare_api_token.eq(new_config.cloudflare.as_ref().map(|c| &c.api_token)),
+				cloudflare_zone.eq(new_config.cloudflare.as_ref().map(|c| &c.zone)),
+				cloudflare_record.eq(new_config.cloudflare.as_ref().map(|c| &c.record)),
+				ip_version.eq(new_config.ip_version.as_str()),
 			))
 			.execute(&mut connection)?;
 		Ok(())
@@ -91,11 +315,112 @@ impl Manager {
 	}
 
 	fn run(&self) {
+		let mut retry_delay = None;
 		loop {
-			if let Err(e) = self.update_my_ip() {
-				error!("Dynamic DNS update error: {:?}", e);
-			}
-			thread::sleep(time::Duration::from_secs(60 * 30));
+			let (result, update_interval) = match self.config() {
+				Ok(config) => (self.update_my_ip(&config), config.update_interval()),
+				Err(e) => (Err(e), MIN_UPDATE_INTERVAL),
+			};
+			let delay = match result {
+				Ok(()) => {
+					retry_delay = None;
+					update_interval
+				}
+				Err(e) => {
+					error!("Dynamic DNS update error: {:?}", e);
+					let delay = next_retry_delay(retry_delay, update_interval);
+					retry_delay = Some(delay);
+					delay
+				}
+			};
+			thread::sleep(delay);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn substitutes_update_url_template() {
+		assert_eq!(
+			make_update_url(
+				"https://www.duckdns.org/update?domains={host}&token=abc&ip={ip}",
+				"polaris",
+				Some("1.2.3.4".parse().unwrap())
+			),
+			"https://www.duckdns.org/update?domains=polaris&token=abc&ip=1.2.3.4"
+		);
+		assert_eq!(
+			make_update_url(
+				YDNS_EXPLICIT_IP_UPDATE_URL,
+				"polaris.ydns.eu",
+				Some("2001:db8::1".parse().unwrap())
+			),
+			"https://ydns.io/api/v1/update/?host=polaris.ydns.eu&ip=2001:db8::1"
+		);
+		assert_eq!(
+			make_update_url(
+				"https://dyn.example.com/update?host={host}",
+				"polaris",
+				None
+			),
+			"https://dyn.example.com/update?host=polaris"
+		);
+	}
+
+	#[test]
+	fn ydns_only_receives_explicit_addresses_for_ipv6() {
+		let config = Config::default();
+		assert_eq!(config.update_url_template(), YDNS_UPDATE_URL);
+		assert!(!config.update_url_template().contains("{ip}"));
+		for ip_version in [IpVersion::V6, IpVersion::Both] {
+			let config = Config {
+				ip_version,
+				..Default::default()
+			};
+			assert_eq!(config.update_url_template(), YDNS_EXPLICIT_IP_UPDATE_URL);
+		}
+	}
+
+	#[test]
+	fn ip_version_round_trips() {
+		for ip_version in [IpVersion::V4, IpVersion::V6, IpVersion::Both] {
+			assert_eq!(IpVersion::parse(ip_version.as_str()).unwrap(), ip_version);
+		}
+		assert!(matches!(
+			IpVersion::parse("v5"),
+			Err(Error::UnknownIpVersion(_))
+		));
+		assert_eq!(IpVersion::Both.lookup_urls().len(), 2);
+	}
+
+	#[test]
+	fn detects_rejected_updates() {
+		assert!(check_update_response("OK").is_ok());
+		assert!(check_update_response("good 1.2.3.4").is_ok());
+		assert!(check_update_response("nochg 1.2.3.4\n").is_ok());
+		assert!(matches!(
+			check_update_response("KO"),
+			Err(Error::UpdateRejected(_))
+		));
+		assert!(matches!(
+			check_update_response("badauth"),
+			Err(Error::UpdateRejected(_))
+		));
+	}
+
+	#[test]
+	fn retries_back_off_up_to_update_interval() {
+		let update_interval = Duration::from_secs(300);
+		let mut delay = None;
+		let mut delays = Vec::new();
+		for _ in 0..6 {
+			let next = next_retry_delay(delay, update_interval);
+			delays.push(next.as_secs());
+			delay = Some(next);
 		}
+		assert_eq!(delays, vec![30, 60, 120, 240, 300, 300]);
 	}
 }
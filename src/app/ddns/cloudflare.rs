@@ -0,0 +1,156 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::net::IpAddr;
+
+use super::{query_error, Error};
+
+const API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Config {
+	// Needs the `Zone.DNS` edit permission
+	pub api_token: String,
+	// For example `example.com`
+	pub zone: String,
+	// For example `music.example.com`
+	pub record: String,
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+	success: bool,
+	#[serde(default)]
+	errors: Vec<ResponseError>,
+	result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+	message: String,
+}
+
+#[derive(Deserialize)]
+struct Zone {
+	id: String,
+}
+
+#[derive(Deserialize)]
+struct DnsRecord {
+	id: String,
+	content: String,
+}
+
+// Points the A or AAAA record at this IP address, creating the record if needed
+pub fn update_record(config: &Config, ip: IpAddr) -> Result<(), Error> {
+	let record_type = match ip {
+		IpAddr::V4(_) => "A",
+		IpAddr::V6(_) => "AAAA",
+	};
+	let ip = ip.to_string();
+
+	let zones: Vec<Zone> = call(
+		config,
+		ureq::get(&format!("{}/zones", API_URL)).query("name", &config.zone),
+		None,
+	)?;
+	let zone = zones
+		.first()
+		.ok_or_else(|| Error::UpdateRejected(format!("Zone `{}` not found", config.zone)))?;
+
+	let records_url = format!("{}/zones/{}/dns_records", API_URL, zone.id);
+	let records: Vec<DnsRecord> = call(
+		config,
+		ureq::get(&records_url)
+			.query("type", record_type)
+			.query("name", &config.record),
+		None,
+	)?;
+
+	match records.first() {
+		Some(record) if record.content == ip => Ok(()),
+		Some(record) => {
+			let record_url = format!("{}/{}", records_url, record.id);
+			call::<DnsRecord>(
+				config,
+				ureq::patch(&record_url),
+				Some(json!({ "content": ip })),
+			)?;
+			Ok(())
+		}
+		None => {
+			let new_record = json!({
+				"type": record_type,
+				"name": config.record,
+				"content": ip,
+				"ttl": 1, // Automatic
+			});
+			call::<DnsRecord>(config, ureq::post(&records_url), Some(new_record))?;
+			Ok(())
+		}
+	}
+}
+
+fn call<T: DeserializeOwned>(
+	config: &Config,
+	request: ureq::Request,
+	body: Option<serde_json::Value>,
+) -> Result<T, Error> {
+	let request = request.set("Authorization", &format!("Bearer {}", config.api_token));
+	let response = match body {
+		Some(body) => request
+			.set("Content-Type", "application/json")
+			.send_string(&body.to_string()),
+		None => request.call(),
+	}
+	.map_err(query_error)?;
+	let body = response
+		.into_string()
+		.map_err(|_| Error::UpdateQueryTransport)?;
+	parse_response(&body)
+}
+
+fn parse_response<T: DeserializeOwned>(body: &str) -> Result<T, Error> {
+	let response: Response<T> =
+		serde_json::from_str(body).map_err(|_| Error::UpdateRejected(body.to_owned()))?;
+	match response.result {
+		Some(result) if response.success => Ok(result),
+		_ => {
+			let messages: Vec<String> = response.errors.into_iter().map(|e| e.message).collect();
+			Err(Error::UpdateRejected(messages.join(", ")))
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parses_successful_response() {
+		let body = r#"{
+			"success": true,
+			"errors": [],
+			"messages": [],
+			"result": [{ "id": "372e67954025e0ba6aaa6d586b9e0b59", "type": "A", "name": "music.example.com", "content": "198.51.100.4" }]
+		}"#;
+		let records: Vec<DnsRecord> = parse_response(body).unwrap();
+		assert_eq!(records.len(), 1);
+		assert_eq!(records[0].id, "372e67954025e0ba6aaa6d586b9e0b59");
+		assert_eq!(records[0].content, "198.51.100.4");
+	}
+
+	#[test]
+	fn reports_api_errors() {
+		let body = r#"{
+			"success": false,
+			"errors": [{ "code": 9109, "message": "Invalid access token" }],
+			"messages": [],
+			"result": null
+		}"#;
+		assert!(matches!(
+			parse_response::<Vec<Zone>>(body),
+			Err(Error::UpdateRejected(m)) if m == "Invalid access token"
+		));
+	}
+}
@@ -0,0 +1,600 @@
+use log::error;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::app::index::{CollectionFile, Directory, Index, QueryError, Song};
+use crate::app::settings::AuthSecret;
+use crate::utils::{self, AudioFormat};
+
+mod ssdp;
+
+const ROOT_ID: &str = "0";
+const DEFAULT_FRIENDLY_NAME: &str = "Polaris";
+const CONTENT_DIRECTORY_TYPE: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+const CONNECTION_MANAGER_TYPE: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+const CONTENT_DIRECTORY_SCPD: &str = include_str!("dlna/content_directory.xml");
+const CONNECTION_MANAGER_SCPD: &str = include_str!("dlna/connection_manager.xml");
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("DLNA is not enabled")]
+	NotEnabled,
+	#[error("No such object: `{0}`")]
+	ObjectNotFound(String),
+	#[error("Invalid action: `{0}`")]
+	InvalidAction(String),
+	#[error("Invalid arguments")]
+	InvalidArguments,
+	#[error(transparent)]
+	Query(#[from] QueryError),
+}
+
+impl Error {
+	// Error codes from the UPnP Device Architecture and ContentDirectory specifications
+	fn upnp_code(&self) -> u16 {
+		match self {
+			Error::InvalidAction(_) => 401,
+			Error::InvalidArguments => 402,
+			Error::ObjectNotFound(_) => 701,
+			Error::NotEnabled | Error::Query(_) => 501,
+		}
+	}
+}
+
+/// Shares the collection with smart TVs, AV receivers and other UPnP devices on the local network.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	// Devices cannot log in, so they browse the collection as this user
+	pub user: String,
+	#[serde(default)]
+	pub friendly_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceType {
+	ContentDirectory,
+	ConnectionManager,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	config: Option<Config>,
+	uuid: String,
+	port: u16,
+	url_base: String,
+	index: Index,
+}
+
+impl Manager {
+	pub fn new(
+		config: Option<Config>,
+		auth_secret: &AuthSecret,
+		port: u16,
+		url_base: String,
+		index: Index,
+	) -> Self {
+		Self {
+			config,
+			uuid: get_uuid(auth_secret),
+			port,
+			url_base,
+			index,
+		}
+	}
+
+	/// User whose collection is shared.
+	pub fn get_user(&self) -> Result<&str, Error> {
+		self.config
+			.as_ref()
+			.map(|c| c.user.as_str())
+			.ok_or(Error::NotEnabled)
+	}
+
+	pub fn begin_announcing(&self) {
+		if self.config.is_none() {
+			return;
+		}
+		let announcer = ssdp::Announcer::new(&self.uuid, self.port, &self.url_base);
+		std::thread::spawn(move || {
+			if let Err(e) = announcer.run() {
+				error!("Could not announce DLNA server: {}", e);
+			}
+		});
+	}
+
+	pub fn shutdown(&self) {
+		if self.config.is_some() {
+			ssdp::Announcer::new(&self.uuid, self.port, &self.url_base).say_goodbye();
+		}
+	}
+
+	pub fn get_description(&self) -> Result<String, Error> {
+		let config = self.config.as_ref().ok_or(Error::NotEnabled)?;
+		let friendly_name = config
+			.friendly_name
+			.as_deref()
+			.unwrap_or(DEFAULT_FRIENDLY_NAME);
+		let base = escape(&self.url_base);
+		Ok(format!(
+			r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+	<specVersion><major>1</major><minor>0</minor></specVersion>
+	<device>
+		<deviceType>{}</deviceType>
+		<friendlyName>{}</friendlyName>
+		<manufacturer>Polaris</manufacturer>
+		<manufacturerURL>https://github.com/agersant/polaris</manufacturerURL>
+		<modelName>Polaris</modelName>
+		<modelNumber>{}</modelNumber>
+		<UDN>uuid:{}</UDN>
+		<serviceList>
+			<service>
+				<serviceType>{CONTENT_DIRECTORY_TYPE}</serviceType>
+				<serviceId>urn:upnp-org:serviceId:ContentDirectory</serviceId>
+				<SCPDURL>{base}/dlna/content_directory.xml</SCPDURL>
+				<controlURL>{base}/dlna/control/content_directory</controlURL>
+				<eventSubURL>{base}/dlna/events/content_directory</eventSubURL>
+			</service>
+			<service>
+				<serviceType>{CONNECTION_MANAGER_TYPE}</serviceType>
+				<serviceId>urn:upnp-org:serviceId:ConnectionManager</serviceId>
+				<SCPDURL>{base}/dlna/connection_manager.xml</SCPDURL>
+				<controlURL>{base}/dlna/control/connection_manager</controlURL>
+				<eventSubURL>{base}/dlna/events/connection_manager</eventSubURL>
+			</service>
+		</serviceList>
+	</device>
+</root>
+"#,
+			ssdp::DEVICE_TYPE,
+			escape(friendly_name),
+			env!("CARGO_PKG_VERSION"),
+			self.uuid,
+		))
+	}
+
+	pub fn get_service_description(&self, service: ServiceType) -> Result<&'static str, Error> {
+		self.get_user()?;
+		Ok(match service {
+			ServiceType::ContentDirectory => CONTENT_DIRECTORY_SCPD,
+			ServiceType::ConnectionManager => CONNECTION_MANAGER_SCPD,
+		})
+	}
+
+	/// Runs a SOAP action. Media URLs in responses start with `base_url`, which should be the
+	/// address devices used to reach the server.
+	pub fn control(
+		&self,
+		service: ServiceType,
+		action: &str,
+		body: &str,
+		base_url: &str,
+	) -> Result<String, Error> {
+		let user = self.get_user()?;
+		let arguments = match (service, action) {
+			(ServiceType::ContentDirectory, "Browse") => {
+				let object_id = get_argument(body, "ObjectID").ok_or(Error::InvalidArguments)?;
+				let start = get_argument(body, "StartingIndex")
+					.and_then(|s| s.parse().ok())
+					.unwrap_or(0);
+				let count = get_argument(body, "RequestedCount")
+					.and_then(|s| s.parse().ok())
+					.filter(|c| *c > 0)
+					.unwrap_or(usize::MAX);
+				let browse = match get_argument(body, "BrowseFlag").as_deref() {
+					Some("BrowseMetadata") => self.browse_metadata(user, &object_id, base_url)?,
+					Some("BrowseDirectChildren") => {
+						self.browse_children(user, &object_id, start, count, base_url)?
+					}
+					_ => return Err(Error::InvalidArguments),
+				};
+				vec![
+					("Result", escape(&browse.didl)),
+					("NumberReturned", browse.number_returned.to_string()),
+					("TotalMatches", browse.total_matches.to_string()),
+					("UpdateID", "0".to_owned()),
+				]
+			}
+			(ServiceType::ContentDirectory, "GetSystemUpdateID") => {
+				vec![("Id", "0".to_owned())]
+			}
+			(ServiceType::ContentDirectory, "GetSearchCapabilities") => {
+				vec![("SearchCaps", String::new())]
+			}
+			(ServiceType::ContentDirectory, "GetSortCapabilities") => {
+				vec![("SortCaps", String::new())]
+			}
+			(ServiceType::ConnectionManager, "GetProtocolInfo") => {
+				vec![
+					("Source", "http-get:*:audio/*:*".to_owned()),
+					("Sink", String::new()),
+				]
+			}
+			(ServiceType::ConnectionManager, "GetCurrentConnectionIDs") => {
+				vec![("ConnectionIDs", "0".to_owned())]
+			}
+			_ => return Err(Error::InvalidAction(action.to_owned())),
+		};
+
+		let service_type = match service {
+			ServiceType::ContentDirectory => CONTENT_DIRECTORY_TYPE,
+			ServiceType::ConnectionManager => CONNECTION_MANAGER_TYPE,
+		};
+		let arguments = arguments
+			.into_iter()
+			.map(|(name, value)| format!("<{name}>{value}</{name}>"))
+			.collect::<String>();
+		Ok(envelope(&format!(
+			r#"<u:{action}Response xmlns:u="{service_type}">{arguments}</u:{action}Response>"#
+		)))
+	}
+
+	fn browse_metadata(
+		&self,
+		user: &str,
+		object_id: &str,
+		base_url: &str,
+	) -> Result<Browse, Error> {
+		let didl = if object_id == ROOT_ID {
+			let child_count = self.index.browse(user, Path::new(""))?.len();
+			format!(
+				r#"<container id="{ROOT_ID}" parentID="-1" restricted="1" childCount="{child_count}"><dc:title>{}</dc:title><upnp:class>object.container.storageFolder</upnp:class></container>"#,
+				DEFAULT_FRIENDLY_NAME
+			)
+		} else {
+			// Looking objects up through their parent only finds what the user can see
+			let path = Path::new(object_id);
+			let parent = path.parent().unwrap_or(Path::new(""));
+			let file = self
+				.index
+				.browse(user, parent)
+				.map_err(|_| Error::ObjectNotFound(object_id.to_owned()))?
+				.into_iter()
+				.find(|f| match f {
+					CollectionFile::Directory(d) => Path::new(&d.path) == path,
+					CollectionFile::Song(s) => Path::new(&s.path) == path,
+				})
+				.ok_or_else(|| Error::ObjectNotFound(object_id.to_owned()))?;
+			self.get_didl_object(user, &file, base_url)
+		};
+		Ok(Browse {
+			didl: didl_lite(&didl),
+			number_returned: 1,
+			total_matches: 1,
+		})
+	}
+
+	fn browse_children(
+		&self,
+		user: &str,
+		object_id: &str,
+		start: usize,
+		count: usize,
+		base_url: &str,
+	) -> Result<Browse, Error> {
+		let path = match object_id {
+			ROOT_ID => "",
+			id => id,
+		};
+		let files = self
+			.index
+			.browse(user, Path::new(path))
+			.map_err(|_| Error::ObjectNotFound(object_id.to_owned()))?;
+		let total_matches = files.len();
+		let objects = files
+			.iter()
+			.skip(start)
+			.take(count)
+			.map(|f| self.get_didl_object(user, f, base_url))
+			.collect::<Vec<_>>();
+		Ok(Browse {
+			number_returned: objects.len(),
+			didl: didl_lite(&objects.concat()),
+			total_matches,
+		})
+	}
+
+	fn get_didl_object(&self, user: &str, file: &CollectionFile, base_url: &str) -> String {
+		match file {
+			CollectionFile::Directory(directory) => {
+				let child_count = self
+					.index
+					.browse(user, Path::new(&directory.path))
+					.map_or(0, |f| f.len());
+				get_container(directory, child_count, base_url)
+			}
+			CollectionFile::Song(song) => get_item(song, base_url),
+		}
+	}
+}
+
+struct Browse {
+	didl: String,
+	number_returned: usize,
+	total_matches: usize,
+}
+
+/// SOAP fault sent back when an action fails.
+pub fn get_fault(error: &Error) -> String {
+	envelope(&format!(
+		r#"<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{}</errorCode><errorDescription>{}</errorDescription></UPnPError></detail></s:Fault>"#,
+		error.upnp_code(),
+		escape(&error.to_string())
+	))
+}
+
+/// Devices are not authenticated, so they are only served on local networks.
+pub fn is_local_address(address: IpAddr) -> bool {
+	match address {
+		IpAddr::V4(a) => a.is_loopback() || a.is_private() || a.is_link_local(),
+		IpAddr::V6(a) => match a.to_ipv4_mapped() {
+			Some(a) => is_local_address(IpAddr::V4(a)),
+			// Unique local (fc00::/7) and link local (fe80::/10) addresses
+			None => {
+				a.is_loopback()
+					|| (a.segments()[0] & 0xfe00) == 0xfc00
+					|| (a.segments()[0] & 0xffc0) == 0xfe80
+			}
+		},
+	}
+}
+
+pub fn get_mime_type(path: &Path) -> &'static str {
+	match utils::get_audio_format(path) {
+		Some(AudioFormat::AIFF) => "audio/aiff",
+		Some(AudioFormat::APE) => "audio/x-ape",
+		Some(AudioFormat::FLAC) => "audio/flac",
+		Some(AudioFormat::MP3) => "audio/mpeg",
+		Some(AudioFormat::MP4) | Some(AudioFormat::M4B) => "audio/mp4",
+		Some(AudioFormat::MPC) => "audio/x-musepack",
+		Some(AudioFormat::OGG) | Some(AudioFormat::OPUS) => "audio/ogg",
+		Some(AudioFormat::WAVE) => "audio/wav",
+		None => "application/octet-stream",
+	}
+}
+
+fn get_container(directory: &Directory, child_count: usize, base_url: &str) -> String {
+	let path = Path::new(&directory.path);
+	let title = directory.album.clone().unwrap_or_else(|| file_name(path));
+	let mut xml = format!(
+		r#"<container id="{}" parentID="{}" restricted="1" childCount="{child_count}"><dc:title>{}</dc:title>"#,
+		escape(&directory.path),
+		escape(&get_parent_id(path)),
+		escape(&title),
+	);
+	if directory.album.is_some() {
+		xml.push_str("<upnp:class>object.container.album.musicAlbum</upnp:class>");
+		if let Some(artist) = &directory.artist {
+			xml.push_str(&format!("<upnp:artist>{}</upnp:artist>", escape(artist)));
+		}
+	} else {
+		xml.push_str("<upnp:class>object.container.storageFolder</upnp:class>");
+	}
+	if let Some(artwork) = &directory.artwork {
+		xml.push_str(&format!(
+			"<upnp:albumArtURI>{}</upnp:albumArtURI>",
+			escape(&get_url(base_url, "artwork", artwork))
+		));
+	}
+	xml.push_str("</container>");
+	xml
+}
+
+fn get_item(song: &Song, base_url: &str) -> String {
+	let path = Path::new(&song.path);
+	// Cue tracks are served as flac segments of their source file
+	let mime_type = match song.cue_source {
+		Some(_) => "audio/flac",
+		None => get_mime_type(path),
+	};
+	let title = song.title.clone().unwrap_or_else(|| file_name(path));
+	let mut xml = format!(
+		r#"<item id="{}" parentID="{}" restricted="1"><dc:title>{}</dc:title><upnp:class>object.item.audioItem.musicTrack</upnp:class>"#,
+		escape(&song.path),
+		escape(&get_parent_id(path)),
+		escape(&title),
+	);
+	let tags = [
+		("upnp:artist", song.artist.clone()),
+		("dc:creator", song.artist.clone()),
+		("upnp:album", song.album.clone()),
+		("upnp:genre", song.genre.clone()),
+		(
+			"upnp:originalTrackNumber",
+			song.track_number.map(|n| n.to_string()),
+		),
+		("dc:date", song.year.map(|y| format!("{y}-01-01"))),
+		(
+			"upnp:albumArtURI",
+			song.artwork
+				.as_ref()
+				.map(|a| get_url(base_url, "artwork", a)),
+		),
+	];
+	for (name, value) in tags {
+		if let Some(value) = value {
+			xml.push_str(&format!("<{name}>{}</{name}>", escape(&value)));
+		}
+	}
+	let duration = song
+		.duration
+		.map(|d| format!(r#" duration="{}""#, format_duration(d)))
+		.unwrap_or_default();
+	xml.push_str(&format!(
+		r#"<res protocolInfo="http-get:*:{mime_type}:*"{duration}>{}</res></item>"#,
+		escape(&get_url(base_url, "audio", &song.path))
+	));
+	xml
+}
+
+fn get_parent_id(path: &Path) -> String {
+	match path.parent() {
+		Some(parent) if parent.components().count() > 0 => parent.to_string_lossy().into_owned(),
+		_ => ROOT_ID.to_owned(),
+	}
+}
+
+fn get_url(base_url: &str, endpoint: &str, virtual_path: &str) -> String {
+	let path = utf8_percent_encode(virtual_path, NON_ALPHANUMERIC);
+	format!("{base_url}/dlna/{endpoint}/{path}")
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.unwrap_or_default()
+		.to_string_lossy()
+		.into_owned()
+}
+
+fn format_duration(seconds: i32) -> String {
+	let seconds = seconds.max(0);
+	format!(
+		"{}:{:02}:{:02}.000",
+		seconds / 3600,
+		(seconds / 60) % 60,
+		seconds % 60
+	)
+}
+
+fn didl_lite(objects: &str) -> String {
+	format!(
+		r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">{objects}</DIDL-Lite>"#
+	)
+}
+
+fn envelope(body: &str) -> String {
+	format!(
+		r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body>{body}</s:Body></s:Envelope>"#
+	)
+}
+
+// Arguments of SOAP actions are elements without namespace prefixes
+fn get_argument(body: &str, name: &str) -> Option<String> {
+	let open = format!("<{name}>");
+	let close = format!("</{name}>");
+	let start = body.find(&open)? + open.len();
+	let end = start + body[start..].find(&close)?;
+	Some(unescape(body[start..end].trim()))
+}
+
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&apos;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+fn unescape(text: &str) -> String {
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+// Devices recognize servers by their UUID, so it must not change between runs
+fn get_uuid(auth_secret: &AuthSecret) -> String {
+	let mut halves = [0u64; 2];
+	for (i, half) in halves.iter_mut().enumerate() {
+		let mut hasher = DefaultHasher::new();
+		("polaris-dlna", i, auth_secret.key).hash(&mut hasher);
+		*half = hasher.finish();
+	}
+	let hex = format!("{:016x}{:016x}", halves[0], halves[1]);
+	format!(
+		"{}-{}-{}-{}-{}",
+		&hex[0..8],
+		&hex[8..12],
+		&hex[12..16],
+		&hex[16..20],
+		&hex[20..32]
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	#[test]
+	fn reads_soap_arguments() {
+		let body = r#"<s:Envelope><s:Body><u:Browse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1"><ObjectID>root/AC&amp;DC</ObjectID><BrowseFlag>BrowseDirectChildren</BrowseFlag><StartingIndex>0</StartingIndex></u:Browse></s:Body></s:Envelope>"#;
+		assert_eq!(
+			get_argument(body, "ObjectID").as_deref(),
+			Some("root/AC&DC")
+		);
+		assert_eq!(
+			get_argument(body, "BrowseFlag").as_deref(),
+			Some("BrowseDirectChildren")
+		);
+		assert_eq!(get_argument(body, "RequestedCount"), None);
+	}
+
+	#[test]
+	fn description_escapes_configured_names() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let config = Config {
+			user: "Walter".to_owned(),
+			friendly_name: Some("Rock & <Roll>".to_owned()),
+		};
+		let manager = Manager::new(
+			Some(config),
+			&AuthSecret::default(),
+			5050,
+			"/music&more".to_owned(),
+			ctx.index,
+		);
+		let description = manager.get_description().unwrap();
+		assert!(description.contains("<friendlyName>Rock &amp; &lt;Roll&gt;</friendlyName>"));
+		assert!(
+			description.contains("<SCPDURL>/music&amp;more/dlna/content_directory.xml</SCPDURL>")
+		);
+		assert!(!description.contains("/music&more"));
+	}
+
+	#[test]
+	fn faults_carry_upnp_error_codes() {
+		let fault = get_fault(&Error::ObjectNotFound("a/b".to_owned()));
+		assert!(fault.contains("<errorCode>701</errorCode>"));
+		let fault = get_fault(&Error::InvalidAction("Search".to_owned()));
+		assert!(fault.contains("<errorCode>401</errorCode>"));
+	}
+
+	#[test]
+	fn only_local_addresses_are_served() {
+		for address in ["127.0.0.1", "192.168.1.20", "10.0.0.3", "fd00::1", "::1"] {
+			assert!(is_local_address(address.parse().unwrap()), "{}", address);
+		}
+		for address in ["8.8.8.8", "2001:db8::1", "::ffff:8.8.8.8"] {
+			assert!(!is_local_address(address.parse().unwrap()), "{}", address);
+		}
+	}
+
+	#[test]
+	fn uuid_is_stable() {
+		let secret = AuthSecret { key: [7; 32] };
+		let uuid = get_uuid(&secret);
+		assert_eq!(uuid, get_uuid(&secret));
+		assert_eq!(uuid.len(), 36);
+		assert_ne!(uuid, get_uuid(&AuthSecret { key: [8; 32] }));
+	}
+
+	#[test]
+	fn formats_durations() {
+		assert_eq!(format_duration(205), "0:03:25.000");
+		assert_eq!(format_duration(3725), "1:02:05.000");
+	}
+}
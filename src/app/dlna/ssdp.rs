@@ -0,0 +1,232 @@
+use log::{error, info};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::{CONNECTION_MANAGER_TYPE, CONTENT_DIRECTORY_TYPE};
+
+pub const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+
+const MULTICAST_ADDRESS: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const PORT: u16 = 1900;
+const MAX_AGE: Duration = Duration::from_secs(1800);
+// Devices forget servers whose announcements expire, so they are renewed well before that
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(300);
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct Announcer {
+	uuid: String,
+	port: u16,
+	url_base: String,
+}
+
+impl Announcer {
+	pub fn new(uuid: &str, port: u16, url_base: &str) -> Self {
+		Self {
+			uuid: uuid.to_owned(),
+			port,
+			url_base: url_base.to_owned(),
+		}
+	}
+
+	pub fn run(&self) -> io::Result<()> {
+		let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+		socket.set_reuse_address(true)?;
+		socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+		socket.join_multicast_v4(&MULTICAST_ADDRESS, &Ipv4Addr::UNSPECIFIED)?;
+		socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+		let socket: UdpSocket = socket.into();
+		info!("Announcing DLNA server on the local network");
+
+		let mut last_notify: Option<Instant> = None;
+		let mut buffer = [0u8; 2048];
+		loop {
+			if last_notify.is_none_or(|t| t.elapsed() >= NOTIFY_INTERVAL) {
+				self.notify(&socket, "ssdp:alive");
+				last_notify = Some(Instant::now());
+			}
+
+			let (length, peer) = match socket.recv_from(&mut buffer) {
+				Ok(r) => r,
+				Err(e)
+					if matches!(
+						e.kind(),
+						io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+					) =>
+				{
+					continue
+				}
+				Err(e) => return Err(e),
+			};
+			let request = String::from_utf8_lossy(&buffer[..length]);
+			let Some(search_target) = parse_search_request(&request) else {
+				continue;
+			};
+			let Ok(local_ip) = get_local_ip(peer) else {
+				continue;
+			};
+			for (target, usn) in self.get_matching_targets(&search_target) {
+				let response = format!(
+					"HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\n\r\n",
+					MAX_AGE.as_secs(),
+					self.get_location(local_ip),
+					get_server_name(),
+					target,
+					usn,
+				);
+				if let Err(e) = socket.send_to(response.as_bytes(), peer) {
+					error!("Could not answer SSDP search from {}: {}", peer, e);
+				}
+			}
+		}
+	}
+
+	/// Tells devices the server is going away, so they do not wait for its announcements to expire.
+	pub fn say_goodbye(&self) {
+		match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+			Ok(socket) => self.notify(&socket, "ssdp:byebye"),
+			Err(e) => error!("Could not send SSDP byebye: {}", e),
+		}
+	}
+
+	fn notify(&self, socket: &UdpSocket, sub_type: &str) {
+		let destination = SocketAddrV4::new(MULTICAST_ADDRESS, PORT);
+		let location = match get_local_ip(destination.into()) {
+			Ok(local_ip) => self.get_location(local_ip),
+			Err(e) => {
+				error!("Could not find local address for SSDP announcement: {}", e);
+				return;
+			}
+		};
+		for (target, usn) in self.get_targets() {
+			let message = format!(
+				"NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nCACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nSERVER: {}\r\nNT: {}\r\nNTS: {}\r\nUSN: {}\r\n\r\n",
+				MULTICAST_ADDRESS,
+				PORT,
+				MAX_AGE.as_secs(),
+				location,
+				get_server_name(),
+				target,
+				sub_type,
+				usn,
+			);
+			if let Err(e) = socket.send_to(message.as_bytes(), destination) {
+				error!("Could not send SSDP announcement: {}", e);
+			}
+		}
+	}
+
+	fn get_location(&self, local_ip: IpAddr) -> String {
+		format!(
+			"http://{}:{}{}/dlna/description.xml",
+			local_ip, self.port, self.url_base
+		)
+	}
+
+	// Notification types and unique service names this server answers to
+	fn get_targets(&self) -> Vec<(String, String)> {
+		let uuid = format!("uuid:{}", self.uuid);
+		let mut targets = vec![
+			(
+				"upnp:rootdevice".to_owned(),
+				format!("{uuid}::upnp:rootdevice"),
+			),
+			(uuid.clone(), uuid.clone()),
+		];
+		for target in [DEVICE_TYPE, CONTENT_DIRECTORY_TYPE, CONNECTION_MANAGER_TYPE] {
+			targets.push((target.to_owned(), format!("{uuid}::{target}")));
+		}
+		targets
+	}
+
+	fn get_matching_targets(&self, search_target: &str) -> Vec<(String, String)> {
+		self.get_targets()
+			.into_iter()
+			.filter(|(target, _)| search_target == "ssdp:all" || search_target == target)
+			.collect()
+	}
+}
+
+// Returns the search target of M-SEARCH discovery requests
+fn parse_search_request(request: &str) -> Option<String> {
+	let mut lines = request.lines();
+	if !lines.next()?.starts_with("M-SEARCH ") {
+		return None;
+	}
+	let mut is_discovery = false;
+	let mut search_target = None;
+	for line in lines {
+		let Some((name, value)) = line.split_once(':') else {
+			continue;
+		};
+		let value = value.trim();
+		match name.trim().to_ascii_uppercase().as_str() {
+			"MAN" => is_discovery = value.trim_matches('"') == "ssdp:discover",
+			"ST" => search_target = Some(value.to_owned()),
+			_ => (),
+		}
+	}
+	search_target.filter(|_| is_discovery)
+}
+
+// Address of the network interface which can reach `peer`
+fn get_local_ip(peer: SocketAddr) -> io::Result<IpAddr> {
+	let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+	socket.connect(peer)?;
+	Ok(socket.local_addr()?.ip())
+}
+
+fn get_server_name() -> String {
+	format!(
+		"{}/1.0 UPnP/1.0 DLNADOC/1.50 Polaris/{}",
+		std::env::consts::OS,
+		env!("CARGO_PKG_VERSION")
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parses_search_requests() {
+		let request = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: upnp:rootdevice\r\n\r\n";
+		assert_eq!(
+			parse_search_request(request).as_deref(),
+			Some("upnp:rootdevice")
+		);
+
+		let notify = "NOTIFY * HTTP/1.1\r\nNT: upnp:rootdevice\r\n\r\n";
+		assert_eq!(parse_search_request(notify), None);
+
+		let not_discovery = "M-SEARCH * HTTP/1.1\r\nST: ssdp:all\r\n\r\n";
+		assert_eq!(parse_search_request(not_discovery), None);
+	}
+
+	#[test]
+	fn answers_matching_searches() {
+		let announcer = Announcer::new("1234", 5050, "");
+		assert_eq!(announcer.get_matching_targets("ssdp:all").len(), 5);
+		assert_eq!(
+			announcer.get_matching_targets(DEVICE_TYPE),
+			vec![(DEVICE_TYPE.to_owned(), format!("uuid:1234::{DEVICE_TYPE}"))]
+		);
+		assert_eq!(
+			announcer.get_matching_targets("uuid:1234"),
+			vec![("uuid:1234".to_owned(), "uuid:1234".to_owned())]
+		);
+		assert!(announcer
+			.get_matching_targets("urn:schemas-upnp-org:device:MediaRenderer:1")
+			.is_empty());
+	}
+
+	#[test]
+	fn location_points_to_description() {
+		let announcer = Announcer::new("1234", 5050, "/polaris");
+		assert_eq!(
+			announcer.get_location(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))),
+			"http://192.168.1.2:5050/polaris/dlna/description.xml"
+		);
+	}
+}
@@ -0,0 +1,437 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::vfs::{self, VFS};
+use crate::utils::get_audio_format;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const IMAGE_EXTENSIONS: [&str; 5] = ["bmp", "gif", "jpeg", "jpg", "png"];
+
+// Archives are written without Zip64 extensions, which caps their size and number of entries
+const MAX_ARCHIVE_SIZE: u64 = u32::MAX as u64;
+const MAX_ENTRIES: usize = u16::MAX as usize;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const VERSION: u16 = 20;
+// Sizes and checksums follow each file's data, names are UTF-8
+const FLAGS: u16 = (1 << 3) | (1 << 11);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Archive for `{0}` is too large")]
+	ArchiveTooLarge(PathBuf),
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("`{0}` is not a directory")]
+	NotADirectory(PathBuf),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+struct Entry {
+	real_path: PathBuf,
+	name: String,
+	modified: (u16, u16),
+}
+
+struct CurrentEntry {
+	file: File,
+	hasher: crc32fast::Hasher,
+	size: u64,
+}
+
+// Zip archive of a directory's songs and artwork, produced chunk by chunk as files are read.
+// Files are stored without compression since audio and images barely compress.
+pub struct Archive {
+	entries: std::vec::IntoIter<Entry>,
+	current: Option<(Entry, CurrentEntry)>,
+	central_directory: Vec<u8>,
+	num_entries: usize,
+	offset: u64,
+	finished: bool,
+	virtual_path: PathBuf,
+}
+
+impl Archive {
+	pub fn new(vfs: &VFS, virtual_path: &Path) -> Result<Self, Error> {
+		let real_path = vfs.virtual_to_real(virtual_path)?;
+		if !real_path.is_dir() {
+			return Err(Error::NotADirectory(virtual_path.to_owned()));
+		}
+
+		let root_name = virtual_path
+			.file_name()
+			.map(|n| n.to_string_lossy().to_string())
+			.unwrap_or_default();
+		let mut entries = Vec::new();
+		let mut ancestors = Vec::new();
+		collect_entries(
+			vfs,
+			virtual_path,
+			&real_path,
+			&root_name,
+			&mut ancestors,
+			&mut entries,
+		)?;
+
+		let archive_size: u64 = entries
+			.iter()
+			.map(|e| {
+				let size = fs::metadata(&e.real_path).map(|m| m.len()).unwrap_or(0);
+				size + 2 * e.name.len() as u64 + 30 + 16 + 46
+			})
+			.sum();
+		if entries.len() > MAX_ENTRIES || archive_size > MAX_ARCHIVE_SIZE {
+			return Err(Error::ArchiveTooLarge(virtual_path.to_owned()));
+		}
+
+		Ok(Self {
+			entries: entries.into_iter(),
+			current: None,
+			central_directory: Vec::new(),
+			num_entries: 0,
+			offset: 0,
+			finished: false,
+			virtual_path: virtual_path.to_owned(),
+		})
+	}
+
+	pub fn file_name(&self) -> String {
+		let name = self
+			.virtual_path
+			.file_name()
+			.map(|n| n.to_string_lossy().to_string())
+			.unwrap_or_default();
+		format!("{}.zip", name)
+	}
+
+	fn begin_entry(&mut self, entry: Entry) -> Result<Vec<u8>, Error> {
+		let file =
+			File::open(&entry.real_path).map_err(|e| Error::Io(entry.real_path.clone(), e))?;
+
+		let mut header = Vec::new();
+		put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+		put_u16(&mut header, VERSION);
+		put_u16(&mut header, FLAGS);
+		put_u16(&mut header, 0); // Stored
+		put_u16(&mut header, entry.modified.0);
+		put_u16(&mut header, entry.modified.1);
+		put_u32(&mut header, 0); // CRC-32, in data descriptor
+		put_u32(&mut header, 0); // Compressed size, in data descriptor
+		put_u32(&mut header, 0); // Uncompressed size, in data descriptor
+		put_u16(&mut header, entry.name.len() as u16);
+		put_u16(&mut header, 0); // Extra field length
+		header.extend_from_slice(entry.name.as_bytes());
+
+		let current = CurrentEntry {
+			file,
+			hasher: crc32fast::Hasher::new(),
+			size: 0,
+		};
+		self.current = Some((entry, current));
+		Ok(header)
+	}
+
+	fn end_entry(&mut self, entry: Entry, current: CurrentEntry) -> Result<Vec<u8>, Error> {
+		// Files may have grown since the archive size was checked
+		if current.size > MAX_ARCHIVE_SIZE || self.offset > MAX_ARCHIVE_SIZE {
+			return Err(Error::ArchiveTooLarge(self.virtual_path.clone()));
+		}
+		let crc = current.hasher.finalize();
+		let size = current.size as u32;
+		let header_offset = self.offset as u32;
+
+		let mut descriptor = Vec::new();
+		put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+		put_u32(&mut descriptor, crc);
+		put_u32(&mut descriptor, size);
+		put_u32(&mut descriptor, size);
+
+		let record = &mut self.central_directory;
+		put_u32(record, CENTRAL_HEADER_SIGNATURE);
+		put_u16(record, VERSION); // Version made by
+		put_u16(record, VERSION); // Version needed to extract
+		put_u16(record, FLAGS);
+		put_u16(record, 0); // Stored
+		put_u16(record, entry.modified.0);
+		put_u16(record, entry.modified.1);
+		put_u32(record, crc);
+		put_u32(record, size);
+		put_u32(record, size);
+		put_u16(record, entry.name.len() as u16);
+		put_u16(record, 0); // Extra field length
+		put_u16(record, 0); // Comment length
+		put_u16(record, 0); // Disk number
+		put_u16(record, 0); // Internal attributes
+		put_u32(record, 0); // External attributes
+		put_u32(record, header_offset);
+		record.extend_from_slice(entry.name.as_bytes());
+		self.num_entries += 1;
+
+		self.offset += 30 + entry.name.len() as u64 + current.size + descriptor.len() as u64;
+		Ok(descriptor)
+	}
+
+	fn end_archive(&mut self) -> Result<Vec<u8>, Error> {
+		if self.offset > MAX_ARCHIVE_SIZE {
+			return Err(Error::ArchiveTooLarge(self.virtual_path.clone()));
+		}
+		let mut end = std::mem::take(&mut self.central_directory);
+		let central_directory_size = end.len() as u32;
+		put_u32(&mut end, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+		put_u16(&mut end, 0); // Disk number
+		put_u16(&mut end, 0); // Disk with central directory
+		put_u16(&mut end, self.num_entries as u16);
+		put_u16(&mut end, self.num_entries as u16);
+		put_u32(&mut end, central_directory_size);
+		put_u32(&mut end, self.offset as u32);
+		put_u16(&mut end, 0); // Comment length
+		Ok(end)
+	}
+
+	fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, Error> {
+		if let Some((entry, current)) = self.current.as_mut() {
+			let mut buffer = vec![0; CHUNK_SIZE];
+			let read = current
+				.file
+				.read(&mut buffer)
+				.map_err(|e| Error::Io(entry.real_path.clone(), e))?;
+			if read > 0 {
+				buffer.truncate(read);
+				current.hasher.update(&buffer);
+				current.size += read as u64;
+				return Ok(Some(buffer));
+			}
+			let (entry, current) = self.current.take().unwrap();
+			return self.end_entry(entry, current).map(Some);
+		}
+
+		if let Some(entry) = self.entries.next() {
+			return self.begin_entry(entry).map(Some);
+		}
+
+		if !self.finished {
+			self.finished = true;
+			return self.end_archive().map(Some);
+		}
+
+		Ok(None)
+	}
+}
+
+impl Iterator for Archive {
+	type Item = Result<Vec<u8>, Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let chunk = self.next_chunk();
+		if chunk.is_err() {
+			self.finished = true;
+			self.current = None;
+			self.entries = Vec::new().into_iter();
+		}
+		chunk.transpose()
+	}
+}
+
+fn is_archived(path: &Path) -> bool {
+	if get_audio_format(path).is_some() {
+		return true;
+	}
+	path.extension()
+		.and_then(|e| e.to_str())
+		.map(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+		.unwrap_or(false)
+}
+
+fn collect_entries(
+	vfs: &VFS,
+	virtual_path: &Path,
+	real_path: &Path,
+	name_prefix: &str,
+	ancestors: &mut Vec<PathBuf>,
+	entries: &mut Vec<Entry>,
+) -> Result<(), Error> {
+	// Symlinks leading back up the tree would otherwise be followed forever
+	let canonical_path = real_path
+		.canonicalize()
+		.map_err(|e| Error::Io(real_path.to_owned(), e))?;
+	if ancestors.contains(&canonical_path) {
+		return Ok(());
+	}
+	ancestors.push(canonical_path);
+
+	let mut children = fs::read_dir(real_path)
+		.map_err(|e| Error::Io(real_path.to_owned(), e))?
+		.filter_map(|e| e.ok())
+		.map(|e| e.file_name())
+		.collect::<Vec<_>>();
+	children.sort();
+
+	for child in children {
+		let child_virtual_path = virtual_path.join(&child);
+		// Goes through the VFS so symlinks obey the mount's policy
+		let Ok(child_real_path) = vfs.virtual_to_real(&child_virtual_path) else {
+			continue;
+		};
+		let child_name = match name_prefix {
+			"" => child.to_string_lossy().to_string(),
+			_ => format!("{}/{}", name_prefix, child.to_string_lossy()),
+		};
+		if child_real_path.is_dir() {
+			collect_entries(
+				vfs,
+				&child_virtual_path,
+				&child_real_path,
+				&child_name,
+				ancestors,
+				entries,
+			)?;
+		} else if is_archived(&child_real_path) {
+			let modified = fs::metadata(&child_real_path)
+				.and_then(|m| m.modified())
+				.map(to_dos_date_time)
+				.unwrap_or(DOS_EPOCH);
+			entries.push(Entry {
+				real_path: child_real_path,
+				name: child_name,
+				modified,
+			});
+		}
+	}
+
+	ancestors.pop();
+	Ok(())
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+	buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+	buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+// Time and date fields of 1980-01-01 00:00, the earliest value zip archives can represent
+const DOS_EPOCH: (u16, u16) = (0, (1 << 5) | 1);
+
+// Converts to MS-DOS time and date fields, in UTC
+fn to_dos_date_time(time: SystemTime) -> (u16, u16) {
+	let Ok(since_epoch) = time.duration_since(UNIX_EPOCH) else {
+		return DOS_EPOCH;
+	};
+	let seconds = since_epoch.as_secs();
+	let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+
+	// Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+	let z = days + 719468;
+	let era = z / 146097;
+	let day_of_era = z % 146097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	if !(1980..=2107).contains(&year) {
+		return DOS_EPOCH;
+	}
+	let time = ((seconds_of_day / 3600) << 11)
+		| (((seconds_of_day % 3600) / 60) << 5)
+		| ((seconds_of_day % 60) / 2);
+	let date = ((year - 1980) << 9) | (month << 5) | day;
+	(time as u16, date as u16)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::vfs::Mount;
+
+	fn make_vfs() -> VFS {
+		VFS::new(vec![Mount {
+			source: PathBuf::from("test-data/small-collection"),
+			name: "root".to_owned(),
+			follow_symlinks: false,
+			content_type: Default::default(),
+			album_art_pattern: None,
+			art_overlay: None,
+		}])
+	}
+
+	fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+		u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+	}
+
+	fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+		u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+	}
+
+	#[test]
+	fn archives_songs_and_artwork() {
+		let archive = Archive::new(&make_vfs(), Path::new("root/Khemmis")).unwrap();
+		assert_eq!(archive.file_name(), "Khemmis.zip");
+		let bytes = archive.collect::<Result<Vec<_>, _>>().unwrap().concat();
+
+		let end = bytes.len() - 22;
+		assert_eq!(read_u32(&bytes, end), END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+		assert_eq!(read_u16(&bytes, end + 10), 6);
+
+		let mut names = Vec::new();
+		let mut offset = read_u32(&bytes, end + 16) as usize;
+		while offset < end {
+			assert_eq!(read_u32(&bytes, offset), CENTRAL_HEADER_SIGNATURE);
+			let size = read_u32(&bytes, offset + 24) as usize;
+			let name_length = read_u16(&bytes, offset + 28) as usize;
+			let name = &bytes[offset + 46..offset + 46 + name_length];
+			let name = String::from_utf8(name.to_vec()).unwrap();
+
+			let header_offset = read_u32(&bytes, offset + 42) as usize;
+			assert_eq!(read_u32(&bytes, header_offset), LOCAL_HEADER_SIGNATURE);
+			let data_offset = header_offset + 30 + name_length;
+			let data = &bytes[data_offset..data_offset + size];
+			let expected = fs::read(Path::new("test-data/small-collection").join(&name)).unwrap();
+			assert!(data == expected.as_slice());
+			assert_eq!(read_u32(&bytes, offset + 16), crc32fast::hash(&expected));
+
+			names.push(name);
+			offset += 46 + name_length;
+		}
+		assert_eq!(
+			names,
+			vec![
+				"Khemmis/Hunted/01 - Above The Water.mp3",
+				"Khemmis/Hunted/02 - Candlelight.mp3",
+				"Khemmis/Hunted/03 - Three Gates.mp3",
+				"Khemmis/Hunted/04 - Beyond The Door.mp3",
+				"Khemmis/Hunted/05 - Hunted.mp3",
+				"Khemmis/Hunted/Folder.jpg",
+			]
+		);
+	}
+
+	#[test]
+	fn rejects_files() {
+		let path = Path::new("root/Khemmis/Hunted/05 - Hunted.mp3");
+		assert!(matches!(
+			Archive::new(&make_vfs(), path),
+			Err(Error::NotADirectory(_))
+		));
+	}
+
+	#[test]
+	fn converts_dos_date_time() {
+		// 2016-06-10 13:45:30 UTC
+		let time = UNIX_EPOCH + std::time::Duration::from_secs(1465566330);
+		let (time, date) = to_dos_date_time(time);
+		assert_eq!(time, (13 << 11) | (45 << 5) | 15);
+		assert_eq!(date, (36 << 9) | (6 << 5) | 10);
+		assert_eq!(to_dos_date_time(UNIX_EPOCH), DOS_EPOCH);
+	}
+}
@@ -0,0 +1,256 @@
+use diesel::prelude::*;
+use log::error;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::app::{fingerprint, vfs};
+use crate::db::{self, song_fingerprints, songs, DB};
+
+// Copies of a recording in different formats can differ slightly in length
+const DURATION_TOLERANCE_SECONDS: i32 = 2;
+// Share of identical fingerprint bits above which two songs are the same recording
+const MIN_SIMILARITY: f32 = 0.85;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error(transparent)]
+	Fingerprint(#[from] fingerprint::Error),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Duplicate {
+	pub path: PathBuf,
+	pub duration: i32,
+	pub file_size: Option<i64>,
+}
+
+#[derive(Queryable)]
+struct Candidate {
+	path: String,
+	duration: i32,
+	file_modified: i64,
+	file_size: Option<i64>,
+	content_hash: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	/// Groups of songs which sound like the same recording, regardless of their format or
+	/// mount. Fingerprints are kept so later reports only process new or modified files.
+	pub fn find_duplicates(&self) -> Result<Vec<Vec<Duplicate>>, Error> {
+		let candidates = self.get_candidates()?;
+		let mut fingerprints = self.read_fingerprints()?;
+
+		let mut get_fingerprint = |candidate: &Candidate| -> Result<Option<Vec<u32>>, Error> {
+			if let Some((modified, fingerprint)) = fingerprints.get(&candidate.path) {
+				if *modified == candidate.file_modified {
+					return Ok(Some(fingerprint.clone()));
+				}
+			}
+			let fingerprint = match fingerprint::compute_raw(Path::new(&candidate.path)) {
+				Ok(f) => f,
+				Err(e @ fingerprint::Error::FpcalcNotFound(_, _)) => return Err(e.into()),
+				Err(e) => {
+					error!("Could not fingerprint `{}`: {}", candidate.path, e);
+					return Ok(None);
+				}
+			};
+			self.write_fingerprint(candidate, &fingerprint)?;
+			fingerprints.insert(
+				candidate.path.clone(),
+				(candidate.file_modified, fingerprint.clone()),
+			);
+			Ok(Some(fingerprint))
+		};
+
+		let groups = group_similar(&candidates, |a, b| -> Result<bool, Error> {
+			// Copies of the same file are found without fingerprinting them
+			if a.content_hash.is_some() && a.content_hash == b.content_hash {
+				return Ok(true);
+			}
+			let (Some(a), Some(b)) = (get_fingerprint(a)?, get_fingerprint(b)?) else {
+				return Ok(false);
+			};
+			Ok(fingerprint::similarity(&a, &b) >= MIN_SIMILARITY)
+		})?;
+
+		let vfs = self.vfs_manager.get_vfs()?;
+		let mut duplicates = groups
+			.into_iter()
+			.map(|group| {
+				let mut songs = group
+					.into_iter()
+					.filter_map(|c| {
+						let path = vfs.real_to_virtual(Path::new(&c.path)).ok()?;
+						Some(Duplicate {
+							path,
+							duration: c.duration,
+							file_size: c.file_size,
+						})
+					})
+					.collect::<Vec<_>>();
+				songs.sort_by(|a, b| a.path.cmp(&b.path));
+				songs
+			})
+			.filter(|songs| songs.len() > 1)
+			.collect::<Vec<_>>();
+		duplicates.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+		Ok(duplicates)
+	}
+
+	// Songs cut from a cue sheet cannot be fingerprinted on their own
+	fn get_candidates(&self) -> Result<Vec<Candidate>, Error> {
+		let mut connection = self.db.connect()?;
+		let candidates = songs::table
+			.filter(songs::cue_source.is_null())
+			.filter(songs::duration.is_not_null())
+			.filter(songs::file_modified.is_not_null())
+			.select((
+				songs::path,
+				songs::duration.assume_not_null(),
+				songs::file_modified.assume_not_null(),
+				songs::file_size,
+				songs::content_hash,
+			))
+			.order_by(songs::duration)
+			.load(&mut connection)?;
+		Ok(candidates)
+	}
+
+	fn read_fingerprints(&self) -> Result<HashMap<String, (i64, Vec<u32>)>, Error> {
+		let mut connection = self.db.connect()?;
+		diesel::delete(
+			song_fingerprints::table
+				.filter(song_fingerprints::path.ne_all(songs::table.select(songs::path))),
+		)
+		.execute(&mut connection)?;
+		let fingerprints: Vec<(String, i64, Vec<u8>)> = song_fingerprints::table
+			.select((
+				song_fingerprints::path,
+				song_fingerprints::file_modified,
+				song_fingerprints::fingerprint,
+			))
+			.load(&mut connection)?;
+		Ok(fingerprints
+			.into_iter()
+			.map(|(path, modified, bytes)| {
+				let fingerprint = bytes
+					.chunks_exact(4)
+					.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+					.collect();
+				(path, (modified, fingerprint))
+			})
+			.collect())
+	}
+
+	fn write_fingerprint(&self, candidate: &Candidate, fingerprint: &[u32]) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		let bytes = fingerprint
+			.iter()
+			.flat_map(|v| v.to_le_bytes())
+			.collect::<Vec<_>>();
+		diesel::insert_into(song_fingerprints::table)
+			.values((
+				song_fingerprints::path.eq(&candidate.path),
+				song_fingerprints::file_modified.eq(candidate.file_modified),
+				song_fingerprints::fingerprint.eq(bytes),
+			))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+}
+
+// Only songs of about the same duration are compared, candidates must be sorted by duration
+fn group_similar<E>(
+	candidates: &[Candidate],
+	mut is_similar: impl FnMut(&Candidate, &Candidate) -> Result<bool, E>,
+) -> Result<Vec<Vec<&Candidate>>, E> {
+	let mut parents = (0..candidates.len()).collect::<Vec<_>>();
+	fn find(parents: &mut [usize], i: usize) -> usize {
+		let mut root = i;
+		while parents[root] != root {
+			root = parents[root];
+		}
+		parents[i] = root;
+		root
+	}
+
+	for (i, a) in candidates.iter().enumerate() {
+		for (j, b) in candidates.iter().enumerate().skip(i + 1) {
+			if b.duration - a.duration > DURATION_TOLERANCE_SECONDS {
+				break;
+			}
+			if find(&mut parents, i) == find(&mut parents, j) {
+				continue;
+			}
+			if is_similar(a, b)? {
+				let root = find(&mut parents, j);
+				parents[root] = find(&mut parents, i);
+			}
+		}
+	}
+
+	let mut groups: HashMap<usize, Vec<&Candidate>> = HashMap::new();
+	for (i, candidate) in candidates.iter().enumerate() {
+		let root = find(&mut parents, i);
+		groups.entry(root).or_default().push(candidate);
+	}
+	Ok(groups.into_values().filter(|g| g.len() > 1).collect())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn candidate(path: &str, duration: i32) -> Candidate {
+		Candidate {
+			path: path.to_owned(),
+			duration,
+			file_modified: 0,
+			file_size: None,
+			content_hash: None,
+		}
+	}
+
+	#[test]
+	fn groups_similar_songs_of_similar_durations() {
+		let candidates = vec![
+			candidate("a.flac", 200),
+			candidate("a.mp3", 201),
+			candidate("b.flac", 201),
+			candidate("a.ogg", 202),
+			candidate("a_radio_edit.mp3", 210),
+		];
+		let mut comparisons = 0;
+		let groups = group_similar(&candidates, |a, b| {
+			comparisons += 1;
+			Ok::<_, ()>(a.path.starts_with("a.") && b.path.starts_with("a."))
+		})
+		.unwrap();
+
+		assert_eq!(groups.len(), 1);
+		let mut paths = groups[0]
+			.iter()
+			.map(|c| c.path.as_str())
+			.collect::<Vec<_>>();
+		paths.sort();
+		assert_eq!(paths, vec!["a.flac", "a.mp3", "a.ogg"]);
+		// Songs far apart in duration and songs already grouped are not compared
+		assert_eq!(comparisons, 5);
+	}
+}
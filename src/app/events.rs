@@ -0,0 +1,96 @@
+use futures_channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use std::sync::{Arc, Mutex};
+
+/// Changes clients may want to reflect without polling the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+	IndexUpdateStarted,
+	IndexUpdateCompleted,
+	PlaylistsChanged { username: String },
+	QueueChanged { username: String },
+	NowPlaying { username: String, path: String },
+}
+
+impl Event {
+	// Events about a user's activity are only sent to that user
+	fn recipient(&self) -> Option<&str> {
+		match self {
+			Event::IndexUpdateStarted | Event::IndexUpdateCompleted => None,
+			Event::PlaylistsChanged { username } => Some(username),
+			Event::QueueChanged { username } => Some(username),
+			Event::NowPlaying { username, .. } => Some(username),
+		}
+	}
+}
+
+struct Subscriber {
+	username: String,
+	sender: UnboundedSender<Event>,
+}
+
+#[derive(Clone, Default)]
+pub struct Manager {
+	subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl Manager {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// Subscriptions end when their receiver is dropped
+	pub fn subscribe(&self, username: &str) -> UnboundedReceiver<Event> {
+		let (sender, receiver) = mpsc::unbounded();
+		self.subscribers.lock().unwrap().push(Subscriber {
+			username: username.to_owned(),
+			sender,
+		});
+		receiver
+	}
+
+	pub fn publish(&self, event: Event) {
+		let recipient = event.recipient();
+		self.subscribers.lock().unwrap().retain(|s| {
+			if recipient.is_some_and(|r| r != s.username) {
+				return !s.sender.is_closed();
+			}
+			s.sender.unbounded_send(event.clone()).is_ok()
+		});
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn user_events_only_reach_their_user() {
+		let manager = Manager::new();
+		let mut alice = manager.subscribe("alice");
+		let mut bob = manager.subscribe("bob");
+
+		manager.publish(Event::IndexUpdateStarted);
+		manager.publish(Event::PlaylistsChanged {
+			username: "alice".to_owned(),
+		});
+
+		assert_eq!(alice.try_next().unwrap(), Some(Event::IndexUpdateStarted));
+		assert_eq!(
+			alice.try_next().unwrap(),
+			Some(Event::PlaylistsChanged {
+				username: "alice".to_owned()
+			})
+		);
+		assert_eq!(bob.try_next().unwrap(), Some(Event::IndexUpdateStarted));
+		assert!(bob.try_next().is_err());
+	}
+
+	#[test]
+	fn closed_subscriptions_are_dropped() {
+		let manager = Manager::new();
+		let receiver = manager.subscribe("alice");
+		drop(receiver);
+		manager.publish(Event::IndexUpdateStarted);
+		assert!(manager.subscribers.lock().unwrap().is_empty());
+	}
+}
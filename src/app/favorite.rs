@@ -0,0 +1,228 @@
+use diesel::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::app::index::{CollectionFile, Directory, Song};
+use crate::app::vfs;
+use crate::db::{self, directories, favorites, songs, users, DB};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("No song or directory at `{0}`")]
+	FileNotFound(PathBuf),
+	#[error("`{0}` is not a favorite")]
+	FavoriteNotFound(PathBuf),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = favorites)]
+struct NewFavorite {
+	user: i32,
+	path: String,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	pub fn add_favorite(&self, username: &str, virtual_path: &Path) -> Result<(), Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let real_path = vfs.virtual_to_real(virtual_path)?;
+		let real_path_string = real_path.to_string_lossy().into_owned();
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let num_songs: i64 = songs::table
+			.filter(songs::path.eq(&real_path_string))
+			.count()
+			.get_result(&mut connection)?;
+		let num_directories: i64 = directories::table
+			.filter(directories::path.eq(&real_path_string))
+			.count()
+			.get_result(&mut connection)?;
+		if num_songs + num_directories == 0 {
+			return Err(Error::FileNotFound(virtual_path.to_owned()));
+		}
+
+		diesel::insert_into(favorites::table)
+			.values(&NewFavorite {
+				user: user_id,
+				path: real_path_string,
+			})
+			.execute(&mut connection)?;
+
+		Ok(())
+	}
+
+	pub fn remove_favorite(&self, username: &str, virtual_path: &Path) -> Result<(), Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let real_path = vfs.virtual_to_real(virtual_path)?;
+		let real_path_string = real_path.to_string_lossy().into_owned();
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let q = favorites::table
+			.filter(favorites::user.eq(user_id))
+			.filter(favorites::path.eq(real_path_string));
+		match diesel::delete(q).execute(&mut connection)? {
+			0 => Err(Error::FavoriteNotFound(virtual_path.to_owned())),
+			_ => Ok(()),
+		}
+	}
+
+	pub fn list_favorites(&self, username: &str) -> Result<Vec<CollectionFile>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let favorite_paths = favorites::table
+			.filter(favorites::user.eq(user_id))
+			.select(favorites::path);
+
+		let real_directories: Vec<Directory> = directories::table
+			.filter(directories::path.eq_any(favorite_paths))
+			.order(directories::path)
+			.load(&mut connection)?;
+		let virtual_directories = real_directories
+			.into_iter()
+			.filter_map(|d| d.virtualize(&vfs))
+			.map(CollectionFile::Directory);
+
+		let real_songs: Vec<Song> = songs::table
+			.filter(songs::path.eq_any(favorite_paths))
+			.order(songs::path)
+			.load(&mut connection)?;
+		let virtual_songs = real_songs
+			.into_iter()
+			.filter_map(|s| s.virtualize(&vfs))
+			.map(CollectionFile::Song);
+
+		Ok(virtual_directories.chain(virtual_songs).collect())
+	}
+
+	// Virtual paths of all the songs and directories a user marked as favorite
+	pub fn get_favorite_paths(&self, username: &str) -> Result<HashSet<String>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let real_paths: Vec<String> = favorites::table
+			.filter(favorites::user.eq(user_id))
+			.select(favorites::path)
+			.load(&mut connection)?;
+
+		Ok(real_paths
+			.into_iter()
+			.filter_map(|p| vfs.real_to_virtual(Path::new(&p)).ok())
+			.map(|p| p.to_string_lossy().into_owned())
+			.collect())
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	#[test]
+	fn add_and_list_favorites() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let album: PathBuf = [TEST_MOUNT_NAME, "Tobokegao", "Picnic"].iter().collect();
+		let song: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect();
+		ctx.favorite_manager.add_favorite(TEST_USER, &song).unwrap();
+		ctx.favorite_manager
+			.add_favorite(TEST_USER, &album)
+			.unwrap();
+		ctx.favorite_manager.add_favorite(TEST_USER, &song).unwrap();
+
+		let favorites = ctx.favorite_manager.list_favorites(TEST_USER).unwrap();
+		assert_eq!(favorites.len(), 2);
+		assert!(
+			matches!(&favorites[0], CollectionFile::Directory(d) if album == Path::new(&d.path))
+		);
+		assert!(matches!(&favorites[1], CollectionFile::Song(s) if song == Path::new(&s.path)));
+
+		let paths = ctx.favorite_manager.get_favorite_paths(TEST_USER).unwrap();
+		assert!(paths.contains(song.to_string_lossy().as_ref()));
+		assert!(paths.contains(album.to_string_lossy().as_ref()));
+	}
+
+	#[test]
+	fn remove_favorite_golden_path() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let song: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect();
+		ctx.favorite_manager.add_favorite(TEST_USER, &song).unwrap();
+		ctx.favorite_manager
+			.remove_favorite(TEST_USER, &song)
+			.unwrap();
+
+		assert!(ctx
+			.favorite_manager
+			.list_favorites(TEST_USER)
+			.unwrap()
+			.is_empty());
+		assert!(matches!(
+			ctx.favorite_manager.remove_favorite(TEST_USER, &song),
+			Err(Error::FavoriteNotFound(_))
+		));
+	}
+
+	#[test]
+	fn cannot_favorite_unknown_file() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+
+		let song: PathBuf = [TEST_MOUNT_NAME, "missing.mp3"].iter().collect();
+		assert!(matches!(
+			ctx.favorite_manager.add_favorite(TEST_USER, &song),
+			Err(Error::FileNotFound(_))
+		));
+	}
+}
@@ -0,0 +1,125 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const DEFAULT_FPCALC_PATH: &str = "fpcalc";
+// Each value of a raw fingerprint covers about an eighth of a second, this allows
+// fingerprints to be offset by up to half a second
+const MAX_OFFSET: usize = 4;
+// Fingerprints overlapping less than this many values are too short to compare
+const MIN_OVERLAP: usize = 16;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Could not run fpcalc from `{0}`: `{1}`")]
+	FpcalcNotFound(PathBuf, std::io::Error),
+	#[error("fpcalc could not fingerprint `{0}`:\n\n{1}")]
+	Fpcalc(PathBuf, String),
+}
+
+/// Chromaprint fingerprint, compressed the way AcoustID expects it.
+#[derive(Deserialize)]
+pub struct Fingerprint {
+	pub duration: f64,
+	pub fingerprint: String,
+}
+
+#[derive(Deserialize)]
+struct RawFingerprint {
+	// Older versions of fpcalc print these as signed integers
+	fingerprint: Vec<i64>,
+}
+
+pub fn get_fpcalc_path() -> PathBuf {
+	std::env::var_os("POLARIS_FPCALC_PATH")
+		.map(PathBuf::from)
+		.unwrap_or_else(|| PathBuf::from(DEFAULT_FPCALC_PATH))
+}
+
+fn run_fpcalc(fpcalc_path: &Path, audio_path: &Path, raw: bool) -> Result<Vec<u8>, Error> {
+	let mut command = Command::new(fpcalc_path);
+	command.arg("-json");
+	if raw {
+		command.arg("-raw");
+	}
+	let output = command
+		.arg(audio_path)
+		.stdin(Stdio::null())
+		.output()
+		.map_err(|e| Error::FpcalcNotFound(fpcalc_path.to_owned(), e))?;
+	if !output.status.success() {
+		let message = String::from_utf8_lossy(&output.stderr).into_owned();
+		return Err(Error::Fpcalc(audio_path.to_owned(), message));
+	}
+	Ok(output.stdout)
+}
+
+pub fn compute(audio_path: &Path) -> Result<Fingerprint, Error> {
+	let output = run_fpcalc(&get_fpcalc_path(), audio_path, false)?;
+	serde_json::from_slice(&output).map_err(|e| Error::Fpcalc(audio_path.to_owned(), e.to_string()))
+}
+
+/// Uncompressed fingerprint of the beginning of a song, suitable for comparisons.
+pub fn compute_raw(audio_path: &Path) -> Result<Vec<u32>, Error> {
+	let output = run_fpcalc(&get_fpcalc_path(), audio_path, true)?;
+	let raw: RawFingerprint = serde_json::from_slice(&output)
+		.map_err(|e| Error::Fpcalc(audio_path.to_owned(), e.to_string()))?;
+	Ok(raw.fingerprint.into_iter().map(|v| v as u32).collect())
+}
+
+/// Share of identical bits between two raw fingerprints, from 0 to 1, at the offset where
+/// they line up best.
+pub fn similarity(a: &[u32], b: &[u32]) -> f32 {
+	let compare = |a: &[u32], b: &[u32], offset: usize| {
+		let overlap = a.len().saturating_sub(offset).min(b.len());
+		if overlap < MIN_OVERLAP {
+			return 0.0;
+		}
+		let errors: u32 = a[offset..offset + overlap]
+			.iter()
+			.zip(b)
+			.map(|(x, y)| (x ^ y).count_ones())
+			.sum();
+		1.0 - errors as f32 / (overlap * 32) as f32
+	};
+	(0..=MAX_OFFSET)
+		.flat_map(|offset| [compare(a, b, offset), compare(b, a, offset)])
+		.fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn noise(mut seed: u32) -> Vec<u32> {
+		(0..64)
+			.map(|_| {
+				seed ^= seed << 13;
+				seed ^= seed >> 17;
+				seed ^= seed << 5;
+				seed
+			})
+			.collect()
+	}
+
+	#[test]
+	fn similarity_allows_offsets() {
+		let a = noise(1);
+		assert_eq!(similarity(&a, &a), 1.0);
+		assert_eq!(similarity(&a[2..], &a), 1.0);
+		assert_eq!(similarity(&a, &a[3..]), 1.0);
+
+		assert!(similarity(&a, &noise(2)) < 0.6);
+		assert_eq!(similarity(&a[..8], &a[..8]), 0.0);
+	}
+
+	#[test]
+	fn reports_missing_fpcalc() {
+		let fpcalc_path = Path::new("test-data/not-fpcalc");
+		let audio_path = Path::new("test-data/formats/sample.flac");
+		assert!(matches!(
+			run_fpcalc(fpcalc_path, audio_path, true),
+			Err(Error::FpcalcNotFound(_, _))
+		));
+	}
+}
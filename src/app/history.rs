@@ -0,0 +1,241 @@
+use diesel::prelude::*;
+use diesel::sql_types;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::index::Song;
+use crate::app::vfs;
+use crate::db::{self, listens, songs, users, DB};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Song not found: `{0}`")]
+	SongNotFound(PathBuf),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+#[derive(Debug, PartialEq, QueryableByName, Serialize, Deserialize)]
+pub struct PlayCount {
+	#[diesel(embed)]
+	#[serde(flatten)]
+	pub song: Song,
+	#[diesel(sql_type = sql_types::BigInt)]
+	pub play_count: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = listens)]
+struct NewListen {
+	user: i32,
+	path: String,
+	timestamp: i64,
+	completion: Option<i32>,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	// Completion is the percentage of the song which was listened to, when known
+	pub fn record_listen(
+		&self,
+		username: &str,
+		virtual_path: &Path,
+		completion: Option<u8>,
+	) -> Result<(), Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let real_path = vfs.virtual_to_real(virtual_path)?;
+		let real_path_string = real_path.to_string_lossy().into_owned();
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let song_exists: i64 = songs::table
+			.filter(songs::path.eq(&real_path_string))
+			.count()
+			.get_result(&mut connection)?;
+		if song_exists == 0 {
+			return Err(Error::SongNotFound(virtual_path.to_owned()));
+		}
+
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs() as i64)
+			.unwrap_or_default();
+
+		diesel::insert_into(listens::table)
+			.values(&NewListen {
+				user: user_id,
+				path: real_path_string,
+				timestamp,
+				completion: completion.map(|c| c.min(100) as i32),
+			})
+			.execute(&mut connection)?;
+
+		Ok(())
+	}
+
+	// Each song appears once, ordered by the last time it was listened to
+	pub fn get_recently_played(&self, username: &str, count: i64) -> Result<Vec<Song>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let query = diesel::sql_query(
+			r#"
+			SELECT s.id, s.path, s.parent, s.track_number, s.disc_number, s.title, s.artist, s.album_artist, s.year, s.album, s.artwork, s.duration, s.lyricist, s.composer, s.genre, s.label, s.file_modified, s.file_size, s.has_embedded_artwork, s.cue_source, s.cue_start, s.cue_end, s.disc_total, s.compilation, s.compilation_tag, s.conductor, s.artist_sort, s.album_artist_sort, s.album_sort, s.title_sort, s.track_gain, s.track_peak, s.album_gain, s.album_peak, s.lyrics, s.has_embedded_lyrics, s.musicbrainz_recording_id, s.musicbrainz_release_id, s.musicbrainz_artist_id, s.musicbrainz_album_artist_id, s.content_hash, s.sample_rate, s.encoder_delay, s.encoder_padding, s.sample_count
+			FROM listens l
+			INNER JOIN songs s ON l.path = s.path
+			WHERE l.user = ?
+			GROUP BY s.id
+			ORDER BY MAX(l.timestamp) DESC, MAX(l.id) DESC
+			LIMIT ?
+		"#,
+		);
+		let songs: Vec<Song> = query
+			.bind::<sql_types::Integer, _>(user_id)
+			.bind::<sql_types::BigInt, _>(count)
+			.get_results(&mut connection)?;
+
+		Ok(songs
+			.into_iter()
+			.filter_map(|s| s.virtualize(&vfs))
+			.collect())
+	}
+
+	pub fn get_most_played(&self, username: &str, count: i64) -> Result<Vec<PlayCount>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let query = diesel::sql_query(
+			r#"
+			SELECT s.id, s.path, s.parent, s.track_number, s.disc_number, s.title, s.artist, s.album_artist, s.year, s.album, s.artwork, s.duration, s.lyricist, s.composer, s.genre, s.label, s.file_modified, s.file_size, s.has_embedded_artwork, s.cue_source, s.cue_start, s.cue_end, s.disc_total, s.compilation, s.compilation_tag, s.conductor, s.artist_sort, s.album_artist_sort, s.album_sort, s.title_sort, s.track_gain, s.track_peak, s.album_gain, s.album_peak, s.lyrics, s.has_embedded_lyrics, s.musicbrainz_recording_id, s.musicbrainz_release_id, s.musicbrainz_artist_id, s.musicbrainz_album_artist_id, s.content_hash, s.sample_rate, s.encoder_delay, s.encoder_padding, s.sample_count, COUNT(l.id) AS play_count
+			FROM listens l
+			INNER JOIN songs s ON l.path = s.path
+			WHERE l.user = ?
+			GROUP BY s.id
+			ORDER BY play_count DESC, MAX(l.timestamp) DESC
+			LIMIT ?
+		"#,
+		);
+		let play_counts: Vec<PlayCount> = query
+			.bind::<sql_types::Integer, _>(user_id)
+			.bind::<sql_types::BigInt, _>(count)
+			.get_results(&mut connection)?;
+
+		Ok(play_counts
+			.into_iter()
+			.filter_map(|p| {
+				Some(PlayCount {
+					song: p.song.virtualize(&vfs)?,
+					play_count: p.play_count,
+				})
+			})
+			.collect())
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn song_path(name: &str) -> PathBuf {
+		[TEST_MOUNT_NAME, "Khemmis", "Hunted", name]
+			.iter()
+			.collect()
+	}
+
+	#[test]
+	fn recently_played_lists_each_song_once() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let first = song_path("01 - Above The Water.mp3");
+		let second = song_path("02 - Candlelight.mp3");
+		for path in [&first, &second, &first] {
+			ctx.history_manager
+				.record_listen(TEST_USER, path, Some(100))
+				.unwrap();
+		}
+
+		let songs = ctx
+			.history_manager
+			.get_recently_played(TEST_USER, 10)
+			.unwrap();
+		let paths: Vec<PathBuf> = songs.iter().map(|s| PathBuf::from(&s.path)).collect();
+		assert_eq!(paths, vec![first, second]);
+	}
+
+	#[test]
+	fn most_played_counts_listens() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let first = song_path("01 - Above The Water.mp3");
+		let second = song_path("02 - Candlelight.mp3");
+		for path in [&first, &second, &second] {
+			ctx.history_manager
+				.record_listen(TEST_USER, path, None)
+				.unwrap();
+		}
+
+		let play_counts = ctx.history_manager.get_most_played(TEST_USER, 1).unwrap();
+		assert_eq!(play_counts.len(), 1);
+		assert_eq!(PathBuf::from(&play_counts[0].song.path), second);
+		assert_eq!(play_counts[0].play_count, 2);
+	}
+
+	#[test]
+	fn cannot_record_listen_of_unknown_song() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+
+		assert!(matches!(
+			ctx.history_manager
+				.record_listen(TEST_USER, &song_path("missing.mp3"), None)
+				.unwrap_err(),
+			Error::SongNotFound(_)
+		));
+	}
+}
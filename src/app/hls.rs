@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Bitrates offered to HLS clients, in kbps. Clients switch between them from one segment to
+/// the next as their connection allows.
+pub const BITRATES: [u32; 3] = [64, 128, 256];
+/// Length of each segment, in seconds.
+pub const SEGMENT_DURATION: u32 = 6;
+pub const PLAYLIST_MIME_TYPE: &str = "application/vnd.apple.mpegurl";
+pub const SEGMENT_MIME_TYPE: &str = "video/mp2t";
+pub const PLAYLIST_FILE_NAME: &str = "index.m3u8";
+// AAC-LC, which every HLS client can play
+const CODECS: &str = "mp4a.40.2";
+// Share of MPEG-TS packet headers on top of the audio bitrate
+const CONTAINER_OVERHEAD: f32 = 1.1;
+
+/// Song transcoded at a single bitrate and cut into segments, as written to disk by ffmpeg.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stream {
+	directory: PathBuf,
+	pub segment_durations: Vec<f32>, // Seconds
+}
+
+impl Stream {
+	pub fn read(directory: &Path) -> std::io::Result<Self> {
+		let playlist = fs::read_to_string(directory.join(PLAYLIST_FILE_NAME))?;
+		Ok(Self {
+			directory: directory.to_owned(),
+			segment_durations: parse_segment_durations(&playlist),
+		})
+	}
+
+	/// Stream which ffmpeg is still writing, cut into full segments from a duration in
+	/// milliseconds.
+	pub fn estimate(directory: &Path, duration: u32) -> Self {
+		let segment_duration = SEGMENT_DURATION * 1000;
+		let count = duration.div_ceil(segment_duration).max(1);
+		let segment_durations = (0..count)
+			.map(|i| (duration - i * segment_duration).min(segment_duration) as f32 / 1000.0)
+			.collect();
+		Self {
+			directory: directory.to_owned(),
+			segment_durations,
+		}
+	}
+
+	pub fn segment_path(&self, index: usize) -> Option<PathBuf> {
+		(index < self.segment_durations.len())
+			.then(|| self.directory.join(segment_file_name(index)))
+	}
+
+	/// Media playlist listing the segments of this stream.
+	pub fn playlist(&self, segment_uri: impl Fn(usize) -> String) -> String {
+		let target_duration = self
+			.segment_durations
+			.iter()
+			.fold(SEGMENT_DURATION as f32, |max, d| max.max(*d))
+			.ceil();
+		let mut content = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+		content.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", target_duration));
+		content.push_str("#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n");
+		for (i, duration) in self.segment_durations.iter().enumerate() {
+			content.push_str(&format!("#EXTINF:{:.3},\n{}\n", duration, segment_uri(i)));
+		}
+		content.push_str("#EXT-X-ENDLIST\n");
+		content
+	}
+}
+
+/// Name of a segment file, following the `%d.ts` pattern given to ffmpeg.
+pub fn segment_file_name(index: usize) -> String {
+	format!("{}.ts", index)
+}
+
+/// Master playlist pointing to a media playlist for each bitrate.
+pub fn master_playlist(variant_uri: impl Fn(u32) -> String) -> String {
+	let mut content = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+	for bitrate in BITRATES {
+		let bandwidth = (bitrate as f32 * 1000.0 * CONTAINER_OVERHEAD).round() as u32;
+		content.push_str(&format!(
+			"#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{}\"\n{}\n",
+			bandwidth,
+			CODECS,
+			variant_uri(bitrate)
+		));
+	}
+	content
+}
+
+fn parse_segment_durations(playlist: &str) -> Vec<f32> {
+	playlist
+		.lines()
+		.filter_map(|line| line.strip_prefix("#EXTINF:"))
+		.filter_map(|info| info.split(',').next()?.trim().parse::<f32>().ok())
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn lists_all_bitrates() {
+		let playlist = master_playlist(|bitrate| format!("{}/index.m3u8", bitrate));
+		assert_eq!(
+			playlist,
+			"#EXTM3U\n#EXT-X-VERSION:3\n\
+			#EXT-X-STREAM-INF:BANDWIDTH=70400,CODECS=\"mp4a.40.2\"\n64/index.m3u8\n\
+			#EXT-X-STREAM-INF:BANDWIDTH=140800,CODECS=\"mp4a.40.2\"\n128/index.m3u8\n\
+			#EXT-X-STREAM-INF:BANDWIDTH=281600,CODECS=\"mp4a.40.2\"\n256/index.m3u8\n"
+		);
+	}
+
+	#[test]
+	fn rewrites_ffmpeg_playlists() {
+		let ffmpeg_playlist = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:7\n\
+			#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n\
+			#EXTINF:6.013000,\n0.ts\n#EXTINF:6.989000,\n1.ts\n#EXTINF:2.500000,\n2.ts\n\
+			#EXT-X-ENDLIST\n";
+		let stream = Stream {
+			directory: PathBuf::from("stream"),
+			segment_durations: parse_segment_durations(ffmpeg_playlist),
+		};
+		assert_eq!(stream.segment_durations, vec![6.013, 6.989, 2.5]);
+		assert_eq!(
+			stream.segment_path(2),
+			Some(Path::new("stream").join("2.ts"))
+		);
+		assert_eq!(stream.segment_path(3), None);
+		assert_eq!(
+			stream.playlist(|i| format!("{}?auth_token=abc", segment_file_name(i))),
+			"#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:7\n\
+			#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n\
+			#EXTINF:6.013,\n0.ts?auth_token=abc\n#EXTINF:6.989,\n1.ts?auth_token=abc\n\
+			#EXTINF:2.500,\n2.ts?auth_token=abc\n#EXT-X-ENDLIST\n"
+		);
+	}
+
+	#[test]
+	fn estimates_segments_of_streams_in_progress() {
+		let stream = Stream::estimate(Path::new("stream"), 14_500);
+		assert_eq!(stream.segment_durations, vec![6.0, 6.0, 2.5]);
+		let stream = Stream::estimate(Path::new("stream"), 12_000);
+		assert_eq!(stream.segment_durations, vec![6.0, 6.0]);
+	}
+}
@@ -1,41 +1,88 @@
 use log::error;
-use std::sync::{Arc, Condvar, Mutex};
-use std::time::Duration;
+use std::cmp::min;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
-use crate::app::{settings, vfs};
+use crate::app::{events, settings, thumbnail, vfs};
 use crate::db::DB;
 
+mod cache;
+mod chapters;
+mod collation;
+mod cue;
+mod edit;
+mod lyrics;
 mod metadata;
+mod missing;
 mod query;
+mod search;
+mod suggest;
 #[cfg(test)]
 mod test;
 mod types;
 mod update;
+mod views;
+mod watcher;
 
+pub use self::chapters::Chapter;
+pub use self::edit::*;
+pub use self::lyrics::Lyrics;
+pub use self::missing::MissingSong;
 pub use self::query::*;
+pub use self::suggest::{Suggestion, SuggestionKind};
 pub use self::types::*;
 pub use self::update::*;
 
+const AUTOMATIC_REINDEX_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Clone)]
 pub struct Index {
 	db: DB,
 	vfs_manager: vfs::Manager,
 	settings_manager: settings::Manager,
+	events_manager: events::Manager,
+	thumbnail_manager: thumbnail::Manager,
 	pending_reindex: Arc<(Mutex<bool>, Condvar)>,
+	progress: Arc<Progress>,
+	shutting_down: Arc<AtomicBool>,
+	streaming_until: Arc<Mutex<Option<Instant>>>,
+	suggestions: Arc<RwLock<Option<suggest::SuggestionIndex>>>,
+	generation: Arc<AtomicU64>,
+	browse_cache: Arc<Mutex<cache::BrowseCache>>,
+	flatten_cache: Arc<Mutex<cache::FlattenCache>>,
+	search_cache: Arc<Mutex<cache::SearchCache>>,
 }
 
 impl Index {
-	pub fn new(db: DB, vfs_manager: vfs::Manager, settings_manager: settings::Manager) -> Self {
+	pub fn new(
+		db: DB,
+		vfs_manager: vfs::Manager,
+		settings_manager: settings::Manager,
+		events_manager: events::Manager,
+		thumbnail_manager: thumbnail::Manager,
+	) -> Self {
 		let index = Self {
 			db,
 			vfs_manager,
 			settings_manager,
+			events_manager,
+			thumbnail_manager,
 
 			pending_reindex: Arc::new((
 				#[allow(clippy::mutex_atomic)]
 				Mutex::new(false),
 				Condvar::new(),
 			)),
+			progress: Arc::default(),
+			shutting_down: Arc::default(),
+			streaming_until: Arc::default(),
+			suggestions: Arc::default(),
+			generation: Arc::default(),
+			browse_cache: Arc::default(),
+			flatten_cache: Arc::default(),
+			search_cache: Arc::default(),
 		};
 
 		let commands_index = index.clone();
@@ -46,6 +93,16 @@ impl Index {
 		index
 	}
 
+	fn get_collation_locale(&self) -> String {
+		self.settings_manager
+			.get_collation_locale()
+			.unwrap_or_default()
+	}
+
+	fn get_collator(&self) -> collation::Collator {
+		collation::Collator::new(&self.get_collation_locale())
+	}
+
 	pub fn trigger_reindex(&self) {
 		let (lock, cvar) = &*self.pending_reindex;
 		let mut pending_reindex = lock.lock().unwrap();
@@ -53,6 +110,20 @@ impl Index {
 		cvar.notify_one();
 	}
 
+	// Interrupts the update in progress and prevents new ones from starting. Returns whether
+	// the interrupted update committed its pending writes before the timeout.
+	pub fn shutdown(&self, timeout: Duration) -> bool {
+		self.shutting_down.store(true, Ordering::SeqCst);
+		let start = Instant::now();
+		while self.get_status().is_running {
+			if start.elapsed() >= timeout {
+				return false;
+			}
+			std::thread::sleep(SHUTDOWN_POLL_INTERVAL);
+		}
+		true
+	}
+
 	pub fn begin_periodic_updates(&self) {
 		let auto_index = self.clone();
 		std::thread::spawn(move || {
@@ -70,6 +141,9 @@ impl Index {
 				}
 				*pending = false;
 			}
+			if self.shutting_down.load(Ordering::SeqCst) {
+				return;
+			}
 			if let Err(e) = self.update() {
 				error!("Error while updating index: {}", e);
 			}
@@ -79,14 +153,25 @@ impl Index {
 	fn automatic_reindex(&self) {
 		loop {
 			self.trigger_reindex();
-			let sleep_duration = self
-				.settings_manager
-				.get_index_sleep_duration()
-				.unwrap_or_else(|e| {
-					error!("Could not retrieve index sleep duration: {}", e);
-					Duration::from_secs(1800)
-				});
-			std::thread::sleep(sleep_duration);
+			// Sleep in small increments so changes to the reindex interval apply without a restart
+			let start = Instant::now();
+			loop {
+				let sleep_duration = self
+					.settings_manager
+					.get_index_sleep_duration()
+					.unwrap_or_else(|e| {
+						error!("Could not retrieve index sleep duration: {}", e);
+						Duration::from_secs(1800)
+					});
+				let elapsed = start.elapsed();
+				if elapsed >= sleep_duration {
+					break;
+				}
+				std::thread::sleep(min(
+					sleep_duration - elapsed,
+					AUTOMATIC_REINDEX_POLL_INTERVAL,
+				));
+			}
 		}
 	}
 }
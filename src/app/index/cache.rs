@@ -0,0 +1,119 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use super::*;
+
+// Results kept by each cache, oldest results are dropped first
+const MAX_ENTRIES: usize = 256;
+
+// Results are shared by users who can see the same mounts
+pub(super) type BrowseCache = QueryCache<(Vec<vfs::Mount>, PathBuf, String), Vec<CollectionFile>>;
+pub(super) type FlattenCache = QueryCache<(Vec<vfs::Mount>, PathBuf, Page), (Vec<Song>, usize)>;
+pub(super) type SearchCache =
+	QueryCache<(Vec<vfs::Mount>, String, bool, bool), Vec<CollectionFile>>;
+
+/// Results of recent queries, which are only valid for the index generation they were computed
+/// in. Each update or edit of the index starts a new generation.
+pub(super) struct QueryCache<K, V> {
+	generation: u64,
+	entries: HashMap<K, V>,
+	insertion_order: VecDeque<K>,
+}
+
+impl<K, V> Default for QueryCache<K, V> {
+	fn default() -> Self {
+		Self {
+			generation: 0,
+			entries: HashMap::new(),
+			insertion_order: VecDeque::new(),
+		}
+	}
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> QueryCache<K, V> {
+	fn get(&mut self, generation: u64, key: &K) -> Option<V> {
+		self.advance(generation);
+		self.entries.get(key).cloned()
+	}
+
+	fn insert(&mut self, generation: u64, key: K, value: V) {
+		// Results computed while the index changed may already be outdated
+		if generation < self.generation {
+			return;
+		}
+		self.advance(generation);
+		if self.entries.insert(key.clone(), value).is_none() {
+			self.insertion_order.push_back(key);
+		}
+		while self.insertion_order.len() > MAX_ENTRIES {
+			if let Some(oldest) = self.insertion_order.pop_front() {
+				self.entries.remove(&oldest);
+			}
+		}
+	}
+
+	fn advance(&mut self, generation: u64) {
+		if generation > self.generation {
+			self.generation = generation;
+			self.entries.clear();
+			self.insertion_order.clear();
+		}
+	}
+}
+
+impl Index {
+	// Returns the cached result of a query, or runs it and caches its result
+	pub(super) fn cached<K, V, E>(
+		&self,
+		cache: &Mutex<QueryCache<K, V>>,
+		key: K,
+		query: impl FnOnce() -> Result<V, E>,
+	) -> Result<V, E>
+	where
+		K: Clone + Eq + Hash,
+		V: Clone,
+	{
+		let generation = self.generation.load(Ordering::SeqCst);
+		if let Some(value) = cache.lock().unwrap().get(generation, &key) {
+			return Ok(value);
+		}
+		let value = query()?;
+		cache.lock().unwrap().insert(generation, key, value.clone());
+		Ok(value)
+	}
+
+	pub(super) fn invalidate_caches(&self) {
+		self.generation.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn new_generation_drops_results() {
+		let mut cache = QueryCache::default();
+		cache.insert(0, "key", 1);
+		assert_eq!(cache.get(0, &"key"), Some(1));
+		assert_eq!(cache.get(1, &"key"), None);
+
+		// Results from a previous generation are not kept
+		cache.insert(0, "key", 1);
+		assert_eq!(cache.get(1, &"key"), None);
+	}
+
+	#[test]
+	fn oldest_results_are_dropped() {
+		let mut cache = QueryCache::default();
+		for i in 0..=MAX_ENTRIES {
+			cache.insert(0, i, i);
+		}
+		assert_eq!(cache.get(0, &0), None);
+		assert_eq!(cache.get(0, &1), Some(1));
+		assert_eq!(cache.get(0, &MAX_ENTRIES), Some(MAX_ENTRIES));
+	}
+}
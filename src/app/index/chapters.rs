@@ -0,0 +1,214 @@
+use id3::TagLike;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::metadata::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+	pub title: Option<String>,
+	// Milliseconds from the beginning of the file
+	pub start: u32,
+	pub end: Option<u32>,
+}
+
+pub fn from_id3(tag: &id3::Tag) -> Vec<Chapter> {
+	let mut chapters = tag
+		.chapters()
+		.map(|c| Chapter {
+			title: c.title().map(str::to_owned),
+			start: c.start_time,
+			end: Some(c.end_time),
+		})
+		.collect::<Vec<_>>();
+	chapters.sort_by_key(|c| c.start);
+	chapters
+}
+
+/// Reads chapters written as `CHAPTER001=00:01:02.500` and `CHAPTER001NAME=Title` comments.
+pub fn from_vorbis_comments<I>(comments: I) -> Vec<Chapter>
+where
+	I: IntoIterator<Item = (String, String)>,
+{
+	let mut starts = Vec::new();
+	let mut names = Vec::new();
+	for (key, value) in comments {
+		let key = key.to_ascii_uppercase();
+		let Some(number) = key.strip_prefix("CHAPTER") else {
+			continue;
+		};
+		if let Some(number) = number.strip_suffix("NAME") {
+			if let Ok(number) = number.parse::<u32>() {
+				names.push((number, value));
+			}
+		} else if let Ok(number) = number.parse::<u32>() {
+			if let Some(start) = parse_timestamp(&value) {
+				starts.push((number, start));
+			}
+		}
+	}
+	starts.sort();
+	let chapters = starts.iter().map(|(number, start)| Chapter {
+		title: names
+			.iter()
+			.find(|(n, _)| n == number)
+			.map(|(_, name)| name.clone()),
+		start: *start,
+		end: None,
+	});
+	fill_ends(chapters.collect())
+}
+
+/// Reads chapters from the Nero `chpl` atom (`moov/udta/chpl`), which is how M4B audiobooks
+/// usually store them.
+pub fn from_mp4(path: &Path) -> Result<Vec<Chapter>, Error> {
+	let io_error = |e| Error::Io(path.to_owned(), e);
+	let mut file = fs::File::open(path).map_err(io_error)?;
+	let length = file.metadata().map_err(io_error)?.len();
+	let Some(moov) = find_atom(&mut file, 0, length, b"moov").map_err(io_error)? else {
+		return Ok(Vec::new());
+	};
+	let Some(udta) = find_atom(&mut file, moov.0, moov.1, b"udta").map_err(io_error)? else {
+		return Ok(Vec::new());
+	};
+	let Some(chpl) = find_atom(&mut file, udta.0, udta.1, b"chpl").map_err(io_error)? else {
+		return Ok(Vec::new());
+	};
+	let mut bytes = vec![0; (chpl.1 - chpl.0) as usize];
+	file.seek(SeekFrom::Start(chpl.0)).map_err(io_error)?;
+	file.read_exact(&mut bytes).map_err(io_error)?;
+	Ok(fill_ends(parse_chpl(&bytes).unwrap_or_default()))
+}
+
+// Returns the start and end offsets of the content of the first atom of the given type
+fn find_atom(
+	file: &mut fs::File,
+	start: u64,
+	end: u64,
+	atom_type: &[u8; 4],
+) -> std::io::Result<Option<(u64, u64)>> {
+	let mut offset = start;
+	while offset + 8 <= end {
+		let mut header = [0; 8];
+		file.seek(SeekFrom::Start(offset))?;
+		file.read_exact(&mut header)?;
+		let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+		let mut header_size = 8;
+		if size == 1 {
+			let mut large_size = [0; 8];
+			file.read_exact(&mut large_size)?;
+			size = u64::from_be_bytes(large_size);
+			header_size = 16;
+		} else if size == 0 {
+			size = end - offset;
+		}
+		if size < header_size || offset + size > end {
+			return Ok(None);
+		}
+		if &header[4..8] == atom_type {
+			return Ok(Some((offset + header_size, offset + size)));
+		}
+		offset += size;
+	}
+	Ok(None)
+}
+
+fn parse_chpl(bytes: &[u8]) -> Option<Vec<Chapter>> {
+	let version = *bytes.first()?;
+	// Version and flags, followed by a reserved field in newer versions
+	let mut offset = if version > 0 { 8 } else { 4 };
+	let count = *bytes.get(offset)?;
+	offset += 1;
+	let mut chapters = Vec::new();
+	for _ in 0..count {
+		let start = u64::from_be_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?);
+		let title_length = *bytes.get(offset + 8)? as usize;
+		let title = bytes.get(offset + 9..offset + 9 + title_length)?;
+		offset += 9 + title_length;
+		let title = String::from_utf8_lossy(title).trim().to_owned();
+		chapters.push(Chapter {
+			title: (!title.is_empty()).then_some(title),
+			// Stored in 100ns units
+			start: (start / 10_000) as u32,
+			end: None,
+		});
+	}
+	Some(chapters)
+}
+
+fn parse_timestamp(value: &str) -> Option<u32> {
+	let (time, fraction) = value.trim().split_once('.').unwrap_or((value.trim(), "0"));
+	let mut seconds = 0;
+	for part in time.split(':') {
+		seconds = seconds * 60 + part.parse::<u32>().ok()?;
+	}
+	let fraction = format!("{:0<3}", fraction.get(..3.min(fraction.len()))?);
+	Some(seconds * 1000 + fraction.parse::<u32>().ok()?)
+}
+
+// Chapters without an explicit end run until the next one
+fn fill_ends(mut chapters: Vec<Chapter>) -> Vec<Chapter> {
+	for i in 1..chapters.len() {
+		let next_start = chapters[i].start;
+		chapters[i - 1].end.get_or_insert(next_start);
+	}
+	chapters
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn reads_vorbis_comment_chapters() {
+		let comments = vec![
+			("CHAPTER002".to_owned(), "00:10:00.5".to_owned()),
+			("CHAPTER001".to_owned(), "00:00:00.000".to_owned()),
+			("chapter001name".to_owned(), "Prologue".to_owned()),
+			("CHAPTER002NAME".to_owned(), "Chapter One".to_owned()),
+			("TITLE".to_owned(), "The Book".to_owned()),
+		];
+		assert_eq!(
+			from_vorbis_comments(comments),
+			vec![
+				Chapter {
+					title: Some("Prologue".to_owned()),
+					start: 0,
+					end: Some(600_500),
+				},
+				Chapter {
+					title: Some("Chapter One".to_owned()),
+					start: 600_500,
+					end: None,
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn reads_nero_chapters() {
+		let mut bytes = vec![1, 0, 0, 0, 0, 0, 0, 0, 2];
+		bytes.extend(0u64.to_be_bytes());
+		bytes.push(5);
+		bytes.extend(b"Intro");
+		bytes.extend(600_000_000u64.to_be_bytes());
+		bytes.push(0);
+		assert_eq!(
+			fill_ends(parse_chpl(&bytes).unwrap()),
+			vec![
+				Chapter {
+					title: Some("Intro".to_owned()),
+					start: 0,
+					end: Some(60_000),
+				},
+				Chapter {
+					title: None,
+					start: 60_000,
+					end: None,
+				},
+			]
+		);
+		assert!(parse_chpl(&bytes[..12]).is_none());
+	}
+}
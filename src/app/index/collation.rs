@@ -0,0 +1,141 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Gap between the weights of consecutive characters, leaving room for letters tailored after them
+const WEIGHT_STEP: u32 = 8;
+
+// Letters which some languages sort as distinct letters rather than accented variants
+const DANISH_NORWEGIAN: &[(char, char, u32)] = &[('æ', 'z', 1), ('ø', 'z', 2), ('å', 'z', 3)];
+const SPANISH: &[(char, char, u32)] = &[('ñ', 'n', 1)];
+const SWEDISH_FINNISH: &[(char, char, u32)] = &[('å', 'z', 1), ('ä', 'z', 2), ('ö', 'z', 3)];
+
+/// Orders text by base letters first, then accents, then case, as opposed to sqlite's byte
+/// ordering which puts `Édith Piaf` after `Zazie` and `alt-J` after `ZZ Top`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Collator {
+	// Letter, letter it is sorted after, position among letters sorted after the same one
+	tailoring: &'static [(char, char, u32)],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CollationKey {
+	primary: Vec<u32>,
+	secondary: Vec<char>,
+	tertiary: Vec<bool>,
+	text: String,
+}
+
+impl Collator {
+	// Locales are language tags like `sv` or `sv-SE`, unknown languages use the default rules
+	pub fn new(locale: &str) -> Self {
+		let language = locale
+			.split(['-', '_'])
+			.next()
+			.unwrap_or_default()
+			.to_lowercase();
+		let tailoring = match language.as_str() {
+			"da" | "nb" | "nn" | "no" => DANISH_NORWEGIAN,
+			"es" => SPANISH,
+			"fi" | "sv" => SWEDISH_FINNISH,
+			_ => &[],
+		};
+		Self { tailoring }
+	}
+
+	pub fn key(&self, text: &str) -> CollationKey {
+		let mut key = CollationKey {
+			primary: Vec::new(),
+			secondary: Vec::new(),
+			tertiary: Vec::new(),
+			text: text.to_owned(),
+		};
+
+		for c in text.nfc() {
+			let lowercase = c.to_lowercase().next().unwrap_or(c);
+			key.tertiary.push(lowercase != c);
+
+			let tailored = self
+				.tailoring
+				.iter()
+				.find(|(letter, _, _)| *letter == lowercase);
+			if let Some((_, after, position)) = tailored {
+				key.primary.push(get_weight(*after) + position);
+				key.secondary.push('\0');
+				continue;
+			}
+
+			for d in lowercase.nfd() {
+				if is_combining_mark(d) {
+					key.secondary.push(d);
+				} else {
+					key.primary.push(get_weight(d));
+					key.secondary.push('\0');
+				}
+			}
+		}
+
+		key
+	}
+}
+
+fn get_weight(c: char) -> u32 {
+	// Katakana sort along with their hiragana equivalent
+	let c = match c {
+		'\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+		_ => c,
+	};
+	c as u32 * WEIGHT_STEP
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn sort(collator: &Collator, words: &[&str]) -> Vec<String> {
+		let mut words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+		words.sort_by_cached_key(|w| collator.key(w));
+		words
+	}
+
+	#[test]
+	fn ignores_case_and_accents_before_letters() {
+		let collator = Collator::default();
+		assert_eq!(
+			sort(
+				&collator,
+				&["Zazie", "Édith Piaf", "alt-J", "Eels", "ZZ Top"]
+			),
+			vec!["alt-J", "Édith Piaf", "Eels", "Zazie", "ZZ Top"]
+		);
+		assert_eq!(
+			sort(&collator, &["resume", "Résumé", "résumé", "Resume"]),
+			vec!["resume", "Resume", "résumé", "Résumé"]
+		);
+	}
+
+	#[test]
+	fn sorts_katakana_with_hiragana() {
+		let collator = Collator::default();
+		assert_eq!(
+			sort(&collator, &["さくら", "カ", "あ"]),
+			vec!["あ", "カ", "さくら"]
+		);
+	}
+
+	#[test]
+	fn applies_locale_rules() {
+		let words = ["Öresund", "Zorn", "Oslo"];
+		assert_eq!(
+			sort(&Collator::new("en"), &words),
+			vec!["Öresund", "Oslo", "Zorn"]
+		);
+		assert_eq!(
+			sort(&Collator::new("sv-SE"), &words),
+			vec!["Oslo", "Zorn", "Öresund"]
+		);
+		assert_eq!(
+			sort(&Collator::new("es"), &["Ñu", "Nube", "Oro"]),
+			vec!["Nube", "Ñu", "Oro"]
+		);
+	}
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+
+use super::metadata::{parse_peak, parse_replay_gain};
+
+// Cue sheet timestamps count frames of 1/75th of a second
+const FRAMES_PER_SECOND: u32 = 75;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("Invalid cue sheet `{0}` at line {1}")]
+	Syntax(PathBuf, usize),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+	pub title: Option<String>,
+	pub performer: Option<String>,
+	pub songwriter: Option<String>,
+	pub genre: Option<String>,
+	pub year: Option<i32>,
+	pub disc_number: Option<u32>,
+	pub album_gain: Option<f32>,
+	pub album_peak: Option<f32>,
+	pub files: Vec<CueFile>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueFile {
+	pub name: String,
+	pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueTrack {
+	pub number: u32,
+	pub title: Option<String>,
+	pub performer: Option<String>,
+	pub songwriter: Option<String>,
+	// Milliseconds from the beginning of the file
+	pub start: u32,
+	pub track_gain: Option<f32>,
+	pub track_peak: Option<f32>,
+}
+
+impl CueFile {
+	// Each track lasts until the next one in the same file, the last one until the end of the file
+	pub fn get_end(&self, track_index: usize) -> Option<u32> {
+		self.tracks.get(track_index + 1).map(|t| t.start)
+	}
+}
+
+pub fn is_cue_sheet(path: &Path) -> bool {
+	path.extension()
+		.map(|e| e.eq_ignore_ascii_case("cue"))
+		.unwrap_or(false)
+}
+
+// Tracks are indexed as `Album.cue#03`, which makes them unique and sort in order
+pub fn get_track_path(sheet_path: &Path, track_number: u32) -> String {
+	format!("{}#{:02}", sheet_path.to_string_lossy(), track_number)
+}
+
+pub fn get_sheet_path(track_path: &str) -> Option<&str> {
+	let (sheet_path, track_number) = track_path.rsplit_once('#')?;
+	if track_number.is_empty() || !track_number.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	is_cue_sheet(Path::new(sheet_path)).then_some(sheet_path)
+}
+
+pub fn read(path: &Path) -> Result<CueSheet, Error> {
+	let bytes = fs::read(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+	// Older rippers write cue sheets in Latin-1 rather than UTF-8
+	let content: String = match String::from_utf8(bytes) {
+		Ok(s) => s.nfc().collect(),
+		Err(e) => e.into_bytes().into_iter().map(char::from).collect(),
+	};
+	parse(&content).map_err(|line| Error::Syntax(path.to_owned(), line))
+}
+
+// Returns the line number of the first invalid command, if any
+fn parse(content: &str) -> Result<CueSheet, usize> {
+	let mut sheet = CueSheet::default();
+
+	for (line_index, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+		let line_number = line_index + 1;
+		let (command, arguments) = match line.trim().split_once(char::is_whitespace) {
+			Some((command, arguments)) => (command, arguments.trim()),
+			None => (line.trim(), ""),
+		};
+		let current_file = sheet.files.last_mut();
+		let current_track = current_file.and_then(|f| f.tracks.last_mut());
+
+		match command.to_uppercase().as_str() {
+			"FILE" => sheet.files.push(CueFile {
+				name: parse_file_name(arguments).ok_or(line_number)?,
+				tracks: Vec::new(),
+			}),
+			"TRACK" => {
+				let number = arguments
+					.split_whitespace()
+					.next()
+					.and_then(|n| n.parse().ok())
+					.ok_or(line_number)?;
+				let file = sheet.files.last_mut().ok_or(line_number)?;
+				file.tracks.push(CueTrack {
+					number,
+					..Default::default()
+				});
+			}
+			"INDEX" => {
+				let track = current_track.ok_or(line_number)?;
+				let mut arguments = arguments.split_whitespace();
+				let index_number: u32 = arguments
+					.next()
+					.and_then(|n| n.parse().ok())
+					.ok_or(line_number)?;
+				let start = arguments
+					.next()
+					.and_then(parse_timestamp)
+					.ok_or(line_number)?;
+				// Index 00 marks the pregap, tracks start at index 01
+				if index_number == 1 {
+					track.start = start;
+				}
+			}
+			"TITLE" => match current_track {
+				Some(track) => track.title = Some(unquote(arguments)),
+				None => sheet.title = Some(unquote(arguments)),
+			},
+			"PERFORMER" => match current_track {
+				Some(track) => track.performer = Some(unquote(arguments)),
+				None => sheet.performer = Some(unquote(arguments)),
+			},
+			"SONGWRITER" => match current_track {
+				Some(track) => track.songwriter = Some(unquote(arguments)),
+				None => sheet.songwriter = Some(unquote(arguments)),
+			},
+			"REM" => {
+				let (key, value) = arguments
+					.split_once(char::is_whitespace)
+					.unwrap_or_default();
+				let value = unquote(value.trim());
+				match (key.to_uppercase().as_str(), current_track) {
+					("GENRE", _) => sheet.genre = Some(value),
+					("DATE", _) => sheet.year = value.get(..4).and_then(|y| y.parse().ok()),
+					("DISCNUMBER", _) => sheet.disc_number = value.parse().ok(),
+					("REPLAYGAIN_ALBUM_GAIN", _) => sheet.album_gain = parse_replay_gain(&value),
+					("REPLAYGAIN_ALBUM_PEAK", _) => sheet.album_peak = parse_peak(&value),
+					("REPLAYGAIN_TRACK_GAIN", Some(track)) => {
+						track.track_gain = parse_replay_gain(&value)
+					}
+					("REPLAYGAIN_TRACK_PEAK", Some(track)) => track.track_peak = parse_peak(&value),
+					_ => (),
+				}
+			}
+			_ => (),
+		}
+	}
+
+	Ok(sheet)
+}
+
+fn unquote(value: &str) -> String {
+	let value = value.trim();
+	value
+		.strip_prefix('"')
+		.and_then(|v| v.strip_suffix('"'))
+		.unwrap_or(value)
+		.to_owned()
+}
+
+// File names may contain spaces and are followed by the file type, as in `"Disc 1.flac" WAVE`
+fn parse_file_name(arguments: &str) -> Option<String> {
+	if let Some(quoted) = arguments.strip_prefix('"') {
+		let (name, _) = quoted.split_once('"')?;
+		return Some(name.to_owned());
+	}
+	let (name, _) = arguments.rsplit_once(char::is_whitespace)?;
+	Some(name.trim().to_owned())
+}
+
+// Timestamps are written as `mm:ss:ff`
+fn parse_timestamp(timestamp: &str) -> Option<u32> {
+	let mut parts = timestamp.split(':').map(|p| p.parse::<u32>().ok());
+	let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+	if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+		return None;
+	}
+	Some((minutes * 60 + seconds) * 1000 + frames * 1000 / FRAMES_PER_SECOND)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parses_cue_sheet() {
+		let content = r#"REM GENRE Classical
+REM DATE 1988
+PERFORMER "Berliner Philharmoniker"
+TITLE "Symphony No. 9"
+FILE "Symphony No. 9.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Allegro ma non troppo"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Molto vivace"
+    PERFORMER "Herbert von Karajan"
+    INDEX 00 15:52:10
+    REM REPLAYGAIN_TRACK_GAIN -2.50 dB
+    INDEX 01 15:54:37
+"#;
+		let sheet = parse(content).unwrap();
+		assert_eq!(sheet.title.as_deref(), Some("Symphony No. 9"));
+		assert_eq!(sheet.performer.as_deref(), Some("Berliner Philharmoniker"));
+		assert_eq!(sheet.genre.as_deref(), Some("Classical"));
+		assert_eq!(sheet.year, Some(1988));
+		assert_eq!(sheet.files.len(), 1);
+
+		let file = &sheet.files[0];
+		assert_eq!(file.name, "Symphony No. 9.flac");
+		assert_eq!(file.tracks.len(), 2);
+		assert_eq!(
+			file.tracks[0].title.as_deref(),
+			Some("Allegro ma non troppo")
+		);
+		assert_eq!(file.tracks[0].start, 0);
+		assert_eq!(file.get_end(0), Some(954_493));
+		assert_eq!(file.tracks[1].number, 2);
+		assert_eq!(
+			file.tracks[1].performer.as_deref(),
+			Some("Herbert von Karajan")
+		);
+		assert_eq!(file.tracks[1].start, 954_493);
+		assert_eq!(file.tracks[1].track_gain, Some(-2.5));
+		assert_eq!(file.get_end(1), None);
+	}
+
+	#[test]
+	fn rejects_tracks_outside_of_files() {
+		assert_eq!(parse("TRACK 01 AUDIO\n"), Err(1));
+		assert_eq!(parse("FILE \"a.flac\" WAVE\nINDEX 01 00:00:00\n"), Err(2));
+		assert_eq!(
+			parse("FILE \"a.flac\" WAVE\nTRACK 01 AUDIO\nINDEX 01 00:75:00\n"),
+			Err(3)
+		);
+	}
+
+	#[test]
+	fn maps_track_paths_to_sheets() {
+		let track_path = get_track_path(Path::new("Music/Album.cue"), 3);
+		assert_eq!(track_path, "Music/Album.cue#03");
+		assert_eq!(get_sheet_path(&track_path), Some("Music/Album.cue"));
+		assert_eq!(get_sheet_path("Music/#1 Hits/Song.mp3"), None);
+		assert_eq!(get_sheet_path("Music/Album.flac#03"), None);
+	}
+}
@@ -0,0 +1,313 @@
+use diesel::prelude::*;
+use id3::TagLike;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::*;
+use crate::app::settings::AlbumArtPriority;
+use crate::db::{self, songs};
+use crate::utils::{self, AudioFormat};
+
+#[derive(thiserror::Error, Debug)]
+pub enum EditError {
+	#[error("Artwork must be a jpeg or png image")]
+	ArtworkFormatUnsupported,
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("Tags of `{0}` cannot be edited")]
+	FormatUnsupported(PathBuf),
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("Could not write tags to `{0}`:\n\n{1}")]
+	Metadata(PathBuf, metadata::Error),
+	#[error("Song was not found: `{0}`")]
+	SongNotFound(PathBuf),
+}
+
+/// Changes to the tags of a song. Fields left to `None` are not modified.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SongEdit {
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub album_artist: Option<String>,
+	pub track_number: Option<u32>,
+	pub disc_number: Option<u32>,
+	pub year: Option<i32>,
+	pub artwork: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy)]
+enum ArtworkFormat {
+	Jpeg,
+	Png,
+}
+
+impl ArtworkFormat {
+	fn guess(data: &[u8]) -> Option<Self> {
+		match image::guess_format(data).ok()? {
+			image::ImageFormat::Jpeg => Some(Self::Jpeg),
+			image::ImageFormat::Png => Some(Self::Png),
+			_ => None,
+		}
+	}
+
+	fn mime_type(&self) -> &'static str {
+		match self {
+			Self::Jpeg => "image/jpeg",
+			Self::Png => "image/png",
+		}
+	}
+}
+
+impl Index {
+	/// Writes tags to an audio file and updates its song in the index. Tags are written to a
+	/// copy of the file which then replaces it, so an interrupted edit leaves the file untouched.
+	pub fn edit_song(&self, real_path: &Path, edit: &SongEdit) -> Result<(), EditError> {
+		let real_path_string = real_path.to_string_lossy().into_owned();
+		let mut connection = self.db.connect()?;
+		let song: Song = songs::table
+			.filter(songs::path.eq(&real_path_string))
+			.filter(songs::cue_source.is_null())
+			.get_result(&mut connection)
+			.optional()?
+			.ok_or_else(|| EditError::SongNotFound(real_path.to_owned()))?;
+
+		let format = utils::get_audio_format(real_path)
+			.ok_or_else(|| EditError::FormatUnsupported(real_path.to_owned()))?;
+		let artwork = match &edit.artwork {
+			Some(data) => Some((
+				ArtworkFormat::guess(data).ok_or(EditError::ArtworkFormatUnsupported)?,
+				data.as_slice(),
+			)),
+			None => None,
+		};
+
+		let file_name = real_path.file_name().unwrap_or_default().to_string_lossy();
+		let temporary_path = real_path.with_file_name(format!(".{}.polaris-edit", file_name));
+		fs::copy(real_path, &temporary_path)
+			.map_err(|e| EditError::Io(temporary_path.clone(), e))?;
+		let written = write_tags(&temporary_path, format, edit, artwork).and_then(|_| {
+			fs::rename(&temporary_path, real_path)
+				.map_err(|e| EditError::Io(real_path.to_owned(), e))
+		});
+		if written.is_err() {
+			fs::remove_file(&temporary_path).ok();
+		}
+		written?;
+
+		let tags = metadata::try_read(real_path)
+			.map_err(|e| EditError::Metadata(real_path.to_owned(), e))?
+			.ok_or_else(|| EditError::FormatUnsupported(real_path.to_owned()))?
+			.normalized();
+		let file_metadata =
+			fs::metadata(real_path).map_err(|e| EditError::Io(real_path.to_owned(), e))?;
+		let file_modified = file_metadata
+			.modified()
+			.ok()
+			.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+			.map(|d| d.as_millis() as i64);
+
+		// New embedded artwork is used the same way the index would on its next update
+		let artwork_path = match self
+			.settings_manager
+			.get_index_album_art_priority()
+			.unwrap_or_default()
+		{
+			_ if edit.artwork.is_none() => song.artwork,
+			AlbumArtPriority::Embedded => Some(real_path_string.clone()),
+			AlbumArtPriority::Folder => song.artwork.or(Some(real_path_string.clone())),
+		};
+
+		diesel::update(songs::table.filter(songs::path.eq(&real_path_string)))
+			.set((
+				songs::title.eq(tags.title),
+				songs::artist.eq(tags.artist),
+				songs::album.eq(tags.album),
+				songs::album_artist.eq(tags.album_artist),
+				songs::track_number.eq(tags.track_number.map(|n| n as i32)),
+				songs::disc_number.eq(tags.disc_number.map(|n| n as i32)),
+				songs::year.eq(tags.year),
+				songs::artwork.eq(artwork_path),
+				songs::has_embedded_artwork.eq(tags.has_artwork),
+				songs::file_modified.eq(file_modified),
+				songs::file_size.eq(file_metadata.len() as i64),
+			))
+			.execute(&mut connection)?;
+		self.invalidate_caches();
+
+		// Albums and artists of the song's directory are only computed during updates
+		self.trigger_reindex();
+		Ok(())
+	}
+}
+
+fn write_tags(
+	path: &Path,
+	format: AudioFormat,
+	edit: &SongEdit,
+	artwork: Option<(ArtworkFormat, &[u8])>,
+) -> Result<(), EditError> {
+	let metadata_error = |e: metadata::Error| EditError::Metadata(path.to_owned(), e);
+	match format {
+		AudioFormat::AIFF | AudioFormat::MP3 | AudioFormat::WAVE => {
+			write_id3(path, format, edit, artwork).map_err(|e| metadata_error(e.into()))
+		}
+		AudioFormat::FLAC => write_flac(path, edit, artwork).map_err(|e| metadata_error(e.into())),
+		AudioFormat::MP4 | AudioFormat::M4B => {
+			write_mp4(path, edit, artwork).map_err(|e| metadata_error(e.into()))
+		}
+		AudioFormat::APE | AudioFormat::MPC if artwork.is_none() => {
+			write_ape(path, edit).map_err(|e| metadata_error(e.into()))
+		}
+		_ => Err(EditError::FormatUnsupported(path.to_owned())),
+	}
+}
+
+fn write_id3(
+	path: &Path,
+	format: AudioFormat,
+	edit: &SongEdit,
+	artwork: Option<(ArtworkFormat, &[u8])>,
+) -> Result<(), id3::Error> {
+	let read = match format {
+		AudioFormat::AIFF => id3::Tag::read_from_aiff_path(path),
+		AudioFormat::WAVE => id3::Tag::read_from_wav_path(path),
+		_ => id3::Tag::read_from_path(path),
+	};
+	let mut tag = match read {
+		Ok(tag) => tag,
+		Err(id3::Error {
+			kind: id3::ErrorKind::NoTag,
+			..
+		}) => id3::Tag::new(),
+		Err(e) => return Err(e),
+	};
+
+	if let Some(title) = &edit.title {
+		tag.set_title(title);
+	}
+	if let Some(artist) = &edit.artist {
+		tag.set_artist(artist);
+	}
+	if let Some(album) = &edit.album {
+		tag.set_album(album);
+	}
+	if let Some(album_artist) = &edit.album_artist {
+		tag.set_album_artist(album_artist);
+	}
+	if let Some(track_number) = edit.track_number {
+		tag.set_track(track_number);
+	}
+	if let Some(disc_number) = edit.disc_number {
+		tag.set_disc(disc_number);
+	}
+	if let Some(year) = edit.year {
+		tag.set_year(year);
+	}
+	if let Some((artwork_format, data)) = artwork {
+		tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+		tag.add_frame(id3::frame::Picture {
+			mime_type: artwork_format.mime_type().to_owned(),
+			picture_type: id3::frame::PictureType::CoverFront,
+			description: String::new(),
+			data: data.to_vec(),
+		});
+	}
+
+	let version = tag.version();
+	match format {
+		AudioFormat::AIFF => tag.write_to_aiff_path(path, version),
+		AudioFormat::WAVE => tag.write_to_wav_path(path, version),
+		_ => tag.write_to_path(path, version),
+	}
+}
+
+fn write_flac(
+	path: &Path,
+	edit: &SongEdit,
+	artwork: Option<(ArtworkFormat, &[u8])>,
+) -> Result<(), metaflac::Error> {
+	let mut tag = metaflac::Tag::read_from_path(path)?;
+	let mut set = |key: &str, value: Option<String>| {
+		if let Some(value) = value {
+			tag.set_vorbis(key, vec![value]);
+		}
+	};
+	set("TITLE", edit.title.clone());
+	set("ARTIST", edit.artist.clone());
+	set("ALBUM", edit.album.clone());
+	set("ALBUMARTIST", edit.album_artist.clone());
+	set("TRACKNUMBER", edit.track_number.map(|n| n.to_string()));
+	set("DISCNUMBER", edit.disc_number.map(|n| n.to_string()));
+	set("DATE", edit.year.map(|n| n.to_string()));
+	if let Some((artwork_format, data)) = artwork {
+		tag.add_picture(
+			artwork_format.mime_type(),
+			metaflac::block::PictureType::CoverFront,
+			data.to_vec(),
+		);
+	}
+	tag.save()
+}
+
+fn write_mp4(
+	path: &Path,
+	edit: &SongEdit,
+	artwork: Option<(ArtworkFormat, &[u8])>,
+) -> Result<(), mp4ameta::Error> {
+	let mut tag = mp4ameta::Tag::read_from_path(path)?;
+	if let Some(title) = &edit.title {
+		tag.set_title(title);
+	}
+	if let Some(artist) = &edit.artist {
+		tag.set_artist(artist);
+	}
+	if let Some(album) = &edit.album {
+		tag.set_album(album);
+	}
+	if let Some(album_artist) = &edit.album_artist {
+		tag.set_album_artist(album_artist);
+	}
+	if let Some(track_number) = edit.track_number {
+		tag.set_track_number(track_number.min(u16::MAX as u32) as u16);
+	}
+	if let Some(disc_number) = edit.disc_number {
+		tag.set_disc_number(disc_number.min(u16::MAX as u32) as u16);
+	}
+	if let Some(year) = edit.year {
+		tag.set_year(year.to_string());
+	}
+	if let Some((artwork_format, data)) = artwork {
+		let format = match artwork_format {
+			ArtworkFormat::Jpeg => mp4ameta::ImgFmt::Jpeg,
+			ArtworkFormat::Png => mp4ameta::ImgFmt::Png,
+		};
+		tag.set_artwork(mp4ameta::Img::new(format, data.to_vec()));
+	}
+	tag.write_to_path(path)
+}
+
+fn write_ape(path: &Path, edit: &SongEdit) -> Result<(), ape::Error> {
+	let mut tag = match ape::read_from_path(path) {
+		Ok(tag) => tag,
+		Err(ape::Error::TagNotFound) => ape::Tag::new(),
+		Err(e) => return Err(e),
+	};
+	let mut set = |key: &str, value: Option<String>| -> Result<(), ape::Error> {
+		if let Some(value) = value {
+			tag.set_item(ape::Item::from_text(key, value)?);
+		}
+		Ok(())
+	};
+	set("Title", edit.title.clone())?;
+	set("Artist", edit.artist.clone())?;
+	set("Album", edit.album.clone())?;
+	set("Album artist", edit.album_artist.clone())?;
+	set("Track", edit.track_number.map(|n| n.to_string()))?;
+	set("Disc", edit.disc_number.map(|n| n.to_string()))?;
+	set("Year", edit.year.map(|n| n.to_string()))?;
+	ape::write_to_path(&tag, path)
+}
@@ -0,0 +1,149 @@
+use std::path::Path;
+
+pub fn is_lyrics_file(path: &Path) -> bool {
+	path.extension()
+		.and_then(|e| e.to_str())
+		.is_some_and(|e| e.eq_ignore_ascii_case("lrc"))
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Lyrics {
+	pub synced: bool,
+	pub lines: Vec<LyricsLine>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LyricsLine {
+	// Milliseconds from the beginning of the song, for time-synced lyrics
+	pub start: Option<u32>,
+	pub text: String,
+}
+
+/// Lyrics with at least one LRC timestamp (`[mm:ss.xx]`) are time-synced, other lines are
+/// then dropped. Lyrics without any are plain text.
+pub fn parse(text: &str) -> Lyrics {
+	let text = text.trim_start_matches('\u{feff}');
+	let mut offset = 0;
+	let mut synced_lines = Vec::new();
+	for line in text.lines() {
+		let (timestamps, line_text) = split_timestamps(line);
+		if timestamps.is_empty() {
+			if let Some(value) = read_tag(line, "offset") {
+				offset = value.parse::<i64>().unwrap_or_default();
+			}
+			continue;
+		}
+		for start in timestamps {
+			synced_lines.push((start, line_text.trim().to_owned()));
+		}
+	}
+
+	if synced_lines.is_empty() {
+		return Lyrics {
+			synced: false,
+			lines: text
+				.lines()
+				.map(|l| LyricsLine {
+					start: None,
+					text: l.trim_end().to_owned(),
+				})
+				.collect(),
+		};
+	}
+
+	// Positive offsets make lyrics appear sooner
+	synced_lines.sort_by_key(|(start, _)| *start);
+	Lyrics {
+		synced: true,
+		lines: synced_lines
+			.into_iter()
+			.map(|(start, text)| LyricsLine {
+				start: Some((start as i64 - offset).max(0) as u32),
+				text,
+			})
+			.collect(),
+	}
+}
+
+fn split_timestamps(mut line: &str) -> (Vec<u32>, &str) {
+	let mut timestamps = Vec::new();
+	while let Some(rest) = line.trim_start().strip_prefix('[') {
+		let Some((timestamp, after)) = rest.split_once(']') else {
+			break;
+		};
+		match parse_timestamp(timestamp) {
+			Some(t) => timestamps.push(t),
+			None => break,
+		}
+		line = after;
+	}
+	(timestamps, line)
+}
+
+// Timestamps look like `mm:ss`, `mm:ss.xx` or `mm:ss.xxx`
+fn parse_timestamp(timestamp: &str) -> Option<u32> {
+	let (minutes, seconds) = timestamp.split_once(':')?;
+	let minutes = minutes.trim().parse::<u32>().ok()?;
+	let (seconds, fraction) = seconds.split_once(['.', ':']).unwrap_or((seconds, ""));
+	let seconds = seconds.trim().parse::<u32>().ok()?;
+	let milliseconds = match fraction.len() {
+		0 => 0,
+		1..=3 => {
+			let digits = fraction.parse::<u32>().ok()?;
+			digits * 10u32.pow(3 - fraction.len() as u32)
+		}
+		_ => return None,
+	};
+	Some(minutes * 60_000 + seconds * 1000 + milliseconds)
+}
+
+fn read_tag<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+	let tag = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+	let (key, value) = tag.split_once(':')?;
+	key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parses_synced_lyrics() {
+		let lyrics = parse(
+			"\u{feff}[ar:Khemmis]\n[offset:+500]\n[00:12.50]First line\n[01:02.3][00:01]Chorus\r\nNot synced",
+		);
+		assert!(lyrics.synced);
+		assert_eq!(
+			lyrics.lines,
+			vec![
+				LyricsLine {
+					start: Some(500),
+					text: "Chorus".to_owned()
+				},
+				LyricsLine {
+					start: Some(12_000),
+					text: "First line".to_owned()
+				},
+				LyricsLine {
+					start: Some(61_800),
+					text: "Chorus".to_owned()
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn parses_plain_lyrics() {
+		let lyrics = parse("First line\n\n[Chorus]\nSecond line");
+		assert!(!lyrics.synced);
+		assert_eq!(
+			lyrics
+				.lines
+				.iter()
+				.map(|l| l.text.as_str())
+				.collect::<Vec<_>>(),
+			vec!["First line", "", "[Chorus]", "Second line"]
+		);
+		assert!(lyrics.lines.iter().all(|l| l.start.is_none()));
+	}
+}
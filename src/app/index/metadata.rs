@@ -1,10 +1,13 @@
 use id3::TagLike;
 use lewton::inside_ogg::OggStreamReader;
-use log::error;
 use regex::Regex;
 use std::fs;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
 
+use super::chapters::{self, Chapter};
 use crate::utils;
 use crate::utils::AudioFormat;
 
@@ -28,9 +31,10 @@ pub enum Error {
 	VorbisCommentNotFoundInFlacFile,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SongTags {
 	pub disc_number: Option<u32>,
+	pub disc_total: Option<u32>,
 	pub track_number: Option<u32>,
 	pub title: Option<String>,
 	pub duration: Option<u32>,
@@ -39,10 +43,71 @@ pub struct SongTags {
 	pub album: Option<String>,
 	pub year: Option<i32>,
 	pub has_artwork: bool,
+	pub has_lyrics: bool,
 	pub lyricist: Option<String>,
 	pub composer: Option<String>,
+	pub conductor: Option<String>,
 	pub genre: Option<String>,
 	pub label: Option<String>,
+	pub compilation: bool,
+	pub artist_sort: Option<String>,
+	pub album_artist_sort: Option<String>,
+	pub album_sort: Option<String>,
+	pub title_sort: Option<String>,
+	// ReplayGain adjustments in dB and peaks relative to full scale
+	pub track_gain: Option<f32>,
+	pub track_peak: Option<f32>,
+	pub album_gain: Option<f32>,
+	pub album_peak: Option<f32>,
+	// MusicBrainz identifiers, as written by taggers like Picard
+	pub musicbrainz_recording_id: Option<String>,
+	pub musicbrainz_release_id: Option<String>,
+	pub musicbrainz_artist_id: Option<String>,
+	pub musicbrainz_album_artist_id: Option<String>,
+	// Samples added by the encoder around the audio, and the number of samples between them
+	pub sample_rate: Option<u32>,
+	pub encoder_delay: Option<u32>,
+	pub encoder_padding: Option<u32>,
+	pub sample_count: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Gapless {
+	sample_rate: u32,
+	encoder_delay: u32,
+	encoder_padding: u32,
+	sample_count: u64,
+}
+
+impl SongTags {
+	// Programs writing tags disagree on Unicode normal forms, so the same name could be stored
+	// as different strings
+	pub fn normalized(self) -> Self {
+		let nfc = |text: Option<String>| text.map(|t| t.nfc().collect());
+		Self {
+			title: nfc(self.title),
+			artist: nfc(self.artist),
+			album_artist: nfc(self.album_artist),
+			album: nfc(self.album),
+			lyricist: nfc(self.lyricist),
+			composer: nfc(self.composer),
+			conductor: nfc(self.conductor),
+			genre: nfc(self.genre),
+			label: nfc(self.label),
+			artist_sort: nfc(self.artist_sort),
+			album_artist_sort: nfc(self.album_artist_sort),
+			album_sort: nfc(self.album_sort),
+			title_sort: nfc(self.title_sort),
+			..self
+		}
+	}
+
+	fn set_gapless(&mut self, gapless: Option<Gapless>) {
+		self.sample_rate = gapless.map(|g| g.sample_rate);
+		self.encoder_delay = gapless.map(|g| g.encoder_delay);
+		self.encoder_padding = gapless.map(|g| g.encoder_padding);
+		self.sample_count = gapless.map(|g| g.sample_count);
+	}
 }
 
 impl From<id3::Tag> for SongTags {
@@ -53,6 +118,7 @@ impl From<id3::Tag> for SongTags {
 		let title = tag.title().map(|s| s.to_string());
 		let duration = tag.duration();
 		let disc_number = tag.disc();
+		let disc_total = tag.total_discs();
 		let track_number = tag.track();
 		let year = tag
 			.year()
@@ -60,13 +126,36 @@ impl From<id3::Tag> for SongTags {
 			.or_else(|| tag.original_date_released().map(|d| d.year))
 			.or_else(|| tag.date_recorded().map(|d| d.year));
 		let has_artwork = tag.pictures().count() > 0;
+		let has_lyrics =
+			tag.lyrics().next().is_some() || tag.synchronised_lyrics().next().is_some();
 		let lyricist = tag.get_text("TEXT");
 		let composer = tag.get_text("TCOM");
+		let conductor = tag.get_text("TPE3");
+		let artist_sort = tag.get_text("TSOP");
+		let album_artist_sort = tag.get_text("TSO2");
+		let album_sort = tag.get_text("TSOA");
+		let title_sort = tag.get_text("TSOT");
+		let get_extended_text = |description: &str| {
+			tag.extended_texts()
+				.find(|t| t.description.eq_ignore_ascii_case(description))
+				.map(|t| t.value.as_str())
+		};
+		let track_gain = get_extended_text("REPLAYGAIN_TRACK_GAIN").and_then(parse_replay_gain);
+		let track_peak = get_extended_text("REPLAYGAIN_TRACK_PEAK").and_then(parse_peak);
+		let album_gain = get_extended_text("REPLAYGAIN_ALBUM_GAIN").and_then(parse_replay_gain);
+		let album_peak = get_extended_text("REPLAYGAIN_ALBUM_PEAK").and_then(parse_peak);
+		let musicbrainz_recording_id = read_id3_musicbrainz_recording_id(&tag);
+		let musicbrainz_release_id = get_extended_text("MusicBrainz Album Id").map(String::from);
+		let musicbrainz_artist_id = get_extended_text("MusicBrainz Artist Id").map(String::from);
+		let musicbrainz_album_artist_id =
+			get_extended_text("MusicBrainz Album Artist Id").map(String::from);
 		let genre = tag.genre().map(|s| s.to_string());
 		let label = tag.get_text("TPUB");
+		let compilation = tag.get_text("TCMP").is_some_and(|v| parse_flag(&v));
 
 		SongTags {
 			disc_number,
+			disc_total,
 			track_number,
 			title,
 			duration,
@@ -75,15 +164,55 @@ impl From<id3::Tag> for SongTags {
 			album,
 			year,
 			has_artwork,
+			has_lyrics,
 			lyricist,
 			composer,
+			conductor,
 			genre,
 			label,
+			compilation,
+			artist_sort,
+			album_artist_sort,
+			album_sort,
+			title_sort,
+			track_gain,
+			track_peak,
+			album_gain,
+			album_peak,
+			musicbrainz_recording_id,
+			musicbrainz_release_id,
+			musicbrainz_artist_id,
+			musicbrainz_album_artist_id,
+			sample_rate: None,
+			encoder_delay: None,
+			encoder_padding: None,
+			sample_count: None,
 		}
 	}
 }
 
-pub fn read(path: &Path) -> Option<SongTags> {
+// MusicBrainz recording IDs are stored in a unique file identifier frame, as the owner
+// `http://musicbrainz.org` followed by a null byte and the ID
+fn read_id3_musicbrainz_recording_id(tag: &id3::Tag) -> Option<String> {
+	tag.frames()
+		.filter(|f| f.id() == "UFID")
+		.filter_map(|f| f.content().to_unknown().ok())
+		.find_map(|unknown| {
+			let (owner, id) = unknown
+				.data
+				.split_at(unknown.data.iter().position(|&b| b == 0)?);
+			(owner == b"http://musicbrainz.org")
+				.then(|| String::from_utf8_lossy(&id[1..]).into_owned())
+		})
+}
+
+#[cfg(test)]
+fn read(path: &Path) -> Option<SongTags> {
+	try_read(path).unwrap()
+}
+
+/// Returns `Ok(None)` for files which are not in a supported audio format.
+pub fn try_read(path: &Path) -> Result<Option<SongTags>, Error> {
 	let data = match utils::get_audio_format(path) {
 		Some(AudioFormat::AIFF) => read_aiff(path),
 		Some(AudioFormat::FLAC) => read_flac(path),
@@ -93,15 +222,112 @@ pub fn read(path: &Path) -> Option<SongTags> {
 		Some(AudioFormat::WAVE) => read_wave(path),
 		Some(AudioFormat::APE) | Some(AudioFormat::MPC) => read_ape(path),
 		Some(AudioFormat::MP4) | Some(AudioFormat::M4B) => read_mp4(path),
-		None => return None,
+		None => return Ok(None),
 	};
-	match data {
-		Ok(d) => Some(d),
-		Err(e) => {
-			error!("Error while reading file metadata for '{:?}': {}", path, e);
-			None
+	data.map(Some)
+}
+
+/// Returns the lyrics embedded in a song. Time-synced lyrics are formatted as LRC.
+pub fn read_lyrics(path: &Path) -> Result<Option<String>, Error> {
+	let lyrics = match utils::get_audio_format(path) {
+		Some(AudioFormat::AIFF) => read_id3_lyrics(&id3::Tag::read_from_aiff_path(path)?),
+		Some(AudioFormat::MP3) => read_id3_lyrics(&id3::Tag::read_from_path(path)?),
+		Some(AudioFormat::WAVE) => read_id3_lyrics(&id3::Tag::read_from_wav_path(path)?),
+		Some(AudioFormat::FLAC) => metaflac::Tag::read_from_path(path)?
+			.vorbis_comments()
+			.and_then(find_vorbis_lyrics),
+		Some(AudioFormat::OGG) => {
+			let file = fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+			let source = OggStreamReader::new(file)?;
+			read_vorbis_lyrics(source.comment_hdr.comment_list)
+		}
+		Some(AudioFormat::OPUS) => {
+			let headers = opus_headers::parse_from_path(path)?;
+			read_vorbis_lyrics(headers.comments.user_comments.into_iter().collect())
+		}
+		Some(AudioFormat::APE) | Some(AudioFormat::MPC) => ape::read_from_path(path)?
+			.item("Lyrics")
+			.and_then(read_ape_string),
+		Some(AudioFormat::MP4) | Some(AudioFormat::M4B) => {
+			mp4ameta::Tag::read_from_path(path)?.take_lyrics()
+		}
+		None => None,
+	};
+	Ok(lyrics)
+}
+
+pub fn read_chapters(path: &Path) -> Result<Vec<Chapter>, Error> {
+	let chapters = match utils::get_audio_format(path) {
+		Some(AudioFormat::AIFF) => chapters::from_id3(&id3::Tag::read_from_aiff_path(path)?),
+		Some(AudioFormat::MP3) => chapters::from_id3(&id3::Tag::read_from_path(path)?),
+		Some(AudioFormat::WAVE) => chapters::from_id3(&id3::Tag::read_from_wav_path(path)?),
+		Some(AudioFormat::FLAC) => metaflac::Tag::read_from_path(path)?
+			.vorbis_comments()
+			.map(|v| {
+				chapters::from_vorbis_comments(v.comments.iter().flat_map(|(key, values)| {
+					values.iter().map(|value| (key.clone(), value.clone()))
+				}))
+			})
+			.unwrap_or_default(),
+		Some(AudioFormat::OGG) => {
+			let file = fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+			let source = OggStreamReader::new(file)?;
+			chapters::from_vorbis_comments(source.comment_hdr.comment_list)
+		}
+		Some(AudioFormat::OPUS) => {
+			let headers = opus_headers::parse_from_path(path)?;
+			chapters::from_vorbis_comments(headers.comments.user_comments)
+		}
+		Some(AudioFormat::MP4) | Some(AudioFormat::M4B) => chapters::from_mp4(path)?,
+		Some(AudioFormat::APE) | Some(AudioFormat::MPC) | None => Vec::new(),
+	};
+	Ok(chapters)
+}
+
+fn read_id3_lyrics(tag: &id3::Tag) -> Option<String> {
+	let synced = tag
+		.synchronised_lyrics()
+		.find(|l| l.timestamp_format == id3::frame::TimestampFormat::Ms);
+	if let Some(synced) = synced {
+		let lines = synced
+			.content
+			.iter()
+			.map(|(time, text)| {
+				let minutes = time / 60_000;
+				let centiseconds = (time % 60_000) / 10;
+				format!(
+					"[{:02}:{:02}.{:02}]{}",
+					minutes,
+					centiseconds / 100,
+					centiseconds % 100,
+					text
+				)
+			})
+			.collect::<Vec<_>>();
+		return Some(lines.join("\n"));
+	}
+	tag.lyrics().next().map(|l| l.text.clone())
+}
+
+fn find_vorbis_lyrics(vorbis: &metaflac::block::VorbisComment) -> Option<String> {
+	vorbis
+		.get("LYRICS")
+		.or_else(|| vorbis.get("UNSYNCEDLYRICS"))
+		.map(|v| v[0].clone())
+}
+
+fn read_vorbis_lyrics(comments: Vec<(String, String)>) -> Option<String> {
+	let mut unsynced_lyrics = None;
+	for (key, value) in comments {
+		utils::match_ignore_case! {
+			match key {
+				"LYRICS" => return Some(value),
+				"UNSYNCEDLYRICS" => unsynced_lyrics = Some(value),
+				_ => (),
+			}
 		}
 	}
+	unsynced_lyrics
 }
 
 trait FrameContent {
@@ -137,9 +363,102 @@ fn read_mp3(path: &Path) -> Result<SongTags, Error> {
 
 	let mut song_tags: SongTags = tag.into();
 	song_tags.duration = duration; // Use duration from mp3_duration instead of from tags.
+	song_tags.set_gapless(read_lame_header(path)?);
 	Ok(song_tags)
 }
 
+fn read_lame_header(path: &Path) -> Result<Option<Gapless>, Error> {
+	// Large enough for the first frame at the highest bitrate
+	const MAX_FRAME_SIZE: u64 = 4096;
+	let io_error = |e| Error::Io(path.to_owned(), e);
+	let mut file = fs::File::open(path).map_err(io_error)?;
+	let mut offset = 0;
+	loop {
+		let mut header = [0; 10];
+		file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+		if file.read_exact(&mut header).is_err() || &header[0..3] != b"ID3" {
+			break;
+		}
+		let size = header[6..10]
+			.iter()
+			.fold(0u64, |size, b| (size << 7) | (b & 0x7f) as u64);
+		let footer_size = if header[5] & 0x10 != 0 { 10 } else { 0 };
+		offset += 10 + size + footer_size;
+	}
+
+	let mut buffer = Vec::new();
+	file.seek(SeekFrom::Start(offset))
+		.and_then(|_| file.take(MAX_FRAME_SIZE).read_to_end(&mut buffer))
+		.map_err(io_error)?;
+	let frame_start = buffer
+		.windows(2)
+		.position(|w| w[0] == 0xff && w[1] & 0xe0 == 0xe0);
+	Ok(frame_start.and_then(|start| parse_lame_header(&buffer[start..])))
+}
+
+// LAME and FFmpeg write a Xing header in place of the audio of the first frame, followed by the
+// encoder delay and padding
+fn parse_lame_header(frame: &[u8]) -> Option<Gapless> {
+	const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+	let read_u32 = |offset: usize| {
+		let bytes = frame.get(offset..offset + 4)?;
+		Some(u32::from_be_bytes(bytes.try_into().ok()?))
+	};
+
+	let header = frame.get(0..4)?;
+	let version = (header[1] >> 3) & 0b11; // MPEG-2.5, reserved, MPEG-2, MPEG-1
+	let layer = (header[1] >> 1) & 0b11; // Reserved, III, II, I
+	let sample_rate_index = ((header[2] >> 2) & 0b11) as usize;
+	let mono = header[3] >> 6 == 0b11;
+	if version == 1 || layer != 1 || sample_rate_index == 3 {
+		return None;
+	}
+	let mpeg1 = version == 3;
+	let sample_rate = SAMPLE_RATES[sample_rate_index]
+		/ match version {
+			3 => 1,
+			2 => 2,
+			_ => 4,
+		};
+	let samples_per_frame: u64 = if mpeg1 { 1152 } else { 576 };
+	let side_info_size = match (mpeg1, mono) {
+		(true, false) => 32,
+		(true, true) | (false, false) => 17,
+		(false, true) => 9,
+	};
+
+	let xing = 4 + side_info_size;
+	if !matches!(frame.get(xing..xing + 4)?, b"Xing" | b"Info") {
+		return None;
+	}
+	let flags = read_u32(xing + 4)?;
+	if flags & 0x1 == 0 {
+		return None;
+	}
+	let frame_count = read_u32(xing + 8)? as u64;
+	let mut lame = xing + 12;
+	for (flag, size) in [(0x2, 4), (0x4, 100), (0x8, 4)] {
+		if flags & flag != 0 {
+			lame += size;
+		}
+	}
+	if !matches!(frame.get(lame..lame + 4)?, b"LAME" | b"Lavc" | b"Lavf") {
+		return None;
+	}
+
+	// Both are 12-bit numbers, after the encoder version and settings
+	let bytes = frame.get(lame + 21..lame + 24)?;
+	let encoder_delay = ((bytes[0] as u32) << 4) | (bytes[1] >> 4) as u32;
+	let encoder_padding = (((bytes[1] & 0x0f) as u32) << 8) | bytes[2] as u32;
+	Some(Gapless {
+		sample_rate,
+		encoder_delay,
+		encoder_padding,
+		sample_count: (frame_count * samples_per_frame)
+			.saturating_sub(encoder_delay as u64 + encoder_padding as u64),
+	})
+}
+
 fn read_aiff(path: &Path) -> Result<SongTags, Error> {
 	let tag = id3::Tag::read_from_aiff_path(path).or_else(|error| {
 		if let Some(tag) = error.partial_tag {
@@ -176,16 +495,65 @@ fn read_ape_i32(item: &ape::Item) -> Option<i32> {
 	}
 }
 
+/// Parses the number at the start of a tag value.
+/// Handles values like "3/12" for track numbers or "2016-05-12" for dates.
+fn parse_leading_number<T: FromStr>(value: &str) -> Option<T> {
+	let format = Regex::new(r#"^\d+"#).unwrap();
+	let m = format.find(value.trim())?;
+	m.as_str().parse().ok()
+}
+
+// Numbers like `2/3` also carry a total
+fn parse_total<T: FromStr>(value: &str) -> Option<T> {
+	let (_, total) = value.split_once('/')?;
+	parse_leading_number(total)
+}
+
+// ReplayGain adjustments are written like `-6.54 dB`
+pub fn parse_replay_gain(value: &str) -> Option<f32> {
+	let value = value.trim();
+	let value = match value.len().checked_sub(2) {
+		Some(unit_start)
+			if value.is_char_boundary(unit_start)
+				&& value[unit_start..].eq_ignore_ascii_case("db") =>
+		{
+			&value[..unit_start]
+		}
+		_ => value,
+	};
+	value.trim().parse().ok().filter(|g: &f32| g.is_finite())
+}
+
+pub fn parse_peak(value: &str) -> Option<f32> {
+	value
+		.trim()
+		.parse()
+		.ok()
+		.filter(|p: &f32| p.is_finite() && *p >= 0.0)
+}
+
+// Opus R128 gains are Q7.8 numbers relative to -23 LUFS, 5dB below the ReplayGain reference level
+fn parse_r128_gain(value: &str) -> Option<f32> {
+	const R128_TO_REPLAY_GAIN: f32 = 5.0;
+	let gain: i16 = value.trim().parse().ok()?;
+	Some(gain as f32 / 256.0 + R128_TO_REPLAY_GAIN)
+}
+
+// Flags like the compilation flag are written as `1`
+fn parse_flag(value: &str) -> bool {
+	value.trim() == "1"
+}
+
 fn read_ape_x_of_y(item: &ape::Item) -> Option<u32> {
 	match item.value {
-		ape::ItemValue::Text(ref s) => {
-			let format = Regex::new(r#"^\d+"#).unwrap();
-			if let Some(m) = format.find(s) {
-				s[m.start()..m.end()].parse().ok()
-			} else {
-				None
-			}
-		}
+		ape::ItemValue::Text(ref s) => parse_leading_number(s),
+		_ => None,
+	}
+}
+
+fn read_ape_total(item: &ape::Item) -> Option<u32> {
+	match item.value {
+		ape::ItemValue::Text(ref s) => parse_total(s),
 		_ => None,
 	}
 }
@@ -198,11 +566,37 @@ fn read_ape(path: &Path) -> Result<SongTags, Error> {
 	let title = tag.item("Title").and_then(read_ape_string);
 	let year = tag.item("Year").and_then(read_ape_i32);
 	let disc_number = tag.item("Disc").and_then(read_ape_x_of_y);
+	let disc_total = tag.item("Disc").and_then(read_ape_total);
 	let track_number = tag.item("Track").and_then(read_ape_x_of_y);
 	let lyricist = tag.item("LYRICIST").and_then(read_ape_string);
 	let composer = tag.item("COMPOSER").and_then(read_ape_string);
+	let conductor = tag.item("CONDUCTOR").and_then(read_ape_string);
+	let artist_sort = tag.item("ARTISTSORT").and_then(read_ape_string);
+	let album_artist_sort = tag.item("ALBUMARTISTSORT").and_then(read_ape_string);
+	let album_sort = tag.item("ALBUMSORT").and_then(read_ape_string);
+	let title_sort = tag.item("TITLESORT").and_then(read_ape_string);
+	let has_lyrics = tag.item("Lyrics").is_some();
+	let read_ape_number = |key: &str, parse: fn(&str) -> Option<f32>| {
+		tag.item(key)
+			.and_then(read_ape_string)
+			.and_then(|v| parse(&v))
+	};
+	let track_gain = read_ape_number("REPLAYGAIN_TRACK_GAIN", parse_replay_gain);
+	let track_peak = read_ape_number("REPLAYGAIN_TRACK_PEAK", parse_peak);
+	let album_gain = read_ape_number("REPLAYGAIN_ALBUM_GAIN", parse_replay_gain);
+	let album_peak = read_ape_number("REPLAYGAIN_ALBUM_PEAK", parse_peak);
+	let musicbrainz_recording_id = tag.item("MUSICBRAINZ_TRACKID").and_then(read_ape_string);
+	let musicbrainz_release_id = tag.item("MUSICBRAINZ_ALBUMID").and_then(read_ape_string);
+	let musicbrainz_artist_id = tag.item("MUSICBRAINZ_ARTISTID").and_then(read_ape_string);
+	let musicbrainz_album_artist_id = tag
+		.item("MUSICBRAINZ_ALBUMARTISTID")
+		.and_then(read_ape_string);
 	let genre = tag.item("GENRE").and_then(read_ape_string);
 	let label = tag.item("PUBLISHER").and_then(read_ape_string);
+	let compilation = tag
+		.item("Compilation")
+		.and_then(read_ape_string)
+		.is_some_and(|v| parse_flag(&v));
 	Ok(SongTags {
 		artist,
 		album_artist,
@@ -210,20 +604,37 @@ fn read_ape(path: &Path) -> Result<SongTags, Error> {
 		title,
 		duration: None,
 		disc_number,
+		disc_total,
 		track_number,
 		year,
 		has_artwork: false,
+		has_lyrics,
 		lyricist,
 		composer,
+		conductor,
 		genre,
 		label,
+		compilation,
+		artist_sort,
+		album_artist_sort,
+		album_sort,
+		title_sort,
+		track_gain,
+		track_peak,
+		album_gain,
+		album_peak,
+		musicbrainz_recording_id,
+		musicbrainz_release_id,
+		musicbrainz_artist_id,
+		musicbrainz_album_artist_id,
+		sample_rate: None,
+		encoder_delay: None,
+		encoder_padding: None,
+		sample_count: None,
 	})
 }
 
-fn read_vorbis(path: &Path) -> Result<SongTags, Error> {
-	let file = fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
-	let source = OggStreamReader::new(file)?;
-
+fn read_vorbis_comments(comments: Vec<(String, String)>) -> SongTags {
 	let mut tags = SongTags {
 		artist: None,
 		album_artist: None,
@@ -231,75 +642,142 @@ fn read_vorbis(path: &Path) -> Result<SongTags, Error> {
 		title: None,
 		duration: None,
 		disc_number: None,
+		disc_total: None,
 		track_number: None,
 		year: None,
 		has_artwork: false,
+		has_lyrics: false,
 		lyricist: None,
 		composer: None,
+		conductor: None,
 		genre: None,
 		label: None,
+		compilation: false,
+		artist_sort: None,
+		album_artist_sort: None,
+		album_sort: None,
+		title_sort: None,
+		track_gain: None,
+		track_peak: None,
+		album_gain: None,
+		album_peak: None,
+		musicbrainz_recording_id: None,
+		musicbrainz_release_id: None,
+		musicbrainz_artist_id: None,
+		musicbrainz_album_artist_id: None,
+		sample_rate: None,
+		encoder_delay: None,
+		encoder_padding: None,
+		sample_count: None,
 	};
 
-	for (key, value) in source.comment_hdr.comment_list {
+	for (key, value) in comments {
 		utils::match_ignore_case! {
 			match key {
 				"TITLE" => tags.title = Some(value),
 				"ALBUM" => tags.album = Some(value),
 				"ARTIST" => tags.artist = Some(value),
 				"ALBUMARTIST" => tags.album_artist = Some(value),
-				"TRACKNUMBER" => tags.track_number = value.parse::<u32>().ok(),
-				"DISCNUMBER" => tags.disc_number = value.parse::<u32>().ok(),
-				"DATE" => tags.year = value.parse::<i32>().ok(),
+				"TRACKNUMBER" => tags.track_number = parse_leading_number(&value),
+				"DISCNUMBER" => {
+					tags.disc_number = parse_leading_number(&value);
+					tags.disc_total = parse_total(&value).or(tags.disc_total);
+				},
+				"DISCTOTAL" => tags.disc_total = parse_leading_number(&value),
+				"TOTALDISCS" => tags.disc_total = parse_leading_number(&value),
+				"DATE" => tags.year = parse_leading_number(&value),
 				"LYRICIST" => tags.lyricist = Some(value),
+				"LYRICS" => tags.has_lyrics = true,
+				"UNSYNCEDLYRICS" => tags.has_lyrics = true,
 				"COMPOSER" => tags.composer = Some(value),
+				"CONDUCTOR" => tags.conductor = Some(value),
 				"GENRE" => tags.genre = Some(value),
 				"PUBLISHER" => tags.label = Some(value),
+				"COMPILATION" => tags.compilation = parse_flag(&value),
+				"ARTISTSORT" => tags.artist_sort = Some(value),
+				"ALBUMARTISTSORT" => tags.album_artist_sort = Some(value),
+				"ALBUMSORT" => tags.album_sort = Some(value),
+				"TITLESORT" => tags.title_sort = Some(value),
+				"REPLAYGAIN_TRACK_GAIN" => tags.track_gain = parse_replay_gain(&value),
+				"REPLAYGAIN_TRACK_PEAK" => tags.track_peak = parse_peak(&value),
+				"REPLAYGAIN_ALBUM_GAIN" => tags.album_gain = parse_replay_gain(&value),
+				"REPLAYGAIN_ALBUM_PEAK" => tags.album_peak = parse_peak(&value),
+				"MUSICBRAINZ_TRACKID" => tags.musicbrainz_recording_id = Some(value),
+				"MUSICBRAINZ_ALBUMID" => tags.musicbrainz_release_id = Some(value),
+				"MUSICBRAINZ_ARTISTID" => tags.musicbrainz_artist_id = Some(value),
+				"MUSICBRAINZ_ALBUMARTISTID" => tags.musicbrainz_album_artist_id = Some(value),
+				// Opus files carry R128 gains instead, which take precedence
+				"R128_TRACK_GAIN" => tags.track_gain = parse_r128_gain(&value).or(tags.track_gain),
+				"R128_ALBUM_GAIN" => tags.album_gain = parse_r128_gain(&value).or(tags.album_gain),
 				_ => (),
 			}
 		}
 	}
 
-	Ok(tags)
+	tags
 }
 
-fn read_opus(path: &Path) -> Result<SongTags, Error> {
-	let headers = opus_headers::parse_from_path(path)?;
+/// Returns the granule position of the last page in an Ogg file.
+/// For Vorbis and Opus streams, this is the total number of samples.
+fn read_ogg_last_granule_position(path: &Path) -> Result<Option<u64>, Error> {
+	// Ogg pages are at most 65307 bytes long
+	const MAX_PAGE_SIZE: u64 = 65_307;
+	let mut file = fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+	let file_size = file
+		.metadata()
+		.map_err(|e| Error::Io(path.to_owned(), e))?
+		.len();
+	let start = file_size.saturating_sub(2 * MAX_PAGE_SIZE);
+	let mut buffer = Vec::new();
+	file.seek(SeekFrom::Start(start))
+		.and_then(|_| file.read_to_end(&mut buffer))
+		.map_err(|e| Error::Io(path.to_owned(), e))?;
 
-	let mut tags = SongTags {
-		artist: None,
-		album_artist: None,
-		album: None,
-		title: None,
-		duration: None,
-		disc_number: None,
-		track_number: None,
-		year: None,
-		has_artwork: false,
-		lyricist: None,
-		composer: None,
-		genre: None,
-		label: None,
-	};
+	let granule_position = buffer
+		.windows(4)
+		.rposition(|w| w == b"OggS")
+		.and_then(|page_start| buffer.get(page_start + 6..page_start + 14))
+		.and_then(|bytes| bytes.try_into().ok())
+		.map(i64::from_le_bytes)
+		.and_then(|position| u64::try_from(position).ok());
+	Ok(granule_position)
+}
 
-	for (key, value) in headers.comments.user_comments {
-		utils::match_ignore_case! {
-			match key {
-				"TITLE" => tags.title = Some(value),
-				"ALBUM" => tags.album = Some(value),
-				"ARTIST" => tags.artist = Some(value),
-				"ALBUMARTIST" => tags.album_artist = Some(value),
-				"TRACKNUMBER" => tags.track_number = value.parse::<u32>().ok(),
-				"DISCNUMBER" => tags.disc_number = value.parse::<u32>().ok(),
-				"DATE" => tags.year = value.parse::<i32>().ok(),
-				"LYRICIST" => tags.lyricist = Some(value),
-				"COMPOSER" => tags.composer = Some(value),
-				"GENRE" => tags.genre = Some(value),
-				"PUBLISHER" => tags.label = Some(value),
-				_ => (),
-			}
-		}
+fn read_vorbis(path: &Path) -> Result<SongTags, Error> {
+	let file = fs::File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+	let source = OggStreamReader::new(file)?;
+	let sample_rate = source.ident_hdr.audio_sample_rate as u64;
+
+	let mut tags = read_vorbis_comments(source.comment_hdr.comment_list);
+	if sample_rate > 0 {
+		// The granule position of the last page leaves out the padding of the final packet
+		let sample_count = read_ogg_last_granule_position(path)?;
+		tags.duration = sample_count.map(|s| (s / sample_rate) as u32);
+		tags.set_gapless(sample_count.map(|sample_count| Gapless {
+			sample_rate: sample_rate as u32,
+			encoder_delay: 0,
+			encoder_padding: 0,
+			sample_count,
+		}));
 	}
+	Ok(tags)
+}
+
+fn read_opus(path: &Path) -> Result<SongTags, Error> {
+	// Opus granule positions are always expressed at 48kHz
+	const OPUS_SAMPLE_RATE: u64 = 48_000;
+	let headers = opus_headers::parse_from_path(path)?;
+	let pre_skip = headers.id.pre_skip as u64;
 
+	let mut tags = read_vorbis_comments(headers.comments.user_comments.into_iter().collect());
+	let sample_count = read_ogg_last_granule_position(path)?.map(|s| s.saturating_sub(pre_skip));
+	tags.duration = sample_count.map(|s| (s / OPUS_SAMPLE_RATE) as u32);
+	tags.set_gapless(sample_count.map(|sample_count| Gapless {
+		sample_rate: OPUS_SAMPLE_RATE as u32,
+		encoder_delay: pre_skip as u32,
+		encoder_padding: 0,
+		sample_count,
+	}));
 	Ok(tags)
 }
 
@@ -310,13 +788,28 @@ fn read_flac(path: &Path) -> Result<SongTags, Error> {
 		.ok_or(Error::VorbisCommentNotFoundInFlacFile)?;
 	let disc_number = vorbis
 		.get("DISCNUMBER")
-		.and_then(|d| d[0].parse::<u32>().ok());
-	let year = vorbis.get("DATE").and_then(|d| d[0].parse::<i32>().ok());
+		.and_then(|d| parse_leading_number(&d[0]));
+	let disc_total = vorbis
+		.get("DISCTOTAL")
+		.or_else(|| vorbis.get("TOTALDISCS"))
+		.and_then(|d| parse_leading_number(&d[0]))
+		.or_else(|| vorbis.get("DISCNUMBER").and_then(|d| parse_total(&d[0])));
+	let track_number = vorbis
+		.get("TRACKNUMBER")
+		.and_then(|d| parse_leading_number(&d[0]));
+	let year = vorbis.get("DATE").and_then(|d| parse_leading_number(&d[0]));
 	let mut streaminfo = tag.get_blocks(metaflac::BlockType::StreamInfo);
-	let duration = match streaminfo.next() {
-		Some(metaflac::Block::StreamInfo(s)) => Some(s.total_samples as u32 / s.sample_rate),
+	// FLAC is lossless, its stream holds the exact samples of the source
+	let gapless = match streaminfo.next() {
+		Some(metaflac::Block::StreamInfo(s)) if s.sample_rate > 0 => Some(Gapless {
+			sample_rate: s.sample_rate,
+			encoder_delay: 0,
+			encoder_padding: 0,
+			sample_count: s.total_samples,
+		}),
 		_ => None,
 	};
+	let duration = gapless.map(|g| (g.sample_count / g.sample_rate as u64) as u32);
 	let has_artwork = tag.pictures().count() > 0;
 
 	Ok(SongTags {
@@ -326,19 +819,71 @@ fn read_flac(path: &Path) -> Result<SongTags, Error> {
 		title: vorbis.title().map(|v| v[0].clone()),
 		duration,
 		disc_number,
-		track_number: vorbis.track(),
+		disc_total,
+		track_number,
 		year,
 		has_artwork,
+		has_lyrics: find_vorbis_lyrics(vorbis).is_some(),
 		lyricist: vorbis.get("LYRICIST").map(|v| v[0].clone()),
 		composer: vorbis.get("COMPOSER").map(|v| v[0].clone()),
+		conductor: vorbis.get("CONDUCTOR").map(|v| v[0].clone()),
 		genre: vorbis.get("GENRE").map(|v| v[0].clone()),
 		label: vorbis.get("PUBLISHER").map(|v| v[0].clone()),
+		compilation: vorbis.get("COMPILATION").is_some_and(|v| parse_flag(&v[0])),
+		artist_sort: vorbis.get("ARTISTSORT").map(|v| v[0].clone()),
+		album_artist_sort: vorbis.get("ALBUMARTISTSORT").map(|v| v[0].clone()),
+		album_sort: vorbis.get("ALBUMSORT").map(|v| v[0].clone()),
+		title_sort: vorbis.get("TITLESORT").map(|v| v[0].clone()),
+		track_gain: vorbis
+			.get("REPLAYGAIN_TRACK_GAIN")
+			.and_then(|v| parse_replay_gain(&v[0])),
+		track_peak: vorbis
+			.get("REPLAYGAIN_TRACK_PEAK")
+			.and_then(|v| parse_peak(&v[0])),
+		album_gain: vorbis
+			.get("REPLAYGAIN_ALBUM_GAIN")
+			.and_then(|v| parse_replay_gain(&v[0])),
+		album_peak: vorbis
+			.get("REPLAYGAIN_ALBUM_PEAK")
+			.and_then(|v| parse_peak(&v[0])),
+		musicbrainz_recording_id: vorbis.get("MUSICBRAINZ_TRACKID").map(|v| v[0].clone()),
+		musicbrainz_release_id: vorbis.get("MUSICBRAINZ_ALBUMID").map(|v| v[0].clone()),
+		musicbrainz_artist_id: vorbis.get("MUSICBRAINZ_ARTISTID").map(|v| v[0].clone()),
+		musicbrainz_album_artist_id: vorbis
+			.get("MUSICBRAINZ_ALBUMARTISTID")
+			.map(|v| v[0].clone()),
+		sample_rate: gapless.map(|g| g.sample_rate),
+		encoder_delay: gapless.map(|g| g.encoder_delay),
+		encoder_padding: gapless.map(|g| g.encoder_padding),
+		sample_count: gapless.map(|g| g.sample_count),
 	})
 }
 
 fn read_mp4(path: &Path) -> Result<SongTags, Error> {
 	let mut tag = mp4ameta::Tag::read_from_path(path)?;
 	let label_ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", "Label");
+	let conductor_ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", "CONDUCTOR");
+	let read_mp4_number = |tag: &mp4ameta::Tag, name: &str, parse: fn(&str) -> Option<f32>| {
+		let ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", name);
+		let value = tag.strings_of(&ident).next().and_then(parse);
+		value
+	};
+	let track_gain = read_mp4_number(&tag, "replaygain_track_gain", parse_replay_gain);
+	let track_peak = read_mp4_number(&tag, "replaygain_track_peak", parse_peak);
+	let album_gain = read_mp4_number(&tag, "replaygain_album_gain", parse_replay_gain);
+	let album_peak = read_mp4_number(&tag, "replaygain_album_peak", parse_peak);
+	let read_mp4_string = |tag: &mp4ameta::Tag, name: &str| {
+		let ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", name);
+		let value = tag.strings_of(&ident).next().map(String::from);
+		value
+	};
+	let musicbrainz_recording_id = read_mp4_string(&tag, "MusicBrainz Track Id");
+	let musicbrainz_release_id = read_mp4_string(&tag, "MusicBrainz Album Id");
+	let musicbrainz_artist_id = read_mp4_string(&tag, "MusicBrainz Artist Id");
+	let musicbrainz_album_artist_id = read_mp4_string(&tag, "MusicBrainz Album Artist Id");
+	let gapless = tag.sample_rate().and_then(|sample_rate| {
+		read_mp4_string(&tag, "iTunSMPB").and_then(|v| parse_itunes_gapless(&v, sample_rate.hz()))
+	});
 
 	Ok(SongTags {
 		artist: tag.take_artist(),
@@ -347,13 +892,48 @@ fn read_mp4(path: &Path) -> Result<SongTags, Error> {
 		title: tag.take_title(),
 		duration: tag.duration().map(|v| v.as_secs() as u32),
 		disc_number: tag.disc_number().map(|d| d as u32),
+		disc_total: tag.total_discs().map(|d| d as u32),
 		track_number: tag.track_number().map(|d| d as u32),
-		year: tag.year().and_then(|v| v.parse::<i32>().ok()),
+		year: tag.year().and_then(parse_leading_number),
 		has_artwork: tag.artwork().is_some(),
+		has_lyrics: tag.lyrics().is_some(),
 		lyricist: tag.take_lyricist(),
 		composer: tag.take_composer(),
+		conductor: tag.take_strings_of(&conductor_ident).next(),
 		genre: tag.take_genre(),
 		label: tag.take_strings_of(&label_ident).next(),
+		compilation: tag.compilation(),
+		artist_sort: tag.take_strings_of(&mp4ameta::Fourcc(*b"soar")).next(),
+		album_artist_sort: tag.take_strings_of(&mp4ameta::Fourcc(*b"soaa")).next(),
+		album_sort: tag.take_strings_of(&mp4ameta::Fourcc(*b"soal")).next(),
+		title_sort: tag.take_strings_of(&mp4ameta::Fourcc(*b"sonm")).next(),
+		track_gain,
+		track_peak,
+		album_gain,
+		album_peak,
+		musicbrainz_recording_id,
+		musicbrainz_release_id,
+		musicbrainz_artist_id,
+		musicbrainz_album_artist_id,
+		sample_rate: gapless.map(|g| g.sample_rate),
+		encoder_delay: gapless.map(|g| g.encoder_delay),
+		encoder_padding: gapless.map(|g| g.encoder_padding),
+		sample_count: gapless.map(|g| g.sample_count),
+	})
+}
+
+// iTunes stores gapless information as hexadecimal numbers following a reserved field, such as
+// ` 00000000 00000840 000001CA 00000000003F31F6 …`
+fn parse_itunes_gapless(value: &str, sample_rate: u32) -> Option<Gapless> {
+	let mut fields = value
+		.split_whitespace()
+		.map(|f| u64::from_str_radix(f, 16).ok());
+	let _reserved = fields.next()?;
+	Some(Gapless {
+		sample_rate,
+		encoder_delay: u32::try_from(fields.next()??).ok()?,
+		encoder_padding: u32::try_from(fields.next()??).ok()?,
+		sample_count: fields.next()??,
 	})
 }
 
@@ -361,6 +941,7 @@ fn read_mp4(path: &Path) -> Result<SongTags, Error> {
 fn reads_file_metadata() {
 	let sample_tags = SongTags {
 		disc_number: Some(3),
+		disc_total: Some(4),
 		track_number: Some(1),
 		title: Some("TEST TITLE".into()),
 		artist: Some("TEST ARTIST".into()),
@@ -369,21 +950,66 @@ fn reads_file_metadata() {
 		duration: None,
 		year: Some(2016),
 		has_artwork: false,
+		has_lyrics: false,
 		lyricist: Some("TEST LYRICIST".into()),
 		composer: Some("TEST COMPOSER".into()),
+		conductor: None,
 		genre: Some("TEST GENRE".into()),
 		label: Some("TEST LABEL".into()),
+		compilation: false,
+		artist_sort: None,
+		album_artist_sort: None,
+		album_sort: None,
+		title_sort: None,
+		track_gain: None,
+		track_peak: None,
+		album_gain: None,
+		album_peak: None,
+		musicbrainz_recording_id: None,
+		musicbrainz_release_id: None,
+		musicbrainz_artist_id: None,
+		musicbrainz_album_artist_id: None,
+		sample_rate: None,
+		encoder_delay: None,
+		encoder_padding: None,
+		sample_count: None,
 	};
 	let flac_sample_tag = SongTags {
 		duration: Some(0),
+		sample_rate: Some(44100),
+		encoder_delay: Some(0),
+		encoder_padding: Some(0),
+		sample_count: Some(20375),
 		..sample_tags.clone()
 	};
 	let mp3_sample_tag = SongTags {
 		duration: Some(0),
+		sample_rate: Some(44100),
+		encoder_delay: Some(576),
+		encoder_padding: Some(2089),
+		sample_count: Some(20375),
 		..sample_tags.clone()
 	};
 	let m4a_sample_tag = SongTags {
 		duration: Some(0),
+		disc_total: None,
+		..sample_tags.clone()
+	};
+	let ogg_sample_tag = SongTags {
+		duration: Some(0),
+		sample_rate: Some(44100),
+		encoder_delay: Some(0),
+		encoder_padding: Some(0),
+		sample_count: Some(20375),
+		..sample_tags.clone()
+	};
+	let opus_sample_tag = SongTags {
+		duration: Some(30),
+		disc_total: None,
+		sample_rate: Some(48000),
+		encoder_delay: Some(312),
+		encoder_padding: Some(0),
+		sample_count: Some(1440000),
 		..sample_tags.clone()
 	};
 	assert_eq!(
@@ -396,7 +1022,7 @@ fn reads_file_metadata() {
 	);
 	assert_eq!(
 		read(Path::new("test-data/formats/sample.ogg")).unwrap(),
-		sample_tags
+		ogg_sample_tag
 	);
 	assert_eq!(
 		read(Path::new("test-data/formats/sample.flac")).unwrap(),
@@ -408,7 +1034,7 @@ fn reads_file_metadata() {
 	);
 	assert_eq!(
 		read(Path::new("test-data/formats/sample.opus")).unwrap(),
-		sample_tags
+		opus_sample_tag
 	);
 	assert_eq!(
 		read(Path::new("test-data/formats/sample.ape")).unwrap(),
@@ -420,6 +1046,32 @@ fn reads_file_metadata() {
 	);
 }
 
+#[test]
+fn reads_itunes_gapless_info() {
+	let value = " 00000000 00000840 000001CA 00000000003F31F6 00000000 00000000 00000000";
+	assert_eq!(
+		parse_itunes_gapless(value, 44100),
+		Some(Gapless {
+			sample_rate: 44100,
+			encoder_delay: 2112,
+			encoder_padding: 458,
+			sample_count: 4141558,
+		})
+	);
+	assert_eq!(parse_itunes_gapless(" 00000000 00000840", 44100), None);
+}
+
+#[test]
+fn normalizes_tags() {
+	let tags = read_vorbis_comments(vec![
+		("ARTIST".to_owned(), "Bjo\u{308}rk".to_owned()),
+		("ALBUM".to_owned(), "Homogenic".to_owned()),
+	])
+	.normalized();
+	assert_eq!(tags.artist.as_deref(), Some("Bj\u{f6}rk"));
+	assert_eq!(tags.album.as_deref(), Some("Homogenic"));
+}
+
 #[test]
 fn reads_embedded_artwork() {
 	assert!(
@@ -448,3 +1100,246 @@ fn reads_embedded_artwork() {
 			.has_artwork
 	);
 }
+
+#[test]
+fn reads_flac_dates_and_numbers_with_extra_content() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+	let path = output_dir.join("sample.flac");
+	fs::copy("test-data/formats/sample.flac", &path).unwrap();
+
+	let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+	tag.set_vorbis("DATE", vec!["2016-05-12"]);
+	tag.set_vorbis("TRACKNUMBER", vec!["4/12"]);
+	tag.set_vorbis("DISCNUMBER", vec!["2/3"]);
+	tag.remove_vorbis("DISCTOTAL");
+	tag.remove_vorbis("TOTALDISCS");
+	tag.save().unwrap();
+
+	let tags = read(&path).unwrap();
+	assert_eq!(tags.year, Some(2016));
+	assert_eq!(tags.track_number, Some(4));
+	assert_eq!(tags.disc_number, Some(2));
+	assert_eq!(tags.disc_total, Some(3));
+}
+
+#[test]
+fn reads_vorbis_dates_and_numbers_with_extra_content() {
+	let tags = read_vorbis_comments(vec![
+		("DATE".to_owned(), "2016-05-12".to_owned()),
+		("TRACKNUMBER".to_owned(), "4/12".to_owned()),
+		("DISCNUMBER".to_owned(), "2/3".to_owned()),
+	]);
+	assert_eq!(tags.year, Some(2016));
+	assert_eq!(tags.track_number, Some(4));
+	assert_eq!(tags.disc_number, Some(2));
+	assert_eq!(tags.disc_total, Some(3));
+
+	let tags = read_vorbis_comments(vec![
+		("DISCTOTAL".to_owned(), "3".to_owned()),
+		("DISCNUMBER".to_owned(), "2".to_owned()),
+	]);
+	assert_eq!(tags.disc_number, Some(2));
+	assert_eq!(tags.disc_total, Some(3));
+}
+
+#[test]
+fn reads_mp4_full_dates() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+	let path = output_dir.join("sample.m4a");
+	fs::copy("test-data/formats/sample.m4a", &path).unwrap();
+
+	let mut tag = mp4ameta::Tag::read_from_path(&path).unwrap();
+	tag.set_year("2016-05-12T07:00:00Z");
+	tag.write_to_path(&path).unwrap();
+
+	assert_eq!(read(&path).unwrap().year, Some(2016));
+}
+
+#[test]
+fn reads_compilation_flags() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+
+	let flac_path = output_dir.join("sample.flac");
+	fs::copy("test-data/formats/sample.flac", &flac_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&flac_path).unwrap();
+	tag.set_vorbis("COMPILATION", vec!["1"]);
+	tag.save().unwrap();
+	assert!(read(&flac_path).unwrap().compilation);
+
+	let mp3_path = output_dir.join("sample.mp3");
+	fs::copy("test-data/formats/sample.mp3", &mp3_path).unwrap();
+	let mut tag = id3::Tag::read_from_path(&mp3_path).unwrap();
+	tag.set_text("TCMP", "1");
+	tag.write_to_path(&mp3_path, id3::Version::Id3v24).unwrap();
+	assert!(read(&mp3_path).unwrap().compilation);
+
+	let m4a_path = output_dir.join("sample.m4a");
+	fs::copy("test-data/formats/sample.m4a", &m4a_path).unwrap();
+	let mut tag = mp4ameta::Tag::read_from_path(&m4a_path).unwrap();
+	tag.set_compilation();
+	tag.write_to_path(&m4a_path).unwrap();
+	assert!(read(&m4a_path).unwrap().compilation);
+
+	let tags = read_vorbis_comments(vec![("COMPILATION".to_owned(), "0".to_owned())]);
+	assert!(!tags.compilation);
+}
+
+#[test]
+fn reads_conductors() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+
+	let flac_path = output_dir.join("sample.flac");
+	fs::copy("test-data/formats/sample.flac", &flac_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&flac_path).unwrap();
+	tag.set_vorbis("CONDUCTOR", vec!["Herbert von Karajan"]);
+	tag.save().unwrap();
+	assert_eq!(
+		read(&flac_path).unwrap().conductor.as_deref(),
+		Some("Herbert von Karajan")
+	);
+
+	let mp3_path = output_dir.join("sample.mp3");
+	fs::copy("test-data/formats/sample.mp3", &mp3_path).unwrap();
+	let mut tag = id3::Tag::read_from_path(&mp3_path).unwrap();
+	tag.set_text("TPE3", "Herbert von Karajan");
+	tag.write_to_path(&mp3_path, id3::Version::Id3v24).unwrap();
+	assert_eq!(
+		read(&mp3_path).unwrap().conductor.as_deref(),
+		Some("Herbert von Karajan")
+	);
+
+	let m4a_path = output_dir.join("sample.m4a");
+	fs::copy("test-data/formats/sample.m4a", &m4a_path).unwrap();
+	let mut tag = mp4ameta::Tag::read_from_path(&m4a_path).unwrap();
+	let conductor_ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", "CONDUCTOR");
+	tag.set_data(
+		conductor_ident,
+		mp4ameta::Data::Utf8("Herbert von Karajan".into()),
+	);
+	tag.write_to_path(&m4a_path).unwrap();
+	assert_eq!(
+		read(&m4a_path).unwrap().conductor.as_deref(),
+		Some("Herbert von Karajan")
+	);
+}
+
+#[test]
+fn reads_replay_gain() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+
+	let flac_path = output_dir.join("sample.flac");
+	fs::copy("test-data/formats/sample.flac", &flac_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&flac_path).unwrap();
+	tag.set_vorbis("REPLAYGAIN_TRACK_GAIN", vec!["-6.54 dB"]);
+	tag.set_vorbis("REPLAYGAIN_TRACK_PEAK", vec!["0.988159"]);
+	tag.set_vorbis("REPLAYGAIN_ALBUM_GAIN", vec!["+1.20 dB"]);
+	tag.save().unwrap();
+	let tags = read(&flac_path).unwrap();
+	assert_eq!(tags.track_gain, Some(-6.54));
+	assert_eq!(tags.track_peak, Some(0.988159));
+	assert_eq!(tags.album_gain, Some(1.2));
+	assert_eq!(tags.album_peak, None);
+
+	let mp3_path = output_dir.join("sample.mp3");
+	fs::copy("test-data/formats/sample.mp3", &mp3_path).unwrap();
+	let mut tag = id3::Tag::read_from_path(&mp3_path).unwrap();
+	tag.add_frame(id3::frame::ExtendedText {
+		description: "replaygain_album_gain".to_owned(),
+		value: "-3.00 dB".to_owned(),
+	});
+	tag.write_to_path(&mp3_path, id3::Version::Id3v24).unwrap();
+	assert_eq!(read(&mp3_path).unwrap().album_gain, Some(-3.0));
+
+	let tags = read_vorbis_comments(vec![
+		("REPLAYGAIN_TRACK_GAIN".to_owned(), "-6.00 dB".to_owned()),
+		("R128_TRACK_GAIN".to_owned(), "-512".to_owned()),
+	]);
+	assert_eq!(tags.track_gain, Some(3.0));
+}
+
+#[test]
+fn reads_sort_names() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+
+	let flac_path = output_dir.join("sample.flac");
+	fs::copy("test-data/formats/sample.flac", &flac_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&flac_path).unwrap();
+	tag.set_vorbis("ARTISTSORT", vec!["Beatles, The"]);
+	tag.set_vorbis("ALBUMSORT", vec!["White Album, The"]);
+	tag.save().unwrap();
+	let tags = read(&flac_path).unwrap();
+	assert_eq!(tags.artist_sort.as_deref(), Some("Beatles, The"));
+	assert_eq!(tags.album_sort.as_deref(), Some("White Album, The"));
+
+	let mp3_path = output_dir.join("sample.mp3");
+	fs::copy("test-data/formats/sample.mp3", &mp3_path).unwrap();
+	let mut tag = id3::Tag::read_from_path(&mp3_path).unwrap();
+	tag.set_text("TSOP", "Beatles, The");
+	tag.set_text("TSOT", "Revolution 9");
+	tag.write_to_path(&mp3_path, id3::Version::Id3v24).unwrap();
+	let tags = read(&mp3_path).unwrap();
+	assert_eq!(tags.artist_sort.as_deref(), Some("Beatles, The"));
+	assert_eq!(tags.title_sort.as_deref(), Some("Revolution 9"));
+
+	let m4a_path = output_dir.join("sample.m4a");
+	fs::copy("test-data/formats/sample.m4a", &m4a_path).unwrap();
+	let mut tag = mp4ameta::Tag::read_from_path(&m4a_path).unwrap();
+	tag.set_data(
+		mp4ameta::Fourcc(*b"soaa"),
+		mp4ameta::Data::Utf8("Beatles, The".into()),
+	);
+	tag.write_to_path(&m4a_path).unwrap();
+	assert_eq!(
+		read(&m4a_path).unwrap().album_artist_sort.as_deref(),
+		Some("Beatles, The")
+	);
+}
+
+#[test]
+fn reads_musicbrainz_ids() {
+	let output_dir = crate::test::prepare_test_directory(crate::test_name!());
+	let recording_id = "0a2b9a5c-6a55-4b5c-9a6e-6c0a2f6b5f11";
+	let release_id = "7d3a9c2e-1b4f-4e8a-9f0d-3c5b2a1e6d42";
+
+	let flac_path = output_dir.join("sample.flac");
+	fs::copy("test-data/formats/sample.flac", &flac_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&flac_path).unwrap();
+	tag.set_vorbis("MUSICBRAINZ_TRACKID", vec![recording_id]);
+	tag.set_vorbis("MUSICBRAINZ_ALBUMID", vec![release_id]);
+	tag.save().unwrap();
+	let tags = read(&flac_path).unwrap();
+	assert_eq!(tags.musicbrainz_recording_id.as_deref(), Some(recording_id));
+	assert_eq!(tags.musicbrainz_release_id.as_deref(), Some(release_id));
+
+	let mp3_path = output_dir.join("sample.mp3");
+	fs::copy("test-data/formats/sample.mp3", &mp3_path).unwrap();
+	let mut tag = id3::Tag::read_from_path(&mp3_path).unwrap();
+	let mut ufid = b"http://musicbrainz.org\0".to_vec();
+	ufid.extend_from_slice(recording_id.as_bytes());
+	tag.add_frame(id3::Frame::with_content(
+		"UFID",
+		id3::Content::Unknown(id3::frame::Unknown {
+			data: ufid,
+			version: id3::Version::Id3v24,
+		}),
+	));
+	tag.add_frame(id3::frame::ExtendedText {
+		description: "MusicBrainz Album Id".to_owned(),
+		value: release_id.to_owned(),
+	});
+	tag.write_to_path(&mp3_path, id3::Version::Id3v24).unwrap();
+	let tags = read(&mp3_path).unwrap();
+	assert_eq!(tags.musicbrainz_recording_id.as_deref(), Some(recording_id));
+	assert_eq!(tags.musicbrainz_release_id.as_deref(), Some(release_id));
+
+	let m4a_path = output_dir.join("sample.m4a");
+	fs::copy("test-data/formats/sample.m4a", &m4a_path).unwrap();
+	let mut tag = mp4ameta::Tag::read_from_path(&m4a_path).unwrap();
+	let ident = mp4ameta::FreeformIdent::new("com.apple.iTunes", "MusicBrainz Track Id");
+	tag.set_data(ident, mp4ameta::Data::Utf8(recording_id.into()));
+	tag.write_to_path(&m4a_path).unwrap();
+	assert_eq!(
+		read(&m4a_path).unwrap().musicbrainz_recording_id.as_deref(),
+		Some(recording_id)
+	);
+}
@@ -0,0 +1,229 @@
+use diesel::prelude::*;
+use diesel::sql_types;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::*;
+use crate::db::{
+	favorites, listens, metadata_lookups, missing_songs, ratings, song_fingerprints, songs,
+};
+
+const PURGE_BUFFER_SIZE: usize = 500; // Deletions in each statement
+
+// Tables referring to songs by path, whose rows follow songs which moved
+const MOVED_TABLES: [&str; 6] = [
+	"audiobook_positions",
+	"favorites",
+	"listens",
+	"play_queue_songs",
+	"playlist_songs",
+	"ratings",
+];
+
+/// Song which disappeared from disk. Its ratings, favorites and listens are kept until the
+/// grace period runs out, in case the file comes back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingSong {
+	pub path: PathBuf,
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub missing_since: i64, // Seconds since the Unix epoch
+	pub play_count: i64,
+	pub rating: Option<f32>, // Averaged over all users who rated the song
+}
+
+#[derive(QueryableByName)]
+struct MissingSongRow {
+	#[diesel(sql_type = sql_types::Text)]
+	path: String,
+	#[diesel(sql_type = sql_types::Nullable<sql_types::Text>)]
+	title: Option<String>,
+	#[diesel(sql_type = sql_types::Nullable<sql_types::Text>)]
+	artist: Option<String>,
+	#[diesel(sql_type = sql_types::Nullable<sql_types::Text>)]
+	album: Option<String>,
+	#[diesel(sql_type = sql_types::BigInt)]
+	missing_since: i64,
+	#[diesel(sql_type = sql_types::BigInt)]
+	play_count: i64,
+	#[diesel(sql_type = sql_types::Nullable<sql_types::Float>)]
+	rating: Option<f32>,
+}
+
+#[derive(QueryableByName)]
+struct MoveRow {
+	#[diesel(sql_type = sql_types::Text)]
+	old_path: String,
+	#[diesel(sql_type = sql_types::Text)]
+	new_path: String,
+}
+
+impl Index {
+	/// Songs which disappeared from disk during recent updates, most recent first. Songs
+	/// outside of all mount points are listed by their real path.
+	pub fn get_missing_songs(&self) -> Result<Vec<MissingSong>, QueryError> {
+		let vfs = self.vfs_manager.get_vfs()?;
+		let mut connection = self.db.connect()?;
+		let rows: Vec<MissingSongRow> = diesel::sql_query(
+			r#"
+			SELECT m.path, m.title, m.artist, m.album, m.missing_since,
+				(SELECT COUNT(*) FROM listens l WHERE l.path = m.path) AS play_count,
+				(SELECT AVG(r.rating) FROM ratings r WHERE r.path = m.path) AS rating
+			FROM missing_songs m
+			ORDER BY m.missing_since DESC, m.path
+			"#,
+		)
+		.load(&mut connection)?;
+
+		Ok(rows
+			.into_iter()
+			.map(|row| {
+				let real_path = PathBuf::from(row.path);
+				MissingSong {
+					path: vfs.real_to_virtual(&real_path).unwrap_or(real_path),
+					title: row.title,
+					artist: row.artist,
+					album: row.album,
+					missing_since: row.missing_since,
+					play_count: row.play_count,
+					rating: row.rating,
+				}
+			})
+			.collect())
+	}
+
+	/// Forgets all missing songs right away, along with their ratings, favorites and listens.
+	pub fn purge_missing_songs(&self) -> Result<usize, QueryError> {
+		let mut connection = self.db.connect()?;
+		Ok(purge(&mut connection, None)?)
+	}
+}
+
+pub fn record_missing(
+	connection: &mut SqliteConnection,
+	real_paths: &[impl AsRef<Path>],
+) -> Result<(), diesel::result::Error> {
+	let missing_since = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default();
+	let paths = real_paths
+		.iter()
+		.map(|p| p.as_ref().to_string_lossy().into_owned())
+		.collect::<Vec<_>>();
+	for chunk in paths.chunks(PURGE_BUFFER_SIZE) {
+		let tombstones = songs::table.filter(songs::path.eq_any(chunk)).select((
+			songs::path,
+			songs::title,
+			songs::artist,
+			songs::album,
+			missing_since.into_sql::<sql_types::BigInt>(),
+			songs::content_hash,
+		));
+		diesel::insert_into(missing_songs::table)
+			.values(tombstones)
+			.into_columns((
+				missing_songs::path,
+				missing_songs::title,
+				missing_songs::artist,
+				missing_songs::album,
+				missing_songs::missing_since,
+				missing_songs::content_hash,
+			))
+			.execute(connection)?;
+	}
+	Ok(())
+}
+
+/// Songs which reappeared at a different path keep their ratings, favorites, listens and
+/// playlist entries. Moves are only followed when their audio is found exactly once among both
+/// missing and indexed songs, and when the new path has no data of its own.
+pub fn follow_moves(connection: &mut SqliteConnection) -> Result<usize, diesel::result::Error> {
+	let moves: Vec<MoveRow> = diesel::sql_query(
+		r#"
+		SELECT m.path AS old_path, s.path AS new_path
+		FROM missing_songs m
+		JOIN songs s ON s.content_hash = m.content_hash
+		WHERE (SELECT COUNT(*) FROM missing_songs WHERE content_hash = m.content_hash) = 1
+			AND (SELECT COUNT(*) FROM songs WHERE content_hash = m.content_hash) = 1
+			AND NOT EXISTS (SELECT 1 FROM favorites WHERE path = s.path)
+			AND NOT EXISTS (SELECT 1 FROM listens WHERE path = s.path)
+			AND NOT EXISTS (SELECT 1 FROM ratings WHERE path = s.path)
+		"#,
+	)
+	.load(connection)?;
+
+	connection.transaction(|connection| {
+		for m in &moves {
+			for table in MOVED_TABLES {
+				diesel::sql_query(format!(
+					"UPDATE OR IGNORE {table} SET path = ? WHERE path = ?"
+				))
+				.bind::<sql_types::Text, _>(&m.new_path)
+				.bind::<sql_types::Text, _>(&m.old_path)
+				.execute(connection)?;
+			}
+			diesel::delete(metadata_lookups::table.filter(metadata_lookups::path.eq(&m.old_path)))
+				.execute(connection)?;
+			diesel::delete(
+				song_fingerprints::table.filter(song_fingerprints::path.eq(&m.old_path)),
+			)
+			.execute(connection)?;
+			diesel::delete(missing_songs::table.filter(missing_songs::path.eq(&m.old_path)))
+				.execute(connection)?;
+		}
+		Ok(moves.len())
+	})
+}
+
+/// Songs which came back are no longer missing, songs missing for longer than the grace period
+/// are forgotten.
+pub fn prune(
+	connection: &mut SqliteConnection,
+	grace_period: Duration,
+) -> Result<usize, diesel::result::Error> {
+	diesel::delete(
+		missing_songs::table.filter(missing_songs::path.eq_any(songs::table.select(songs::path))),
+	)
+	.execute(connection)?;
+	let cutoff = SystemTime::now()
+		.checked_sub(grace_period)
+		.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default();
+	purge(connection, Some(cutoff))
+}
+
+fn purge(
+	connection: &mut SqliteConnection,
+	missing_before: Option<i64>,
+) -> Result<usize, diesel::result::Error> {
+	// Files which came back since the last update keep their data
+	let mut query = missing_songs::table
+		.select(missing_songs::path)
+		.filter(missing_songs::path.ne_all(songs::table.select(songs::path)))
+		.into_boxed();
+	if let Some(cutoff) = missing_before {
+		query = query.filter(missing_songs::missing_since.lt(cutoff));
+	}
+	let paths: Vec<String> = query.load(connection)?;
+
+	connection.transaction(|connection| {
+		for chunk in paths.chunks(PURGE_BUFFER_SIZE) {
+			diesel::delete(favorites::table.filter(favorites::path.eq_any(chunk)))
+				.execute(connection)?;
+			diesel::delete(listens::table.filter(listens::path.eq_any(chunk)))
+				.execute(connection)?;
+			diesel::delete(ratings::table.filter(ratings::path.eq_any(chunk)))
+				.execute(connection)?;
+			diesel::delete(metadata_lookups::table.filter(metadata_lookups::path.eq_any(chunk)))
+				.execute(connection)?;
+			diesel::delete(song_fingerprints::table.filter(song_fingerprints::path.eq_any(chunk)))
+				.execute(connection)?;
+			diesel::delete(missing_songs::table.filter(missing_songs::path.eq_any(chunk)))
+				.execute(connection)?;
+		}
+		Ok(paths.len())
+	})
+}
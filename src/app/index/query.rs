@@ -1,10 +1,15 @@
-use diesel::dsl::sql;
 use diesel::prelude::*;
-use diesel::sql_types;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::path::{Path, PathBuf};
 
+use super::search::{Filter, FuzzyMatcher, SearchQuery};
 use super::*;
-use crate::db::{self, directories, songs};
+use crate::app::transcode;
+use crate::db::{self, directories, search_key, songs};
+use crate::utils;
+
+const FUZZY_SEARCH_CHUNK_SIZE: usize = 500; // Matches loaded in each query
 
 #[derive(thiserror::Error, Debug)]
 pub enum QueryError {
@@ -12,8 +17,18 @@ pub enum QueryError {
 	Database(#[from] diesel::result::Error),
 	#[error(transparent)]
 	DatabaseConnection(#[from] db::Error),
+	#[error("Could not read chapters from `{0}`:\n\n{1}")]
+	ChaptersReading(PathBuf, metadata::Error),
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("Song has no lyrics: `{0}`")]
+	LyricsNotFound(PathBuf),
+	#[error("Could not read lyrics from `{0}`:\n\n{1}")]
+	LyricsReading(PathBuf, metadata::Error),
 	#[error("Song was not found: `{0}`")]
 	SongNotFound(PathBuf),
+	#[error("View was not found: `{0}`")]
+	ViewNotFound(String),
 	#[error(transparent)]
 	Vfs(#[from] vfs::Error),
 }
@@ -24,96 +39,179 @@ sql_function!(
 );
 
 impl Index {
-	pub fn browse<P>(&self, virtual_path: P) -> Result<Vec<CollectionFile>, QueryError>
+	pub fn browse<P>(
+		&self,
+		username: &str,
+		virtual_path: P,
+	) -> Result<Vec<CollectionFile>, QueryError>
 	where
 		P: AsRef<Path>,
 	{
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let virtual_path = virtual_path.as_ref();
+		let locale = self.get_collation_locale();
+		let key = (
+			vfs.mounts().clone(),
+			virtual_path.to_owned(),
+			locale.clone(),
+		);
+		self.cached(&self.browse_cache, key, || {
+			self.query_browse(&vfs, virtual_path, &locale)
+		})
+	}
+
+	fn query_browse(
+		&self,
+		vfs: &vfs::VFS,
+		virtual_path: &Path,
+		locale: &str,
+	) -> Result<Vec<CollectionFile>, QueryError> {
 		let mut output = Vec::new();
-		let vfs = self.vfs_manager.get_vfs()?;
 		let mut connection = self.db.connect()?;
+		let collator = collation::Collator::new(locale);
+		let file_name_key = |path: &str| {
+			let file_name = Path::new(path).file_name().unwrap_or_default();
+			collator.key(&file_name.to_string_lossy())
+		};
 
-		if virtual_path.as_ref().components().count() == 0 {
+		if virtual_path.components().count() == 0 {
 			// Browse top-level
-			let real_directories: Vec<Directory> = directories::table
+			let mut real_directories: Vec<Directory> = directories::table
 				.filter(directories::parent.is_null())
 				.load(&mut connection)?;
+			real_directories.sort_by_cached_key(|d| file_name_key(&d.path));
 			let virtual_directories = real_directories
 				.into_iter()
-				.filter_map(|d| d.virtualize(&vfs));
+				.filter_map(|d| d.virtualize(vfs));
 			output.extend(virtual_directories.map(CollectionFile::Directory));
 		} else {
 			// Browse sub-directory
 			let real_path = vfs.virtual_to_real(virtual_path)?;
 			let real_path_string = real_path.as_path().to_string_lossy().into_owned();
 
-			let real_directories: Vec<Directory> = directories::table
+			let mut real_directories: Vec<Directory> = directories::table
 				.filter(directories::parent.eq(&real_path_string))
-				.order(sql::<sql_types::Bool>("path COLLATE NOCASE ASC"))
 				.load(&mut connection)?;
+			real_directories.sort_by_cached_key(|d| file_name_key(&d.path));
 			let virtual_directories = real_directories
 				.into_iter()
-				.filter_map(|d| d.virtualize(&vfs));
+				.filter_map(|d| d.virtualize(vfs));
 			output.extend(virtual_directories.map(CollectionFile::Directory));
 
-			let real_songs: Vec<Song> = songs::table
+			let mut real_songs: Vec<Song> = songs::table
 				.filter(songs::parent.eq(&real_path_string))
-				.order(sql::<sql_types::Bool>("path COLLATE NOCASE ASC"))
 				.load(&mut connection)?;
-			let virtual_songs = real_songs.into_iter().filter_map(|s| s.virtualize(&vfs));
+			// Discs of a multi-disc album are kept together, even when file names interleave them
+			real_songs
+				.sort_by_cached_key(|s| (s.disc_number, s.track_number, file_name_key(&s.path)));
+			let virtual_songs = real_songs.into_iter().filter_map(|s| s.virtualize(vfs));
 			output.extend(virtual_songs.map(CollectionFile::Song));
 		}
 
 		Ok(output)
 	}
 
-	pub fn flatten<P>(&self, virtual_path: P) -> Result<Vec<Song>, QueryError>
+	// Also returns the number of songs across all pages
+	pub fn flatten<P>(
+		&self,
+		username: &str,
+		virtual_path: P,
+		page: Page,
+	) -> Result<(Vec<Song>, usize), QueryError>
 	where
 		P: AsRef<Path>,
 	{
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let virtual_path = virtual_path.as_ref();
+		// Listings of whole directories can be too large to keep around
+		if page.limit.is_none() {
+			return self.query_flatten(&vfs, virtual_path, page);
+		}
+		let key = (vfs.mounts().clone(), virtual_path.to_owned(), page);
+		self.cached(&self.flatten_cache, key, || {
+			self.query_flatten(&vfs, virtual_path, page)
+		})
+	}
+
+	fn query_flatten(
+		&self,
+		vfs: &vfs::VFS,
+		virtual_path: &Path,
+		page: Page,
+	) -> Result<(Vec<Song>, usize), QueryError> {
 		use self::songs::dsl::*;
-		let vfs = self.vfs_manager.get_vfs()?;
 		let mut connection = self.db.connect()?;
 
-		let real_songs: Vec<Song> = if virtual_path.as_ref().parent().is_some() {
-			let real_path = vfs.virtual_to_real(virtual_path)?;
-			let song_path_filter = {
-				let mut path_buf = real_path;
+		// Filtered on real paths so the database can paginate without loading the whole collection
+		let real_roots = if virtual_path.parent().is_some() {
+			vec![vfs.virtual_to_real(virtual_path)?]
+		} else {
+			vfs.mounts().iter().map(|m| m.source.clone()).collect()
+		};
+		if real_roots.is_empty() {
+			return Ok((Vec::new(), 0));
+		}
+		let song_path_filters: Vec<String> = real_roots
+			.into_iter()
+			.map(|mut path_buf| {
 				path_buf.push("%");
 				path_buf.as_path().to_string_lossy().into_owned()
-			};
-			songs
-				.filter(path.like(&song_path_filter))
-				.order(path)
-				.load(&mut connection)?
-		} else {
-			songs.order(path).load(&mut connection)?
+			})
+			.collect();
+		let make_query = || {
+			let mut query = songs.into_boxed();
+			for song_path_filter in &song_path_filters {
+				query = query.or_filter(path.like(song_path_filter));
+			}
+			query
 		};
 
-		let virtual_songs = real_songs.into_iter().filter_map(|s| s.virtualize(&vfs));
-		Ok(virtual_songs.collect::<Vec<_>>())
+		let total: i64 = make_query().count().get_result(&mut connection)?;
+		let limit = page.limit.map(|l| l as i64).unwrap_or(-1); // Negative means no limit
+		let real_songs: Vec<Song> = make_query()
+			.order((parent, disc_number, track_number, path))
+			.limit(limit)
+			.offset(page.offset as i64)
+			.load(&mut connection)?;
+
+		let virtual_songs = real_songs.into_iter().filter_map(|s| s.virtualize(vfs));
+		Ok((virtual_songs.collect::<Vec<_>>(), total as usize))
 	}
 
-	pub fn get_random_albums(&self, count: i64) -> Result<Vec<Directory>, QueryError> {
-		use self::directories::dsl::*;
-		let vfs = self.vfs_manager.get_vfs()?;
+	pub fn get_random_albums(
+		&self,
+		username: &str,
+		count: i64,
+	) -> Result<Vec<Directory>, QueryError> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
 		let mut connection = self.db.connect()?;
-		let real_directories: Vec<Directory> = directories
-			.filter(album.is_not_null())
-			.limit(count)
-			.order(random())
-			.load(&mut connection)?;
+		let mounts = vfs
+			.mounts()
+			.iter()
+			.filter(|m| m.content_type != vfs::ContentType::Audiobook);
+		let Some(query) = albums_within(mounts) else {
+			return Ok(Vec::new());
+		};
+		let real_directories: Vec<Directory> =
+			query.order(random()).limit(count).load(&mut connection)?;
 		let virtual_directories = real_directories
 			.into_iter()
 			.filter_map(|d| d.virtualize(&vfs));
 		Ok(virtual_directories.collect::<Vec<_>>())
 	}
 
-	pub fn get_recent_albums(&self, count: i64) -> Result<Vec<Directory>, QueryError> {
+	pub fn get_recent_albums(
+		&self,
+		username: &str,
+		count: i64,
+	) -> Result<Vec<Directory>, QueryError> {
 		use self::directories::dsl::*;
-		let vfs = self.vfs_manager.get_vfs()?;
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
 		let mut connection = self.db.connect()?;
-		let real_directories: Vec<Directory> = directories
-			.filter(album.is_not_null())
+		let Some(query) = albums_within(vfs.mounts().iter()) else {
+			return Ok(Vec::new());
+		};
+		let real_directories: Vec<Directory> = query
 			.order(date_added.desc())
 			.limit(count)
 			.load(&mut connection)?;
@@ -123,51 +221,144 @@ impl Index {
 		Ok(virtual_directories.collect::<Vec<_>>())
 	}
 
-	pub fn search(&self, query: &str) -> Result<Vec<CollectionFile>, QueryError> {
-		let vfs = self.vfs_manager.get_vfs()?;
+	pub fn search(&self, username: &str, query: &str) -> Result<Vec<CollectionFile>, QueryError> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let fold = self
+			.settings_manager
+			.get_search_accent_folding()
+			.unwrap_or(true);
+		let fuzzy = self.settings_manager.get_search_fuzzy().unwrap_or(false);
+		let key = (vfs.mounts().clone(), query.to_owned(), fold, fuzzy);
+		self.cached(&self.search_cache, key, || {
+			self.query_search(&vfs, query, fold, fuzzy)
+		})
+	}
+
+	fn query_search(
+		&self,
+		vfs: &vfs::VFS,
+		query: &str,
+		fold: bool,
+		fuzzy: bool,
+	) -> Result<Vec<CollectionFile>, QueryError> {
 		let mut connection = self.db.connect()?;
-		let like_test = format!("%{}%", query);
+		let query = SearchQuery::parse(query);
+		let like_test = format!("%{}%", utils::get_search_key(&query.text, fold));
+		let like = |value: &str| format!("%{}%", utils::get_search_key(value, fold));
 		let mut output = Vec::new();
 
-		// Find dirs with matching path and parent not matching
-		{
+		// Find dirs with matching path and parent not matching. Filters only apply to songs.
+		if query.filters.is_empty() {
 			use self::directories::dsl::*;
 			let real_directories: Vec<Directory> = directories
-				.filter(path.like(&like_test))
-				.filter(parent.not_like(&like_test))
+				.filter(search_key(path.nullable(), fold).like(&like_test))
+				.filter(search_key(parent, fold).not_like(&like_test))
 				.load(&mut connection)?;
 
 			let virtual_directories = real_directories
 				.into_iter()
-				.filter_map(|d| d.virtualize(&vfs));
+				.filter_map(|d| d.virtualize(vfs));
 
 			output.extend(virtual_directories.map(CollectionFile::Directory));
 		}
 
-		// Find songs with matching title/album/artist and non-matching parent
+		// Find songs with matching title/album/artist/genre/composer/conductor and non-matching parent
 		{
 			use self::songs::dsl::*;
-			let real_songs: Vec<Song> = songs
-				.filter(
-					path.like(&like_test)
-						.or(title.like(&like_test))
-						.or(album.like(&like_test))
-						.or(artist.like(&like_test))
-						.or(album_artist.like(&like_test)),
-				)
-				.filter(parent.not_like(&like_test))
-				.load(&mut connection)?;
+			let mut songs_query = songs.into_boxed();
+			if !query.text.is_empty() || query.filters.is_empty() {
+				songs_query = songs_query
+					.filter(
+						search_key(path.nullable(), fold)
+							.like(&like_test)
+							.or(search_key(title, fold).like(&like_test))
+							.or(search_key(album, fold).like(&like_test))
+							.or(search_key(artist, fold).like(&like_test))
+							.or(search_key(album_artist, fold).like(&like_test))
+							.or(search_key(genre, fold).like(&like_test))
+							.or(search_key(composer, fold).like(&like_test))
+							.or(search_key(conductor, fold).like(&like_test)),
+					)
+					.filter(search_key(parent.nullable(), fold).not_like(&like_test));
+			}
+			for filter in &query.filters {
+				songs_query = match filter {
+					Filter::Album(value) => {
+						songs_query.filter(search_key(album, fold).like(like(value)))
+					}
+					Filter::Artist(value) => songs_query.filter(
+						search_key(artist, fold).like(like(value)).or(search_key(
+							album_artist,
+							fold,
+						)
+						.like(like(value))),
+					),
+					Filter::Composer(value) => {
+						songs_query.filter(search_key(composer, fold).like(like(value)))
+					}
+					Filter::Conductor(value) => {
+						songs_query.filter(search_key(conductor, fold).like(like(value)))
+					}
+					Filter::Format(extension) => {
+						songs_query.filter(path.like(format!("%.{}", extension)))
+					}
+					Filter::Genre(value) => {
+						songs_query.filter(search_key(genre, fold).like(like(value)))
+					}
+					Filter::Title(value) => {
+						songs_query.filter(search_key(title, fold).like(like(value)))
+					}
+					Filter::Year(from, to) => {
+						if let Some(from) = from {
+							songs_query = songs_query.filter(year.ge(from));
+						}
+						if let Some(to) = to {
+							songs_query = songs_query.filter(year.le(to));
+						}
+						songs_query
+					}
+				};
+			}
+			let real_songs: Vec<Song> = songs_query.load(&mut connection)?;
 
-			let virtual_songs = real_songs.into_iter().filter_map(|d| d.virtualize(&vfs));
+			let virtual_songs = real_songs.into_iter().filter_map(|d| d.virtualize(vfs));
 
 			output.extend(virtual_songs.map(CollectionFile::Song));
 		}
 
+		// Results starting with the query come first
+		let text_key = utils::get_search_key(&query.text, fold);
+		if !text_key.is_empty() {
+			output.sort_by_key(|f| !is_prefix_match(f, &text_key, fold));
+		}
+
+		if fuzzy && query.filters.is_empty() && !text_key.is_empty() {
+			let exact_paths = output
+				.iter()
+				.map(|f| match f {
+					CollectionFile::Directory(d) => d.path.clone(),
+					CollectionFile::Song(s) => s.path.clone(),
+				})
+				.collect::<HashSet<_>>();
+			let fuzzy_matches = fuzzy_search(&mut connection, &text_key, fold)?;
+			output.extend(fuzzy_matches.into_iter().filter_map(|f| {
+				let f = match f {
+					CollectionFile::Directory(d) => CollectionFile::Directory(d.virtualize(vfs)?),
+					CollectionFile::Song(s) => CollectionFile::Song(s.virtualize(vfs)?),
+				};
+				let path = match &f {
+					CollectionFile::Directory(d) => &d.path,
+					CollectionFile::Song(s) => &s.path,
+				};
+				(!exact_paths.contains(path)).then_some(f)
+			}));
+		}
+
 		Ok(output)
 	}
 
-	pub fn get_song(&self, virtual_path: &Path) -> Result<Song, QueryError> {
-		let vfs = self.vfs_manager.get_vfs()?;
+	pub fn get_song(&self, username: &str, virtual_path: &Path) -> Result<Song, QueryError> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
 		let mut connection = self.db.connect()?;
 
 		let real_path = vfs.virtual_to_real(virtual_path)?;
@@ -183,4 +374,230 @@ impl Index {
 			None => Err(QueryError::SongNotFound(real_path)),
 		}
 	}
+
+	pub fn get_volume(
+		&self,
+		real_path: &Path,
+		normalization: transcode::Normalization,
+	) -> Result<Option<transcode::Volume>, QueryError> {
+		let target = self
+			.settings_manager
+			.get_loudness_target()
+			.unwrap_or(transcode::REPLAY_GAIN_REFERENCE);
+		if normalization == transcode::Normalization::Loudness {
+			return Ok(Some(transcode::Volume::Loudness(target)));
+		}
+		let mut connection = self.db.connect()?;
+		let values = songs::table
+			.filter(songs::path.eq(real_path.to_string_lossy()))
+			.select((
+				songs::track_gain,
+				songs::track_peak,
+				songs::album_gain,
+				songs::album_peak,
+			))
+			.get_result::<(Option<f32>, Option<f32>, Option<f32>, Option<f32>)>(&mut connection)
+			.optional()?;
+		let Some((track_gain, track_peak, album_gain, album_peak)) = values else {
+			return Ok(None);
+		};
+		let track = track_gain.map(|g| transcode::Gain::with_target(g, track_peak, target));
+		let album = album_gain.map(|g| transcode::Gain::with_target(g, album_peak, target));
+		let gain = match normalization {
+			transcode::Normalization::Album => album.or(track),
+			_ => track.or(album),
+		};
+		Ok(gain.map(transcode::Volume::Gain))
+	}
+
+	pub fn get_lyrics(&self, real_path: &Path) -> Result<Lyrics, QueryError> {
+		let mut connection = self.db.connect()?;
+		let lyrics_path: Option<String> = songs::table
+			.filter(songs::path.eq(real_path.to_string_lossy()))
+			.select(songs::lyrics)
+			.get_result(&mut connection)
+			.optional()?
+			.flatten();
+		let lyrics_path = PathBuf::from(
+			lyrics_path.ok_or_else(|| QueryError::LyricsNotFound(real_path.to_owned()))?,
+		);
+
+		let text = if lyrics_path == real_path {
+			metadata::read_lyrics(&lyrics_path)
+				.map_err(|e| QueryError::LyricsReading(lyrics_path.clone(), e))?
+		} else {
+			// LRC files are not always encoded in UTF-8
+			let bytes =
+				fs::read(&lyrics_path).map_err(|e| QueryError::Io(lyrics_path.clone(), e))?;
+			Some(String::from_utf8_lossy(&bytes).into_owned())
+		};
+		text.map(|t| lyrics::parse(&t))
+			.ok_or_else(|| QueryError::LyricsNotFound(real_path.to_owned()))
+	}
+
+	/// Chapters embedded in a song. The last chapter ends with the song.
+	pub fn get_chapters(&self, real_path: &Path) -> Result<Vec<Chapter>, QueryError> {
+		let mut connection = self.db.connect()?;
+		let duration: Option<i32> = songs::table
+			.filter(songs::path.eq(real_path.to_string_lossy()))
+			.select(songs::duration)
+			.get_result(&mut connection)
+			.optional()?
+			.ok_or_else(|| QueryError::SongNotFound(real_path.to_owned()))?;
+		if !real_path.is_file() {
+			return Ok(Vec::new());
+		}
+		let mut chapters = metadata::read_chapters(real_path)
+			.map_err(|e| QueryError::ChaptersReading(real_path.to_owned(), e))?;
+		if let (Some(last), Some(duration)) = (chapters.last_mut(), duration) {
+			last.end.get_or_insert(duration as u32 * 1000);
+		}
+		Ok(chapters)
+	}
+
+	// Cue sheet tracks do not exist on disk, they are served from a time range of another file
+	pub fn get_cue_segment(
+		&self,
+		real_path: &Path,
+	) -> Result<Option<(PathBuf, transcode::Segment)>, QueryError> {
+		let mut connection = self.db.connect()?;
+		let cue: Option<(Option<String>, Option<i32>, Option<i32>)> = songs::table
+			.filter(songs::path.eq(real_path.to_string_lossy()))
+			.select((songs::cue_source, songs::cue_start, songs::cue_end))
+			.get_result(&mut connection)
+			.optional()?;
+		Ok(match cue {
+			Some((Some(source), Some(start), end)) => Some((
+				PathBuf::from(source),
+				transcode::Segment {
+					start: start as u32,
+					end: end.map(|e| e as u32),
+				},
+			)),
+			_ => None,
+		})
+	}
+}
+
+// Filtered on real paths so the database can apply limits without loading the whole collection
+fn albums_within<'a, I>(
+	mounts: I,
+) -> Option<directories::BoxedQuery<'static, diesel::sqlite::Sqlite>>
+where
+	I: Iterator<Item = &'a vfs::Mount>,
+{
+	use self::directories::dsl::*;
+	let mut path_filter: Option<
+		Box<dyn BoxableExpression<_, _, SqlType = diesel::sql_types::Bool>>,
+	> = None;
+	for mount in mounts {
+		let source = mount.source.to_string_lossy().into_owned();
+		let children = mount.source.join("%").to_string_lossy().into_owned();
+		let mount_filter = path.eq(source).or(path.like(children));
+		path_filter = Some(match path_filter {
+			Some(f) => Box::new(f.or(mount_filter)),
+			None => Box::new(mount_filter),
+		});
+	}
+	Some(
+		directories
+			.filter(album.is_not_null())
+			.filter(path_filter?)
+			.into_boxed(),
+	)
+}
+
+fn is_prefix_match(file: &CollectionFile, text_key: &str, fold: bool) -> bool {
+	let starts_with_text = |s: &str| utils::get_search_key(s, fold).starts_with(text_key);
+	match file {
+		CollectionFile::Directory(d) => Path::new(&d.path)
+			.file_name()
+			.is_some_and(|n| starts_with_text(&n.to_string_lossy())),
+		CollectionFile::Song(s) => [&s.title, &s.artist, &s.album_artist, &s.album]
+			.into_iter()
+			.flatten()
+			.any(|f| starts_with_text(f)),
+	}
+}
+
+#[derive(Queryable)]
+struct FuzzyCandidate {
+	path: String,
+	parent: String,
+	title: Option<String>,
+	artist: Option<String>,
+	album_artist: Option<String>,
+	album: Option<String>,
+}
+
+// Compares every directory and song to the query, closest matches first. Like exact searches,
+// content is left out when its parent directory already matches.
+fn fuzzy_search(
+	connection: &mut SqliteConnection,
+	text_key: &str,
+	fold: bool,
+) -> Result<Vec<CollectionFile>, QueryError> {
+	let matcher = FuzzyMatcher::new(text_key);
+	let distance = |text: &str| matcher.distance(&utils::get_search_key(text, fold));
+
+	let mut directory_distances = directories::table
+		.select((directories::path, directories::parent))
+		.load::<(String, Option<String>)>(connection)?
+		.into_iter()
+		.filter(|(_, parent)| parent.as_deref().and_then(distance).is_none())
+		.filter_map(|(path, _)| Some((path.clone(), distance(&path)?)))
+		.collect::<HashMap<_, _>>();
+
+	let mut song_distances = songs::table
+		.select((
+			songs::path,
+			songs::parent,
+			songs::title,
+			songs::artist,
+			songs::album_artist,
+			songs::album,
+		))
+		.load::<FuzzyCandidate>(connection)?
+		.into_iter()
+		.filter(|c| distance(&c.parent).is_none())
+		.filter_map(|c| {
+			let text = [
+				Some(c.path.as_str()),
+				c.title.as_deref(),
+				c.artist.as_deref(),
+				c.album_artist.as_deref(),
+				c.album.as_deref(),
+			]
+			.into_iter()
+			.flatten()
+			.collect::<Vec<_>>()
+			.join(" ");
+			Some((c.path, distance(&text)?))
+		})
+		.collect::<HashMap<_, _>>();
+
+	let mut matches = Vec::new();
+	let directory_paths = directory_distances.keys().cloned().collect::<Vec<_>>();
+	for chunk in directory_paths.chunks(FUZZY_SEARCH_CHUNK_SIZE) {
+		let found: Vec<Directory> = directories::table
+			.filter(directories::path.eq_any(chunk))
+			.load(connection)?;
+		matches.extend(found.into_iter().filter_map(|d| {
+			let distance = directory_distances.remove(&d.path)?;
+			Some((distance, CollectionFile::Directory(d)))
+		}));
+	}
+	let song_paths = song_distances.keys().cloned().collect::<Vec<_>>();
+	for chunk in song_paths.chunks(FUZZY_SEARCH_CHUNK_SIZE) {
+		let found: Vec<Song> = songs::table
+			.filter(songs::path.eq_any(chunk))
+			.load(connection)?;
+		matches.extend(found.into_iter().filter_map(|s| {
+			let distance = song_distances.remove(&s.path)?;
+			Some((distance, CollectionFile::Song(s)))
+		}));
+	}
+
+	matches.sort_by_key(|(distance, _)| *distance);
+	Ok(matches.into_iter().map(|(_, f)| f).collect())
 }
@@ -0,0 +1,235 @@
+/// A search query split into free text and `field:value` filters, such as
+/// `artist:radiohead year:1997..2001 format:flac`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+	pub text: String,
+	pub filters: Vec<Filter>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Filter {
+	Album(String),
+	Artist(String),
+	Composer(String),
+	Conductor(String),
+	Format(String),
+	Genre(String),
+	Title(String),
+	Year(Option<i32>, Option<i32>),
+}
+
+impl SearchQuery {
+	pub fn parse(query: &str) -> Self {
+		let mut words = Vec::new();
+		let mut filters = Vec::new();
+		for token in tokenize(query) {
+			match token.split_once(':').and_then(|(f, v)| parse_filter(f, v)) {
+				Some(filter) => filters.push(filter),
+				None => words.push(token),
+			}
+		}
+		Self {
+			text: words.join(" "),
+			filters,
+		}
+	}
+}
+
+/// Matches search keys against free text while tolerating typos, so that `zepelin` finds
+/// `Led Zeppelin`. Every word of the text must be close to a word of the candidate.
+pub struct FuzzyMatcher {
+	words: Vec<Vec<char>>,
+}
+
+impl FuzzyMatcher {
+	pub fn new(text_key: &str) -> Self {
+		Self {
+			words: split_words(text_key),
+		}
+	}
+
+	/// Number of typos needed for the candidate to match, `None` when it is too different.
+	pub fn distance(&self, candidate_key: &str) -> Option<usize> {
+		if self.words.is_empty() {
+			return None;
+		}
+		let candidate_words = split_words(candidate_key);
+		self.words.iter().try_fold(0, |total, word| {
+			let distance = candidate_words
+				.iter()
+				.map(|c| word_distance(word, c))
+				.min()?;
+			(distance <= max_typos(word.len())).then_some(total + distance)
+		})
+	}
+}
+
+fn split_words(key: &str) -> Vec<Vec<char>> {
+	key.split(|c: char| !c.is_alphanumeric())
+		.filter(|w| !w.is_empty())
+		.map(|w| w.chars().collect())
+		.collect()
+}
+
+fn max_typos(word_length: usize) -> usize {
+	match word_length {
+		0..=3 => 0,
+		4..=7 => 1,
+		_ => 2,
+	}
+}
+
+// Words may be typed partially, so they are also compared to the start of candidate words
+fn word_distance(word: &[char], candidate: &[char]) -> usize {
+	let prefix_lengths = word.len().saturating_sub(1)..=word.len() + 1;
+	prefix_lengths
+		.filter(|l| *l > 0 && *l < candidate.len())
+		.map(|l| levenshtein(word, &candidate[..l]))
+		.chain(std::iter::once(levenshtein(word, candidate)))
+		.min()
+		.unwrap_or(usize::MAX)
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+	let mut row = (0..=b.len()).collect::<Vec<_>>();
+	for (i, ca) in a.iter().enumerate() {
+		let mut diagonal = row[0];
+		row[0] = i + 1;
+		for (j, cb) in b.iter().enumerate() {
+			let above = row[j + 1];
+			row[j + 1] = if ca == cb {
+				diagonal
+			} else {
+				1 + diagonal.min(above).min(row[j])
+			};
+			diagonal = above;
+		}
+	}
+	row[b.len()]
+}
+
+// Splits on whitespace, except within double quotes
+fn tokenize(query: &str) -> Vec<String> {
+	let mut tokens = Vec::new();
+	let mut token = String::new();
+	let mut quoted = false;
+	for c in query.chars() {
+		match c {
+			'"' => quoted = !quoted,
+			c if c.is_whitespace() && !quoted => {
+				if !token.is_empty() {
+					tokens.push(std::mem::take(&mut token));
+				}
+			}
+			c => token.push(c),
+		}
+	}
+	if !token.is_empty() {
+		tokens.push(token);
+	}
+	tokens
+}
+
+fn parse_filter(field: &str, value: &str) -> Option<Filter> {
+	let value = value.trim();
+	if value.is_empty() {
+		return None;
+	}
+	let value_string = value.to_owned();
+	Some(match field.to_lowercase().as_str() {
+		"album" => Filter::Album(value_string),
+		"artist" => Filter::Artist(value_string),
+		"composer" => Filter::Composer(value_string),
+		"conductor" => Filter::Conductor(value_string),
+		"format" => Filter::Format(value.trim_start_matches('.').to_lowercase()),
+		"genre" => Filter::Genre(value_string),
+		"title" => Filter::Title(value_string),
+		"year" => match value.split_once("..") {
+			Some((from, to)) => {
+				let from = parse_year(from)?;
+				let to = parse_year(to)?;
+				if from.is_none() && to.is_none() {
+					return None;
+				}
+				Filter::Year(from, to)
+			}
+			None => {
+				let year = value.parse().ok()?;
+				Filter::Year(Some(year), Some(year))
+			}
+		},
+		_ => return None,
+	})
+}
+
+// Open ends of year ranges are empty strings
+fn parse_year(year: &str) -> Option<Option<i32>> {
+	match year {
+		"" => Some(None),
+		y => y.parse().ok().map(Some),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parses_filters_and_text() {
+		let query = SearchQuery::parse("artist:radiohead year:1997..2001 format:FLAC paranoid");
+		assert_eq!(query.text, "paranoid");
+		assert_eq!(
+			query.filters,
+			vec![
+				Filter::Artist("radiohead".to_owned()),
+				Filter::Year(Some(1997), Some(2001)),
+				Filter::Format("flac".to_owned()),
+			]
+		);
+	}
+
+	#[test]
+	fn parses_quoted_values() {
+		let query = SearchQuery::parse("album:\"ok computer\" \"let down\"");
+		assert_eq!(query.text, "let down");
+		assert_eq!(query.filters, vec![Filter::Album("ok computer".to_owned())]);
+	}
+
+	#[test]
+	fn parses_year_ranges() {
+		let year = |query: &str| SearchQuery::parse(query).filters;
+		assert_eq!(
+			year("year:1997"),
+			vec![Filter::Year(Some(1997), Some(1997))]
+		);
+		assert_eq!(year("year:..2001"), vec![Filter::Year(None, Some(2001))]);
+		assert_eq!(year("year:1997.."), vec![Filter::Year(Some(1997), None)]);
+		assert!(year("year:..").is_empty());
+		assert!(year("year:soon").is_empty());
+	}
+
+	#[test]
+	fn fuzzy_matches_tolerate_typos() {
+		let matcher = FuzzyMatcher::new("led zepelin");
+		assert_eq!(matcher.distance("led zeppelin"), Some(1));
+		assert_eq!(matcher.distance("/music/led zeppelin/iv"), Some(1));
+		assert_eq!(matcher.distance("led zeppelin ii"), Some(1));
+		assert_eq!(matcher.distance("red zeppelin"), None);
+		assert_eq!(matcher.distance("zeppelin"), None);
+	}
+
+	#[test]
+	fn fuzzy_matches_partial_words() {
+		let matcher = FuzzyMatcher::new("zepp");
+		assert_eq!(matcher.distance("led zeppelin"), Some(0));
+		assert_eq!(FuzzyMatcher::new("zepel").distance("led zeppelin"), Some(1));
+		assert_eq!(FuzzyMatcher::new("").distance("led zeppelin"), None);
+	}
+
+	#[test]
+	fn unknown_fields_are_text() {
+		let query = SearchQuery::parse("re:mix artist:");
+		assert_eq!(query.text, "re:mix artist:");
+		assert!(query.filters.is_empty());
+	}
+}
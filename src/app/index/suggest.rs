@@ -0,0 +1,209 @@
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+use super::*;
+use crate::db::songs;
+use crate::utils;
+
+const DEFAULT_SUGGESTION_COUNT: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SuggestionKind {
+	Artist,
+	Album,
+	Track,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Suggestion {
+	pub kind: SuggestionKind,
+	pub text: String,
+	pub artist: Option<String>, // For albums and tracks
+}
+
+struct Entry {
+	suggestion: Suggestion,
+	song_count: usize,
+	mounts: Vec<String>,
+}
+
+/// Artists, albums and tracks of the collection, sorted by search key so completions can be
+/// found without querying the database. Every word of a name can start a completion.
+pub struct SuggestionIndex {
+	fold: bool,
+	entries: Vec<Entry>,
+	keys: Vec<(String, usize)>,
+}
+
+#[derive(Queryable)]
+struct SongNames {
+	path: String,
+	title: Option<String>,
+	artist: Option<String>,
+	album_artist: Option<String>,
+	album: Option<String>,
+}
+
+impl SuggestionIndex {
+	fn build(
+		connection: &mut SqliteConnection,
+		vfs: &vfs::VFS,
+		fold: bool,
+	) -> Result<Self, QueryError> {
+		let songs: Vec<SongNames> = songs::table
+			.select((
+				songs::path,
+				songs::title,
+				songs::artist,
+				songs::album_artist,
+				songs::album,
+			))
+			.load(connection)?;
+
+		let mut entries: HashMap<Suggestion, Entry> = HashMap::new();
+		for song in songs {
+			let Some(mount) = get_mount_name(vfs, Path::new(&song.path)) else {
+				continue;
+			};
+			let album_artist = song.album_artist.or_else(|| song.artist.clone());
+			let artists = [song.artist.clone(), album_artist.clone()];
+			let mut suggestions = artists
+				.into_iter()
+				.flatten()
+				.map(|artist| Suggestion {
+					kind: SuggestionKind::Artist,
+					text: artist,
+					artist: None,
+				})
+				.chain(song.album.map(|album| Suggestion {
+					kind: SuggestionKind::Album,
+					text: album,
+					artist: album_artist,
+				}))
+				.chain(song.title.map(|title| Suggestion {
+					kind: SuggestionKind::Track,
+					text: title,
+					artist: song.artist,
+				}))
+				.collect::<Vec<_>>();
+			// Artist and album artist are often the same
+			suggestions.dedup();
+			for suggestion in suggestions {
+				let entry = entries.entry(suggestion.clone()).or_insert_with(|| Entry {
+					suggestion,
+					song_count: 0,
+					mounts: Vec::new(),
+				});
+				entry.song_count += 1;
+				if !entry.mounts.contains(&mount) {
+					entry.mounts.push(mount.clone());
+				}
+			}
+		}
+
+		let entries = entries.into_values().collect::<Vec<_>>();
+		let mut keys = Vec::new();
+		for (index, entry) in entries.iter().enumerate() {
+			let key = utils::get_search_key(&entry.suggestion.text, fold);
+			let word_starts = key
+				.char_indices()
+				.filter(|(i, _)| *i == 0 || key[..*i].ends_with(|c: char| !c.is_alphanumeric()))
+				.map(|(i, _)| i)
+				.collect::<Vec<_>>();
+			for start in word_starts {
+				keys.push((key[start..].to_owned(), index));
+			}
+		}
+		keys.sort();
+
+		Ok(Self {
+			fold,
+			entries,
+			keys,
+		})
+	}
+
+	// Most common names come first
+	fn suggest(&self, prefix: &str, mounts: &[String], count: usize) -> Vec<Suggestion> {
+		let prefix = utils::get_search_key(prefix, self.fold);
+		if prefix.is_empty() {
+			return Vec::new();
+		}
+		let start = self
+			.keys
+			.partition_point(|(key, _)| key.as_str() < prefix.as_str());
+		let mut matches = self.keys[start..]
+			.iter()
+			.take_while(|(key, _)| key.starts_with(&prefix))
+			.map(|(_, index)| *index)
+			.filter(|index| {
+				let entry = &self.entries[*index];
+				entry.mounts.iter().any(|m| mounts.contains(m))
+			})
+			.collect::<Vec<_>>();
+		matches.sort_unstable();
+		matches.dedup();
+		matches.sort_by(|a, b| {
+			let (a, b) = (&self.entries[*a], &self.entries[*b]);
+			b.song_count
+				.cmp(&a.song_count)
+				.then_with(|| a.suggestion.text.cmp(&b.suggestion.text))
+		});
+		matches
+			.into_iter()
+			.take(count)
+			.map(|index| self.entries[index].suggestion.clone())
+			.collect()
+	}
+}
+
+fn get_mount_name(vfs: &vfs::VFS, real_path: &Path) -> Option<String> {
+	let virtual_path = vfs.real_to_virtual(real_path).ok()?;
+	match virtual_path.components().next()? {
+		Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+		_ => None,
+	}
+}
+
+impl Index {
+	/// Completions for what users are typing in a search field. The underlying index is built
+	/// on first use and after each index update.
+	pub fn suggest(
+		&self,
+		username: &str,
+		prefix: &str,
+		count: Option<usize>,
+	) -> Result<Vec<Suggestion>, QueryError> {
+		let fold = self
+			.settings_manager
+			.get_search_accent_folding()
+			.unwrap_or(true);
+		let mounts = self
+			.vfs_manager
+			.get_vfs_for_user(username)?
+			.mounts()
+			.iter()
+			.map(|m| m.name.clone())
+			.collect::<Vec<_>>();
+		let count = count.unwrap_or(DEFAULT_SUGGESTION_COUNT);
+
+		{
+			let suggestions = self.suggestions.read().unwrap();
+			if let Some(suggestions) = suggestions.as_ref().filter(|s| s.fold == fold) {
+				return Ok(suggestions.suggest(prefix, &mounts, count));
+			}
+		}
+
+		let vfs = self.vfs_manager.get_vfs()?;
+		let mut connection = self.db.connect()?;
+		let index = SuggestionIndex::build(&mut connection, &vfs, fold)?;
+		let suggestions = index.suggest(prefix, &mounts, count);
+		*self.suggestions.write().unwrap() = Some(index);
+		Ok(suggestions)
+	}
+
+	pub(super) fn invalidate_suggestions(&self) {
+		*self.suggestions.write().unwrap() = None;
+	}
+}
@@ -3,11 +3,12 @@ use std::default::Default;
 use std::path::{Path, PathBuf};
 
 use super::*;
-use crate::app::test;
-use crate::db::{directories, songs};
+use crate::app::{events, test, transcode, vfs};
+use crate::db::{directories, metadata_lookups, songs};
 use crate::test_name;
 
 const TEST_MOUNT_NAME: &str = "root";
+const TEST_USER: &str = "test_user";
 
 #[test]
 fn update_adds_new_content() {
@@ -25,6 +26,114 @@ fn update_adds_new_content() {
 	assert_eq!(all_songs.len(), 13);
 }
 
+#[test]
+fn update_reports_status() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+
+	assert_eq!(ctx.index.get_status(), Status::default());
+
+	ctx.index.update().unwrap();
+
+	let status = ctx.index.get_status();
+	assert!(!status.is_running);
+	assert_eq!(status.directories_processed, 6);
+	assert_eq!(status.songs_processed, 13);
+	assert_eq!(status.errors, 0);
+	assert_eq!(status.eta_seconds, None);
+}
+
+#[test]
+fn update_publishes_events() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+
+	let mut receiver = ctx.events_manager.subscribe(TEST_USER);
+	ctx.index.update().unwrap();
+
+	assert_eq!(
+		receiver.try_next().unwrap(),
+		Some(events::Event::IndexUpdateStarted)
+	);
+	assert_eq!(
+		receiver.try_next().unwrap(),
+		Some(events::Event::IndexUpdateCompleted)
+	);
+}
+
+#[test]
+fn update_is_interrupted_by_shutdown() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+
+	assert!(ctx.index.shutdown(Duration::from_secs(5)));
+	ctx.index.update().unwrap();
+
+	let mut connection = ctx.db.connect().unwrap();
+	let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+	assert!(all_songs.is_empty());
+	assert!(!ctx.index.get_status().is_running);
+}
+
+#[test]
+fn interrupted_update_keeps_previous_index() {
+	let builder = test::ContextBuilder::new(test_name!());
+
+	let original_collection_dir: PathBuf = ["test-data", "small-collection"].iter().collect();
+	let test_collection_dir: PathBuf = builder.test_directory.join("small-collection");
+	let copy_options = fs_extra::dir::CopyOptions::new();
+	fs_extra::dir::copy(
+		original_collection_dir,
+		&builder.test_directory,
+		&copy_options,
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, test_collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	// Changed songs are read again, slowly enough for the update to be stopped along the way
+	set_modified_recursive(&test_collection_dir, std::time::UNIX_EPOCH);
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			max_files_per_second: Some(1),
+			..Default::default()
+		})
+		.unwrap();
+	let index = ctx.index.clone();
+	let update = std::thread::spawn(move || index.update());
+	while !ctx.index.get_status().is_running {
+		std::thread::sleep(Duration::from_millis(10));
+	}
+	std::thread::sleep(Duration::from_millis(1500));
+	assert!(ctx.index.shutdown(Duration::from_secs(5)));
+	update.join().unwrap().unwrap();
+	assert!(ctx.index.get_status().songs_processed < 13);
+
+	let mut connection = ctx.db.connect().unwrap();
+	let all_directories: Vec<Directory> = directories::table.load(&mut connection).unwrap();
+	let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+	assert_eq!(all_directories.len(), 6);
+	assert_eq!(all_songs.len(), 13);
+}
+
+fn set_modified_recursive(directory: &Path, time: std::time::SystemTime) {
+	for entry in std::fs::read_dir(directory).unwrap().flatten() {
+		let path = entry.path();
+		if path.is_dir() {
+			set_modified_recursive(&path, time);
+		} else {
+			let file = std::fs::File::options().write(true).open(&path).unwrap();
+			file.set_modified(time).unwrap();
+		}
+	}
+}
+
 #[test]
 fn update_removes_missing_content() {
 	let builder = test::ContextBuilder::new(test_name!());
@@ -66,6 +175,317 @@ fn update_removes_missing_content() {
 	}
 }
 
+#[test]
+fn update_skips_unavailable_mounts() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	let offline_dir = builder.test_directory.join("offline");
+	std::fs::create_dir(&collection_dir).unwrap();
+	std::fs::copy(
+		"test-data/formats/sample.flac",
+		collection_dir.join("sample.flac"),
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+	assert!(ctx.index.get_status().unavailable_mounts.is_empty());
+
+	let count_songs = || {
+		let mut connection = ctx.db.connect().unwrap();
+		songs::table
+			.count()
+			.get_result::<i64>(&mut connection)
+			.unwrap()
+	};
+	assert_eq!(count_songs(), 1);
+
+	// Offline network shares usually leave an empty directory, or none at all
+	std::fs::rename(&collection_dir, &offline_dir).unwrap();
+	ctx.index.update().unwrap();
+	assert_eq!(count_songs(), 1);
+	std::fs::create_dir(&collection_dir).unwrap();
+	ctx.index.update().unwrap();
+	assert_eq!(count_songs(), 1);
+	assert_eq!(
+		ctx.index.get_status().unavailable_mounts,
+		vec![TEST_MOUNT_NAME.to_owned()]
+	);
+	assert!(ctx.index.get_missing_songs().unwrap().is_empty());
+
+	std::fs::remove_dir(&collection_dir).unwrap();
+	std::fs::rename(&offline_dir, &collection_dir).unwrap();
+	ctx.index.update().unwrap();
+	assert_eq!(count_songs(), 1);
+	assert!(ctx.index.get_status().unavailable_mounts.is_empty());
+}
+
+#[test]
+fn update_only_reads_modified_files() {
+	let builder = test::ContextBuilder::new(test_name!());
+
+	let original_collection_dir: PathBuf = ["test-data", "small-collection"].iter().collect();
+	let test_collection_dir: PathBuf = builder.test_directory.join("small-collection");
+
+	let copy_options = fs_extra::dir::CopyOptions::new();
+	fs_extra::dir::copy(
+		original_collection_dir,
+		&builder.test_directory,
+		&copy_options,
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, test_collection_dir.to_str().unwrap())
+		.build();
+
+	ctx.index.update().unwrap();
+
+	let song_real_path = test_collection_dir
+		.join("Khemmis")
+		.join("Hunted")
+		.join("05 - Hunted.mp3");
+	let song_real_path_string = song_real_path.to_string_lossy().to_string();
+	let read_title = || -> Option<String> {
+		let mut connection = ctx.db.connect().unwrap();
+		songs::table
+			.filter(songs::path.eq(&song_real_path_string))
+			.select(songs::title)
+			.get_result(&mut connection)
+			.unwrap()
+	};
+
+	{
+		let mut connection = ctx.db.connect().unwrap();
+		diesel::update(songs::table.filter(songs::path.eq(&song_real_path_string)))
+			.set(songs::title.eq("Stale title"))
+			.execute(&mut connection)
+			.unwrap();
+	}
+
+	ctx.index.update().unwrap();
+	assert_eq!(read_title(), Some("Stale title".to_owned()));
+
+	let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+	std::fs::File::options()
+		.write(true)
+		.open(&song_real_path)
+		.unwrap()
+		.set_modified(modified)
+		.unwrap();
+
+	ctx.index.update().unwrap();
+	assert_eq!(read_title(), Some("Hunted".to_owned()));
+}
+
+#[test]
+fn watched_mounts_are_indexed_on_change() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let test_collection_dir: PathBuf = builder.test_directory.join("collection");
+	std::fs::create_dir_all(&test_collection_dir).unwrap();
+
+	let ctx = builder
+		.mount_dir(vfs::MountDir {
+			name: TEST_MOUNT_NAME.to_owned(),
+			source: test_collection_dir.to_string_lossy().into_owned(),
+			watch: true,
+			..Default::default()
+		})
+		.build();
+	ctx.index.begin_watching_mounts();
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	std::fs::copy(
+		"test-data/small-collection/Khemmis/Hunted/05 - Hunted.mp3",
+		test_collection_dir.join("05 - Hunted.mp3"),
+	)
+	.unwrap();
+
+	let start = std::time::Instant::now();
+	loop {
+		let mut connection = ctx.db.connect().unwrap();
+		let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+		if all_songs.len() == 1 {
+			break;
+		}
+		assert!(start.elapsed() < std::time::Duration::from_secs(30));
+		std::thread::sleep(std::time::Duration::from_millis(100));
+	}
+}
+
+#[test]
+fn update_pregenerates_thumbnails_of_new_albums() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let thumbnails_dir = builder.test_directory.join("cache");
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			pregenerate_thumbnails: Some(true),
+			..Default::default()
+		})
+		.unwrap();
+	ctx.index.update().unwrap();
+
+	// Small and large thumbnails of both albums with artwork
+	let start = std::time::Instant::now();
+	while std::fs::read_dir(&thumbnails_dir).map_or(0, |d| d.count()) < 4 {
+		assert!(start.elapsed() < std::time::Duration::from_secs(30));
+		std::thread::sleep(std::time::Duration::from_millis(100));
+	}
+}
+
+#[test]
+fn update_skips_ignored_content() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+
+	ctx.index.update().unwrap();
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			ignore_patterns: Some(vec!["^Khemmis$".to_owned(), r"\(Remix\)\.mp3$".to_owned()]),
+			..Default::default()
+		})
+		.unwrap();
+	ctx.index.update().unwrap();
+
+	let mut connection = ctx.db.connect().unwrap();
+	let all_directories: Vec<Directory> = directories::table.load(&mut connection).unwrap();
+	let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+	assert_eq!(all_directories.len(), 4);
+	assert_eq!(all_songs.len(), 7);
+}
+
+#[test]
+fn update_skips_content_listed_in_ignore_files() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let original_collection_dir: PathBuf = ["test-data", "small-collection"].iter().collect();
+	let test_collection_dir: PathBuf = builder.test_directory.join("small-collection");
+	let copy_options = fs_extra::dir::CopyOptions::new();
+	fs_extra::dir::copy(
+		original_collection_dir,
+		&builder.test_directory,
+		&copy_options,
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, test_collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let tobokegao_dir = test_collection_dir.join("Tobokegao");
+	std::fs::write(test_collection_dir.join(".polarisignore"), "Khemmis/\n").unwrap();
+	std::fs::write(
+		tobokegao_dir.join(".polarisignore"),
+		"# Remixes\n*(Remix).mp3\n",
+	)
+	.unwrap();
+	std::fs::write(
+		tobokegao_dir.join("Picnic").join(".polarisignore"),
+		"0[5-7] - *\n!05 - *\n",
+	)
+	.unwrap();
+	ctx.index.update().unwrap();
+
+	let mut connection = ctx.db.connect().unwrap();
+	let all_directories: Vec<Directory> = directories::table.load(&mut connection).unwrap();
+	let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+	assert_eq!(all_directories.len(), 4);
+	assert_eq!(all_songs.len(), 5);
+}
+
+// Khemmis is a real directory, `Hunted` links inside the collection, `Tobokegao` links outside
+// of it and `Khemmis/Hunted/Loop` links back to the collection root.
+#[cfg(unix)]
+fn make_symlinked_collection(test_directory: &Path) -> PathBuf {
+	use std::os::unix::fs::symlink;
+
+	let collection_dir = test_directory.join("collection");
+	std::fs::create_dir_all(&collection_dir).unwrap();
+	let copy_options = fs_extra::dir::CopyOptions::new();
+	fs_extra::dir::copy(
+		Path::new("test-data/small-collection/Khemmis"),
+		&collection_dir,
+		&copy_options,
+	)
+	.unwrap();
+
+	let outside_dir = Path::new("test-data/small-collection/Tobokegao")
+		.canonicalize()
+		.unwrap();
+	symlink(outside_dir, collection_dir.join("Tobokegao")).unwrap();
+	symlink(
+		Path::new("Khemmis").join("Hunted"),
+		collection_dir.join("Hunted"),
+	)
+	.unwrap();
+	symlink(
+		Path::new("..").join(".."),
+		collection_dir.join("Khemmis").join("Hunted").join("Loop"),
+	)
+	.unwrap();
+	collection_dir
+}
+
+#[cfg(unix)]
+#[test]
+fn update_skips_symlinks_by_default() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = make_symlinked_collection(&builder.test_directory);
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+
+	ctx.index.update().unwrap();
+
+	let mut connection = ctx.db.connect().unwrap();
+	let all_directories: Vec<Directory> = directories::table.load(&mut connection).unwrap();
+	let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+	assert_eq!(all_directories.len(), 3);
+	assert_eq!(all_songs.len(), 5);
+
+	let vfs = ctx.vfs_manager.get_vfs().unwrap();
+	assert!(vfs
+		.virtual_to_real(Path::new("root/Khemmis/Hunted"))
+		.is_ok());
+	assert!(vfs.virtual_to_real(Path::new("root/Hunted")).is_err());
+	assert!(vfs.virtual_to_real(Path::new("root/Tobokegao")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn update_follows_symlinks_inside_mount() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = make_symlinked_collection(&builder.test_directory);
+	let ctx = builder
+		.mount_dir(vfs::MountDir {
+			name: TEST_MOUNT_NAME.to_owned(),
+			source: collection_dir.to_string_lossy().into_owned(),
+			follow_symlinks: true,
+			..Default::default()
+		})
+		.build();
+
+	ctx.index.update().unwrap();
+
+	let mut connection = ctx.db.connect().unwrap();
+	let all_directories: Vec<Directory> = directories::table.load(&mut connection).unwrap();
+	let all_songs: Vec<Song> = songs::table.load(&mut connection).unwrap();
+	assert_eq!(all_directories.len(), 4);
+	assert_eq!(all_songs.len(), 10);
+
+	let vfs = ctx.vfs_manager.get_vfs().unwrap();
+	assert!(vfs.virtual_to_real(Path::new("root/Hunted")).is_ok());
+	assert!(vfs.virtual_to_real(Path::new("root/Tobokegao")).is_err());
+	assert!(vfs.virtual_to_real(Path::new("root/../..")).is_err());
+}
+
 #[test]
 fn can_browse_top_level() {
 	let ctx = test::ContextBuilder::new(test_name!())
@@ -74,7 +494,7 @@ fn can_browse_top_level() {
 	ctx.index.update().unwrap();
 
 	let root_path = Path::new(TEST_MOUNT_NAME);
-	let files = ctx.index.browse(Path::new("")).unwrap();
+	let files = ctx.index.browse(TEST_USER, Path::new("")).unwrap();
 	assert_eq!(files.len(), 1);
 	match files[0] {
 		CollectionFile::Directory(ref d) => assert_eq!(d.path, root_path.to_str().unwrap()),
@@ -82,6 +502,36 @@ fn can_browse_top_level() {
 	}
 }
 
+#[test]
+fn cached_results_follow_updates() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let test_collection_dir: PathBuf = builder.test_directory.join("collection");
+	std::fs::create_dir_all(&test_collection_dir).unwrap();
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, test_collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let browse = || {
+		ctx.index
+			.browse(TEST_USER, Path::new(TEST_MOUNT_NAME))
+			.unwrap()
+	};
+	let search = || ctx.index.search(TEST_USER, "hunted").unwrap();
+	assert!(browse().is_empty());
+	assert!(search().is_empty());
+
+	std::fs::copy(
+		"test-data/small-collection/Khemmis/Hunted/05 - Hunted.mp3",
+		test_collection_dir.join("05 - Hunted.mp3"),
+	)
+	.unwrap();
+	assert!(browse().is_empty());
+	ctx.index.update().unwrap();
+	assert_eq!(browse().len(), 1);
+	assert_eq!(search().len(), 1);
+}
+
 #[test]
 fn can_browse_directory() {
 	let khemmis_path: PathBuf = [TEST_MOUNT_NAME, "Khemmis"].iter().collect();
@@ -92,7 +542,10 @@ fn can_browse_directory() {
 		.build();
 	ctx.index.update().unwrap();
 
-	let files = ctx.index.browse(Path::new(TEST_MOUNT_NAME)).unwrap();
+	let files = ctx
+		.index
+		.browse(TEST_USER, Path::new(TEST_MOUNT_NAME))
+		.unwrap();
 
 	assert_eq!(files.len(), 2);
 	match files[0] {
@@ -112,11 +565,35 @@ fn can_flatten_root() {
 		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
 		.build();
 	ctx.index.update().unwrap();
-	let songs = ctx.index.flatten(Path::new(TEST_MOUNT_NAME)).unwrap();
+	let (songs, total) = ctx
+		.index
+		.flatten(TEST_USER, Path::new(TEST_MOUNT_NAME), Page::default())
+		.unwrap();
 	assert_eq!(songs.len(), 13);
+	assert_eq!(total, 13);
 	assert_eq!(songs[0].title, Some("Above The Water".to_owned()));
 }
 
+#[test]
+fn can_flatten_one_page() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+	ctx.index.update().unwrap();
+
+	let (all_songs, _) = ctx
+		.index
+		.flatten(TEST_USER, Path::new(""), Page::default())
+		.unwrap();
+	let page = Page {
+		offset: 10,
+		limit: Some(5),
+	};
+	let (songs, total) = ctx.index.flatten(TEST_USER, Path::new(""), page).unwrap();
+	assert_eq!(total, 13);
+	assert_eq!(songs, all_songs.into_iter().skip(10).collect::<Vec<_>>());
+}
+
 #[test]
 fn can_flatten_directory() {
 	let ctx = test::ContextBuilder::new(test_name!())
@@ -124,7 +601,11 @@ fn can_flatten_directory() {
 		.build();
 	ctx.index.update().unwrap();
 	let path: PathBuf = [TEST_MOUNT_NAME, "Tobokegao"].iter().collect();
-	let songs = ctx.index.flatten(path).unwrap();
+	let songs = ctx
+		.index
+		.flatten(TEST_USER, path, Page::default())
+		.unwrap()
+		.0;
 	assert_eq!(songs.len(), 8);
 }
 
@@ -135,17 +616,79 @@ fn can_flatten_directory_with_shared_prefix() {
 		.build();
 	ctx.index.update().unwrap();
 	let path: PathBuf = [TEST_MOUNT_NAME, "Tobokegao", "Picnic"].iter().collect(); // Prefix of '(Picnic Remixes)'
-	let songs = ctx.index.flatten(path).unwrap();
+	let songs = ctx
+		.index
+		.flatten(TEST_USER, path, Page::default())
+		.unwrap()
+		.0;
 	assert_eq!(songs.len(), 7);
 }
 
+#[test]
+fn queries_only_see_mounts_visible_to_user() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.user(TEST_USER, "password", false)
+		.mount("Khemmis", "test-data/small-collection/Khemmis")
+		.mount("Tobokegao", "test-data/small-collection/Tobokegao")
+		.build();
+	ctx.index.update().unwrap();
+	ctx.user_manager
+		.set_visible_mounts(TEST_USER, Some(&["Tobokegao".to_owned()]))
+		.unwrap();
+
+	let files = ctx.index.browse(TEST_USER, Path::new("")).unwrap();
+	assert_eq!(files.len(), 1);
+	assert!(matches!(&files[0], CollectionFile::Directory(d) if d.path == "Tobokegao"));
+	assert!(ctx.index.browse(TEST_USER, Path::new("Khemmis")).is_err());
+
+	assert_eq!(
+		ctx.index
+			.flatten(TEST_USER, Path::new(""), Page::default())
+			.unwrap()
+			.0
+			.len(),
+		8
+	);
+	assert!(ctx
+		.index
+		.flatten(TEST_USER, Path::new("Khemmis"), Page::default())
+		.is_err());
+
+	assert!(ctx.index.search(TEST_USER, "Khemmis").unwrap().is_empty());
+	assert!(!ctx.index.search(TEST_USER, "Picnic").unwrap().is_empty());
+	assert!(ctx
+		.index
+		.suggest(TEST_USER, "Khemmis", None)
+		.unwrap()
+		.is_empty());
+	assert!(!ctx
+		.index
+		.suggest(TEST_USER, "Picnic", None)
+		.unwrap()
+		.is_empty());
+
+	let random_albums = ctx.index.get_random_albums(TEST_USER, 1).unwrap();
+	assert_eq!(random_albums.len(), 1);
+	let recent_albums = ctx.index.get_recent_albums(TEST_USER, 100).unwrap();
+	assert!(!recent_albums.is_empty());
+	assert!(random_albums
+		.iter()
+		.chain(recent_albums.iter())
+		.all(|d| d.path.starts_with("Tobokegao")));
+
+	ctx.user_manager
+		.set_visible_mounts(TEST_USER, None)
+		.unwrap();
+	assert_eq!(ctx.index.browse(TEST_USER, Path::new("")).unwrap().len(), 2);
+}
+
 #[test]
 fn can_get_random_albums() {
 	let ctx = test::ContextBuilder::new(test_name!())
 		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
 		.build();
 	ctx.index.update().unwrap();
-	let albums = ctx.index.get_random_albums(1).unwrap();
+	let albums = ctx.index.get_random_albums(TEST_USER, 1).unwrap();
 	assert_eq!(albums.len(), 1);
 }
 
@@ -155,7 +698,7 @@ fn can_get_recent_albums() {
 		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
 		.build();
 	ctx.index.update().unwrap();
-	let albums = ctx.index.get_recent_albums(2).unwrap();
+	let albums = ctx.index.get_recent_albums(TEST_USER, 2).unwrap();
 	assert_eq!(albums.len(), 2);
 	assert!(albums[0].date_added >= albums[1].date_added);
 }
@@ -172,7 +715,7 @@ fn can_get_a_song() {
 	let song_virtual_path = picnic_virtual_dir.join("05 - シャーベット (Sherbet).mp3");
 	let artwork_virtual_path = picnic_virtual_dir.join("Folder.png");
 
-	let song = ctx.index.get_song(&song_virtual_path).unwrap();
+	let song = ctx.index.get_song(TEST_USER, &song_virtual_path).unwrap();
 	assert_eq!(song.path, song_virtual_path.to_string_lossy().as_ref());
 	assert_eq!(song.track_number, Some(5));
 	assert_eq!(song.disc_number, None);
@@ -198,13 +741,38 @@ fn indexes_embedded_artwork() {
 	let picnic_virtual_dir: PathBuf = [TEST_MOUNT_NAME, "Tobokegao", "Picnic"].iter().collect();
 	let song_virtual_path = picnic_virtual_dir.join("07 - なぜ (Why).mp3");
 
-	let song = ctx.index.get_song(&song_virtual_path).unwrap();
+	let song = ctx.index.get_song(TEST_USER, &song_virtual_path).unwrap();
 	assert_eq!(
 		song.artwork,
 		Some(song_virtual_path.to_string_lossy().into_owned())
 	);
 }
 
+#[test]
+fn album_art_priority_can_prefer_folder_images() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			album_art_priority: Some(settings::AlbumArtPriority::Folder),
+			..Default::default()
+		})
+		.unwrap();
+	ctx.index.update().unwrap();
+
+	let picnic_virtual_dir: PathBuf = [TEST_MOUNT_NAME, "Tobokegao", "Picnic"].iter().collect();
+	let song_virtual_path = picnic_virtual_dir.join("07 - なぜ (Why).mp3");
+	let artwork_virtual_path = picnic_virtual_dir.join("Folder.png");
+
+	let song = ctx.index.get_song(TEST_USER, &song_virtual_path).unwrap();
+	assert_eq!(
+		song.artwork,
+		Some(artwork_virtual_path.to_string_lossy().into_owned())
+	);
+}
+
 #[test]
 fn album_art_pattern_is_case_insensitive() {
 	let ctx = test::ContextBuilder::new(test_name!())
@@ -224,10 +792,1116 @@ fn album_art_pattern_is_case_insensitive() {
 
 		let hunted_virtual_dir: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
 		let artwork_virtual_path = hunted_virtual_dir.join("Folder.jpg");
-		let song = &ctx.index.flatten(&hunted_virtual_dir).unwrap()[0];
+		let song = &ctx
+			.index
+			.flatten(TEST_USER, &hunted_virtual_dir, Page::default())
+			.unwrap()
+			.0[0];
 		assert_eq!(
 			song.artwork,
 			Some(artwork_virtual_path.to_string_lossy().into_owned())
 		);
 	}
 }
+
+#[test]
+fn album_art_pattern_can_be_set_per_mount() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount_dir(vfs::MountDir {
+			name: "Khemmis".to_owned(),
+			source: "test-data/small-collection/Khemmis".to_owned(),
+			album_art_pattern: Some("cover".to_owned()),
+			..Default::default()
+		})
+		.mount("Tobokegao", "test-data/small-collection/Tobokegao")
+		.build();
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			album_art_priority: Some(settings::AlbumArtPriority::Folder),
+			..Default::default()
+		})
+		.unwrap();
+	ctx.index.update().unwrap();
+
+	let hunted_virtual_dir: PathBuf = ["Khemmis", "Hunted"].iter().collect();
+	let song = &ctx
+		.index
+		.flatten(TEST_USER, &hunted_virtual_dir, Page::default())
+		.unwrap()
+		.0[0];
+	assert_eq!(song.artwork, None);
+
+	let picnic_virtual_dir: PathBuf = ["Tobokegao", "Picnic"].iter().collect();
+	let song_virtual_path = picnic_virtual_dir.join("07 - なぜ (Why).mp3");
+	let song = ctx.index.get_song(TEST_USER, &song_virtual_path).unwrap();
+	assert_eq!(
+		song.artwork,
+		Some(
+			picnic_virtual_dir
+				.join("Folder.png")
+				.to_string_lossy()
+				.into_owned()
+		)
+	);
+}
+
+#[test]
+fn subdirectories_without_artwork_use_parent_artwork() {
+	let builder = test::ContextBuilder::new(test_name!());
+
+	let collection_dir = builder.test_directory.join("collection");
+	let album_dir = collection_dir.join("Hunted");
+	let disc_dir = album_dir.join("Disc 1");
+	std::fs::create_dir_all(&disc_dir).unwrap();
+	std::fs::copy(
+		"test-data/small-collection/Khemmis/Hunted/Folder.jpg",
+		album_dir.join("Folder.jpg"),
+	)
+	.unwrap();
+	std::fs::copy(
+		"test-data/small-collection/Khemmis/Hunted/05 - Hunted.mp3",
+		disc_dir.join("05 - Hunted.mp3"),
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let album_virtual_dir: PathBuf = [TEST_MOUNT_NAME, "Hunted"].iter().collect();
+	let song_virtual_path = album_virtual_dir.join("Disc 1").join("05 - Hunted.mp3");
+	let song = ctx.index.get_song(TEST_USER, &song_virtual_path).unwrap();
+	assert_eq!(
+		song.artwork,
+		Some(
+			album_virtual_dir
+				.join("Folder.jpg")
+				.to_string_lossy()
+				.into_owned()
+		)
+	);
+}
+
+#[test]
+fn art_overlay_takes_precedence() {
+	let builder = test::ContextBuilder::new(test_name!());
+
+	let art_overlay_dir = builder.test_directory.join("overlay");
+	let overlay_album_dir = art_overlay_dir.join("Tobokegao").join("Picnic");
+	std::fs::create_dir_all(&overlay_album_dir).unwrap();
+	std::fs::copy(
+		"test-data/small-collection/Khemmis/Hunted/Folder.jpg",
+		overlay_album_dir.join("Folder.jpg"),
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount_dir(vfs::MountDir {
+			name: TEST_MOUNT_NAME.to_owned(),
+			source: "test-data/small-collection".to_owned(),
+			art_overlay: Some(art_overlay_dir.to_string_lossy().into_owned()),
+			..Default::default()
+		})
+		.build();
+
+	ctx.index.update().unwrap();
+
+	let picnic_virtual_dir: PathBuf = [TEST_MOUNT_NAME, "Tobokegao", "Picnic"].iter().collect();
+	let song_virtual_path = picnic_virtual_dir.join("07 - なぜ (Why).mp3");
+	let artwork_virtual_path = picnic_virtual_dir.join("Folder.jpg");
+
+	let song = ctx.index.get_song(TEST_USER, &song_virtual_path).unwrap();
+	assert_eq!(
+		song.artwork,
+		Some(artwork_virtual_path.to_string_lossy().into_owned())
+	);
+
+	let vfs = ctx.vfs_manager.get_vfs().unwrap();
+	assert_eq!(
+		vfs.virtual_to_real(&artwork_virtual_path).unwrap(),
+		overlay_album_dir.join("Folder.jpg")
+	);
+
+	// Albums without overlay art keep their own
+	let hunted_virtual_dir: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let song = &ctx
+		.index
+		.flatten(TEST_USER, &hunted_virtual_dir, Page::default())
+		.unwrap()
+		.0[0];
+	assert_eq!(
+		song.artwork,
+		Some(
+			hunted_virtual_dir
+				.join("Folder.jpg")
+				.to_string_lossy()
+				.into_owned()
+		)
+	);
+}
+
+#[test]
+fn can_browse_tag_views() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+	ctx.index.update().unwrap();
+
+	let paths = |view_path: &str| -> Vec<String> {
+		ctx.index
+			.browse_view(TEST_USER, view_path)
+			.unwrap()
+			.into_iter()
+			.map(|f| match f {
+				CollectionFile::Directory(d) => d.path,
+				CollectionFile::Song(s) => s.path,
+			})
+			.collect()
+	};
+
+	assert_eq!(paths(""), vec!["Artists", "Composers", "Genres", "Years"]);
+	assert_eq!(
+		paths("Artists"),
+		vec!["Artists/Khemmis", "Artists/Tobokegao"]
+	);
+	assert_eq!(
+		paths("Artists/Tobokegao"),
+		vec![
+			"Artists/Tobokegao/Picnic",
+			"Artists/Tobokegao/Picnic (Remixes)"
+		]
+	);
+	assert_eq!(paths("Artists/Khemmis/Hunted").len(), 5);
+	assert_eq!(
+		paths("Genres/Doom Metal"),
+		vec!["Genres/Doom Metal/Khemmis"]
+	);
+	assert_eq!(paths("Genres/Doom Metal/Khemmis/Hunted").len(), 5);
+	assert_eq!(paths("Years"), vec!["Years/2016"]);
+	assert_eq!(paths("Years/2016").len(), 3);
+	assert!(paths("Years/1999").is_empty());
+
+	assert!(matches!(
+		ctx.index.browse_view(TEST_USER, "Labels"),
+		Err(QueryError::ViewNotFound(_))
+	));
+	assert!(matches!(
+		ctx.index
+			.browse_view(TEST_USER, "Years/2016/Hunted/Too deep"),
+		Err(QueryError::ViewNotFound(_))
+	));
+}
+
+#[test]
+fn cue_sheets_split_songs_into_tracks() {
+	let builder = test::ContextBuilder::new(test_name!());
+
+	let original_collection_dir: PathBuf = ["test-data", "cue-sheet"].iter().collect();
+	let test_collection_dir: PathBuf = builder.test_directory.join("cue-sheet");
+
+	let copy_options = fs_extra::dir::CopyOptions::new();
+	fs_extra::dir::copy(
+		original_collection_dir,
+		&builder.test_directory,
+		&copy_options,
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, test_collection_dir.to_str().unwrap())
+		.build();
+
+	ctx.index.update().unwrap();
+	ctx.index.update().unwrap();
+
+	let (songs, _) = ctx
+		.index
+		.flatten(TEST_USER, Path::new(TEST_MOUNT_NAME), Page::default())
+		.unwrap();
+	let paths: Vec<_> = songs.iter().map(|s| s.path.as_str()).collect();
+	let expected_paths: Vec<String> = (1..=3)
+		.map(|n| {
+			let sheet_path = Path::new(TEST_MOUNT_NAME).join("Symphony.cue");
+			format!("{}#0{}", sheet_path.to_string_lossy(), n)
+		})
+		.collect();
+	assert_eq!(paths, expected_paths);
+
+	let track = &songs[1];
+	assert_eq!(track.title, Some("Andante con moto".to_owned()));
+	assert_eq!(track.track_number, Some(2));
+	assert_eq!(track.album, Some("Symphony No. 5".to_owned()));
+	assert_eq!(track.artist, Some("Berliner Philharmoniker".to_owned()));
+	assert_eq!(track.genre, Some("Classical".to_owned()));
+	assert_eq!(track.year, Some(1963));
+	assert_eq!(track.duration, Some(588));
+
+	let track_real_path = test_collection_dir.join("Symphony.cue#02");
+	let (source, segment) = ctx
+		.index
+		.get_cue_segment(&track_real_path)
+		.unwrap()
+		.unwrap();
+	assert_eq!(source, test_collection_dir.join("Symphony.flac"));
+	assert_eq!(segment.start, 451_666);
+	assert_eq!(segment.end, Some(1_040_000));
+
+	std::fs::remove_file(test_collection_dir.join("Symphony.cue")).unwrap();
+	ctx.index.update().unwrap();
+	let (songs, _) = ctx
+		.index
+		.flatten(TEST_USER, Path::new(TEST_MOUNT_NAME), Page::default())
+		.unwrap();
+	assert_eq!(songs.len(), 1);
+	assert_eq!(
+		songs[0].path,
+		Path::new(TEST_MOUNT_NAME)
+			.join("Symphony.flac")
+			.to_string_lossy()
+	);
+}
+
+#[test]
+fn multi_disc_albums_are_ordered_by_disc() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let album_dir = builder.test_directory.join("album");
+	std::fs::create_dir(&album_dir).unwrap();
+	for (file_name, disc_number, track_number) in [
+		("01 - a.flac", "2/2", "1"),
+		("01 - b.flac", "1/2", "1"),
+		("02 - c.flac", "1/2", "2"),
+	] {
+		let path = album_dir.join(file_name);
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("DISCNUMBER", vec![disc_number]);
+		tag.remove_vorbis("DISCTOTAL");
+		tag.set_vorbis("TRACKNUMBER", vec![track_number]);
+		tag.save().unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, album_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let file_names = |songs: Vec<Song>| -> Vec<String> {
+		songs
+			.iter()
+			.map(|s| {
+				Path::new(&s.path)
+					.file_name()
+					.unwrap()
+					.to_string_lossy()
+					.into_owned()
+			})
+			.collect()
+	};
+	let expected = vec!["01 - b.flac", "02 - c.flac", "01 - a.flac"];
+
+	let files = ctx.index.browse(TEST_USER, TEST_MOUNT_NAME).unwrap();
+	let songs = files
+		.into_iter()
+		.filter_map(|f| match f {
+			CollectionFile::Song(s) => Some(s),
+			CollectionFile::Directory(_) => None,
+		})
+		.collect::<Vec<_>>();
+	assert!(songs.iter().all(|s| s.disc_total == Some(2)));
+	assert_eq!(file_names(songs), expected);
+
+	let (songs, _) = ctx
+		.index
+		.flatten(TEST_USER, TEST_MOUNT_NAME, Page::default())
+		.unwrap();
+	assert_eq!(file_names(songs), expected);
+}
+
+#[test]
+fn compilations_are_grouped_under_various_artists() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let album_dir = builder.test_directory.join("album");
+	std::fs::create_dir(&album_dir).unwrap();
+	for artist in ["Aphex Twin", "Boards of Canada", "Autechre"] {
+		let path = album_dir.join(format!("{artist}.flac"));
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("ARTIST", vec![artist]);
+		tag.set_vorbis("ALBUM", vec!["Warp 20"]);
+		tag.remove_vorbis("ALBUMARTIST");
+		tag.save().unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, album_dir.to_str().unwrap())
+		.build();
+	let paths = |view_path: &str| -> Vec<String> {
+		ctx.index
+			.browse_view(TEST_USER, view_path)
+			.unwrap()
+			.into_iter()
+			.map(|f| match f {
+				CollectionFile::Directory(d) => d.path,
+				CollectionFile::Song(s) => s.path,
+			})
+			.collect()
+	};
+	let compilation_flags = || -> Vec<bool> {
+		let (songs, _) = ctx
+			.index
+			.flatten(TEST_USER, TEST_MOUNT_NAME, Page::default())
+			.unwrap();
+		songs.iter().map(|s| s.compilation).collect()
+	};
+
+	ctx.index.update().unwrap();
+	assert_eq!(paths("Artists"), vec!["Artists/Various Artists"]);
+	assert_eq!(
+		paths("Artists/Various Artists"),
+		vec!["Artists/Various Artists/Warp 20"]
+	);
+	assert_eq!(paths("Artists/Various Artists/Warp 20").len(), 3);
+	assert_eq!(compilation_flags(), vec![true, true, true]);
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			group_compilations: Some(false),
+			..Default::default()
+		})
+		.unwrap();
+	ctx.index.update().unwrap();
+	assert_eq!(
+		paths("Artists"),
+		vec![
+			"Artists/Aphex Twin",
+			"Artists/Autechre",
+			"Artists/Boards of Canada"
+		]
+	);
+	assert_eq!(compilation_flags(), vec![false, false, false]);
+}
+
+#[test]
+fn can_browse_composers_and_search_conductors() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let album_dir = builder.test_directory.join("album");
+	std::fs::create_dir(&album_dir).unwrap();
+	for (file_name, composer) in [("1.flac", "Beethoven"), ("2.flac", "Brahms")] {
+		let path = album_dir.join(file_name);
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("COMPOSER", vec![composer]);
+		tag.set_vorbis("CONDUCTOR", vec!["Herbert von Karajan"]);
+		tag.save().unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, album_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let paths = |view_path: &str| -> Vec<String> {
+		ctx.index
+			.browse_view(TEST_USER, view_path)
+			.unwrap()
+			.into_iter()
+			.map(|f| match f {
+				CollectionFile::Directory(d) => d.path,
+				CollectionFile::Song(s) => s.path,
+			})
+			.collect()
+	};
+	assert_eq!(
+		paths("Composers"),
+		vec!["Composers/Beethoven", "Composers/Brahms"]
+	);
+	assert_eq!(
+		paths("Composers/Brahms"),
+		vec!["Composers/Brahms/TEST ALBUM"]
+	);
+	assert_eq!(paths("Composers/Brahms/TEST ALBUM").len(), 1);
+
+	let results = ctx.index.search(TEST_USER, "Karajan").unwrap();
+	assert_eq!(results.len(), 2);
+	assert!(results.iter().all(|f| match f {
+		CollectionFile::Song(s) => s.conductor.as_deref() == Some("Herbert von Karajan"),
+		CollectionFile::Directory(_) => false,
+	}));
+}
+
+#[test]
+fn views_are_ordered_by_sort_names() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	for (artist, artist_sort, album) in [
+		("The Beatles", Some("Beatles, The"), "Abbey Road"),
+		("Blur", None, "Parklife"),
+		("Aphex Twin", None, "Drukqs"),
+	] {
+		let path = collection_dir.join(format!("{artist}.flac"));
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("ARTIST", vec![artist]);
+		tag.set_vorbis("ALBUM", vec![album]);
+		tag.remove_vorbis("ALBUMARTIST");
+		if let Some(artist_sort) = artist_sort {
+			tag.set_vorbis("ARTISTSORT", vec![artist_sort]);
+		}
+		tag.save().unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let paths: Vec<String> = ctx
+		.index
+		.browse_view(TEST_USER, "Artists")
+		.unwrap()
+		.into_iter()
+		.map(|f| match f {
+			CollectionFile::Directory(d) => d.path,
+			CollectionFile::Song(s) => s.path,
+		})
+		.collect();
+	assert_eq!(
+		paths,
+		vec!["Artists/Aphex Twin", "Artists/The Beatles", "Artists/Blur"]
+	);
+}
+
+#[test]
+fn browse_follows_collation_locale() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	for name in ["Zorn", "Öresund", "Oslo"] {
+		let directory = collection_dir.join(name);
+		std::fs::create_dir_all(&directory).unwrap();
+		std::fs::copy(
+			"test-data/formats/sample.flac",
+			directory.join("sample.flac"),
+		)
+		.unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let file_names = || -> Vec<String> {
+		ctx.index
+			.browse(TEST_USER, TEST_MOUNT_NAME)
+			.unwrap()
+			.into_iter()
+			.map(|f| match f {
+				CollectionFile::Directory(d) => d.path,
+				CollectionFile::Song(s) => s.path,
+			})
+			.map(|p| {
+				Path::new(&p)
+					.file_name()
+					.unwrap()
+					.to_string_lossy()
+					.into_owned()
+			})
+			.collect()
+	};
+	assert_eq!(file_names(), vec!["Öresund", "Oslo", "Zorn"]);
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			collation_locale: Some("sv".to_owned()),
+			..Default::default()
+		})
+		.unwrap();
+	assert_eq!(file_names(), vec!["Oslo", "Zorn", "Öresund"]);
+}
+
+#[test]
+fn search_and_views_ignore_unicode_forms() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	for (artist, album) in [("Bj\u{f6}rk", "Homogenic"), ("Bjo\u{308}rk", "Vespertine")] {
+		let path = collection_dir.join(format!("{album}.flac"));
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("ARTIST", vec![artist]);
+		tag.set_vorbis("ALBUM", vec![album]);
+		tag.remove_vorbis("ALBUMARTIST");
+		tag.save().unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let artists: Vec<String> = ctx
+		.index
+		.browse_view(TEST_USER, "Artists")
+		.unwrap()
+		.into_iter()
+		.map(|f| match f {
+			CollectionFile::Directory(d) => d.path,
+			CollectionFile::Song(s) => s.path,
+		})
+		.collect();
+	assert_eq!(artists, vec!["Artists/Bj\u{f6}rk"]);
+
+	assert_eq!(ctx.index.search(TEST_USER, "bjork").unwrap().len(), 2);
+	assert_eq!(ctx.index.search(TEST_USER, "BJ\u{d6}RK").unwrap().len(), 2);
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			search_accent_folding: Some(false),
+			..Default::default()
+		})
+		.unwrap();
+	assert!(ctx.index.search(TEST_USER, "bjork").unwrap().is_empty());
+	assert_eq!(ctx.index.search(TEST_USER, "BJ\u{d6}RK").unwrap().len(), 2);
+}
+
+#[test]
+fn search_ranks_prefix_matches_and_tolerates_typos() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	for (file_name, artist, title) in [
+		("1.flac", "Led Zeppelin", "Black Dog"),
+		("2.flac", "Florence and the Machine", "Dog Days Are Over"),
+	] {
+		let path = collection_dir.join(file_name);
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("ARTIST", vec![artist]);
+		tag.set_vorbis("TITLE", vec![title]);
+		tag.save().unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let titles = |query: &str| -> Vec<String> {
+		ctx.index
+			.search(TEST_USER, query)
+			.unwrap()
+			.into_iter()
+			.filter_map(|f| match f {
+				CollectionFile::Song(s) => s.title,
+				CollectionFile::Directory(_) => None,
+			})
+			.collect()
+	};
+
+	assert_eq!(titles("dog"), vec!["Dog Days Are Over", "Black Dog"]);
+	assert!(titles("led zepelin").is_empty());
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			search_fuzzy: Some(true),
+			..Default::default()
+		})
+		.unwrap();
+	assert_eq!(titles("led zepelin"), vec!["Black Dog"]);
+	assert_eq!(titles("dog"), vec!["Dog Days Are Over", "Black Dog"]);
+}
+
+#[test]
+fn suggestions_complete_any_word() {
+	let ctx = test::ContextBuilder::new(test_name!())
+		.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+		.build();
+	assert!(ctx
+		.index
+		.suggest(TEST_USER, "wat", None)
+		.unwrap()
+		.is_empty());
+
+	ctx.index.update().unwrap();
+
+	let suggestions = ctx.index.suggest(TEST_USER, "wat", None).unwrap();
+	assert_eq!(
+		suggestions,
+		vec![Suggestion {
+			kind: SuggestionKind::Track,
+			text: "Above The Water".to_owned(),
+			artist: Some("Khemmis".to_owned()),
+		}]
+	);
+
+	// The most common names come first
+	let suggestions = ctx.index.suggest(TEST_USER, "", None).unwrap();
+	assert!(suggestions.is_empty());
+	let suggestions = ctx.index.suggest(TEST_USER, "picnic", Some(1)).unwrap();
+	assert_eq!(suggestions[0].kind, SuggestionKind::Album);
+	assert_eq!(suggestions[0].text, "Picnic");
+}
+
+#[test]
+fn search_applies_field_filters() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	for (file_name, artist, title, year) in [
+		("1.flac", "Radiohead", "Paranoid Android", "1997"),
+		("2.flac", "Radiohead", "There There", "2003"),
+		("3.flac", "Portishead", "Roads", "1994"),
+	] {
+		let path = collection_dir.join(file_name);
+		std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("ARTIST", vec![artist]);
+		tag.set_vorbis("TITLE", vec![title]);
+		tag.set_vorbis("DATE", vec![year]);
+		tag.save().unwrap();
+	}
+	std::fs::copy("test-data/formats/sample.mp3", collection_dir.join("4.mp3")).unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let titles = |query: &str| -> Vec<String> {
+		let mut titles = ctx
+			.index
+			.search(TEST_USER, query)
+			.unwrap()
+			.into_iter()
+			.filter_map(|f| match f {
+				CollectionFile::Song(s) => s.title,
+				CollectionFile::Directory(_) => None,
+			})
+			.collect::<Vec<_>>();
+		titles.sort();
+		titles
+	};
+
+	assert_eq!(
+		titles("artist:radiohead"),
+		vec!["Paranoid Android", "There There"]
+	);
+	assert_eq!(
+		titles("artist:radiohead year:1990..2000"),
+		vec!["Paranoid Android"]
+	);
+	assert_eq!(titles("year:..1995"), vec!["Roads"]);
+	assert_eq!(titles("artist:radiohead there"), vec!["There There"]);
+	assert_eq!(titles("title:\"there there\""), vec!["There There"]);
+	assert_eq!(titles("format:flac").len(), 3);
+	assert_eq!(ctx.index.search(TEST_USER, "format:mp3").unwrap().len(), 1);
+}
+
+#[test]
+fn replay_gain_is_indexed() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	let path = collection_dir.join("song.flac");
+	std::fs::copy("test-data/formats/sample.flac", &path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+	tag.set_vorbis("REPLAYGAIN_TRACK_GAIN", vec!["-6.20 dB"]);
+	tag.set_vorbis("REPLAYGAIN_TRACK_PEAK", vec!["0.988"]);
+	tag.set_vorbis("REPLAYGAIN_ALBUM_GAIN", vec!["+2.00 dB"]);
+	tag.save().unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let song = ctx
+		.index
+		.get_song(TEST_USER, &Path::new(TEST_MOUNT_NAME).join("song.flac"))
+		.unwrap();
+	assert_eq!(song.track_gain, Some(-6.2));
+	assert_eq!(song.track_peak, Some(0.988));
+	assert_eq!(song.album_gain, Some(2.0));
+	assert_eq!(song.album_peak, None);
+
+	let volume = |normalization| ctx.index.get_volume(&path, normalization).unwrap();
+	assert_eq!(
+		volume(transcode::Normalization::Track),
+		Some(transcode::Volume::Gain(transcode::Gain::new(
+			-6.2,
+			Some(0.988)
+		)))
+	);
+	assert_eq!(
+		volume(transcode::Normalization::Album),
+		Some(transcode::Volume::Gain(transcode::Gain::new(2.0, None)))
+	);
+
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			loudness_target: Some(-23),
+			..Default::default()
+		})
+		.unwrap();
+	assert_eq!(
+		volume(transcode::Normalization::Album),
+		Some(transcode::Volume::Gain(transcode::Gain::new(-3.0, None)))
+	);
+	assert_eq!(
+		volume(transcode::Normalization::Loudness),
+		Some(transcode::Volume::Loudness(-23))
+	);
+}
+
+#[test]
+fn lyrics_are_read_from_tags_and_sidecar_files() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+
+	let embedded_path = collection_dir.join("embedded.flac");
+	std::fs::copy("test-data/formats/sample.flac", &embedded_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&embedded_path).unwrap();
+	tag.set_vorbis("LYRICS", vec!["First line\nSecond line"]);
+	tag.save().unwrap();
+
+	let sidecar_path = collection_dir.join("sidecar.flac");
+	std::fs::copy("test-data/formats/sample.flac", &sidecar_path).unwrap();
+	std::fs::write(
+		collection_dir.join("sidecar.lrc"),
+		"[00:01.00]First line\n[00:02.50]Second line",
+	)
+	.unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let song = ctx
+		.index
+		.get_song(TEST_USER, &Path::new(TEST_MOUNT_NAME).join("sidecar.flac"))
+		.unwrap();
+	assert_eq!(
+		song.lyrics,
+		Some(
+			Path::new(TEST_MOUNT_NAME)
+				.join("sidecar.lrc")
+				.to_string_lossy()
+				.into_owned()
+		)
+	);
+
+	let lyrics = ctx.index.get_lyrics(&embedded_path).unwrap();
+	assert!(!lyrics.synced);
+	assert_eq!(lyrics.lines.len(), 2);
+	assert_eq!(lyrics.lines[1].text, "Second line");
+
+	let lyrics = ctx.index.get_lyrics(&sidecar_path).unwrap();
+	assert!(lyrics.synced);
+	assert_eq!(lyrics.lines[1].start, Some(2500));
+	assert_eq!(lyrics.lines[1].text, "Second line");
+}
+
+#[test]
+fn metadata_lookups_fill_in_missing_tags() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+
+	let song_path = collection_dir.join("untagged.flac");
+	std::fs::copy("test-data/formats/sample.flac", &song_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&song_path).unwrap();
+	tag.remove_vorbis("ARTIST");
+	tag.remove_vorbis("ALBUM");
+	tag.save().unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			metadata_lookup: Some(true),
+			..Default::default()
+		})
+		.unwrap();
+
+	// Stands in for a previous lookup, so this test does not reach MusicBrainz
+	{
+		let mut connection = ctx.db.connect().unwrap();
+		diesel::insert_into(metadata_lookups::table)
+			.values((
+				metadata_lookups::path.eq(song_path.to_string_lossy()),
+				metadata_lookups::title.eq("Looked up title"),
+				metadata_lookups::artist.eq("Looked up artist"),
+				metadata_lookups::album.eq("Looked up album"),
+			))
+			.execute(&mut connection)
+			.unwrap();
+	}
+
+	ctx.index.update().unwrap();
+
+	let song = ctx
+		.index
+		.get_song(TEST_USER, &Path::new(TEST_MOUNT_NAME).join("untagged.flac"))
+		.unwrap();
+	assert_eq!(song.artist.as_deref(), Some("Looked up artist"));
+	assert_eq!(song.album.as_deref(), Some("Looked up album"));
+	assert_eq!(song.title.as_deref(), Some("TEST TITLE"));
+}
+
+#[test]
+fn edits_are_written_to_files_and_index() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	let file_names = [
+		"sample.aif",
+		"sample.ape",
+		"sample.flac",
+		"sample.m4a",
+		"sample.mp3",
+		"sample.wav",
+	];
+	for file_name in file_names {
+		std::fs::copy(
+			Path::new("test-data/formats").join(file_name),
+			collection_dir.join(file_name),
+		)
+		.unwrap();
+	}
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let edit = SongEdit {
+		title: Some("Edited title".to_owned()),
+		album: Some("Edited album".to_owned()),
+		track_number: Some(7),
+		year: Some(2024),
+		..Default::default()
+	};
+	for file_name in file_names {
+		let real_path = collection_dir.join(file_name);
+		ctx.index.edit_song(&real_path, &edit).unwrap();
+
+		let tags = metadata::try_read(&real_path).unwrap().unwrap();
+		assert_eq!(tags.title.as_deref(), Some("Edited title"));
+		assert_eq!(tags.album.as_deref(), Some("Edited album"));
+		assert_eq!(tags.track_number, Some(7));
+		assert_eq!(tags.year, Some(2024));
+		assert_eq!(tags.artist.as_deref(), Some("TEST ARTIST"));
+
+		let song = ctx
+			.index
+			.get_song(TEST_USER, &Path::new(TEST_MOUNT_NAME).join(file_name))
+			.unwrap();
+		assert_eq!(song.title.as_deref(), Some("Edited title"));
+		assert_eq!(song.album.as_deref(), Some("Edited album"));
+		assert_eq!(song.track_number, Some(7));
+		assert_eq!(song.year, Some(2024));
+	}
+
+	let leftovers = std::fs::read_dir(&collection_dir).unwrap().count();
+	assert_eq!(leftovers, file_names.len());
+}
+
+#[test]
+fn edited_artwork_is_embedded() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	let real_path = collection_dir.join("sample.flac");
+	std::fs::copy("test-data/formats/sample.flac", &real_path).unwrap();
+
+	let ctx = builder
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let virtual_path = Path::new(TEST_MOUNT_NAME).join("sample.flac");
+	assert_eq!(
+		ctx.index
+			.get_song(TEST_USER, &virtual_path)
+			.unwrap()
+			.artwork,
+		None
+	);
+
+	let not_an_image = SongEdit {
+		artwork: Some(b"not an image".to_vec()),
+		..Default::default()
+	};
+	assert!(matches!(
+		ctx.index.edit_song(&real_path, &not_an_image),
+		Err(EditError::ArtworkFormatUnsupported)
+	));
+
+	let edit = SongEdit {
+		artwork: Some(std::fs::read("test-data/artwork/Folder.png").unwrap()),
+		..Default::default()
+	};
+	ctx.index.edit_song(&real_path, &edit).unwrap();
+	assert!(metadata::try_read(&real_path).unwrap().unwrap().has_artwork);
+	assert_eq!(
+		ctx.index
+			.get_song(TEST_USER, &virtual_path)
+			.unwrap()
+			.artwork,
+		Some(virtual_path.to_string_lossy().into_owned())
+	);
+}
+
+#[test]
+fn missing_songs_keep_their_data_for_a_grace_period() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	let real_path = collection_dir.join("sample.flac");
+	std::fs::copy("test-data/formats/sample.flac", &real_path).unwrap();
+	// Keeps the mount from looking unavailable once the song is gone
+	std::fs::write(collection_dir.join("notes.txt"), "").unwrap();
+
+	let ctx = builder
+		.user(TEST_USER, "password", false)
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let virtual_path = Path::new(TEST_MOUNT_NAME).join("sample.flac");
+	ctx.rating_manager
+		.set_rating(TEST_USER, &virtual_path, 4)
+		.unwrap();
+	ctx.history_manager
+		.record_listen(TEST_USER, &virtual_path, None)
+		.unwrap();
+
+	// Files which come back within the grace period keep their data
+	std::fs::rename(&real_path, collection_dir.join("sample.bak")).unwrap();
+	ctx.index.update().unwrap();
+	let missing_songs = ctx.index.get_missing_songs().unwrap();
+	assert_eq!(missing_songs.len(), 1);
+	assert_eq!(missing_songs[0].path, virtual_path);
+	assert_eq!(missing_songs[0].title.as_deref(), Some("TEST TITLE"));
+	assert_eq!(missing_songs[0].play_count, 1);
+	assert_eq!(missing_songs[0].rating, Some(4.0));
+
+	std::fs::rename(collection_dir.join("sample.bak"), &real_path).unwrap();
+	ctx.index.update().unwrap();
+	assert!(ctx.index.get_missing_songs().unwrap().is_empty());
+	assert_eq!(ctx.rating_manager.get_ratings(TEST_USER).unwrap().len(), 1);
+
+	// Files missing for longer are forgotten
+	ctx.settings_manager
+		.amend(&settings::NewSettings {
+			missing_grace_period_days: Some(0),
+			..Default::default()
+		})
+		.unwrap();
+	std::fs::remove_file(&real_path).unwrap();
+	ctx.index.update().unwrap();
+	std::thread::sleep(std::time::Duration::from_secs(1));
+	ctx.index.update().unwrap();
+	assert!(ctx.index.get_missing_songs().unwrap().is_empty());
+	assert!(ctx
+		.rating_manager
+		.get_ratings(TEST_USER)
+		.unwrap()
+		.is_empty());
+}
+
+#[test]
+fn moved_songs_keep_their_data() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir_all(collection_dir.join("old")).unwrap();
+	std::fs::create_dir_all(collection_dir.join("new")).unwrap();
+	let old_real_path = collection_dir.join("old").join("sample.flac");
+	std::fs::copy("test-data/formats/sample.flac", &old_real_path).unwrap();
+
+	let ctx = builder
+		.user(TEST_USER, "password", false)
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let old_path = Path::new(TEST_MOUNT_NAME).join("old").join("sample.flac");
+	let new_path = Path::new(TEST_MOUNT_NAME).join("new").join("renamed.flac");
+	let content_hash = ctx
+		.index
+		.get_song(TEST_USER, &old_path)
+		.unwrap()
+		.content_hash;
+	assert!(content_hash.is_some());
+	ctx.rating_manager
+		.set_rating(TEST_USER, &old_path, 4)
+		.unwrap();
+	ctx.playlist_manager
+		.save_playlist(
+			"playlist",
+			TEST_USER,
+			&[old_path.to_string_lossy().into_owned()],
+		)
+		.unwrap();
+
+	// Editing tags while moving the file does not change its audio
+	let new_real_path = collection_dir.join("new").join("renamed.flac");
+	std::fs::rename(&old_real_path, &new_real_path).unwrap();
+	let mut tag = metaflac::Tag::read_from_path(&new_real_path).unwrap();
+	tag.set_vorbis("TITLE", vec!["Renamed"]);
+	tag.write_to_path(&new_real_path).unwrap();
+	ctx.index.update().unwrap();
+
+	let song = ctx.index.get_song(TEST_USER, &new_path).unwrap();
+	assert_eq!(song.title.as_deref(), Some("Renamed"));
+	assert_eq!(song.content_hash, content_hash);
+	assert!(ctx.index.get_missing_songs().unwrap().is_empty());
+	let ratings = ctx.rating_manager.get_ratings(TEST_USER).unwrap();
+	assert_eq!(ratings.get(new_path.to_str().unwrap()), Some(&4));
+	let playlist = ctx
+		.playlist_manager
+		.read_playlist("playlist", TEST_USER)
+		.unwrap();
+	assert_eq!(playlist.len(), 1);
+	assert_eq!(Path::new(&playlist[0].path), new_path);
+}
+
+#[test]
+fn missing_songs_can_be_purged() {
+	let builder = test::ContextBuilder::new(test_name!());
+	let collection_dir = builder.test_directory.join("collection");
+	std::fs::create_dir(&collection_dir).unwrap();
+	let real_path = collection_dir.join("sample.flac");
+	std::fs::copy("test-data/formats/sample.flac", &real_path).unwrap();
+	// Keeps the mount from looking unavailable once the song is gone
+	std::fs::write(collection_dir.join("notes.txt"), "").unwrap();
+
+	let ctx = builder
+		.user(TEST_USER, "password", false)
+		.mount(TEST_MOUNT_NAME, collection_dir.to_str().unwrap())
+		.build();
+	ctx.index.update().unwrap();
+
+	let virtual_path = Path::new(TEST_MOUNT_NAME).join("sample.flac");
+	ctx.rating_manager
+		.set_rating(TEST_USER, &virtual_path, 4)
+		.unwrap();
+
+	std::fs::remove_file(&real_path).unwrap();
+	ctx.index.update().unwrap();
+	assert_eq!(ctx.index.get_missing_songs().unwrap().len(), 1);
+
+	assert_eq!(ctx.index.purge_missing_songs().unwrap(), 1);
+	assert!(ctx.index.get_missing_songs().unwrap().is_empty());
+	assert!(ctx
+		.rating_manager
+		.get_ratings(TEST_USER)
+		.unwrap()
+		.is_empty());
+}
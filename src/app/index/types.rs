@@ -4,13 +4,17 @@ use std::path::Path;
 use crate::app::vfs::VFS;
 use crate::db::songs;
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+// Album artist of compilations whose songs do not have one
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum CollectionFile {
 	Directory(Directory),
 	Song(Song),
 }
 
-#[derive(Debug, PartialEq, Eq, Queryable, QueryableByName, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Queryable, QueryableByName, Serialize, Deserialize)]
 #[diesel(table_name = songs)]
 pub struct Song {
 	#[serde(skip_serializing, skip_deserializing)]
@@ -31,6 +35,50 @@ pub struct Song {
 	pub composer: Option<String>,
 	pub genre: Option<String>,
 	pub label: Option<String>,
+	#[serde(skip_serializing, skip_deserializing)]
+	pub file_modified: Option<i64>,
+	#[serde(skip_serializing, skip_deserializing)]
+	pub file_size: Option<i64>,
+	#[serde(skip_serializing, skip_deserializing)]
+	pub has_embedded_artwork: Option<bool>,
+	// Audio file a cue sheet track is cut from, and the track's bounds within it in milliseconds
+	#[serde(skip_serializing, skip_deserializing)]
+	pub cue_source: Option<String>,
+	#[serde(skip_serializing, skip_deserializing)]
+	pub cue_start: Option<i32>,
+	#[serde(skip_serializing, skip_deserializing)]
+	pub cue_end: Option<i32>,
+	pub disc_total: Option<i32>,
+	// Part of an album by various artists, either tagged as such or guessed from its songs
+	pub compilation: bool,
+	#[serde(skip_serializing, skip_deserializing)]
+	pub compilation_tag: bool,
+	pub conductor: Option<String>,
+	// Names used for ordering, like `Beatles, The` or the reading of a name written in kanji
+	pub artist_sort: Option<String>,
+	pub album_artist_sort: Option<String>,
+	pub album_sort: Option<String>,
+	pub title_sort: Option<String>,
+	// ReplayGain adjustments in dB and peaks relative to full scale, for clients normalizing volume
+	pub track_gain: Option<f32>,
+	pub track_peak: Option<f32>,
+	pub album_gain: Option<f32>,
+	pub album_peak: Option<f32>,
+	// File to read lyrics from, which is either the song itself or an LRC file next to it
+	pub lyrics: Option<String>,
+	pub has_embedded_lyrics: Option<bool>,
+	pub musicbrainz_recording_id: Option<String>,
+	pub musicbrainz_release_id: Option<String>,
+	pub musicbrainz_artist_id: Option<String>,
+	pub musicbrainz_album_artist_id: Option<String>,
+	// Hash of the audio stream, which stays the same when tags are edited or the file is moved
+	pub content_hash: Option<String>,
+	// Samples added by the encoder before and after the audio, and the number of samples left
+	// once they are trimmed, for clients playing albums without gaps
+	pub sample_rate: Option<i32>,
+	pub encoder_delay: Option<i32>,
+	pub encoder_padding: Option<i32>,
+	pub sample_count: Option<i64>,
 }
 
 impl Song {
@@ -45,11 +93,17 @@ impl Song {
 				_ => None,
 			};
 		}
+		if let Some(lyrics_path) = self.lyrics {
+			self.lyrics = match vfs.real_to_virtual(Path::new(&lyrics_path)) {
+				Ok(p) => Some(p.to_string_lossy().into_owned()),
+				_ => None,
+			};
+		}
 		Some(self)
 	}
 }
 
-#[derive(Debug, PartialEq, Eq, Queryable, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Queryable, Serialize, Deserialize)]
 pub struct Directory {
 	#[serde(skip_serializing, skip_deserializing)]
 	id: i32,
@@ -64,6 +118,20 @@ pub struct Directory {
 }
 
 impl Directory {
+	// Entry of a tag-based view, which does not exist on disk
+	pub(super) fn view(path: String) -> Directory {
+		Directory {
+			id: 0,
+			path,
+			parent: None,
+			artist: None,
+			year: None,
+			album: None,
+			artwork: None,
+			date_added: 0,
+		}
+	}
+
 	pub fn virtualize(mut self, vfs: &VFS) -> Option<Directory> {
 		self.path = match vfs.real_to_virtual(Path::new(&self.path)) {
 			Ok(p) => p.to_string_lossy().into_owned(),
@@ -78,3 +146,30 @@ impl Directory {
 		Some(self)
 	}
 }
+
+// Slice of a listing, which includes everything by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Page {
+	pub offset: usize,
+	pub limit: Option<usize>,
+}
+
+impl Page {
+	pub fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+		items
+			.into_iter()
+			.skip(self.offset)
+			.take(self.limit.unwrap_or(usize::MAX))
+			.collect()
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+	pub is_running: bool,
+	pub directories_processed: usize,
+	pub songs_processed: usize,
+	pub errors: usize,
+	pub eta_seconds: Option<u64>,
+	pub unavailable_mounts: Vec<String>, // Mounts skipped by the last update
+}
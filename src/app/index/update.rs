@@ -1,25 +1,46 @@
-use log::{error, info};
+use diesel::prelude::*;
+use log::{error, info, warn};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time;
 
 mod cleaner;
 mod collector;
+mod enricher;
+mod hasher;
+mod ignore_file;
 mod inserter;
+mod io_throttle;
 mod traverser;
 
-use crate::app::index::Index;
-use crate::app::vfs;
-use crate::db;
+use crate::app::events::Event;
+use crate::app::index::{metadata::SongTags, Index, Song, Status};
+use crate::app::{sync, vfs};
+use crate::db::{self, directories, songs};
 
 use cleaner::Cleaner;
 use collector::Collector;
+use enricher::Enricher;
+use ignore_file::IgnoreFiles;
 use inserter::Inserter;
-use traverser::Traverser;
+use io_throttle::IoThrottle;
+use traverser::{FileInfo, KnownSong, Traverser};
+
+// Assumed length of songs being streamed when it is unknown
+const DEFAULT_STREAM_DURATION: time::Duration = time::Duration::from_secs(5 * 60);
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error(transparent)]
 	IndexClean(#[from] cleaner::Error),
 	#[error(transparent)]
+	IndexEnrich(#[from] enricher::Error),
+	#[error(transparent)]
 	Database(#[from] diesel::result::Error),
 	#[error(transparent)]
 	DatabaseConnection(#[from] db::Error),
@@ -27,34 +48,213 @@ pub enum Error {
 	Vfs(#[from] vfs::Error),
 }
 
+#[derive(Default)]
+pub struct Progress {
+	directories_processed: AtomicUsize,
+	songs_processed: AtomicUsize,
+	errors: AtomicUsize,
+	timing: Mutex<Timing>,
+	unavailable_mounts: Mutex<Vec<String>>,
+}
+
+#[derive(Default)]
+struct Timing {
+	start: Option<time::Instant>,
+	last_num_directories: Option<usize>,
+}
+
+impl Progress {
+	fn begin(&self) {
+		self.directories_processed.store(0, Ordering::SeqCst);
+		self.songs_processed.store(0, Ordering::SeqCst);
+		self.errors.store(0, Ordering::SeqCst);
+		self.timing.lock().unwrap().start = Some(time::Instant::now());
+	}
+
+	fn end(&self) {
+		let mut timing = self.timing.lock().unwrap();
+		timing.start = None;
+		timing.last_num_directories = Some(self.directories_processed.load(Ordering::SeqCst));
+	}
+
+	pub fn on_directory_processed(&self) {
+		self.directories_processed.fetch_add(1, Ordering::SeqCst);
+	}
+
+	pub fn on_songs_processed(&self, count: usize) {
+		self.songs_processed.fetch_add(count, Ordering::SeqCst);
+	}
+
+	pub fn on_error(&self) {
+		self.errors.fetch_add(1, Ordering::SeqCst);
+	}
+
+	fn status(&self) -> Status {
+		let timing = self.timing.lock().unwrap();
+		let directories_processed = self.directories_processed.load(Ordering::SeqCst);
+
+		// Estimate based on the size of the collection during the previous update
+		let eta_seconds = match (timing.start, timing.last_num_directories) {
+			(Some(start), Some(num_directories)) if directories_processed > 0 => {
+				let remaining = num_directories.saturating_sub(directories_processed);
+				let elapsed = start.elapsed().as_secs_f64();
+				Some((elapsed * remaining as f64 / directories_processed as f64).round() as u64)
+			}
+			_ => None,
+		};
+
+		Status {
+			is_running: timing.start.is_some(),
+			directories_processed,
+			songs_processed: self.songs_processed.load(Ordering::SeqCst),
+			errors: self.errors.load(Ordering::SeqCst),
+			eta_seconds,
+			unavailable_mounts: self.unavailable_mounts.lock().unwrap().clone(),
+		}
+	}
+}
+
 impl Index {
+	pub fn get_status(&self) -> Status {
+		self.progress.status()
+	}
+
+	/// Lets index updates pause while a song is being listened to, when they are set to.
+	pub fn on_stream(&self, real_path: &Path) {
+		let duration = self.db.connect().ok().and_then(|mut connection| {
+			songs::table
+				.filter(songs::path.eq(real_path.to_string_lossy()))
+				.select(songs::duration)
+				.get_result::<Option<i32>>(&mut connection)
+				.ok()
+				.flatten()
+		});
+		let duration = duration
+			.map(|d| time::Duration::from_secs(d.max(0) as u64))
+			.unwrap_or(DEFAULT_STREAM_DURATION);
+		let mut streaming_until = self.streaming_until.lock().unwrap();
+		let until = time::Instant::now() + duration;
+		*streaming_until = Some(streaming_until.map_or(until, |t| t.max(until)));
+	}
+
 	pub fn update(&self) -> Result<(), Error> {
+		self.progress.begin();
+		self.events_manager.publish(Event::IndexUpdateStarted);
+		let result = self.run_update();
+		self.invalidate_suggestions();
+		self.invalidate_caches();
+		self.progress.end();
+		self.events_manager.publish(Event::IndexUpdateCompleted);
+		result
+	}
+
+	fn run_update(&self) -> Result<(), Error> {
 		let start = time::Instant::now();
 		info!("Beginning library index update");
 
 		let album_art_pattern = self.settings_manager.get_index_album_art_pattern().ok();
+		let album_art_priority = self
+			.settings_manager
+			.get_index_album_art_priority()
+			.unwrap_or_default();
+		let group_compilations = self
+			.settings_manager
+			.get_index_group_compilations()
+			.unwrap_or(true);
+		let ignore_patterns = self
+			.settings_manager
+			.get_index_ignore_patterns()
+			.unwrap_or_else(|e| {
+				error!("Could not read index ignore patterns: {}", e);
+				Vec::new()
+			});
+		let num_threads = self.settings_manager.get_index_num_threads().ok().flatten();
+		let max_files_per_second = self
+			.settings_manager
+			.get_index_max_files_per_second()
+			.ok()
+			.flatten();
+		let pause_while_streaming = self
+			.settings_manager
+			.get_index_pause_while_streaming()
+			.unwrap_or(false);
+		let io_throttle = Arc::new(IoThrottle::new(
+			max_files_per_second,
+			pause_while_streaming.then(|| self.streaming_until.clone()),
+			self.shutting_down.clone(),
+		));
+
+		// Songs of unavailable mounts are left alone until they come back
+		let vfs = self.vfs_manager.get_vfs()?;
+		let (unavailable_mounts, mounts): (Vec<_>, Vec<_>) =
+			vfs.mounts().iter().cloned().partition(is_unavailable);
+		let unavailable_mounts = unavailable_mounts
+			.into_iter()
+			.map(|m| {
+				warn!(
+					"Skipping mount `{}` because `{}` is unreachable or empty",
+					m.name,
+					m.source.display()
+				);
+				m.name
+			})
+			.collect::<Vec<_>>();
+		*self.progress.unavailable_mounts.lock().unwrap() = unavailable_mounts.clone();
 
-		let cleaner = Cleaner::new(self.db.clone(), self.vfs_manager.clone());
+		let ignore_files = Arc::new(IgnoreFiles::default());
+		let cleaner = Cleaner::new(
+			self.db.clone(),
+			self.vfs_manager.clone(),
+			ignore_patterns.clone(),
+			ignore_files.clone(),
+			unavailable_mounts,
+		);
 		cleaner.clean()?;
 
+		let known_songs = self.get_known_songs()?;
+		let pregenerate_thumbnails = self
+			.settings_manager
+			.get_index_pregenerate_thumbnails()
+			.unwrap_or(false);
+		let known_artworks = match pregenerate_thumbnails {
+			true => self.get_album_artworks()?,
+			false => HashSet::new(),
+		};
+
 		let (insert_sender, insert_receiver) = crossbeam_channel::unbounded();
 		let inserter_db = self.db.clone();
+		let inserter_progress = self.progress.clone();
 		let insertion_thread = std::thread::spawn(move || {
-			let mut inserter = Inserter::new(inserter_db, insert_receiver);
+			let mut inserter = Inserter::new(inserter_db, insert_receiver, inserter_progress);
 			inserter.insert();
 		});
 
 		let (collect_sender, collect_receiver) = crossbeam_channel::unbounded();
 		let collector_thread = std::thread::spawn(move || {
-			let collector = Collector::new(collect_receiver, insert_sender, album_art_pattern);
+			let mut collector = Collector::new(
+				collect_receiver,
+				insert_sender,
+				album_art_pattern,
+				album_art_priority,
+				group_compilations,
+			);
 			collector.collect();
 		});
 
-		let vfs = self.vfs_manager.get_vfs()?;
+		let traverser_progress = self.progress.clone();
+		let traverser_shutting_down = self.shutting_down.clone();
 		let traverser_thread = std::thread::spawn(move || {
-			let mounts = vfs.mounts();
-			let traverser = Traverser::new(collect_sender);
-			traverser.traverse(mounts.iter().map(|p| p.source.clone()).collect());
+			let traverser = Traverser::new(
+				collect_sender,
+				known_songs,
+				num_threads,
+				ignore_patterns,
+				ignore_files,
+				io_throttle,
+				traverser_progress,
+				traverser_shutting_down,
+			);
+			traverser.traverse(mounts);
 		});
 
 		if let Err(e) = traverser_thread.join() {
@@ -69,6 +269,49 @@ impl Index {
 			error!("Error joining on inserter thread: {:?}", e);
 		}
 
+		cleaner.clean_cue_sources()?;
+		let num_moved_songs = cleaner.follow_moved_songs()?;
+		if num_moved_songs > 0 {
+			info!("Followed {} songs to their new location", num_moved_songs);
+		}
+		match self.settings_manager.get_index_missing_grace_period() {
+			Ok(grace_period) => cleaner.prune_missing_songs(grace_period)?,
+			Err(e) => error!("Could not read missing songs grace period: {}", e),
+		}
+
+		if self
+			.settings_manager
+			.get_index_metadata_lookup()
+			.unwrap_or(false)
+		{
+			let acoustid_api_key = self.settings_manager.get_acoustid_api_key().ok().flatten();
+			let enricher = Enricher::new(
+				self.db.clone(),
+				acoustid_api_key,
+				self.shutting_down.clone(),
+			);
+			enricher.enrich()?;
+		}
+
+		let mut connection = self.db.connect()?;
+		sync::record_song_changes(&mut connection)?;
+
+		if pregenerate_thumbnails {
+			let new_artworks = self
+				.get_album_artworks()?
+				.into_iter()
+				.filter(|a| !known_artworks.contains(a))
+				.map(PathBuf::from)
+				.collect::<Vec<_>>();
+			if !new_artworks.is_empty() {
+				info!(
+					"Generating thumbnails for {} new albums",
+					new_artworks.len()
+				);
+				self.thumbnail_manager.pregenerate(new_artworks);
+			}
+		}
+
 		info!(
 			"Library index update took {} seconds",
 			start.elapsed().as_millis() as f32 / 1000.0
@@ -76,4 +319,87 @@ impl Index {
 
 		Ok(())
 	}
+
+	fn get_album_artworks(&self) -> Result<HashSet<String>, Error> {
+		let mut connection = self.db.connect()?;
+		let artworks: Vec<String> = directories::table
+			.filter(directories::artwork.is_not_null())
+			.select(directories::artwork.assume_not_null())
+			.distinct()
+			.load(&mut connection)?;
+		Ok(artworks.into_iter().collect())
+	}
+
+	fn get_known_songs(&self) -> Result<HashMap<PathBuf, KnownSong>, Error> {
+		let mut connection = self.db.connect()?;
+		let songs: Vec<Song> = songs::table
+			.filter(songs::file_modified.is_not_null())
+			.load(&mut connection)?;
+		Ok(songs.into_iter().filter_map(known_song).collect())
+	}
+}
+
+// Network shares which are offline often leave an empty directory behind
+fn is_unavailable(mount: &vfs::Mount) -> bool {
+	match fs::read_dir(&mount.source) {
+		Ok(mut entries) => entries.next().is_none(),
+		Err(_) => true,
+	}
+}
+
+fn is_ignored(file_name: &OsStr, ignore_patterns: &[Regex]) -> bool {
+	let file_name = file_name.to_string_lossy();
+	ignore_patterns.iter().any(|p| p.is_match(&file_name))
+}
+
+fn known_song(song: Song) -> Option<(PathBuf, KnownSong)> {
+	let file_info = FileInfo {
+		modified: song.file_modified?,
+		size: song.file_size?,
+	};
+	let has_artwork = song.has_embedded_artwork?;
+	let has_lyrics = song.has_embedded_lyrics?;
+	let metadata = SongTags {
+		disc_number: song.disc_number.map(|n| n as u32),
+		disc_total: song.disc_total.map(|n| n as u32),
+		track_number: song.track_number.map(|n| n as u32),
+		title: song.title,
+		duration: song.duration.map(|n| n as u32),
+		artist: song.artist,
+		album_artist: song.album_artist,
+		album: song.album,
+		year: song.year,
+		has_artwork,
+		has_lyrics,
+		lyricist: song.lyricist,
+		composer: song.composer,
+		conductor: song.conductor,
+		genre: song.genre,
+		label: song.label,
+		compilation: song.compilation_tag,
+		artist_sort: song.artist_sort,
+		album_artist_sort: song.album_artist_sort,
+		album_sort: song.album_sort,
+		title_sort: song.title_sort,
+		track_gain: song.track_gain,
+		track_peak: song.track_peak,
+		album_gain: song.album_gain,
+		album_peak: song.album_peak,
+		musicbrainz_recording_id: song.musicbrainz_recording_id,
+		musicbrainz_release_id: song.musicbrainz_release_id,
+		musicbrainz_artist_id: song.musicbrainz_artist_id,
+		musicbrainz_album_artist_id: song.musicbrainz_album_artist_id,
+		sample_rate: song.sample_rate.map(|n| n as u32),
+		encoder_delay: song.encoder_delay.map(|n| n as u32),
+		encoder_padding: song.encoder_padding.map(|n| n as u32),
+		sample_count: song.sample_count.map(|n| n as u64),
+	};
+	Some((
+		PathBuf::from(song.path),
+		KnownSong {
+			metadata,
+			file_info,
+			content_hash: song.content_hash,
+		},
+	))
 }
@@ -1,11 +1,16 @@
 use diesel::prelude::*;
 use rayon::prelude::*;
+use regex::Regex;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+use super::ignore_file::IgnoreFiles;
+use crate::app::index::{cue, missing};
 use crate::app::vfs;
 use crate::db::{self, directories, songs, DB};
 
-const INDEX_BUILDING_CLEAN_BUFFER_SIZE: usize = 500; // Deletions in each transaction
+const INDEX_BUILDING_CLEAN_BUFFER_SIZE: usize = 500; // Deletions in each statement
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -22,11 +27,26 @@ pub enum Error {
 pub struct Cleaner {
 	db: DB,
 	vfs_manager: vfs::Manager,
+	ignore_patterns: Vec<Regex>,
+	ignore_files: Arc<IgnoreFiles>,
+	unavailable_mounts: Vec<String>,
 }
 
 impl Cleaner {
-	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
-		Self { db, vfs_manager }
+	pub fn new(
+		db: DB,
+		vfs_manager: vfs::Manager,
+		ignore_patterns: Vec<Regex>,
+		ignore_files: Arc<IgnoreFiles>,
+		unavailable_mounts: Vec<String>,
+	) -> Self {
+		Self {
+			db,
+			vfs_manager,
+			ignore_patterns,
+			ignore_files,
+			unavailable_mounts,
+		}
 	}
 
 	pub fn clean(&self) -> Result<(), Error> {
@@ -39,28 +59,55 @@ impl Cleaner {
 				.load(&mut connection)?
 		};
 
-		let all_songs: Vec<String> = {
+		let all_songs: Vec<(String, Option<String>)> = {
 			let mut connection = self.db.connect()?;
-			songs::table.select(songs::path).load(&mut connection)?
+			songs::table
+				.select((songs::path, songs::cue_source))
+				.load(&mut connection)?
+		};
+
+		let is_missing = |real_path: &str, is_dir: bool| {
+			let path = Path::new(real_path);
+			match vfs.real_to_virtual(path) {
+				Err(_) => true,
+				Ok(virtual_path) if self.is_unavailable(&virtual_path) => false,
+				Ok(virtual_path) => {
+					let mount_root = virtual_path.components().next().and_then(|mount_name| {
+						vfs.mounts()
+							.iter()
+							.find(|m| mount_name.as_os_str() == m.name.as_str())
+							.map(|m| &m.source)
+					});
+					// First component is the mount name, which cannot be ignored
+					!path.exists()
+						|| vfs.virtual_to_real(&virtual_path).is_err()
+						|| virtual_path
+							.components()
+							.skip(1)
+							.any(|c| super::is_ignored(c.as_os_str(), &self.ignore_patterns))
+						|| mount_root
+							.is_some_and(|root| self.ignore_files.is_ignored(root, path, is_dir))
+				}
+			}
 		};
 
 		let list_missing_directories = || {
 			all_directories
 				.par_iter()
-				.filter(|ref directory_path| {
-					let path = Path::new(&directory_path);
-					!path.exists() || vfs.real_to_virtual(path).is_err()
-				})
+				.filter(|directory_path| is_missing(directory_path, true))
 				.collect::<Vec<_>>()
 		};
 
+		// Cue sheet tracks go away with their sheet or with the audio file they are cut from
 		let list_missing_songs = || {
 			all_songs
 				.par_iter()
-				.filter(|ref song_path| {
-					let path = Path::new(&song_path);
-					!path.exists() || vfs.real_to_virtual(path).is_err()
+				.filter(|(song_path, cue_source)| {
+					let song_path = cue::get_sheet_path(song_path).unwrap_or(song_path);
+					is_missing(song_path, false)
+						|| cue_source.as_deref().is_some_and(|s| is_missing(s, false))
 				})
+				.map(|(song_path, _)| song_path)
 				.collect::<Vec<_>>()
 		};
 
@@ -68,18 +115,48 @@ impl Cleaner {
 		let (missing_directories, missing_songs) =
 			thread_pool.join(list_missing_directories, list_missing_songs);
 
-		{
-			let mut connection = self.db.connect()?;
+		let mut connection = self.db.connect()?;
+		connection.transaction::<_, Error, _>(|connection| {
 			for chunk in missing_directories[..].chunks(INDEX_BUILDING_CLEAN_BUFFER_SIZE) {
 				diesel::delete(directories::table.filter(directories::path.eq_any(chunk)))
-					.execute(&mut connection)?;
+					.execute(connection)?;
 			}
+			// Songs are remembered for a while in case their files come back
+			missing::record_missing(connection, &missing_songs)?;
 			for chunk in missing_songs[..].chunks(INDEX_BUILDING_CLEAN_BUFFER_SIZE) {
 				diesel::delete(songs::table.filter(songs::path.eq_any(chunk)))
-					.execute(&mut connection)?;
+					.execute(connection)?;
 			}
-		}
+			Ok(())
+		})
+	}
+
+	fn is_unavailable(&self, virtual_path: &Path) -> bool {
+		virtual_path.components().next().is_some_and(|mount_name| {
+			self.unavailable_mounts
+				.iter()
+				.any(|m| mount_name.as_os_str() == m.as_str())
+		})
+	}
+
+	pub fn follow_moved_songs(&self) -> Result<usize, Error> {
+		let mut connection = self.db.connect()?;
+		Ok(missing::follow_moves(&mut connection)?)
+	}
+
+	pub fn prune_missing_songs(&self, grace_period: Duration) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		missing::prune(&mut connection, grace_period)?;
+		Ok(())
+	}
 
+	// Audio files split by a cue sheet are only listed through its tracks
+	pub fn clean_cue_sources(&self) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		diesel::sql_query(
+			"DELETE FROM songs WHERE path IN (SELECT cue_source FROM songs WHERE cue_source IS NOT NULL)",
+		)
+		.execute(&mut connection)?;
 		Ok(())
 	}
 }
@@ -2,12 +2,27 @@ use crossbeam_channel::{Receiver, Sender};
 use log::error;
 use regex::Regex;
 
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::app::index::cue::{self, CueFile, CueSheet};
+use crate::app::index::lyrics;
+use crate::app::index::VARIOUS_ARTISTS;
+use crate::app::settings::AlbumArtPriority;
+
 use super::*;
 
+// Albums by this many different artists, none of them credited as album artist, are compilations
+const MIN_COMPILATION_ARTISTS: usize = 3;
+
 pub struct Collector {
 	receiver: Receiver<traverser::Directory>,
 	sender: Sender<inserter::Item>,
 	album_art_pattern: Option<Regex>,
+	album_art_priority: AlbumArtPriority,
+	group_compilations: bool,
+	// Album art found in or above each directory, for subfolders without art of their own
+	inherited_artworks: HashMap<PathBuf, String>,
 }
 
 impl Collector {
@@ -15,21 +30,31 @@ impl Collector {
 		receiver: Receiver<traverser::Directory>,
 		sender: Sender<inserter::Item>,
 		album_art_pattern: Option<Regex>,
+		album_art_priority: AlbumArtPriority,
+		group_compilations: bool,
 	) -> Self {
 		Self {
 			receiver,
 			sender,
 			album_art_pattern,
+			album_art_priority,
+			group_compilations,
+			inherited_artworks: HashMap::new(),
 		}
 	}
 
-	pub fn collect(&self) {
+	// Directories are always received after their parent
+	pub fn collect(&mut self) {
 		while let Ok(directory) = self.receiver.recv() {
 			self.collect_directory(directory);
 		}
 	}
 
-	fn collect_directory(&self, directory: traverser::Directory) {
+	fn collect_directory(&mut self, mut directory: traverser::Directory) {
+		for song in &mut directory.songs {
+			song.metadata = song.metadata.clone().normalized();
+		}
+
 		let mut directory_album = None;
 		let mut directory_year = None;
 		let mut directory_artist = None;
@@ -37,13 +62,41 @@ impl Collector {
 		let mut inconsistent_directory_year = false;
 		let mut inconsistent_directory_artist = false;
 
-		let directory_artwork = self.get_artwork(&directory);
+		let overlay_artwork = self.get_overlay_artwork(&directory);
+		let folder_artwork = self.get_folder_artwork(&directory);
+		let inherited_artwork = directory
+			.parent
+			.as_ref()
+			.and_then(|p| self.inherited_artworks.get(p))
+			.cloned();
+		if let Some(artwork) = overlay_artwork
+			.clone()
+			.or_else(|| folder_artwork.clone())
+			.or_else(|| inherited_artwork.clone())
+		{
+			self.inherited_artworks
+				.insert(directory.path.clone(), artwork);
+		}
+		let directory_artwork = overlay_artwork
+			.clone()
+			.or_else(|| folder_artwork.clone())
+			.or_else(|| Self::get_embedded_artwork(&directory))
+			.or(inherited_artwork);
 		let directory_path_string = directory.path.to_string_lossy().to_string();
 		let directory_parent_string = directory.parent.map(|p| p.to_string_lossy().to_string());
+		let guessed_compilation = self.group_compilations && Self::is_compilation(&directory.songs);
 
 		for song in directory.songs {
+			let cue = Self::find_cue_file(&directory.cue_sheets, &song.path);
 			let tags = song.metadata;
 			let path_string = song.path.to_string_lossy().to_string();
+			let compilation = tags.compilation || guessed_compilation;
+			// Compilations are grouped under a single album artist rather than each song's artist
+			let grouping_artist = match &tags.album_artist {
+				Some(album_artist) => Some(album_artist.clone()),
+				None if compilation => Some(VARIOUS_ARTISTS.to_owned()),
+				None => tags.artist.clone(),
+			};
 
 			if tags.year.is_some() {
 				inconsistent_directory_year |=
@@ -57,23 +110,33 @@ impl Collector {
 				directory_album = tags.album.as_ref().cloned();
 			}
 
-			if tags.album_artist.is_some() {
-				inconsistent_directory_artist |=
-					directory_artist.is_some() && directory_artist != tags.album_artist;
-				directory_artist = tags.album_artist.as_ref().cloned();
-			} else if tags.artist.is_some() {
+			if grouping_artist.is_some() {
 				inconsistent_directory_artist |=
-					directory_artist.is_some() && directory_artist != tags.artist;
-				directory_artist = tags.artist.as_ref().cloned();
+					directory_artist.is_some() && directory_artist != grouping_artist;
+				directory_artist = grouping_artist;
 			}
 
-			let artwork_path = if tags.has_artwork {
-				Some(path_string.clone())
-			} else {
-				directory_artwork.as_ref().cloned()
-			};
+			// Sidecar files are preferred since they are more likely to be time-synced
+			let lyrics_path = Self::find_lyrics_file(&directory.other_files, &song.path)
+				.map(|p| p.to_string_lossy().to_string())
+				.or_else(|| tags.has_lyrics.then(|| path_string.clone()));
+
+			// Art supplied through the overlay always wins
+			let embedded_artwork = tags.has_artwork.then(|| path_string.clone());
+			let artwork_path = overlay_artwork
+				.clone()
+				.or_else(|| match self.album_art_priority {
+					AlbumArtPriority::Embedded => {
+						embedded_artwork.or_else(|| directory_artwork.as_ref().cloned())
+					}
+					AlbumArtPriority::Folder => folder_artwork
+						.as_ref()
+						.cloned()
+						.or(embedded_artwork)
+						.or_else(|| directory_artwork.as_ref().cloned()),
+				});
 
-			if let Err(e) = self.sender.send(inserter::Item::Song(inserter::Song {
+			let song = inserter::Song {
 				path: path_string,
 				parent: directory_path_string.clone(),
 				disc_number: tags.disc_number.map(|n| n as i32),
@@ -89,8 +152,48 @@ impl Collector {
 				composer: tags.composer,
 				genre: tags.genre,
 				label: tags.label,
-			})) {
-				error!("Error while sending song from collector: {}", e);
+				file_modified: song.file_info.map(|i| i.modified),
+				file_size: song.file_info.map(|i| i.size),
+				has_embedded_artwork: tags.has_artwork,
+				cue_source: None,
+				cue_start: None,
+				cue_end: None,
+				disc_total: tags.disc_total.map(|n| n as i32),
+				compilation,
+				compilation_tag: tags.compilation,
+				conductor: tags.conductor,
+				artist_sort: tags.artist_sort,
+				album_artist_sort: tags.album_artist_sort,
+				album_sort: tags.album_sort,
+				title_sort: tags.title_sort,
+				track_gain: tags.track_gain,
+				track_peak: tags.track_peak,
+				album_gain: tags.album_gain,
+				album_peak: tags.album_peak,
+				lyrics: lyrics_path,
+				has_embedded_lyrics: tags.has_lyrics,
+				musicbrainz_recording_id: tags.musicbrainz_recording_id,
+				musicbrainz_release_id: tags.musicbrainz_release_id,
+				musicbrainz_artist_id: tags.musicbrainz_artist_id,
+				musicbrainz_album_artist_id: tags.musicbrainz_album_artist_id,
+				content_hash: song.content_hash,
+				sample_rate: tags.sample_rate.map(|n| n as i32),
+				encoder_delay: tags.encoder_delay.map(|n| n as i32),
+				encoder_padding: tags.encoder_padding.map(|n| n as i32),
+				sample_count: tags.sample_count.map(|n| n as i64),
+			};
+
+			// Audio files split by a cue sheet are replaced by its tracks
+			let songs = match cue {
+				Some((sheet_path, sheet, file)) => {
+					Self::split_cue_tracks(song, sheet_path, sheet, file)
+				}
+				None => vec![song],
+			};
+			for song in songs {
+				if let Err(e) = self.sender.send(inserter::Item::Song(song)) {
+					error!("Error while sending song from collector: {}", e);
+				}
 			}
 		}
 
@@ -119,12 +222,183 @@ impl Collector {
 		}
 	}
 
-	fn get_artwork(&self, directory: &traverser::Directory) -> Option<String> {
-		let regex_artwork = directory.other_files.iter().find_map(|path| {
+	// Cue sheets often refer to the format of the original rip, like `Album.wav` for `Album.flac`
+	fn find_lyrics_file<'a>(other_files: &'a [PathBuf], song_path: &Path) -> Option<&'a PathBuf> {
+		let file_stem = song_path.file_stem()?;
+		other_files
+			.iter()
+			.find(|p| lyrics::is_lyrics_file(p) && p.file_stem() == Some(file_stem))
+	}
+
+	fn find_cue_file<'a>(
+		cue_sheets: &'a [(PathBuf, CueSheet)],
+		song_path: &Path,
+	) -> Option<(&'a Path, &'a CueSheet, &'a CueFile)> {
+		let file_name = song_path.file_name()?;
+		let file_stem = song_path.file_stem()?;
+		let files = || {
+			cue_sheets.iter().flat_map(|(sheet_path, sheet)| {
+				sheet
+					.files
+					.iter()
+					.map(move |file| (sheet_path.as_path(), sheet, file))
+			})
+		};
+		files()
+			.find(|(_, _, file)| Path::new(&file.name).file_name() == Some(file_name))
+			.or_else(|| {
+				files().find(|(_, _, file)| Path::new(&file.name).file_stem() == Some(file_stem))
+			})
+	}
+
+	fn split_cue_tracks(
+		song: inserter::Song,
+		sheet_path: &Path,
+		sheet: &CueSheet,
+		file: &CueFile,
+	) -> Vec<inserter::Song> {
+		let file_duration = song.duration.map(|d| d as u32 * 1000);
+		file.tracks
+			.iter()
+			.enumerate()
+			.map(|(index, track)| {
+				let end = file.get_end(index);
+				let duration = end
+					.or(file_duration)
+					.map(|end| end.saturating_sub(track.start) / 1000);
+				let artist = track
+					.performer
+					.clone()
+					.or_else(|| sheet.performer.clone())
+					.or_else(|| song.artist.clone());
+				let album_artist = sheet
+					.performer
+					.clone()
+					.or_else(|| song.album_artist.clone());
+				let album = sheet.title.clone().or_else(|| song.album.clone());
+				// Sort names and MusicBrainz IDs of the audio file only apply to the tags the cue sheet
+				// does not override
+				let inherited =
+					|value: &Option<String>, file_value: &Option<String>, sort: &Option<String>| {
+						if value == file_value {
+							sort.clone()
+						} else {
+							None
+						}
+					};
+				inserter::Song {
+					path: cue::get_track_path(sheet_path, track.number),
+					parent: song.parent.clone(),
+					track_number: Some(track.number as i32),
+					disc_number: sheet.disc_number.map(|n| n as i32).or(song.disc_number),
+					title: track.title.clone(),
+					artist_sort: inherited(&artist, &song.artist, &song.artist_sort),
+					album_artist_sort: inherited(
+						&album_artist,
+						&song.album_artist,
+						&song.album_artist_sort,
+					),
+					album_sort: inherited(&album, &song.album, &song.album_sort),
+					title_sort: None,
+					// Gains measured on the whole file apply to the album rather than to its tracks
+					track_gain: track.track_gain,
+					track_peak: track.track_peak,
+					album_gain: sheet.album_gain.or(song.album_gain).or(song.track_gain),
+					album_peak: sheet.album_peak.or(song.album_peak).or(song.track_peak),
+					// Lyrics of the whole file do not line up with individual tracks
+					lyrics: None,
+					has_embedded_lyrics: song.has_embedded_lyrics,
+					// The recording of the whole file is not any of its tracks
+					musicbrainz_recording_id: None,
+					musicbrainz_release_id: song.musicbrainz_release_id.clone(),
+					musicbrainz_artist_id: inherited(
+						&artist,
+						&song.artist,
+						&song.musicbrainz_artist_id,
+					),
+					musicbrainz_album_artist_id: inherited(
+						&album_artist,
+						&song.album_artist,
+						&song.musicbrainz_album_artist_id,
+					),
+					artist,
+					album_artist,
+					year: sheet.year.or(song.year),
+					album,
+					artwork: song.artwork.clone(),
+					duration: duration.map(|d| d as i32),
+					lyricist: song.lyricist.clone(),
+					composer: track
+						.songwriter
+						.clone()
+						.or_else(|| sheet.songwriter.clone())
+						.or_else(|| song.composer.clone()),
+					genre: sheet.genre.clone().or_else(|| song.genre.clone()),
+					label: song.label.clone(),
+					file_modified: None,
+					file_size: None,
+					has_embedded_artwork: song.has_embedded_artwork,
+					cue_source: Some(song.path.clone()),
+					cue_start: Some(track.start as i32),
+					cue_end: end.map(|e| e as i32),
+					disc_total: song.disc_total,
+					compilation: song.compilation,
+					compilation_tag: song.compilation_tag,
+					conductor: song.conductor.clone(),
+					// Tracks are not files of their own
+					content_hash: None,
+					sample_rate: song.sample_rate,
+					encoder_delay: None,
+					encoder_padding: None,
+					sample_count: None,
+				}
+			})
+			.collect()
+	}
+
+	// Songs of a single album by many artists, as opposed to an album with a few guest artists
+	fn is_compilation(songs: &[traverser::Song]) -> bool {
+		let Some(album) = songs.first().and_then(|s| s.metadata.album.as_ref()) else {
+			return false;
+		};
+		let same_album = songs
+			.iter()
+			.all(|s| s.metadata.album.as_ref() == Some(album) && s.metadata.album_artist.is_none());
+		let artists: HashSet<&String> = songs
+			.iter()
+			.filter_map(|s| s.metadata.artist.as_ref())
+			.collect();
+		same_album && artists.len() >= MIN_COMPILATION_ARTISTS
+	}
+
+	fn get_embedded_artwork(directory: &traverser::Directory) -> Option<String> {
+		directory.songs.iter().find_map(|song| {
+			if song.metadata.has_artwork {
+				Some(song.path.to_string_lossy().to_string())
+			} else {
+				None
+			}
+		})
+	}
+
+	fn get_folder_artwork(&self, directory: &traverser::Directory) -> Option<String> {
+		self.find_artwork(directory, &directory.other_files)
+	}
+
+	fn get_overlay_artwork(&self, directory: &traverser::Directory) -> Option<String> {
+		self.find_artwork(directory, &directory.overlay_files)
+	}
+
+	fn find_artwork(&self, directory: &traverser::Directory, files: &[PathBuf]) -> Option<String> {
+		let album_art_pattern = directory
+			.album_art_pattern
+			.as_ref()
+			.or(self.album_art_pattern.as_ref());
+		files.iter().find_map(|path| {
 			let matches = path
 				.file_name()
 				.and_then(|name| name.to_str())
-				.map(|name| match &self.album_art_pattern {
+				.map(|name| match album_art_pattern {
 					Some(pattern) => pattern.is_match(name),
 					None => false,
 				})
@@ -134,16 +408,6 @@ impl Collector {
 			} else {
 				None
 			}
-		});
-
-		let embedded_artwork = directory.songs.iter().find_map(|song| {
-			if song.metadata.has_artwork {
-				Some(song.path.to_string_lossy().to_string())
-			} else {
-				None
-			}
-		});
-
-		regex_artwork.or(embedded_artwork)
+		})
 	}
 }
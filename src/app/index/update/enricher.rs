@@ -0,0 +1,497 @@
+use diesel::dsl::not;
+use diesel::prelude::*;
+use log::{error, info};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::app::fingerprint;
+use crate::db::{self, metadata_lookups, songs, DB};
+
+const MUSICBRAINZ_API_URL: &str = "https://musicbrainz.org/ws/2";
+const ACOUSTID_API_URL: &str = "https://api.acoustid.org/v2";
+// MusicBrainz asks clients to send at most one request per second
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+// Lookups are spread over several updates so large untagged collections do not hold them up
+const MAX_LOOKUPS_PER_UPDATE: i64 = 200;
+// MusicBrainz search scores range from 0 to 100, AcoustID scores from 0 to 1
+const MIN_SEARCH_SCORE: u32 = 90;
+const MIN_FINGERPRINT_SCORE: f32 = 0.9;
+// Songs found by searching must last about as long as the file
+const SEARCH_DURATION_TOLERANCE_MS: i32 = 3000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+enum LookupError {
+	#[error(transparent)]
+	Fingerprint(#[from] fingerprint::Error),
+	#[error("Request failed: `{0}`")]
+	Request(Box<ureq::Error>),
+	#[error("Could not parse response: `{0}`")]
+	Response(String),
+}
+
+impl From<ureq::Error> for LookupError {
+	fn from(error: ureq::Error) -> Self {
+		LookupError::Request(Box::new(error))
+	}
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Lookup {
+	title: Option<String>,
+	artist: Option<String>,
+	album: Option<String>,
+	year: Option<i32>,
+	musicbrainz_recording_id: Option<String>,
+	musicbrainz_release_id: Option<String>,
+	musicbrainz_artist_id: Option<String>,
+}
+
+#[derive(Queryable)]
+struct PendingSong {
+	path: String,
+	title: Option<String>,
+	artist: Option<String>,
+	album: Option<String>,
+	duration: Option<i32>,
+}
+
+/// Fills in missing tags from MusicBrainz. Results are kept apart from songs, so they survive
+/// rescans and files are never modified.
+pub struct Enricher {
+	db: DB,
+	acoustid_api_key: Option<String>,
+	shutting_down: Arc<AtomicBool>,
+}
+
+impl Enricher {
+	pub fn new(db: DB, acoustid_api_key: Option<String>, shutting_down: Arc<AtomicBool>) -> Self {
+		Self {
+			db,
+			acoustid_api_key,
+			shutting_down,
+		}
+	}
+
+	pub fn enrich(&self) -> Result<(), Error> {
+		let pending_songs = self.get_pending_songs()?;
+		if !pending_songs.is_empty() {
+			info!("Looking up metadata for {} songs", pending_songs.len());
+		}
+
+		for song in pending_songs {
+			if self.shutting_down.load(Ordering::SeqCst) {
+				break;
+			}
+			match self.look_up(&song) {
+				Ok(lookup) => self.save(&song.path, lookup.unwrap_or_default())?,
+				Err(e) => error!("Metadata lookup failed for `{}`: {}", song.path, e),
+			}
+			std::thread::sleep(REQUEST_INTERVAL);
+		}
+
+		self.apply()
+	}
+
+	fn get_pending_songs(&self) -> Result<Vec<PendingSong>, Error> {
+		let mut connection = self.db.connect()?;
+		let looked_up = metadata_lookups::table.select(metadata_lookups::path);
+		let songs = songs::table
+			.filter(songs::artist.is_null().or(songs::album.is_null()))
+			.filter(songs::cue_source.is_null())
+			.filter(not(songs::path.eq_any(looked_up)))
+			.select((
+				songs::path,
+				songs::title,
+				songs::artist,
+				songs::album,
+				songs::duration,
+			))
+			.limit(MAX_LOOKUPS_PER_UPDATE)
+			.load(&mut connection)?;
+		Ok(songs)
+	}
+
+	fn look_up(&self, song: &PendingSong) -> Result<Option<Lookup>, LookupError> {
+		if let Some(api_key) = &self.acoustid_api_key {
+			match look_up_fingerprint(Path::new(&song.path), api_key) {
+				Ok(Some(lookup)) => return Ok(Some(lookup)),
+				Ok(None) => (),
+				Err(e) => error!("Could not identify `{}` by fingerprint: {}", song.path, e),
+			}
+			std::thread::sleep(REQUEST_INTERVAL);
+		}
+		look_up_search(song)
+	}
+
+	fn save(&self, path: &str, lookup: Lookup) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		diesel::insert_into(metadata_lookups::table)
+			.values((
+				metadata_lookups::path.eq(path),
+				metadata_lookups::title.eq(lookup.title),
+				metadata_lookups::artist.eq(lookup.artist),
+				metadata_lookups::album.eq(lookup.album),
+				metadata_lookups::year.eq(lookup.year),
+				metadata_lookups::musicbrainz_recording_id.eq(lookup.musicbrainz_recording_id),
+				metadata_lookups::musicbrainz_release_id.eq(lookup.musicbrainz_release_id),
+				metadata_lookups::musicbrainz_artist_id.eq(lookup.musicbrainz_artist_id),
+			))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
+	// Tags read from files always take precedence over looked up values
+	fn apply(&self) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		diesel::sql_query(
+			r#"
+			UPDATE songs SET
+				title = COALESCE(title, (SELECT l.title FROM metadata_lookups l WHERE l.path = songs.path)),
+				artist = COALESCE(artist, (SELECT l.artist FROM metadata_lookups l WHERE l.path = songs.path)),
+				album = COALESCE(album, (SELECT l.album FROM metadata_lookups l WHERE l.path = songs.path)),
+				year = COALESCE(year, (SELECT l.year FROM metadata_lookups l WHERE l.path = songs.path)),
+				musicbrainz_recording_id = COALESCE(musicbrainz_recording_id, (SELECT l.musicbrainz_recording_id FROM metadata_lookups l WHERE l.path = songs.path)),
+				musicbrainz_release_id = COALESCE(musicbrainz_release_id, (SELECT l.musicbrainz_release_id FROM metadata_lookups l WHERE l.path = songs.path)),
+				musicbrainz_artist_id = COALESCE(musicbrainz_artist_id, (SELECT l.musicbrainz_artist_id FROM metadata_lookups l WHERE l.path = songs.path))
+			WHERE path IN (SELECT path FROM metadata_lookups)
+			"#,
+		)
+		.execute(&mut connection)?;
+		Ok(())
+	}
+}
+
+fn user_agent() -> String {
+	format!(
+		"Polaris/{} ( https://github.com/agersant/polaris )",
+		env!("CARGO_PKG_VERSION")
+	)
+}
+
+fn read_json<T: DeserializeOwned>(response: ureq::Response) -> Result<T, LookupError> {
+	let body = response
+		.into_string()
+		.map_err(|e| LookupError::Response(e.to_string()))?;
+	serde_json::from_str(&body).map_err(|e| LookupError::Response(e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct AcoustidResponse {
+	#[serde(default)]
+	results: Vec<AcoustidResult>,
+}
+
+#[derive(Deserialize)]
+struct AcoustidResult {
+	score: f32,
+	#[serde(default)]
+	recordings: Vec<AcoustidRecording>,
+}
+
+#[derive(Deserialize)]
+struct AcoustidRecording {
+	id: String,
+	title: Option<String>,
+	#[serde(default)]
+	artists: Vec<Credit>,
+	#[serde(default)]
+	releases: Vec<AcoustidRelease>,
+}
+
+#[derive(Deserialize)]
+struct AcoustidRelease {
+	id: String,
+	title: Option<String>,
+	date: Option<AcoustidDate>,
+}
+
+#[derive(Deserialize)]
+struct AcoustidDate {
+	year: Option<i32>,
+}
+
+fn look_up_fingerprint(path: &Path, api_key: &str) -> Result<Option<Lookup>, LookupError> {
+	let fingerprint = fingerprint::compute(path)?;
+	let duration = (fingerprint.duration.round() as i64).to_string();
+	let response = ureq::post(&format!("{}/lookup", ACOUSTID_API_URL))
+		.set("User-Agent", &user_agent())
+		.send_form(&[
+			("client", api_key),
+			("meta", "recordings releases"),
+			("duration", &duration),
+			("fingerprint", &fingerprint.fingerprint),
+		])?;
+	Ok(read_acoustid_response(read_json(response)?))
+}
+
+fn read_acoustid_response(response: AcoustidResponse) -> Option<Lookup> {
+	let recording = response
+		.results
+		.into_iter()
+		.filter(|r| r.score >= MIN_FINGERPRINT_SCORE)
+		.flat_map(|r| r.recordings)
+		.find(|r| r.title.is_some())?;
+	let release = recording.releases.into_iter().next();
+	Some(Lookup {
+		title: recording.title,
+		artist: credited_name(&recording.artists),
+		album: release.as_ref().and_then(|r| r.title.clone()),
+		year: release.as_ref().and_then(|r| r.date.as_ref()?.year),
+		musicbrainz_recording_id: Some(recording.id),
+		musicbrainz_release_id: release.map(|r| r.id),
+		musicbrainz_artist_id: recording.artists.first().map(|a| a.id.clone()),
+	})
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+	#[serde(default)]
+	recordings: Vec<SearchRecording>,
+}
+
+#[derive(Deserialize)]
+struct SearchRecording {
+	id: String,
+	#[serde(default)]
+	score: u32,
+	title: String,
+	#[serde(rename = "artist-credit", default)]
+	artist_credit: Vec<SearchCredit>,
+	#[serde(default)]
+	releases: Vec<SearchRelease>,
+}
+
+#[derive(Deserialize)]
+struct SearchCredit {
+	name: String,
+	#[serde(default)]
+	joinphrase: String,
+	artist: SearchArtist,
+}
+
+#[derive(Deserialize)]
+struct SearchArtist {
+	id: String,
+}
+
+#[derive(Deserialize)]
+struct SearchRelease {
+	id: String,
+	title: String,
+	date: Option<String>,
+}
+
+// Artists credited on a recording, with how their names are joined (`feat.`, ` & `...)
+#[derive(Deserialize)]
+struct Credit {
+	id: String,
+	name: String,
+	joinphrase: Option<String>,
+}
+
+impl From<SearchCredit> for Credit {
+	fn from(credit: SearchCredit) -> Self {
+		Self {
+			id: credit.artist.id,
+			name: credit.name,
+			joinphrase: Some(credit.joinphrase),
+		}
+	}
+}
+
+fn credited_name(credits: &[Credit]) -> Option<String> {
+	if credits.is_empty() {
+		return None;
+	}
+	Some(
+		credits
+			.iter()
+			.map(|c| format!("{}{}", c.name, c.joinphrase.as_deref().unwrap_or_default()))
+			.collect(),
+	)
+}
+
+// Songs without a title tag are searched by file name
+fn search_query(song: &PendingSong) -> Option<String> {
+	let title = song.title.clone().or_else(|| {
+		Path::new(&song.path)
+			.file_stem()
+			.map(|s| s.to_string_lossy().into_owned())
+	})?;
+	let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+	let mut query = format!("recording:{}", quote(&title));
+	if let Some(artist) = &song.artist {
+		query.push_str(&format!(" AND artist:{}", quote(artist)));
+	}
+	if let Some(album) = &song.album {
+		query.push_str(&format!(" AND release:{}", quote(album)));
+	}
+	if let Some(duration) = song.duration {
+		let duration = duration * 1000;
+		query.push_str(&format!(
+			" AND dur:[{} TO {}]",
+			(duration - SEARCH_DURATION_TOLERANCE_MS).max(0),
+			duration + SEARCH_DURATION_TOLERANCE_MS
+		));
+	}
+	Some(query)
+}
+
+fn look_up_search(song: &PendingSong) -> Result<Option<Lookup>, LookupError> {
+	let Some(query) = search_query(song) else {
+		return Ok(None);
+	};
+	let response = ureq::get(&format!("{}/recording", MUSICBRAINZ_API_URL))
+		.set("User-Agent", &user_agent())
+		.query("query", &query)
+		.query("fmt", "json")
+		.query("limit", "5")
+		.call()?;
+	Ok(read_search_response(
+		read_json(response)?,
+		song.album.as_deref(),
+	))
+}
+
+// Releases named like the album tag are preferred over other releases of the same recording
+fn read_search_response(response: SearchResponse, album: Option<&str>) -> Option<Lookup> {
+	let recording = response
+		.recordings
+		.into_iter()
+		.find(|r| r.score >= MIN_SEARCH_SCORE)?;
+	let mut releases = recording.releases;
+	let release_index = releases
+		.iter()
+		.position(|r| album.is_some_and(|a| r.title.eq_ignore_ascii_case(a)))
+		.unwrap_or_default();
+	let release = (!releases.is_empty()).then(|| releases.swap_remove(release_index));
+	let credits = recording
+		.artist_credit
+		.into_iter()
+		.map(Credit::from)
+		.collect::<Vec<_>>();
+	Some(Lookup {
+		title: Some(recording.title),
+		artist: credited_name(&credits),
+		album: release.as_ref().map(|r| r.title.clone()),
+		year: release
+			.as_ref()
+			.and_then(|r| r.date.as_ref()?.get(..4)?.parse().ok()),
+		musicbrainz_recording_id: Some(recording.id),
+		musicbrainz_release_id: release.map(|r| r.id),
+		musicbrainz_artist_id: credits.first().map(|c| c.id.clone()),
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn search_query_escapes_tags() {
+		let song = PendingSong {
+			path: "/music/Unknown/01 - Track.mp3".to_owned(),
+			title: Some("Say \"Hello\"".to_owned()),
+			artist: None,
+			album: Some("Hits".to_owned()),
+			duration: Some(200),
+		};
+		assert_eq!(
+			search_query(&song).unwrap(),
+			r#"recording:"Say \"Hello\"" AND release:"Hits" AND dur:[197000 TO 203000]"#
+		);
+
+		let song = PendingSong {
+			title: None,
+			album: None,
+			duration: None,
+			..song
+		};
+		assert_eq!(search_query(&song).unwrap(), r#"recording:"01 - Track""#);
+	}
+
+	#[test]
+	fn reads_search_response() {
+		let response: SearchResponse = serde_json::from_str(
+			r#"{
+				"recordings": [{
+					"id": "rec-1",
+					"score": 98,
+					"title": "Candlelight",
+					"artist-credit": [
+						{ "name": "Khemmis", "joinphrase": " feat. ", "artist": { "id": "art-1" } },
+						{ "name": "Guest", "artist": { "id": "art-2" } }
+					],
+					"releases": [
+						{ "id": "rel-1", "title": "Hunted (Deluxe)", "date": "2017" },
+						{ "id": "rel-2", "title": "Hunted", "date": "2016-10-21" }
+					]
+				}]
+			}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			read_search_response(response, Some("hunted")),
+			Some(Lookup {
+				title: Some("Candlelight".to_owned()),
+				artist: Some("Khemmis feat. Guest".to_owned()),
+				album: Some("Hunted".to_owned()),
+				year: Some(2016),
+				musicbrainz_recording_id: Some("rec-1".to_owned()),
+				musicbrainz_release_id: Some("rel-2".to_owned()),
+				musicbrainz_artist_id: Some("art-1".to_owned()),
+			})
+		);
+	}
+
+	#[test]
+	fn ignores_uncertain_matches() {
+		let response: SearchResponse = serde_json::from_str(
+			r#"{ "recordings": [{ "id": "rec-1", "score": 60, "title": "Candlelight" }] }"#,
+		)
+		.unwrap();
+		assert_eq!(read_search_response(response, None), None);
+
+		let response: AcoustidResponse = serde_json::from_str(
+			r#"{ "status": "ok", "results": [{ "id": "a", "score": 0.5, "recordings": [{ "id": "rec-1", "title": "Candlelight" }] }] }"#,
+		)
+		.unwrap();
+		assert_eq!(read_acoustid_response(response), None);
+	}
+
+	#[test]
+	fn reads_acoustid_response() {
+		let response: AcoustidResponse = serde_json::from_str(
+			r#"{
+				"status": "ok",
+				"results": [{
+					"id": "a",
+					"score": 0.97,
+					"recordings": [{
+						"id": "rec-1",
+						"title": "Candlelight",
+						"artists": [{ "id": "art-1", "name": "Khemmis" }],
+						"releases": [{ "id": "rel-1", "title": "Hunted", "date": { "year": 2016 } }]
+					}]
+				}]
+			}"#,
+		)
+		.unwrap();
+		let lookup = read_acoustid_response(response).unwrap();
+		assert_eq!(lookup.artist.as_deref(), Some("Khemmis"));
+		assert_eq!(lookup.album.as_deref(), Some("Hunted"));
+		assert_eq!(lookup.year, Some(2016));
+		assert_eq!(lookup.musicbrainz_release_id.as_deref(), Some("rel-1"));
+	}
+}
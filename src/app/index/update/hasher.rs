@@ -0,0 +1,169 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::utils::{self, AudioFormat};
+
+/// Hashes the audio stream of a file, so that editing its tags does not change the result.
+/// Tags are skipped in MP3, APE, Musepack and FLAC files, other formats are hashed in full.
+pub fn hash_audio(path: &Path) -> io::Result<String> {
+	let mut file = fs::File::open(path)?;
+	let length = file.metadata()?.len();
+	let (start, end) = match utils::get_audio_format(path) {
+		Some(AudioFormat::MP3) | Some(AudioFormat::APE) | Some(AudioFormat::MPC) => {
+			let start = skip_id3v2(&mut file)?;
+			(start, find_trailing_tags(&mut file, start, length)?)
+		}
+		Some(AudioFormat::FLAC) => (skip_flac_metadata(&mut file, length)?, length),
+		_ => (0, length),
+	};
+
+	let mut hasher = Sha256::new();
+	file.seek(SeekFrom::Start(start))?;
+	io::copy(&mut file.take(end.saturating_sub(start)), &mut hasher)?;
+	Ok(hasher
+		.finalize()
+		.iter()
+		.map(|b| format!("{:02x}", b))
+		.collect())
+}
+
+// Returns the offset following any ID3v2 tags at the beginning of the file
+fn skip_id3v2(file: &mut fs::File) -> io::Result<u64> {
+	let mut offset = 0;
+	loop {
+		let mut header = [0; 10];
+		file.seek(SeekFrom::Start(offset))?;
+		if read_fully(file, &mut header)? < header.len() || &header[0..3] != b"ID3" {
+			return Ok(offset);
+		}
+		let size = header[6..10]
+			.iter()
+			.fold(0u64, |size, b| (size << 7) | (b & 0x7f) as u64);
+		let footer_size = if header[5] & 0x10 != 0 { 10 } else { 0 };
+		offset += 10 + size + footer_size;
+	}
+}
+
+// Returns the offset of the ID3v1, Lyrics3 and APE tags at the end of the file, if any
+fn find_trailing_tags(file: &mut fs::File, start: u64, length: u64) -> io::Result<u64> {
+	let mut end = length;
+	loop {
+		if end >= start + 128 && read_at(file, end - 128, 3)? == b"TAG" {
+			end -= 128;
+		} else if end >= start + 15 && read_at(file, end - 9, 9)? == b"LYRICS200" {
+			let size = String::from_utf8_lossy(&read_at(file, end - 15, 6)?)
+				.parse::<u64>()
+				.unwrap_or(u64::MAX);
+			match end.checked_sub(15 + size) {
+				Some(tag_start) if tag_start >= start => end = tag_start,
+				_ => return Ok(end),
+			}
+		} else if end >= start + 32 && read_at(file, end - 32, 8)? == b"APETAGEX" {
+			let footer = read_at(file, end - 32, 32)?;
+			let size = u32::from_le_bytes([footer[12], footer[13], footer[14], footer[15]]) as u64;
+			let flags = u32::from_le_bytes([footer[20], footer[21], footer[22], footer[23]]);
+			let header_size = if flags & 0x8000_0000 != 0 { 32 } else { 0 };
+			match end.checked_sub(size + header_size) {
+				Some(tag_start) if tag_start >= start => end = tag_start,
+				_ => return Ok(end),
+			}
+		} else {
+			return Ok(end);
+		}
+	}
+}
+
+// Returns the offset of the first audio frame, after the `fLaC` marker and metadata blocks
+fn skip_flac_metadata(file: &mut fs::File, length: u64) -> io::Result<u64> {
+	let start = skip_id3v2(file)?;
+	if read_at(file, start, 4)? != b"fLaC" {
+		return Ok(0);
+	}
+	let mut offset = start + 4;
+	while offset + 4 <= length {
+		let header = read_at(file, offset, 4)?;
+		let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+		offset += 4 + size;
+		if header[0] & 0x80 != 0 {
+			return Ok(offset.min(length));
+		}
+	}
+	Ok(0)
+}
+
+fn read_at(file: &mut fs::File, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+	let mut bytes = vec![0; size];
+	file.seek(SeekFrom::Start(offset))?;
+	let read = read_fully(file, &mut bytes)?;
+	bytes.truncate(read);
+	Ok(bytes)
+}
+
+fn read_fully(file: &mut fs::File, buffer: &mut [u8]) -> io::Result<usize> {
+	let mut read = 0;
+	while read < buffer.len() {
+		match file.read(&mut buffer[read..])? {
+			0 => break,
+			n => read += n,
+		}
+	}
+	Ok(read)
+}
+
+#[cfg(test)]
+mod test {
+	use id3::TagLike;
+	use std::path::PathBuf;
+
+	use super::*;
+	use crate::test::prepare_test_directory;
+	use crate::test_name;
+
+	#[test]
+	fn retagging_keeps_hash() {
+		let directory = prepare_test_directory(test_name!());
+		for source in [
+			"test-data/small-collection/Khemmis/Hunted/05 - Hunted.mp3",
+			"test-data/small-collection/Tobokegao/Picnic/07 - なぜ (Why).mp3",
+		] {
+			let path = directory.join(PathBuf::from(source).file_name().unwrap());
+			fs::copy(source, &path).unwrap();
+			let before = hash_audio(&path).unwrap();
+
+			let mut tag = id3::Tag::read_from_path(&path).unwrap_or_default();
+			tag.set_title("A much longer title than the one this song had before");
+			tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+			assert_eq!(hash_audio(&path).unwrap(), before);
+		}
+	}
+
+	#[test]
+	fn flac_metadata_is_skipped() {
+		let directory = prepare_test_directory(test_name!());
+		let source = "test-data/formats/sample.flac";
+		let path = directory.join("sample.flac");
+		fs::copy(source, &path).unwrap();
+		let before = hash_audio(&path).unwrap();
+
+		let mut tag = metaflac::Tag::read_from_path(&path).unwrap();
+		tag.set_vorbis("TITLE", vec!["Retitled"]);
+		tag.write_to_path(&path).unwrap();
+		assert_eq!(hash_audio(&path).unwrap(), before);
+	}
+
+	#[test]
+	fn different_audio_has_different_hash() {
+		let a = hash_audio(Path::new(
+			"test-data/small-collection/Khemmis/Hunted/05 - Hunted.mp3",
+		))
+		.unwrap();
+		let b = hash_audio(Path::new(
+			"test-data/small-collection/Tobokegao/Picnic/07 - なぜ (Why).mp3",
+		))
+		.unwrap();
+		assert_ne!(a, b);
+		assert_eq!(a.len(), 64);
+	}
+}
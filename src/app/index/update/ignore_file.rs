@@ -0,0 +1,206 @@
+use log::error;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const IGNORE_FILE_NAME: &str = ".polarisignore";
+
+/// Patterns from a `.polarisignore` file, following the rules of `.gitignore` files.
+#[derive(Debug, Default)]
+pub struct IgnoreFile {
+	rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+	regex: Regex,
+	negated: bool,
+	directory_only: bool,
+}
+
+impl IgnoreFile {
+	pub fn parse(text: &str) -> Self {
+		Self {
+			rules: text.lines().filter_map(parse_rule).collect(),
+		}
+	}
+
+	// Relative paths use `/` separators. The last matching rule wins, `None` when none matched.
+	fn matches(&self, relative_path: &str, is_dir: bool) -> Option<bool> {
+		self.rules
+			.iter()
+			.rev()
+			.find(|r| (is_dir || !r.directory_only) && r.regex.is_match(relative_path))
+			.map(|r| !r.negated)
+	}
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+	let line = line.trim_end();
+	if line.is_empty() || line.starts_with('#') {
+		return None;
+	}
+	let (negated, pattern) = match line.strip_prefix('!') {
+		Some(pattern) => (true, pattern),
+		None => (false, line),
+	};
+	let (directory_only, pattern) = match pattern.strip_suffix('/') {
+		Some(pattern) => (true, pattern),
+		None => (false, pattern),
+	};
+	// Patterns without a slash apply at any depth, others from the ignore file's directory
+	let anchored = pattern.contains('/');
+	let pattern = pattern.trim_start_matches('/');
+	if pattern.is_empty() {
+		return None;
+	}
+
+	let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+	let chars = pattern.chars().collect::<Vec<_>>();
+	let mut i = 0;
+	while i < chars.len() {
+		match chars[i] {
+			'*' if chars.get(i + 1) == Some(&'*') => {
+				if chars.get(i + 2) == Some(&'/') {
+					regex.push_str("(?:.*/)?");
+					i += 1;
+				} else {
+					regex.push_str(".*");
+				}
+				i += 1;
+			}
+			'*' => regex.push_str("[^/]*"),
+			'?' => regex.push_str("[^/]"),
+			'[' => match chars[i + 1..].iter().position(|c| *c == ']') {
+				Some(length) => {
+					let class = chars[i + 1..i + 1 + length].iter().collect::<String>();
+					let class = match class.strip_prefix('!') {
+						Some(negated_class) => format!("^{}", negated_class),
+						None => class,
+					};
+					regex.push('[');
+					regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+					regex.push(']');
+					i += length + 1;
+				}
+				None => regex.push_str("\\["),
+			},
+			'\\' if i + 1 < chars.len() => {
+				regex.push_str(&regex::escape(&chars[i + 1].to_string()));
+				i += 1;
+			}
+			c => regex.push_str(&regex::escape(&c.to_string())),
+		}
+		i += 1;
+	}
+	regex.push('$');
+
+	match Regex::new(&regex) {
+		Ok(regex) => Some(Rule {
+			regex,
+			negated,
+			directory_only,
+		}),
+		Err(e) => {
+			error!("Invalid pattern `{}` in {}: {}", line, IGNORE_FILE_NAME, e);
+			None
+		}
+	}
+}
+
+/// Ignore files found within mounts, each read at most once per index update.
+#[derive(Default)]
+pub struct IgnoreFiles {
+	cache: Mutex<HashMap<PathBuf, Option<Arc<IgnoreFile>>>>,
+}
+
+impl IgnoreFiles {
+	/// Whether a file or directory is excluded by the ignore files of its mount, including
+	/// when one of its parent directories is.
+	pub fn is_ignored(&self, mount_root: &Path, path: &Path, is_dir: bool) -> bool {
+		let Ok(relative_path) = path.strip_prefix(mount_root) else {
+			return false;
+		};
+		let components = relative_path
+			.components()
+			.filter_map(|c| match c {
+				Component::Normal(c) => Some(c.to_string_lossy()),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+
+		let directories = (0..components.len())
+			.map(|depth| {
+				let directory = components[..depth]
+					.iter()
+					.fold(mount_root.to_owned(), |p, c| p.join(c.as_ref()));
+				self.get(&directory)
+			})
+			.collect::<Vec<_>>();
+
+		(1..=components.len()).any(|end| {
+			let is_dir = is_dir || end < components.len();
+			let mut ignored = false;
+			// Ignore files deeper in the tree take precedence
+			for (start, ignore_file) in directories[..end].iter().enumerate() {
+				let Some(ignore_file) = ignore_file else {
+					continue;
+				};
+				let relative_path = components[start..end].join("/");
+				if let Some(matched) = ignore_file.matches(&relative_path, is_dir) {
+					ignored = matched;
+				}
+			}
+			ignored
+		})
+	}
+
+	fn get(&self, directory: &Path) -> Option<Arc<IgnoreFile>> {
+		if let Some(ignore_file) = self.cache.lock().unwrap().get(directory) {
+			return ignore_file.clone();
+		}
+		let ignore_file = fs::read_to_string(directory.join(IGNORE_FILE_NAME))
+			.ok()
+			.map(|text| Arc::new(IgnoreFile::parse(&text)));
+		self.cache
+			.lock()
+			.unwrap()
+			.insert(directory.to_owned(), ignore_file.clone());
+		ignore_file
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn matches_gitignore_patterns() {
+		let ignore_file = IgnoreFile::parse(
+			"# Comment\n\n*.wav\n!keep.wav\nDemos/\n/Live\nsessions/**/take?.flac\n[Bb]ootlegs\n",
+		);
+		let matches = |path, is_dir| ignore_file.matches(path, is_dir);
+
+		assert_eq!(matches("song.wav", false), Some(true));
+		assert_eq!(matches("Album/song.wav", false), Some(true));
+		assert_eq!(matches("Album/keep.wav", false), Some(false));
+		assert_eq!(matches("song.flac", false), None);
+
+		assert_eq!(matches("Demos", true), Some(true));
+		assert_eq!(matches("Album/Demos", true), Some(true));
+		assert_eq!(matches("Demos", false), None);
+
+		assert_eq!(matches("Live", true), Some(true));
+		assert_eq!(matches("Album/Live", true), None);
+
+		assert_eq!(matches("sessions/take1.flac", false), Some(true));
+		assert_eq!(matches("sessions/2019/june/take2.flac", false), Some(true));
+		assert_eq!(matches("sessions/take10.flac", false), None);
+
+		assert_eq!(matches("bootlegs", true), Some(true));
+		assert_eq!(matches("Bootlegs", true), Some(true));
+		assert_eq!(matches("Bootlegs/song.flac", false), None);
+	}
+}
@@ -2,9 +2,12 @@ use crossbeam_channel::Receiver;
 use diesel::prelude::*;
 use log::error;
 
+use std::sync::Arc;
+
+use super::Progress;
 use crate::db::{directories, songs, DB};
 
-const INDEX_BUILDING_INSERT_BUFFER_SIZE: usize = 1000; // Insertions in each transaction
+const INDEX_BUILDING_INSERT_BUFFER_SIZE: usize = 5000; // Insertions in each transaction
 
 #[derive(Debug, Insertable)]
 #[diesel(table_name = songs)]
@@ -24,6 +27,35 @@ pub struct Song {
 	pub composer: Option<String>,
 	pub genre: Option<String>,
 	pub label: Option<String>,
+	pub file_modified: Option<i64>,
+	pub file_size: Option<i64>,
+	pub has_embedded_artwork: bool,
+	pub cue_source: Option<String>,
+	pub cue_start: Option<i32>,
+	pub cue_end: Option<i32>,
+	pub disc_total: Option<i32>,
+	pub compilation: bool,
+	pub compilation_tag: bool,
+	pub conductor: Option<String>,
+	pub artist_sort: Option<String>,
+	pub album_artist_sort: Option<String>,
+	pub album_sort: Option<String>,
+	pub title_sort: Option<String>,
+	pub track_gain: Option<f32>,
+	pub track_peak: Option<f32>,
+	pub album_gain: Option<f32>,
+	pub album_peak: Option<f32>,
+	pub lyrics: Option<String>,
+	pub has_embedded_lyrics: bool,
+	pub musicbrainz_recording_id: Option<String>,
+	pub musicbrainz_release_id: Option<String>,
+	pub musicbrainz_artist_id: Option<String>,
+	pub musicbrainz_album_artist_id: Option<String>,
+	pub content_hash: Option<String>,
+	pub sample_rate: Option<i32>,
+	pub encoder_delay: Option<i32>,
+	pub encoder_padding: Option<i32>,
+	pub sample_count: Option<i64>,
 }
 
 #[derive(Debug, Insertable)]
@@ -38,6 +70,7 @@ pub struct Directory {
 	pub date_added: i32,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum Item {
 	Directory(Directory),
 	Song(Song),
@@ -48,10 +81,11 @@ pub struct Inserter {
 	new_directories: Vec<Directory>,
 	new_songs: Vec<Song>,
 	db: DB,
+	progress: Arc<Progress>,
 }
 
 impl Inserter {
-	pub fn new(db: DB, receiver: Receiver<Item>) -> Self {
+	pub fn new(db: DB, receiver: Receiver<Item>, progress: Arc<Progress>) -> Self {
 		let new_directories = Vec::with_capacity(INDEX_BUILDING_INSERT_BUFFER_SIZE);
 		let new_songs = Vec::with_capacity(INDEX_BUILDING_INSERT_BUFFER_SIZE);
 		Self {
@@ -59,6 +93,7 @@ impl Inserter {
 			new_directories,
 			new_songs,
 			db,
+			progress,
 		}
 	}
 
@@ -70,55 +105,46 @@ impl Inserter {
 
 	fn insert_item(&mut self, insert: Item) {
 		match insert {
-			Item::Directory(d) => {
-				self.new_directories.push(d);
-				if self.new_directories.len() >= INDEX_BUILDING_INSERT_BUFFER_SIZE {
-					self.flush_directories();
-				}
-			}
-			Item::Song(s) => {
-				self.new_songs.push(s);
-				if self.new_songs.len() >= INDEX_BUILDING_INSERT_BUFFER_SIZE {
-					self.flush_songs();
-				}
-			}
+			Item::Directory(d) => self.new_directories.push(d),
+			Item::Song(s) => self.new_songs.push(s),
 		};
-	}
-
-	fn flush_directories(&mut self) {
-		let res = self.db.connect().ok().and_then(|mut connection| {
-			diesel::insert_into(directories::table)
-				.values(&self.new_directories)
-				.execute(&mut *connection) // TODO https://github.com/diesel-rs/diesel/issues/1822
-				.ok()
-		});
-		if res.is_none() {
-			error!("Could not insert new directories in database");
+		if self.new_directories.len() + self.new_songs.len() >= INDEX_BUILDING_INSERT_BUFFER_SIZE {
+			self.flush();
 		}
-		self.new_directories.clear();
 	}
 
-	fn flush_songs(&mut self) {
+	// Directories and songs are written in a single transaction, so the database only syncs
+	// to disk once per batch
+	fn flush(&mut self) {
 		let res = self.db.connect().ok().and_then(|mut connection| {
-			diesel::insert_into(songs::table)
-				.values(&self.new_songs)
-				.execute(&mut *connection) // TODO https://github.com/diesel-rs/diesel/issues/1822
+			connection
+				.transaction::<_, diesel::result::Error, _>(|connection| {
+					diesel::insert_into(directories::table)
+						.values(&self.new_directories)
+						.execute(connection)?; // TODO https://github.com/diesel-rs/diesel/issues/1822
+					diesel::insert_into(songs::table)
+						.values(&self.new_songs)
+						.execute(connection)?;
+					Ok(())
+				})
 				.ok()
 		});
-		if res.is_none() {
-			error!("Could not insert new songs in database");
+		match res {
+			Some(_) => self.progress.on_songs_processed(self.new_songs.len()),
+			None => {
+				error!("Could not insert new directories and songs in database");
+				self.progress.on_error();
+			}
 		}
+		self.new_directories.clear();
 		self.new_songs.clear();
 	}
 }
 
 impl Drop for Inserter {
 	fn drop(&mut self) {
-		if !self.new_directories.is_empty() {
-			self.flush_directories();
-		}
-		if !self.new_songs.is_empty() {
-			self.flush_songs();
+		if !self.new_directories.is_empty() || !self.new_songs.is_empty() {
+			self.flush();
 		}
 	}
 }
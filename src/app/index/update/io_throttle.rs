@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const STREAMING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Spaces out file reads during index updates, so scanning a slow disk does not get in the way
+/// of songs being streamed from it.
+pub struct IoThrottle {
+	interval: Option<Duration>,
+	next_read: Mutex<Instant>,
+	// Set when streams are being served and updates should wait for them to end
+	streaming_until: Option<Arc<Mutex<Option<Instant>>>>,
+	shutting_down: Arc<AtomicBool>,
+}
+
+impl IoThrottle {
+	pub fn new(
+		max_files_per_second: Option<u32>,
+		streaming_until: Option<Arc<Mutex<Option<Instant>>>>,
+		shutting_down: Arc<AtomicBool>,
+	) -> Self {
+		Self {
+			interval: max_files_per_second.map(|n| Duration::from_secs(1) / n.max(1)),
+			next_read: Mutex::new(Instant::now()),
+			streaming_until,
+			shutting_down,
+		}
+	}
+
+	/// Blocks until the next file can be read. Returns early when the index is shutting down.
+	pub fn wait(&self) {
+		if let Some(streaming_until) = &self.streaming_until {
+			while !self.shutting_down.load(Ordering::SeqCst) {
+				let remaining = streaming_until
+					.lock()
+					.unwrap()
+					.and_then(|t| t.checked_duration_since(Instant::now()));
+				match remaining {
+					Some(remaining) => thread::sleep(remaining.min(STREAMING_POLL_INTERVAL)),
+					None => break,
+				}
+			}
+		}
+
+		if let Some(interval) = self.interval {
+			// Reads from all traverser threads share the same budget
+			let read_at = {
+				let mut next_read = self.next_read.lock().unwrap();
+				let read_at = (*next_read).max(Instant::now());
+				*next_read = read_at + interval;
+				read_at
+			};
+			if let Some(delay) = read_at.checked_duration_since(Instant::now()) {
+				thread::sleep(delay);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn limits_reads_per_second() {
+		let throttle = IoThrottle::new(Some(100), None, Arc::default());
+		let start = Instant::now();
+		for _ in 0..11 {
+			throttle.wait();
+		}
+		assert!(start.elapsed() >= Duration::from_millis(100));
+	}
+
+	#[test]
+	fn waits_for_streams_to_end() {
+		let streaming_until = Arc::new(Mutex::new(Some(
+			Instant::now() + Duration::from_millis(200),
+		)));
+		let throttle = IoThrottle::new(None, Some(streaming_until), Arc::default());
+		let start = Instant::now();
+		throttle.wait();
+		assert!(start.elapsed() >= Duration::from_millis(200));
+	}
+
+	#[test]
+	fn stops_waiting_on_shutdown() {
+		let streaming_until =
+			Arc::new(Mutex::new(Some(Instant::now() + Duration::from_secs(3600))));
+		let shutting_down = Arc::new(AtomicBool::new(true));
+		let throttle = IoThrottle::new(None, Some(streaming_until), shutting_down);
+		let start = Instant::now();
+		throttle.wait();
+		assert!(start.elapsed() < Duration::from_secs(1));
+	}
+}
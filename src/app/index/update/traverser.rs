@@ -1,20 +1,46 @@
 use crossbeam_channel::{self, Receiver, Sender};
-use log::{error, info};
+use log::{error, info, warn};
+use regex::Regex;
 use std::cmp::min;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use super::hasher;
+use super::ignore_file::IgnoreFiles;
+use super::io_throttle::IoThrottle;
+use super::Progress;
+use crate::app::index::cue::{self, CueSheet};
 use crate::app::index::metadata::{self, SongTags};
+use crate::app::vfs::Mount;
+use crate::utils;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+	pub modified: i64,
+	pub size: i64,
+}
 
 #[derive(Debug)]
 pub struct Song {
 	pub path: PathBuf,
 	pub metadata: SongTags,
+	pub file_info: Option<FileInfo>,
+	pub content_hash: Option<String>,
+}
+
+/// Song from a previous index update, whose tags and hash can be re-used if the file is
+/// unchanged.
+#[derive(Debug)]
+pub struct KnownSong {
+	pub metadata: SongTags,
+	pub file_info: FileInfo,
+	pub content_hash: Option<String>,
 }
 
 #[derive(Debug)]
@@ -22,33 +48,80 @@ pub struct Directory {
 	pub parent: Option<PathBuf>,
 	pub path: PathBuf,
 	pub songs: Vec<Song>,
+	pub cue_sheets: Vec<(PathBuf, CueSheet)>,
 	pub other_files: Vec<PathBuf>,
+	// Album art pattern of the mount, when it overrides the global one
+	pub album_art_pattern: Option<Regex>,
+	// Files from the matching directory of the mount's art overlay
+	pub overlay_files: Vec<PathBuf>,
 	pub created: i32,
 }
 
 pub struct Traverser {
 	directory_sender: Sender<Directory>,
+	known_songs: Arc<HashMap<PathBuf, KnownSong>>,
+	num_threads: Option<usize>,
+	ignore_patterns: Arc<Vec<Regex>>,
+	ignore_files: Arc<IgnoreFiles>,
+	io_throttle: Arc<IoThrottle>,
+	progress: Arc<Progress>,
+	shutting_down: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct Root {
+	path: PathBuf,
+	real_path: PathBuf,
+	follow_symlinks: bool,
+	album_art_pattern: Option<Regex>,
+	art_overlay: Option<PathBuf>,
 }
 
 #[derive(Debug)]
 struct WorkItem {
 	parent: Option<PathBuf>,
 	path: PathBuf,
+	root: Arc<Root>,
+	// Canonical paths of this directory and all its parents, to detect symlink cycles
+	ancestors: Vec<PathBuf>,
 }
 
 impl Traverser {
-	pub fn new(directory_sender: Sender<Directory>) -> Self {
-		Self { directory_sender }
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		directory_sender: Sender<Directory>,
+		known_songs: HashMap<PathBuf, KnownSong>,
+		num_threads: Option<usize>,
+		ignore_patterns: Vec<Regex>,
+		ignore_files: Arc<IgnoreFiles>,
+		io_throttle: Arc<IoThrottle>,
+		progress: Arc<Progress>,
+		shutting_down: Arc<AtomicBool>,
+	) -> Self {
+		Self {
+			directory_sender,
+			known_songs: Arc::new(known_songs),
+			num_threads,
+			ignore_patterns: Arc::new(ignore_patterns),
+			ignore_files,
+			io_throttle,
+			progress,
+			shutting_down,
+		}
 	}
 
-	pub fn traverse(&self, roots: Vec<PathBuf>) {
-		let num_pending_work_items = Arc::new(AtomicUsize::new(roots.len()));
+	pub fn traverse(&self, mounts: Vec<Mount>) {
+		let num_pending_work_items = Arc::new(AtomicUsize::new(mounts.len()));
 		let (work_item_sender, work_item_receiver) = crossbeam_channel::unbounded();
 
 		let key = "POLARIS_NUM_TRAVERSER_THREADS";
-		let num_threads = std::env::var_os(key)
-			.map(|v| v.to_string_lossy().to_string())
-			.and_then(|v| usize::from_str(&v).ok())
+		let num_threads = self
+			.num_threads
+			.or_else(|| {
+				std::env::var_os(key)
+					.map(|v| v.to_string_lossy().to_string())
+					.and_then(|v| usize::from_str(&v).ok())
+			})
 			.unwrap_or_else(|| min(num_cpus::get(), 4));
 		info!("Browsing collection using {} threads", num_threads);
 
@@ -58,21 +131,54 @@ impl Traverser {
 			let work_item_receiver = work_item_receiver.clone();
 			let directory_sender = self.directory_sender.clone();
 			let num_pending_work_items = num_pending_work_items.clone();
+			let known_songs = self.known_songs.clone();
+			let ignore_patterns = self.ignore_patterns.clone();
+			let ignore_files = self.ignore_files.clone();
+			let io_throttle = self.io_throttle.clone();
+			let progress = self.progress.clone();
+			let shutting_down = self.shutting_down.clone();
 			threads.push(thread::spawn(move || {
 				let worker = Worker {
 					work_item_sender,
 					work_item_receiver,
 					directory_sender,
 					num_pending_work_items,
+					known_songs,
+					ignore_patterns,
+					ignore_files,
+					io_throttle,
+					progress,
+					shutting_down,
 				};
 				worker.run();
 			}));
 		}
 
-		for root in roots {
+		for mount in mounts {
+			// Unreadable mounts are reported when their directory is processed
+			let real_path =
+				fs::canonicalize(&mount.source).unwrap_or_else(|_| mount.source.clone());
+			let album_art_pattern = mount.album_art_pattern.as_ref().and_then(|pattern| {
+				Regex::new(&format!("(?i){}", pattern))
+					.map_err(|e| {
+						error!(
+							"Invalid album art pattern for mount `{}`: {}",
+							mount.name, e
+						)
+					})
+					.ok()
+			});
 			let work_item = WorkItem {
 				parent: None,
-				path: root,
+				path: mount.source.clone(),
+				ancestors: vec![real_path.clone()],
+				root: Arc::new(Root {
+					path: mount.source,
+					real_path,
+					follow_symlinks: mount.follow_symlinks,
+					album_art_pattern,
+					art_overlay: mount.art_overlay,
+				}),
 			};
 			if let Err(e) = work_item_sender.send(work_item) {
 				error!("Error initializing traverser: {:#?}", e);
@@ -87,11 +193,23 @@ impl Traverser {
 	}
 }
 
+impl WorkItem {
+	fn real_path(&self) -> &Path {
+		self.ancestors.last().unwrap_or(&self.root.real_path)
+	}
+}
+
 struct Worker {
 	work_item_sender: Sender<WorkItem>,
 	work_item_receiver: Receiver<WorkItem>,
 	directory_sender: Sender<Directory>,
 	num_pending_work_items: Arc<AtomicUsize>,
+	known_songs: Arc<HashMap<PathBuf, KnownSong>>,
+	ignore_patterns: Arc<Vec<Regex>>,
+	ignore_files: Arc<IgnoreFiles>,
+	io_throttle: Arc<IoThrottle>,
+	progress: Arc<Progress>,
+	shutting_down: Arc<AtomicBool>,
 }
 
 impl Worker {
@@ -104,7 +222,8 @@ impl Worker {
 
 	fn find_work_item(&self) -> Option<WorkItem> {
 		loop {
-			if self.is_all_work_done() {
+			// Directories already sent along still get written to the index
+			if self.is_all_work_done() || self.shutting_down.load(Ordering::SeqCst) {
 				return None;
 			}
 			if let Ok(w) = self
@@ -131,6 +250,7 @@ impl Worker {
 
 	fn emit_directory(&self, directory: Directory) {
 		self.directory_sender.send(directory).unwrap();
+		self.progress.on_directory_processed();
 	}
 
 	pub fn process_work_item(&self, work_item: WorkItem) {
@@ -142,54 +262,191 @@ impl Worker {
 					work_item.path.display(),
 					e
 				);
+				self.progress.on_error();
 				return;
 			}
 		};
 
 		let mut sub_directories = Vec::new();
 		let mut songs = Vec::new();
+		let mut cue_sheets = Vec::new();
 		let mut other_files = Vec::new();
 
 		for entry in read_dir {
-			let path = match entry {
-				Ok(ref f) => f.path(),
+			// Directories are dropped rather than sent half read, which keeps their previous
+			// content in the index
+			if self.shutting_down.load(Ordering::SeqCst) {
+				return;
+			}
+			let entry = match entry {
+				Ok(entry) => entry,
 				Err(e) => {
 					error!(
 						"File read error within `{}`: {}",
 						work_item.path.display(),
 						e
 					);
+					self.progress.on_error();
 					break;
 				}
 			};
+			let path = entry.path();
+
+			let is_ignored = path
+				.file_name()
+				.map(|name| super::is_ignored(name, &self.ignore_patterns))
+				.unwrap_or(false)
+				|| self
+					.ignore_files
+					.is_ignored(&work_item.root.path, &path, path.is_dir());
+			if is_ignored {
+				continue;
+			}
+
+			let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+			let real_path = if is_symlink {
+				match Self::resolve_symlink(&work_item.root, &path) {
+					Some(real_path) => real_path,
+					None => continue,
+				}
+			} else {
+				work_item.real_path().join(entry.file_name())
+			};
 
 			if path.is_dir() {
-				sub_directories.push(path);
-			} else if let Some(metadata) = metadata::read(&path) {
-				songs.push(Song { path, metadata });
+				if work_item.ancestors.contains(&real_path) {
+					warn!("Skipping symlink cycle at `{}`", path.display());
+					continue;
+				}
+				sub_directories.push((path, real_path));
+			} else if let Some(song) = self.read_song(&path) {
+				songs.push(song);
+			} else if cue::is_cue_sheet(&path) {
+				match cue::read(&path) {
+					Ok(cue_sheet) => cue_sheets.push((path, cue_sheet)),
+					Err(e) => {
+						error!("Error while reading cue sheet: {}", e);
+						self.progress.on_error();
+					}
+				}
 			} else {
 				other_files.push(path);
 			}
 		}
 
 		let created = Self::get_date_created(&work_item.path).unwrap_or_default();
+		let overlay_files = Self::list_overlay_files(&work_item);
 
 		self.emit_directory(Directory {
 			path: work_item.path.to_owned(),
 			parent: work_item.parent,
 			songs,
+			cue_sheets,
 			other_files,
+			album_art_pattern: work_item.root.album_art_pattern.clone(),
+			overlay_files,
 			created,
 		});
 
-		for sub_directory in sub_directories.into_iter() {
+		for (sub_directory, real_path) in sub_directories.into_iter() {
+			let mut ancestors = work_item.ancestors.clone();
+			ancestors.push(real_path);
 			self.queue_work(WorkItem {
 				parent: Some(work_item.path.clone()),
 				path: sub_directory,
+				root: work_item.root.clone(),
+				ancestors,
 			});
 		}
 	}
 
+	fn list_overlay_files(work_item: &WorkItem) -> Vec<PathBuf> {
+		let Some(art_overlay) = &work_item.root.art_overlay else {
+			return Vec::new();
+		};
+		let Ok(relative_path) = work_item.path.strip_prefix(&work_item.root.path) else {
+			return Vec::new();
+		};
+		match fs::read_dir(art_overlay.join(relative_path)) {
+			Ok(read_dir) => read_dir
+				.filter_map(|entry| entry.ok())
+				.map(|entry| entry.path())
+				.filter(|path| path.is_file())
+				.collect(),
+			Err(_) => Vec::new(),
+		}
+	}
+
+	// Symlinks are only followed when their mount allows it, and never outside of the mount
+	fn resolve_symlink(root: &Root, path: &Path) -> Option<PathBuf> {
+		if !root.follow_symlinks {
+			return None;
+		}
+		let real_path = fs::canonicalize(path).ok()?;
+		if !real_path.starts_with(&root.real_path) {
+			warn!(
+				"Skipping symlink `{}` which points outside of its mount",
+				path.display()
+			);
+			return None;
+		}
+		Some(real_path)
+	}
+
+	fn read_song(&self, path: &Path) -> Option<Song> {
+		if let Some(known_song) = self.known_songs.get(path) {
+			let file_info = Self::get_file_info(path);
+			if file_info == Some(known_song.file_info) {
+				return Some(Song {
+					path: path.to_owned(),
+					metadata: known_song.metadata.clone(),
+					file_info,
+					content_hash: known_song.content_hash.clone(),
+				});
+			}
+		}
+
+		utils::get_audio_format(path)?;
+		self.io_throttle.wait();
+		match metadata::try_read(path) {
+			Ok(metadata) => metadata.map(|metadata| Song {
+				path: path.to_owned(),
+				metadata,
+				file_info: Self::get_file_info(path),
+				content_hash: self.hash_audio(path),
+			}),
+			Err(e) => {
+				error!("Error while reading file metadata for '{:?}': {}", path, e);
+				self.progress.on_error();
+				None
+			}
+		}
+	}
+
+	fn hash_audio(&self, path: &Path) -> Option<String> {
+		match hasher::hash_audio(path) {
+			Ok(hash) => Some(hash),
+			Err(e) => {
+				error!("Error while hashing '{:?}': {}", path, e);
+				self.progress.on_error();
+				None
+			}
+		}
+	}
+
+	fn get_file_info(path: &Path) -> Option<FileInfo> {
+		let metadata = fs::metadata(path).ok()?;
+		let modified = metadata
+			.modified()
+			.ok()?
+			.duration_since(std::time::UNIX_EPOCH)
+			.ok()?;
+		Some(FileInfo {
+			modified: modified.as_millis() as i64,
+			size: metadata.len() as i64,
+		})
+	}
+
 	fn get_date_created(path: &Path) -> Option<i32> {
 		if let Ok(t) = fs::metadata(path).and_then(|m| m.created().or_else(|_| m.modified())) {
 			t.duration_since(std::time::UNIX_EPOCH)
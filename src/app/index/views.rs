@@ -0,0 +1,218 @@
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types;
+use std::collections::HashMap;
+
+use super::*;
+use crate::db::songs;
+
+const ARTISTS_VIEW: &str = "Artists";
+const COMPOSERS_VIEW: &str = "Composers";
+const GENRES_VIEW: &str = "Genres";
+const YEARS_VIEW: &str = "Years";
+
+// Tag values may contain slashes, which would otherwise be read as path separators
+const SLASH_SUBSTITUTE: &str = "\u{2215}";
+
+// Separates the values of multi-valued tags, like ID3v2.4 genres
+const TAG_VALUE_SEPARATOR: char = '\0';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Level {
+	Artist,
+	Album,
+	Composer,
+	Genre,
+	Year,
+}
+
+fn get_levels(view: &str) -> Option<&'static [Level]> {
+	match view {
+		ARTISTS_VIEW => Some(&[Level::Artist, Level::Album]),
+		COMPOSERS_VIEW => Some(&[Level::Composer, Level::Album]),
+		GENRES_VIEW => Some(&[Level::Genre, Level::Artist, Level::Album]),
+		YEARS_VIEW => Some(&[Level::Year, Level::Album]),
+		_ => None,
+	}
+}
+
+fn to_component(value: &str) -> String {
+	value.replace('/', SLASH_SUBSTITUTE)
+}
+
+fn from_component(component: &str) -> String {
+	component.replace(SLASH_SUBSTITUTE, "/")
+}
+
+// Songs are grouped under their album artist when they have one, compilations under `Various Artists`
+fn get_artist(song: &Song) -> Option<String> {
+	match &song.album_artist {
+		Some(album_artist) => Some(album_artist.clone()),
+		None if song.compilation => Some(VARIOUS_ARTISTS.to_owned()),
+		None => song.artist.clone(),
+	}
+}
+
+// Sort names like `Beatles, The` are only known for tags holding a single value
+fn get_sort_name(song: &Song, level: Level, value: &str) -> Option<String> {
+	let (tag, sort_name) = match level {
+		Level::Artist => match &song.album_artist {
+			Some(album_artist) => (Some(album_artist), &song.album_artist_sort),
+			None if song.compilation => return None,
+			None => (song.artist.as_ref(), &song.artist_sort),
+		},
+		Level::Album => (song.album.as_ref(), &song.album_sort),
+		Level::Composer | Level::Genre | Level::Year => return None,
+	};
+	if tag.map(String::as_str) == Some(value) {
+		sort_name.clone()
+	} else {
+		None
+	}
+}
+
+fn get_values(song: &Song, level: Level) -> Vec<String> {
+	let tag = match level {
+		Level::Artist => get_artist(song),
+		Level::Album => song.album.clone(),
+		Level::Composer => song.composer.clone(),
+		Level::Genre => song.genre.clone(),
+		Level::Year => song.year.map(|y| y.to_string()),
+	};
+	tag.iter()
+		.flat_map(|t| t.split(TAG_VALUE_SEPARATOR))
+		.filter(|v| !v.is_empty())
+		.map(|v| v.to_owned())
+		.collect()
+}
+
+impl Index {
+	// Browses virtual hierarchies built from song tags, as opposed to the directory structure.
+	// Paths look like `Artists/Khemmis/Hunted`, `Composers/Beethoven`, `Genres/Metal/Khemmis` or `Years/2016`.
+	pub fn browse_view(
+		&self,
+		username: &str,
+		view_path: &str,
+	) -> Result<Vec<CollectionFile>, QueryError> {
+		let path_components: Vec<&str> = view_path.split('/').filter(|c| !c.is_empty()).collect();
+		let parent_path = path_components.join("/");
+		let Some((view, components)) = path_components.split_first() else {
+			return Ok([ARTISTS_VIEW, COMPOSERS_VIEW, GENRES_VIEW, YEARS_VIEW]
+				.into_iter()
+				.map(|v| CollectionFile::Directory(Directory::view(v.to_owned())))
+				.collect());
+		};
+
+		let not_found = || QueryError::ViewNotFound(view_path.to_owned());
+		let levels = get_levels(view).ok_or_else(not_found)?;
+		if components.len() > levels.len() {
+			return Err(not_found());
+		}
+
+		let filters: Vec<(Level, String)> = levels
+			.iter()
+			.zip(components)
+			.map(|(level, component)| (*level, from_component(component)))
+			.collect();
+		let mut songs = self.get_view_songs(username, &filters)?;
+
+		let Some(level) = levels.get(components.len()) else {
+			let collator = self.get_collator();
+			songs.sort_by_cached_key(|s| {
+				let title = s.title_sort.as_ref().or(s.title.as_ref());
+				(
+					s.disc_number,
+					s.track_number,
+					title.map(|t| collator.key(t)),
+				)
+			});
+			return Ok(songs.into_iter().map(CollectionFile::Song).collect());
+		};
+
+		let mut children: HashMap<String, Directory> = HashMap::new();
+		let mut sort_names: HashMap<String, String> = HashMap::new();
+		for song in songs {
+			for value in get_values(&song, *level) {
+				if let Some(sort_name) = get_sort_name(&song, *level, &value) {
+					sort_names.entry(value.clone()).or_insert(sort_name);
+				}
+				children.entry(value.clone()).or_insert_with(|| {
+					let path = format!("{}/{}", parent_path, to_component(&value));
+					let mut directory = Directory::view(path);
+					match level {
+						Level::Artist => directory.artist = Some(value),
+						Level::Album => {
+							directory.artist = get_artist(&song);
+							directory.album = Some(value);
+							directory.year = song.year;
+							directory.artwork = song.artwork.clone();
+						}
+						Level::Composer | Level::Genre => (),
+						Level::Year => directory.year = song.year,
+					}
+					directory
+				});
+			}
+		}
+
+		let collator = self.get_collator();
+		let mut children: Vec<(collation::CollationKey, Directory)> = children
+			.into_iter()
+			.map(|(value, directory)| {
+				let sort_key = match level {
+					Level::Year => collator.key(&format!("{:0>8}", value)),
+					_ => collator.key(sort_names.get(&value).unwrap_or(&value)),
+				};
+				(sort_key, directory)
+			})
+			.collect();
+		children.sort_by(|(a, x), (b, y)| a.cmp(b).then_with(|| x.path.cmp(&y.path)));
+
+		Ok(children
+			.into_iter()
+			.map(|(_, directory)| CollectionFile::Directory(directory))
+			.collect())
+	}
+
+	fn get_view_songs(
+		&self,
+		username: &str,
+		filters: &[(Level, String)],
+	) -> Result<Vec<Song>, QueryError> {
+		let mut query = songs::table.into_boxed();
+		let artist_filter = format!(
+			"instr(COALESCE(album_artist, CASE WHEN compilation THEN '{}' ELSE artist END), ",
+			VARIOUS_ARTISTS
+		);
+
+		// Text filters are narrowed down in the query, then matched exactly against each tag value.
+		// LIKE would stop at the first value of multi-valued tags, instr() reads all of them.
+		for (level, value) in filters {
+			let (prefix, suffix) = match level {
+				Level::Artist => (artist_filter.as_str(), ") > 0"),
+				Level::Album => ("instr(album, ", ") > 0"),
+				Level::Composer => ("instr(composer, ", ") > 0"),
+				Level::Genre => ("instr(genre, ", ") > 0"),
+				Level::Year => ("year = ", ""),
+			};
+			query = query.filter(
+				sql::<sql_types::Bool>(prefix)
+					.bind::<sql_types::Text, _>(value)
+					.sql(suffix),
+			);
+		}
+
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let real_songs: Vec<Song> = query.order(songs::path).load(&mut connection)?;
+		Ok(real_songs
+			.into_iter()
+			.filter(|s| {
+				filters
+					.iter()
+					.all(|(level, value)| get_values(s, *level).contains(value))
+			})
+			.filter_map(|s| s.virtualize(&vfs))
+			.collect())
+	}
+}
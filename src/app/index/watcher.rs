@@ -0,0 +1,106 @@
+use log::{error, info};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::app::index::Index;
+use crate::app::vfs::Mount;
+
+// How often to check for changes to the list of watched mounts
+const WATCHED_MOUNTS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// Quiet period after a file change before the index is updated
+const FILE_CHANGE_DEBOUNCE_DURATION: Duration = Duration::from_secs(2);
+// Longest delay before the index is updated when files keep changing
+const FILE_CHANGE_MAX_DELAY: Duration = Duration::from_secs(30);
+
+type Event = notify::Result<notify::Event>;
+
+impl Index {
+	pub fn begin_watching_mounts(&self) {
+		let watcher_index = self.clone();
+		std::thread::spawn(move || {
+			watcher_index.watch_mounts();
+		});
+	}
+
+	fn watch_mounts(&self) {
+		let (event_sender, event_receiver) = mpsc::channel();
+		let mut watched_sources = Vec::new();
+		let mut _watcher = None;
+		let mut last_mounts_check: Option<Instant> = None;
+		let mut pending_change: Option<Instant> = None;
+
+		loop {
+			if last_mounts_check.is_none_or(|t| t.elapsed() >= WATCHED_MOUNTS_POLL_INTERVAL) {
+				last_mounts_check = Some(Instant::now());
+				let sources = self.get_watched_sources();
+				if sources != watched_sources {
+					_watcher = Self::create_watcher(&sources, event_sender.clone());
+					watched_sources = sources;
+				}
+			}
+
+			if Self::wait_for_file_change(&event_receiver) {
+				let first_change = *pending_change.get_or_insert_with(Instant::now);
+				if first_change.elapsed() < FILE_CHANGE_MAX_DELAY {
+					continue;
+				}
+			}
+
+			if pending_change.take().is_some() {
+				info!("Updating index after changes to watched mounts");
+				self.trigger_reindex();
+			}
+		}
+	}
+
+	/// Returns whether a file change happened before the debounce duration elapsed.
+	fn wait_for_file_change(event_receiver: &Receiver<Event>) -> bool {
+		match event_receiver.recv_timeout(FILE_CHANGE_DEBOUNCE_DURATION) {
+			Ok(Ok(event)) => !matches!(event.kind, EventKind::Access(_)),
+			Ok(Err(e)) => {
+				error!("Error while watching mounts: {}", e);
+				false
+			}
+			Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => false,
+		}
+	}
+
+	fn get_watched_sources(&self) -> Vec<PathBuf> {
+		match self.vfs_manager.mount_dirs() {
+			Ok(mount_dirs) => mount_dirs
+				.into_iter()
+				.filter(|m| m.watch)
+				.map(|m| Mount::from(m).source)
+				.collect(),
+			Err(e) => {
+				error!("Could not retrieve mounts to watch: {}", e);
+				Vec::new()
+			}
+		}
+	}
+
+	fn create_watcher(
+		sources: &[PathBuf],
+		event_sender: Sender<Event>,
+	) -> Option<RecommendedWatcher> {
+		if sources.is_empty() {
+			return None;
+		}
+		let mut watcher = match notify::recommended_watcher(event_sender) {
+			Ok(w) => w,
+			Err(e) => {
+				error!("Could not create file watcher: {}", e);
+				return None;
+			}
+		};
+		for source in sources {
+			match watcher.watch(source, RecursiveMode::Recursive) {
+				Ok(()) => info!("Watching `{}` for changes", source.display()),
+				Err(e) => error!("Could not watch `{}` for changes: {}", source.display(), e),
+			}
+		}
+		Some(watcher)
+	}
+}
@@ -0,0 +1,450 @@
+use log::error;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::app::{events, queue, transcode, vfs};
+
+pub const MAX_VOLUME: u8 = 100;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[cfg(target_os = "macos")]
+const DEFAULT_OUTPUT: (&str, &str) = ("audiotoolbox", "0");
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_OUTPUT: (&str, &str) = ("alsa", "default");
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Jukebox is not enabled")]
+	NotEnabled,
+	#[error("Play queue is empty")]
+	EmptyQueue,
+	#[error("Volume must be between 0 and {MAX_VOLUME}, not {0}")]
+	InvalidVolume(u8),
+	#[error("Could not run ffmpeg from `{0}`: `{1}`")]
+	FfmpegNotFound(PathBuf, std::io::Error),
+	#[error(transparent)]
+	Queue(#[from] queue::Error),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+/// Plays play queues on the sound card of the server when present in the config file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	// ffmpeg output format, for example `alsa`, `pulse` or `audiotoolbox`
+	#[serde(default)]
+	pub output: Option<String>,
+	// Sound card to play on, as named by the output format
+	#[serde(default)]
+	pub device: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+	pub username: Option<String>, // Owner of the queue being played
+	pub is_playing: bool,
+	pub current_track: Option<usize>,
+	pub position_seconds: u32,
+	pub volume: u8,
+}
+
+struct Playback {
+	username: String,
+	process: Child,
+	track: usize,
+	start_position: u32, // seconds
+	started: Instant,
+}
+
+impl Playback {
+	fn position(&self) -> u32 {
+		self.start_position + self.started.elapsed().as_secs() as u32
+	}
+}
+
+struct Player {
+	username: Option<String>,
+	playback: Option<Playback>,
+	volume: u8,
+	generation: usize, // Lets the thread watching a playback know it was replaced
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	config: Option<Config>,
+	ffmpeg_path: PathBuf,
+	queue_manager: queue::Manager,
+	vfs_manager: vfs::Manager,
+	events_manager: events::Manager,
+	player: Arc<Mutex<Player>>,
+}
+
+impl Manager {
+	pub fn new(
+		config: Option<Config>,
+		queue_manager: queue::Manager,
+		vfs_manager: vfs::Manager,
+		events_manager: events::Manager,
+	) -> Self {
+		Self {
+			config,
+			ffmpeg_path: transcode::get_ffmpeg_path(),
+			queue_manager,
+			vfs_manager,
+			events_manager,
+			player: Arc::new(Mutex::new(Player {
+				username: None,
+				playback: None,
+				volume: MAX_VOLUME,
+				generation: 0,
+			})),
+		}
+	}
+
+	pub fn get_status(&self) -> Result<Status, Error> {
+		self.check_enabled()?;
+		let player = self.player.lock().unwrap();
+		Ok(Status {
+			username: player.username.clone(),
+			is_playing: player.playback.is_some(),
+			current_track: player.playback.as_ref().map(|p| p.track),
+			position_seconds: player.playback.as_ref().map_or(0, |p| p.position()),
+			volume: player.volume,
+		})
+	}
+
+	/// Plays the queue of a user from its current track, taking over from any other queue.
+	pub fn play(&self, username: &str) -> Result<(), Error> {
+		self.check_enabled()?;
+		let mut player = self.player.lock().unwrap();
+		self.stop(&mut player)?;
+		let queue = self.queue_manager.get_queue(username)?;
+		if queue.songs.is_empty() {
+			return Err(Error::EmptyQueue);
+		}
+		let track = queue.current_track.unwrap_or(0);
+		player.username = Some(username.to_owned());
+		self.start(&mut player, username, &queue, track, queue.position_seconds)
+	}
+
+	pub fn pause(&self) -> Result<(), Error> {
+		self.check_enabled()?;
+		let mut player = self.player.lock().unwrap();
+		self.stop(&mut player)
+	}
+
+	pub fn next(&self, username: &str) -> Result<(), Error> {
+		self.skip(username, |t| Some(t + 1))
+	}
+
+	pub fn previous(&self, username: &str) -> Result<(), Error> {
+		self.skip(username, |t| Some(t.saturating_sub(1)))
+	}
+
+	pub fn set_volume(&self, volume: u8) -> Result<(), Error> {
+		self.check_enabled()?;
+		if volume > MAX_VOLUME {
+			return Err(Error::InvalidVolume(volume));
+		}
+		let mut player = self.player.lock().unwrap();
+		player.volume = volume;
+		// ffmpeg cannot change volume on the fly, so playback resumes where it was
+		if let Some(username) = player.playback.as_ref().map(|p| p.username.clone()) {
+			self.stop(&mut player)?;
+			let queue = self.queue_manager.get_queue(&username)?;
+			if let Some(track) = queue.current_track {
+				self.start(
+					&mut player,
+					&username,
+					&queue,
+					track,
+					queue.position_seconds,
+				)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Stops playback and remembers where it was, so it can resume after a restart.
+	pub fn shutdown(&self) {
+		let mut player = self.player.lock().unwrap();
+		if let Err(e) = self.stop(&mut player) {
+			error!("Could not save jukebox position: {}", e);
+		}
+	}
+
+	fn check_enabled(&self) -> Result<(), Error> {
+		match self.config {
+			Some(_) => Ok(()),
+			None => Err(Error::NotEnabled),
+		}
+	}
+
+	// Skipping a paused jukebox moves the current track without starting playback
+	fn skip<F>(&self, username: &str, get_track: F) -> Result<(), Error>
+	where
+		F: FnOnce(usize) -> Option<usize>,
+	{
+		self.check_enabled()?;
+		let mut player = self.player.lock().unwrap();
+		let was_playing = player.playback.is_some();
+		let username = player
+			.username
+			.clone()
+			.unwrap_or_else(|| username.to_owned());
+		self.stop(&mut player)?;
+		player.username = Some(username.clone());
+
+		let queue = self.queue_manager.get_queue(&username)?;
+		let track = queue
+			.current_track
+			.and_then(get_track)
+			.or((!queue.songs.is_empty()).then_some(0))
+			.filter(|t| *t < queue.songs.len());
+		self.queue_manager.set_current_track(&username, track, 0)?;
+		self.events_manager.publish(events::Event::QueueChanged {
+			username: username.clone(),
+		});
+		match track {
+			Some(track) if was_playing => self.start(&mut player, &username, &queue, track, 0),
+			_ => Ok(()),
+		}
+	}
+
+	fn start(
+		&self,
+		player: &mut Player,
+		username: &str,
+		queue: &queue::PlayQueue,
+		track: usize,
+		position: u32,
+	) -> Result<(), Error> {
+		let Some(song) = queue.songs.get(track) else {
+			return Ok(());
+		};
+		let (output, device) = self.output();
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+
+		// Cue tracks are segments of a larger file
+		let (audio_path, start, end) = match &song.cue_source {
+			Some(source) => (
+				PathBuf::from(source),
+				song.cue_start.unwrap_or(0).max(0) as u32,
+				song.cue_end.map(|e| e.max(0) as u32),
+			),
+			None => (vfs.virtual_to_real(Path::new(&song.path))?, 0, None),
+		};
+		let start = start + position * 1000;
+
+		let mut command = Command::new(&self.ffmpeg_path);
+		command
+			.arg("-nostdin")
+			.args(["-loglevel", "error"])
+			.args(["-ss", &transcode::format_seconds(start)])
+			.arg("-i")
+			.arg(&audio_path)
+			.arg("-vn");
+		if let Some(end) = end {
+			command.args(["-t", &transcode::format_seconds(end.saturating_sub(start))]);
+		}
+		let volume = player.volume as f32 / MAX_VOLUME as f32;
+		let process = command
+			.args(["-af", &format!("volume={:.2}", volume)])
+			.args(["-f", &output, &device])
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn()
+			.map_err(|e| Error::FfmpegNotFound(self.ffmpeg_path.clone(), e))?;
+
+		self.queue_manager
+			.set_current_track(username, Some(track), position)?;
+		player.generation += 1;
+		player.playback = Some(Playback {
+			username: username.to_owned(),
+			process,
+			track,
+			start_position: position,
+			started: Instant::now(),
+		});
+
+		let manager = self.clone();
+		let generation = player.generation;
+		std::thread::spawn(move || manager.watch(generation));
+		Ok(())
+	}
+
+	// Kills ffmpeg and saves the position reached in the queue
+	fn stop(&self, player: &mut Player) -> Result<(), Error> {
+		if let Some(mut playback) = player.playback.take() {
+			playback.process.kill().ok();
+			playback.process.wait().ok();
+			self.queue_manager.set_current_track(
+				&playback.username,
+				Some(playback.track),
+				playback.position(),
+			)?;
+		}
+		Ok(())
+	}
+
+	// Moves on to the next song of the queue once ffmpeg is done with the current one
+	fn watch(&self, generation: usize) {
+		loop {
+			std::thread::sleep(POLL_INTERVAL);
+			let mut player = self.player.lock().unwrap();
+			if player.generation != generation {
+				return;
+			}
+			let Some(playback) = player.playback.as_mut() else {
+				return;
+			};
+			if matches!(playback.process.try_wait(), Ok(None)) {
+				continue;
+			}
+			let Some(playback) = player.playback.take() else {
+				return;
+			};
+			if let Err(e) = self.advance(&mut player, &playback.username, playback.track) {
+				error!("Jukebox could not play the next song: {}", e);
+			}
+			return;
+		}
+	}
+
+	fn advance(&self, player: &mut Player, username: &str, track: usize) -> Result<(), Error> {
+		let queue = self.queue_manager.get_queue(username)?;
+		let next = Some(track + 1).filter(|t| *t < queue.songs.len());
+		self.events_manager.publish(events::Event::QueueChanged {
+			username: username.to_owned(),
+		});
+		match next {
+			Some(next) => self.start(player, username, &queue, next, 0),
+			None => {
+				self.queue_manager.set_current_track(username, None, 0)?;
+				Ok(())
+			}
+		}
+	}
+
+	fn output(&self) -> (String, String) {
+		let config = self.config.clone().unwrap_or_default();
+		(
+			config.output.unwrap_or_else(|| DEFAULT_OUTPUT.0.to_owned()),
+			config.device.unwrap_or_else(|| DEFAULT_OUTPUT.1.to_owned()),
+		)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn build_manager(ctx: &test::Context, ffmpeg_path: &Path) -> Manager {
+		Manager {
+			ffmpeg_path: ffmpeg_path.to_owned(),
+			..Manager::new(
+				Some(Config::default()),
+				ctx.queue_manager.clone(),
+				ctx.vfs_manager.clone(),
+				ctx.events_manager.clone(),
+			)
+		}
+	}
+
+	fn build_context(test_name: String) -> test::Context {
+		let ctx = test::ContextBuilder::new(test_name)
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+		let songs = ["01 - Above The Water.mp3", "02 - Candlelight.mp3"]
+			.iter()
+			.map(|f| [TEST_MOUNT_NAME, "Khemmis", "Hunted", f].iter().collect())
+			.collect::<Vec<PathBuf>>();
+		ctx.queue_manager.append(TEST_USER, &songs).unwrap();
+		ctx
+	}
+
+	#[test]
+	fn requires_config() {
+		let ctx = build_context(test_name!());
+		let manager = Manager::new(
+			None,
+			ctx.queue_manager.clone(),
+			ctx.vfs_manager.clone(),
+			ctx.events_manager.clone(),
+		);
+		assert!(matches!(manager.play(TEST_USER), Err(Error::NotEnabled)));
+		assert!(matches!(manager.get_status(), Err(Error::NotEnabled)));
+	}
+
+	#[test]
+	fn rejects_empty_queue_and_invalid_volume() {
+		let ctx = build_context(test_name!());
+		ctx.queue_manager.clear(TEST_USER).unwrap();
+		let manager = build_manager(&ctx, Path::new("ffmpeg"));
+		assert!(matches!(manager.play(TEST_USER), Err(Error::EmptyQueue)));
+		assert!(matches!(
+			manager.set_volume(101),
+			Err(Error::InvalidVolume(101))
+		));
+	}
+
+	#[test]
+	fn reports_missing_ffmpeg() {
+		let ctx = build_context(test_name!());
+		let manager = build_manager(&ctx, &ctx.test_directory.join("not-ffmpeg"));
+		assert!(matches!(
+			manager.play(TEST_USER),
+			Err(Error::FfmpegNotFound(_, _))
+		));
+		assert!(!manager.get_status().unwrap().is_playing);
+	}
+
+	#[test]
+	fn skipping_while_paused_moves_current_track() {
+		let ctx = build_context(test_name!());
+		let manager = build_manager(&ctx, Path::new("ffmpeg"));
+		manager.next(TEST_USER).unwrap();
+		let queue = ctx.queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queue.current_track, Some(0));
+		manager.next(TEST_USER).unwrap();
+		let queue = ctx.queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queue.current_track, Some(1));
+		manager.previous(TEST_USER).unwrap();
+		let queue = ctx.queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queue.current_track, Some(0));
+		assert!(!manager.get_status().unwrap().is_playing);
+	}
+
+	// `true` stands in for ffmpeg and finishes every song right away
+	#[cfg(unix)]
+	#[test]
+	fn plays_through_queue() {
+		let ctx = build_context(test_name!());
+		let manager = build_manager(&ctx, Path::new("true"));
+		manager.play(TEST_USER).unwrap();
+		assert_eq!(
+			manager.get_status().unwrap().username.as_deref(),
+			Some(TEST_USER)
+		);
+
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while manager.get_status().unwrap().is_playing {
+			assert!(Instant::now() < deadline);
+			std::thread::sleep(POLL_INTERVAL);
+		}
+		let queue = ctx.queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queue.current_track, None);
+	}
+}
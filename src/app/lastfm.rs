@@ -63,7 +63,7 @@ impl Manager {
 
 	pub fn scrobble(&self, username: &str, track: &Path) -> Result<(), Error> {
 		let mut scrobbler = Scrobbler::new(LASTFM_API_KEY, LASTFM_API_SECRET);
-		let scrobble = self.scrobble_from_path(track)?;
+		let scrobble = self.scrobble_from_path(username, track)?;
 		let auth_token = self.user_manager.get_lastfm_session_key(username)?;
 		scrobbler.authenticate_with_session_key(&auth_token);
 		scrobbler.scrobble(&scrobble).map_err(Error::Scrobble)?;
@@ -72,7 +72,7 @@ impl Manager {
 
 	pub fn now_playing(&self, username: &str, track: &Path) -> Result<(), Error> {
 		let mut scrobbler = Scrobbler::new(LASTFM_API_KEY, LASTFM_API_SECRET);
-		let scrobble = self.scrobble_from_path(track)?;
+		let scrobble = self.scrobble_from_path(username, track)?;
 		let auth_token = self.user_manager.get_lastfm_session_key(username)?;
 		scrobbler.authenticate_with_session_key(&auth_token);
 		scrobbler
@@ -81,8 +81,8 @@ impl Manager {
 		Ok(())
 	}
 
-	fn scrobble_from_path(&self, track: &Path) -> Result<Scrobble, Error> {
-		let song = self.index.get_song(track)?;
+	fn scrobble_from_path(&self, username: &str, track: &Path) -> Result<Scrobble, Error> {
+		let song = self.index.get_song(username, track)?;
 		Ok(Scrobble::new(
 			song.artist.as_deref().unwrap_or(""),
 			song.title.as_deref().unwrap_or(""),
@@ -0,0 +1,172 @@
+use ldap3::{dn_escape, LdapConn, LdapConnSettings, Scope};
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+const USERNAME_PLACEHOLDER: &str = "{username}";
+
+// Result code sent by directory servers when a bind is attempted with a wrong password
+const INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Invalid LDAP credentials")]
+	InvalidCredentials,
+	#[error("LDAP bind DN template must contain `{USERNAME_PLACEHOLDER}`")]
+	MissingUsernamePlaceholder,
+	#[error("LDAP authentication is not configured")]
+	NotConfigured,
+	#[error("User does not match the LDAP group filter")]
+	NotInGroup,
+	#[error(transparent)]
+	Ldap(#[from] ldap3::LdapError),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	// For example `ldaps://ldap.example.com:636`
+	pub url: String,
+	// For example `uid={username},ou=people,dc=example,dc=com`
+	pub bind_dn_template: String,
+	// Search filter the user's own entry must match, for example
+	// `(memberOf=cn=polaris,ou=groups,dc=example,dc=com)`
+	#[serde(default)]
+	pub group_filter: Option<String>,
+}
+
+impl Config {
+	fn user_dn(&self, username: &str) -> String {
+		self.bind_dn_template
+			.replace(USERNAME_PLACEHOLDER, &dn_escape(username))
+	}
+}
+
+#[derive(Clone, Default)]
+pub struct Manager {
+	config: Arc<RwLock<Option<Config>>>,
+}
+
+impl Manager {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn set_config(&self, config: &Config) -> Result<(), Error> {
+		if !config.bind_dn_template.contains(USERNAME_PLACEHOLDER) {
+			return Err(Error::MissingUsernamePlaceholder);
+		}
+		*self.config.write().unwrap() = Some(config.clone());
+		Ok(())
+	}
+
+	pub fn clear_config(&self) {
+		*self.config.write().unwrap() = None;
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.config.read().unwrap().is_some()
+	}
+
+	// Binds to the directory as the user, which only succeeds with their password
+	pub fn verify(&self, username: &str, password: &str) -> Result<(), Error> {
+		let config = self.config.read().unwrap().clone();
+		let config = config.ok_or(Error::NotConfigured)?;
+
+		// Binds without a password are anonymous binds, which most servers accept
+		if username.is_empty() || password.is_empty() {
+			return Err(Error::InvalidCredentials);
+		}
+
+		let user_dn = config.user_dn(username);
+		let settings = LdapConnSettings::new().set_conn_timeout(CONNECTION_TIMEOUT);
+		let mut connection = LdapConn::with_settings(settings, &config.url)?;
+		let result = verify_user(&mut connection, &config, &user_dn, password);
+		connection.unbind().ok();
+		result
+	}
+}
+
+fn verify_user(
+	connection: &mut LdapConn,
+	config: &Config,
+	user_dn: &str,
+	password: &str,
+) -> Result<(), Error> {
+	let bind_result = connection.simple_bind(user_dn, password)?;
+	if bind_result.rc == INVALID_CREDENTIALS {
+		return Err(Error::InvalidCredentials);
+	}
+	bind_result.success()?;
+
+	if let Some(group_filter) = &config.group_filter {
+		let (entries, _) = connection
+			.search(user_dn, Scope::Base, group_filter, vec!["1.1"])?
+			.success()?;
+		if entries.is_empty() {
+			return Err(Error::NotInGroup);
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn test_config() -> Config {
+		Config {
+			url: "ldap://127.0.0.1:1".to_owned(),
+			bind_dn_template: "uid={username},ou=people,dc=example,dc=com".to_owned(),
+			group_filter: None,
+		}
+	}
+
+	#[test]
+	fn user_dn_escapes_username() {
+		let config = test_config();
+		assert_eq!(
+			config.user_dn("walter"),
+			"uid=walter,ou=people,dc=example,dc=com"
+		);
+		assert_eq!(
+			config.user_dn("walter,ou=admins"),
+			"uid=walter\\2cou\\3dadmins,ou=people,dc=example,dc=com"
+		);
+	}
+
+	#[test]
+	fn rejects_template_without_username() {
+		let manager = Manager::new();
+		let config = Config {
+			bind_dn_template: "cn=admin,dc=example,dc=com".to_owned(),
+			..test_config()
+		};
+		assert!(matches!(
+			manager.set_config(&config),
+			Err(Error::MissingUsernamePlaceholder)
+		));
+		assert!(!manager.is_enabled());
+	}
+
+	#[test]
+	fn rejects_empty_password() {
+		let manager = Manager::new();
+		manager.set_config(&test_config()).unwrap();
+		assert!(matches!(
+			manager.verify("walter", ""),
+			Err(Error::InvalidCredentials)
+		));
+	}
+
+	#[test]
+	fn reports_unreachable_server() {
+		let manager = Manager::new();
+		manager.set_config(&test_config()).unwrap();
+		assert!(matches!(
+			manager.verify("walter", "super_secret!"),
+			Err(Error::Ldap(_))
+		));
+	}
+}
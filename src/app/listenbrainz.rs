@@ -0,0 +1,169 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::{
+	index::{Index, QueryError, Song},
+	user,
+};
+
+const LISTENBRAINZ_API_URL: &str = "https://api.listenbrainz.org/1";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("ListenBrainz rejected the user token")]
+	InvalidToken,
+	#[error("ListenBrainz request failed with HTTP status code `{0}`")]
+	RequestFailed(u16),
+	#[error("ListenBrainz request failed due to a transport error")]
+	RequestTransport,
+	#[error(transparent)]
+	Query(#[from] QueryError),
+	#[error(transparent)]
+	User(#[from] user::Error),
+}
+
+impl From<ureq::Error> for Error {
+	fn from(error: ureq::Error) -> Self {
+		match error {
+			ureq::Error::Status(401, _) => Error::InvalidToken,
+			ureq::Error::Status(code, _) => Error::RequestFailed(code),
+			ureq::Error::Transport(_) => Error::RequestTransport,
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct TokenValidation {
+	valid: bool,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	index: Index,
+	user_manager: user::Manager,
+}
+
+impl Manager {
+	pub fn new(index: Index, user_manager: user::Manager) -> Self {
+		Self {
+			index,
+			user_manager,
+		}
+	}
+
+	pub fn link(&self, username: &str, token: &str) -> Result<(), Error> {
+		let response = ureq::get(&format!("{}/validate-token", LISTENBRAINZ_API_URL))
+			.set("Authorization", &format!("Token {}", token))
+			.call()?;
+		let validation: TokenValidation = response
+			.into_string()
+			.ok()
+			.and_then(|s| serde_json::from_str(&s).ok())
+			.ok_or(Error::RequestTransport)?;
+		if !validation.valid {
+			return Err(Error::InvalidToken);
+		}
+		self.user_manager
+			.listenbrainz_link(username, token)
+			.map_err(|e| e.into())
+	}
+
+	pub fn unlink(&self, username: &str) -> Result<(), Error> {
+		self.user_manager
+			.listenbrainz_unlink(username)
+			.map_err(|e| e.into())
+	}
+
+	pub fn scrobble(&self, username: &str, track: &Path) -> Result<(), Error> {
+		let song = self.index.get_song(username, track)?;
+		let listened_at = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs())
+			.unwrap_or_default();
+		let payload = json!({
+			"listen_type": "single",
+			"payload": [{
+				"listened_at": listened_at,
+				"track_metadata": track_metadata(&song),
+			}],
+		});
+		self.submit(username, &payload)
+	}
+
+	pub fn now_playing(&self, username: &str, track: &Path) -> Result<(), Error> {
+		let song = self.index.get_song(username, track)?;
+		let payload = json!({
+			"listen_type": "playing_now",
+			"payload": [{
+				"track_metadata": track_metadata(&song),
+			}],
+		});
+		self.submit(username, &payload)
+	}
+
+	fn submit(&self, username: &str, payload: &serde_json::Value) -> Result<(), Error> {
+		let token = self.user_manager.get_listenbrainz_token(username)?;
+		ureq::post(&format!("{}/submit-listens", LISTENBRAINZ_API_URL))
+			.set("Authorization", &format!("Token {}", token))
+			.set("Content-Type", "application/json")
+			.send_string(&payload.to_string())?;
+		Ok(())
+	}
+}
+
+fn track_metadata(song: &Song) -> serde_json::Value {
+	let mut additional_info = json!({ "submission_client": "Polaris" });
+	if let Some(duration) = song.duration {
+		additional_info["duration_ms"] = json!(duration as i64 * 1000);
+	}
+	if let Some(track_number) = song.track_number {
+		additional_info["tracknumber"] = json!(track_number);
+	}
+	if let Some(recording_id) = &song.musicbrainz_recording_id {
+		additional_info["recording_mbid"] = json!(recording_id);
+	}
+	if let Some(release_id) = &song.musicbrainz_release_id {
+		additional_info["release_mbid"] = json!(release_id);
+	}
+	if let Some(artist_id) = &song.musicbrainz_artist_id {
+		additional_info["artist_mbids"] = json!([artist_id]);
+	}
+	json!({
+		"artist_name": song.artist.as_deref().unwrap_or(""),
+		"track_name": song.title.as_deref().unwrap_or(""),
+		"release_name": song.album.as_deref().unwrap_or(""),
+		"additional_info": additional_info,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	#[test]
+	fn builds_track_metadata_from_song() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.mount("root", "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let path: PathBuf = ["root", "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect();
+		let song = ctx.index.get_song("test_user", &path).unwrap();
+		let metadata = track_metadata(&song);
+
+		assert_eq!(metadata["artist_name"], "Khemmis");
+		assert_eq!(metadata["track_name"], "Candlelight");
+		assert_eq!(metadata["release_name"], "Hunted");
+		assert_eq!(metadata["additional_info"]["tracknumber"], 2);
+		assert_eq!(metadata["additional_info"]["submission_client"], "Polaris");
+		assert!(metadata["additional_info"].get("recording_mbid").is_none());
+	}
+}
@@ -0,0 +1,371 @@
+use log::{error, info};
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::app::{events, index::Index, jukebox, queue};
+
+mod session;
+
+use self::session::{Reply, Session};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 6600;
+const DEFAULT_MAX_CONNECTIONS: usize = 10;
+const PROTOCOL_VERSION: &str = "0.23.5";
+// How often idle connections check for events
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+// Combined length of the commands in a command list, same as the default limit of MPD itself
+const MAX_COMMAND_LIST_LENGTH: usize = 2 * 1024 * 1024;
+
+/// Lets Music Player Daemon clients browse the collection and control the jukebox.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	// Address to listen on, only reachable from this machine by default
+	#[serde(default)]
+	pub address: Option<String>,
+	#[serde(default)]
+	pub port: Option<u16>,
+	#[serde(default)]
+	pub max_connections: Option<usize>,
+	// MPD clients cannot log in, so they act on behalf of this user
+	pub user: String,
+	// Required from clients with the `password` command when set
+	#[serde(default)]
+	pub password: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	config: Option<Config>,
+	index: Index,
+	queue_manager: queue::Manager,
+	jukebox_manager: jukebox::Manager,
+	events_manager: events::Manager,
+	started: Instant,
+	connections: Arc<AtomicUsize>,
+}
+
+impl Manager {
+	pub fn new(
+		config: Option<Config>,
+		index: Index,
+		queue_manager: queue::Manager,
+		jukebox_manager: jukebox::Manager,
+		events_manager: events::Manager,
+	) -> Self {
+		Self {
+			config,
+			index,
+			queue_manager,
+			jukebox_manager,
+			events_manager,
+			started: Instant::now(),
+			connections: Arc::default(),
+		}
+	}
+
+	pub fn begin_listening(&self) {
+		let Some(config) = &self.config else {
+			return;
+		};
+		let address = config.address.as_deref().unwrap_or(DEFAULT_ADDRESS);
+		let port = config.port.unwrap_or(DEFAULT_PORT);
+		let listener = match TcpListener::bind((address, port)) {
+			Ok(l) => l,
+			Err(e) => {
+				error!("Could not start MPD server on {}:{}: {}", address, port, e);
+				return;
+			}
+		};
+		info!("MPD server listening on {}:{}", address, port);
+		let manager = self.clone();
+		std::thread::spawn(move || manager.accept(listener));
+	}
+
+	fn accept(&self, listener: TcpListener) {
+		let max_connections = self
+			.config
+			.as_ref()
+			.and_then(|c| c.max_connections)
+			.unwrap_or(DEFAULT_MAX_CONNECTIONS);
+		for stream in listener.incoming() {
+			match stream {
+				Ok(_) if self.connections.load(Ordering::SeqCst) >= max_connections => {
+					info!(
+						"Refusing MPD connection, {} are already open",
+						max_connections
+					);
+				}
+				Ok(stream) => {
+					let manager = self.clone();
+					let connection = Connection::open(&self.connections);
+					std::thread::spawn(move || {
+						if let Err(e) = manager.serve(stream) {
+							info!("MPD connection closed: {}", e);
+						}
+						drop(connection);
+					});
+				}
+				Err(e) => error!("Could not accept MPD connection: {}", e),
+			}
+		}
+	}
+
+	fn serve(&self, stream: TcpStream) -> io::Result<()> {
+		let Some(config) = &self.config else {
+			return Ok(());
+		};
+		stream.set_read_timeout(Some(POLL_INTERVAL))?;
+		let mut reader = LineReader::new(BufReader::new(stream.try_clone()?));
+		let mut writer = stream;
+		let mut session = Session::new(self, config);
+		writer.write_all(format!("OK MPD {PROTOCOL_VERSION}\n").as_bytes())?;
+
+		loop {
+			let Some(line) = reader.wait_line()? else {
+				return Ok(());
+			};
+			let response = match line.as_str() {
+				"command_list_begin" | "command_list_ok_begin" => {
+					let list_ok = line == "command_list_ok_begin";
+					let mut commands = Vec::new();
+					let mut length = 0;
+					loop {
+						match reader.wait_line()? {
+							Some(l) if l == "command_list_end" => break,
+							Some(l) => {
+								length += l.len();
+								if length > MAX_COMMAND_LIST_LENGTH {
+									return Err(io::Error::new(
+										io::ErrorKind::InvalidData,
+										"Command list too long",
+									));
+								}
+								commands.push(l);
+							}
+							None => return Ok(()),
+						}
+					}
+					session.execute_list(&commands, list_ok)
+				}
+				_ => match session.execute(&line) {
+					Ok(Reply::Text(text)) => text + "OK\n",
+					Ok(Reply::Close) => return Ok(()),
+					Ok(Reply::Idle(subsystems)) => {
+						let changed = self.idle(&mut session, &mut reader, &subsystems)?;
+						let Some(changed) = changed else {
+							return Ok(());
+						};
+						changed
+							.iter()
+							.map(|s| format!("changed: {s}\n"))
+							.collect::<String>() + "OK\n"
+					}
+					Err(ack) => ack.to_string(),
+				},
+			};
+			writer.write_all(response.as_bytes())?;
+		}
+	}
+
+	// Waits for changes to the requested subsystems, or for the client to send `noidle`.
+	// Returns None when the connection should be closed.
+	fn idle(
+		&self,
+		session: &mut Session,
+		reader: &mut LineReader,
+		subsystems: &[String],
+	) -> io::Result<Option<Vec<&'static str>>> {
+		loop {
+			let changed = session.take_changes(subsystems);
+			if !changed.is_empty() {
+				return Ok(Some(changed));
+			}
+			match reader.poll_line()? {
+				Poll::Pending => (),
+				Poll::Line(l) if l == "noidle" => return Ok(Some(Vec::new())),
+				Poll::Line(_) | Poll::Closed => return Ok(None),
+			}
+		}
+	}
+}
+
+// Counts open connections for as long as it is alive
+struct Connection(Arc<AtomicUsize>);
+
+impl Connection {
+	fn open(connections: &Arc<AtomicUsize>) -> Self {
+		connections.fetch_add(1, Ordering::SeqCst);
+		Self(connections.clone())
+	}
+}
+
+impl Drop for Connection {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+enum Poll {
+	Line(String),
+	Pending,
+	Closed,
+}
+
+// Reads lines from a socket with a read timeout, keeping partial lines between attempts
+struct LineReader {
+	reader: BufReader<TcpStream>,
+	buffer: String,
+}
+
+impl LineReader {
+	fn new(reader: BufReader<TcpStream>) -> Self {
+		Self {
+			reader,
+			buffer: String::new(),
+		}
+	}
+
+	fn poll_line(&mut self) -> io::Result<Poll> {
+		// Reads are capped so clients cannot grow the buffer by never sending a line break
+		let limit = (MAX_LINE_LENGTH + 1 - self.buffer.len()) as u64;
+		match (&mut self.reader).take(limit).read_line(&mut self.buffer) {
+			Ok(0) => Ok(Poll::Closed),
+			Ok(_) if self.buffer.len() > MAX_LINE_LENGTH => {
+				Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"))
+			}
+			Ok(_) if !self.buffer.ends_with('\n') => Ok(Poll::Pending),
+			Ok(_) => {
+				let line = std::mem::take(&mut self.buffer);
+				Ok(Poll::Line(line.trim_end_matches(['\r', '\n']).to_owned()))
+			}
+			Err(e)
+				if matches!(
+					e.kind(),
+					io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+				) =>
+			{
+				Ok(Poll::Pending)
+			}
+			Err(e) => Err(e),
+		}
+	}
+
+	fn wait_line(&mut self) -> io::Result<Option<String>> {
+		loop {
+			match self.poll_line()? {
+				Poll::Line(l) => return Ok(Some(l)),
+				Poll::Pending => (),
+				Poll::Closed => return Ok(None),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::net::SocketAddr;
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	fn start_server(test_name: String, max_connections: Option<usize>) -> SocketAddr {
+		let ctx = test::ContextBuilder::new(test_name)
+			.user("test_user", "password", false)
+			.build();
+		let jukebox_manager = jukebox::Manager::new(
+			None,
+			ctx.queue_manager.clone(),
+			ctx.vfs_manager.clone(),
+			ctx.events_manager.clone(),
+		);
+		let config = Config {
+			user: "test_user".to_owned(),
+			max_connections,
+			..Default::default()
+		};
+		let manager = Manager::new(
+			Some(config),
+			ctx.index.clone(),
+			ctx.queue_manager.clone(),
+			jukebox_manager,
+			ctx.events_manager.clone(),
+		);
+
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap();
+		std::thread::spawn(move || manager.accept(listener));
+		address
+	}
+
+	// Sends some input and returns everything the server sent until it closed the connection
+	fn converse(address: SocketAddr, input: &[u8]) -> String {
+		let mut client = TcpStream::connect(address).unwrap();
+		client.write_all(input).unwrap();
+		let mut output = Vec::new();
+		let _ = client.read_to_end(&mut output);
+		String::from_utf8_lossy(&output).into_owned()
+	}
+
+	#[test]
+	fn serves_clients_over_tcp() {
+		let address = start_server(test_name!(), None);
+		let mut client = TcpStream::connect(address).unwrap();
+		client
+			.write_all(b"ping\ncommand_list_ok_begin\nping\nping\ncommand_list_end\nclose\n")
+			.unwrap();
+		let mut output = String::new();
+		client.read_to_string(&mut output).unwrap();
+		assert_eq!(
+			output,
+			format!("OK MPD {PROTOCOL_VERSION}\nOK\nlist_OK\nlist_OK\nOK\n")
+		);
+	}
+
+	#[test]
+	fn closes_connections_sending_long_lines() {
+		let address = start_server(test_name!(), None);
+		let mut input = vec![b'a'; MAX_LINE_LENGTH + 1];
+		input.extend_from_slice(b"\nping\n");
+		let output = converse(address, &input);
+		assert!(output.lines().all(|l| l.starts_with("OK MPD")), "{output}");
+	}
+
+	#[test]
+	fn closes_connections_sending_long_command_lists() {
+		let address = start_server(test_name!(), None);
+		let mut input = b"command_list_ok_begin\n".to_vec();
+		for _ in 0..=MAX_COMMAND_LIST_LENGTH / 4 {
+			input.extend_from_slice(b"ping\n");
+		}
+		input.extend_from_slice(b"command_list_end\nping\n");
+		let output = converse(address, &input);
+		assert!(output.lines().all(|l| l.starts_with("OK MPD")), "{output}");
+	}
+
+	#[test]
+	fn limits_concurrent_connections() {
+		let address = start_server(test_name!(), Some(1));
+
+		let mut first_client = TcpStream::connect(address).unwrap();
+		let mut greeting = [0; 6];
+		first_client.read_exact(&mut greeting).unwrap();
+		assert_eq!(&greeting, b"OK MPD");
+		assert_eq!(converse(address, b"ping\nclose\n"), "");
+
+		drop(first_client);
+		let output = (0..20)
+			.map(|_| {
+				std::thread::sleep(POLL_INTERVAL);
+				converse(address, b"ping\nclose\n")
+			})
+			.find(|o| !o.is_empty());
+		assert_eq!(output, Some(format!("OK MPD {PROTOCOL_VERSION}\nOK\n")));
+	}
+}
@@ -0,0 +1,893 @@
+use futures_channel::mpsc::UnboundedReceiver;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+use super::{Config, Manager};
+use crate::app::events::Event;
+use crate::app::index::{CollectionFile, Index, Page, QueryError, Song};
+use crate::app::queue::PlayQueue;
+use crate::app::{events, jukebox, queue};
+
+// Error codes from the MPD protocol
+const ACK_ERROR_ARG: u32 = 2;
+const ACK_ERROR_PASSWORD: u32 = 3;
+const ACK_ERROR_PERMISSION: u32 = 4;
+const ACK_ERROR_UNKNOWN: u32 = 5;
+const ACK_ERROR_NO_EXIST: u32 = 50;
+const ACK_ERROR_SYSTEM: u32 = 52;
+
+// Commands which can be sent before the password
+const PUBLIC_COMMANDS: [&str; 6] = [
+	"close",
+	"commands",
+	"notcommands",
+	"password",
+	"ping",
+	"tagtypes",
+];
+
+const COMMANDS: [&str; 43] = [
+	"add",
+	"addid",
+	"clear",
+	"close",
+	"commands",
+	"consume",
+	"currentsong",
+	"decoders",
+	"delete",
+	"deleteid",
+	"find",
+	"idle",
+	"list",
+	"listall",
+	"listallinfo",
+	"lsinfo",
+	"move",
+	"moveid",
+	"next",
+	"noidle",
+	"notcommands",
+	"outputs",
+	"password",
+	"pause",
+	"ping",
+	"play",
+	"playid",
+	"playlistid",
+	"playlistinfo",
+	"plchanges",
+	"plchangesposid",
+	"previous",
+	"random",
+	"repeat",
+	"search",
+	"setvol",
+	"single",
+	"stats",
+	"status",
+	"stop",
+	"tagtypes",
+	"update",
+	"urlhandlers",
+];
+
+const TAG_TYPES: [&str; 9] = [
+	"Artist",
+	"AlbumArtist",
+	"Album",
+	"Title",
+	"Track",
+	"Disc",
+	"Date",
+	"Genre",
+	"Composer",
+];
+
+pub struct Ack {
+	code: u32,
+	list_index: usize,
+	command: String,
+	message: String,
+}
+
+impl Ack {
+	fn new(code: u32, message: impl Into<String>) -> Self {
+		Self {
+			code,
+			list_index: 0,
+			command: String::new(),
+			message: message.into(),
+		}
+	}
+}
+
+impl fmt::Display for Ack {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(
+			f,
+			"ACK [{}@{}] {{{}}} {}",
+			self.code, self.list_index, self.command, self.message
+		)
+	}
+}
+
+impl From<QueryError> for Ack {
+	fn from(error: QueryError) -> Self {
+		Ack::new(ACK_ERROR_NO_EXIST, error.to_string())
+	}
+}
+
+impl From<queue::Error> for Ack {
+	fn from(error: queue::Error) -> Self {
+		match error {
+			queue::Error::TrackNotFound(_) => Ack::new(ACK_ERROR_ARG, "Bad song index"),
+			queue::Error::SongNotFound(_) => Ack::new(ACK_ERROR_NO_EXIST, error.to_string()),
+			_ => Ack::new(ACK_ERROR_SYSTEM, error.to_string()),
+		}
+	}
+}
+
+impl From<jukebox::Error> for Ack {
+	fn from(error: jukebox::Error) -> Self {
+		match error {
+			jukebox::Error::InvalidVolume(_) => Ack::new(ACK_ERROR_ARG, error.to_string()),
+			jukebox::Error::Queue(e) => e.into(),
+			_ => Ack::new(ACK_ERROR_SYSTEM, error.to_string()),
+		}
+	}
+}
+
+pub enum Reply {
+	Text(String),
+	Idle(Vec<String>),
+	Close,
+}
+
+pub struct Session {
+	user: String,
+	password: Option<String>,
+	is_authorized: bool,
+	index: Index,
+	queue_manager: queue::Manager,
+	jukebox_manager: jukebox::Manager,
+	events_manager: events::Manager,
+	events: UnboundedReceiver<Event>,
+	changes: BTreeSet<&'static str>,
+	started: Instant,
+}
+
+impl Session {
+	pub fn new(manager: &Manager, config: &Config) -> Self {
+		Self {
+			user: config.user.clone(),
+			password: config.password.clone(),
+			is_authorized: config.password.is_none(),
+			index: manager.index.clone(),
+			queue_manager: manager.queue_manager.clone(),
+			jukebox_manager: manager.jukebox_manager.clone(),
+			events_manager: manager.events_manager.clone(),
+			events: manager.events_manager.subscribe(&config.user),
+			changes: BTreeSet::new(),
+			started: manager.started,
+		}
+	}
+
+	pub fn execute(&mut self, line: &str) -> Result<Reply, Ack> {
+		let (command, args) = match tokenize(line) {
+			Ok(mut tokens) if !tokens.is_empty() => {
+				let command = tokens.remove(0);
+				(command, tokens)
+			}
+			Ok(_) => return Err(Ack::new(ACK_ERROR_UNKNOWN, "No command given")),
+			Err(e) => return Err(e),
+		};
+		self.run(&command, &args).map_err(|mut ack| {
+			ack.command = command;
+			ack
+		})
+	}
+
+	pub fn execute_list(&mut self, commands: &[String], list_ok: bool) -> String {
+		let mut output = String::new();
+		for (list_index, command) in commands.iter().enumerate() {
+			let result = match self.execute(command) {
+				Ok(Reply::Idle(_)) => Err(Ack {
+					command: "idle".to_owned(),
+					..Ack::new(ACK_ERROR_ARG, "idle is not allowed in command lists")
+				}),
+				r => r,
+			};
+			match result {
+				Ok(Reply::Text(text)) => output.push_str(&text),
+				Ok(_) => (),
+				Err(ack) => {
+					output.push_str(&Ack { list_index, ..ack }.to_string());
+					return output;
+				}
+			}
+			if list_ok {
+				output.push_str("list_OK\n");
+			}
+		}
+		output + "OK\n"
+	}
+
+	/// Subsystems among `subsystems` (or all of them when empty) which changed since last asked.
+	pub fn take_changes(&mut self, subsystems: &[String]) -> Vec<&'static str> {
+		while let Ok(Some(event)) = self.events.try_next() {
+			self.changes.extend(get_subsystems(&event));
+		}
+		let changed = self
+			.changes
+			.iter()
+			.copied()
+			.filter(|c| subsystems.is_empty() || subsystems.iter().any(|s| s == c))
+			.collect::<Vec<_>>();
+		for change in &changed {
+			self.changes.remove(change);
+		}
+		changed
+	}
+
+	fn run(&mut self, command: &str, args: &[String]) -> Result<Reply, Ack> {
+		if !self.is_authorized && !PUBLIC_COMMANDS.contains(&command) {
+			return Err(Ack::new(ACK_ERROR_PERMISSION, "you don't have permission"));
+		}
+
+		let text = match command {
+			"ping" => String::new(),
+			"close" => return Ok(Reply::Close),
+			"password" => {
+				let password = get_arg(args, 0)?;
+				if self.password.as_deref() != Some(password) {
+					return Err(Ack::new(ACK_ERROR_PASSWORD, "incorrect password"));
+				}
+				self.is_authorized = true;
+				String::new()
+			}
+			"commands" => COMMANDS
+				.iter()
+				.filter(|c| self.is_authorized || PUBLIC_COMMANDS.contains(c))
+				.map(|c| format!("command: {c}\n"))
+				.collect(),
+			"notcommands" => COMMANDS
+				.iter()
+				.filter(|c| !self.is_authorized && !PUBLIC_COMMANDS.contains(c))
+				.map(|c| format!("command: {c}\n"))
+				.collect(),
+			"tagtypes" => TAG_TYPES
+				.iter()
+				.map(|t| format!("tagtype: {t}\n"))
+				.collect(),
+			"urlhandlers" | "decoders" => String::new(),
+			"idle" => return Ok(Reply::Idle(args.to_vec())),
+			"noidle" => String::new(),
+			"outputs" => format!(
+				"outputid: 0\noutputname: Jukebox\nplugin: polaris\noutputenabled: {}\n",
+				self.jukebox_manager.get_status().is_ok() as u8
+			),
+			"update" => {
+				self.index.trigger_reindex();
+				"updating_db: 1\n".to_owned()
+			}
+
+			"status" => self.get_status()?,
+			"stats" => self.get_stats()?,
+			"currentsong" => {
+				let queue = self.queue_manager.get_queue(&self.user)?;
+				match queue.current_track {
+					Some(track) => format_queue_tracks(&queue, track..track + 1),
+					None => String::new(),
+				}
+			}
+
+			"lsinfo" => {
+				let path = get_path(args);
+				let mut output = String::new();
+				for file in self.index.browse(&self.user, &path)? {
+					match file {
+						CollectionFile::Directory(d) => {
+							writeln!(output, "directory: {}", d.path).unwrap()
+						}
+						CollectionFile::Song(s) => output.push_str(&format_song(&s)),
+					}
+				}
+				output
+			}
+			"listall" => self
+				.get_songs(&get_path(args))?
+				.iter()
+				.map(|s| format!("file: {}\n", s.path))
+				.collect(),
+			"listallinfo" => self
+				.get_songs(&get_path(args))?
+				.iter()
+				.map(format_song)
+				.collect(),
+			"search" | "find" => {
+				let filters = parse_filters(args)?;
+				let songs = self.find_songs(&filters, command == "find")?;
+				songs.iter().map(format_song).collect()
+			}
+			"list" => {
+				let tag = get_arg(args, 0)?;
+				// Grouping is not supported, so `group` arguments are ignored
+				let filter_args = args[1..]
+					.iter()
+					.take_while(|a| !a.eq_ignore_ascii_case("group"))
+					.cloned()
+					.collect::<Vec<_>>();
+				let filters = parse_filters(&filter_args)?;
+				let songs = self.find_songs(&filters, true)?;
+				let mut seen = HashSet::new();
+				let tag_name = get_tag_name(tag)?;
+				let mut output = String::new();
+				for value in songs.iter().filter_map(|s| get_tag(s, tag)) {
+					if seen.insert(value.clone()) {
+						writeln!(output, "{tag_name}: {value}").unwrap();
+					}
+				}
+				output
+			}
+
+			"playlistinfo" => {
+				let queue = self.queue_manager.get_queue(&self.user)?;
+				let range = match args.first() {
+					Some(a) => parse_range(a)?,
+					None => 0..queue.songs.len(),
+				};
+				format_queue_tracks(&queue, range)
+			}
+			"playlistid" => {
+				let queue = self.queue_manager.get_queue(&self.user)?;
+				let range = match args.first() {
+					Some(a) => {
+						let id = parse_number(a)?;
+						id..id + 1
+					}
+					None => 0..queue.songs.len(),
+				};
+				format_queue_tracks(&queue, range)
+			}
+			// Queue changes are not tracked, so clients receive the whole queue
+			"plchanges" => {
+				let queue = self.queue_manager.get_queue(&self.user)?;
+				format_queue_tracks(&queue, 0..queue.songs.len())
+			}
+			"plchangesposid" => {
+				let queue = self.queue_manager.get_queue(&self.user)?;
+				(0..queue.songs.len())
+					.map(|i| format!("cpos: {i}\nId: {i}\n"))
+					.collect()
+			}
+			"add" => {
+				let songs = self.get_songs(Path::new(get_arg(args, 0)?))?;
+				if songs.is_empty() {
+					return Err(Ack::new(ACK_ERROR_NO_EXIST, "No such directory"));
+				}
+				let paths = songs
+					.iter()
+					.map(|s| PathBuf::from(&s.path))
+					.collect::<Vec<_>>();
+				self.queue_manager.append(&self.user, &paths)?;
+				self.on_queue_changed();
+				String::new()
+			}
+			"addid" => {
+				let path = PathBuf::from(get_arg(args, 0)?);
+				self.queue_manager
+					.append(&self.user, std::slice::from_ref(&path))?;
+				let mut id = self.queue_manager.get_queue(&self.user)?.songs.len() - 1;
+				if let Some(position) = args.get(1) {
+					let position = parse_number(position)?;
+					self.queue_manager.move_track(&self.user, id, position)?;
+					id = position;
+				}
+				self.on_queue_changed();
+				format!("Id: {id}\n")
+			}
+			"delete" => {
+				let range = parse_range(get_arg(args, 0)?)?;
+				for track in range.rev() {
+					self.queue_manager.remove(&self.user, track)?;
+				}
+				self.on_queue_changed();
+				String::new()
+			}
+			"deleteid" => {
+				let id = parse_number(get_arg(args, 0)?)?;
+				self.queue_manager.remove(&self.user, id)?;
+				self.on_queue_changed();
+				String::new()
+			}
+			"move" | "moveid" => {
+				let from = parse_number(get_arg(args, 0)?)?;
+				let to = parse_number(get_arg(args, 1)?)?;
+				self.queue_manager.move_track(&self.user, from, to)?;
+				self.on_queue_changed();
+				String::new()
+			}
+			"clear" => {
+				self.queue_manager.clear(&self.user)?;
+				self.on_queue_changed();
+				String::new()
+			}
+
+			"play" | "playid" => {
+				if let Some(track) = args.first() {
+					let track = parse_number(track)?;
+					if self.queue_manager.get_queue(&self.user)?.songs.len() <= track {
+						return Err(Ack::new(ACK_ERROR_ARG, "Bad song index"));
+					}
+					self.queue_manager
+						.set_current_track(&self.user, Some(track), 0)?;
+				}
+				self.jukebox_manager.play(&self.user)?;
+				self.changes.insert("player");
+				String::new()
+			}
+			"pause" => {
+				let pause = match args.first().map(String::as_str) {
+					Some("1") => true,
+					Some("0") => false,
+					Some(_) => return Err(Ack::new(ACK_ERROR_ARG, "Boolean (0/1) expected")),
+					None => self.jukebox_manager.get_status()?.is_playing,
+				};
+				match pause {
+					true => self.jukebox_manager.pause()?,
+					false => self.jukebox_manager.play(&self.user)?,
+				}
+				self.changes.insert("player");
+				String::new()
+			}
+			"stop" => {
+				self.jukebox_manager.pause()?;
+				self.changes.insert("player");
+				String::new()
+			}
+			"next" => {
+				self.jukebox_manager.next(&self.user)?;
+				self.on_queue_changed();
+				String::new()
+			}
+			"previous" => {
+				self.jukebox_manager.previous(&self.user)?;
+				self.on_queue_changed();
+				String::new()
+			}
+			"setvol" => {
+				let volume = parse_number(get_arg(args, 0)?)?;
+				self.jukebox_manager.set_volume(volume)?;
+				self.changes.insert("mixer");
+				String::new()
+			}
+			// Playback modes other than playing the queue in order are not supported
+			"random" | "repeat" | "single" | "consume" => match get_arg(args, 0)? {
+				"0" => String::new(),
+				_ => {
+					return Err(Ack::new(
+						ACK_ERROR_ARG,
+						format!("{command} is not supported"),
+					))
+				}
+			},
+
+			_ => {
+				return Err(Ack::new(
+					ACK_ERROR_UNKNOWN,
+					format!("unknown command \"{command}\""),
+				))
+			}
+		};
+		Ok(Reply::Text(text))
+	}
+
+	fn on_queue_changed(&self) {
+		self.events_manager.publish(events::Event::QueueChanged {
+			username: self.user.clone(),
+		});
+	}
+
+	fn get_songs(&self, path: &Path) -> Result<Vec<Song>, Ack> {
+		let (songs, _) = self.index.flatten(&self.user, path, Page::default())?;
+		Ok(songs)
+	}
+
+	// `find` only keeps songs whose tags are exactly the requested values
+	fn find_songs(&self, filters: &[(String, String)], exact: bool) -> Result<Vec<Song>, Ack> {
+		if filters.is_empty() {
+			return self.get_songs(Path::new(""));
+		}
+		let query = filters
+			.iter()
+			.map(|(tag, value)| {
+				let value = value.replace('"', "");
+				match tag.as_str() {
+					"any" | "file" | "base" => format!("\"{value}\""),
+					"albumartist" => format!("artist:\"{value}\""),
+					"date" => format!("year:\"{value}\""),
+					tag => format!("{tag}:\"{value}\""),
+				}
+			})
+			.collect::<Vec<_>>()
+			.join(" ");
+		let songs = self
+			.index
+			.search(&self.user, &query)?
+			.into_iter()
+			.filter_map(|f| match f {
+				CollectionFile::Song(s) => Some(s),
+				CollectionFile::Directory(_) => None,
+			})
+			.filter(|s| {
+				!exact
+					|| filters.iter().all(|(tag, value)| match get_tag(s, tag) {
+						Some(v) => v == *value,
+						None => matches!(tag.as_str(), "any" | "base"),
+					})
+			})
+			.collect();
+		Ok(songs)
+	}
+
+	fn get_status(&self) -> Result<String, Ack> {
+		let queue = self.queue_manager.get_queue(&self.user)?;
+		let jukebox = self
+			.jukebox_manager
+			.get_status()
+			.ok()
+			.filter(|s| s.username.as_deref().unwrap_or(&self.user) == self.user);
+		let is_playing = jukebox.as_ref().is_some_and(|s| s.is_playing);
+
+		let mut output = String::new();
+		let volume = self
+			.jukebox_manager
+			.get_status()
+			.map_or(-1, |s| s.volume as i32);
+		writeln!(output, "volume: {volume}").unwrap();
+		output.push_str("repeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\n");
+		writeln!(output, "playlist: {}", get_queue_version(&queue)).unwrap();
+		writeln!(output, "playlistlength: {}", queue.songs.len()).unwrap();
+		let state = match (is_playing, queue.current_track) {
+			(true, _) => "play",
+			(false, Some(_)) => "pause",
+			(false, None) => "stop",
+		};
+		writeln!(output, "state: {state}").unwrap();
+		let current_track = jukebox
+			.as_ref()
+			.and_then(|s| s.current_track)
+			.or(queue.current_track);
+		if let Some(track) = current_track.filter(|t| *t < queue.songs.len()) {
+			let elapsed = match is_playing {
+				true => jukebox.as_ref().map_or(0, |s| s.position_seconds),
+				false => queue.position_seconds,
+			};
+			let duration = queue.songs[track].duration.unwrap_or(0);
+			writeln!(output, "song: {track}\nsongid: {track}").unwrap();
+			writeln!(output, "time: {elapsed}:{duration}").unwrap();
+			writeln!(output, "elapsed: {elapsed}.000\nduration: {duration}.000").unwrap();
+			if track + 1 < queue.songs.len() {
+				writeln!(output, "nextsong: {}\nnextsongid: {}", track + 1, track + 1).unwrap();
+			}
+		}
+		Ok(output)
+	}
+
+	fn get_stats(&self) -> Result<String, Ack> {
+		let songs = self.get_songs(Path::new(""))?;
+		let artists = songs
+			.iter()
+			.filter_map(|s| s.artist.as_ref())
+			.collect::<HashSet<_>>();
+		let albums = songs
+			.iter()
+			.filter_map(|s| s.album.as_ref())
+			.collect::<HashSet<_>>();
+		let playtime: i64 = songs.iter().filter_map(|s| s.duration).map(i64::from).sum();
+		Ok(format!(
+			"artists: {}\nalbums: {}\nsongs: {}\nuptime: {}\nplaytime: 0\ndb_playtime: {}\n",
+			artists.len(),
+			albums.len(),
+			songs.len(),
+			self.started.elapsed().as_secs(),
+			playtime
+		))
+	}
+}
+
+fn get_subsystems(event: &Event) -> &'static [&'static str] {
+	match event {
+		Event::IndexUpdateStarted => &["update"],
+		Event::IndexUpdateCompleted => &["database", "update"],
+		Event::PlaylistsChanged { .. } => &["stored_playlist"],
+		// The jukebox saves its position in the queue when changing tracks
+		Event::QueueChanged { .. } => &["playlist", "player"],
+		Event::NowPlaying { .. } => &[],
+	}
+}
+
+// Clients use the version to tell whether they should fetch the queue again
+fn get_queue_version(queue: &PlayQueue) -> u32 {
+	let mut hasher = DefaultHasher::new();
+	for song in &queue.songs {
+		song.path.hash(&mut hasher);
+	}
+	(hasher.finish() as u32).max(1)
+}
+
+// Tracks are identified by their position in the queue
+fn format_queue_tracks(queue: &PlayQueue, range: Range<usize>) -> String {
+	let mut output = String::new();
+	for (position, song) in queue.songs.iter().enumerate() {
+		if range.contains(&position) {
+			output.push_str(&format_song(song));
+			writeln!(output, "Pos: {position}\nId: {position}").unwrap();
+		}
+	}
+	output
+}
+
+fn format_song(song: &Song) -> String {
+	let mut output = format!("file: {}\n", song.path);
+	if let Some(duration) = song.duration {
+		writeln!(output, "Time: {duration}\nduration: {duration}.000").unwrap();
+	}
+	for tag in TAG_TYPES {
+		if let Some(value) = get_tag(song, tag) {
+			writeln!(output, "{tag}: {value}").unwrap();
+		}
+	}
+	output
+}
+
+fn get_tag(song: &Song, tag: &str) -> Option<String> {
+	match tag.to_lowercase().as_str() {
+		"artist" => song.artist.clone(),
+		"albumartist" => song.album_artist.clone(),
+		"album" => song.album.clone(),
+		"title" => song.title.clone(),
+		"track" => song.track_number.map(|n| n.to_string()),
+		"disc" => song.disc_number.map(|n| n.to_string()),
+		"date" => song.year.map(|y| y.to_string()),
+		"genre" => song.genre.clone(),
+		"composer" => song.composer.clone(),
+		"file" => Some(song.path.clone()),
+		_ => None,
+	}
+}
+
+fn get_tag_name(tag: &str) -> Result<&'static str, Ack> {
+	if tag.eq_ignore_ascii_case("file") {
+		return Ok("file");
+	}
+	TAG_TYPES
+		.iter()
+		.find(|t| t.eq_ignore_ascii_case(tag))
+		.copied()
+		.ok_or_else(|| Ack::new(ACK_ERROR_ARG, format!("Unknown tag type: {tag}")))
+}
+
+// Filters are given as pairs of tag and value, like `artist "Khemmis" album "Hunted"`
+fn parse_filters(args: &[String]) -> Result<Vec<(String, String)>, Ack> {
+	if !args.len().is_multiple_of(2) {
+		return Err(Ack::new(
+			ACK_ERROR_ARG,
+			"Filter expressions are not supported, use tag and value pairs",
+		));
+	}
+	args.chunks(2)
+		.map(|pair| {
+			let tag = pair[0].to_lowercase();
+			match tag.as_str() {
+				"any" | "file" | "base" => Ok((tag, pair[1].clone())),
+				_ => get_tag_name(&tag).map(|_| (tag, pair[1].clone())),
+			}
+		})
+		.collect()
+}
+
+fn get_arg(args: &[String], index: usize) -> Result<&str, Ack> {
+	args.get(index)
+		.map(String::as_str)
+		.ok_or_else(|| Ack::new(ACK_ERROR_ARG, "wrong number of arguments"))
+}
+
+fn get_path(args: &[String]) -> PathBuf {
+	PathBuf::from(args.first().map_or("", |a| a.trim_matches('/')))
+}
+
+fn parse_number<T: FromStr>(arg: &str) -> Result<T, Ack> {
+	arg.parse()
+		.map_err(|_| Ack::new(ACK_ERROR_ARG, format!("Integer expected: {arg}")))
+}
+
+// Ranges are written `start:end`, with an optional end, or as a single position
+fn parse_range(arg: &str) -> Result<Range<usize>, Ack> {
+	match arg.split_once(':') {
+		Some((start, "")) => Ok(parse_number(start)?..usize::MAX),
+		Some((start, end)) => Ok(parse_number(start)?..parse_number(end)?),
+		None => {
+			let position = parse_number(arg)?;
+			Ok(position..position + 1)
+		}
+	}
+}
+
+// Arguments are separated by spaces, and can be quoted with backslash escapes
+fn tokenize(line: &str) -> Result<Vec<String>, Ack> {
+	let mut tokens = Vec::new();
+	let mut chars = line.trim().chars().peekable();
+	while let Some(c) = chars.next() {
+		if c.is_whitespace() {
+			continue;
+		}
+		let mut token = String::new();
+		if c == '"' {
+			loop {
+				match chars.next() {
+					Some('"') => break,
+					Some('\\') => token.extend(chars.next()),
+					Some(c) => token.push(c),
+					None => return Err(Ack::new(ACK_ERROR_ARG, "Missing closing '\"'")),
+				}
+			}
+		} else {
+			token.push(c);
+			while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+				token.push(c);
+			}
+		}
+		tokens.push(token);
+	}
+	Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn build_session(ctx: &test::Context, password: Option<&str>) -> Session {
+		let jukebox_manager = jukebox::Manager::new(
+			None,
+			ctx.queue_manager.clone(),
+			ctx.vfs_manager.clone(),
+			ctx.events_manager.clone(),
+		);
+		let config = Config {
+			user: TEST_USER.to_owned(),
+			password: password.map(str::to_owned),
+			..Default::default()
+		};
+		let manager = Manager::new(
+			Some(config.clone()),
+			ctx.index.clone(),
+			ctx.queue_manager.clone(),
+			jukebox_manager,
+			ctx.events_manager.clone(),
+		);
+		Session::new(&manager, &config)
+	}
+
+	fn build_context(test_name: String) -> test::Context {
+		let ctx = test::ContextBuilder::new(test_name)
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+		ctx
+	}
+
+	fn run(session: &mut Session, line: &str) -> String {
+		match session.execute(line) {
+			Ok(Reply::Text(text)) => text,
+			Ok(_) => panic!("Unexpected reply to {line}"),
+			Err(ack) => ack.to_string(),
+		}
+	}
+
+	#[test]
+	fn tokenizes_arguments() {
+		assert_eq!(
+			tokenize(r#"find artist "Khemmis" album "The \"Hunted\"""#)
+				.ok()
+				.unwrap(),
+			vec!["find", "artist", "Khemmis", "album", r#"The "Hunted""#]
+		);
+		assert!(tokenize(r#"find "artist"#).is_err());
+	}
+
+	#[test]
+	fn requires_password() {
+		let ctx = build_context(test_name!());
+		let mut session = build_session(&ctx, Some("secret"));
+		assert_eq!(
+			run(&mut session, "status"),
+			"ACK [4@0] {status} you don't have permission\n"
+		);
+		assert_eq!(
+			run(&mut session, "password wrong"),
+			"ACK [3@0] {password} incorrect password\n"
+		);
+		assert_eq!(run(&mut session, "password secret"), "");
+		assert!(run(&mut session, "status").contains("state: stop"));
+	}
+
+	#[test]
+	fn browses_and_searches_collection() {
+		let ctx = build_context(test_name!());
+		let mut session = build_session(&ctx, None);
+
+		let root = run(&mut session, "lsinfo");
+		assert_eq!(root, format!("directory: {TEST_MOUNT_NAME}\n"));
+
+		let songs = run(&mut session, "find album Hunted title Candlelight");
+		assert!(songs.starts_with("file: root/Khemmis/Hunted/02 - Candlelight.mp3\n"));
+		assert!(songs.contains("Artist: Khemmis\n"));
+		assert_eq!(songs.matches("file: ").count(), 1);
+
+		let albums = run(&mut session, "list album artist Khemmis");
+		assert_eq!(albums, "Album: Hunted\n");
+	}
+
+	#[test]
+	fn edits_queue() {
+		let ctx = build_context(test_name!());
+		let mut session = build_session(&ctx, None);
+		let album = "root/Khemmis/Hunted";
+
+		assert_eq!(run(&mut session, &format!("add \"{album}\"")), "");
+		assert_eq!(
+			run(
+				&mut session,
+				&format!("addid \"{album}/05 - Hunted.mp3\" 0")
+			),
+			"Id: 0\n"
+		);
+		assert!(run(&mut session, "status").contains("playlistlength: 6\n"));
+
+		let output = session.execute_list(&["delete 1:3".to_owned(), "move 0 3".to_owned()], true);
+		assert_eq!(output, "list_OK\nlist_OK\nOK\n");
+		let queue = ctx.queue_manager.get_queue(TEST_USER).unwrap();
+		let paths = queue
+			.songs
+			.iter()
+			.map(|s| s.path.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(
+			paths,
+			vec![
+				format!("{album}/03 - Three Gates.mp3"),
+				format!("{album}/04 - Beyond The Door.mp3"),
+				format!("{album}/05 - Hunted.mp3"),
+				format!("{album}/05 - Hunted.mp3"),
+			]
+		);
+
+		assert_eq!(
+			session.take_changes(&["playlist".to_owned()]),
+			vec!["playlist"]
+		);
+		assert_eq!(
+			run(&mut session, "deleteid 10"),
+			"ACK [2@0] {deleteid} Bad song index\n"
+		);
+		assert_eq!(
+			run(&mut session, "play"),
+			"ACK [52@0] {play} Jukebox is not enabled\n"
+		);
+	}
+}
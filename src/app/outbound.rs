@@ -0,0 +1,105 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+/// HTTP agent for URLs supplied by users, which refuses to connect to loopback, private and
+/// link-local addresses. Addresses are checked when connecting, which also covers redirects and
+/// host names resolving to a different address than when the URL was saved.
+pub fn public_agent() -> ureq::Agent {
+	ureq::AgentBuilder::new()
+		.resolver(|netloc: &str| -> io::Result<Vec<SocketAddr>> {
+			let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+			let public_addresses: Vec<SocketAddr> = addresses
+				.into_iter()
+				.filter(|a| is_public(&a.ip()))
+				.collect();
+			if public_addresses.is_empty() {
+				return Err(io::Error::new(
+					io::ErrorKind::PermissionDenied,
+					format!("`{}` does not resolve to a public address", netloc),
+				));
+			}
+			Ok(public_addresses)
+		})
+		.build()
+}
+
+pub fn is_public(address: &IpAddr) -> bool {
+	match address {
+		IpAddr::V4(a) => is_public_v4(a),
+		IpAddr::V6(a) => match a.to_ipv4_mapped() {
+			Some(a) => is_public_v4(&a),
+			None => is_public_v6(a),
+		},
+	}
+}
+
+fn is_public_v4(address: &Ipv4Addr) -> bool {
+	let [a, b, ..] = address.octets();
+	let is_shared = a == 100 && (64..128).contains(&b);
+	!(address.is_loopback()
+		|| address.is_private()
+		|| address.is_link_local()
+		|| address.is_unspecified()
+		|| address.is_broadcast()
+		|| address.is_documentation()
+		|| address.is_multicast()
+		|| is_shared
+		|| a == 0)
+}
+
+fn is_public_v6(address: &Ipv6Addr) -> bool {
+	let first_segment = address.segments()[0];
+	let is_unique_local = (first_segment & 0xfe00) == 0xfc00;
+	let is_link_local = (first_segment & 0xffc0) == 0xfe80;
+	!(address.is_loopback()
+		|| address.is_unspecified()
+		|| address.is_multicast()
+		|| is_unique_local
+		|| is_link_local)
+}
+
+#[cfg(test)]
+mod test {
+	use std::net::TcpListener;
+
+	use super::*;
+
+	#[test]
+	fn recognizes_public_addresses() {
+		let public = ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"];
+		let local = [
+			"127.0.0.1",
+			"10.0.0.1",
+			"172.16.5.4",
+			"192.168.1.5",
+			"169.254.169.254",
+			"100.64.0.1",
+			"0.0.0.0",
+			"::1",
+			"::",
+			"fd00::1",
+			"fe80::1",
+			"::ffff:127.0.0.1",
+		];
+		for address in public {
+			assert!(is_public(&address.parse().unwrap()), "{}", address);
+		}
+		for address in local {
+			assert!(!is_public(&address.parse().unwrap()), "{}", address);
+		}
+	}
+
+	#[test]
+	fn agent_refuses_local_addresses() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}/", listener.local_addr().unwrap());
+		assert!(matches!(
+			public_agent().get(&url).call(),
+			Err(ureq::Error::Transport(_))
+		));
+		assert!(matches!(
+			public_agent().get("http://localhost/").call(),
+			Err(ureq::Error::Transport(_))
+		));
+	}
+}
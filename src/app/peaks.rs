@@ -0,0 +1,163 @@
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::app::transcode;
+
+// Songs are described by the same number of values regardless of their duration
+const NUM_PEAKS: usize = 1000;
+// Audio is decoded to mono at a low sample rate, which is plenty to draw a waveform
+const SAMPLE_RATE: u32 = 8000;
+
+static NEXT_TEMPORARY_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Could not run ffmpeg from `{0}`: `{1}`")]
+	FfmpegNotFound(PathBuf, std::io::Error),
+	#[error("ffmpeg could not decode `{0}`:\n\n{1}")]
+	Ffmpeg(PathBuf, String),
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	peaks_dir_path: PathBuf,
+	ffmpeg_path: PathBuf,
+}
+
+impl Manager {
+	pub fn new(peaks_dir_path: PathBuf) -> Self {
+		Self {
+			peaks_dir_path,
+			ffmpeg_path: transcode::get_ffmpeg_path(),
+		}
+	}
+
+	/// Loudest amplitude within evenly sized slices of a song, from 0 (silence) to 255 (full scale).
+	pub fn get_peaks(
+		&self,
+		audio_path: &Path,
+		segment: Option<&transcode::Segment>,
+	) -> Result<Vec<u8>, Error> {
+		let path = self.get_peaks_path(audio_path, segment);
+		if let Ok(peaks) = fs::read(&path) {
+			return Ok(peaks);
+		}
+		let peaks = self.compute_peaks(audio_path, segment)?;
+		self.write_peaks(&path, &peaks)?;
+		Ok(peaks)
+	}
+
+	fn get_peaks_path(&self, audio_path: &Path, segment: Option<&transcode::Segment>) -> PathBuf {
+		let mut hasher = DefaultHasher::new();
+		audio_path.hash(&mut hasher);
+		if let Ok(modified) = fs::metadata(audio_path).and_then(|m| m.modified()) {
+			modified.hash(&mut hasher);
+		}
+		segment.hash(&mut hasher);
+		self.peaks_dir_path
+			.join(format!("{}.peaks", hasher.finish()))
+	}
+
+	fn compute_peaks(
+		&self,
+		audio_path: &Path,
+		segment: Option<&transcode::Segment>,
+	) -> Result<Vec<u8>, Error> {
+		let mut command = Command::new(&self.ffmpeg_path);
+		command.arg("-nostdin").args(["-loglevel", "error"]);
+		if let Some(segment) = segment {
+			command.args(["-ss", &transcode::format_seconds(segment.start)]);
+		}
+		command.arg("-i").arg(audio_path).arg("-vn");
+		if let Some(segment) = segment {
+			if let Some(end) = segment.end {
+				let duration = end.saturating_sub(segment.start);
+				command.args(["-t", &transcode::format_seconds(duration)]);
+			}
+		}
+		let output = command
+			.args(["-ac", "1"])
+			.args(["-ar", &SAMPLE_RATE.to_string()])
+			.args(["-f", "s16le", "-"])
+			.stdin(Stdio::null())
+			.output()
+			.map_err(|e| Error::FfmpegNotFound(self.ffmpeg_path.clone(), e))?;
+
+		if !output.status.success() {
+			let message = String::from_utf8_lossy(&output.stderr).into_owned();
+			return Err(Error::Ffmpeg(audio_path.to_owned(), message));
+		}
+
+		let samples = output
+			.stdout
+			.chunks_exact(2)
+			.map(|b| i16::from_le_bytes([b[0], b[1]]))
+			.collect::<Vec<_>>();
+		Ok(downsample(&samples, NUM_PEAKS))
+	}
+
+	fn write_peaks(&self, path: &Path, peaks: &[u8]) -> Result<(), Error> {
+		fs::create_dir_all(&self.peaks_dir_path)
+			.map_err(|e| Error::Io(self.peaks_dir_path.clone(), e))?;
+		let id = NEXT_TEMPORARY_FILE_ID.fetch_add(1, Ordering::Relaxed);
+		let mut temporary_file_name: OsString = path.file_name().unwrap().to_owned();
+		temporary_file_name.push(format!(".{}.{}.part", std::process::id(), id));
+		let temporary_path = path.with_file_name(temporary_file_name);
+		fs::write(&temporary_path, peaks).map_err(|e| Error::Io(temporary_path.clone(), e))?;
+		fs::rename(&temporary_path, path).map_err(|e| Error::Io(path.to_owned(), e))
+	}
+}
+
+// Songs shorter than the requested number of peaks get one peak per sample
+fn downsample(samples: &[i16], num_peaks: usize) -> Vec<u8> {
+	let num_peaks = num_peaks.min(samples.len());
+	(0..num_peaks)
+		.map(|i| {
+			let start = i * samples.len() / num_peaks;
+			let end = (i + 1) * samples.len() / num_peaks;
+			let peak = samples[start..end]
+				.iter()
+				.map(|s| s.unsigned_abs())
+				.max()
+				.unwrap_or_default();
+			(peak as u32 * 255 / 32768) as u8
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::test::prepare_test_directory;
+	use crate::test_name;
+
+	#[test]
+	fn downsample_keeps_loudest_samples() {
+		let samples = [0, 100, -32768, 0, 16384, -8192, 0, 0];
+		assert_eq!(downsample(&samples, 4), vec![0, 255, 127, 0]);
+		assert_eq!(downsample(&samples[..2], 4), vec![0, 0]);
+		assert!(downsample(&[], 4).is_empty());
+	}
+
+	#[test]
+	fn reports_missing_ffmpeg() {
+		let output_dir = prepare_test_directory(test_name!());
+		let manager = Manager {
+			peaks_dir_path: output_dir.clone(),
+			ffmpeg_path: output_dir.join("not-ffmpeg"),
+		};
+		let audio_path =
+			Path::new("test-data/small-collection/Khemmis/Hunted/02 - Candlelight.mp3");
+		assert!(matches!(
+			manager.get_peaks(audio_path, None),
+			Err(Error::FfmpegNotFound(_, _))
+		));
+	}
+}
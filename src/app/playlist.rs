@@ -2,12 +2,19 @@ use core::clone::Clone;
 use diesel::prelude::*;
 use diesel::sql_types;
 use diesel::BelongingToDsl;
-use std::path::Path;
+use log::{error, info};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use crate::app::index::Song;
 use crate::app::vfs;
 use crate::db::{self, playlist_songs, playlists, users, DB};
 
+mod file;
+
+pub use self::file::Format;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error(transparent)]
@@ -18,6 +25,8 @@ pub enum Error {
 	UserNotFound,
 	#[error("Playlist not found")]
 	PlaylistNotFound,
+	#[error("A playlist with this name already exists")]
+	PlaylistAlreadyExists,
 	#[error(transparent)]
 	Vfs(#[from] vfs::Error),
 }
@@ -28,6 +37,13 @@ pub struct Manager {
 	vfs_manager: vfs::Manager,
 }
 
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportedPlaylists {
+	pub imported: Vec<String>,
+	// Virtual paths of playlist files which were not imported because their name was taken
+	pub skipped: Vec<PathBuf>,
+}
+
 impl Manager {
 	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
 		Self { db, vfs_manager }
@@ -63,7 +79,7 @@ impl Manager {
 	) -> Result<(), Error> {
 		let new_playlist: NewPlaylist;
 		let playlist: Playlist;
-		let vfs = self.vfs_manager.get_vfs()?;
+		let vfs = self.vfs_manager.get_vfs_for_user(owner)?;
 
 		{
 			let mut connection = self.db.connect()?;
@@ -98,8 +114,7 @@ impl Manager {
 			}
 		}
 
-		let mut new_songs: Vec<NewPlaylistSong> = Vec::new();
-		new_songs.reserve(content.len());
+		let mut new_songs: Vec<NewPlaylistSong> = Vec::with_capacity(content.len());
 
 		for (i, path) in content.iter().enumerate() {
 			let virtual_path = Path::new(&path);
@@ -135,7 +150,7 @@ impl Manager {
 	}
 
 	pub fn read_playlist(&self, playlist_name: &str, owner: &str) -> Result<Vec<Song>, Error> {
-		let vfs = self.vfs_manager.get_vfs()?;
+		let vfs = self.vfs_manager.get_vfs_for_user(owner)?;
 		let songs: Vec<Song>;
 
 		{
@@ -166,7 +181,7 @@ impl Manager {
 			// Select songs. Not using Diesel because we need to LEFT JOIN using a custom column
 			let query = diesel::sql_query(
 				r#"
-			SELECT s.id, s.path, s.parent, s.track_number, s.disc_number, s.title, s.artist, s.album_artist, s.year, s.album, s.artwork, s.duration, s.lyricist, s.composer, s.genre, s.label
+			SELECT s.id, s.path, s.parent, s.track_number, s.disc_number, s.title, s.artist, s.album_artist, s.year, s.album, s.artwork, s.duration, s.lyricist, s.composer, s.genre, s.label, s.file_modified, s.file_size, s.has_embedded_artwork, s.cue_source, s.cue_start, s.cue_end, s.disc_total, s.compilation, s.compilation_tag, s.conductor, s.artist_sort, s.album_artist_sort, s.album_sort, s.title_sort, s.track_gain, s.track_peak, s.album_gain, s.album_peak, s.lyrics, s.has_embedded_lyrics, s.musicbrainz_recording_id, s.musicbrainz_release_id, s.musicbrainz_artist_id, s.musicbrainz_album_artist_id, s.content_hash, s.sample_rate, s.encoder_delay, s.encoder_padding, s.sample_count
 			FROM playlist_songs ps
 			LEFT JOIN songs s ON ps.path = s.path
 			WHERE ps.playlist = ?
@@ -186,6 +201,103 @@ impl Manager {
 		Ok(virtual_songs)
 	}
 
+	pub fn export_playlist(
+		&self,
+		playlist_name: &str,
+		owner: &str,
+		format: Format,
+	) -> Result<String, Error> {
+		let songs = self.read_playlist(playlist_name, owner)?;
+		Ok(file::write(&songs, format))
+	}
+
+	// Saves every playlist file found within the mounts as a playlist of the same name. Existing
+	// playlists are never replaced, see `get_import_name`.
+	pub fn import_playlists(&self, owner: &str) -> Result<ImportedPlaylists, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(owner)?;
+		let existing_playlists: HashSet<String> = self.list_playlists(owner)?.into_iter().collect();
+		let mut report = ImportedPlaylists::default();
+
+		for mount in vfs.mounts() {
+			let mut playlist_paths = find_playlist_files(&mount.source);
+			playlist_paths.sort();
+			for playlist_path in playlist_paths {
+				let Some(playlist_name) =
+					get_import_name(&playlist_path, &existing_playlists, &report.imported)
+				else {
+					info!(
+						"Not importing `{:?}`, its name is already taken",
+						playlist_path
+					);
+					if let Ok(virtual_path) = vfs.real_to_virtual(&playlist_path) {
+						report.skipped.push(virtual_path);
+					}
+					continue;
+				};
+				let content = match fs::read(&playlist_path) {
+					Ok(c) => c,
+					Err(e) => {
+						error!("Could not read playlist `{:?}`: {}", playlist_path, e);
+						continue;
+					}
+				};
+				let content = String::from_utf8_lossy(&content);
+				let tracks: Vec<String> = file::read(&playlist_path, &content)
+					.into_iter()
+					.filter(|p| p.is_file())
+					.filter_map(|p| vfs.real_to_virtual(p).ok())
+					.map(|p| p.to_string_lossy().into_owned())
+					.collect();
+				self.save_playlist(&playlist_name, owner, &tracks)?;
+				report.imported.push(playlist_name);
+			}
+		}
+
+		report.imported.sort();
+		Ok(report)
+	}
+
+	pub fn rename_playlist(
+		&self,
+		playlist_name: &str,
+		new_playlist_name: &str,
+		owner: &str,
+	) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+
+		let user: User = {
+			use self::users::dsl::*;
+			users
+				.filter(name.eq(owner))
+				.select((id,))
+				.first(&mut connection)
+				.optional()?
+				.ok_or(Error::UserNotFound)?
+		};
+
+		{
+			use self::playlists::dsl::*;
+			connection.transaction(|connection| {
+				// Renaming onto an existing playlist would silently replace it
+				let existing: i64 = Playlist::belonging_to(&user)
+					.filter(name.eq(new_playlist_name))
+					.count()
+					.get_result(connection)?;
+				if existing > 0 && playlist_name != new_playlist_name {
+					return Err(Error::PlaylistAlreadyExists);
+				}
+				let q = Playlist::belonging_to(&user).filter(name.eq(playlist_name));
+				match diesel::update(q)
+					.set(name.eq(new_playlist_name))
+					.execute(connection)?
+				{
+					0 => Err(Error::PlaylistNotFound),
+					_ => Ok(()),
+				}
+			})
+		}
+	}
+
 	pub fn delete_playlist(&self, playlist_name: &str, owner: &str) -> Result<(), Error> {
 		let mut connection = self.db.connect()?;
 
@@ -210,6 +322,49 @@ impl Manager {
 	}
 }
 
+// Name to import a playlist file under, which is its file name without extension. Files named
+// like another one from the same import are told apart by the name of their directory. Files
+// named like a playlist which existed before the import are skipped, so importing twice does not
+// duplicate or overwrite anything.
+fn get_import_name(
+	path: &Path,
+	existing_playlists: &HashSet<String>,
+	imported_playlists: &[String],
+) -> Option<String> {
+	let is_taken =
+		|name: &String| existing_playlists.contains(name) || imported_playlists.contains(name);
+	let stem = path.file_stem()?.to_str()?.to_owned();
+	if existing_playlists.contains(&stem) {
+		return None;
+	}
+	if !is_taken(&stem) {
+		return Some(stem);
+	}
+	let directory = path.parent()?.file_name()?.to_str()?;
+	let name = format!("{stem} ({directory})");
+	(!is_taken(&name)).then_some(name)
+}
+
+fn find_playlist_files(directory: &Path) -> Vec<PathBuf> {
+	let mut playlist_files = Vec::new();
+	let read_dir = match fs::read_dir(directory) {
+		Ok(r) => r,
+		Err(e) => {
+			error!("Directory read error for `{:?}`: {}", directory, e);
+			return playlist_files;
+		}
+	};
+	for entry in read_dir.flatten() {
+		let path = entry.path();
+		match entry.file_type() {
+			Ok(t) if t.is_dir() => playlist_files.append(&mut find_playlist_files(&path)),
+			Ok(_) if file::get_format(&path).is_some() => playlist_files.push(path),
+			_ => (),
+		}
+	}
+	playlist_files
+}
+
 #[derive(Identifiable, Queryable, Associations)]
 #[diesel(belongs_to(User, foreign_key = owner))]
 struct Playlist {
@@ -246,9 +401,12 @@ struct User {
 
 #[cfg(test)]
 mod test {
+	use std::fs;
 	use std::path::{Path, PathBuf};
 
+	use crate::app::index::Page;
 	use crate::app::test;
+	use crate::test::prepare_test_directory;
 	use crate::test_name;
 
 	const TEST_USER: &str = "test_user";
@@ -282,8 +440,9 @@ mod test {
 
 		let playlist_content: Vec<String> = ctx
 			.index
-			.flatten(Path::new(TEST_MOUNT_NAME))
+			.flatten(TEST_USER, Path::new(TEST_MOUNT_NAME), Page::default())
 			.unwrap()
+			.0
 			.into_iter()
 			.map(|s| s.path)
 			.collect();
@@ -304,6 +463,47 @@ mod test {
 		assert_eq!(songs.len(), 13);
 	}
 
+	#[test]
+	fn rename_playlist_golden_path() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.build();
+
+		ctx.playlist_manager
+			.save_playlist(TEST_PLAYLIST_NAME, TEST_USER, &Vec::new())
+			.unwrap();
+
+		ctx.playlist_manager
+			.rename_playlist(TEST_PLAYLIST_NAME, "Renamed", TEST_USER)
+			.unwrap();
+
+		let found_playlists = ctx.playlist_manager.list_playlists(TEST_USER).unwrap();
+		assert_eq!(found_playlists, vec!["Renamed".to_owned()]);
+	}
+
+	#[test]
+	fn rename_playlist_does_not_replace_existing_playlist() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.build();
+
+		for name in [TEST_PLAYLIST_NAME, "Other"] {
+			ctx.playlist_manager
+				.save_playlist(name, TEST_USER, &Vec::new())
+				.unwrap();
+		}
+
+		assert!(matches!(
+			ctx.playlist_manager
+				.rename_playlist(TEST_PLAYLIST_NAME, "Other", TEST_USER)
+				.unwrap_err(),
+			super::Error::PlaylistAlreadyExists
+		));
+
+		let found_playlists = ctx.playlist_manager.list_playlists(TEST_USER).unwrap();
+		assert_eq!(found_playlists.len(), 2);
+	}
+
 	#[test]
 	fn delete_playlist_golden_path() {
 		let ctx = test::ContextBuilder::new(test_name!())
@@ -335,8 +535,9 @@ mod test {
 
 		let playlist_content: Vec<String> = ctx
 			.index
-			.flatten(Path::new(TEST_MOUNT_NAME))
+			.flatten(TEST_USER, Path::new(TEST_MOUNT_NAME), Page::default())
 			.unwrap()
+			.0
 			.into_iter()
 			.map(|s| s.path)
 			.collect();
@@ -364,4 +565,141 @@ mod test {
 		.collect();
 		assert_eq!(songs[0].path, first_song_path.to_str().unwrap());
 	}
+
+	#[test]
+	fn import_playlists_from_mounts() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.mount("playlists", "test-data/playlists")
+			.build();
+
+		ctx.index.update().unwrap();
+
+		let report = ctx.playlist_manager.import_playlists(TEST_USER).unwrap();
+		assert_eq!(
+			report.imported,
+			vec!["Doom Favorites".to_owned(), "Picnic".to_owned()]
+		);
+		assert!(report.skipped.is_empty());
+
+		let songs = ctx
+			.playlist_manager
+			.read_playlist("Doom Favorites", TEST_USER)
+			.unwrap();
+		let paths: Vec<PathBuf> = songs.iter().map(|s| PathBuf::from(&s.path)).collect();
+		let expected: Vec<PathBuf> = vec![
+			[TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+				.iter()
+				.collect(),
+			[TEST_MOUNT_NAME, "Khemmis", "Hunted", "05 - Hunted.mp3"]
+				.iter()
+				.collect(),
+		];
+		assert_eq!(paths, expected);
+
+		let songs = ctx
+			.playlist_manager
+			.read_playlist("Picnic", TEST_USER)
+			.unwrap();
+		assert_eq!(songs.len(), 1);
+		assert_eq!(songs[0].title, Some("ピクニック (Picnic)".to_owned()));
+	}
+
+	#[test]
+	fn import_playlists_does_not_overwrite_playlists() {
+		let playlists_directory = prepare_test_directory(format!("{}-mixes", test_name!()));
+		for directory in ["Picnic", "Road Trip", "Summer"] {
+			fs::create_dir(playlists_directory.join(directory)).unwrap();
+		}
+		for file in ["Picnic/Picnic.m3u", "Road Trip/Mix.m3u", "Summer/Mix.m3u"] {
+			fs::write(playlists_directory.join(file), "#EXTM3U\n").unwrap();
+		}
+
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.mount("mixes", playlists_directory.to_str().unwrap())
+			.build();
+		ctx.index.update().unwrap();
+
+		let song_path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect();
+		let song_path = song_path.to_string_lossy().into_owned();
+		ctx.playlist_manager
+			.save_playlist("Picnic", TEST_USER, std::slice::from_ref(&song_path))
+			.unwrap();
+
+		let report = ctx.playlist_manager.import_playlists(TEST_USER).unwrap();
+		assert_eq!(
+			report.imported,
+			vec!["Mix".to_owned(), "Mix (Summer)".to_owned()]
+		);
+		let skipped_path: PathBuf = ["mixes", "Picnic", "Picnic.m3u"].iter().collect();
+		assert_eq!(report.skipped, vec![skipped_path]);
+
+		let songs = ctx
+			.playlist_manager
+			.read_playlist("Picnic", TEST_USER)
+			.unwrap();
+		assert_eq!(songs.len(), 1);
+		assert_eq!(songs[0].path, song_path);
+
+		let report = ctx.playlist_manager.import_playlists(TEST_USER).unwrap();
+		assert!(report.imported.is_empty());
+		assert_eq!(report.skipped.len(), 3);
+	}
+
+	#[test]
+	fn export_playlist_golden_path() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+
+		ctx.index.update().unwrap();
+
+		let song_path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect();
+		let song_path = song_path.to_string_lossy().into_owned();
+		ctx.playlist_manager
+			.save_playlist(
+				TEST_PLAYLIST_NAME,
+				TEST_USER,
+				std::slice::from_ref(&song_path),
+			)
+			.unwrap();
+
+		let m3u = ctx
+			.playlist_manager
+			.export_playlist(TEST_PLAYLIST_NAME, TEST_USER, super::Format::M3U)
+			.unwrap();
+		let expected_duration = ctx
+			.playlist_manager
+			.read_playlist(TEST_PLAYLIST_NAME, TEST_USER)
+			.unwrap()[0]
+			.duration
+			.unwrap();
+		assert_eq!(
+			m3u,
+			format!(
+				"#EXTM3U\n#EXTINF:{},Khemmis - Candlelight\n{}\n",
+				expected_duration, song_path
+			)
+		);
+
+		let pls = ctx
+			.playlist_manager
+			.export_playlist(TEST_PLAYLIST_NAME, TEST_USER, super::Format::PLS)
+			.unwrap();
+		assert_eq!(
+			pls,
+			format!(
+				"[playlist]\nFile1={}\nTitle1=Khemmis - Candlelight\nLength1={}\nNumberOfEntries=1\nVersion=2\n",
+				song_path, expected_duration
+			)
+		);
+	}
 }
@@ -0,0 +1,174 @@
+use std::path::{self, Component, Path, PathBuf};
+
+use crate::app::index::Song;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	M3U,
+	PLS,
+}
+
+impl Format {
+	pub fn mime_type(&self) -> &'static str {
+		match self {
+			Format::M3U => "audio/x-mpegurl",
+			Format::PLS => "audio/x-scpls",
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Format::M3U => "m3u8",
+			Format::PLS => "pls",
+		}
+	}
+}
+
+pub fn get_format(path: &Path) -> Option<Format> {
+	let extension = path.extension()?.to_str()?.to_lowercase();
+	match extension.as_str() {
+		"m3u" | "m3u8" => Some(Format::M3U),
+		"pls" => Some(Format::PLS),
+		_ => None,
+	}
+}
+
+// Returns the real paths of the songs listed in a playlist file, in order
+pub fn read(playlist_path: &Path, content: &str) -> Vec<PathBuf> {
+	let entries = match get_format(playlist_path) {
+		Some(Format::M3U) => parse_m3u(content),
+		Some(Format::PLS) => parse_pls(content),
+		None => Vec::new(),
+	};
+	let playlist_dir = playlist_path.parent().unwrap_or_else(|| Path::new(""));
+	entries
+		.into_iter()
+		.filter_map(|e| resolve_entry(playlist_dir, &e))
+		.collect()
+}
+
+pub fn write(songs: &[Song], format: Format) -> String {
+	match format {
+		Format::M3U => write_m3u(songs),
+		Format::PLS => write_pls(songs),
+	}
+}
+
+fn parse_m3u(content: &str) -> Vec<String> {
+	content
+		.lines()
+		.map(|l| l.trim_start_matches('\u{feff}').trim())
+		.filter(|l| !l.is_empty() && !l.starts_with('#'))
+		.map(|l| l.to_owned())
+		.collect()
+}
+
+fn parse_pls(content: &str) -> Vec<String> {
+	let mut entries: Vec<(u32, String)> = content
+		.lines()
+		.filter_map(|l| {
+			let (key, value) = l.trim().split_once('=')?;
+			let key = key.trim().to_lowercase();
+			let index = key.strip_prefix("file")?.parse().ok()?;
+			Some((index, value.trim().to_owned()))
+		})
+		.collect();
+	entries.sort_by_key(|(index, _)| *index);
+	entries.into_iter().map(|(_, path)| path).collect()
+}
+
+fn resolve_entry(playlist_dir: &Path, entry: &str) -> Option<PathBuf> {
+	let path = if entry.starts_with("file:") {
+		url::Url::parse(entry).ok()?.to_file_path().ok()?
+	} else if entry.contains("://") {
+		return None;
+	} else {
+		let mut separator = String::new();
+		separator.push(path::MAIN_SEPARATOR);
+		PathBuf::from(entry.replace(['\\', '/'], &separator))
+	};
+	Some(normalize(&playlist_dir.join(path)))
+}
+
+// Removes `.` and `..` components without touching the filesystem, so paths still match mount sources
+fn normalize(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::CurDir => (),
+			Component::ParentDir => {
+				if !normalized.pop() {
+					normalized.push(component);
+				}
+			}
+			c => normalized.push(c),
+		}
+	}
+	normalized
+}
+
+fn get_display_title(song: &Song) -> Option<String> {
+	match (&song.artist, &song.title) {
+		(Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+		(None, Some(title)) => Some(title.clone()),
+		_ => None,
+	}
+}
+
+fn write_m3u(songs: &[Song]) -> String {
+	let mut content = String::from("#EXTM3U\n");
+	for song in songs {
+		if let Some(title) = get_display_title(song) {
+			content.push_str(&format!(
+				"#EXTINF:{},{}\n",
+				song.duration.unwrap_or(-1),
+				title
+			));
+		}
+		content.push_str(&song.path);
+		content.push('\n');
+	}
+	content
+}
+
+fn write_pls(songs: &[Song]) -> String {
+	let mut content = String::from("[playlist]\n");
+	for (i, song) in songs.iter().enumerate() {
+		let n = i + 1;
+		content.push_str(&format!("File{}={}\n", n, song.path));
+		if let Some(title) = get_display_title(song) {
+			content.push_str(&format!("Title{}={}\n", n, title));
+		}
+		content.push_str(&format!("Length{}={}\n", n, song.duration.unwrap_or(-1)));
+	}
+	content.push_str(&format!("NumberOfEntries={}\nVersion=2\n", songs.len()));
+	content
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn reads_m3u_entries() {
+		let content = "#EXTM3U\n#EXTINF:123,Artist - Title\nsongs/a.mp3\n\n../b.mp3\nhttp://radio.example/stream\n";
+		let base: PathBuf = ["music", "playlists", "list.m3u"].iter().collect();
+		let expected: Vec<PathBuf> = vec![
+			["music", "playlists", "songs", "a.mp3"].iter().collect(),
+			["music", "b.mp3"].iter().collect(),
+		];
+		assert_eq!(read(&base, content), expected);
+	}
+
+	#[test]
+	fn reads_pls_entries_in_order() {
+		let content = "[playlist]\nFile2=b.mp3\nTitle2=B\nFile1=a.mp3\nNumberOfEntries=2\n";
+		let base: PathBuf = ["music", "list.pls"].iter().collect();
+		let expected: Vec<PathBuf> = vec![
+			["music", "a.mp3"].iter().collect(),
+			["music", "b.mp3"].iter().collect(),
+		];
+		assert_eq!(read(&base, content), expected);
+	}
+}
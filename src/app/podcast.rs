@@ -0,0 +1,679 @@
+use diesel::prelude::*;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::app::outbound;
+use crate::db::{self, podcast_episodes, podcasts, users, DB};
+
+mod feed;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Subscribing to a podcast with automatic downloads should not fetch its entire back catalog
+const MAX_AUTOMATIC_DOWNLOADS: usize = 3;
+const MAX_EPISODE_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Podcast not found")]
+	PodcastNotFound,
+	#[error("Podcast episode not found")]
+	EpisodeNotFound,
+	#[error("Podcast episode is larger than {} bytes", MAX_EPISODE_SIZE)]
+	EpisodeTooLarge,
+	#[error("Already subscribed to this podcast")]
+	AlreadySubscribed,
+	#[error("Podcast feeds must be http or https URLs")]
+	InvalidUrl,
+	#[error("Could not parse podcast feed")]
+	InvalidFeed,
+	#[error("Podcast request failed with HTTP status code `{0}`")]
+	RequestFailed(u16),
+	#[error("Podcast request failed due to a transport error")]
+	RequestTransport,
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, io::Error),
+}
+
+impl From<ureq::Error> for Error {
+	fn from(error: ureq::Error) -> Self {
+		match error {
+			ureq::Error::Status(code, _) => Error::RequestFailed(code),
+			ureq::Error::Transport(_) => Error::RequestTransport,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Podcast {
+	pub id: i32,
+	pub url: String,
+	pub title: String,
+	pub description: Option<String>,
+	pub artwork_url: Option<String>,
+	pub auto_download: bool,
+	pub last_refresh: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Episode {
+	pub id: i32,
+	pub podcast: i32,
+	pub title: String,
+	pub description: Option<String>,
+	pub publication_date: Option<i64>,
+	pub duration: Option<i32>,
+	pub position_seconds: i32,
+	pub completed: bool,
+	pub downloaded: bool,
+}
+
+/// Where the audio of an episode can be read from.
+pub enum Audio {
+	Cached {
+		path: PathBuf,
+		mime_type: Option<String>,
+	},
+	Remote(String),
+}
+
+type PodcastRow = (
+	i32,
+	String,
+	String,
+	Option<String>,
+	Option<String>,
+	bool,
+	Option<i64>,
+);
+
+const PODCAST_COLUMNS: (
+	podcasts::id,
+	podcasts::url,
+	podcasts::title,
+	podcasts::description,
+	podcasts::artwork_url,
+	podcasts::auto_download,
+	podcasts::last_refresh,
+) = (
+	podcasts::id,
+	podcasts::url,
+	podcasts::title,
+	podcasts::description,
+	podcasts::artwork_url,
+	podcasts::auto_download,
+	podcasts::last_refresh,
+);
+
+type EpisodeRow = (
+	i32,
+	i32,
+	String,
+	Option<String>,
+	Option<i64>,
+	Option<i32>,
+	i32,
+	bool,
+);
+
+const EPISODE_COLUMNS: (
+	podcast_episodes::id,
+	podcast_episodes::podcast,
+	podcast_episodes::title,
+	podcast_episodes::description,
+	podcast_episodes::publication_date,
+	podcast_episodes::duration,
+	podcast_episodes::position_seconds,
+	podcast_episodes::completed,
+) = (
+	podcast_episodes::id,
+	podcast_episodes::podcast,
+	podcast_episodes::title,
+	podcast_episodes::description,
+	podcast_episodes::publication_date,
+	podcast_episodes::duration,
+	podcast_episodes::position_seconds,
+	podcast_episodes::completed,
+);
+
+impl From<PodcastRow> for Podcast {
+	fn from(row: PodcastRow) -> Self {
+		let (id, url, title, description, artwork_url, auto_download, last_refresh) = row;
+		Self {
+			id,
+			url,
+			title,
+			description,
+			artwork_url,
+			auto_download,
+			last_refresh,
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	episodes_dir_path: PathBuf,
+	// Feeds are supplied by users, so they must not reach the server's own network
+	agent: ureq::Agent,
+}
+
+impl Manager {
+	pub fn new(db: DB, episodes_dir_path: PathBuf) -> Self {
+		Self {
+			db,
+			episodes_dir_path,
+			agent: outbound::public_agent(),
+		}
+	}
+
+	pub fn subscribe(
+		&self,
+		username: &str,
+		url: &str,
+		auto_download: bool,
+	) -> Result<Podcast, Error> {
+		match url::Url::parse(url) {
+			Ok(u) if u.scheme() == "http" || u.scheme() == "https" => (),
+			_ => return Err(Error::InvalidUrl),
+		}
+		let feed = self.fetch_feed(url)?;
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let existing = podcasts::table
+			.filter(podcasts::owner.eq(user_id).and(podcasts::url.eq(url)))
+			.count()
+			.get_result::<i64>(&mut connection)?;
+		if existing > 0 {
+			return Err(Error::AlreadySubscribed);
+		}
+		diesel::insert_into(podcasts::table)
+			.values((
+				podcasts::owner.eq(user_id),
+				podcasts::url.eq(url),
+				podcasts::title.eq(&feed.title),
+				podcasts::auto_download.eq(auto_download),
+			))
+			.execute(&mut connection)?;
+		let podcast_id = podcasts::table
+			.filter(podcasts::owner.eq(user_id).and(podcasts::url.eq(url)))
+			.select(podcasts::id)
+			.first::<i32>(&mut connection)?;
+		drop(connection);
+
+		self.apply_feed(podcast_id, &feed)?;
+		self.get_podcast(username, podcast_id)
+	}
+
+	pub fn unsubscribe(&self, username: &str, podcast_id: i32) -> Result<(), Error> {
+		let episodes = self.list_episodes(username, podcast_id)?;
+		let mut connection = self.db.connect()?;
+		diesel::delete(podcasts::table.filter(podcasts::id.eq(podcast_id)))
+			.execute(&mut connection)?;
+		for episode in episodes.iter().filter(|e| e.downloaded) {
+			self.remove_cached_file(episode.id)?;
+		}
+		Ok(())
+	}
+
+	pub fn list_podcasts(&self, username: &str) -> Result<Vec<Podcast>, Error> {
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let rows: Vec<PodcastRow> = podcasts::table
+			.filter(podcasts::owner.eq(user_id))
+			.order(podcasts::title)
+			.select(PODCAST_COLUMNS)
+			.load(&mut connection)?;
+		Ok(rows.into_iter().map(Podcast::from).collect())
+	}
+
+	pub fn get_podcast(&self, username: &str, podcast_id: i32) -> Result<Podcast, Error> {
+		let mut connection = self.db.connect()?;
+		let row: PodcastRow = podcasts::table
+			.inner_join(users::table)
+			.filter(podcasts::id.eq(podcast_id).and(users::name.eq(username)))
+			.select(PODCAST_COLUMNS)
+			.first(&mut connection)
+			.optional()?
+			.ok_or(Error::PodcastNotFound)?;
+		Ok(row.into())
+	}
+
+	/// Episodes of a podcast, most recent first.
+	pub fn list_episodes(&self, username: &str, podcast_id: i32) -> Result<Vec<Episode>, Error> {
+		self.get_podcast(username, podcast_id)?;
+		let mut connection = self.db.connect()?;
+		let rows: Vec<EpisodeRow> = podcast_episodes::table
+			.filter(podcast_episodes::podcast.eq(podcast_id))
+			.order((
+				podcast_episodes::publication_date.desc(),
+				podcast_episodes::id.desc(),
+			))
+			.select(EPISODE_COLUMNS)
+			.load(&mut connection)?;
+		Ok(rows.into_iter().map(|r| self.make_episode(r)).collect())
+	}
+
+	pub fn refresh(&self, username: &str, podcast_id: i32) -> Result<Podcast, Error> {
+		let podcast = self.get_podcast(username, podcast_id)?;
+		let feed = self.fetch_feed(&podcast.url)?;
+		self.apply_feed(podcast_id, &feed)?;
+		self.get_podcast(username, podcast_id)
+	}
+
+	pub fn set_position(
+		&self,
+		username: &str,
+		episode_id: i32,
+		position_seconds: u32,
+		completed: bool,
+	) -> Result<(), Error> {
+		self.check_episode_owner(username, episode_id)?;
+		let mut connection = self.db.connect()?;
+		diesel::update(podcast_episodes::table.filter(podcast_episodes::id.eq(episode_id)))
+			.set((
+				podcast_episodes::position_seconds.eq(position_seconds.min(i32::MAX as u32) as i32),
+				podcast_episodes::completed.eq(completed),
+			))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
+	/// Stores an episode in the cache directory, so it can be played without reaching its host.
+	pub fn download(&self, username: &str, episode_id: i32) -> Result<(), Error> {
+		self.check_episode_owner(username, episode_id)?;
+		self.download_episode(episode_id)
+	}
+
+	pub fn remove_download(&self, username: &str, episode_id: i32) -> Result<(), Error> {
+		self.check_episode_owner(username, episode_id)?;
+		self.remove_cached_file(episode_id)
+	}
+
+	pub fn get_audio(&self, username: &str, episode_id: i32) -> Result<Audio, Error> {
+		self.check_episode_owner(username, episode_id)?;
+		let mut connection = self.db.connect()?;
+		let (audio_url, mime_type): (String, Option<String>) = podcast_episodes::table
+			.filter(podcast_episodes::id.eq(episode_id))
+			.select((podcast_episodes::audio_url, podcast_episodes::mime_type))
+			.first(&mut connection)?;
+		let path = self.get_cached_path(episode_id);
+		match path.exists() {
+			true => Ok(Audio::Cached { path, mime_type }),
+			false => Ok(Audio::Remote(audio_url)),
+		}
+	}
+
+	pub fn begin_periodic_refresh(&self) {
+		let manager = self.clone();
+		std::thread::spawn(move || loop {
+			if let Err(e) = manager.refresh_all() {
+				error!("Error while refreshing podcasts: {}", e);
+			}
+			std::thread::sleep(REFRESH_INTERVAL);
+		});
+	}
+
+	fn refresh_all(&self) -> Result<(), Error> {
+		let podcasts: Vec<(i32, String)> = {
+			let mut connection = self.db.connect()?;
+			podcasts::table
+				.select((podcasts::id, podcasts::url))
+				.load(&mut connection)?
+		};
+		for (podcast_id, url) in podcasts {
+			// A single unreachable feed should not prevent others from refreshing
+			let result = self
+				.fetch_feed(&url)
+				.and_then(|feed| self.apply_feed(podcast_id, &feed));
+			if let Err(e) = result {
+				error!("Could not refresh podcast `{}`: {}", url, e);
+			}
+		}
+		Ok(())
+	}
+
+	// Saves feed metadata and new episodes, and downloads them if the podcast asks for it
+	fn apply_feed(&self, podcast_id: i32, feed: &feed::Feed) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		let new_episode_ids = connection.transaction::<_, Error, _>(|connection| {
+			diesel::update(podcasts::table.filter(podcasts::id.eq(podcast_id)))
+				.set((
+					podcasts::title.eq(&feed.title),
+					podcasts::description.eq(&feed.description),
+					podcasts::artwork_url.eq(&feed.artwork_url),
+					podcasts::last_refresh.eq(now()),
+				))
+				.execute(connection)?;
+
+			let mut new_episode_ids = Vec::new();
+			for episode in &feed.episodes {
+				let inserted = diesel::insert_or_ignore_into(podcast_episodes::table)
+					.values((
+						podcast_episodes::podcast.eq(podcast_id),
+						podcast_episodes::guid.eq(&episode.guid),
+						podcast_episodes::title.eq(&episode.title),
+						podcast_episodes::description.eq(&episode.description),
+						podcast_episodes::audio_url.eq(&episode.audio_url),
+						podcast_episodes::mime_type.eq(&episode.mime_type),
+						podcast_episodes::publication_date.eq(episode.publication_date),
+						podcast_episodes::duration.eq(episode.duration),
+					))
+					.execute(connection)?;
+				if inserted > 0 {
+					let id = podcast_episodes::table
+						.filter(
+							podcast_episodes::podcast
+								.eq(podcast_id)
+								.and(podcast_episodes::guid.eq(&episode.guid)),
+						)
+						.select(podcast_episodes::id)
+						.first::<i32>(connection)?;
+					new_episode_ids.push((episode.publication_date, id));
+				}
+			}
+			Ok(new_episode_ids)
+		})?;
+
+		let auto_download = podcasts::table
+			.filter(podcasts::id.eq(podcast_id))
+			.select(podcasts::auto_download)
+			.first::<bool>(&mut connection)?;
+		drop(connection);
+		if auto_download {
+			let mut new_episode_ids = new_episode_ids;
+			new_episode_ids.sort_by(|a, b| b.cmp(a));
+			for (_, episode_id) in new_episode_ids.into_iter().take(MAX_AUTOMATIC_DOWNLOADS) {
+				if let Err(e) = self.download_episode(episode_id) {
+					error!("Could not download podcast episode: {}", e);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn download_episode(&self, episode_id: i32) -> Result<(), Error> {
+		let path = self.get_cached_path(episode_id);
+		if path.exists() {
+			return Ok(());
+		}
+		let audio_url = {
+			let mut connection = self.db.connect()?;
+			podcast_episodes::table
+				.filter(podcast_episodes::id.eq(episode_id))
+				.select(podcast_episodes::audio_url)
+				.first::<String>(&mut connection)
+				.optional()?
+				.ok_or(Error::EpisodeNotFound)?
+		};
+		info!("Downloading podcast episode from {}", audio_url);
+
+		fs::create_dir_all(&self.episodes_dir_path)
+			.map_err(|e| Error::Io(self.episodes_dir_path.clone(), e))?;
+		// Downloads go to a temporary file so interrupted ones are not mistaken for cached episodes
+		let partial_path = path.with_extension("part");
+		let response = self.agent.get(&audio_url).call()?;
+		let mut file =
+			fs::File::create(&partial_path).map_err(|e| Error::Io(partial_path.clone(), e))?;
+		let mut reader = response.into_reader().take(MAX_EPISODE_SIZE + 1);
+		let size = io::copy(&mut reader, &mut file).map_err(|e| {
+			let _ = fs::remove_file(&partial_path);
+			Error::Io(partial_path.clone(), e)
+		})?;
+		if size > MAX_EPISODE_SIZE {
+			let _ = fs::remove_file(&partial_path);
+			return Err(Error::EpisodeTooLarge);
+		}
+		fs::rename(&partial_path, &path).map_err(|e| Error::Io(path.clone(), e))
+	}
+
+	fn fetch_feed(&self, url: &str) -> Result<feed::Feed, Error> {
+		let body = self
+			.agent
+			.get(url)
+			.call()?
+			.into_string()
+			.map_err(|_| Error::RequestTransport)?;
+		feed::parse(&body).ok_or(Error::InvalidFeed)
+	}
+
+	fn remove_cached_file(&self, episode_id: i32) -> Result<(), Error> {
+		let path = self.get_cached_path(episode_id);
+		match fs::remove_file(&path) {
+			Ok(()) => Ok(()),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(Error::Io(path, e)),
+		}
+	}
+
+	fn get_cached_path(&self, episode_id: i32) -> PathBuf {
+		self.episodes_dir_path.join(episode_id.to_string())
+	}
+
+	fn make_episode(&self, row: EpisodeRow) -> Episode {
+		let (
+			id,
+			podcast,
+			title,
+			description,
+			publication_date,
+			duration,
+			position_seconds,
+			completed,
+		) = row;
+		Episode {
+			id,
+			podcast,
+			title,
+			description,
+			publication_date,
+			duration,
+			position_seconds,
+			completed,
+			downloaded: self.get_cached_path(id).exists(),
+		}
+	}
+
+	fn check_episode_owner(&self, username: &str, episode_id: i32) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		podcast_episodes::table
+			.inner_join(podcasts::table.inner_join(users::table))
+			.filter(
+				podcast_episodes::id
+					.eq(episode_id)
+					.and(users::name.eq(username)),
+			)
+			.select(podcast_episodes::id)
+			.first::<i32>(&mut connection)
+			.optional()?
+			.ok_or(Error::EpisodeNotFound)?;
+		Ok(())
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+fn now() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{Read, Write};
+	use std::net::TcpListener;
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+
+	const FEED: &str = r#"<rss version="2.0"><channel>
+		<title>Test Podcast</title>
+		<item>
+			<title>Episode 1</title>
+			<guid>1</guid>
+			<pubDate>Mon, 09 Jun 2003 06:30:00 GMT</pubDate>
+			<enclosure url="{server}/episode.mp3" type="audio/mpeg"/>
+		</item>
+	</channel></rss>"#;
+
+	// Answers HTTP requests with the test feed, or with audio for any other path
+	fn serve_feed() -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let server = format!("http://{}", listener.local_addr().unwrap());
+		let feed = FEED.replace("{server}", &server);
+		std::thread::spawn(move || {
+			for mut stream in listener.incoming().flatten() {
+				let mut request = [0u8; 1024];
+				let length = stream.read(&mut request).unwrap_or(0);
+				let request = String::from_utf8_lossy(&request[..length]);
+				let body = match request.starts_with("GET /feed.xml") {
+					true => feed.clone(),
+					false => "audio".to_owned(),
+				};
+				let response = format!(
+					"HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+					body.len(),
+					body
+				);
+				let _ = stream.write_all(response.as_bytes());
+			}
+		});
+		server
+	}
+
+	fn build_manager(test_name: String) -> (test::Context, Manager) {
+		let ctx = test::ContextBuilder::new(test_name)
+			.user(TEST_USER, "password", false)
+			.build();
+		let manager = Manager::new(ctx.db.clone(), ctx.test_directory.join("podcasts"));
+		// The test feed is served locally
+		let manager = Manager {
+			agent: ureq::agent(),
+			..manager
+		};
+		(ctx, manager)
+	}
+
+	#[test]
+	fn subscribe_and_play_episode() {
+		let (_ctx, manager) = build_manager(test_name!());
+		let server = serve_feed();
+		let url = format!("{server}/feed.xml");
+
+		let podcast = manager.subscribe(TEST_USER, &url, false).unwrap();
+		assert_eq!(podcast.title, "Test Podcast");
+		assert!(matches!(
+			manager.subscribe(TEST_USER, &url, false),
+			Err(Error::AlreadySubscribed)
+		));
+		assert_eq!(
+			manager.list_podcasts(TEST_USER).unwrap(),
+			vec![podcast.clone()]
+		);
+
+		let episodes = manager.list_episodes(TEST_USER, podcast.id).unwrap();
+		assert_eq!(episodes.len(), 1);
+		let episode = &episodes[0];
+		assert_eq!(episode.title, "Episode 1");
+		assert!(!episode.downloaded);
+		assert!(matches!(
+			manager.get_audio(TEST_USER, episode.id),
+			Ok(Audio::Remote(u)) if u == format!("{server}/episode.mp3")
+		));
+
+		manager
+			.set_position(TEST_USER, episode.id, 42, false)
+			.unwrap();
+		manager.download(TEST_USER, episode.id).unwrap();
+		let episodes = manager.list_episodes(TEST_USER, podcast.id).unwrap();
+		assert_eq!(episodes[0].position_seconds, 42);
+		assert!(episodes[0].downloaded);
+		let Audio::Cached { path, mime_type } = manager.get_audio(TEST_USER, episode.id).unwrap()
+		else {
+			panic!("Episode should be cached");
+		};
+		assert_eq!(fs::read_to_string(&path).unwrap(), "audio");
+		assert_eq!(mime_type.as_deref(), Some("audio/mpeg"));
+
+		// Refreshing does not duplicate known episodes
+		manager.refresh(TEST_USER, podcast.id).unwrap();
+		assert_eq!(
+			manager.list_episodes(TEST_USER, podcast.id).unwrap().len(),
+			1
+		);
+
+		manager.unsubscribe(TEST_USER, podcast.id).unwrap();
+		assert!(manager.list_podcasts(TEST_USER).unwrap().is_empty());
+		assert!(!path.exists());
+	}
+
+	#[test]
+	fn auto_download_caches_new_episodes() {
+		let (_ctx, manager) = build_manager(test_name!());
+		let server = serve_feed();
+
+		let podcast = manager
+			.subscribe(TEST_USER, &format!("{server}/feed.xml"), true)
+			.unwrap();
+		let episodes = manager.list_episodes(TEST_USER, podcast.id).unwrap();
+		assert!(episodes[0].downloaded);
+	}
+
+	#[test]
+	fn subscribe_refuses_local_addresses() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, "password", false)
+			.build();
+		let manager = Manager::new(ctx.db.clone(), ctx.test_directory.join("podcasts"));
+		let server = serve_feed();
+		assert!(matches!(
+			manager.subscribe(TEST_USER, &format!("{server}/feed.xml"), false),
+			Err(Error::RequestTransport)
+		));
+	}
+
+	#[test]
+	fn subscribe_rejects_non_http_urls() {
+		let (_ctx, manager) = build_manager(test_name!());
+		assert!(matches!(
+			manager.subscribe(TEST_USER, "file:///etc/passwd", false),
+			Err(Error::InvalidUrl)
+		));
+	}
+
+	#[test]
+	fn episodes_are_private() {
+		let (_ctx, manager) = build_manager(test_name!());
+		assert!(matches!(
+			manager.set_position(TEST_USER, 1, 0, true),
+			Err(Error::EpisodeNotFound)
+		));
+		assert!(matches!(
+			manager.list_episodes("someone_else", 1),
+			Err(Error::PodcastNotFound)
+		));
+	}
+}
@@ -0,0 +1,360 @@
+// Minimal RSS reader covering the subset of the format podcast feeds rely on
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Feed {
+	pub title: String,
+	pub description: Option<String>,
+	pub artwork_url: Option<String>,
+	pub episodes: Vec<Episode>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Episode {
+	pub guid: String,
+	pub title: String,
+	pub description: Option<String>,
+	pub audio_url: String,
+	pub mime_type: Option<String>,
+	pub publication_date: Option<i64>,
+	pub duration: Option<i32>,
+}
+
+pub fn parse(xml: &str) -> Option<Feed> {
+	let (_, channel) = find_element(xml, "channel")?;
+	let header = match channel.find("<item") {
+		Some(i) => &channel[..i],
+		None => channel,
+	};
+
+	let title = get_text(header, "title")?;
+	let description =
+		get_text(header, "description").or_else(|| get_text(header, "itunes:summary"));
+	let artwork_url = find_element(header, "itunes:image")
+		.and_then(|(attributes, _)| get_attribute(attributes, "href"))
+		.or_else(|| {
+			let (_, image) = find_element(header, "image")?;
+			get_text(image, "url")
+		});
+
+	let mut episodes = Vec::new();
+	let mut rest = channel;
+	while let Some((_, item, remainder)) = next_element(rest, "item") {
+		if let Some(episode) = parse_episode(item) {
+			episodes.push(episode);
+		}
+		rest = remainder;
+	}
+
+	Some(Feed {
+		title,
+		description,
+		artwork_url,
+		episodes,
+	})
+}
+
+// Items without an audio enclosure are not episodes
+fn parse_episode(item: &str) -> Option<Episode> {
+	let (attributes, _) = find_element(item, "enclosure")?;
+	let audio_url = get_attribute(attributes, "url")?;
+	let mime_type = get_attribute(attributes, "type");
+	let guid = get_text(item, "guid").unwrap_or_else(|| audio_url.clone());
+	Some(Episode {
+		title: get_text(item, "title").unwrap_or_else(|| guid.clone()),
+		guid,
+		description: get_text(item, "description").or_else(|| get_text(item, "itunes:summary")),
+		audio_url,
+		mime_type,
+		publication_date: get_text(item, "pubDate").and_then(|d| parse_date(&d)),
+		duration: get_text(item, "itunes:duration").and_then(|d| parse_duration(&d)),
+	})
+}
+
+// Returns the attributes and content of the first element named `name`
+fn find_element<'a>(xml: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+	next_element(xml, name).map(|(attributes, content, _)| (attributes, content))
+}
+
+// Returns the attributes and content of the first element named `name`, and the text following it
+fn next_element<'a>(xml: &'a str, name: &str) -> Option<(&'a str, &'a str, &'a str)> {
+	let start = find_open_tag(xml, name)?;
+	let tag = &xml[start + name.len() + 1..];
+	let tag_end = tag.find('>')?;
+	let attributes = &tag[..tag_end];
+	if let Some(attributes) = attributes.strip_suffix('/') {
+		return Some((attributes, "", &tag[tag_end + 1..]));
+	}
+	let content = &tag[tag_end + 1..];
+	let closing_tag = format!("</{name}>");
+	let end = find_outside_cdata(content, &closing_tag)?;
+	Some((
+		attributes,
+		&content[..end],
+		&content[end + closing_tag.len()..],
+	))
+}
+
+fn find_open_tag(xml: &str, name: &str) -> Option<usize> {
+	let pattern = format!("<{name}");
+	let mut offset = 0;
+	while let Some(i) = find_outside_cdata(&xml[offset..], &pattern) {
+		let start = offset + i;
+		let next = xml[start + pattern.len()..].chars().next();
+		if next.is_some_and(|c| c == '>' || c == '/' || c.is_whitespace()) {
+			return Some(start);
+		}
+		offset = start + pattern.len();
+	}
+	None
+}
+
+// Episode descriptions often embed HTML within CDATA sections, which must not be parsed
+fn find_outside_cdata(xml: &str, pattern: &str) -> Option<usize> {
+	let mut offset = 0;
+	loop {
+		let rest = &xml[offset..];
+		let found = rest.find(pattern)?;
+		match rest.find("<![CDATA[") {
+			Some(cdata) if cdata < found => {
+				let cdata_end = rest[cdata..].find("]]>")?;
+				offset += cdata + cdata_end + 3;
+			}
+			_ => return Some(offset + found),
+		}
+	}
+}
+
+fn get_attribute(attributes: &str, name: &str) -> Option<String> {
+	let mut rest = attributes;
+	loop {
+		let i = rest.find(name)?;
+		let preceded_by_space = rest[..i].chars().last().is_none_or(|c| c.is_whitespace());
+		let after = rest[i + name.len()..].trim_start();
+		if preceded_by_space {
+			if let Some(value) = after.strip_prefix('=') {
+				let value = value.trim_start();
+				let quote = value.chars().next()?;
+				if quote == '"' || quote == '\'' {
+					let end = value[1..].find(quote)?;
+					return Some(unescape(&value[1..end + 1]));
+				}
+			}
+		}
+		rest = &rest[i + name.len()..];
+	}
+}
+
+fn get_text(xml: &str, name: &str) -> Option<String> {
+	let (_, content) = find_element(xml, name)?;
+	let text = match content.trim().strip_prefix("<![CDATA[") {
+		Some(cdata) => cdata.trim_end().trim_end_matches("]]>").to_owned(),
+		None => unescape(content),
+	};
+	let text = text.trim();
+	(!text.is_empty()).then(|| text.to_owned())
+}
+
+fn unescape(text: &str) -> String {
+	let mut unescaped = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(i) = rest.find('&') {
+		unescaped.push_str(&rest[..i]);
+		rest = &rest[i..];
+		let Some(end) = rest.find(';').filter(|e| *e <= 10) else {
+			unescaped.push('&');
+			rest = &rest[1..];
+			continue;
+		};
+		let entity = &rest[1..end];
+		let c = match entity {
+			"amp" => Some('&'),
+			"lt" => Some('<'),
+			"gt" => Some('>'),
+			"quot" => Some('"'),
+			"apos" => Some('\''),
+			_ => entity
+				.strip_prefix("#x")
+				.or_else(|| entity.strip_prefix("#X"))
+				.map(|hex| u32::from_str_radix(hex, 16))
+				.or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+				.and_then(|n| n.ok())
+				.and_then(char::from_u32),
+		};
+		match c {
+			Some(c) => {
+				unescaped.push(c);
+				rest = &rest[end + 1..];
+			}
+			None => {
+				unescaped.push('&');
+				rest = &rest[1..];
+			}
+		}
+	}
+	unescaped.push_str(rest);
+	unescaped
+}
+
+// Parses RFC 2822 dates, like `Tue, 10 Jun 2003 04:00:00 GMT`, into seconds since epoch
+fn parse_date(date: &str) -> Option<i64> {
+	let date = match date.split_once(',') {
+		Some((_, d)) => d,
+		None => date,
+	};
+	let mut parts = date.split_whitespace();
+	let day: i64 = parts.next()?.parse().ok()?;
+	let month = match parts.next()?.to_ascii_lowercase().get(..3)? {
+		"jan" => 1,
+		"feb" => 2,
+		"mar" => 3,
+		"apr" => 4,
+		"may" => 5,
+		"jun" => 6,
+		"jul" => 7,
+		"aug" => 8,
+		"sep" => 9,
+		"oct" => 10,
+		"nov" => 11,
+		"dec" => 12,
+		_ => return None,
+	};
+	let year: i64 = match parts.next()?.parse().ok()? {
+		y @ 0..=49 => 2000 + y,
+		y @ 50..=99 => 1900 + y,
+		y => y,
+	};
+	let mut time = parts.next()?.split(':');
+	let hours: i64 = time.next()?.parse().ok()?;
+	let minutes: i64 = time.next()?.parse().ok()?;
+	let seconds: i64 = time.next().map_or(Some(0), |s| s.parse().ok())?;
+	let offset = parts.next().map_or(0, parse_zone_offset);
+
+	Some(days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+// Seconds east of UTC
+fn parse_zone_offset(zone: &str) -> i64 {
+	let sign = match zone.chars().next() {
+		Some('+') => 1,
+		Some('-') => -1,
+		_ => {
+			return match zone {
+				"EDT" => -4,
+				"EST" | "CDT" => -5,
+				"CST" | "MDT" => -6,
+				"MST" | "PDT" => -7,
+				"PST" => -8,
+				_ => 0,
+			} * 3600
+		}
+	};
+	let digits = &zone[1..];
+	let hours: i64 = digits.get(..2).and_then(|h| h.parse().ok()).unwrap_or(0);
+	let minutes: i64 = digits.get(2..4).and_then(|m| m.parse().ok()).unwrap_or(0);
+	sign * (hours * 3600 + minutes * 60)
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let month_index = (month + 9) % 12;
+	let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146097 + day_of_era - 719468
+}
+
+// Durations are either a number of seconds or formatted as `[HH:]MM:SS`
+fn parse_duration(duration: &str) -> Option<i32> {
+	let mut seconds = 0;
+	for part in duration.trim().split(':') {
+		let value: f64 = part.parse().ok()?;
+		seconds = seconds * 60 + value as i32;
+	}
+	Some(seconds)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+	<channel>
+		<title>Metal &amp; More</title>
+		<description><![CDATA[<p>Weekly <b>heavy</b> music</p>]]></description>
+		<itunes:image href="https://example.com/cover.jpg"/>
+		<item>
+			<title>Episode 2</title>
+			<guid isPermaLink="false">episode-2</guid>
+			<pubDate>Tue, 10 Jun 2003 04:00:00 GMT</pubDate>
+			<itunes:duration>1:02:03</itunes:duration>
+			<enclosure url="https://example.com/2.mp3?a=1&amp;b=2" length="1234" type="audio/mpeg"/>
+		</item>
+		<item>
+			<title>Announcement</title>
+		</item>
+		<item>
+			<title>Episode 1</title>
+			<description>It&#39;s the first one</description>
+			<pubDate>Mon, 09 Jun 2003 06:30:00 +0200</pubDate>
+			<itunes:duration>95</itunes:duration>
+			<enclosure type="audio/mpeg" url="https://example.com/1.mp3"></enclosure>
+		</item>
+	</channel>
+</rss>"#;
+
+	#[test]
+	fn parses_feed() {
+		let feed = parse(FEED).unwrap();
+		assert_eq!(feed.title, "Metal & More");
+		assert_eq!(
+			feed.description.as_deref(),
+			Some("<p>Weekly <b>heavy</b> music</p>")
+		);
+		assert_eq!(
+			feed.artwork_url.as_deref(),
+			Some("https://example.com/cover.jpg")
+		);
+		assert_eq!(
+			feed.episodes,
+			vec![
+				Episode {
+					guid: "episode-2".to_owned(),
+					title: "Episode 2".to_owned(),
+					description: None,
+					audio_url: "https://example.com/2.mp3?a=1&b=2".to_owned(),
+					mime_type: Some("audio/mpeg".to_owned()),
+					publication_date: Some(1055217600),
+					duration: Some(3723),
+				},
+				Episode {
+					guid: "https://example.com/1.mp3".to_owned(),
+					title: "Episode 1".to_owned(),
+					description: Some("It's the first one".to_owned()),
+					audio_url: "https://example.com/1.mp3".to_owned(),
+					mime_type: Some("audio/mpeg".to_owned()),
+					publication_date: Some(1055133000),
+					duration: Some(95),
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn rejects_documents_without_channel() {
+		assert_eq!(parse("<html><title>Not a feed</title></html>"), None);
+	}
+
+	#[test]
+	fn parses_dates() {
+		assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+		assert_eq!(parse_date("1 Jan 1970 01:00 +0100"), Some(0));
+		assert_eq!(
+			parse_date("Wed, 29 Feb 2024 12:00:00 EST"),
+			Some(1709226000)
+		);
+		assert_eq!(parse_date("yesterday"), None);
+	}
+}
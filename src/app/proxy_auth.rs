@@ -0,0 +1,189 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Invalid trusted proxy address or network: `{0}`")]
+	InvalidTrustedProxy(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	#[serde(default = "default_header")]
+	pub header: String,
+	// Addresses like `127.0.0.1` or networks like `172.16.0.0/12`
+	pub trusted_proxies: Vec<String>,
+}
+
+fn default_header() -> String {
+	"X-Remote-User".to_owned()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Network {
+	address: IpAddr,
+	prefix_length: u32,
+}
+
+impl Network {
+	fn parse(network: &str) -> Result<Self, Error> {
+		let invalid = || Error::InvalidTrustedProxy(network.to_owned());
+		let (address, prefix_length) = match network.split_once('/') {
+			Some((address, prefix_length)) => (
+				address.parse::<IpAddr>().map_err(|_| invalid())?,
+				Some(prefix_length.parse::<u32>().map_err(|_| invalid())?),
+			),
+			None => (network.parse::<IpAddr>().map_err(|_| invalid())?, None),
+		};
+		let max_prefix_length = match address {
+			IpAddr::V4(_) => 32,
+			IpAddr::V6(_) => 128,
+		};
+		let prefix_length = prefix_length.unwrap_or(max_prefix_length);
+		if prefix_length > max_prefix_length {
+			return Err(invalid());
+		}
+		Ok(Self {
+			address,
+			prefix_length,
+		})
+	}
+
+	fn contains(&self, address: IpAddr) -> bool {
+		match (self.address, address) {
+			(IpAddr::V4(network), IpAddr::V4(address)) => {
+				let mask = u32::MAX.checked_shl(32 - self.prefix_length).unwrap_or(0);
+				u32::from(network) & mask == u32::from(address) & mask
+			}
+			(IpAddr::V6(network), IpAddr::V6(address)) => {
+				let mask = u128::MAX.checked_shl(128 - self.prefix_length).unwrap_or(0);
+				u128::from(network) & mask == u128::from(address) & mask
+			}
+			_ => false,
+		}
+	}
+}
+
+// Trusts a username header set by an authenticating reverse proxy (Authelia, Authentik...), but
+// only on requests coming directly from that proxy
+#[derive(Clone, Debug, Default)]
+pub struct Manager {
+	header: Option<String>,
+	trusted_networks: Vec<Network>,
+}
+
+impl Manager {
+	pub fn new(config: Option<&Config>) -> Result<Self, Error> {
+		let Some(config) = config else {
+			return Ok(Self::default());
+		};
+		let trusted_networks = config
+			.trusted_proxies
+			.iter()
+			.map(|n| Network::parse(n))
+			.collect::<Result<_, _>>()?;
+		Ok(Self {
+			header: Some(config.header.clone()),
+			trusted_networks,
+		})
+	}
+
+	pub fn header(&self) -> Option<&str> {
+		self.header.as_deref()
+	}
+
+	pub fn is_trusted_proxy(&self, address: IpAddr) -> bool {
+		self.trusted_networks.iter().any(|n| n.contains(address))
+	}
+
+	// Address of the client behind a chain of trusted proxies. Each proxy appends the address it
+	// received the request from to `X-Forwarded-For`, so hops are read from the right and only
+	// while the current one is trusted. Entries further left can be set by anyone.
+	pub fn get_client_address(&self, peer_address: IpAddr, forwarded_for: &str) -> IpAddr {
+		let mut address = peer_address;
+		for hop in forwarded_for.rsplit(',') {
+			if !self.is_trusted_proxy(address) {
+				break;
+			}
+			match hop.trim().parse::<IpAddr>() {
+				Ok(hop) => address = hop,
+				Err(_) => break,
+			}
+		}
+		address
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn make_manager(trusted_proxies: &[&str]) -> Result<Manager, Error> {
+		Manager::new(Some(&Config {
+			header: default_header(),
+			trusted_proxies: trusted_proxies.iter().map(|p| p.to_string()).collect(),
+		}))
+	}
+
+	#[test]
+	fn disabled_without_config() {
+		let manager = Manager::new(None).unwrap();
+		assert_eq!(manager.header(), None);
+		assert!(!manager.is_trusted_proxy("127.0.0.1".parse().unwrap()));
+	}
+
+	#[test]
+	fn trusts_addresses_and_networks() {
+		let manager = make_manager(&["127.0.0.1", "172.16.0.0/12", "fd00::/8"]).unwrap();
+		assert_eq!(manager.header(), Some("X-Remote-User"));
+		for address in ["127.0.0.1", "172.18.0.3", "fd12::1"] {
+			assert!(manager.is_trusted_proxy(address.parse().unwrap()));
+		}
+		for address in ["127.0.0.2", "172.32.0.1", "192.168.1.1", "::1"] {
+			assert!(!manager.is_trusted_proxy(address.parse().unwrap()));
+		}
+	}
+
+	#[test]
+	fn ignores_forwarded_addresses_from_untrusted_peers() {
+		let peer = "203.0.113.9".parse().unwrap();
+		for manager in [
+			Manager::new(None).unwrap(),
+			make_manager(&["10.0.0.0/8"]).unwrap(),
+		] {
+			assert_eq!(manager.get_client_address(peer, "192.168.1.5"), peer);
+		}
+	}
+
+	#[test]
+	fn reads_forwarded_addresses_from_trusted_proxies() {
+		let manager = make_manager(&["127.0.0.1", "10.0.0.0/8"]).unwrap();
+		let peer = "127.0.0.1".parse().unwrap();
+		let client = "203.0.113.9".parse::<IpAddr>().unwrap();
+		assert_eq!(manager.get_client_address(peer, ""), peer);
+		assert_eq!(manager.get_client_address(peer, "203.0.113.9"), client);
+		assert_eq!(
+			manager.get_client_address(peer, "203.0.113.9, 10.0.0.3"),
+			client
+		);
+		// Entries left of the first untrusted hop are spoofable and ignored
+		assert_eq!(
+			manager.get_client_address(peer, "192.168.1.5, 203.0.113.9"),
+			client
+		);
+		assert_eq!(
+			manager.get_client_address(peer, "192.168.1.5, garbage, 10.0.0.3"),
+			"10.0.0.3".parse::<IpAddr>().unwrap()
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_networks() {
+		for network in ["localhost", "10.0.0.0/33", "10.0.0.0/", "::1/129"] {
+			assert!(matches!(
+				make_manager(&[network]),
+				Err(Error::InvalidTrustedProxy(_))
+			));
+		}
+	}
+}
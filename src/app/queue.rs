@@ -0,0 +1,596 @@
+use diesel::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::index::Song;
+use crate::app::vfs;
+use crate::db::{self, play_queue_songs, play_queues, songs, users, DB};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Song not found: `{0}`")]
+	SongNotFound(PathBuf),
+	#[error("Play queue has no track at position {0}")]
+	TrackNotFound(usize),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+/// Songs a user is listening to, shared by all their devices.
+#[derive(Debug, Default, PartialEq)]
+pub struct PlayQueue {
+	pub songs: Vec<Song>,
+	pub current_track: Option<usize>,
+	pub position_seconds: u32,
+}
+
+/// What a user is listening to, as last reported by one of their devices.
+#[derive(Debug, Default, PartialEq)]
+pub struct Playback {
+	pub device: Option<String>,
+	pub playing: bool,
+	pub update_time: i64,
+	pub queue: PlayQueue,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlaybackReport {
+	pub device: Option<String>,
+	// Replaces the queued songs when set
+	pub tracks: Option<Vec<PathBuf>>,
+	pub current_track: Option<usize>,
+	pub position_seconds: u32,
+	pub playing: bool,
+}
+
+// Queued songs as real paths
+#[derive(Default)]
+struct QueueState {
+	paths: Vec<String>,
+	current_track: Option<usize>,
+	position_seconds: u32,
+	device: Option<String>,
+	playing: bool,
+	update_time: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = play_queue_songs)]
+struct NewQueueSong {
+	user: i32,
+	path: String,
+	ordering: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = play_queues)]
+struct NewQueue {
+	user: i32,
+	current_track: Option<i32>,
+	position_seconds: i32,
+	device: Option<String>,
+	playing: bool,
+	update_time: i64,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	pub fn get_queue(&self, username: &str) -> Result<PlayQueue, Error> {
+		Ok(self.read_playback(username)?.queue)
+	}
+
+	/// The queue of a user, with the position of its current track extrapolated to the present
+	/// time if it is playing.
+	pub fn get_playback(&self, username: &str) -> Result<Playback, Error> {
+		let mut playback = self.read_playback(username)?;
+		let queue = &mut playback.queue;
+		if let (true, Some(track)) = (playback.playing, queue.current_track) {
+			let elapsed = (now() - playback.update_time).max(0) as u32;
+			let mut position_seconds = queue.position_seconds.saturating_add(elapsed);
+			// Positions do not run past the end of the song
+			if let Some(duration) = queue.songs[track].duration.filter(|d| *d > 0) {
+				position_seconds =
+					position_seconds.min(queue.position_seconds.max(duration as u32));
+			}
+			queue.position_seconds = position_seconds;
+		}
+		Ok(playback)
+	}
+
+	pub fn report_playback(&self, username: &str, report: &PlaybackReport) -> Result<(), Error> {
+		self.edit(username, |connection, vfs, state| {
+			if let Some(tracks) = &report.tracks {
+				state.paths = get_real_paths(connection, vfs, tracks)?;
+			}
+			if let Some(index) = report.current_track.filter(|i| *i >= state.paths.len()) {
+				return Err(Error::TrackNotFound(index));
+			}
+			state.current_track = report.current_track;
+			state.position_seconds = match report.current_track {
+				Some(_) => report.position_seconds,
+				None => 0,
+			};
+			state.device = report.device.clone();
+			state.playing = report.playing && report.current_track.is_some();
+			state.update_time = now();
+			Ok(())
+		})
+	}
+
+	fn read_playback(&self, username: &str) -> Result<Playback, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let state = read_state(&mut connection, user_id)?;
+
+		let real_songs: Vec<Song> = songs::table
+			.filter(songs::path.eq_any(&state.paths))
+			.load(&mut connection)?;
+		let real_songs = real_songs
+			.into_iter()
+			.map(|s| (s.path.clone(), s))
+			.collect::<HashMap<_, _>>();
+
+		// Queued songs are not removed when they leave the index, so they are only listed if present
+		let mut songs = Vec::with_capacity(state.paths.len());
+		let mut current_track = None;
+		for (index, path) in state.paths.iter().enumerate() {
+			let Some(song) = real_songs
+				.get(path)
+				.and_then(|s| s.clone().virtualize(&vfs))
+			else {
+				continue;
+			};
+			if state.current_track == Some(index) {
+				current_track = Some(songs.len());
+			}
+			songs.push(song);
+		}
+
+		let queue = PlayQueue {
+			position_seconds: if current_track.is_some() {
+				state.position_seconds
+			} else {
+				0
+			},
+			songs,
+			current_track,
+		};
+		Ok(Playback {
+			device: state.device,
+			playing: state.playing && queue.current_track.is_some(),
+			update_time: state.update_time,
+			queue,
+		})
+	}
+
+	pub fn append(&self, username: &str, virtual_paths: &[PathBuf]) -> Result<(), Error> {
+		self.edit(username, |connection, vfs, state| {
+			let paths = get_real_paths(connection, vfs, virtual_paths)?;
+			state.paths.extend(paths);
+			Ok(())
+		})
+	}
+
+	/// Queues songs right after the current track, or at the start if nothing is playing.
+	pub fn insert_next(&self, username: &str, virtual_paths: &[PathBuf]) -> Result<(), Error> {
+		self.edit(username, |connection, vfs, state| {
+			let paths = get_real_paths(connection, vfs, virtual_paths)?;
+			let position = state.current_track.map_or(0, |t| t + 1);
+			state.paths.splice(position..position, paths);
+			Ok(())
+		})
+	}
+
+	pub fn remove(&self, username: &str, index: usize) -> Result<(), Error> {
+		self.edit(username, |_, _, state| {
+			if index >= state.paths.len() {
+				return Err(Error::TrackNotFound(index));
+			}
+			state.paths.remove(index);
+			match state.current_track {
+				// The next song becomes current
+				Some(current) if current == index => {
+					state.position_seconds = 0;
+					if current >= state.paths.len() {
+						state.current_track = None;
+					}
+				}
+				Some(current) if current > index => state.current_track = Some(current - 1),
+				_ => (),
+			}
+			Ok(())
+		})
+	}
+
+	pub fn move_track(&self, username: &str, from: usize, to: usize) -> Result<(), Error> {
+		self.edit(username, |_, _, state| {
+			for index in [from, to] {
+				if index >= state.paths.len() {
+					return Err(Error::TrackNotFound(index));
+				}
+			}
+			let path = state.paths.remove(from);
+			state.paths.insert(to, path);
+			state.current_track = state.current_track.map(|current| match current {
+				c if c == from => to,
+				c if from < c && c <= to => c - 1,
+				c if to <= c && c < from => c + 1,
+				c => c,
+			});
+			Ok(())
+		})
+	}
+
+	pub fn set_current_track(
+		&self,
+		username: &str,
+		index: Option<usize>,
+		position_seconds: u32,
+	) -> Result<(), Error> {
+		self.edit(username, |_, _, state| {
+			if let Some(index) = index.filter(|i| *i >= state.paths.len()) {
+				return Err(Error::TrackNotFound(index));
+			}
+			state.current_track = index;
+			state.position_seconds = if index.is_some() { position_seconds } else { 0 };
+			state.update_time = now();
+			Ok(())
+		})
+	}
+
+	pub fn clear(&self, username: &str) -> Result<(), Error> {
+		self.edit(username, |_, _, state| {
+			*state = QueueState::default();
+			Ok(())
+		})
+	}
+
+	fn edit<F>(&self, username: &str, operation: F) -> Result<(), Error>
+	where
+		F: FnOnce(&mut SqliteConnection, &vfs::VFS, &mut QueueState) -> Result<(), Error>,
+	{
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		connection.transaction::<_, Error, _>(|connection| {
+			let user_id = get_user_id(connection, username)?;
+			let mut state = read_state(connection, user_id)?;
+			operation(connection, &vfs, &mut state)?;
+			write_state(connection, user_id, &state)
+		})
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+fn get_real_paths(
+	connection: &mut SqliteConnection,
+	vfs: &vfs::VFS,
+	virtual_paths: &[PathBuf],
+) -> Result<Vec<String>, Error> {
+	let real_paths = virtual_paths
+		.iter()
+		.map(|p| {
+			vfs.virtual_to_real(p)
+				.map(|r| r.to_string_lossy().into_owned())
+				.map_err(|_| Error::SongNotFound(p.clone()))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let known_paths: HashSet<String> = songs::table
+		.filter(songs::path.eq_any(&real_paths))
+		.select(songs::path)
+		.load::<String>(connection)?
+		.into_iter()
+		.collect();
+	if let Some(index) = real_paths.iter().position(|p| !known_paths.contains(p)) {
+		return Err(Error::SongNotFound(virtual_paths[index].clone()));
+	}
+
+	Ok(real_paths)
+}
+
+type QueueRow = (Option<i32>, i32, Option<String>, bool, i64);
+
+fn read_state(connection: &mut SqliteConnection, user_id: i32) -> Result<QueueState, Error> {
+	let paths = play_queue_songs::table
+		.filter(play_queue_songs::user.eq(user_id))
+		.order(play_queue_songs::ordering)
+		.select(play_queue_songs::path)
+		.load(connection)?;
+	let (current_track, position_seconds, device, playing, update_time) = play_queues::table
+		.filter(play_queues::user.eq(user_id))
+		.select((
+			play_queues::current_track,
+			play_queues::position_seconds,
+			play_queues::device,
+			play_queues::playing,
+			play_queues::update_time,
+		))
+		.get_result::<QueueRow>(connection)
+		.optional()?
+		.unwrap_or_default();
+	Ok(QueueState {
+		paths,
+		current_track: current_track.map(|t| t as usize),
+		position_seconds: position_seconds.max(0) as u32,
+		device,
+		playing,
+		update_time,
+	})
+}
+
+fn write_state(
+	connection: &mut SqliteConnection,
+	user_id: i32,
+	state: &QueueState,
+) -> Result<(), Error> {
+	diesel::delete(play_queue_songs::table.filter(play_queue_songs::user.eq(user_id)))
+		.execute(connection)?;
+	let new_songs = state
+		.paths
+		.iter()
+		.enumerate()
+		.map(|(i, path)| NewQueueSong {
+			user: user_id,
+			path: path.clone(),
+			ordering: i as i32,
+		})
+		.collect::<Vec<_>>();
+	diesel::insert_into(play_queue_songs::table)
+		.values(&new_songs)
+		.execute(connection)?;
+	diesel::replace_into(play_queues::table)
+		.values(&NewQueue {
+			user: user_id,
+			current_track: state.current_track.map(|t| t as i32),
+			position_seconds: state.position_seconds as i32,
+			device: state.device.clone(),
+			playing: state.playing,
+			update_time: state.update_time,
+		})
+		.execute(connection)?;
+	Ok(())
+}
+
+fn now() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn song_path(file_name: &str) -> PathBuf {
+		[TEST_MOUNT_NAME, "Khemmis", "Hunted", file_name]
+			.iter()
+			.collect()
+	}
+
+	fn queued_paths(queue: &PlayQueue) -> Vec<PathBuf> {
+		queue.songs.iter().map(|s| PathBuf::from(&s.path)).collect()
+	}
+
+	fn build_context() -> test::Context {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+		ctx
+	}
+
+	#[test]
+	fn append_and_insert_next() {
+		let ctx = build_context();
+		let (a, b, c) = (
+			song_path("01 - Above The Water.mp3"),
+			song_path("02 - Candlelight.mp3"),
+			song_path("03 - Three Gates.mp3"),
+		);
+		let queue_manager = &ctx.queue_manager;
+		assert_eq!(
+			queue_manager.get_queue(TEST_USER).unwrap(),
+			PlayQueue::default()
+		);
+
+		queue_manager
+			.append(TEST_USER, &[a.clone(), b.clone()])
+			.unwrap();
+		queue_manager
+			.set_current_track(TEST_USER, Some(0), 42)
+			.unwrap();
+		queue_manager
+			.insert_next(TEST_USER, std::slice::from_ref(&c))
+			.unwrap();
+
+		let queue = queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queued_paths(&queue), vec![a, c, b]);
+		assert_eq!(queue.current_track, Some(0));
+		assert_eq!(queue.position_seconds, 42);
+	}
+
+	#[test]
+	fn remove_and_move_keep_current_track() {
+		let ctx = build_context();
+		let (a, b, c) = (
+			song_path("01 - Above The Water.mp3"),
+			song_path("02 - Candlelight.mp3"),
+			song_path("03 - Three Gates.mp3"),
+		);
+		let queue_manager = &ctx.queue_manager;
+		queue_manager
+			.append(TEST_USER, &[a.clone(), b.clone(), c.clone()])
+			.unwrap();
+		queue_manager
+			.set_current_track(TEST_USER, Some(1), 10)
+			.unwrap();
+
+		queue_manager.move_track(TEST_USER, 2, 0).unwrap();
+		let queue = queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queued_paths(&queue), vec![c.clone(), a.clone(), b.clone()]);
+		assert_eq!(queue.current_track, Some(2));
+
+		queue_manager.remove(TEST_USER, 0).unwrap();
+		let queue = queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queued_paths(&queue), vec![a.clone(), b]);
+		assert_eq!(queue.current_track, Some(1));
+		assert_eq!(queue.position_seconds, 10);
+
+		queue_manager.remove(TEST_USER, 1).unwrap();
+		let queue = queue_manager.get_queue(TEST_USER).unwrap();
+		assert_eq!(queued_paths(&queue), vec![a]);
+		assert_eq!(queue.current_track, None);
+		assert_eq!(queue.position_seconds, 0);
+	}
+
+	#[test]
+	fn report_playback_replaces_queue() {
+		let ctx = build_context();
+		let (a, b) = (
+			song_path("01 - Above The Water.mp3"),
+			song_path("02 - Candlelight.mp3"),
+		);
+		let queue_manager = &ctx.queue_manager;
+		queue_manager
+			.append(TEST_USER, std::slice::from_ref(&a))
+			.unwrap();
+		queue_manager
+			.report_playback(
+				TEST_USER,
+				&PlaybackReport {
+					device: Some("Desktop".to_owned()),
+					tracks: Some(vec![b.clone(), a.clone()]),
+					current_track: Some(1),
+					position_seconds: 30,
+					playing: false,
+				},
+			)
+			.unwrap();
+
+		let playback = queue_manager.get_playback(TEST_USER).unwrap();
+		assert_eq!(playback.device.as_deref(), Some("Desktop"));
+		assert!(!playback.playing);
+		assert!(playback.update_time > 0);
+		assert_eq!(queued_paths(&playback.queue), vec![b, a]);
+		assert_eq!(playback.queue.current_track, Some(1));
+		assert_eq!(playback.queue.position_seconds, 30);
+
+		assert!(matches!(
+			queue_manager.report_playback(
+				TEST_USER,
+				&PlaybackReport {
+					current_track: Some(2),
+					..Default::default()
+				}
+			),
+			Err(Error::TrackNotFound(2))
+		));
+	}
+
+	#[test]
+	fn playing_position_is_extrapolated() {
+		let ctx = build_context();
+		let song = song_path("01 - Above The Water.mp3");
+		let queue_manager = &ctx.queue_manager;
+		queue_manager
+			.report_playback(
+				TEST_USER,
+				&PlaybackReport {
+					tracks: Some(vec![song]),
+					current_track: Some(0),
+					position_seconds: 0,
+					playing: true,
+					..Default::default()
+				},
+			)
+			.unwrap();
+
+		// Pretend the report was sent an hour ago
+		let mut connection = ctx.db.connect().unwrap();
+		diesel::update(play_queues::table)
+			.set(play_queues::update_time.eq(now() - 3600))
+			.execute(&mut connection)
+			.unwrap();
+
+		let playback = queue_manager.get_playback(TEST_USER).unwrap();
+		assert!(playback.playing);
+		assert!(playback.queue.position_seconds >= 3600);
+
+		diesel::update(songs::table)
+			.set(songs::duration.eq(120))
+			.execute(&mut connection)
+			.unwrap();
+		let playback = queue_manager.get_playback(TEST_USER).unwrap();
+		assert_eq!(playback.queue.position_seconds, 120);
+		assert_eq!(
+			queue_manager.get_queue(TEST_USER).unwrap().position_seconds,
+			0
+		);
+	}
+
+	#[test]
+	fn invalid_edits_are_rejected() {
+		let ctx = build_context();
+		let queue_manager = &ctx.queue_manager;
+		queue_manager
+			.append(TEST_USER, &[song_path("02 - Candlelight.mp3")])
+			.unwrap();
+
+		assert!(matches!(
+			queue_manager.append(TEST_USER, &[song_path("missing.mp3")]),
+			Err(Error::SongNotFound(_))
+		));
+		assert!(matches!(
+			queue_manager.remove(TEST_USER, 1),
+			Err(Error::TrackNotFound(1))
+		));
+		assert!(matches!(
+			queue_manager.move_track(TEST_USER, 0, 3),
+			Err(Error::TrackNotFound(3))
+		));
+		assert!(matches!(
+			queue_manager.set_current_track(TEST_USER, Some(5), 0),
+			Err(Error::TrackNotFound(5))
+		));
+		assert_eq!(queue_manager.get_queue(TEST_USER).unwrap().songs.len(), 1);
+
+		queue_manager.clear(TEST_USER).unwrap();
+		assert_eq!(
+			queue_manager.get_queue(TEST_USER).unwrap(),
+			PlayQueue::default()
+		);
+	}
+}
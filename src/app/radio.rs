@@ -0,0 +1,359 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+use crate::app::{outbound, user};
+use crate::db::{self, radio_stations, users, DB};
+
+/// Lets the API relay radio streams, for stations which are not available over HTTPS.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	#[serde(default)]
+	pub proxy: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Radio station not found")]
+	StationNotFound,
+	#[error("Radio station name cannot be empty")]
+	EmptyName,
+	#[error("Radio streams must be http or https URLs")]
+	InvalidUrl,
+	#[error("Only administrators can manage shared radio stations")]
+	AdminPermissionRequired,
+	#[error("Radio proxy is not enabled")]
+	ProxyNotEnabled,
+	#[error("Radio stream request failed with HTTP status code `{0}`")]
+	RequestFailed(u16),
+	#[error("Radio stream request failed due to a transport error")]
+	RequestTransport,
+	#[error(transparent)]
+	User(#[from] user::Error),
+}
+
+impl From<ureq::Error> for Error {
+	fn from(error: ureq::Error) -> Self {
+		match error {
+			ureq::Error::Status(code, _) => Error::RequestFailed(code),
+			ureq::Error::Transport(_) => Error::RequestTransport,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Station {
+	pub id: i32,
+	pub name: String,
+	pub stream_url: String,
+	pub homepage_url: Option<String>,
+	// Shared stations are listed for every user
+	pub shared: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewStation {
+	pub name: String,
+	pub stream_url: String,
+	#[serde(default)]
+	pub homepage_url: Option<String>,
+	#[serde(default)]
+	pub shared: bool,
+}
+
+type StationRow = (i32, Option<i32>, String, String, Option<String>);
+
+pub struct Stream {
+	pub content_type: Option<String>,
+	pub reader: Box<dyn Read + Send + Sync>,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	user_manager: user::Manager,
+	config: Option<Config>,
+}
+
+impl Manager {
+	pub fn new(db: DB, user_manager: user::Manager, config: Option<Config>) -> Self {
+		Self {
+			db,
+			user_manager,
+			config,
+		}
+	}
+
+	pub fn list_stations(&self, username: &str) -> Result<Vec<Station>, Error> {
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let rows: Vec<StationRow> = radio_stations::table
+			.filter(
+				radio_stations::owner
+					.is_null()
+					.or(radio_stations::owner.eq(user_id)),
+			)
+			.order(radio_stations::name)
+			.select((
+				radio_stations::id,
+				radio_stations::owner,
+				radio_stations::name,
+				radio_stations::stream_url,
+				radio_stations::homepage_url,
+			))
+			.load(&mut connection)?;
+		Ok(rows
+			.into_iter()
+			.map(|(id, owner, name, stream_url, homepage_url)| Station {
+				id,
+				name,
+				stream_url,
+				homepage_url,
+				shared: owner.is_none(),
+			})
+			.collect())
+	}
+
+	pub fn add_station(&self, username: &str, new_station: &NewStation) -> Result<Station, Error> {
+		let name = new_station.name.trim();
+		if name.is_empty() {
+			return Err(Error::EmptyName);
+		}
+		if !is_http_url(&new_station.stream_url) {
+			return Err(Error::InvalidUrl);
+		}
+		if new_station.shared && !self.user_manager.is_admin(username)? {
+			return Err(Error::AdminPermissionRequired);
+		}
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let owner = (!new_station.shared).then_some(user_id);
+		let id = connection.transaction::<_, Error, _>(|connection| {
+			diesel::insert_into(radio_stations::table)
+				.values((
+					radio_stations::owner.eq(owner),
+					radio_stations::name.eq(name),
+					radio_stations::stream_url.eq(&new_station.stream_url),
+					radio_stations::homepage_url.eq(&new_station.homepage_url),
+				))
+				.execute(connection)?;
+			Ok(radio_stations::table
+				.select(radio_stations::id)
+				.order(radio_stations::id.desc())
+				.first(connection)?)
+		})?;
+		Ok(Station {
+			id,
+			name: name.to_owned(),
+			stream_url: new_station.stream_url.clone(),
+			homepage_url: new_station.homepage_url.clone(),
+			shared: new_station.shared,
+		})
+	}
+
+	pub fn remove_station(&self, username: &str, id: i32) -> Result<(), Error> {
+		let station = self.get_station(username, id)?;
+		if station.shared && !self.user_manager.is_admin(username)? {
+			return Err(Error::AdminPermissionRequired);
+		}
+		let mut connection = self.db.connect()?;
+		diesel::delete(radio_stations::table.filter(radio_stations::id.eq(id)))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
+	/// Connects to the stream of a station, so it can be relayed to clients. Only shared stations
+	/// can be relayed from local addresses.
+	pub fn open_stream(&self, username: &str, id: i32) -> Result<Stream, Error> {
+		if !self.config.as_ref().is_some_and(|c| c.proxy) {
+			return Err(Error::ProxyNotEnabled);
+		}
+		let station = self.get_station(username, id)?;
+		// Stations added by regular users could otherwise be used to read from the server's network
+		let agent = match station.shared {
+			true => ureq::agent(),
+			false => outbound::public_agent(),
+		};
+		let response = agent.get(&station.stream_url).call()?;
+		Ok(Stream {
+			content_type: response.header("Content-Type").map(str::to_owned),
+			reader: response.into_reader(),
+		})
+	}
+
+	fn get_station(&self, username: &str, id: i32) -> Result<Station, Error> {
+		self.list_stations(username)?
+			.into_iter()
+			.find(|s| s.id == id)
+			.ok_or(Error::StationNotFound)
+	}
+}
+
+fn is_http_url(url: &str) -> bool {
+	url::Url::parse(url).is_ok_and(|u| u.scheme() == "http" || u.scheme() == "https")
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+#[cfg(test)]
+mod test {
+	use std::io::{BufRead, BufReader, Write};
+	use std::net::TcpListener;
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_ADMIN: &str = "test_admin";
+
+	fn build_manager(test_name: String) -> (test::Context, Manager) {
+		let ctx = test::ContextBuilder::new(test_name)
+			.user(TEST_USER, "password", false)
+			.user(TEST_ADMIN, "password", true)
+			.build();
+		let manager = Manager::new(ctx.db.clone(), ctx.user_manager.clone(), None);
+		(ctx, manager)
+	}
+
+	fn new_station(name: &str, shared: bool) -> NewStation {
+		NewStation {
+			name: name.to_owned(),
+			stream_url: "http://example.com/stream".to_owned(),
+			homepage_url: None,
+			shared,
+		}
+	}
+
+	#[test]
+	fn private_stations_are_listed_for_owner_only() {
+		let (_ctx, manager) = build_manager(test_name!());
+		let station = manager
+			.add_station(TEST_USER, &new_station("Metal FM", false))
+			.unwrap();
+		assert_eq!(
+			manager.list_stations(TEST_USER).unwrap(),
+			vec![station.clone()]
+		);
+		assert!(manager.list_stations(TEST_ADMIN).unwrap().is_empty());
+		assert!(matches!(
+			manager.remove_station(TEST_ADMIN, station.id),
+			Err(Error::StationNotFound)
+		));
+		manager.remove_station(TEST_USER, station.id).unwrap();
+		assert!(manager.list_stations(TEST_USER).unwrap().is_empty());
+	}
+
+	#[test]
+	fn shared_stations_are_managed_by_admins() {
+		let (_ctx, manager) = build_manager(test_name!());
+		assert!(matches!(
+			manager.add_station(TEST_USER, &new_station("Metal FM", true)),
+			Err(Error::AdminPermissionRequired)
+		));
+		let station = manager
+			.add_station(TEST_ADMIN, &new_station("Metal FM", true))
+			.unwrap();
+		assert!(station.shared);
+		assert_eq!(
+			manager.list_stations(TEST_USER).unwrap(),
+			vec![station.clone()]
+		);
+		assert!(matches!(
+			manager.remove_station(TEST_USER, station.id),
+			Err(Error::AdminPermissionRequired)
+		));
+		manager.remove_station(TEST_ADMIN, station.id).unwrap();
+	}
+
+	#[test]
+	fn add_station_validates_input() {
+		let (_ctx, manager) = build_manager(test_name!());
+		assert!(matches!(
+			manager.add_station(TEST_USER, &new_station(" ", false)),
+			Err(Error::EmptyName)
+		));
+		let station = NewStation {
+			stream_url: "file:///dev/urandom".to_owned(),
+			..new_station("Noise", false)
+		};
+		assert!(matches!(
+			manager.add_station(TEST_USER, &station),
+			Err(Error::InvalidUrl)
+		));
+	}
+
+	#[test]
+	fn proxy_relays_stream() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap();
+		std::thread::spawn(move || {
+			if let Some(Ok(mut stream)) = listener.incoming().next() {
+				let mut request = BufReader::new(&stream);
+				let mut line = String::new();
+				while request.read_line(&mut line).is_ok_and(|n| n > 2) {
+					line.clear();
+				}
+				let _ = stream.write_all(
+					b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\nmusic",
+				);
+			}
+		});
+
+		let (_ctx, manager) = build_manager(test_name!());
+		let station = NewStation {
+			stream_url: format!("http://{address}/stream"),
+			..new_station("Metal FM", true)
+		};
+		let station = manager.add_station(TEST_ADMIN, &station).unwrap();
+		assert!(matches!(
+			manager.open_stream(TEST_USER, station.id),
+			Err(Error::ProxyNotEnabled)
+		));
+
+		let manager = Manager {
+			config: Some(Config { proxy: true }),
+			..manager
+		};
+		let mut stream = manager.open_stream(TEST_USER, station.id).unwrap();
+		assert_eq!(stream.content_type.as_deref(), Some("audio/mpeg"));
+		let mut content = String::new();
+		stream.reader.read_to_string(&mut content).unwrap();
+		assert_eq!(content, "music");
+	}
+
+	#[test]
+	fn proxy_refuses_local_addresses_of_private_stations() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap();
+
+		let (_ctx, manager) = build_manager(test_name!());
+		let manager = Manager {
+			config: Some(Config { proxy: true }),
+			..manager
+		};
+		let station = NewStation {
+			stream_url: format!("http://{address}/stream"),
+			..new_station("Metal FM", false)
+		};
+		let station = manager.add_station(TEST_USER, &station).unwrap();
+		assert!(matches!(
+			manager.open_stream(TEST_USER, station.id),
+			Err(Error::RequestTransport)
+		));
+	}
+}
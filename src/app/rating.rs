@@ -0,0 +1,197 @@
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::app::vfs;
+use crate::db::{self, ratings, songs, users, DB};
+
+pub const MIN_RATING: u8 = 1;
+pub const MAX_RATING: u8 = 5;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Song not found: `{0}`")]
+	SongNotFound(PathBuf),
+	#[error("`{0}` is not rated")]
+	RatingNotFound(PathBuf),
+	#[error("Ratings must be between {MIN_RATING} and {MAX_RATING}, not {0}")]
+	InvalidRating(u8),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = ratings)]
+struct NewRating {
+	user: i32,
+	path: String,
+	rating: i32,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	pub fn set_rating(&self, username: &str, virtual_path: &Path, rating: u8) -> Result<(), Error> {
+		if !(MIN_RATING..=MAX_RATING).contains(&rating) {
+			return Err(Error::InvalidRating(rating));
+		}
+
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let real_path = vfs.virtual_to_real(virtual_path)?;
+		let real_path_string = real_path.to_string_lossy().into_owned();
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let num_songs: i64 = songs::table
+			.filter(songs::path.eq(&real_path_string))
+			.count()
+			.get_result(&mut connection)?;
+		if num_songs == 0 {
+			return Err(Error::SongNotFound(virtual_path.to_owned()));
+		}
+
+		diesel::insert_into(ratings::table)
+			.values(&NewRating {
+				user: user_id,
+				path: real_path_string,
+				rating: rating as i32,
+			})
+			.execute(&mut connection)?;
+
+		Ok(())
+	}
+
+	pub fn clear_rating(&self, username: &str, virtual_path: &Path) -> Result<(), Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let real_path = vfs.virtual_to_real(virtual_path)?;
+		let real_path_string = real_path.to_string_lossy().into_owned();
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let q = ratings::table
+			.filter(ratings::user.eq(user_id))
+			.filter(ratings::path.eq(real_path_string));
+		match diesel::delete(q).execute(&mut connection)? {
+			0 => Err(Error::RatingNotFound(virtual_path.to_owned())),
+			_ => Ok(()),
+		}
+	}
+
+	// Ratings of all the songs a user rated, keyed by virtual path
+	pub fn get_ratings(&self, username: &str) -> Result<HashMap<String, u8>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+
+		let real_ratings: Vec<(String, i32)> = ratings::table
+			.filter(ratings::user.eq(user_id))
+			.select((ratings::path, ratings::rating))
+			.load(&mut connection)?;
+
+		Ok(real_ratings
+			.into_iter()
+			.filter_map(|(path, rating)| {
+				let virtual_path = vfs.real_to_virtual(Path::new(&path)).ok()?;
+				Some((virtual_path.to_string_lossy().into_owned(), rating as u8))
+			})
+			.collect())
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn song_path() -> PathBuf {
+		[TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect()
+	}
+
+	#[test]
+	fn set_rating_overwrites_previous_rating() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let song = song_path();
+		ctx.rating_manager.set_rating(TEST_USER, &song, 2).unwrap();
+		ctx.rating_manager.set_rating(TEST_USER, &song, 5).unwrap();
+
+		let ratings = ctx.rating_manager.get_ratings(TEST_USER).unwrap();
+		assert_eq!(ratings.len(), 1);
+		assert_eq!(ratings.get(song.to_string_lossy().as_ref()), Some(&5));
+	}
+
+	#[test]
+	fn clear_rating_golden_path() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let song = song_path();
+		ctx.rating_manager.set_rating(TEST_USER, &song, 3).unwrap();
+		ctx.rating_manager.clear_rating(TEST_USER, &song).unwrap();
+
+		assert!(ctx
+			.rating_manager
+			.get_ratings(TEST_USER)
+			.unwrap()
+			.is_empty());
+		assert!(matches!(
+			ctx.rating_manager.clear_rating(TEST_USER, &song),
+			Err(Error::RatingNotFound(_))
+		));
+	}
+
+	#[test]
+	fn rejects_invalid_ratings() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		for rating in [0, 6] {
+			assert!(matches!(
+				ctx.rating_manager.set_rating(TEST_USER, &song_path(), rating),
+				Err(Error::InvalidRating(r)) if r == rating
+			));
+		}
+	}
+}
@@ -0,0 +1,291 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::app::settings;
+
+// Players download songs faster than they play them, so sessions stay open for a while after
+// their last request instead of coming and going between requests
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Session not found")]
+	SessionNotFound,
+	#[error(transparent)]
+	Settings(#[from] settings::Error),
+	#[error("Users cannot play more than {0} streams at once")]
+	TooManyStreams(u32),
+}
+
+/// Song being streamed to a client of a user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+	pub id: u64,
+	pub username: String,
+	pub client: String,
+	pub path: PathBuf,
+	// None when the original file is streamed
+	pub bitrate: Option<u32>,
+	pub started_at: i64, // Unix timestamp
+}
+
+struct Entry {
+	session: Session,
+	requests_in_progress: usize,
+	last_active: Instant,
+	terminated: Arc<AtomicBool>,
+}
+
+impl Entry {
+	fn is_active(&self, now: Instant) -> bool {
+		self.requests_in_progress > 0
+			|| now.saturating_duration_since(self.last_active) < IDLE_TIMEOUT
+	}
+}
+
+#[derive(Default)]
+struct State {
+	next_id: u64,
+	entries: Vec<Entry>,
+}
+
+/// Request for a session's audio, which ends when dropped.
+pub struct Request {
+	id: u64,
+	terminated: Arc<AtomicBool>,
+	state: Arc<Mutex<State>>,
+}
+
+impl Request {
+	pub fn is_terminated(&self) -> bool {
+		self.terminated.load(Ordering::SeqCst)
+	}
+}
+
+impl Drop for Request {
+	fn drop(&mut self) {
+		let mut state = self.state.lock().unwrap();
+		if let Some(entry) = state.entries.iter_mut().find(|e| e.session.id == self.id) {
+			entry.requests_in_progress = entry.requests_in_progress.saturating_sub(1);
+			entry.last_active = Instant::now();
+		}
+	}
+}
+
+// Keeps track of who is listening to what, one session per user and client
+#[derive(Clone)]
+pub struct Manager {
+	settings_manager: settings::Manager,
+	state: Arc<Mutex<State>>,
+}
+
+impl Manager {
+	pub fn new(settings_manager: settings::Manager) -> Self {
+		Self {
+			settings_manager,
+			state: Arc::default(),
+		}
+	}
+
+	/// Starts a request for a song, within the session of the client it comes from. Clients
+	/// play one song at a time, so a request for another song moves their session to it.
+	pub fn begin_request(
+		&self,
+		username: &str,
+		client: &str,
+		path: &Path,
+		bitrate: Option<u32>,
+	) -> Result<Request, Error> {
+		let max_streams = self.settings_manager.get_max_streams_per_user()?;
+		self.begin_request_at(username, client, path, bitrate, max_streams, Instant::now())
+	}
+
+	fn begin_request_at(
+		&self,
+		username: &str,
+		client: &str,
+		path: &Path,
+		bitrate: Option<u32>,
+		max_streams: Option<u32>,
+		now: Instant,
+	) -> Result<Request, Error> {
+		let mut state = self.state.lock().unwrap();
+		state.entries.retain(|e| e.is_active(now));
+
+		let existing = state
+			.entries
+			.iter()
+			.position(|e| e.session.username == username && e.session.client == client);
+		let index = match existing {
+			Some(index) => index,
+			None => {
+				let user_streams = state
+					.entries
+					.iter()
+					.filter(|e| e.session.username == username)
+					.count();
+				if let Some(max_streams) = max_streams {
+					if user_streams >= max_streams as usize {
+						return Err(Error::TooManyStreams(max_streams));
+					}
+				}
+				state.next_id += 1;
+				let session = Session {
+					id: state.next_id,
+					username: username.to_owned(),
+					client: client.to_owned(),
+					path: path.to_owned(),
+					bitrate,
+					started_at: unix_time(),
+				};
+				state.entries.push(Entry {
+					session,
+					requests_in_progress: 0,
+					last_active: now,
+					terminated: Arc::default(),
+				});
+				state.entries.len() - 1
+			}
+		};
+
+		let entry = &mut state.entries[index];
+		if entry.session.path != path {
+			entry.session.path = path.to_owned();
+			entry.session.started_at = unix_time();
+		}
+		entry.session.bitrate = bitrate;
+		entry.requests_in_progress += 1;
+		entry.last_active = now;
+
+		Ok(Request {
+			id: entry.session.id,
+			terminated: entry.terminated.clone(),
+			state: self.state.clone(),
+		})
+	}
+
+	pub fn list_sessions(&self) -> Vec<Session> {
+		let now = Instant::now();
+		let state = self.state.lock().unwrap();
+		state
+			.entries
+			.iter()
+			.filter(|e| e.is_active(now))
+			.map(|e| e.session.clone())
+			.collect()
+	}
+
+	/// Ends a session, cutting off the audio it is sending.
+	pub fn terminate(&self, id: u64) -> Result<(), Error> {
+		let mut state = self.state.lock().unwrap();
+		let index = state
+			.entries
+			.iter()
+			.position(|e| e.session.id == id)
+			.ok_or(Error::SessionNotFound)?;
+		let entry = state.entries.remove(index);
+		entry.terminated.store(true, Ordering::SeqCst);
+		Ok(())
+	}
+}
+
+fn unix_time() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const USERNAME: &str = "alice";
+
+	fn manager() -> Manager {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		Manager::new(ctx.settings_manager)
+	}
+
+	#[test]
+	fn clients_have_one_session_each() {
+		let manager = manager();
+		let now = Instant::now();
+		let begin = |client: &str, song: &str| {
+			manager
+				.begin_request_at(USERNAME, client, Path::new(song), Some(128), None, now)
+				.unwrap()
+		};
+
+		let _phone = begin("phone", "a.mp3");
+		let _seek = begin("phone", "a.mp3");
+		let _next_song = begin("phone", "b.mp3");
+		let _laptop = begin("laptop", "c.mp3");
+
+		let mut sessions = manager.list_sessions();
+		sessions.sort_by_key(|s| s.id);
+		assert_eq!(sessions.len(), 2);
+		assert_eq!(sessions[0].client, "phone");
+		assert_eq!(sessions[0].path, Path::new("b.mp3"));
+		assert_eq!(sessions[1].client, "laptop");
+		assert_eq!(sessions[1].bitrate, Some(128));
+	}
+
+	#[test]
+	fn streams_per_user_are_limited() {
+		let manager = manager();
+		let now = Instant::now();
+		let begin = |username: &str, client: &str, now: Instant| {
+			manager.begin_request_at(username, client, Path::new("a.mp3"), None, Some(1), now)
+		};
+
+		let phone = begin(USERNAME, "phone", now).unwrap();
+		assert!(matches!(
+			begin(USERNAME, "laptop", now),
+			Err(Error::TooManyStreams(1))
+		));
+		assert!(begin("bob", "laptop", now).is_ok());
+
+		// Sessions stay open for a while after their last request
+		drop(phone);
+		assert!(begin(USERNAME, "laptop", Instant::now()).is_err());
+		let later = Instant::now() + IDLE_TIMEOUT;
+		assert!(begin(USERNAME, "laptop", later).is_ok());
+	}
+
+	#[test]
+	fn sessions_can_be_terminated() {
+		let manager = manager();
+		let request = manager
+			.begin_request_at(
+				USERNAME,
+				"phone",
+				Path::new("a.mp3"),
+				None,
+				Some(1),
+				Instant::now(),
+			)
+			.unwrap();
+		let id = manager.list_sessions()[0].id;
+		manager.terminate(id).unwrap();
+		assert!(request.is_terminated());
+		assert!(manager.list_sessions().is_empty());
+		assert!(matches!(manager.terminate(id), Err(Error::SessionNotFound)));
+
+		// Terminating a session frees its slot
+		manager
+			.begin_request_at(
+				USERNAME,
+				"laptop",
+				Path::new("a.mp3"),
+				None,
+				Some(1),
+				Instant::now(),
+			)
+			.unwrap();
+	}
+}
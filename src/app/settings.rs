@@ -1,11 +1,15 @@
 use diesel::prelude::*;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::time::Duration;
 
 use crate::db::{self, misc_settings, DB};
 
+// Range of loudness targets accepted by ffmpeg, in LUFS
+const MIN_LOUDNESS_TARGET: i32 = -70;
+const MAX_LOUDNESS_TARGET: i32 = -5;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("Auth secret does not have the expected format")]
@@ -18,6 +22,10 @@ pub enum Error {
 	MiscSettingsNotFound,
 	#[error("Index album art pattern is not a valid regex")]
 	IndexAlbumArtPatternInvalid,
+	#[error("Index album art priority is not a supported value")]
+	IndexAlbumArtPriorityInvalid,
+	#[error("Index ignore pattern `{0}` is not a valid regex")]
+	IndexIgnorePatternInvalid(String),
 	#[error(transparent)]
 	Database(#[from] diesel::result::Error),
 }
@@ -27,16 +35,130 @@ pub struct AuthSecret {
 	pub key: [u8; 32],
 }
 
+/// Which artwork songs use when they have both embedded artwork and a matching image in their directory.
+/// Directories always prefer images matching the album art pattern.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumArtPriority {
+	#[default]
+	Embedded,
+	Folder,
+}
+
+impl AlbumArtPriority {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Embedded => "embedded",
+			Self::Folder => "folder",
+		}
+	}
+
+	fn parse(value: &str) -> Option<Self> {
+		match value {
+			"embedded" => Some(Self::Embedded),
+			"folder" => Some(Self::Folder),
+			_ => None,
+		}
+	}
+}
+
 #[derive(Debug, Queryable)]
 pub struct Settings {
 	pub index_sleep_duration_seconds: i32,
 	pub index_album_art_pattern: String,
+	pub auth_token_lifetime_seconds: i32,
+	pub index_album_art_priority: String,
+	pub index_num_threads: i32,
+	pub index_ignore_patterns: String,
+	pub thumbnail_cache_size_mb: i32,
+	pub index_group_compilations: bool,
+	pub collation_locale: String,
+	pub search_accent_folding: bool,
+	pub transcode_loudness_target: i32,
+	pub index_metadata_lookup: bool,
+	pub acoustid_api_key: String,
+	pub index_missing_grace_period_days: i32,
+	pub index_max_files_per_second: i32,
+	pub index_pause_while_streaming: bool,
+	pub search_fuzzy: bool,
+	pub bandwidth_limit_kbps: i32,
+	pub bandwidth_user_limit_kbps: i32,
+	pub bandwidth_stream_limit_kbps: i32,
+	pub max_streams_per_user: i32,
+	pub index_pregenerate_thumbnails: bool,
+	pub transcode_cache_size_mb: i32,
+}
+
+impl Settings {
+	pub fn ignore_patterns(&self) -> Vec<String> {
+		serde_json::from_str(&self.index_ignore_patterns).unwrap_or_default()
+	}
 }
 
 #[derive(Debug, Default, Deserialize)]
 pub struct NewSettings {
 	pub reindex_every_n_seconds: Option<i32>,
 	pub album_art_pattern: Option<String>,
+	pub auth_token_lifetime_seconds: Option<i32>,
+	pub album_art_priority: Option<AlbumArtPriority>,
+	pub index_num_threads: Option<i32>,
+	pub ignore_patterns: Option<Vec<String>>,
+	pub thumbnail_cache_size_mb: Option<i32>,
+	pub group_compilations: Option<bool>,
+	pub collation_locale: Option<String>,
+	pub search_accent_folding: Option<bool>,
+	pub loudness_target: Option<i32>,
+	pub metadata_lookup: Option<bool>,
+	pub acoustid_api_key: Option<String>,
+	pub missing_grace_period_days: Option<i32>,
+	pub max_files_per_second: Option<i32>,
+	pub pause_while_streaming: Option<bool>,
+	pub search_fuzzy: Option<bool>,
+	pub bandwidth_limit_kbps: Option<i32>,
+	pub user_bandwidth_limit_kbps: Option<i32>,
+	pub stream_bandwidth_limit_kbps: Option<i32>,
+	pub max_streams_per_user: Option<i32>,
+	pub pregenerate_thumbnails: Option<bool>,
+	pub transcode_cache_size_mb: Option<i32>,
+}
+
+impl NewSettings {
+	/// Values settings start with in a new database.
+	pub fn defaults() -> Self {
+		Self {
+			reindex_every_n_seconds: Some(1800),
+			album_art_pattern: Some("Folder.(jpeg|jpg|png)".to_owned()),
+			auth_token_lifetime_seconds: Some(30 * 24 * 60 * 60),
+			album_art_priority: Some(AlbumArtPriority::default()),
+			index_num_threads: Some(0),
+			ignore_patterns: Some(Vec::new()),
+			thumbnail_cache_size_mb: Some(0),
+			group_compilations: Some(true),
+			collation_locale: Some(String::new()),
+			search_accent_folding: Some(true),
+			loudness_target: Some(-18),
+			metadata_lookup: Some(false),
+			acoustid_api_key: Some(String::new()),
+			missing_grace_period_days: Some(30),
+			max_files_per_second: Some(0),
+			pause_while_streaming: Some(false),
+			search_fuzzy: Some(false),
+			bandwidth_limit_kbps: Some(0),
+			user_bandwidth_limit_kbps: Some(0),
+			stream_bandwidth_limit_kbps: Some(0),
+			max_streams_per_user: Some(0),
+			pregenerate_thumbnails: Some(false),
+			transcode_cache_size_mb: Some(0),
+		}
+	}
+}
+
+/// Upload rates that streams are held to, in kbps. `None` means unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BandwidthLimits {
+	pub total: Option<u32>,
+	pub per_user: Option<u32>,
+	pub per_stream: Option<u32>,
 }
 
 #[derive(Clone)]
@@ -79,12 +201,178 @@ impl Manager {
 		Ok(regex)
 	}
 
+	pub fn get_index_album_art_priority(&self) -> Result<AlbumArtPriority, Error> {
+		let settings = self.read()?;
+		AlbumArtPriority::parse(&settings.index_album_art_priority)
+			.ok_or(Error::IndexAlbumArtPriorityInvalid)
+	}
+
+	pub fn get_index_ignore_patterns(&self) -> Result<Vec<Regex>, Error> {
+		let settings = self.read()?;
+		settings
+			.ignore_patterns()
+			.iter()
+			.map(|p| Regex::new(p).map_err(|_| Error::IndexIgnorePatternInvalid(p.clone())))
+			.collect()
+	}
+
+	// Whether albums by many artists without an album artist are grouped under `Various Artists`
+	pub fn get_index_group_compilations(&self) -> Result<bool, Error> {
+		let settings = self.read()?;
+		Ok(settings.index_group_compilations)
+	}
+
+	// Whether thumbnails of new album art are generated during index updates
+	pub fn get_index_pregenerate_thumbnails(&self) -> Result<bool, Error> {
+		let settings = self.read()?;
+		Ok(settings.index_pregenerate_thumbnails)
+	}
+
+	// Language tag like `sv-SE` whose sorting rules apply to artists, albums and file names
+	pub fn get_collation_locale(&self) -> Result<String, Error> {
+		let settings = self.read()?;
+		Ok(settings.collation_locale)
+	}
+
+	// Whether searches ignore accents, so that `Bjork` finds `Björk`
+	pub fn get_search_accent_folding(&self) -> Result<bool, Error> {
+		let settings = self.read()?;
+		Ok(settings.search_accent_folding)
+	}
+
+	// Whether searches also return results with small typos, so that `Zepelin` finds `Zeppelin`
+	pub fn get_search_fuzzy(&self) -> Result<bool, Error> {
+		let settings = self.read()?;
+		Ok(settings.search_fuzzy)
+	}
+
+	// Loudness in LUFS that transcoded songs are normalized to, when clients ask for it
+	pub fn get_loudness_target(&self) -> Result<i32, Error> {
+		let settings = self.read()?;
+		Ok(settings
+			.transcode_loudness_target
+			.clamp(MIN_LOUDNESS_TARGET, MAX_LOUDNESS_TARGET))
+	}
+
+	// Whether songs missing artist or album tags are looked up on MusicBrainz
+	pub fn get_index_metadata_lookup(&self) -> Result<bool, Error> {
+		let settings = self.read()?;
+		Ok(settings.index_metadata_lookup)
+	}
+
+	// Songs are identified by their audio fingerprint when an AcoustID key is set
+	pub fn get_acoustid_api_key(&self) -> Result<Option<String>, Error> {
+		let settings = self.read()?;
+		Ok(Some(settings.acoustid_api_key).filter(|k| !k.is_empty()))
+	}
+
+	// How long songs which disappeared from disk keep their ratings, favorites and listens
+	pub fn get_index_missing_grace_period(&self) -> Result<Duration, Error> {
+		let settings = self.read()?;
+		Ok(Duration::from_secs(
+			settings.index_missing_grace_period_days.max(0) as u64 * 24 * 60 * 60,
+		))
+	}
+
+	// Zero means files are read as fast as possible
+	pub fn get_index_max_files_per_second(&self) -> Result<Option<u32>, Error> {
+		let settings = self.read()?;
+		Ok(match settings.index_max_files_per_second {
+			n if n > 0 => Some(n as u32),
+			_ => None,
+		})
+	}
+
+	pub fn get_index_pause_while_streaming(&self) -> Result<bool, Error> {
+		let settings = self.read()?;
+		Ok(settings.index_pause_while_streaming)
+	}
+
+	// Zero means streams are not limited
+	pub fn get_bandwidth_limits(&self) -> Result<BandwidthLimits, Error> {
+		let settings = self.read()?;
+		let limit = |kbps: i32| u32::try_from(kbps).ok().filter(|k| *k > 0);
+		Ok(BandwidthLimits {
+			total: limit(settings.bandwidth_limit_kbps),
+			per_user: limit(settings.bandwidth_user_limit_kbps),
+			per_stream: limit(settings.bandwidth_stream_limit_kbps),
+		})
+	}
+
+	// Zero means users can play any number of streams at once
+	pub fn get_max_streams_per_user(&self) -> Result<Option<u32>, Error> {
+		let settings = self.read()?;
+		Ok(match settings.max_streams_per_user {
+			n if n > 0 => Some(n as u32),
+			_ => None,
+		})
+	}
+
+	// Zero means the number of threads is picked automatically
+	pub fn get_index_num_threads(&self) -> Result<Option<usize>, Error> {
+		let settings = self.read()?;
+		Ok(match settings.index_num_threads {
+			n if n > 0 => Some(n as usize),
+			_ => None,
+		})
+	}
+
+	// Zero means the thumbnail cache can grow without limit
+	pub fn get_thumbnail_cache_size(&self) -> Result<Option<u64>, Error> {
+		let settings = self.read()?;
+		Ok(match settings.thumbnail_cache_size_mb {
+			n if n > 0 => Some(n as u64 * 1024 * 1024),
+			_ => None,
+		})
+	}
+
+	// Zero means the transcode cache can grow without limit
+	pub fn get_transcode_cache_size(&self) -> Result<Option<u64>, Error> {
+		let settings = self.read()?;
+		Ok(match settings.transcode_cache_size_mb {
+			n if n > 0 => Some(n as u64 * 1024 * 1024),
+			_ => None,
+		})
+	}
+
+	// A lifetime of zero means auth tokens never expire
+	pub fn get_auth_token_lifetime(&self) -> Result<Duration, Error> {
+		let settings = self.read()?;
+		Ok(Duration::from_secs(
+			settings.auth_token_lifetime_seconds.max(0) as u64,
+		))
+	}
+
 	pub fn read(&self) -> Result<Settings, Error> {
 		use self::misc_settings::dsl::*;
 		let mut connection = self.db.connect()?;
 
 		let settings: Settings = misc_settings
-			.select((index_sleep_duration_seconds, index_album_art_pattern))
+			.select((
+				index_sleep_duration_seconds,
+				index_album_art_pattern,
+				auth_token_lifetime_seconds,
+				index_album_art_priority,
+				index_num_threads,
+				index_ignore_patterns,
+				thumbnail_cache_size_mb,
+				index_group_compilations,
+				collation_locale,
+				search_accent_folding,
+				transcode_loudness_target,
+				index_metadata_lookup,
+				acoustid_api_key,
+				index_missing_grace_period_days,
+				index_max_files_per_second,
+				index_pause_while_streaming,
+				search_fuzzy,
+				bandwidth_limit_kbps,
+				bandwidth_user_limit_kbps,
+				bandwidth_stream_limit_kbps,
+				max_streams_per_user,
+				index_pregenerate_thumbnails,
+				transcode_cache_size_mb,
+			))
 			.get_result(&mut connection)
 			.map_err(|e| match e {
 				diesel::result::Error::NotFound => Error::MiscSettingsNotFound,
@@ -95,6 +383,13 @@ impl Manager {
 	}
 
 	pub fn amend(&self, new_settings: &NewSettings) -> Result<(), Error> {
+		// Checked before writing anything so an invalid pattern leaves all settings untouched
+		if let Some(ref ignore_patterns) = new_settings.ignore_patterns {
+			if let Some(pattern) = ignore_patterns.iter().find(|p| Regex::new(p).is_err()) {
+				return Err(Error::IndexIgnorePatternInvalid(pattern.clone()));
+			}
+		}
+
 		let mut connection = self.db.connect()?;
 
 		if let Some(sleep_duration) = new_settings.reindex_every_n_seconds {
@@ -109,6 +404,133 @@ impl Manager {
 				.execute(&mut connection)?;
 		}
 
+		if let Some(lifetime) = new_settings.auth_token_lifetime_seconds {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::auth_token_lifetime_seconds.eq(lifetime))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(priority) = new_settings.album_art_priority {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_album_art_priority.eq(priority.as_str()))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(num_threads) = new_settings.index_num_threads {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_num_threads.eq(num_threads))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(ref ignore_patterns) = new_settings.ignore_patterns {
+			let ignore_patterns = serde_json::to_string(ignore_patterns).unwrap_or_default();
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_ignore_patterns.eq(ignore_patterns))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(cache_size) = new_settings.thumbnail_cache_size_mb {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::thumbnail_cache_size_mb.eq(cache_size))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(group_compilations) = new_settings.group_compilations {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_group_compilations.eq(group_compilations))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(ref locale) = new_settings.collation_locale {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::collation_locale.eq(locale))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(accent_folding) = new_settings.search_accent_folding {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::search_accent_folding.eq(accent_folding))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(loudness_target) = new_settings.loudness_target {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::transcode_loudness_target.eq(loudness_target))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(metadata_lookup) = new_settings.metadata_lookup {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_metadata_lookup.eq(metadata_lookup))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(ref api_key) = new_settings.acoustid_api_key {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::acoustid_api_key.eq(api_key))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(grace_period) = new_settings.missing_grace_period_days {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_missing_grace_period_days.eq(grace_period))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(max_files_per_second) = new_settings.max_files_per_second {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_max_files_per_second.eq(max_files_per_second))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(pause_while_streaming) = new_settings.pause_while_streaming {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_pause_while_streaming.eq(pause_while_streaming))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(fuzzy) = new_settings.search_fuzzy {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::search_fuzzy.eq(fuzzy))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(limit) = new_settings.bandwidth_limit_kbps {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::bandwidth_limit_kbps.eq(limit))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(limit) = new_settings.user_bandwidth_limit_kbps {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::bandwidth_user_limit_kbps.eq(limit))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(limit) = new_settings.stream_bandwidth_limit_kbps {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::bandwidth_stream_limit_kbps.eq(limit))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(max_streams) = new_settings.max_streams_per_user {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::max_streams_per_user.eq(max_streams))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(pregenerate_thumbnails) = new_settings.pregenerate_thumbnails {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::index_pregenerate_thumbnails.eq(pregenerate_thumbnails))
+				.execute(&mut connection)?;
+		}
+
+		if let Some(cache_size) = new_settings.transcode_cache_size_mb {
+			diesel::update(misc_settings::table)
+				.set(misc_settings::transcode_cache_size_mb.eq(cache_size))
+				.execute(&mut connection)?;
+		}
+
 		Ok(())
 	}
 }
@@ -0,0 +1,490 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{hash_map::Entry, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::app::index::{Index, Page, QueryError, Song};
+use crate::app::{download, playlist, user, vfs};
+use crate::db::{self, shares, users, DB};
+
+const TOKEN_LENGTH: usize = 24;
+// Further requests from a listener for a track they started playing this recently are part of
+// the same play
+const PLAY_DURATION: Duration = Duration::from_secs(30 * 60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Share not found")]
+	ShareNotFound,
+	#[error("Share has expired")]
+	ShareExpired,
+	#[error("Share has reached its maximum number of plays")]
+	PlayLimitReached,
+	#[error("Share does not allow downloads")]
+	DownloadNotAllowed,
+	#[error("Shares must target either a path or a playlist")]
+	InvalidTarget,
+	#[error("Nothing to share at `{0}`")]
+	NothingToShare(PathBuf),
+	#[error("Share has no track at position {0}")]
+	TrackNotFound(usize),
+	#[error(transparent)]
+	Download(#[from] download::Error),
+	#[error(transparent)]
+	Playlist(#[from] playlist::Error),
+	#[error(transparent)]
+	Query(#[from] QueryError),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+/// Link letting people without an account listen to a song, a directory or a playlist.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Share {
+	pub token: String,
+	pub path: Option<String>,
+	pub playlist: Option<String>,
+	pub creation_time: i64,
+	pub expiration_time: Option<i64>,
+	pub allow_download: bool,
+	pub max_plays: Option<u32>,
+	pub play_count: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NewShare {
+	pub path: Option<PathBuf>,
+	pub playlist: Option<String>,
+	pub expiration_time: Option<i64>, // seconds since epoch
+	pub allow_download: bool,
+	pub max_plays: Option<u32>,
+}
+
+/// What a share link gives access to.
+#[derive(Debug, PartialEq)]
+pub struct SharedContent {
+	pub name: String,
+	pub owner: String,
+	pub share: Share,
+	pub songs: Vec<Song>,
+}
+
+pub enum Download {
+	Archive(download::Archive),
+	File(PathBuf),
+}
+
+type ShareRow = (
+	String,
+	Option<String>,
+	Option<String>,
+	i64,
+	Option<i64>,
+	bool,
+	Option<i32>,
+	i32,
+);
+
+const SHARE_COLUMNS: (
+	shares::token,
+	shares::path,
+	shares::playlist,
+	shares::creation_time,
+	shares::expiration_time,
+	shares::allow_download,
+	shares::max_plays,
+	shares::play_count,
+) = (
+	shares::token,
+	shares::path,
+	shares::playlist,
+	shares::creation_time,
+	shares::expiration_time,
+	shares::allow_download,
+	shares::max_plays,
+	shares::play_count,
+);
+
+impl From<ShareRow> for Share {
+	fn from(row: ShareRow) -> Self {
+		let (
+			token,
+			path,
+			playlist,
+			creation_time,
+			expiration_time,
+			allow_download,
+			max_plays,
+			play_count,
+		) = row;
+		Self {
+			token,
+			path,
+			playlist,
+			creation_time,
+			expiration_time,
+			allow_download,
+			max_plays: max_plays.map(|m| m.max(0) as u32),
+			play_count: play_count.max(0) as u32,
+		}
+	}
+}
+
+// Share token, track and listener of a play in progress
+type Play = (String, usize, String);
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	index: Index,
+	vfs_manager: vfs::Manager,
+	playlist_manager: playlist::Manager,
+	plays: Arc<Mutex<HashMap<Play, Instant>>>,
+}
+
+impl Manager {
+	pub fn new(
+		db: DB,
+		index: Index,
+		vfs_manager: vfs::Manager,
+		playlist_manager: playlist::Manager,
+	) -> Self {
+		Self {
+			db,
+			index,
+			vfs_manager,
+			playlist_manager,
+			plays: Arc::default(),
+		}
+	}
+
+	pub fn create(&self, username: &str, new_share: NewShare) -> Result<Share, Error> {
+		let (path, playlist_name) = match (new_share.path, new_share.playlist) {
+			(Some(path), None) => {
+				let (songs, _) = self.index.flatten(username, &path, Page::default())?;
+				if songs.is_empty() {
+					return Err(Error::NothingToShare(path));
+				}
+				(Some(path.to_string_lossy().into_owned()), None)
+			}
+			(None, Some(playlist_name)) => {
+				self.playlist_manager
+					.read_playlist(&playlist_name, username)?;
+				(None, Some(playlist_name))
+			}
+			_ => return Err(Error::InvalidTarget),
+		};
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let share = Share {
+			token: user::generate_random_string(TOKEN_LENGTH),
+			path,
+			playlist: playlist_name,
+			creation_time: now(),
+			expiration_time: new_share.expiration_time,
+			allow_download: new_share.allow_download,
+			max_plays: new_share.max_plays,
+			play_count: 0,
+		};
+		diesel::insert_into(shares::table)
+			.values((
+				shares::owner.eq(user_id),
+				shares::token.eq(&share.token),
+				shares::path.eq(&share.path),
+				shares::playlist.eq(&share.playlist),
+				shares::creation_time.eq(share.creation_time),
+				shares::expiration_time.eq(share.expiration_time),
+				shares::allow_download.eq(share.allow_download),
+				shares::max_plays.eq(share.max_plays.map(|m| m.min(i32::MAX as u32) as i32)),
+			))
+			.execute(&mut connection)?;
+		Ok(share)
+	}
+
+	pub fn list(&self, username: &str) -> Result<Vec<Share>, Error> {
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let rows: Vec<ShareRow> = shares::table
+			.filter(shares::owner.eq(user_id))
+			.order(shares::creation_time)
+			.select(SHARE_COLUMNS)
+			.load(&mut connection)?;
+		Ok(rows.into_iter().map(Share::from).collect())
+	}
+
+	pub fn delete(&self, username: &str, token: &str) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, username)?;
+		let deleted = diesel::delete(
+			shares::table.filter(shares::owner.eq(user_id).and(shares::token.eq(token))),
+		)
+		.execute(&mut connection)?;
+		match deleted {
+			0 => Err(Error::ShareNotFound),
+			_ => Ok(()),
+		}
+	}
+
+	pub fn get_content(&self, token: &str) -> Result<SharedContent, Error> {
+		let (share, owner) = self.get_share(token)?;
+		let (name, songs) = match (&share.path, &share.playlist) {
+			(Some(path), _) => {
+				let name = Path::new(path)
+					.file_name()
+					.map(|n| n.to_string_lossy().into_owned())
+					.unwrap_or_default();
+				let (songs, _) = self.index.flatten(&owner, path, Page::default())?;
+				(name, songs)
+			}
+			(None, Some(playlist_name)) => (
+				playlist_name.clone(),
+				self.playlist_manager.read_playlist(playlist_name, &owner)?,
+			),
+			(None, None) => return Err(Error::InvalidTarget),
+		};
+		Ok(SharedContent {
+			name,
+			owner,
+			share,
+			songs,
+		})
+	}
+
+	/// Real path of a shared track. The first request of a listener for it counts towards the
+	/// play limit of the share, while their further requests for the same play (like seeking)
+	/// do not. Requests without a listener only read metadata and never count.
+	pub fn get_track(
+		&self,
+		token: &str,
+		track: usize,
+		listener: Option<&str>,
+	) -> Result<PathBuf, Error> {
+		let content = self.get_content(token)?;
+		let song = content
+			.songs
+			.get(track)
+			.ok_or(Error::TrackNotFound(track))?;
+		if let Some(listener) = listener {
+			let mut plays = self.plays.lock().unwrap();
+			plays.retain(|_, at| at.elapsed() < PLAY_DURATION);
+			let play = (token.to_owned(), track, listener.to_owned());
+			if let Entry::Vacant(entry) = plays.entry(play) {
+				self.count_play(token)?;
+				entry.insert(Instant::now());
+			}
+		}
+		let vfs = self.vfs_manager.get_vfs_for_user(&content.owner)?;
+		Ok(vfs.virtual_to_real(Path::new(&song.path))?)
+	}
+
+	fn count_play(&self, token: &str) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		let updated = diesel::update(
+			shares::table.filter(
+				shares::token.eq(token).and(
+					shares::max_plays
+						.is_null()
+						.or(shares::play_count.nullable().lt(shares::max_plays)),
+				),
+			),
+		)
+		.set(shares::play_count.eq(shares::play_count + 1))
+		.execute(&mut connection)?;
+		match updated {
+			0 => Err(Error::PlayLimitReached),
+			_ => Ok(()),
+		}
+	}
+
+	pub fn get_download(&self, token: &str) -> Result<Download, Error> {
+		let (share, owner) = self.get_share(token)?;
+		if !share.allow_download {
+			return Err(Error::DownloadNotAllowed);
+		}
+		// Playlists are spread across directories, which archives cannot represent
+		let Some(path) = share.path else {
+			return Err(Error::DownloadNotAllowed);
+		};
+		let vfs = self.vfs_manager.get_vfs_for_user(&owner)?;
+		let real_path = vfs.virtual_to_real(Path::new(&path))?;
+		match real_path.is_dir() {
+			true => Ok(Download::Archive(download::Archive::new(
+				&vfs,
+				Path::new(&path),
+			)?)),
+			false => Ok(Download::File(real_path)),
+		}
+	}
+
+	// Returns the share and the name of its owner, unless it has expired
+	fn get_share(&self, token: &str) -> Result<(Share, String), Error> {
+		let mut connection = self.db.connect()?;
+		let (row, owner): (ShareRow, String) = shares::table
+			.inner_join(users::table)
+			.filter(shares::token.eq(token))
+			.select((SHARE_COLUMNS, users::name))
+			.first(&mut connection)
+			.optional()?
+			.ok_or(Error::ShareNotFound)?;
+		let share = Share::from(row);
+		if share.expiration_time.is_some_and(|t| t <= now()) {
+			return Err(Error::ShareExpired);
+		}
+		Ok((share, owner))
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+fn now() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn build_context(test_name: String) -> (test::Context, Manager) {
+		let ctx = test::ContextBuilder::new(test_name)
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+		let manager = Manager::new(
+			ctx.db.clone(),
+			ctx.index.clone(),
+			ctx.vfs_manager.clone(),
+			ctx.playlist_manager.clone(),
+		);
+		(ctx, manager)
+	}
+
+	fn album_path() -> PathBuf {
+		[TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect()
+	}
+
+	#[test]
+	fn share_directory() {
+		let (_ctx, manager) = build_context(test_name!());
+		let share = manager
+			.create(
+				TEST_USER,
+				NewShare {
+					path: Some(album_path()),
+					..Default::default()
+				},
+			)
+			.unwrap();
+		assert_eq!(share.token.len(), TOKEN_LENGTH);
+		assert_eq!(manager.list(TEST_USER).unwrap(), vec![share.clone()]);
+
+		let content = manager.get_content(&share.token).unwrap();
+		assert_eq!(content.name, "Hunted");
+		assert_eq!(content.owner, TEST_USER);
+		assert_eq!(content.songs.len(), 5);
+
+		let track = manager
+			.get_track(&share.token, 1, Some("listener"))
+			.unwrap();
+		assert!(track.ends_with("02 - Candlelight.mp3"));
+		assert!(matches!(
+			manager.get_download(&share.token),
+			Err(Error::DownloadNotAllowed)
+		));
+
+		manager.delete(TEST_USER, &share.token).unwrap();
+		assert!(matches!(
+			manager.get_content(&share.token),
+			Err(Error::ShareNotFound)
+		));
+	}
+
+	#[test]
+	fn share_limits_are_enforced() {
+		let (_ctx, manager) = build_context(test_name!());
+		let share = manager
+			.create(
+				TEST_USER,
+				NewShare {
+					path: Some(album_path()),
+					max_plays: Some(1),
+					..Default::default()
+				},
+			)
+			.unwrap();
+		manager
+			.get_track(&share.token, 0, Some("listener"))
+			.unwrap();
+		manager
+			.get_track(&share.token, 0, Some("listener"))
+			.unwrap();
+		manager.get_track(&share.token, 0, None).unwrap();
+		assert!(matches!(
+			manager.get_track(&share.token, 0, Some("other listener")),
+			Err(Error::PlayLimitReached)
+		));
+		assert!(matches!(
+			manager.get_track(&share.token, 1, Some("listener")),
+			Err(Error::PlayLimitReached)
+		));
+
+		let expired = manager
+			.create(
+				TEST_USER,
+				NewShare {
+					path: Some(album_path()),
+					expiration_time: Some(now() - 1),
+					..Default::default()
+				},
+			)
+			.unwrap();
+		assert!(matches!(
+			manager.get_content(&expired.token),
+			Err(Error::ShareExpired)
+		));
+	}
+
+	#[test]
+	fn share_requires_single_target() {
+		let (_ctx, manager) = build_context(test_name!());
+		assert!(matches!(
+			manager.create(TEST_USER, NewShare::default()),
+			Err(Error::InvalidTarget)
+		));
+		assert!(matches!(
+			manager.create(
+				TEST_USER,
+				NewShare {
+					path: Some(PathBuf::from("root/not_a_directory")),
+					..Default::default()
+				}
+			),
+			Err(Error::NothingToShare(_))
+		));
+	}
+}
@@ -0,0 +1,379 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::index::Song;
+use crate::app::rating::{MAX_RATING, MIN_RATING};
+use crate::app::vfs;
+use crate::db::{self, directories, listens, ratings, smart_playlists, songs, users, DB};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Smart playlist not found")]
+	SmartPlaylistNotFound,
+	#[error("Invalid smart playlist rule: {0}")]
+	InvalidRule(String),
+	#[error("Could not parse smart playlist rules:\n\n{0}")]
+	RuleParsing(serde_json::Error),
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+/// A condition songs must satisfy to appear in a smart playlist.
+/// Rating and play history conditions are evaluated against the playlist owner's data.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+	Genre { genre: String },
+	YearBetween { from: i32, to: i32 },
+	MinRating { rating: u8 },
+	NotPlayedInDays { days: u32 },
+	AddedInDays { days: u32 },
+}
+
+impl Rule {
+	fn validate(&self) -> Result<(), Error> {
+		match self {
+			Rule::YearBetween { from, to } if from > to => Err(Error::InvalidRule(format!(
+				"year range starts after it ends ({} > {})",
+				from, to
+			))),
+			Rule::MinRating { rating } if !(MIN_RATING..=MAX_RATING).contains(rating) => {
+				Err(Error::InvalidRule(format!(
+					"ratings must be between {} and {}, not {}",
+					MIN_RATING, MAX_RATING, rating
+				)))
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SmartPlaylist {
+	pub name: String,
+	pub rules: Vec<Rule>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = smart_playlists)]
+struct NewSmartPlaylist {
+	owner: i32,
+	name: String,
+	rules: String,
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	pub fn list_smart_playlists(&self, owner: &str) -> Result<Vec<SmartPlaylist>, Error> {
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, owner)?;
+
+		let found: Vec<(String, String)> = smart_playlists::table
+			.filter(smart_playlists::owner.eq(user_id))
+			.select((smart_playlists::name, smart_playlists::rules))
+			.order(smart_playlists::name)
+			.load(&mut connection)?;
+
+		found
+			.into_iter()
+			.map(|(name, rules)| {
+				Ok(SmartPlaylist {
+					name,
+					rules: serde_json::from_str(&rules).map_err(Error::RuleParsing)?,
+				})
+			})
+			.collect()
+	}
+
+	pub fn save_smart_playlist(
+		&self,
+		playlist_name: &str,
+		owner: &str,
+		rules: &[Rule],
+	) -> Result<(), Error> {
+		for rule in rules {
+			rule.validate()?;
+		}
+		let rules = serde_json::to_string(rules).map_err(Error::RuleParsing)?;
+
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, owner)?;
+
+		diesel::insert_into(smart_playlists::table)
+			.values(&NewSmartPlaylist {
+				owner: user_id,
+				name: playlist_name.to_owned(),
+				rules,
+			})
+			.execute(&mut connection)?;
+
+		Ok(())
+	}
+
+	// Songs are computed from the rules every time the playlist is read
+	pub fn read_smart_playlist(
+		&self,
+		playlist_name: &str,
+		owner: &str,
+	) -> Result<Vec<Song>, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(owner)?;
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, owner)?;
+
+		let rules: String = smart_playlists::table
+			.filter(smart_playlists::owner.eq(user_id))
+			.filter(smart_playlists::name.eq(playlist_name))
+			.select(smart_playlists::rules)
+			.first(&mut connection)
+			.optional()?
+			.ok_or(Error::SmartPlaylistNotFound)?;
+		let rules: Vec<Rule> = serde_json::from_str(&rules).map_err(Error::RuleParsing)?;
+
+		let now = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs() as i64)
+			.unwrap_or_default();
+
+		let mut query = songs::table.into_boxed();
+		for rule in rules {
+			query = match rule {
+				Rule::Genre { genre } => query.filter(songs::genre.eq(genre)),
+				Rule::YearBetween { from, to } => query.filter(songs::year.between(from, to)),
+				Rule::MinRating { rating } => query.filter(
+					songs::path.eq_any(
+						ratings::table
+							.filter(ratings::user.eq(user_id))
+							.filter(ratings::rating.ge(rating as i32))
+							.select(ratings::path),
+					),
+				),
+				Rule::NotPlayedInDays { days } => query.filter(
+					songs::path.ne_all(
+						listens::table
+							.filter(listens::user.eq(user_id))
+							.filter(listens::timestamp.ge(now - days as i64 * SECONDS_PER_DAY))
+							.select(listens::path),
+					),
+				),
+				Rule::AddedInDays { days } => query.filter(
+					songs::parent.eq_any(
+						directories::table
+							.filter(
+								directories::date_added
+									.ge((now - days as i64 * SECONDS_PER_DAY) as i32),
+							)
+							.select(directories::path),
+					),
+				),
+			};
+		}
+
+		let real_songs: Vec<Song> = query.order(songs::path).load(&mut connection)?;
+		Ok(real_songs
+			.into_iter()
+			.filter_map(|s| s.virtualize(&vfs))
+			.collect())
+	}
+
+	pub fn delete_smart_playlist(&self, playlist_name: &str, owner: &str) -> Result<(), Error> {
+		let mut connection = self.db.connect()?;
+		let user_id = get_user_id(&mut connection, owner)?;
+
+		let q = smart_playlists::table
+			.filter(smart_playlists::owner.eq(user_id))
+			.filter(smart_playlists::name.eq(playlist_name));
+		match diesel::delete(q).execute(&mut connection)? {
+			0 => Err(Error::SmartPlaylistNotFound),
+			_ => Ok(()),
+		}
+	}
+}
+
+fn get_user_id(connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+	users::table
+		.filter(users::name.eq(username))
+		.select(users::id)
+		.first(connection)
+		.optional()?
+		.ok_or(Error::UserNotFound)
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::{Path, PathBuf};
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_PASSWORD: &str = "password";
+	const TEST_PLAYLIST_NAME: &str = "Smart";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn read(ctx: &test::Context, rules: &[Rule]) -> Vec<Song> {
+		ctx.smart_playlist_manager
+			.save_smart_playlist(TEST_PLAYLIST_NAME, TEST_USER, rules)
+			.unwrap();
+		ctx.smart_playlist_manager
+			.read_smart_playlist(TEST_PLAYLIST_NAME, TEST_USER)
+			.unwrap()
+	}
+
+	#[test]
+	fn save_and_list_smart_playlists() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.build();
+
+		let rules = vec![
+			Rule::Genre {
+				genre: "Metal".to_owned(),
+			},
+			Rule::NotPlayedInDays { days: 30 },
+		];
+		ctx.smart_playlist_manager
+			.save_smart_playlist(TEST_PLAYLIST_NAME, TEST_USER, &rules)
+			.unwrap();
+
+		let playlists = ctx
+			.smart_playlist_manager
+			.list_smart_playlists(TEST_USER)
+			.unwrap();
+		assert_eq!(
+			playlists,
+			vec![SmartPlaylist {
+				name: TEST_PLAYLIST_NAME.to_owned(),
+				rules
+			}]
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_rules() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.build();
+
+		for rule in [
+			Rule::YearBetween {
+				from: 2020,
+				to: 2010,
+			},
+			Rule::MinRating { rating: 6 },
+		] {
+			assert!(matches!(
+				ctx.smart_playlist_manager.save_smart_playlist(
+					TEST_PLAYLIST_NAME,
+					TEST_USER,
+					&[rule]
+				),
+				Err(Error::InvalidRule(_))
+			));
+		}
+	}
+
+	#[test]
+	fn filters_by_metadata() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		assert_eq!(read(&ctx, &[]).len(), 13);
+
+		let songs = read(
+			&ctx,
+			&[Rule::YearBetween {
+				from: 2010,
+				to: 2020,
+			}],
+		);
+		assert_eq!(songs.len(), 13);
+		let songs = read(
+			&ctx,
+			&[Rule::YearBetween {
+				from: 1990,
+				to: 2000,
+			}],
+		);
+		assert!(songs.is_empty());
+
+		assert!(read(&ctx, &[Rule::AddedInDays { days: 0 }]).is_empty());
+		assert_eq!(read(&ctx, &[Rule::AddedInDays { days: 100_000 }]).len(), 13);
+	}
+
+	#[test]
+	fn filters_by_rating_and_history() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+
+		let loved: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+			.iter()
+			.collect();
+		let liked: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "03 - Three Gates.mp3"]
+			.iter()
+			.collect();
+		ctx.rating_manager.set_rating(TEST_USER, &loved, 5).unwrap();
+		ctx.rating_manager.set_rating(TEST_USER, &liked, 3).unwrap();
+
+		let songs = read(&ctx, &[Rule::MinRating { rating: 4 }]);
+		assert_eq!(songs.len(), 1);
+		assert_eq!(Path::new(&songs[0].path), loved);
+
+		ctx.history_manager
+			.record_listen(TEST_USER, &loved, None)
+			.unwrap();
+		let songs = read(
+			&ctx,
+			&[
+				Rule::MinRating { rating: 3 },
+				Rule::NotPlayedInDays { days: 30 },
+			],
+		);
+		assert_eq!(songs.len(), 1);
+		assert_eq!(Path::new(&songs[0].path), liked);
+	}
+
+	#[test]
+	fn delete_smart_playlist_golden_path() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, TEST_PASSWORD, false)
+			.build();
+
+		ctx.smart_playlist_manager
+			.save_smart_playlist(TEST_PLAYLIST_NAME, TEST_USER, &[])
+			.unwrap();
+		ctx.smart_playlist_manager
+			.delete_smart_playlist(TEST_PLAYLIST_NAME, TEST_USER)
+			.unwrap();
+
+		assert!(matches!(
+			ctx.smart_playlist_manager
+				.read_smart_playlist(TEST_PLAYLIST_NAME, TEST_USER),
+			Err(Error::SmartPlaylistNotFound)
+		));
+	}
+}
@@ -0,0 +1,511 @@
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::Path;
+
+use crate::app::index::Song;
+use crate::app::vfs;
+use crate::db::{self, playlist_songs, playlists, songs, sync_playlists, sync_songs, users, DB};
+
+const SONG_QUERY_CHUNK_SIZE: usize = 500;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Database(#[from] diesel::result::Error),
+	#[error(transparent)]
+	DatabaseConnection(#[from] db::Error),
+	#[error("User not found")]
+	UserNotFound,
+	#[error("Invalid sync token")]
+	InvalidToken,
+	#[error(transparent)]
+	Vfs(#[from] vfs::Error),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SongChanges {
+	pub added: Vec<Song>,
+	pub modified: Vec<Song>,
+	pub removed: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PlaylistChanges {
+	pub added: Vec<String>,
+	pub modified: Vec<String>,
+	pub removed: Vec<String>,
+}
+
+/// Everything that changed since a revision, along with the revision clients are now at.
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+	pub revision: i64,
+	pub songs: SongChanges,
+	pub playlists: PlaylistChanges,
+}
+
+// What is known about an entry as of its latest revision
+#[derive(Clone, Copy)]
+struct Tracked {
+	signature: i64,
+	created_revision: i64,
+	revision: i64,
+	removed: bool,
+}
+
+type TrackedSongRow = (String, i64, i64, i64, bool);
+type TrackedPlaylistRow = (i32, String, i64, i64, i64, bool);
+
+#[derive(Clone)]
+pub struct Manager {
+	db: DB,
+	vfs_manager: vfs::Manager,
+}
+
+impl Manager {
+	pub fn new(db: DB, vfs_manager: vfs::Manager) -> Self {
+		Self { db, vfs_manager }
+	}
+
+	/// Lists changes since the revision encoded in `since`, or the whole collection without one.
+	pub fn get_changes(&self, username: &str, since: Option<&str>) -> Result<Changes, Error> {
+		let vfs = self.vfs_manager.get_vfs_for_user(username)?;
+		let mut connection = self.db.connect()?;
+		let user_id: i32 = users::table
+			.filter(users::name.eq(username))
+			.select(users::id)
+			.first(&mut connection)
+			.optional()?
+			.ok_or(Error::UserNotFound)?;
+
+		record_playlist_changes(&mut connection)?;
+
+		let revision = current_revision(&mut connection)?;
+		let since = match since {
+			Some(token) => token
+				.parse::<i64>()
+				.ok()
+				.filter(|s| *s >= 0 && *s <= revision)
+				.ok_or(Error::InvalidToken)?,
+			None => 0,
+		};
+
+		let song_rows: Vec<(String, i64, bool)> = sync_songs::table
+			.filter(sync_songs::revision.gt(since))
+			.order(sync_songs::path)
+			.select((
+				sync_songs::path,
+				sync_songs::created_revision,
+				sync_songs::removed,
+			))
+			.load(&mut connection)?;
+		let mut songs = SongChanges::default();
+		let mut added_paths = Vec::new();
+		let mut modified_paths = Vec::new();
+		for (path, created_revision, removed) in song_rows {
+			match classify(created_revision, removed, since) {
+				Some(Change::Added) => added_paths.push(path),
+				Some(Change::Modified) => modified_paths.push(path),
+				Some(Change::Removed) => {
+					if let Ok(p) = vfs.real_to_virtual(Path::new(&path)) {
+						songs.removed.push(p.to_string_lossy().into_owned());
+					}
+				}
+				None => (),
+			}
+		}
+		songs.added = load_songs(&mut connection, &vfs, &added_paths)?;
+		songs.modified = load_songs(&mut connection, &vfs, &modified_paths)?;
+
+		let playlist_rows: Vec<(String, i64, bool)> = sync_playlists::table
+			.filter(sync_playlists::owner.eq(user_id))
+			.filter(sync_playlists::revision.gt(since))
+			.order(sync_playlists::name)
+			.select((
+				sync_playlists::name,
+				sync_playlists::created_revision,
+				sync_playlists::removed,
+			))
+			.load(&mut connection)?;
+		let mut playlists = PlaylistChanges::default();
+		for (name, created_revision, removed) in playlist_rows {
+			match classify(created_revision, removed, since) {
+				Some(Change::Added) => playlists.added.push(name),
+				Some(Change::Modified) => playlists.modified.push(name),
+				Some(Change::Removed) => playlists.removed.push(name),
+				None => (),
+			}
+		}
+
+		Ok(Changes {
+			revision,
+			songs,
+			playlists,
+		})
+	}
+}
+
+enum Change {
+	Added,
+	Modified,
+	Removed,
+}
+
+// Entries both created and removed since the client last synced are not worth mentioning
+fn classify(created_revision: i64, removed: bool, since: i64) -> Option<Change> {
+	match (removed, created_revision > since) {
+		(true, true) => None,
+		(true, false) => Some(Change::Removed),
+		(false, true) => Some(Change::Added),
+		(false, false) => Some(Change::Modified),
+	}
+}
+
+fn load_songs(
+	connection: &mut SqliteConnection,
+	vfs: &vfs::VFS,
+	real_paths: &[String],
+) -> Result<Vec<Song>, Error> {
+	let mut found = Vec::with_capacity(real_paths.len());
+	for chunk in real_paths.chunks(SONG_QUERY_CHUNK_SIZE) {
+		let real_songs: Vec<Song> = songs::table
+			.filter(songs::path.eq_any(chunk))
+			.order(songs::path)
+			.load(connection)?;
+		found.extend(real_songs.into_iter().filter_map(|s| s.virtualize(vfs)));
+	}
+	Ok(found)
+}
+
+fn current_revision(connection: &mut SqliteConnection) -> Result<i64, diesel::result::Error> {
+	let songs: Option<i64> = sync_songs::table
+		.select(diesel::dsl::max(sync_songs::revision))
+		.first(connection)?;
+	let playlists: Option<i64> = sync_playlists::table
+		.select(diesel::dsl::max(sync_playlists::revision))
+		.first(connection)?;
+	Ok(songs.unwrap_or_default().max(playlists.unwrap_or_default()))
+}
+
+// Describes what clients see of a song, so that only meaningful changes are reported
+fn song_signature(song: &Song) -> i64 {
+	let json = serde_json::to_vec(song).unwrap_or_default();
+	crc32fast::hash(&json) as i64
+}
+
+fn diff<K: Clone + Eq + Hash>(
+	tracked: &HashMap<K, Tracked>,
+	current: &HashMap<K, i64>,
+	revision: i64,
+) -> Vec<(K, Tracked)> {
+	let mut changes = Vec::new();
+	for (key, signature) in current {
+		let created_revision = match tracked.get(key) {
+			Some(t) if !t.removed && t.signature == *signature => continue,
+			Some(t) if !t.removed => t.created_revision,
+			_ => revision,
+		};
+		changes.push((
+			key.clone(),
+			Tracked {
+				signature: *signature,
+				created_revision,
+				revision,
+				removed: false,
+			},
+		));
+	}
+	for (key, t) in tracked {
+		if !t.removed && !current.contains_key(key) {
+			changes.push((
+				key.clone(),
+				Tracked {
+					revision,
+					removed: true,
+					..*t
+				},
+			));
+		}
+	}
+	changes
+}
+
+/// Compares the index with what clients were last told about, and records differences under a
+/// new revision.
+pub fn record_song_changes(connection: &mut SqliteConnection) -> Result<(), diesel::result::Error> {
+	connection.immediate_transaction(|connection| {
+		let current: HashMap<String, i64> = songs::table
+			.load::<Song>(connection)?
+			.into_iter()
+			.map(|s| {
+				let signature = song_signature(&s);
+				(s.path, signature)
+			})
+			.collect();
+		let tracked: HashMap<String, Tracked> = sync_songs::table
+			.select((
+				sync_songs::path,
+				sync_songs::signature,
+				sync_songs::created_revision,
+				sync_songs::revision,
+				sync_songs::removed,
+			))
+			.load::<TrackedSongRow>(connection)?
+			.into_iter()
+			.map(|(path, signature, created_revision, revision, removed)| {
+				let tracked = Tracked {
+					signature,
+					created_revision,
+					revision,
+					removed,
+				};
+				(path, tracked)
+			})
+			.collect();
+
+		let revision = current_revision(connection)? + 1;
+		let changes = diff(&tracked, &current, revision);
+		for chunk in changes.chunks(SONG_QUERY_CHUNK_SIZE) {
+			let rows = chunk
+				.iter()
+				.map(|(path, t)| {
+					(
+						sync_songs::path.eq(path),
+						sync_songs::signature.eq(t.signature),
+						sync_songs::created_revision.eq(t.created_revision),
+						sync_songs::revision.eq(t.revision),
+						sync_songs::removed.eq(t.removed),
+					)
+				})
+				.collect::<Vec<_>>();
+			diesel::replace_into(sync_songs::table)
+				.values(&rows)
+				.execute(connection)?;
+		}
+		Ok(())
+	})
+}
+
+// Playlists are compared whenever changes are requested, as they are edited outside of the index
+fn record_playlist_changes(connection: &mut SqliteConnection) -> Result<(), diesel::result::Error> {
+	connection.immediate_transaction(|connection| {
+		let names: HashMap<i32, (i32, String)> = playlists::table
+			.select((playlists::id, playlists::owner, playlists::name))
+			.load::<(i32, i32, String)>(connection)?
+			.into_iter()
+			.map(|(id, owner, name)| (id, (owner, name)))
+			.collect();
+		let mut hashers: HashMap<i32, crc32fast::Hasher> = HashMap::new();
+		let contents: Vec<(i32, String)> = playlist_songs::table
+			.order((playlist_songs::playlist, playlist_songs::ordering))
+			.select((playlist_songs::playlist, playlist_songs::path))
+			.load(connection)?;
+		for (playlist, path) in contents {
+			let hasher = hashers.entry(playlist).or_default();
+			hasher.update(path.as_bytes());
+			hasher.update(b"\n");
+		}
+		let current: HashMap<(i32, String), i64> = names
+			.into_iter()
+			.map(|(id, key)| {
+				let signature = hashers.remove(&id).unwrap_or_default().finalize() as i64;
+				(key, signature)
+			})
+			.collect();
+
+		let tracked: HashMap<(i32, String), Tracked> = sync_playlists::table
+			.select((
+				sync_playlists::owner,
+				sync_playlists::name,
+				sync_playlists::signature,
+				sync_playlists::created_revision,
+				sync_playlists::revision,
+				sync_playlists::removed,
+			))
+			.load::<TrackedPlaylistRow>(connection)?
+			.into_iter()
+			.map(
+				|(owner, name, signature, created_revision, revision, removed)| {
+					let tracked = Tracked {
+						signature,
+						created_revision,
+						revision,
+						removed,
+					};
+					((owner, name), tracked)
+				},
+			)
+			.collect();
+
+		let revision = current_revision(connection)? + 1;
+		for ((owner, name), t) in diff(&tracked, &current, revision) {
+			diesel::replace_into(sync_playlists::table)
+				.values((
+					sync_playlists::owner.eq(owner),
+					sync_playlists::name.eq(name),
+					sync_playlists::signature.eq(t.signature),
+					sync_playlists::created_revision.eq(t.created_revision),
+					sync_playlists::revision.eq(t.revision),
+					sync_playlists::removed.eq(t.removed),
+				))
+				.execute(connection)?;
+		}
+		Ok(())
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use id3::TagLike;
+	use std::fs;
+	use std::path::PathBuf;
+	use std::time::{Duration, SystemTime};
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+
+	const TEST_USER: &str = "test_user";
+	const TEST_MOUNT_NAME: &str = "root";
+
+	fn song_paths(songs: &[Song]) -> Vec<String> {
+		songs.iter().map(|s| s.path.clone()).collect()
+	}
+
+	fn virtual_path(file_name: &str) -> String {
+		[TEST_MOUNT_NAME, "Khemmis", "Hunted", file_name]
+			.iter()
+			.collect::<PathBuf>()
+			.to_string_lossy()
+			.into_owned()
+	}
+
+	#[test]
+	fn reports_song_changes_since_revision() {
+		let builder = test::ContextBuilder::new(test_name!());
+		let collection = builder.test_directory.join("collection");
+		let album = collection.join("Khemmis").join("Hunted");
+		fs::create_dir_all(&album).unwrap();
+		for file_name in ["01 - Above The Water.mp3", "02 - Candlelight.mp3"] {
+			fs::copy(
+				Path::new("test-data/small-collection/Khemmis/Hunted").join(file_name),
+				album.join(file_name),
+			)
+			.unwrap();
+		}
+		let ctx = builder
+			.user(TEST_USER, "password", false)
+			.mount(TEST_MOUNT_NAME, collection.to_str().unwrap())
+			.build();
+		let manager = Manager::new(ctx.db.clone(), ctx.vfs_manager.clone());
+		ctx.index.update().unwrap();
+
+		let changes = manager.get_changes(TEST_USER, None).unwrap();
+		assert_eq!(
+			song_paths(&changes.songs.added),
+			vec![
+				virtual_path("01 - Above The Water.mp3"),
+				virtual_path("02 - Candlelight.mp3")
+			]
+		);
+		let token = changes.revision.to_string();
+
+		// Re-indexing an unchanged collection does not report anything
+		ctx.index.update().unwrap();
+		let changes = manager.get_changes(TEST_USER, Some(&token)).unwrap();
+		assert_eq!(changes.songs, SongChanges::default());
+
+		fs::remove_file(album.join("01 - Above The Water.mp3")).unwrap();
+		fs::copy(
+			"test-data/small-collection/Khemmis/Hunted/03 - Three Gates.mp3",
+			album.join("03 - Three Gates.mp3"),
+		)
+		.unwrap();
+		ctx.index.update().unwrap();
+
+		let changes = manager.get_changes(TEST_USER, Some(&token)).unwrap();
+		assert_eq!(
+			song_paths(&changes.songs.added),
+			vec![virtual_path("03 - Three Gates.mp3")]
+		);
+		assert!(changes.songs.modified.is_empty());
+		assert_eq!(
+			changes.songs.removed,
+			vec![virtual_path("01 - Above The Water.mp3")]
+		);
+
+		let token = changes.revision.to_string();
+		let song = album.join("02 - Candlelight.mp3");
+		let mut tag = id3::Tag::read_from_path(&song).unwrap();
+		tag.set_title("Candlelight (Live)");
+		tag.write_to_path(&song, id3::Version::Id3v24).unwrap();
+		fs::File::options()
+			.write(true)
+			.open(&song)
+			.unwrap()
+			.set_modified(SystemTime::now() + Duration::from_secs(10))
+			.unwrap();
+		ctx.index.update().unwrap();
+
+		let changes = manager.get_changes(TEST_USER, Some(&token)).unwrap();
+		assert!(changes.songs.added.is_empty());
+		assert_eq!(
+			changes.songs.modified[0].title.as_deref(),
+			Some("Candlelight (Live)")
+		);
+		assert!(changes.songs.removed.is_empty());
+
+		assert!(matches!(
+			manager.get_changes(TEST_USER, Some("banana")),
+			Err(Error::InvalidToken)
+		));
+		assert!(matches!(
+			manager.get_changes(TEST_USER, Some(&(changes.revision + 1).to_string())),
+			Err(Error::InvalidToken)
+		));
+	}
+
+	#[test]
+	fn reports_playlist_changes_since_revision() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USER, "password", false)
+			.mount(TEST_MOUNT_NAME, "test-data/small-collection")
+			.build();
+		ctx.index.update().unwrap();
+		let manager = Manager::new(ctx.db.clone(), ctx.vfs_manager.clone());
+		let playlist_manager = &ctx.playlist_manager;
+
+		playlist_manager
+			.save_playlist("Chill", TEST_USER, &[virtual_path("02 - Candlelight.mp3")])
+			.unwrap();
+		playlist_manager
+			.save_playlist("Loud", TEST_USER, &[virtual_path("03 - Three Gates.mp3")])
+			.unwrap();
+		let changes = manager.get_changes(TEST_USER, None).unwrap();
+		assert_eq!(changes.playlists.added, vec!["Chill", "Loud"]);
+		let token = changes.revision.to_string();
+
+		playlist_manager
+			.save_playlist("Chill", TEST_USER, &[virtual_path("05 - Hunted.mp3")])
+			.unwrap();
+		playlist_manager.delete_playlist("Loud", TEST_USER).unwrap();
+		playlist_manager
+			.save_playlist("New", TEST_USER, &[])
+			.unwrap();
+		let changes = manager.get_changes(TEST_USER, Some(&token)).unwrap();
+		assert_eq!(
+			changes.playlists,
+			PlaylistChanges {
+				added: vec!["New".to_owned()],
+				modified: vec!["Chill".to_owned()],
+				removed: vec!["Loud".to_owned()],
+			}
+		);
+
+		let token = changes.revision.to_string();
+		let changes = manager.get_changes(TEST_USER, Some(&token)).unwrap();
+		assert_eq!(changes.playlists, PlaylistChanges::default());
+		assert_eq!(changes.revision.to_string(), token);
+	}
+}
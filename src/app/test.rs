@@ -1,6 +1,11 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
 use std::path::PathBuf;
 
-use crate::app::{config, ddns, index::Index, lastfm, playlist, settings, thumbnail, user, vfs};
+use crate::app::{
+	config, ddns, events, favorite, history, index::Index, ldap, playlist, queue, rating, settings,
+	smart_playlist, thumbnail, user, vfs,
+};
 use crate::db::DB;
 use crate::test::*;
 
@@ -9,8 +14,13 @@ pub struct Context {
 	pub index: Index,
 	pub config_manager: config::Manager,
 	pub ddns_manager: ddns::Manager,
-	pub lastfm_manager: lastfm::Manager,
+	pub events_manager: events::Manager,
+	pub favorite_manager: favorite::Manager,
+	pub history_manager: history::Manager,
 	pub playlist_manager: playlist::Manager,
+	pub queue_manager: queue::Manager,
+	pub rating_manager: rating::Manager,
+	pub smart_playlist_manager: smart_playlist::Manager,
 	pub settings_manager: settings::Manager,
 	pub thumbnail_manager: thumbnail::Manager,
 	pub user_manager: user::Manager,
@@ -38,19 +48,26 @@ impl ContextBuilder {
 			.push(user::NewUser {
 				name: name.to_owned(),
 				password: password.to_owned(),
+				password_hash: None,
 				admin: is_admin,
+				mounts: None,
 			});
 		self
 	}
 
-	pub fn mount(mut self, name: &str, source: &str) -> Self {
+	pub fn mount(self, name: &str, source: &str) -> Self {
+		self.mount_dir(vfs::MountDir {
+			name: name.to_owned(),
+			source: source.to_owned(),
+			..Default::default()
+		})
+	}
+
+	pub fn mount_dir(mut self, mount_dir: vfs::MountDir) -> Self {
 		self.config
 			.mount_dirs
 			.get_or_insert(Vec::new())
-			.push(vfs::MountDir {
-				name: name.to_owned(),
-				source: source.to_owned(),
-			});
+			.push(mount_dir);
 		self
 	}
 
@@ -61,7 +78,13 @@ impl ContextBuilder {
 		let db = DB::new(&db_path).unwrap();
 		let settings_manager = settings::Manager::new(db.clone());
 		let auth_secret = settings_manager.get_auth_secret().unwrap();
-		let user_manager = user::Manager::new(db.clone(), auth_secret);
+		let ldap_manager = ldap::Manager::new();
+		let user_manager = user::Manager::new(
+			db.clone(),
+			auth_secret,
+			settings_manager.clone(),
+			ldap_manager.clone(),
+		);
 		let vfs_manager = vfs::Manager::new(db.clone());
 		let ddns_manager = ddns::Manager::new(db.clone());
 		let config_manager = config::Manager::new(
@@ -69,11 +92,23 @@ impl ContextBuilder {
 			user_manager.clone(),
 			vfs_manager.clone(),
 			ddns_manager.clone(),
+			ldap_manager.clone(),
+		);
+		let events_manager = events::Manager::new();
+		let thumbnail_manager = thumbnail::Manager::new(cache_output_dir, settings_manager.clone());
+		let index = Index::new(
+			db.clone(),
+			vfs_manager.clone(),
+			settings_manager.clone(),
+			events_manager.clone(),
+			thumbnail_manager.clone(),
 		);
-		let index = Index::new(db.clone(), vfs_manager.clone(), settings_manager.clone());
 		let playlist_manager = playlist::Manager::new(db.clone(), vfs_manager.clone());
-		let thumbnail_manager = thumbnail::Manager::new(cache_output_dir);
-		let lastfm_manager = lastfm::Manager::new(index.clone(), user_manager.clone());
+		let history_manager = history::Manager::new(db.clone(), vfs_manager.clone());
+		let favorite_manager = favorite::Manager::new(db.clone(), vfs_manager.clone());
+		let queue_manager = queue::Manager::new(db.clone(), vfs_manager.clone());
+		let rating_manager = rating::Manager::new(db.clone(), vfs_manager.clone());
+		let smart_playlist_manager = smart_playlist::Manager::new(db.clone(), vfs_manager.clone());
 
 		config_manager.apply(&self.config).unwrap();
 
@@ -82,8 +117,13 @@ impl ContextBuilder {
 			index,
 			config_manager,
 			ddns_manager,
-			lastfm_manager,
+			events_manager,
+			favorite_manager,
+			history_manager,
 			playlist_manager,
+			queue_manager,
+			rating_manager,
+			smart_playlist_manager,
 			settings_manager,
 			thumbnail_manager,
 			user_manager,
@@ -92,3 +132,37 @@ impl ContextBuilder {
 		}
 	}
 }
+
+// Directory server which accepts any bind, returns its `ldap://` URL
+pub fn start_ldap_server() -> String {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let url = format!("ldap://{}", listener.local_addr().unwrap());
+	std::thread::spawn(move || {
+		for mut stream in listener.incoming().flatten() {
+			let mut buffer = [0; 1024];
+			while let Ok(length) = stream.read(&mut buffer) {
+				// Messages are a sequence holding a message ID and an operation
+				let mut offset = 2;
+				if buffer[1] & 0x80 != 0 {
+					offset += (buffer[1] & 0x7f) as usize;
+				}
+				if length < offset + 2 {
+					break;
+				}
+				let id = &buffer[offset..offset + 2 + buffer[offset + 1] as usize];
+				let is_bind_request = buffer.get(offset + id.len()) == Some(&0x60);
+				if !is_bind_request {
+					break;
+				}
+				// Bind response with a success result code
+				let mut response = vec![0x30, (id.len() + 9) as u8];
+				response.extend_from_slice(id);
+				response.extend_from_slice(&[0x61, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00]);
+				if stream.write_all(&response).is_err() {
+					break;
+				}
+			}
+		}
+	});
+	url
+}
@@ -0,0 +1,241 @@
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Each client can attempt to log in this many times in a row, then once per refill interval
+const BUCKET_CAPACITY: u32 = 10;
+const BUCKET_REFILL_INTERVAL: Duration = Duration::from_secs(6);
+
+// Accounts are locked after this many consecutive failures, for a duration doubling with each
+// further failure
+const LOCKOUT_THRESHOLD: u32 = 5;
+const LOCKOUT_BASE_DURATION: Duration = Duration::from_secs(30);
+const LOCKOUT_MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+
+// Idle entries are dropped past this size so spraying addresses or usernames cannot exhaust memory
+const MAX_TRACKED_ENTRIES: usize = 10_000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Too many login attempts, retry in {} seconds", .0.as_secs())]
+	TooManyAttempts(Duration),
+}
+
+struct Bucket {
+	tokens: u32,
+	last_refill: Instant,
+}
+
+impl Bucket {
+	fn new(now: Instant) -> Self {
+		Self {
+			tokens: BUCKET_CAPACITY,
+			last_refill: now,
+		}
+	}
+
+	fn refill(&mut self, now: Instant) {
+		let elapsed = now.saturating_duration_since(self.last_refill);
+		let new_tokens = (elapsed.as_secs_f64() / BUCKET_REFILL_INTERVAL.as_secs_f64()) as u32;
+		if new_tokens > 0 {
+			self.tokens = (self.tokens + new_tokens).min(BUCKET_CAPACITY);
+			self.last_refill += BUCKET_REFILL_INTERVAL * new_tokens;
+		}
+		if self.tokens == BUCKET_CAPACITY {
+			self.last_refill = now;
+		}
+	}
+
+	fn is_idle(&mut self, now: Instant) -> bool {
+		self.refill(now);
+		self.tokens == BUCKET_CAPACITY
+	}
+}
+
+#[derive(Default)]
+struct Failures {
+	count: u32,
+	locked_until: Option<Instant>,
+}
+
+impl Failures {
+	fn is_idle(&self, now: Instant) -> bool {
+		self.locked_until.map(|t| t <= now).unwrap_or(true) && self.count < LOCKOUT_THRESHOLD
+	}
+}
+
+#[derive(Default)]
+struct State {
+	buckets: HashMap<String, Bucket>,
+	failures: HashMap<String, Failures>,
+}
+
+impl State {
+	fn prune(&mut self, now: Instant) {
+		if self.buckets.len() > MAX_TRACKED_ENTRIES {
+			self.buckets.retain(|_, b| !b.is_idle(now));
+		}
+		if self.failures.len() > MAX_TRACKED_ENTRIES {
+			self.failures.retain(|_, f| !f.is_idle(now));
+		}
+	}
+}
+
+// Slows down password guessing, per client address and per account
+#[derive(Clone, Default)]
+pub struct Manager {
+	state: Arc<Mutex<State>>,
+}
+
+impl Manager {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// Must be called before checking credentials, consumes one attempt from the client's budget
+	pub fn begin_attempt(&self, address: &str, username: &str) -> Result<(), Error> {
+		self.begin_attempt_at(address, username, Instant::now())
+	}
+
+	pub fn record_failure(&self, address: &str, username: &str) {
+		self.record_failure_at(address, username, Instant::now())
+	}
+
+	// Successful logins do not count against the client's budget
+	pub fn record_success(&self, address: &str, username: &str) {
+		let mut state = self.state.lock().unwrap();
+		state.failures.remove(username);
+		if let Some(bucket) = state.buckets.get_mut(address) {
+			bucket.tokens = (bucket.tokens + 1).min(BUCKET_CAPACITY);
+		}
+	}
+
+	fn begin_attempt_at(&self, address: &str, username: &str, now: Instant) -> Result<(), Error> {
+		let mut state = self.state.lock().unwrap();
+		state.prune(now);
+
+		if let Some(locked_until) = state.failures.get(username).and_then(|f| f.locked_until) {
+			if locked_until > now {
+				return Err(Error::TooManyAttempts(locked_until - now));
+			}
+		}
+
+		let bucket = state
+			.buckets
+			.entry(address.to_owned())
+			.or_insert_with(|| Bucket::new(now));
+		bucket.refill(now);
+		if bucket.tokens == 0 {
+			let retry_after =
+				(bucket.last_refill + BUCKET_REFILL_INTERVAL).saturating_duration_since(now);
+			return Err(Error::TooManyAttempts(retry_after));
+		}
+		bucket.tokens -= 1;
+
+		Ok(())
+	}
+
+	fn record_failure_at(&self, address: &str, username: &str, now: Instant) {
+		// Consistent format so tools like fail2ban can match it
+		warn!(
+			"Authentication failure for user `{}` from {}",
+			username, address
+		);
+
+		let mut state = self.state.lock().unwrap();
+		let failures = state.failures.entry(username.to_owned()).or_default();
+		failures.count += 1;
+		if failures.count >= LOCKOUT_THRESHOLD {
+			let exponent = (failures.count - LOCKOUT_THRESHOLD).min(16);
+			let duration = (LOCKOUT_BASE_DURATION * 2u32.pow(exponent)).min(LOCKOUT_MAX_DURATION);
+			failures.locked_until = Some(now + duration);
+			warn!(
+				"Locking out user `{}` for {} seconds after {} failed logins",
+				username,
+				duration.as_secs(),
+				failures.count
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const ADDRESS: &str = "127.0.0.1";
+	const USERNAME: &str = "test_user";
+
+	#[test]
+	fn client_attempts_are_rate_limited() {
+		let manager = Manager::new();
+		let now = Instant::now();
+
+		for i in 0..BUCKET_CAPACITY {
+			let username = format!("user_{}", i);
+			manager.begin_attempt_at(ADDRESS, &username, now).unwrap();
+		}
+		assert!(matches!(
+			manager.begin_attempt_at(ADDRESS, USERNAME, now),
+			Err(Error::TooManyAttempts(d)) if d == BUCKET_REFILL_INTERVAL
+		));
+		manager
+			.begin_attempt_at("192.168.0.1", USERNAME, now)
+			.unwrap();
+
+		let later = now + BUCKET_REFILL_INTERVAL;
+		manager.begin_attempt_at(ADDRESS, USERNAME, later).unwrap();
+		assert!(manager.begin_attempt_at(ADDRESS, USERNAME, later).is_err());
+	}
+
+	#[test]
+	fn accounts_are_locked_after_repeated_failures() {
+		let manager = Manager::new();
+		let now = Instant::now();
+
+		for _ in 0..LOCKOUT_THRESHOLD {
+			manager.begin_attempt_at(ADDRESS, USERNAME, now).unwrap();
+			manager.record_failure_at(ADDRESS, USERNAME, now);
+		}
+		assert!(matches!(
+			manager.begin_attempt_at("192.168.0.1", USERNAME, now),
+			Err(Error::TooManyAttempts(d)) if d == LOCKOUT_BASE_DURATION
+		));
+		manager
+			.begin_attempt_at("192.168.0.1", "other_user", now)
+			.unwrap();
+
+		let later = now + LOCKOUT_BASE_DURATION;
+		manager.begin_attempt_at(ADDRESS, USERNAME, later).unwrap();
+		manager.record_failure_at(ADDRESS, USERNAME, later);
+		assert!(matches!(
+			manager.begin_attempt_at(ADDRESS, USERNAME, later),
+			Err(Error::TooManyAttempts(d)) if d == LOCKOUT_BASE_DURATION * 2
+		));
+	}
+
+	#[test]
+	fn success_resets_failures() {
+		let manager = Manager::new();
+		let now = Instant::now();
+
+		for _ in 0..LOCKOUT_THRESHOLD - 1 {
+			manager.record_failure_at(ADDRESS, USERNAME, now);
+		}
+		manager.record_success(ADDRESS, USERNAME);
+		manager.record_failure_at(ADDRESS, USERNAME, now);
+		manager.begin_attempt_at(ADDRESS, USERNAME, now).unwrap();
+	}
+
+	#[test]
+	fn success_does_not_consume_attempts() {
+		let manager = Manager::new();
+		let now = Instant::now();
+
+		for _ in 0..BUCKET_CAPACITY * 2 {
+			manager.begin_attempt_at(ADDRESS, USERNAME, now).unwrap();
+			manager.record_success(ADDRESS, USERNAME);
+		}
+	}
+}
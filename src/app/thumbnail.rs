@@ -1,11 +1,14 @@
 use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageOutputFormat};
+use log::error;
 use std::cmp;
 use std::collections::hash_map::DefaultHasher;
 use std::fs::{self, File};
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use crate::utils::{get_audio_format, AudioFormat};
+use crate::app::settings;
+use crate::utils::{get_audio_format, mark_as_used, AudioFormat, CacheLimit};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -42,15 +45,57 @@ impl Default for Options {
 	}
 }
 
+// Sizes requested by the small and large thumbnails of the web client
+const PREGENERATED_DIMENSIONS: [u32; 2] = [400, 1200];
+
 #[derive(Clone)]
 pub struct Manager {
 	thumbnails_dir_path: PathBuf,
+	settings_manager: settings::Manager,
+	pregeneration_sender: Arc<Mutex<Option<crossbeam_channel::Sender<PathBuf>>>>,
+	cache_limit: CacheLimit,
 }
 
 impl Manager {
-	pub fn new(thumbnails_dir_path: PathBuf) -> Self {
+	pub fn new(thumbnails_dir_path: PathBuf, settings_manager: settings::Manager) -> Self {
 		Self {
 			thumbnails_dir_path,
+			settings_manager,
+			pregeneration_sender: Arc::default(),
+			cache_limit: CacheLimit::default(),
+		}
+	}
+
+	/// Generates the common thumbnails of these images in the background, so they are ready
+	/// before clients ask for them. Images are processed one at a time on a single thread, to
+	/// leave the rest of the machine to requests.
+	pub fn pregenerate(&self, image_paths: Vec<PathBuf>) {
+		let mut sender = self.pregeneration_sender.lock().unwrap();
+		let sender = sender.get_or_insert_with(|| {
+			let (sender, receiver) = crossbeam_channel::unbounded();
+			let manager = self.clone();
+			std::thread::spawn(move || manager.process_pregenerations(receiver));
+			sender
+		});
+		for image_path in image_paths {
+			sender.send(image_path).ok();
+		}
+	}
+
+	fn process_pregenerations(&self, receiver: crossbeam_channel::Receiver<PathBuf>) {
+		for image_path in receiver {
+			for max_dimension in PREGENERATED_DIMENSIONS {
+				let options = Options {
+					max_dimension: Some(max_dimension),
+					..Default::default()
+				};
+				if self.get_thumbnail_path(&image_path, &options).exists() {
+					continue;
+				}
+				if let Err(e) = self.get_thumbnail(&image_path, &options) {
+					error!("Could not pregenerate thumbnail: {}", e);
+				}
+			}
 		}
 	}
 
@@ -61,7 +106,18 @@ impl Manager {
 	) -> Result<PathBuf, Error> {
 		match self.retrieve_thumbnail(image_path, thumbnailoptions) {
 			Some(path) => Ok(path),
-			None => self.create_thumbnail(image_path, thumbnailoptions),
+			None => {
+				let path = self.create_thumbnail(image_path, thumbnailoptions)?;
+				if let Ok(Some(max_size)) = self.settings_manager.get_thumbnail_cache_size() {
+					if let Err(e) = self
+						.cache_limit
+						.add(&self.thumbnails_dir_path, max_size, &path)
+					{
+						error!("Could not evict thumbnails from cache: {}", e);
+					}
+				}
+				Ok(path)
+			}
 		}
 	}
 
@@ -75,6 +131,7 @@ impl Manager {
 	fn retrieve_thumbnail(&self, image_path: &Path, thumbnailoptions: &Options) -> Option<PathBuf> {
 		let path = self.get_thumbnail_path(image_path, thumbnailoptions);
 		if path.exists() {
+			mark_as_used(&path);
 			Some(path)
 		} else {
 			None
@@ -103,6 +160,10 @@ impl Manager {
 	fn hash(path: &Path, thumbnailoptions: &Options) -> u64 {
 		let mut hasher = DefaultHasher::new();
 		path.hash(&mut hasher);
+		// Artwork edited in place must not be served from an older thumbnail
+		if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+			modified.hash(&mut hasher);
+		}
 		thumbnailoptions.hash(&mut hasher);
 		hasher.finish()
 	}
@@ -217,6 +278,73 @@ fn read_opus(_: &Path) -> Result<DynamicImage, Error> {
 mod test {
 
 	use super::*;
+	use crate::app::test;
+	use crate::test::prepare_test_directory;
+	use crate::test_name;
+	use crate::utils::evict_least_recently_used;
+	use std::time::{Duration, Instant, SystemTime};
+
+	#[test]
+	fn pregenerates_common_sizes() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let image_path = Path::new("test-data/artwork/Folder.png");
+		ctx.thumbnail_manager
+			.pregenerate(vec![image_path.to_owned()]);
+
+		let start = Instant::now();
+		for max_dimension in PREGENERATED_DIMENSIONS {
+			let options = Options {
+				max_dimension: Some(max_dimension),
+				..Default::default()
+			};
+			let thumbnail_path = ctx
+				.thumbnail_manager
+				.get_thumbnail_path(image_path, &options);
+			while !thumbnail_path.exists() {
+				assert!(start.elapsed() < Duration::from_secs(30));
+				std::thread::sleep(Duration::from_millis(100));
+			}
+		}
+	}
+
+	#[test]
+	fn evicts_least_recently_used_thumbnails() {
+		let thumbnails_dir_path = prepare_test_directory(test_name!());
+		let now = SystemTime::now();
+		let make_thumbnail = |name: &str, age_seconds: u64| {
+			let path = thumbnails_dir_path.join(name);
+			fs::write(&path, [0; 100]).unwrap();
+			File::options()
+				.write(true)
+				.open(&path)
+				.unwrap()
+				.set_modified(now - Duration::from_secs(age_seconds))
+				.unwrap();
+			path
+		};
+		let oldest = make_thumbnail("oldest.jpg", 300);
+		let old = make_thumbnail("old.jpg", 200);
+		let recent = make_thumbnail("recent.jpg", 100);
+		let new = make_thumbnail("new.jpg", 0);
+
+		evict_least_recently_used(&thumbnails_dir_path, 250, &new).unwrap();
+
+		assert!(!oldest.exists());
+		assert!(!old.exists());
+		assert!(recent.exists());
+		assert!(new.exists());
+	}
+
+	#[test]
+	fn eviction_keeps_requested_thumbnail() {
+		let thumbnails_dir_path = prepare_test_directory(test_name!());
+		let thumbnail = thumbnails_dir_path.join("large.jpg");
+		fs::write(&thumbnail, [0; 100]).unwrap();
+
+		evict_least_recently_used(&thumbnails_dir_path, 10, &thumbnail).unwrap();
+
+		assert!(thumbnail.exists());
+	}
 
 	#[test]
 	fn can_read_artwork_data() {
@@ -0,0 +1,202 @@
+use log::{error, info};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+const CERTIFICATE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("No certificate found in `{0}`")]
+	MissingCertificate(PathBuf),
+	#[error("No private key found in `{0}`")]
+	MissingPrivateKey(PathBuf),
+	#[error("Unsupported private key in `{0}`")]
+	UnsupportedPrivateKey(PathBuf),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+	pub cert_path: PathBuf,
+	pub key_path: PathBuf,
+}
+
+pub fn make_server_config(config: Config) -> Result<ServerConfig, Error> {
+	let resolver = CertificateResolver::new(config)?;
+	Ok(ServerConfig::builder()
+		.with_safe_defaults()
+		.with_no_client_auth()
+		.with_cert_resolver(Arc::new(resolver)))
+}
+
+struct LoadedCertificate {
+	key: Arc<CertifiedKey>,
+	cert_modified: Option<SystemTime>,
+	key_modified: Option<SystemTime>,
+	last_check: Instant,
+}
+
+// Serves the certificate from disk and picks up renewed files without a restart
+pub struct CertificateResolver {
+	config: Config,
+	certificate: RwLock<LoadedCertificate>,
+}
+
+impl CertificateResolver {
+	pub fn new(config: Config) -> Result<Self, Error> {
+		let certificate = load(&config)?;
+		Ok(Self {
+			config,
+			certificate: RwLock::new(certificate),
+		})
+	}
+
+	fn refresh(&self) {
+		{
+			let certificate = self.certificate.read().unwrap();
+			if certificate.last_check.elapsed() < CERTIFICATE_POLL_INTERVAL {
+				return;
+			}
+		}
+
+		let mut certificate = self.certificate.write().unwrap();
+		certificate.last_check = Instant::now();
+		if get_modified_time(&self.config.cert_path) == certificate.cert_modified
+			&& get_modified_time(&self.config.key_path) == certificate.key_modified
+		{
+			return;
+		}
+
+		match load(&self.config) {
+			Ok(new_certificate) => {
+				info!(
+					"Reloaded TLS certificate from `{}`",
+					self.config.cert_path.display()
+				);
+				*certificate = new_certificate;
+			}
+			Err(e) => error!("Could not reload TLS certificate: {}", e),
+		}
+	}
+
+	fn current_key(&self) -> Arc<CertifiedKey> {
+		self.certificate.read().unwrap().key.clone()
+	}
+}
+
+impl ResolvesServerCert for CertificateResolver {
+	fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+		self.refresh();
+		Some(self.current_key())
+	}
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load(config: &Config) -> Result<LoadedCertificate, Error> {
+	// Read modification times first so a renewal happening mid-load is picked up next time
+	let cert_modified = get_modified_time(&config.cert_path);
+	let key_modified = get_modified_time(&config.key_path);
+
+	let certs = read_pem(&config.cert_path)?
+		.into_iter()
+		.filter_map(|item| match item {
+			rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+	if certs.is_empty() {
+		return Err(Error::MissingCertificate(config.cert_path.clone()));
+	}
+
+	let private_key = read_pem(&config.key_path)?
+		.into_iter()
+		.find_map(|item| match item {
+			rustls_pemfile::Item::PKCS8Key(der)
+			| rustls_pemfile::Item::RSAKey(der)
+			| rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+			_ => None,
+		})
+		.ok_or_else(|| Error::MissingPrivateKey(config.key_path.clone()))?;
+	let signing_key = rustls::sign::any_supported_type(&private_key)
+		.map_err(|_| Error::UnsupportedPrivateKey(config.key_path.clone()))?;
+
+	Ok(LoadedCertificate {
+		key: Arc::new(CertifiedKey::new(certs, signing_key)),
+		cert_modified,
+		key_modified,
+		last_check: Instant::now(),
+	})
+}
+
+fn read_pem(path: &Path) -> Result<Vec<rustls_pemfile::Item>, Error> {
+	let file = File::open(path).map_err(|e| Error::Io(path.to_owned(), e))?;
+	rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(|e| Error::Io(path.to_owned(), e))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::test::prepare_test_directory;
+	use crate::test_name;
+
+	fn test_config() -> Config {
+		Config {
+			cert_path: PathBuf::from("test-data/tls/cert.pem"),
+			key_path: PathBuf::from("test-data/tls/key.pem"),
+		}
+	}
+
+	#[test]
+	fn loads_certificate_and_key() {
+		let resolver = CertificateResolver::new(test_config()).unwrap();
+		assert_eq!(resolver.current_key().cert.len(), 1);
+	}
+
+	#[test]
+	fn rejects_missing_private_key() {
+		let config = Config {
+			key_path: PathBuf::from("test-data/tls/cert.pem"),
+			..test_config()
+		};
+		assert!(matches!(
+			CertificateResolver::new(config),
+			Err(Error::MissingPrivateKey(_))
+		));
+	}
+
+	#[test]
+	fn reloads_renewed_certificate() {
+		let output_dir = prepare_test_directory(test_name!());
+		let config = Config {
+			cert_path: output_dir.join("cert.pem"),
+			key_path: output_dir.join("key.pem"),
+		};
+		std::fs::copy("test-data/tls/cert.pem", &config.cert_path).unwrap();
+		std::fs::copy("test-data/tls/key.pem", &config.key_path).unwrap();
+
+		let resolver = CertificateResolver::new(config.clone()).unwrap();
+		let original = resolver.current_key().cert.clone();
+
+		std::fs::copy("test-data/tls/renewed-cert.pem", &config.cert_path).unwrap();
+		std::fs::copy("test-data/tls/renewed-key.pem", &config.key_path).unwrap();
+		{
+			let mut certificate = resolver.certificate.write().unwrap();
+			certificate.last_check = Instant::now() - CERTIFICATE_POLL_INTERVAL;
+			certificate.cert_modified = None;
+		}
+		resolver.refresh();
+
+		assert_ne!(resolver.current_key().cert, original);
+	}
+}
@@ -0,0 +1,860 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::app::{hls, settings};
+use crate::utils::{mark_as_used, CacheLimit};
+
+const DEFAULT_FFMPEG_PATH: &str = "ffmpeg";
+const MIN_BITRATE: u32 = 32;
+const MAX_BITRATE: u32 = 320;
+// Loudness that ReplayGain adjustments bring songs to, in LUFS
+pub const REPLAY_GAIN_REFERENCE: i32 = -18;
+// How often readers check for output written by ffmpeg
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static NEXT_TEMPORARY_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error("Could not run ffmpeg from `{0}`: `{1}`")]
+	FfmpegNotFound(PathBuf, std::io::Error),
+	#[error("ffmpeg could not transcode `{0}`:\n\n{1}")]
+	Ffmpeg(PathBuf, String),
+	#[error("Bitrate must be between {MIN_BITRATE} and {MAX_BITRATE}kbps, not {0}kbps")]
+	InvalidBitrate(u32),
+	#[error("Filesystem error for `{0}`: `{1}`")]
+	Io(PathBuf, std::io::Error),
+	#[error("Stream has no segment {0}")]
+	StreamSegmentNotFound(usize),
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+	MP3,
+	Opus,
+}
+
+impl Format {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Format::MP3 => "mp3",
+			Format::Opus => "opus",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<Self> {
+		match value {
+			"mp3" => Some(Format::MP3),
+			"opus" => Some(Format::Opus),
+			_ => None,
+		}
+	}
+
+	pub fn mime_type(&self) -> &'static str {
+		match self {
+			Format::MP3 => "audio/mpeg",
+			Format::Opus => "audio/ogg",
+		}
+	}
+
+	fn extension(&self) -> &'static str {
+		match self {
+			Format::MP3 => "mp3",
+			Format::Opus => "opus",
+		}
+	}
+
+	fn codec(&self) -> &'static str {
+		match self {
+			Format::MP3 => "libmp3lame",
+			Format::Opus => "libopus",
+		}
+	}
+
+	fn container(&self) -> &'static str {
+		match self {
+			Format::MP3 => "mp3",
+			Format::Opus => "ogg",
+		}
+	}
+}
+
+#[derive(Debug, Hash)]
+pub struct Options {
+	pub format: Format,
+	pub bitrate: u32, // kbps
+	pub volume: Option<Volume>,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			format: Format::Opus,
+			bitrate: 128,
+			volume: None,
+		}
+	}
+}
+
+/// How transcoded songs are brought to a consistent loudness.
+/// `Track` and `Album` use ReplayGain tags and fall back to each other when a song is missing
+/// the requested values. `Loudness` has ffmpeg measure songs as they are transcoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+	Track,
+	Album,
+	Loudness,
+}
+
+impl Normalization {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Normalization::Track => "track",
+			Normalization::Album => "album",
+			Normalization::Loudness => "loudness",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<Self> {
+		match value {
+			"track" => Some(Normalization::Track),
+			"album" => Some(Normalization::Album),
+			"loudness" => Some(Normalization::Loudness),
+			_ => None,
+		}
+	}
+}
+
+/// Volume adjustment applied while transcoding.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Volume {
+	Gain(Gain),
+	Loudness(i32), // LUFS
+}
+
+/// Volume adjustment applied while transcoding, in hundredths of a decibel.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Gain(i32);
+
+impl Gain {
+	// Peaks are relative to full scale, the adjustment is reduced so they do not clip
+	pub fn new(gain: f32, peak: Option<f32>) -> Self {
+		let headroom = peak
+			.filter(|p| *p > 0.0)
+			.map_or(f32::INFINITY, |p| -20.0 * p.log10());
+		Self((gain.min(headroom) * 100.0).round() as i32)
+	}
+
+	// ReplayGain values are relative to a reference loudness, which may differ from the target
+	pub fn with_target(gain: f32, peak: Option<f32>, target: i32) -> Self {
+		Self::new(gain + (target - REPLAY_GAIN_REFERENCE) as f32, peak)
+	}
+
+	pub fn decibels(&self) -> f32 {
+		self.0 as f32 / 100.0
+	}
+}
+
+/// Time range of a file, in milliseconds. Segments without an end last until the end of the file.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Segment {
+	pub start: u32,
+	pub end: Option<u32>,
+}
+
+/// Transcoding preferences stored for a user or one of their clients.
+/// Songs are served untouched when no format is set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Profile {
+	pub format: Option<Format>,
+	pub max_bitrate: Option<u32>,
+	#[serde(default)]
+	pub normalization: Option<Normalization>,
+}
+
+impl Profile {
+	pub fn options(&self) -> Option<Options> {
+		let mut options = Options {
+			format: self.format?,
+			..Default::default()
+		};
+		options.bitrate = self.max_bitrate.unwrap_or(options.bitrate);
+		Some(options)
+	}
+}
+
+/// Result of a transcode. Files are served from the cache once ffmpeg is done with them, and
+/// read as they are written until then.
+pub enum Output {
+	File(PathBuf),
+	Live(LiveOutput),
+	Memory(Vec<u8>),
+}
+
+/// Transcode which ffmpeg is still writing. Reads wait for more data until ffmpeg exits.
+pub struct LiveOutput {
+	file: fs::File,
+	job: Arc<Job>,
+}
+
+impl Read for LiveOutput {
+	fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+		loop {
+			// Checked before reading, so nothing written in between can be missed
+			let state = self.job.state();
+			let length = self.file.read(buffer)?;
+			match state {
+				_ if length > 0 => return Ok(length),
+				JobState::Running => self.job.wait(),
+				JobState::Done => return Ok(0),
+				JobState::Failed(message) => return Err(io::Error::other(message)),
+			}
+		}
+	}
+}
+
+#[derive(Clone)]
+enum JobState {
+	Running,
+	Done,
+	Failed(String),
+}
+
+// ffmpeg process writing to a temporary file or directory of the cache
+struct Job {
+	path: PathBuf,
+	state: Mutex<JobState>,
+	changed: Condvar,
+}
+
+impl Job {
+	fn new(path: PathBuf) -> Self {
+		Self {
+			path,
+			state: Mutex::new(JobState::Running),
+			changed: Condvar::new(),
+		}
+	}
+
+	fn state(&self) -> JobState {
+		self.state.lock().unwrap().clone()
+	}
+
+	fn finish(&self, state: JobState) {
+		*self.state.lock().unwrap() = state;
+		self.changed.notify_all();
+	}
+
+	// Waits for the job to end, or for its output to grow
+	fn wait(&self) {
+		let state = self.state.lock().unwrap();
+		if matches!(*state, JobState::Running) {
+			let _ = self.changed.wait_timeout(state, POLL_INTERVAL);
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct Manager {
+	transcodes_dir_path: PathBuf,
+	ffmpeg_path: PathBuf,
+	settings_manager: settings::Manager,
+	hls_jobs: Arc<Mutex<HashMap<PathBuf, Arc<Job>>>>,
+	cache_limit: CacheLimit,
+}
+
+impl Manager {
+	pub fn new(transcodes_dir_path: PathBuf, settings_manager: settings::Manager) -> Self {
+		Self {
+			transcodes_dir_path,
+			ffmpeg_path: get_ffmpeg_path(),
+			settings_manager,
+			hls_jobs: Arc::default(),
+			cache_limit: CacheLimit::default(),
+		}
+	}
+
+	pub fn get_transcode(&self, audio_path: &Path, options: &Options) -> Result<Output, Error> {
+		self.get_output(audio_path, Some(options), None)
+	}
+
+	// Segments are transcoded with the given options, or losslessly to flac without any
+	pub fn get_segment(
+		&self,
+		audio_path: &Path,
+		segment: &Segment,
+		options: Option<&Options>,
+	) -> Result<Output, Error> {
+		self.get_output(audio_path, options, Some(segment))
+	}
+
+	/// Transcodes a song to AAC at the bitrate of the given options and cuts it into HLS segments.
+	/// The format of the options is not used. While the stream is being transcoded, its segments
+	/// are listed from the duration of the song when it is known.
+	pub fn get_hls_stream(
+		&self,
+		audio_path: &Path,
+		segment: Option<&Segment>,
+		options: &Options,
+		duration: Option<u32>, // Milliseconds
+	) -> Result<hls::Stream, Error> {
+		if !(MIN_BITRATE..=MAX_BITRATE).contains(&options.bitrate) {
+			return Err(Error::InvalidBitrate(options.bitrate));
+		}
+		let directory = self.get_hls_path(audio_path, segment, options);
+		if let Some(job) = self.get_hls_job(audio_path, segment, options, &directory)? {
+			if let Some(duration) = duration {
+				return Ok(hls::Stream::estimate(&directory, duration));
+			}
+			while let JobState::Running = job.state() {
+				job.wait();
+			}
+			if let JobState::Failed(message) = job.state() {
+				return Err(Error::Ffmpeg(audio_path.to_owned(), message));
+			}
+		}
+		mark_as_used(&directory.join(hls::PLAYLIST_FILE_NAME));
+		hls::Stream::read(&directory).map_err(|e| Error::Io(directory, e))
+	}
+
+	/// Waits for the requested segment only, rather than for the whole stream.
+	pub fn get_hls_segment(
+		&self,
+		audio_path: &Path,
+		segment: Option<&Segment>,
+		options: &Options,
+		index: usize,
+	) -> Result<Output, Error> {
+		if !(MIN_BITRATE..=MAX_BITRATE).contains(&options.bitrate) {
+			return Err(Error::InvalidBitrate(options.bitrate));
+		}
+		let directory = self.get_hls_path(audio_path, segment, options);
+		if let Some(job) = self.get_hls_job(audio_path, segment, options, &directory)? {
+			// ffmpeg renames segments once they are complete. They are read in full, as the
+			// temporary directory moves into the cache when ffmpeg exits.
+			let segment_path = job.path.join(hls::segment_file_name(index));
+			loop {
+				let state = job.state();
+				match fs::read(&segment_path) {
+					Ok(content) => return Ok(Output::Memory(content)),
+					Err(e) if e.kind() != io::ErrorKind::NotFound => {
+						return Err(Error::Io(segment_path, e))
+					}
+					Err(_) => (),
+				}
+				match state {
+					JobState::Running => job.wait(),
+					JobState::Done => break,
+					JobState::Failed(message) => {
+						return Err(Error::Ffmpeg(audio_path.to_owned(), message))
+					}
+				}
+			}
+		}
+		mark_as_used(&directory.join(hls::PLAYLIST_FILE_NAME));
+		hls::Stream::read(&directory)
+			.map_err(|e| Error::Io(directory, e))?
+			.segment_path(index)
+			.map(Output::File)
+			.ok_or(Error::StreamSegmentNotFound(index))
+	}
+
+	fn get_output(
+		&self,
+		audio_path: &Path,
+		options: Option<&Options>,
+		segment: Option<&Segment>,
+	) -> Result<Output, Error> {
+		if let Some(options) = options {
+			if !(MIN_BITRATE..=MAX_BITRATE).contains(&options.bitrate) {
+				return Err(Error::InvalidBitrate(options.bitrate));
+			}
+		}
+		let path = self.get_transcode_path(audio_path, options, segment);
+		if path.exists() {
+			mark_as_used(&path);
+			return Ok(Output::File(path));
+		}
+		let output = self.create_transcode(audio_path, options, segment, &path)?;
+		Ok(Output::Live(output))
+	}
+
+	// Makes room in the cache after `keep` was added to it
+	fn evict(&self, keep: &Path) {
+		if let Ok(Some(max_size)) = self.settings_manager.get_transcode_cache_size() {
+			if let Err(e) = self
+				.cache_limit
+				.add(&self.transcodes_dir_path, max_size, keep)
+			{
+				error!("Could not evict transcodes from cache: {}", e);
+			}
+		}
+	}
+
+	fn get_transcode_path(
+		&self,
+		audio_path: &Path,
+		options: Option<&Options>,
+		segment: Option<&Segment>,
+	) -> PathBuf {
+		let hash = Manager::hash(audio_path, options, segment);
+		let extension = options.map_or("flac", |o| o.format.extension());
+		let mut transcode_path = self.transcodes_dir_path.clone();
+		transcode_path.push(format!("{}.{}", hash, extension));
+		transcode_path
+	}
+
+	fn get_hls_path(
+		&self,
+		audio_path: &Path,
+		segment: Option<&Segment>,
+		options: &Options,
+	) -> PathBuf {
+		let hash = Manager::hash(audio_path, Some(options), segment);
+		self.transcodes_dir_path.join(format!("{}.hls", hash))
+	}
+
+	fn get_temporary_path(path: &Path) -> PathBuf {
+		let id = NEXT_TEMPORARY_FILE_ID.fetch_add(1, Ordering::Relaxed);
+		let mut temporary_name: OsString = path.file_name().unwrap().to_owned();
+		temporary_name.push(format!(".{}.{}.part", std::process::id(), id));
+		path.with_file_name(temporary_name)
+	}
+
+	// Returns once ffmpeg has written some of the transcode, so failures to read the file can
+	// still be reported as such
+	fn create_transcode(
+		&self,
+		audio_path: &Path,
+		options: Option<&Options>,
+		segment: Option<&Segment>,
+		transcode_path: &Path,
+	) -> Result<LiveOutput, Error> {
+		fs::create_dir_all(&self.transcodes_dir_path)
+			.map_err(|e| Error::Io(self.transcodes_dir_path.clone(), e))?;
+
+		// Concurrent requests for the same file each write their own output and the last rename wins
+		let temporary_path = Manager::get_temporary_path(transcode_path);
+		// Created ahead of ffmpeg so it can be read from the start
+		fs::File::create(&temporary_path).map_err(|e| Error::Io(temporary_path.clone(), e))?;
+		let file =
+			fs::File::open(&temporary_path).map_err(|e| Error::Io(temporary_path.clone(), e))?;
+
+		let mut command = self.get_command(audio_path, segment, options.and_then(|o| o.volume));
+		command.args(["-map_metadata", "0"]);
+		// The encoder writes its own delay and padding, those of the source would not line up
+		command.args(["-metadata", "iTunSMPB="]);
+		match options {
+			Some(options) => command
+				.args(["-c:a", options.format.codec()])
+				.args(["-b:a", &format!("{}k", options.bitrate)])
+				.args(["-f", options.format.container()]),
+			None => command.args(["-c:a", "flac"]).args(["-f", "flac"]),
+		};
+		command.arg(&temporary_path);
+		let job = Arc::new(Job::new(temporary_path));
+		let destination = transcode_path.to_owned();
+		self.run(command, audio_path, job.clone(), move |manager, job| {
+			fs::rename(&job.path, &destination)?;
+			manager.evict(&destination);
+			Ok(())
+		})?;
+
+		let output = LiveOutput { file, job };
+		loop {
+			let state = output.job.state();
+			let length = output.file.metadata().map_or(0, |m| m.len());
+			match state {
+				JobState::Running if length == 0 => output.job.wait(),
+				JobState::Failed(message) => {
+					return Err(Error::Ffmpeg(audio_path.to_owned(), message))
+				}
+				_ => return Ok(output),
+			}
+		}
+	}
+
+	// Running stream for the given directory, if it is not in the cache yet. Every request for
+	// the stream follows the same job.
+	fn get_hls_job(
+		&self,
+		audio_path: &Path,
+		segment: Option<&Segment>,
+		options: &Options,
+		directory: &Path,
+	) -> Result<Option<Arc<Job>>, Error> {
+		let mut hls_jobs = self.hls_jobs.lock().unwrap();
+		if let Some(job) = hls_jobs.get(directory) {
+			return Ok(Some(job.clone()));
+		}
+		if directory.exists() {
+			return Ok(None);
+		}
+
+		// Segments are written to a temporary directory which then takes the place of the stream
+		let temporary_directory = Manager::get_temporary_path(directory);
+		fs::create_dir_all(&temporary_directory)
+			.map_err(|e| Error::Io(temporary_directory.clone(), e))?;
+
+		let mut command = self.get_command(audio_path, segment, options.volume);
+		command
+			.args(["-c:a", "aac"])
+			.args(["-b:a", &format!("{}k", options.bitrate)])
+			.args(["-f", "hls"])
+			.args(["-hls_time", &hls::SEGMENT_DURATION.to_string()])
+			.args(["-hls_playlist_type", "vod"])
+			.args(["-hls_segment_type", "mpegts"])
+			.args(["-hls_flags", "temp_file"])
+			.arg("-hls_segment_filename")
+			.arg(temporary_directory.join("%d.ts"))
+			.arg(temporary_directory.join(hls::PLAYLIST_FILE_NAME));
+		let job = Arc::new(Job::new(temporary_directory));
+		let destination = directory.to_owned();
+		self.run(command, audio_path, job.clone(), move |manager, job| {
+			let renamed = fs::rename(&job.path, &destination);
+			manager.hls_jobs.lock().unwrap().remove(&destination);
+			// Directories cannot replace each other, the stream from another process is kept
+			if let Err(e) = renamed {
+				fs::remove_dir_all(&job.path).ok();
+				if !destination.exists() {
+					return Err(e);
+				}
+			}
+			manager.evict(&destination);
+			Ok(())
+		})?;
+		hls_jobs.insert(directory.to_owned(), job.clone());
+		Ok(Some(job))
+	}
+
+	// Starts ffmpeg and waits for it in the background, moving its output into the cache with
+	// `finalize` once it succeeds
+	fn run<F>(
+		&self,
+		mut command: Command,
+		audio_path: &Path,
+		job: Arc<Job>,
+		finalize: F,
+	) -> Result<(), Error>
+	where
+		F: FnOnce(&Manager, &Job) -> io::Result<()> + Send + 'static,
+	{
+		let child = command
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|e| {
+				remove_output(&job.path);
+				Error::FfmpegNotFound(self.ffmpeg_path.clone(), e)
+			})?;
+
+		let manager = self.clone();
+		let audio_path = audio_path.to_owned();
+		thread::spawn(move || {
+			let state = match child.wait_with_output() {
+				Ok(output) if output.status.success() => match finalize(&manager, &job) {
+					Ok(()) => JobState::Done,
+					Err(e) => JobState::Failed(e.to_string()),
+				},
+				Ok(output) => {
+					JobState::Failed(String::from_utf8_lossy(&output.stderr).into_owned())
+				}
+				Err(e) => JobState::Failed(e.to_string()),
+			};
+			if let JobState::Failed(message) = &state {
+				remove_output(&job.path);
+				manager
+					.hls_jobs
+					.lock()
+					.unwrap()
+					.retain(|_, j| !Arc::ptr_eq(j, &job));
+				error!(
+					"ffmpeg could not transcode `{}`: {}",
+					audio_path.display(),
+					message
+				);
+			}
+			job.finish(state);
+		});
+		Ok(())
+	}
+
+	// Reads the given file, or the part of it covered by the segment, and adjusts its volume
+	fn get_command(
+		&self,
+		audio_path: &Path,
+		segment: Option<&Segment>,
+		volume: Option<Volume>,
+	) -> Command {
+		let mut command = Command::new(&self.ffmpeg_path);
+		command
+			.arg("-nostdin")
+			.args(["-loglevel", "error"])
+			.arg("-y");
+		if let Some(segment) = segment {
+			command.args(["-ss", &format_seconds(segment.start)]);
+		}
+		command.arg("-i").arg(audio_path).arg("-vn");
+		if let Some(segment) = segment {
+			if let Some(end) = segment.end {
+				let duration = end.saturating_sub(segment.start);
+				command.args(["-t", &format_seconds(duration)]);
+			}
+		}
+		match volume {
+			Some(Volume::Gain(gain)) => {
+				command.args(["-af", &format!("volume={:.2}dB", gain.decibels())]);
+			}
+			Some(Volume::Loudness(target)) => {
+				command.args(["-af", &format!("loudnorm=I={target}:TP=-1.5:LRA=11")]);
+			}
+			None => (),
+		}
+		command
+	}
+
+	fn hash(path: &Path, options: Option<&Options>, segment: Option<&Segment>) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		path.hash(&mut hasher);
+		if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+			modified.hash(&mut hasher);
+		}
+		options.hash(&mut hasher);
+		segment.hash(&mut hasher);
+		hasher.finish()
+	}
+}
+
+fn remove_output(path: &Path) {
+	if path.is_dir() {
+		fs::remove_dir_all(path).ok();
+	} else {
+		fs::remove_file(path).ok();
+	}
+}
+
+pub fn get_ffmpeg_path() -> PathBuf {
+	std::env::var_os("POLARIS_FFMPEG_PATH")
+		.map(PathBuf::from)
+		.unwrap_or_else(|| PathBuf::from(DEFAULT_FFMPEG_PATH))
+}
+
+pub fn format_seconds(milliseconds: u32) -> String {
+	format!("{}.{:03}", milliseconds / 1000, milliseconds % 1000)
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs::File;
+	use std::time::{Duration, SystemTime};
+
+	use super::*;
+	use crate::app::test;
+	use crate::test_name;
+	use crate::utils::evict_least_recently_used;
+
+	#[test]
+	fn rejects_invalid_bitrates() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let manager = Manager::new(ctx.test_directory.join("transcodes"), ctx.settings_manager);
+		let audio_path =
+			Path::new("test-data/small-collection/Khemmis/Hunted/02 - Candlelight.mp3");
+		for bitrate in [0, 16, 500] {
+			let options = Options {
+				bitrate,
+				..Default::default()
+			};
+			assert!(matches!(
+				manager.get_transcode(audio_path, &options),
+				Err(Error::InvalidBitrate(b)) if b == bitrate
+			));
+		}
+	}
+
+	#[test]
+	fn gain_does_not_clip_peaks() {
+		assert_eq!(Gain::new(-3.5, Some(0.9)), Gain(-350));
+		assert_eq!(Gain::new(4.0, None), Gain(400));
+		assert_eq!(Gain::new(8.0, Some(0.5)), Gain(602));
+		assert_eq!(Gain::new(4.0, Some(0.0)), Gain(400));
+		assert_eq!(Gain::with_target(-3.5, None, -23), Gain(-850));
+	}
+
+	#[test]
+	fn reports_missing_ffmpeg() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let output_dir = ctx.test_directory.join("transcodes");
+		fs::create_dir_all(&output_dir).unwrap();
+		let manager = Manager {
+			transcodes_dir_path: output_dir.clone(),
+			ffmpeg_path: output_dir.join("not-ffmpeg"),
+			settings_manager: ctx.settings_manager,
+			hls_jobs: Arc::default(),
+			cache_limit: CacheLimit::default(),
+		};
+		let audio_path =
+			Path::new("test-data/small-collection/Khemmis/Hunted/02 - Candlelight.mp3");
+		assert!(matches!(
+			manager.get_transcode(audio_path, &Options::default()),
+			Err(Error::FfmpegNotFound(_, _))
+		));
+		assert!(matches!(
+			manager.get_hls_stream(audio_path, None, &Options::default(), Some(10_000)),
+			Err(Error::FfmpegNotFound(_, _))
+		));
+		// Partial streams are cleaned up
+		assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+	}
+
+	// Stands in for ffmpeg, writing half of its output and the rest a second later
+	#[cfg(unix)]
+	fn make_slow_ffmpeg(directory: &Path) -> PathBuf {
+		use std::os::unix::fs::PermissionsExt;
+		let path = directory.join("slow-ffmpeg");
+		fs::write(
+			&path,
+			"#!/bin/sh\n\
+			for output; do :; done\n\
+			case \"$output\" in\n\
+			*.m3u8) directory=$(dirname \"$output\")\n\
+				printf first > \"$directory/0.ts\"\n\
+				sleep 1\n\
+				printf second > \"$directory/1.ts\"\n\
+				printf '#EXTINF:6.0,\\n0.ts\\n#EXTINF:2.0,\\n1.ts\\n' > \"$output\" ;;\n\
+			*) printf first > \"$output\"\n\
+				sleep 1\n\
+				printf second >> \"$output\" ;;\n\
+			esac\n",
+		)
+		.unwrap();
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+		path
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn streams_transcodes_while_they_are_written() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let output_dir = ctx.test_directory.join("transcodes");
+		let manager = Manager {
+			transcodes_dir_path: output_dir.clone(),
+			ffmpeg_path: make_slow_ffmpeg(&ctx.test_directory),
+			settings_manager: ctx.settings_manager,
+			hls_jobs: Arc::default(),
+			cache_limit: CacheLimit::default(),
+		};
+		let audio_path =
+			Path::new("test-data/small-collection/Khemmis/Hunted/02 - Candlelight.mp3");
+
+		let start = std::time::Instant::now();
+		let Ok(Output::Live(mut output)) = manager.get_transcode(audio_path, &Options::default())
+		else {
+			panic!("Transcode was not streamed");
+		};
+		assert!(start.elapsed() < Duration::from_secs(1));
+		let mut content = String::new();
+		output.read_to_string(&mut content).unwrap();
+		assert_eq!(content, "firstsecond");
+
+		let Ok(Output::File(path)) = manager.get_transcode(audio_path, &Options::default()) else {
+			panic!("Transcode was not cached");
+		};
+		assert_eq!(fs::read_to_string(path).unwrap(), "firstsecond");
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn streams_hls_segments_while_they_are_written() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let output_dir = ctx.test_directory.join("transcodes");
+		let manager = Manager {
+			transcodes_dir_path: output_dir.clone(),
+			ffmpeg_path: make_slow_ffmpeg(&ctx.test_directory),
+			settings_manager: ctx.settings_manager,
+			hls_jobs: Arc::default(),
+			cache_limit: CacheLimit::default(),
+		};
+		let audio_path =
+			Path::new("test-data/small-collection/Khemmis/Hunted/02 - Candlelight.mp3");
+		let options = Options::default();
+
+		let start = std::time::Instant::now();
+		let stream = manager
+			.get_hls_stream(audio_path, None, &options, Some(8_000))
+			.unwrap();
+		assert_eq!(stream.segment_durations, vec![6.0, 2.0]);
+		let Ok(Output::Memory(content)) = manager.get_hls_segment(audio_path, None, &options, 0)
+		else {
+			panic!("Segment was not streamed");
+		};
+		assert_eq!(content, b"first");
+		assert!(start.elapsed() < Duration::from_secs(1));
+
+		let content = match manager.get_hls_segment(audio_path, None, &options, 1) {
+			Ok(Output::Memory(content)) => content,
+			Ok(Output::File(path)) => fs::read(path).unwrap(),
+			_ => panic!("Segment was not transcoded"),
+		};
+		assert_eq!(content, b"second");
+		assert!(matches!(
+			manager.get_hls_segment(audio_path, None, &options, 2),
+			Err(Error::StreamSegmentNotFound(2))
+		));
+		let stream = manager
+			.get_hls_stream(audio_path, None, &options, Some(8_000))
+			.unwrap();
+		assert_eq!(stream.segment_durations, vec![6.0, 2.0]);
+	}
+
+	#[test]
+	fn evicts_least_recently_used_streams() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let output_dir = ctx.test_directory.join("transcodes");
+		fs::create_dir_all(&output_dir).unwrap();
+		let now = SystemTime::now();
+		let make_file = |path: &Path, age_seconds: u64| {
+			fs::write(path, [0; 100]).unwrap();
+			File::options()
+				.write(true)
+				.open(path)
+				.unwrap()
+				.set_modified(now - Duration::from_secs(age_seconds))
+				.unwrap();
+		};
+
+		let old_stream = output_dir.join("old.hls");
+		fs::create_dir_all(&old_stream).unwrap();
+		make_file(&old_stream.join(hls::PLAYLIST_FILE_NAME), 300);
+		make_file(&old_stream.join("0.ts"), 300);
+		let recent_stream = output_dir.join("recent.hls");
+		fs::create_dir_all(&recent_stream).unwrap();
+		make_file(&recent_stream.join(hls::PLAYLIST_FILE_NAME), 300);
+		make_file(&recent_stream.join("0.ts"), 100);
+		let partial = output_dir.join("partial.opus.1.0.part");
+		make_file(&partial, 400);
+		let new = output_dir.join("new.opus");
+		make_file(&new, 0);
+
+		evict_least_recently_used(&output_dir, 350, &new).unwrap();
+
+		assert!(!old_stream.exists());
+		assert!(recent_stream.exists());
+		assert!(partial.exists());
+		assert!(new.exists());
+	}
+}
@@ -1,12 +1,19 @@
+use argon2::Argon2;
 use diesel::prelude::*;
-use pbkdf2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use log::{info, warn};
+use pbkdf2::password_hash::{PasswordHash, PasswordHasher, SaltString};
 use pbkdf2::Pbkdf2;
+use rand::distributions::Alphanumeric;
 use rand::rngs::OsRng;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::app::settings::AuthSecret;
-use crate::db::{self, users, DB};
+use crate::app::ldap;
+use crate::app::settings::{self, AuthSecret};
+use crate::app::transcode;
+use crate::db::{self, api_keys, users, DB};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -14,12 +21,24 @@ pub enum Error {
 	Database(#[from] diesel::result::Error),
 	#[error(transparent)]
 	DatabaseConnection(#[from] db::Error),
+	#[error(transparent)]
+	Settings(#[from] settings::Error),
 	#[error("Cannot use empty username")]
 	EmptyUsername,
 	#[error("Cannot use empty password")]
 	EmptyPassword,
+	#[error("Password hash does not have a supported format")]
+	InvalidPasswordHash,
 	#[error("Username does not exist")]
 	IncorrectUsername,
+	#[error("Client does not exist")]
+	ClientNotFound,
+	#[error("API key does not exist")]
+	ApiKeyNotFound,
+	#[error("An API key with this name already exists")]
+	ApiKeyAlreadyExists,
+	#[error("Cannot use empty API key name")]
+	EmptyApiKeyName,
 	#[error("Password does not match username")]
 	IncorrectPassword,
 	#[error("Invalid auth token")]
@@ -28,6 +47,8 @@ pub enum Error {
 	IncorrectAuthorizationScope,
 	#[error("Last.fm session key is missing")]
 	MissingLastFMSessionKey,
+	#[error("ListenBrainz token is missing")]
+	MissingListenBrainzToken,
 	#[error("Failed to hash password")]
 	PasswordHashing,
 	#[error("Failed to encode authorization token")]
@@ -42,6 +63,8 @@ pub struct User {
 	pub name: String,
 	pub password_hash: String,
 	pub admin: i32,
+	// Created for a user authenticated by an external system, rather than listed in the config
+	pub external: bool,
 }
 
 impl User {
@@ -50,11 +73,29 @@ impl User {
 	}
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct NewUser {
 	pub name: String,
+	#[serde(default)]
 	pub password: String,
+	#[serde(default)]
+	pub password_hash: Option<String>,
+	#[serde(default)]
 	pub admin: bool,
+	// Names of the mount points this user can see, all of them when unset
+	#[serde(default)]
+	pub mounts: Option<Vec<String>>,
+}
+
+impl NewUser {
+	pub fn hash_password(&mut self) -> Result<(), Error> {
+		if self.password.is_empty() {
+			return Ok(());
+		}
+		self.password_hash = Some(hash_password(&self.password)?);
+		self.password.clear();
+		Ok(())
+	}
 }
 
 #[derive(Debug)]
@@ -77,30 +118,102 @@ pub struct Preferences {
 	pub lastfm_username: Option<String>,
 	pub web_theme_base: Option<String>,
 	pub web_theme_accent: Option<String>,
+	#[serde(default)]
+	pub transcoding: transcode::Profile,
+}
+
+/// A device registered by a user, with transcoding preferences overriding the user's own.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Client {
+	pub name: String,
+	pub transcoding: transcode::Profile,
+}
+
+/// A long-lived credential letting scripts authenticate as a user without their password.
+/// The key itself is only revealed when it is created.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+	pub name: String,
+	pub creation_time: i64,
+}
+
+// API keys are formatted as `polaris_<key id>_<secret>`. The key id locates the key in the
+// database, the secret is stored hashed like a password.
+const API_KEY_PREFIX: &str = "polaris_";
+const API_KEY_ID_LENGTH: usize = 16;
+const API_KEY_SECRET_LENGTH: usize = 32;
+
+const EXTERNAL_USER_PASSWORD_LENGTH: usize = 32;
+
+pub fn generate_random_string(length: usize) -> String {
+	OsRng
+		.sample_iter(&Alphanumeric)
+		.take(length)
+		.map(char::from)
+		.collect()
+}
+
+// Format, maximum bitrate and normalization columns of users and clients
+type TranscodingColumns = (Option<String>, Option<i32>, Option<String>);
+
+fn read_transcoding_profile(
+	format: Option<String>,
+	max_bitrate: Option<i32>,
+	normalization: Option<String>,
+) -> transcode::Profile {
+	transcode::Profile {
+		format: format.as_deref().and_then(transcode::Format::parse),
+		max_bitrate: max_bitrate.map(|b| b as u32),
+		normalization: normalization
+			.as_deref()
+			.and_then(transcode::Normalization::parse),
+	}
 }
 
 #[derive(Clone)]
 pub struct Manager {
 	db: DB,
 	auth_secret: AuthSecret,
+	settings_manager: settings::Manager,
+	ldap_manager: ldap::Manager,
 }
 
 impl Manager {
-	pub fn new(db: DB, auth_secret: AuthSecret) -> Self {
-		Self { db, auth_secret }
+	pub fn new(
+		db: DB,
+		auth_secret: AuthSecret,
+		settings_manager: settings::Manager,
+		ldap_manager: ldap::Manager,
+	) -> Self {
+		Self {
+			db,
+			auth_secret,
+			settings_manager,
+			ldap_manager,
+		}
 	}
 
 	pub fn create(&self, new_user: &NewUser) -> Result<(), Error> {
+		self.insert(new_user, false)
+	}
+
+	// External users are authenticated by another system (LDAP directory, reverse proxy) rather
+	// than by their local password
+	fn insert(&self, new_user: &NewUser, is_external: bool) -> Result<(), Error> {
 		if new_user.name.is_empty() {
 			return Err(Error::EmptyUsername);
 		}
 
-		let password_hash = hash_password(&new_user.password)?;
+		let password_hash = match &new_user.password_hash {
+			Some(hash) => validate_password_hash(hash)?,
+			None => hash_password(&new_user.password)?,
+		};
 		let mut connection = self.db.connect()?;
 		let new_user = User {
 			name: new_user.name.to_owned(),
 			password_hash,
 			admin: new_user.admin as i32,
+			external: is_external,
 		};
 
 		diesel::insert_into(users::table)
@@ -126,6 +239,16 @@ impl Manager {
 		Ok(())
 	}
 
+	pub fn set_password_hash(&self, username: &str, hash: &str) -> Result<(), Error> {
+		let hash = validate_password_hash(hash)?;
+		let mut connection = self.db.connect()?;
+		use crate::db::users::dsl::*;
+		diesel::update(users.filter(name.eq(username)))
+			.set(password_hash.eq(hash))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
 	pub fn set_is_admin(&self, username: &str, is_admin: bool) -> Result<(), Error> {
 		use crate::db::users::dsl::*;
 		let mut connection = self.db.connect()?;
@@ -135,7 +258,93 @@ impl Manager {
 		Ok(())
 	}
 
+	pub fn set_visible_mounts(
+		&self,
+		username: &str,
+		mount_names: Option<&[String]>,
+	) -> Result<(), Error> {
+		use crate::db::users::dsl::*;
+		let mount_names = mount_names.map(|m| serde_json::to_string(m).unwrap_or_default());
+		let mut connection = self.db.connect()?;
+		diesel::update(users.filter(name.eq(username)))
+			.set(visible_mounts.eq(mount_names))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
 	pub fn login(&self, username: &str, password: &str) -> Result<AuthToken, Error> {
+		if !self.check_ldap_password(username, password)? {
+			self.check_password(username, password)?;
+		}
+		let authorization = Authorization {
+			username: username.to_owned(),
+			scope: AuthorizationScope::PolarisAuth,
+		};
+		self.generate_auth_token(&authorization)
+	}
+
+	// Returns whether the directory accepted these credentials, creating an account for first-time
+	// users. Local users are never checked against it, so a directory entry with the same name
+	// cannot take over their account.
+	fn check_ldap_password(&self, username: &str, password: &str) -> Result<bool, Error> {
+		if !self.ldap_manager.is_enabled() || !self.accepts_external_login(username)? {
+			return Ok(false);
+		}
+		match self.ldap_manager.verify(username, password) {
+			Ok(()) => (),
+			Err(ldap::Error::InvalidCredentials) | Err(ldap::Error::NotInGroup) => {
+				return Ok(false)
+			}
+			Err(e) => {
+				warn!("Could not check credentials against LDAP directory: {}", e);
+				return Ok(false);
+			}
+		}
+		self.provision(username)?;
+		Ok(true)
+	}
+
+	// Whether a user can be authenticated by an external system, which is the case for users it
+	// created and for unknown users
+	fn accepts_external_login(&self, username: &str) -> Result<bool, Error> {
+		use crate::db::users::dsl::*;
+		let mut connection = self.db.connect()?;
+		let is_external: Option<bool> = users
+			.filter(name.eq(username))
+			.select(external)
+			.get_result(&mut connection)
+			.optional()?;
+		Ok(is_external.unwrap_or(true))
+	}
+
+	// Creates an account for a user authenticated by an external system. Its password is never
+	// used, these users keep logging in through that system.
+	pub fn provision(&self, username: &str) -> Result<(), Error> {
+		if self.exists(username)? {
+			return Ok(());
+		}
+		info!("Creating user `{}` authenticated externally", username);
+		let new_user = NewUser {
+			name: username.to_owned(),
+			password: generate_random_string(EXTERNAL_USER_PASSWORD_LENGTH),
+			password_hash: None,
+			admin: false,
+			mounts: None,
+		};
+		self.insert(&new_user, true)
+	}
+
+	// Starts a session for a user whose identity was already verified, without a password
+	pub fn login_trusted(&self, username: &str) -> Result<AuthToken, Error> {
+		self.provision(username)?;
+		let authorization = Authorization {
+			username: username.to_owned(),
+			scope: AuthorizationScope::PolarisAuth,
+		};
+		self.generate_auth_token(&authorization)
+	}
+
+	pub fn check_password(&self, username: &str, password: &str) -> Result<(), Error> {
 		use crate::db::users::dsl::*;
 		let mut connection = self.db.connect()?;
 		match users
@@ -147,11 +356,7 @@ impl Manager {
 			Ok(hash) => {
 				let hash: String = hash;
 				if verify_password(&hash, password) {
-					let authorization = Authorization {
-						username: username.to_owned(),
-						scope: AuthorizationScope::PolarisAuth,
-					};
-					self.generate_auth_token(&authorization)
+					Ok(())
 				} else {
 					Err(Error::IncorrectPassword)
 				}
@@ -165,6 +370,12 @@ impl Manager {
 		auth_token: &AuthToken,
 		scope: AuthorizationScope,
 	) -> Result<Authorization, Error> {
+		if let Some(api_key) = auth_token.0.strip_prefix(API_KEY_PREFIX) {
+			return match scope {
+				AuthorizationScope::PolarisAuth => self.authenticate_api_key(api_key),
+				_ => Err(Error::IncorrectAuthorizationScope),
+			};
+		}
 		let authorization = self.decode_auth_token(auth_token, scope)?;
 		if self.exists(&authorization.username)? {
 			Ok(authorization)
@@ -180,7 +391,9 @@ impl Manager {
 	) -> Result<Authorization, Error> {
 		let AuthToken(data) = auth_token;
 		let ttl = match scope {
-			AuthorizationScope::PolarisAuth => 0,      // permanent
+			AuthorizationScope::PolarisAuth => {
+				self.settings_manager.get_auth_token_lifetime()?.as_secs() as u32
+			}
 			AuthorizationScope::LastFMLink => 10 * 60, // 10 minutes
 		};
 		let authorization = branca::decode(data, &self.auth_secret.key, ttl)
@@ -219,7 +432,7 @@ impl Manager {
 		use crate::db::users::dsl::*;
 		let mut connection = self.db.connect()?;
 		let listed_users = users
-			.select((name, password_hash, admin))
+			.select((name, password_hash, admin, external))
 			.get_results(&mut connection)?;
 		Ok(listed_users)
 	}
@@ -247,14 +460,23 @@ impl Manager {
 	pub fn read_preferences(&self, username: &str) -> Result<Preferences, Error> {
 		use crate::db::users::dsl::*;
 		let mut connection = self.db.connect()?;
-		let (theme_base, theme_accent, read_lastfm_username) = users
-			.select((web_theme_base, web_theme_accent, lastfm_username))
-			.filter(name.eq(username))
-			.get_result(&mut connection)?;
+		let (theme_base, theme_accent, read_lastfm_username, format, max_bitrate, normalization) =
+			users
+				.select((
+					web_theme_base,
+					web_theme_accent,
+					lastfm_username,
+					transcode_format,
+					transcode_max_bitrate,
+					transcode_normalization,
+				))
+				.filter(name.eq(username))
+				.get_result(&mut connection)?;
 		Ok(Preferences {
 			web_theme_base: theme_base,
 			web_theme_accent: theme_accent,
 			lastfm_username: read_lastfm_username,
+			transcoding: read_transcoding_profile(format, max_bitrate, normalization),
 		})
 	}
 
@@ -269,11 +491,185 @@ impl Manager {
 			.set((
 				web_theme_base.eq(&preferences.web_theme_base),
 				web_theme_accent.eq(&preferences.web_theme_accent),
+				transcode_format.eq(preferences.transcoding.format.map(|f| f.as_str())),
+				transcode_max_bitrate.eq(preferences.transcoding.max_bitrate.map(|b| b as i32)),
+				transcode_normalization
+					.eq(preferences.transcoding.normalization.map(|n| n.as_str())),
+			))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
+	pub fn list_clients(&self, username: &str) -> Result<Vec<Client>, Error> {
+		use crate::db::clients::dsl::*;
+		let mut connection = self.db.connect()?;
+		let user_id = self.get_user_id(&mut connection, username)?;
+		let found_clients: Vec<(String, TranscodingColumns)> = clients
+			.filter(owner.eq(user_id))
+			.select((
+				name,
+				(
+					transcode_format,
+					transcode_max_bitrate,
+					transcode_normalization,
+				),
+			))
+			.order(name)
+			.load(&mut connection)?;
+		Ok(found_clients
+			.into_iter()
+			.map(
+				|(client_name, (format, max_bitrate, normalization))| Client {
+					name: client_name,
+					transcoding: read_transcoding_profile(format, max_bitrate, normalization),
+				},
+			)
+			.collect())
+	}
+
+	pub fn save_client(&self, username: &str, client: &Client) -> Result<(), Error> {
+		use crate::db::clients::dsl::*;
+		let mut connection = self.db.connect()?;
+		let user_id = self.get_user_id(&mut connection, username)?;
+		diesel::insert_into(clients)
+			.values((
+				owner.eq(user_id),
+				name.eq(&client.name),
+				transcode_format.eq(client.transcoding.format.map(|f| f.as_str())),
+				transcode_max_bitrate.eq(client.transcoding.max_bitrate.map(|b| b as i32)),
+				transcode_normalization.eq(client.transcoding.normalization.map(|n| n.as_str())),
 			))
 			.execute(&mut connection)?;
 		Ok(())
 	}
 
+	pub fn delete_client(&self, username: &str, client_name: &str) -> Result<(), Error> {
+		use crate::db::clients::dsl::*;
+		let mut connection = self.db.connect()?;
+		let user_id = self.get_user_id(&mut connection, username)?;
+		let deleted = diesel::delete(clients.filter(owner.eq(user_id).and(name.eq(client_name))))
+			.execute(&mut connection)?;
+		match deleted {
+			0 => Err(Error::ClientNotFound),
+			_ => Ok(()),
+		}
+	}
+
+	// Returns the full key, which cannot be retrieved afterwards
+	pub fn create_api_key(&self, username: &str, key_name: &str) -> Result<String, Error> {
+		use crate::db::api_keys::dsl::*;
+		if key_name.is_empty() {
+			return Err(Error::EmptyApiKeyName);
+		}
+		let mut connection = self.db.connect()?;
+		let user_id = self.get_user_id(&mut connection, username)?;
+
+		let num_existing: i64 = api_keys
+			.filter(owner.eq(user_id).and(name.eq(key_name)))
+			.count()
+			.get_result(&mut connection)?;
+		if num_existing > 0 {
+			return Err(Error::ApiKeyAlreadyExists);
+		}
+
+		let new_key_id = generate_random_string(API_KEY_ID_LENGTH);
+		let secret = generate_random_string(API_KEY_SECRET_LENGTH);
+		let now = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs() as i64)
+			.unwrap_or_default();
+		diesel::insert_into(api_keys)
+			.values((
+				owner.eq(user_id),
+				name.eq(key_name),
+				key_id.eq(&new_key_id),
+				key_hash.eq(hash_api_key_secret(&secret)),
+				creation_time.eq(now),
+			))
+			.execute(&mut connection)?;
+
+		Ok(format!("{}{}_{}", API_KEY_PREFIX, new_key_id, secret))
+	}
+
+	pub fn list_api_keys(&self, username: &str) -> Result<Vec<ApiKey>, Error> {
+		use crate::db::api_keys::dsl::*;
+		let mut connection = self.db.connect()?;
+		let user_id = self.get_user_id(&mut connection, username)?;
+		let found_keys: Vec<(String, i64)> = api_keys
+			.filter(owner.eq(user_id))
+			.select((name, creation_time))
+			.order(name)
+			.load(&mut connection)?;
+		Ok(found_keys
+			.into_iter()
+			.map(|(key_name, key_creation_time)| ApiKey {
+				name: key_name,
+				creation_time: key_creation_time,
+			})
+			.collect())
+	}
+
+	pub fn delete_api_key(&self, username: &str, key_name: &str) -> Result<(), Error> {
+		use crate::db::api_keys::dsl::*;
+		let mut connection = self.db.connect()?;
+		let user_id = self.get_user_id(&mut connection, username)?;
+		let deleted = diesel::delete(api_keys.filter(owner.eq(user_id).and(name.eq(key_name))))
+			.execute(&mut connection)?;
+		match deleted {
+			0 => Err(Error::ApiKeyNotFound),
+			_ => Ok(()),
+		}
+	}
+
+	fn authenticate_api_key(&self, api_key: &str) -> Result<Authorization, Error> {
+		let (attempted_key_id, secret) = api_key.split_once('_').ok_or(Error::InvalidAuthToken)?;
+		let mut connection = self.db.connect()?;
+		let (username, hash): (String, String) = api_keys::table
+			.inner_join(users::table)
+			.filter(api_keys::key_id.eq(attempted_key_id))
+			.select((users::name, api_keys::key_hash))
+			.first(&mut connection)
+			.optional()?
+			.ok_or(Error::InvalidAuthToken)?;
+		let digest = hash_api_key_secret(secret);
+		if !constant_time_eq(hash.as_bytes(), digest.as_bytes()) {
+			return Err(Error::InvalidAuthToken);
+		}
+		Ok(Authorization {
+			username,
+			scope: AuthorizationScope::PolarisAuth,
+		})
+	}
+
+	/// Transcoding profile a user's songs should be served with, favoring the profile of the
+	/// requesting client (if it is registered) over the user's preferences.
+	pub fn get_transcode_profile(
+		&self,
+		username: &str,
+		client_name: Option<&str>,
+	) -> Result<transcode::Profile, Error> {
+		if let Some(client_name) = client_name {
+			let found_client = self
+				.list_clients(username)?
+				.into_iter()
+				.find(|c| c.name == client_name);
+			if let Some(client) = found_client {
+				return Ok(client.transcoding);
+			}
+		}
+		Ok(self.read_preferences(username)?.transcoding)
+	}
+
+	fn get_user_id(&self, connection: &mut SqliteConnection, username: &str) -> Result<i32, Error> {
+		use crate::db::users::dsl::*;
+		users
+			.filter(name.eq(username))
+			.select(id)
+			.first(connection)
+			.optional()?
+			.ok_or(Error::IncorrectUsername)
+	}
+
 	pub fn lastfm_link(
 		&self,
 		username: &str,
@@ -321,6 +717,39 @@ impl Manager {
 			.execute(&mut connection)?;
 		Ok(())
 	}
+
+	pub fn listenbrainz_link(&self, username: &str, token: &str) -> Result<(), Error> {
+		use crate::db::users::dsl::*;
+		let mut connection = self.db.connect()?;
+		diesel::update(users.filter(name.eq(username)))
+			.set(listenbrainz_token.eq(token))
+			.execute(&mut connection)?;
+		Ok(())
+	}
+
+	pub fn get_listenbrainz_token(&self, username: &str) -> Result<String, Error> {
+		use crate::db::users::dsl::*;
+		let mut connection = self.db.connect()?;
+		let token: Option<String> = users
+			.filter(name.eq(username))
+			.select(listenbrainz_token)
+			.get_result(&mut connection)?;
+		token.ok_or(Error::MissingListenBrainzToken)
+	}
+
+	pub fn is_listenbrainz_linked(&self, username: &str) -> bool {
+		self.get_listenbrainz_token(username).is_ok()
+	}
+
+	pub fn listenbrainz_unlink(&self, username: &str) -> Result<(), Error> {
+		use crate::db::users::dsl::*;
+		let mut connection = self.db.connect()?;
+		let null: Option<String> = None;
+		diesel::update(users.filter(name.eq(username)))
+			.set(listenbrainz_token.eq(&null))
+			.execute(&mut connection)?;
+		Ok(())
+	}
 }
 
 fn hash_password(password: &str) -> Result<String, Error> {
@@ -334,10 +763,42 @@ fn hash_password(password: &str) -> Result<String, Error> {
 	}
 }
 
+// Secrets of API keys are long and random, so a fast digest protects them as well as a slow
+// password hash would, without slowing down every request made with them
+fn hash_api_key_secret(secret: &str) -> String {
+	Sha256::digest(secret.as_bytes())
+		.iter()
+		.map(|b| format!("{:02x}", b))
+		.collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len()
+		&& a.iter()
+			.zip(b)
+			.fold(0, |difference, (x, y)| difference | (x ^ y))
+			== 0
+}
+
+// Accepts PHC strings produced by any of the supported algorithms, so that
+// hashes generated outside of Polaris (eg. with the argon2 CLI) can be used in config files.
+fn validate_password_hash(password_hash: &str) -> Result<String, Error> {
+	let hash = PasswordHash::new(password_hash).map_err(|_| Error::InvalidPasswordHash)?;
+	match hash.algorithm.as_str() {
+		"argon2d" | "argon2i" | "argon2id" | "pbkdf2-sha256" | "pbkdf2-sha512" => {
+			Ok(password_hash.to_owned())
+		}
+		_ => Err(Error::InvalidPasswordHash),
+	}
+}
+
 fn verify_password(password_hash: &str, attempted_password: &str) -> bool {
 	match PasswordHash::new(password_hash) {
-		Ok(h) => Pbkdf2
-			.verify_password(attempted_password.as_bytes(), &h)
+		Ok(h) => h
+			.verify_password(
+				&[&Argon2::default(), &Pbkdf2],
+				attempted_password.as_bytes(),
+			)
 			.is_ok(),
 		Err(_) => false,
 	}
@@ -359,7 +820,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 
 		ctx.user_manager.create(&new_user).unwrap();
@@ -375,7 +838,9 @@ mod test {
 		let new_user = NewUser {
 			name: "".to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 		assert!(matches!(
 			ctx.user_manager.create(&new_user).unwrap_err(),
@@ -389,7 +854,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: "".to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 		assert!(matches!(
 			ctx.user_manager.create(&new_user).unwrap_err(),
@@ -403,7 +870,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 		ctx.user_manager.create(&new_user).unwrap();
 		ctx.user_manager.create(&new_user).unwrap_err();
@@ -417,12 +886,19 @@ mod test {
 			web_theme_base: Some("very-dark-theme".to_owned()),
 			web_theme_accent: Some("#FF0000".to_owned()),
 			lastfm_username: None,
+			transcoding: transcode::Profile {
+				format: Some(transcode::Format::MP3),
+				max_bitrate: Some(192),
+				normalization: Some(transcode::Normalization::Album),
+			},
 		};
 
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 		ctx.user_manager.create(&new_user).unwrap();
 
@@ -441,7 +917,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 
 		ctx.user_manager.create(&new_user).unwrap();
@@ -459,12 +937,87 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
+			admin: false,
+			mounts: None,
+		};
+		ctx.user_manager.create(&new_user).unwrap();
+		assert!(ctx.user_manager.login(TEST_USERNAME, TEST_PASSWORD).is_ok())
+	}
+
+	#[test]
+	fn login_falls_back_to_local_users_when_ldap_fails() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+		let ldap_config = crate::app::config::Config {
+			ldap: Some(ldap::Config {
+				url: "ldap://127.0.0.1:1".to_owned(),
+				bind_dn_template: "uid={username},dc=example,dc=com".to_owned(),
+				group_filter: None,
+			}),
+			..Default::default()
+		};
+		ctx.config_manager.apply(&ldap_config).unwrap();
+
+		assert!(ctx.user_manager.login(TEST_USERNAME, TEST_PASSWORD).is_ok());
+		assert!(matches!(
+			ctx.user_manager.login("Jesse", TEST_PASSWORD).unwrap_err(),
+			Error::IncorrectUsername
+		));
+		assert!(!ctx.user_manager.exists("Jesse").unwrap());
+	}
+
+	#[test]
+	fn only_external_users_accept_external_logins() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+		ctx.user_manager.provision("Jesse").unwrap();
+
+		assert!(!ctx
+			.user_manager
+			.accepts_external_login(TEST_USERNAME)
+			.unwrap());
+		assert!(ctx.user_manager.accepts_external_login("Jesse").unwrap());
+		assert!(ctx.user_manager.accepts_external_login("Skyler").unwrap());
+	}
+
+	#[test]
+	fn login_accepts_argon2_password_hash() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let salt = SaltString::generate(&mut OsRng);
+		let hash = Argon2::default()
+			.hash_password(TEST_PASSWORD.as_bytes(), &salt)
+			.unwrap()
+			.to_string();
+		let new_user = NewUser {
+			name: TEST_USERNAME.to_owned(),
+			password: "".to_owned(),
+			password_hash: Some(hash),
 			admin: false,
+			mounts: None,
 		};
 		ctx.user_manager.create(&new_user).unwrap();
 		assert!(ctx.user_manager.login(TEST_USERNAME, TEST_PASSWORD).is_ok())
 	}
 
+	#[test]
+	fn cannot_create_user_with_invalid_password_hash() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+		let new_user = NewUser {
+			name: TEST_USERNAME.to_owned(),
+			password: "".to_owned(),
+			password_hash: Some("$2b$12$not.a.supported.hash".to_owned()),
+			admin: false,
+			mounts: None,
+		};
+		assert!(matches!(
+			ctx.user_manager.create(&new_user).unwrap_err(),
+			Error::InvalidPasswordHash
+		));
+	}
+
 	#[test]
 	fn authenticate_rejects_bad_token() {
 		let ctx = test::ContextBuilder::new(test_name!()).build();
@@ -472,7 +1025,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 
 		ctx.user_manager.create(&new_user).unwrap();
@@ -490,7 +1045,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 
 		ctx.user_manager.create(&new_user).unwrap();
@@ -511,6 +1068,38 @@ mod test {
 		)
 	}
 
+	#[test]
+	fn authenticate_rejects_expired_token() {
+		let ctx = test::ContextBuilder::new(test_name!()).build();
+
+		let new_user = NewUser {
+			name: TEST_USERNAME.to_owned(),
+			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
+			admin: false,
+			mounts: None,
+		};
+
+		ctx.user_manager.create(&new_user).unwrap();
+		ctx.settings_manager
+			.amend(&settings::NewSettings {
+				auth_token_lifetime_seconds: Some(1),
+				..Default::default()
+			})
+			.unwrap();
+		let token = ctx
+			.user_manager
+			.login(TEST_USERNAME, TEST_PASSWORD)
+			.unwrap();
+		std::thread::sleep(std::time::Duration::from_secs(3));
+		assert!(matches!(
+			ctx.user_manager
+				.authenticate(&token, AuthorizationScope::PolarisAuth)
+				.unwrap_err(),
+			Error::InvalidAuthToken
+		));
+	}
+
 	#[test]
 	fn authenticate_validates_scope() {
 		let ctx = test::ContextBuilder::new(test_name!()).build();
@@ -518,7 +1107,9 @@ mod test {
 		let new_user = NewUser {
 			name: TEST_USERNAME.to_owned(),
 			password: TEST_PASSWORD.to_owned(),
+			password_hash: None,
 			admin: false,
+			mounts: None,
 		};
 
 		ctx.user_manager.create(&new_user).unwrap();
@@ -534,4 +1125,240 @@ mod test {
 			Error::IncorrectAuthorizationScope
 		));
 	}
+
+	#[test]
+	fn can_save_list_and_delete_clients() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+
+		let client = Client {
+			name: "phone".to_owned(),
+			transcoding: transcode::Profile {
+				format: Some(transcode::Format::Opus),
+				max_bitrate: Some(96),
+				normalization: Some(transcode::Normalization::Loudness),
+			},
+		};
+		ctx.user_manager
+			.save_client(TEST_USERNAME, &client)
+			.unwrap();
+		assert_eq!(
+			ctx.user_manager.list_clients(TEST_USERNAME).unwrap(),
+			vec![client.clone()]
+		);
+
+		let updated_client = Client {
+			transcoding: transcode::Profile::default(),
+			..client
+		};
+		ctx.user_manager
+			.save_client(TEST_USERNAME, &updated_client)
+			.unwrap();
+		assert_eq!(
+			ctx.user_manager.list_clients(TEST_USERNAME).unwrap(),
+			vec![updated_client]
+		);
+
+		ctx.user_manager
+			.delete_client(TEST_USERNAME, "phone")
+			.unwrap();
+		assert!(ctx
+			.user_manager
+			.list_clients(TEST_USERNAME)
+			.unwrap()
+			.is_empty());
+		assert!(matches!(
+			ctx.user_manager
+				.delete_client(TEST_USERNAME, "phone")
+				.unwrap_err(),
+			Error::ClientNotFound
+		));
+	}
+
+	#[test]
+	fn api_key_authenticates_until_deleted() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+
+		let key = ctx
+			.user_manager
+			.create_api_key(TEST_USERNAME, "backup")
+			.unwrap();
+		assert!(key.starts_with(API_KEY_PREFIX));
+		assert!(matches!(
+			ctx.user_manager
+				.create_api_key(TEST_USERNAME, "backup")
+				.unwrap_err(),
+			Error::ApiKeyAlreadyExists
+		));
+
+		let api_keys = ctx.user_manager.list_api_keys(TEST_USERNAME).unwrap();
+		assert_eq!(api_keys.len(), 1);
+		assert_eq!(api_keys[0].name, "backup");
+
+		let token = AuthToken(key);
+		let authorization = ctx
+			.user_manager
+			.authenticate(&token, AuthorizationScope::PolarisAuth)
+			.unwrap();
+		assert_eq!(authorization.username, TEST_USERNAME);
+		assert!(matches!(
+			ctx.user_manager
+				.authenticate(&token, AuthorizationScope::LastFMLink)
+				.unwrap_err(),
+			Error::IncorrectAuthorizationScope
+		));
+
+		ctx.user_manager
+			.delete_api_key(TEST_USERNAME, "backup")
+			.unwrap();
+		assert!(matches!(
+			ctx.user_manager
+				.authenticate(&token, AuthorizationScope::PolarisAuth)
+				.unwrap_err(),
+			Error::InvalidAuthToken
+		));
+		assert!(matches!(
+			ctx.user_manager
+				.delete_api_key(TEST_USERNAME, "backup")
+				.unwrap_err(),
+			Error::ApiKeyNotFound
+		));
+	}
+
+	#[test]
+	fn api_key_rejects_wrong_secret() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+
+		let key = ctx
+			.user_manager
+			.create_api_key(TEST_USERNAME, "backup")
+			.unwrap();
+		let forged = AuthToken(format!("{}garbage", &key[..key.len() - 7]));
+		assert!(matches!(
+			ctx.user_manager
+				.authenticate(&forged, AuthorizationScope::PolarisAuth)
+				.unwrap_err(),
+			Error::InvalidAuthToken
+		));
+	}
+
+	#[test]
+	fn api_key_secrets_are_stored_as_digests() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+
+		let key = ctx
+			.user_manager
+			.create_api_key(TEST_USERNAME, "backup")
+			.unwrap();
+		let (key_id, secret) = key
+			.strip_prefix(API_KEY_PREFIX)
+			.unwrap()
+			.split_once('_')
+			.unwrap();
+		let stored_hash: String = {
+			let mut connection = ctx.db.connect().unwrap();
+			api_keys::table
+				.filter(api_keys::key_id.eq(key_id))
+				.select(api_keys::key_hash)
+				.first(&mut connection)
+				.unwrap()
+		};
+		assert_eq!(stored_hash, hash_api_key_secret(secret));
+		ctx.user_manager
+			.authenticate(&AuthToken(key), AuthorizationScope::PolarisAuth)
+			.unwrap();
+	}
+
+	#[test]
+	fn client_transcoding_overrides_user_preferences() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+
+		assert!(ctx
+			.user_manager
+			.get_transcode_profile(TEST_USERNAME, None)
+			.unwrap()
+			.options()
+			.is_none());
+
+		let preferences = Preferences {
+			transcoding: transcode::Profile {
+				format: Some(transcode::Format::MP3),
+				max_bitrate: None,
+				normalization: None,
+			},
+			..Default::default()
+		};
+		ctx.user_manager
+			.write_preferences(TEST_USERNAME, &preferences)
+			.unwrap();
+
+		let client = Client {
+			name: "phone".to_owned(),
+			transcoding: transcode::Profile {
+				format: Some(transcode::Format::Opus),
+				max_bitrate: Some(64),
+				normalization: Some(transcode::Normalization::Track),
+			},
+		};
+		ctx.user_manager
+			.save_client(TEST_USERNAME, &client)
+			.unwrap();
+
+		let user_options = ctx
+			.user_manager
+			.get_transcode_profile(TEST_USERNAME, Some("desktop"))
+			.unwrap()
+			.options()
+			.unwrap();
+		assert_eq!(user_options.format, transcode::Format::MP3);
+		assert_eq!(user_options.bitrate, 128);
+
+		let client_profile = ctx
+			.user_manager
+			.get_transcode_profile(TEST_USERNAME, Some("phone"))
+			.unwrap();
+		assert_eq!(
+			client_profile.normalization,
+			Some(transcode::Normalization::Track)
+		);
+		let client_options = client_profile.options().unwrap();
+		assert_eq!(client_options.format, transcode::Format::Opus);
+		assert_eq!(client_options.bitrate, 64);
+	}
+
+	#[test]
+	fn can_link_and_unlink_listenbrainz() {
+		let ctx = test::ContextBuilder::new(test_name!())
+			.user(TEST_USERNAME, TEST_PASSWORD, false)
+			.build();
+
+		assert!(!ctx.user_manager.is_listenbrainz_linked(TEST_USERNAME));
+
+		ctx.user_manager
+			.listenbrainz_link(TEST_USERNAME, "some-token")
+			.unwrap();
+		assert_eq!(
+			ctx.user_manager
+				.get_listenbrainz_token(TEST_USERNAME)
+				.unwrap(),
+			"some-token"
+		);
+
+		ctx.user_manager.listenbrainz_unlink(TEST_USERNAME).unwrap();
+		assert!(matches!(
+			ctx.user_manager
+				.get_listenbrainz_token(TEST_USERNAME)
+				.unwrap_err(),
+			Error::MissingListenBrainzToken
+		));
+	}
 }
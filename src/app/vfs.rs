@@ -1,10 +1,16 @@
 use core::ops::Deref;
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
 use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::path::{self, Path, PathBuf};
+use std::path::{self, Component, Path, PathBuf};
 
-use crate::db::{self, mount_points, DB};
+use crate::db::{self, misc_settings, mount_points, users, DB};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -18,17 +24,129 @@ pub enum Error {
 	Database(#[from] diesel::result::Error),
 }
 
-#[derive(Clone, Debug, Deserialize, Insertable, PartialEq, Eq, Queryable, Serialize)]
+/// What a mount contains. Audiobooks remember where each user stopped listening, and are left
+/// out of random selections.
+#[derive(
+	AsExpression,
+	Clone,
+	Copy,
+	Debug,
+	Default,
+	Deserialize,
+	FromSqlRow,
+	PartialEq,
+	Eq,
+	Hash,
+	Serialize,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+	#[default]
+	Music,
+	Audiobook,
+}
+
+impl ContentType {
+	fn as_str(&self) -> &'static str {
+		match self {
+			ContentType::Music => "music",
+			ContentType::Audiobook => "audiobook",
+		}
+	}
+}
+
+impl ToSql<Text, Sqlite> for ContentType {
+	fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+		out.set_value(self.as_str());
+		Ok(IsNull::No)
+	}
+}
+
+impl FromSql<Text, Sqlite> for ContentType {
+	fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+		let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+		match value.as_str() {
+			"audiobook" => Ok(ContentType::Audiobook),
+			_ => Ok(ContentType::Music),
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Insertable, PartialEq, Eq, Queryable, Serialize)]
 #[diesel(table_name = mount_points)]
 pub struct MountDir {
 	pub source: String,
 	pub name: String,
+	#[serde(default)]
+	pub watch: bool,
+	#[serde(default)]
+	pub follow_symlinks: bool,
+	#[serde(default)]
+	pub content_type: ContentType,
+	/// Overrides the `album_art_pattern` setting for this mount.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub album_art_pattern: Option<String>,
+	/// Directory mirroring the structure of `source`, whose album art takes precedence over
+	/// the art found next to the music.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub art_overlay: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Hash, Serialize)]
 pub struct Mount {
 	pub source: PathBuf,
 	pub name: String,
+	#[serde(default)]
+	pub follow_symlinks: bool,
+	#[serde(default)]
+	pub content_type: ContentType,
+	#[serde(default)]
+	pub album_art_pattern: Option<String>,
+	#[serde(default)]
+	pub art_overlay: Option<PathBuf>,
+}
+
+impl Mount {
+	// Symlinks never lead outside of the mount, and are only crossed when the mount follows them.
+	// Paths which do not exist are let through so callers can report them as missing.
+	pub fn allows(&self, real_path: &Path) -> bool {
+		is_within(&self.source, real_path, self.follow_symlinks)
+	}
+
+	// Only album art is taken from the overlay, so it cannot stand in for songs or playlists
+	fn get_overlay_file(&self, path: &Path, default_pattern: Option<&Regex>) -> Option<PathBuf> {
+		let art_overlay = self.art_overlay.as_ref()?;
+		let mount_pattern = self
+			.album_art_pattern
+			.as_ref()
+			.and_then(|p| Regex::new(&format!("(?i){}", p)).ok());
+		let pattern = mount_pattern.as_ref().or(default_pattern)?;
+		let file_name = path.file_name()?.to_str()?;
+		if !pattern.is_match(file_name) {
+			return None;
+		}
+		let overlay_path = art_overlay.join(path);
+		if overlay_path.is_file() && is_within(art_overlay, &overlay_path, self.follow_symlinks) {
+			Some(overlay_path)
+		} else {
+			None
+		}
+	}
+}
+
+fn is_within(root: &Path, real_path: &Path, follow_symlinks: bool) -> bool {
+	let (Ok(canonical_root), Ok(target)) = (root.canonicalize(), real_path.canonicalize()) else {
+		return true;
+	};
+	if follow_symlinks {
+		target.starts_with(&canonical_root)
+	} else {
+		match real_path.strip_prefix(root) {
+			Ok(p) => canonical_root.join(p) == target,
+			Err(_) => false,
+		}
+	}
 }
 
 impl From<MountDir> for Mount {
@@ -36,11 +154,17 @@ impl From<MountDir> for Mount {
 		let separator_regex = Regex::new(r"\\|/").unwrap();
 		let mut correct_separator = String::new();
 		correct_separator.push(path::MAIN_SEPARATOR);
-		let path_string = separator_regex.replace_all(&m.source, correct_separator.as_str());
-		let source = PathBuf::from(path_string.deref());
+		let clean_path = |path: &str| {
+			let path_string = separator_regex.replace_all(path, correct_separator.as_str());
+			PathBuf::from(path_string.deref())
+		};
 		Self {
 			name: m.name,
-			source,
+			source: clean_path(&m.source),
+			follow_symlinks: m.follow_symlinks,
+			content_type: m.content_type,
+			album_art_pattern: m.album_art_pattern,
+			art_overlay: m.art_overlay.as_deref().map(clean_path),
 		}
 	}
 }
@@ -48,16 +172,33 @@ impl From<MountDir> for Mount {
 #[allow(clippy::upper_case_acronyms)]
 pub struct VFS {
 	mounts: Vec<Mount>,
+	album_art_pattern: Option<Regex>,
 }
 
 impl VFS {
 	pub fn new(mounts: Vec<Mount>) -> VFS {
-		VFS { mounts }
+		VFS {
+			mounts,
+			album_art_pattern: None,
+		}
+	}
+
+	// Pattern deciding which files can be read from art overlays, unless their mount has its own
+	pub fn with_album_art_pattern(mut self, album_art_pattern: Option<Regex>) -> VFS {
+		self.album_art_pattern = album_art_pattern;
+		self
 	}
 
 	pub fn real_to_virtual<P: AsRef<Path>>(&self, real_path: P) -> Result<PathBuf, Error> {
 		for mount in &self.mounts {
-			if let Ok(p) = real_path.as_ref().strip_prefix(&mount.source) {
+			let stripped = real_path
+				.as_ref()
+				.strip_prefix(&mount.source)
+				.or_else(|e| match &mount.art_overlay {
+					Some(art_overlay) => real_path.as_ref().strip_prefix(art_overlay),
+					None => Err(e),
+				});
+			if let Ok(p) = stripped {
 				let mount_path = Path::new(&mount.name);
 				return if p.components().count() == 0 {
 					Ok(mount_path.to_path_buf())
@@ -73,11 +214,24 @@ impl VFS {
 		for mount in &self.mounts {
 			let mount_path = Path::new(&mount.name);
 			if let Ok(p) = virtual_path.as_ref().strip_prefix(mount_path) {
-				return if p.components().count() == 0 {
-					Ok(mount.source.clone())
+				if !p.components().all(|c| matches!(c, Component::Normal(_))) {
+					break;
+				}
+				let real_path = if p.components().count() == 0 {
+					mount.source.clone()
 				} else {
-					Ok(mount.source.join(p))
+					// Album art from the overlay shadows that of the mount
+					if let Some(overlay_path) =
+						mount.get_overlay_file(p, self.album_art_pattern.as_ref())
+					{
+						return Ok(overlay_path);
+					}
+					mount.source.join(p)
 				};
+				if !mount.allows(&real_path) {
+					break;
+				}
+				return Ok(real_path);
 			}
 		}
 		Err(Error::CouldNotMapToRealPath(virtual_path.as_ref().into()))
@@ -86,6 +240,17 @@ impl VFS {
 	pub fn mounts(&self) -> &Vec<Mount> {
 		&self.mounts
 	}
+
+	pub fn is_audiobook<P: AsRef<Path>>(&self, virtual_path: P) -> bool {
+		self.mounts.iter().any(|m| {
+			m.content_type == ContentType::Audiobook
+				&& virtual_path.as_ref().starts_with(Path::new(&m.name))
+		})
+	}
+
+	fn retain_mounts(&mut self, mount_names: &[String]) {
+		self.mounts.retain(|m| mount_names.contains(&m.name));
+	}
 }
 
 #[derive(Clone)]
@@ -100,15 +265,54 @@ impl Manager {
 
 	pub fn get_vfs(&self) -> Result<VFS, Error> {
 		let mount_dirs = self.mount_dirs()?;
-		let mounts = mount_dirs.into_iter().map(|p| p.into()).collect();
-		Ok(VFS::new(mounts))
+		let mounts: Vec<Mount> = mount_dirs.into_iter().map(|p| p.into()).collect();
+		let album_art_pattern = match mounts.iter().any(|m| m.art_overlay.is_some()) {
+			true => self.get_album_art_pattern()?,
+			false => None,
+		};
+		Ok(VFS::new(mounts).with_album_art_pattern(album_art_pattern))
+	}
+
+	fn get_album_art_pattern(&self) -> Result<Option<Regex>, Error> {
+		let mut connection = self.db.connect()?;
+		let pattern: Option<String> = misc_settings::table
+			.select(misc_settings::index_album_art_pattern)
+			.first(&mut connection)
+			.optional()?;
+		Ok(pattern.and_then(|p| Regex::new(&format!("(?i){}", p)).ok()))
+	}
+
+	// Only contains the mount points visible to this user
+	pub fn get_vfs_for_user(&self, username: &str) -> Result<VFS, Error> {
+		let mut vfs = self.get_vfs()?;
+		let mut connection = self.db.connect()?;
+		let visible_mounts: Option<String> = users::table
+			.filter(users::name.eq(username))
+			.select(users::visible_mounts)
+			.first(&mut connection)
+			.optional()?
+			.flatten();
+		if let Some(visible_mounts) = visible_mounts {
+			let mount_names: Vec<String> =
+				serde_json::from_str(&visible_mounts).unwrap_or_default();
+			vfs.retain_mounts(&mount_names);
+		}
+		Ok(vfs)
 	}
 
 	pub fn mount_dirs(&self) -> Result<Vec<MountDir>, Error> {
 		use self::mount_points::dsl::*;
 		let mut connection = self.db.connect()?;
 		let mount_dirs: Vec<MountDir> = mount_points
-			.select((source, name))
+			.select((
+				source,
+				name,
+				watch,
+				follow_symlinks,
+				content_type,
+				album_art_pattern,
+				art_overlay,
+			))
 			.get_results(&mut connection)?;
 		Ok(mount_dirs)
 	}
@@ -137,6 +341,10 @@ mod test {
 		let vfs = VFS::new(vec![Mount {
 			name: "root".to_owned(),
 			source: Path::new("test_dir").to_owned(),
+			follow_symlinks: false,
+			content_type: ContentType::Music,
+			album_art_pattern: None,
+			art_overlay: None,
 		}]);
 		let real_path: PathBuf = ["test_dir", "somewhere", "something.png"].iter().collect();
 		let virtual_path: PathBuf = ["root", "somewhere", "something.png"].iter().collect();
@@ -149,6 +357,10 @@ mod test {
 		let vfs = VFS::new(vec![Mount {
 			name: "root".to_owned(),
 			source: Path::new("test_dir").to_owned(),
+			follow_symlinks: false,
+			content_type: ContentType::Music,
+			album_art_pattern: None,
+			art_overlay: None,
 		}]);
 		let real_path = Path::new("test_dir");
 		let converted_path = vfs.virtual_to_real(Path::new("root")).unwrap();
@@ -160,6 +372,10 @@ mod test {
 		let vfs = VFS::new(vec![Mount {
 			name: "root".to_owned(),
 			source: Path::new("test_dir").to_owned(),
+			follow_symlinks: false,
+			content_type: ContentType::Music,
+			album_art_pattern: None,
+			art_overlay: None,
 		}]);
 		let virtual_path: PathBuf = ["root", "somewhere", "something.png"].iter().collect();
 		let real_path: PathBuf = ["test_dir", "somewhere", "something.png"].iter().collect();
@@ -167,6 +383,86 @@ mod test {
 		assert_eq!(converted_path, virtual_path);
 	}
 
+	#[test]
+	fn converts_virtual_to_real_in_art_overlay() {
+		let vfs = VFS::new(vec![Mount {
+			name: "root".to_owned(),
+			source: Path::new("test-data/small-collection/Khemmis").to_owned(),
+			follow_symlinks: false,
+			content_type: ContentType::Music,
+			album_art_pattern: None,
+			art_overlay: Some(Path::new("test-data/small-collection/Tobokegao").to_owned()),
+		}])
+		.with_album_art_pattern(Some(Regex::new("(?i)Folder.(jpeg|jpg|png)").unwrap()));
+
+		let overlay_file: PathBuf = [
+			"test-data",
+			"small-collection",
+			"Tobokegao",
+			"Picnic",
+			"Folder.png",
+		]
+		.iter()
+		.collect();
+		let virtual_path: PathBuf = ["root", "Picnic", "Folder.png"].iter().collect();
+		assert_eq!(vfs.virtual_to_real(&virtual_path).unwrap(), overlay_file);
+		assert_eq!(vfs.real_to_virtual(&overlay_file).unwrap(), virtual_path);
+
+		let mount_file: PathBuf = [
+			"test-data",
+			"small-collection",
+			"Khemmis",
+			"Hunted",
+			"Folder.jpg",
+		]
+		.iter()
+		.collect();
+		let virtual_path: PathBuf = ["root", "Hunted", "Folder.jpg"].iter().collect();
+		assert_eq!(vfs.virtual_to_real(&virtual_path).unwrap(), mount_file);
+
+		// Files other than album art are never read from the overlay
+		let song_file: PathBuf = [
+			"test-data",
+			"small-collection",
+			"Khemmis",
+			"Picnic",
+			"02 - バースデーパーティ (Birthday Party).mp3",
+		]
+		.iter()
+		.collect();
+		let virtual_path: PathBuf = [
+			"root",
+			"Picnic",
+			"02 - バースデーパーティ (Birthday Party).mp3",
+		]
+		.iter()
+		.collect();
+		assert_eq!(vfs.virtual_to_real(&virtual_path).unwrap(), song_file);
+	}
+
+	#[test]
+	fn rejects_parent_directories_in_virtual_paths() {
+		let vfs = VFS::new(vec![Mount {
+			name: "root".to_owned(),
+			source: Path::new("test-data/small-collection/Khemmis").to_owned(),
+			follow_symlinks: false,
+			content_type: ContentType::Music,
+			album_art_pattern: None,
+			art_overlay: Some(Path::new("test-data/small-collection/Khemmis/Hunted").to_owned()),
+		}])
+		.with_album_art_pattern(Some(Regex::new("(?i)Folder.(jpeg|jpg|png)").unwrap()));
+
+		let virtual_path: PathBuf = ["root", "..", "Tobokegao", "Picnic", "Folder.png"]
+			.iter()
+			.collect();
+		assert!(vfs.virtual_to_real(&virtual_path).is_err());
+
+		let virtual_path: PathBuf = ["root", "..", "..", "Hunted", "Folder.jpg"]
+			.iter()
+			.collect();
+		assert!(vfs.virtual_to_real(&virtual_path).is_err());
+	}
+
 	#[test]
 	fn cleans_path_string() {
 		let mut correct_path = path::PathBuf::new();
@@ -200,6 +496,11 @@ mod test {
 			let mount_dir = MountDir {
 				source: test.to_owned(),
 				name: "name".to_owned(),
+				watch: false,
+				follow_symlinks: false,
+				content_type: ContentType::Music,
+				album_art_pattern: None,
+				art_overlay: None,
 			};
 			let mount: Mount = mount_dir.into();
 			assert_eq!(mount.source, correct_path);
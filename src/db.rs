@@ -1,16 +1,30 @@
 use diesel::r2d2::{self, ConnectionManager, PooledConnection};
 use diesel::sqlite::SqliteConnection;
-use diesel::RunQueryDsl;
+use diesel::{Connection, RunQueryDsl};
 use diesel_migrations::EmbeddedMigrations;
 use diesel_migrations::MigrationHarness;
+use log::info;
 use std::path::{Path, PathBuf};
 
+use crate::utils;
+
 mod schema;
 
 pub use self::schema::*;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
+// Enough connections for API requests to keep reading while an index update holds a few
+const MAX_CONNECTIONS: u32 = 32;
+
+sql_function!(
+	/// Implemented by `utils::get_search_key`
+	fn search_key(
+		text: diesel::sql_types::Nullable<diesel::sql_types::Text>,
+		fold_accents: diesel::sql_types::Bool,
+	) -> diesel::sql_types::Nullable<diesel::sql_types::Text>;
+);
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
 	#[error("Could not initialize database connection pool")]
@@ -21,10 +35,17 @@ pub enum Error {
 	Io(PathBuf, std::io::Error),
 	#[error("Could not apply database migrations")]
 	Migration,
+	#[error("Could not back up database to `{0}`")]
+	Backup(PathBuf),
+	#[error("Could not restore database from `{0}`")]
+	Restore(PathBuf),
+	#[error("Could not checkpoint database")]
+	Checkpoint,
 }
 
 #[derive(Clone)]
 pub struct DB {
+	path: PathBuf,
 	pool: r2d2::Pool<ConnectionManager<SqliteConnection>>,
 }
 
@@ -45,6 +66,10 @@ impl diesel::r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error>
 		query
 			.execute(connection)
 			.map_err(diesel::r2d2::Error::QueryError)?;
+		search_key::register_impl(connection, |text: Option<String>, fold_accents: bool| {
+			text.map(|t| utils::get_search_key(&t, fold_accents))
+		})
+		.map_err(diesel::r2d2::Error::QueryError)?;
 		Ok(())
 	}
 }
@@ -55,10 +80,22 @@ impl DB {
 		std::fs::create_dir_all(directory).map_err(|e| Error::Io(directory.to_owned(), e))?;
 		let manager = ConnectionManager::<SqliteConnection>::new(path.to_string_lossy());
 		let pool = diesel::r2d2::Pool::builder()
+			.max_size(MAX_CONNECTIONS)
 			.connection_customizer(Box::new(ConnectionCustomizer {}))
 			.build(manager)
 			.or(Err(Error::ConnectionPoolBuild))?;
-		let db = DB { pool };
+		let db = DB {
+			path: path.to_owned(),
+			pool,
+		};
+		if db.has_pending_upgrade()? {
+			let backup_path = get_backup_path(path);
+			info!(
+				"Upgrading database schema, previous version saved to `{}`",
+				backup_path.to_string_lossy()
+			);
+			db.backup(&backup_path)?;
+		}
 		db.migrate_up()?;
 		Ok(db)
 	}
@@ -76,6 +113,94 @@ impl DB {
 			.or(Err(Error::Migration))
 	}
 
+	// Databases which were never migrated are brand new and have nothing worth backing up
+	fn has_pending_upgrade(&self) -> Result<bool, Error> {
+		let mut connection = self.connect()?;
+		let has_applied_migrations = !connection
+			.applied_migrations()
+			.or(Err(Error::Migration))?
+			.is_empty();
+		let has_pending_migrations = connection
+			.has_pending_migration(MIGRATIONS)
+			.or(Err(Error::Migration))?;
+		Ok(has_applied_migrations && has_pending_migrations)
+	}
+
+	// Safe to call while the database is in use, the copy is made from a single read transaction
+	pub fn backup(&self, backup_path: &Path) -> Result<(), Error> {
+		if backup_path.exists() {
+			std::fs::remove_file(backup_path).map_err(|e| Error::Io(backup_path.to_owned(), e))?;
+		}
+		let mut connection = self.connect()?;
+		diesel::sql_query("VACUUM INTO ?")
+			.bind::<diesel::sql_types::Text, _>(backup_path.to_string_lossy())
+			.execute(&mut connection)
+			.map_err(|_| Error::Backup(backup_path.to_owned()))?;
+		Ok(())
+	}
+
+	pub fn snapshot(&self) -> Result<Vec<u8>, Error> {
+		let mut file_name = self.path.file_name().unwrap_or_default().to_owned();
+		file_name.push(format!(".snapshot-{}", rand::random::<u64>()));
+		let snapshot_path = self.path.with_file_name(file_name);
+		self.backup(&snapshot_path)?;
+		let content =
+			std::fs::read(&snapshot_path).map_err(|e| Error::Io(snapshot_path.clone(), e));
+		std::fs::remove_file(&snapshot_path).map_err(|e| Error::Io(snapshot_path.clone(), e))?;
+		content
+	}
+
+	// Replaces the database at `path` with a backup. This must not happen while the database is
+	// in use, so it is meant for when Polaris is stopped.
+	pub fn restore(path: &Path, backup_path: &Path) -> Result<(), Error> {
+		let mut file_name = path.file_name().unwrap_or_default().to_owned();
+		file_name.push(".restore");
+		let staging_path = path.with_file_name(file_name);
+		let directory = path.parent().unwrap();
+		std::fs::create_dir_all(directory).map_err(|e| Error::Io(directory.to_owned(), e))?;
+		std::fs::copy(backup_path, &staging_path)
+			.map_err(|e| Error::Io(backup_path.to_owned(), e))?;
+
+		// Validates the backup and brings it up to date with the current schema
+		let is_database = SqliteConnection::establish(&staging_path.to_string_lossy())
+			.ok()
+			.and_then(|mut connection| {
+				diesel::sql_query("SELECT COUNT(*) FROM sqlite_master")
+					.execute(&mut connection)
+					.ok()
+			})
+			.is_some();
+		let staged = match is_database {
+			true => DB::new(&staging_path).and_then(|db| db.checkpoint()),
+			false => Err(Error::Restore(backup_path.to_owned())),
+		};
+		for leftover in [get_backup_path(&staging_path), get_wal_path(&staging_path)] {
+			let _ = std::fs::remove_file(leftover);
+		}
+		if staged.is_err() {
+			let _ = std::fs::remove_file(&staging_path);
+			return Err(Error::Restore(backup_path.to_owned()));
+		}
+
+		for stale_path in [get_wal_path(path), get_shm_path(path)] {
+			if stale_path.exists() {
+				std::fs::remove_file(&stale_path).map_err(|e| Error::Io(stale_path, e))?;
+			}
+		}
+		std::fs::rename(&staging_path, path).map_err(|e| Error::Io(path.to_owned(), e))?;
+		Ok(())
+	}
+
+	// Moves the content of the write-ahead log into the database file, so nothing is left to
+	// recover the next time the database is opened
+	pub fn checkpoint(&self) -> Result<(), Error> {
+		let mut connection = self.connect()?;
+		diesel::sql_query("PRAGMA wal_checkpoint(TRUNCATE)")
+			.execute(&mut connection)
+			.map_err(|_| Error::Checkpoint)?;
+		Ok(())
+	}
+
 	fn migrate_up(&self) -> Result<(), Error> {
 		let mut connection = self.connect()?;
 		connection
@@ -85,6 +210,24 @@ impl DB {
 	}
 }
 
+fn get_backup_path(path: &Path) -> PathBuf {
+	let mut file_name = path.file_name().unwrap_or_default().to_owned();
+	file_name.push(".backup");
+	path.with_file_name(file_name)
+}
+
+fn get_wal_path(path: &Path) -> PathBuf {
+	let mut file_name = path.file_name().unwrap_or_default().to_owned();
+	file_name.push("-wal");
+	path.with_file_name(file_name)
+}
+
+fn get_shm_path(path: &Path) -> PathBuf {
+	let mut file_name = path.file_name().unwrap_or_default().to_owned();
+	file_name.push("-shm");
+	path.with_file_name(file_name)
+}
+
 #[test]
 fn run_migrations() {
 	use crate::test::*;
@@ -96,3 +239,123 @@ fn run_migrations() {
 	db.migrate_down().unwrap();
 	db.migrate_up().unwrap();
 }
+
+#[test]
+fn new_database_is_not_backed_up() {
+	use crate::test::*;
+	use crate::test_name;
+	let output_dir = prepare_test_directory(test_name!());
+	let db_path = output_dir.join("db.sqlite");
+	DB::new(&db_path).unwrap();
+	assert!(!get_backup_path(&db_path).exists());
+}
+
+#[test]
+fn upgraded_database_is_backed_up() {
+	use crate::test::*;
+	use crate::test_name;
+	let output_dir = prepare_test_directory(test_name!());
+	let db_path = output_dir.join("db.sqlite");
+
+	{
+		let db = DB::new(&db_path).unwrap();
+		let mut connection = db.connect().unwrap();
+		connection.revert_last_migration(MIGRATIONS).unwrap();
+	}
+
+	DB::new(&db_path).unwrap();
+
+	let backup_path = get_backup_path(&db_path);
+	assert!(backup_path.exists());
+	let backup = DB::new(&backup_path).unwrap();
+	assert!(!backup.has_pending_upgrade().unwrap());
+}
+
+#[test]
+fn reads_are_not_blocked_by_writes() {
+	use crate::test::*;
+	use crate::test_name;
+	use diesel::prelude::*;
+	let output_dir = prepare_test_directory(test_name!());
+	let db_path = output_dir.join("db.sqlite");
+	let db = DB::new(&db_path).unwrap();
+
+	let mut writer = db.connect().unwrap();
+	let mut reader = db.connect().unwrap();
+	diesel::sql_query("PRAGMA busy_timeout = 0")
+		.execute(&mut reader)
+		.unwrap();
+	writer
+		.immediate_transaction::<_, diesel::result::Error, _>(|writer| {
+			diesel::insert_into(directories::table)
+				.values(directories::path.eq("some_directory"))
+				.execute(writer)?;
+			let count = directories::table.count().get_result::<i64>(&mut reader)?;
+			assert_eq!(count, 0);
+			Ok(())
+		})
+		.unwrap();
+}
+
+#[test]
+fn backup_can_be_restored() {
+	use crate::test::*;
+	use crate::test_name;
+	use diesel::prelude::*;
+	let output_dir = prepare_test_directory(test_name!());
+	let db_path = output_dir.join("db.sqlite");
+	let backup_path = output_dir.join("backup.sqlite");
+
+	{
+		let db = DB::new(&db_path).unwrap();
+		let mut connection = db.connect().unwrap();
+		diesel::insert_into(directories::table)
+			.values(directories::path.eq("some_directory"))
+			.execute(&mut connection)
+			.unwrap();
+		db.backup(&backup_path).unwrap();
+		diesel::delete(directories::table)
+			.execute(&mut connection)
+			.unwrap();
+	}
+
+	DB::restore(&db_path, &backup_path).unwrap();
+
+	let db = DB::new(&db_path).unwrap();
+	let mut connection = db.connect().unwrap();
+	let count = directories::table
+		.count()
+		.get_result::<i64>(&mut connection)
+		.unwrap();
+	assert_eq!(count, 1);
+}
+
+#[test]
+fn invalid_backup_is_not_restored() {
+	use crate::test::*;
+	use crate::test_name;
+	let output_dir = prepare_test_directory(test_name!());
+	let db_path = output_dir.join("db.sqlite");
+	let backup_path = output_dir.join("backup.sqlite");
+	DB::new(&db_path).unwrap();
+	std::fs::write(&backup_path, "not a database").unwrap();
+
+	assert!(DB::restore(&db_path, &backup_path).is_err());
+	assert!(DB::new(&db_path).is_ok());
+}
+
+#[test]
+fn checkpoint_empties_write_ahead_log() {
+	use crate::test::*;
+	use crate::test_name;
+	let output_dir = prepare_test_directory(test_name!());
+	let db_path = output_dir.join("db.sqlite");
+	let db = DB::new(&db_path).unwrap();
+
+	db.checkpoint().unwrap();
+
+	let wal_size = std::fs::metadata(get_wal_path(&db_path))
+		.map(|m| m.len())
+		.unwrap_or(0);
+	assert_eq!(wal_size, 0);
+}
@@ -1,9 +1,47 @@
+table! {
+	api_keys (id) {
+		id -> Integer,
+		owner -> Integer,
+		name -> Text,
+		key_id -> Text,
+		key_hash -> Text,
+		creation_time -> BigInt,
+	}
+}
+
+table! {
+	audiobook_positions (id) {
+		id -> Integer,
+		user -> Integer,
+		path -> Text,
+		position_seconds -> Integer,
+		update_time -> BigInt,
+	}
+}
+
+table! {
+	clients (id) {
+		id -> Integer,
+		owner -> Integer,
+		name -> Text,
+		transcode_format -> Nullable<Text>,
+		transcode_max_bitrate -> Nullable<Integer>,
+		transcode_normalization -> Nullable<Text>,
+	}
+}
+
 table! {
 	ddns_config (id) {
 		id -> Integer,
 		host -> Text,
 		username -> Text,
 		password -> Text,
+		update_url -> Text,
+		update_interval_seconds -> Integer,
+		cloudflare_api_token -> Nullable<Text>,
+		cloudflare_zone -> Nullable<Text>,
+		cloudflare_record -> Nullable<Text>,
+		ip_version -> Text,
 	}
 }
 
@@ -20,12 +58,77 @@ table! {
 	}
 }
 
+table! {
+	favorites (id) {
+		id -> Integer,
+		user -> Integer,
+		path -> Text,
+	}
+}
+
+table! {
+	listens (id) {
+		id -> Integer,
+		user -> Integer,
+		path -> Text,
+		timestamp -> BigInt,
+		completion -> Nullable<Integer>,
+	}
+}
+
+table! {
+	metadata_lookups (id) {
+		id -> Integer,
+		path -> Text,
+		title -> Nullable<Text>,
+		artist -> Nullable<Text>,
+		album -> Nullable<Text>,
+		year -> Nullable<Integer>,
+		musicbrainz_recording_id -> Nullable<Text>,
+		musicbrainz_release_id -> Nullable<Text>,
+		musicbrainz_artist_id -> Nullable<Text>,
+	}
+}
+
 table! {
 	misc_settings (id) {
 		id -> Integer,
 		auth_secret -> Binary,
 		index_sleep_duration_seconds -> Integer,
 		index_album_art_pattern -> Text,
+		auth_token_lifetime_seconds -> Integer,
+		index_album_art_priority -> Text,
+		index_num_threads -> Integer,
+		index_ignore_patterns -> Text,
+		thumbnail_cache_size_mb -> Integer,
+		index_group_compilations -> Bool,
+		collation_locale -> Text,
+		search_accent_folding -> Bool,
+		transcode_loudness_target -> Integer,
+		index_metadata_lookup -> Bool,
+		acoustid_api_key -> Text,
+		index_missing_grace_period_days -> Integer,
+		index_max_files_per_second -> Integer,
+		index_pause_while_streaming -> Bool,
+		search_fuzzy -> Bool,
+		bandwidth_limit_kbps -> Integer,
+		bandwidth_user_limit_kbps -> Integer,
+		bandwidth_stream_limit_kbps -> Integer,
+		max_streams_per_user -> Integer,
+		index_pregenerate_thumbnails -> Bool,
+		transcode_cache_size_mb -> Integer,
+	}
+}
+
+table! {
+	missing_songs (id) {
+		id -> Integer,
+		path -> Text,
+		title -> Nullable<Text>,
+		artist -> Nullable<Text>,
+		album -> Nullable<Text>,
+		missing_since -> BigInt,
+		content_hash -> Nullable<Text>,
 	}
 }
 
@@ -34,6 +137,31 @@ table! {
 		id -> Integer,
 		source -> Text,
 		name -> Text,
+		watch -> Bool,
+		follow_symlinks -> Bool,
+		content_type -> Text,
+		album_art_pattern -> Nullable<Text>,
+		art_overlay -> Nullable<Text>,
+	}
+}
+
+table! {
+	play_queue_songs (id) {
+		id -> Integer,
+		user -> Integer,
+		path -> Text,
+		ordering -> Integer,
+	}
+}
+
+table! {
+	play_queues (user) {
+		user -> Integer,
+		current_track -> Nullable<Integer>,
+		position_seconds -> Integer,
+		device -> Nullable<Text>,
+		playing -> Bool,
+		update_time -> BigInt,
 	}
 }
 
@@ -54,6 +182,87 @@ table! {
 	}
 }
 
+table! {
+	podcast_episodes (id) {
+		id -> Integer,
+		podcast -> Integer,
+		guid -> Text,
+		title -> Text,
+		description -> Nullable<Text>,
+		audio_url -> Text,
+		mime_type -> Nullable<Text>,
+		publication_date -> Nullable<BigInt>,
+		duration -> Nullable<Integer>,
+		position_seconds -> Integer,
+		completed -> Bool,
+	}
+}
+
+table! {
+	podcasts (id) {
+		id -> Integer,
+		owner -> Integer,
+		url -> Text,
+		title -> Text,
+		description -> Nullable<Text>,
+		artwork_url -> Nullable<Text>,
+		auto_download -> Bool,
+		last_refresh -> Nullable<BigInt>,
+	}
+}
+
+table! {
+	radio_stations (id) {
+		id -> Integer,
+		owner -> Nullable<Integer>,
+		name -> Text,
+		stream_url -> Text,
+		homepage_url -> Nullable<Text>,
+	}
+}
+
+table! {
+	ratings (id) {
+		id -> Integer,
+		user -> Integer,
+		path -> Text,
+		rating -> Integer,
+	}
+}
+
+table! {
+	shares (id) {
+		id -> Integer,
+		owner -> Integer,
+		token -> Text,
+		path -> Nullable<Text>,
+		playlist -> Nullable<Text>,
+		creation_time -> BigInt,
+		expiration_time -> Nullable<BigInt>,
+		allow_download -> Bool,
+		max_plays -> Nullable<Integer>,
+		play_count -> Integer,
+	}
+}
+
+table! {
+	smart_playlists (id) {
+		id -> Integer,
+		owner -> Integer,
+		name -> Text,
+		rules -> Text,
+	}
+}
+
+table! {
+	song_fingerprints (id) {
+		id -> Integer,
+		path -> Text,
+		file_modified -> BigInt,
+		fingerprint -> Binary,
+	}
+}
+
 table! {
 	songs (id) {
 		id -> Integer,
@@ -72,6 +281,57 @@ table! {
 		composer -> Nullable<Text>,
 		genre -> Nullable<Text>,
 		label -> Nullable<Text>,
+		file_modified -> Nullable<BigInt>,
+		file_size -> Nullable<BigInt>,
+		has_embedded_artwork -> Nullable<Bool>,
+		cue_source -> Nullable<Text>,
+		cue_start -> Nullable<Integer>,
+		cue_end -> Nullable<Integer>,
+		disc_total -> Nullable<Integer>,
+		compilation -> Bool,
+		compilation_tag -> Bool,
+		conductor -> Nullable<Text>,
+		artist_sort -> Nullable<Text>,
+		album_artist_sort -> Nullable<Text>,
+		album_sort -> Nullable<Text>,
+		title_sort -> Nullable<Text>,
+		track_gain -> Nullable<Float>,
+		track_peak -> Nullable<Float>,
+		album_gain -> Nullable<Float>,
+		album_peak -> Nullable<Float>,
+		lyrics -> Nullable<Text>,
+		has_embedded_lyrics -> Nullable<Bool>,
+		musicbrainz_recording_id -> Nullable<Text>,
+		musicbrainz_release_id -> Nullable<Text>,
+		musicbrainz_artist_id -> Nullable<Text>,
+		musicbrainz_album_artist_id -> Nullable<Text>,
+		content_hash -> Nullable<Text>,
+		sample_rate -> Nullable<Integer>,
+		encoder_delay -> Nullable<Integer>,
+		encoder_padding -> Nullable<Integer>,
+		sample_count -> Nullable<BigInt>,
+	}
+}
+
+table! {
+	sync_playlists (id) {
+		id -> Integer,
+		owner -> Integer,
+		name -> Text,
+		signature -> BigInt,
+		created_revision -> BigInt,
+		revision -> BigInt,
+		removed -> Bool,
+	}
+}
+
+table! {
+	sync_songs (path) {
+		path -> Text,
+		signature -> BigInt,
+		created_revision -> BigInt,
+		revision -> BigInt,
+		removed -> Bool,
 	}
 }
 
@@ -85,19 +345,57 @@ table! {
 		lastfm_session_key -> Nullable<Text>,
 		web_theme_base -> Nullable<Text>,
 		web_theme_accent -> Nullable<Text>,
+		transcode_format -> Nullable<Text>,
+		transcode_max_bitrate -> Nullable<Integer>,
+		listenbrainz_token -> Nullable<Text>,
+		visible_mounts -> Nullable<Text>,
+		transcode_normalization -> Nullable<Text>,
+		external -> Bool,
 	}
 }
 
+joinable!(api_keys -> users (owner));
+joinable!(audiobook_positions -> users (user));
+joinable!(clients -> users (owner));
+joinable!(favorites -> users (user));
+joinable!(listens -> users (user));
+joinable!(play_queue_songs -> users (user));
+joinable!(play_queues -> users (user));
 joinable!(playlist_songs -> playlists (playlist));
 joinable!(playlists -> users (owner));
+joinable!(podcast_episodes -> podcasts (podcast));
+joinable!(podcasts -> users (owner));
+joinable!(radio_stations -> users (owner));
+joinable!(ratings -> users (user));
+joinable!(shares -> users (owner));
+joinable!(smart_playlists -> users (owner));
+joinable!(sync_playlists -> users (owner));
 
 allow_tables_to_appear_in_same_query!(
+	api_keys,
+	audiobook_positions,
+	clients,
 	ddns_config,
 	directories,
+	favorites,
+	listens,
+	metadata_lookups,
 	misc_settings,
+	missing_songs,
 	mount_points,
+	play_queue_songs,
+	play_queues,
 	playlist_songs,
 	playlists,
+	podcast_episodes,
+	podcasts,
+	radio_stations,
+	ratings,
+	shares,
+	smart_playlists,
+	song_fingerprints,
 	songs,
+	sync_playlists,
+	sync_songs,
 	users,
 );
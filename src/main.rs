@@ -6,7 +6,7 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
-use log::info;
+use log::{error, info, warn};
 use simplelog::{
 	ColorChoice, CombinedLogger, LevelFilter, SharedLogger, TermLogger, TerminalMode, WriteLogger,
 };
@@ -29,6 +29,8 @@ pub enum Error {
 	App(#[from] app::Error),
 	#[error("Could not parse command line arguments:\n\n{0}")]
 	CliArgsParsing(getopts::Fail),
+	#[error(transparent)]
+	Database(#[from] db::Error),
 	#[cfg(unix)]
 	#[error("Failed to turn polaris process into a daemon:\n\n{0}")]
 	Daemonize(daemonize::Error),
@@ -72,11 +74,15 @@ fn notify_ready() -> Result<(), Error> {
 
 fn init_logging<T: AsRef<Path>>(
 	log_level: LevelFilter,
+	log_targets: &[String],
 	log_file_path: &Option<T>,
 ) -> Result<(), Error> {
-	let log_config = simplelog::ConfigBuilder::new()
-		.set_location_level(LevelFilter::Error)
-		.build();
+	let mut log_config_builder = simplelog::ConfigBuilder::new();
+	log_config_builder.set_location_level(LevelFilter::Error);
+	for target in log_targets {
+		log_config_builder.add_filter_allow(target.clone());
+	}
+	let log_config = log_config_builder.build();
 
 	let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
 		log_level,
@@ -103,6 +109,20 @@ fn init_logging<T: AsRef<Path>>(
 	Ok(())
 }
 
+fn run_command(command: &options::Command, paths: &paths::Paths) -> Result<(), Error> {
+	match command {
+		options::Command::Backup(file) => {
+			db::DB::new(&paths.db_file_path)?.backup(file)?;
+			println!("Saved database backup to `{}`", file.display());
+		}
+		options::Command::Restore(file) => {
+			db::DB::restore(&paths.db_file_path, file)?;
+			println!("Restored database from `{}`", file.display());
+		}
+	}
+	Ok(())
+}
+
 fn main() -> Result<(), Error> {
 	// Parse CLI options
 	let args: Vec<String> = std::env::args().collect();
@@ -113,16 +133,33 @@ fn main() -> Result<(), Error> {
 
 	if cli_options.show_help {
 		let program = args[0].clone();
-		let brief = format!("Usage: {} [options]", program);
+		let brief = format!("Usage: {} [options] [backup|restore FILE]", program);
 		print!("{}", options_manager.usage(&brief));
 		return Ok(());
 	}
 
-	let paths = paths::Paths::new(&cli_options);
+	let mut paths = paths::Paths::new(&cli_options);
+
+	if let Some(command) = &cli_options.command {
+		return run_command(command, &paths);
+	}
 
 	// Logging
-	let log_level = cli_options.log_level.unwrap_or(LevelFilter::Info);
-	init_logging(log_level, &paths.log_file_path)?;
+	// Config file errors are reported when the app starts, once logging is available
+	let logging_config = paths
+		.config_file_path
+		.as_ref()
+		.and_then(|p| app::config::Config::from_path(p).ok())
+		.and_then(|c| c.logging)
+		.unwrap_or_default();
+	if cli_options.log_file_path.is_none() && logging_config.file_path.is_some() {
+		paths.log_file_path = logging_config.file_path;
+	}
+	let log_level = cli_options
+		.log_level
+		.or(logging_config.level)
+		.unwrap_or(LevelFilter::Info);
+	init_logging(log_level, &logging_config.targets, &paths.log_file_path)?;
 
 	// Fork
 	#[cfg(unix)]
@@ -142,11 +179,25 @@ fn main() -> Result<(), Error> {
 	// Create and run app
 	let app = app::App::new(cli_options.port.unwrap_or(5050), paths)?;
 	app.index.begin_periodic_updates();
+	app.index.begin_watching_mounts();
 	app.ddns_manager.begin_periodic_updates();
+	app.podcast_manager.begin_periodic_refresh();
+	app.dlna_manager.begin_announcing();
+	app.mpd_manager.begin_listening();
+	if let Some(config_path) = &app.config_file_path {
+		app.config_manager
+			.begin_watching(config_path.clone(), app.index.clone());
+	}
+
+	let index = app.index.clone();
+	let jukebox_manager = app.jukebox_manager.clone();
+	let dlna_manager = app.dlna_manager.clone();
+	let db = app.db.clone();
+	let shutdown_grace_period = app.shutdown_grace_period;
 
 	// Start server
 	info!("Starting up server");
-	std::thread::spawn(move || {
+	let server = std::thread::spawn(move || {
 		let _ = service::run(app);
 	});
 
@@ -154,9 +205,17 @@ fn main() -> Result<(), Error> {
 	#[cfg(unix)]
 	notify_ready()?;
 
-	// Run UI
-	ui::run();
+	// Run UI, which returns after the server stops or when the user exits
+	ui::run(server);
 
 	info!("Shutting down server");
+	jukebox_manager.shutdown();
+	dlna_manager.shutdown();
+	if !index.shutdown(shutdown_grace_period) {
+		warn!("Index update did not complete within the shutdown grace period");
+	}
+	if let Err(e) = db.checkpoint() {
+		error!("Could not close database cleanly: {}", e);
+	}
 	Ok(())
 }
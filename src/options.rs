@@ -1,8 +1,14 @@
 use simplelog::LevelFilter;
 use std::path::PathBuf;
 
+pub enum Command {
+	Backup(PathBuf),
+	Restore(PathBuf),
+}
+
 pub struct CLIOptions {
 	pub show_help: bool,
+	pub command: Option<Command>,
 	pub foreground: bool,
 	pub log_file_path: Option<PathBuf>,
 	#[cfg(unix)]
@@ -29,9 +35,19 @@ impl Manager {
 
 	pub fn parse(&self, input: &[String]) -> Result<CLIOptions, getopts::Fail> {
 		let matches = self.protocol.parse(input)?;
+		let command = match matches.free.as_slice() {
+			[] => None,
+			[name, file] if name == "backup" => Some(Command::Backup(PathBuf::from(file))),
+			[name, file] if name == "restore" => Some(Command::Restore(PathBuf::from(file))),
+			[name] if name == "backup" || name == "restore" => {
+				return Err(getopts::Fail::ArgumentMissing(name.clone()))
+			}
+			[argument, ..] => return Err(getopts::Fail::UnexpectedArgument(argument.clone())),
+		};
 
 		Ok(CLIOptions {
 			show_help: matches.opt_present("h"),
+			command,
 			#[cfg(unix)]
 			foreground: matches.opt_present("f"),
 			#[cfg(windows)]
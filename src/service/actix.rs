@@ -1,52 +1,87 @@
 use actix_web::{
 	dev::Service,
+	http::KeepAlive,
 	middleware::{Compress, Logger, NormalizePath},
 	rt::System,
 	web::{self, ServiceConfig},
 	App as ActixApp, HttpServer,
 };
 use log::error;
+use std::time::Duration;
 
-use crate::app::App;
+use crate::app::{tls, App};
 
 mod api;
+mod dlna;
 
 #[cfg(test)]
 pub mod test;
 
 pub fn make_config(app: App) -> impl FnOnce(&mut ServiceConfig) + Clone {
 	move |cfg: &mut ServiceConfig| {
-		cfg.app_data(web::Data::new(app.index))
+		let max_body_size_mb = app.server_config.max_body_size_mb;
+		cfg.app_data(web::Data::new(app.db))
+			.app_data(web::Data::new(app.index))
+			.app_data(web::Data::new(app.artist_info_manager))
+			.app_data(web::Data::new(app.audiobook_manager))
+			.app_data(web::Data::new(app.bandwidth_manager))
 			.app_data(web::Data::new(app.config_manager))
 			.app_data(web::Data::new(app.ddns_manager))
+			.app_data(web::Data::new(app.dlna_manager))
+			.app_data(web::Data::new(app.duplicate_manager))
+			.app_data(web::Data::new(app.events_manager))
+			.app_data(web::Data::new(app.favorite_manager))
+			.app_data(web::Data::new(app.history_manager))
+			.app_data(web::Data::new(app.jukebox_manager))
 			.app_data(web::Data::new(app.lastfm_manager))
+			.app_data(web::Data::new(app.listenbrainz_manager))
+			.app_data(web::Data::new(app.peaks_manager))
 			.app_data(web::Data::new(app.playlist_manager))
+			.app_data(web::Data::new(app.podcast_manager))
+			.app_data(web::Data::new(app.proxy_auth_manager))
+			.app_data(web::Data::new(app.queue_manager))
+			.app_data(web::Data::new(app.radio_manager))
+			.app_data(web::Data::new(app.rating_manager))
+			.app_data(web::Data::new(app.session_manager))
+			.app_data(web::Data::new(app.smart_playlist_manager))
 			.app_data(web::Data::new(app.settings_manager))
+			.app_data(web::Data::new(app.share_manager))
+			.app_data(web::Data::new(app.sync_manager))
 			.app_data(web::Data::new(app.thumbnail_manager))
+			.app_data(web::Data::new(app.throttle_manager))
+			.app_data(web::Data::new(app.transcode_manager))
 			.app_data(web::Data::new(app.user_manager))
 			.app_data(web::Data::new(app.vfs_manager))
+			.app_data(web::Data::new(api::UrlBase(app.url_base.clone())))
 			.service(
-				web::scope("/api")
-					.configure(api::make_config())
-					.wrap(NormalizePath::trim()),
-			)
-			.service(
-				actix_files::Files::new("/swagger", app.swagger_dir_path)
-					.redirect_to_slash_directory()
-					.index_file("index.html"),
-			)
-			.service(
-				actix_files::Files::new("/", app.web_dir_path)
-					.redirect_to_slash_directory()
-					.index_file("index.html"),
+				web::scope(&app.url_base)
+					.service(
+						web::scope("/api")
+							.configure(api::make_config(max_body_size_mb))
+							.wrap(NormalizePath::trim()),
+					)
+					.service(web::scope("/dlna").configure(dlna::make_config()))
+					.service(
+						actix_files::Files::new("/swagger", app.swagger_dir_path)
+							.redirect_to_slash_directory()
+							.index_file("index.html"),
+					)
+					.service(
+						actix_files::Files::new("/", app.web_dir_path)
+							.redirect_to_slash_directory()
+							.index_file("index.html"),
+					),
 			);
 	}
 }
 
 pub fn run(app: App) -> Result<(), std::io::Error> {
 	let address = ("0.0.0.0", app.port);
-	System::new().block_on(
-		HttpServer::new(move || {
+	let tls_config = app.tls_config.clone();
+	let shutdown_grace_period = app.shutdown_grace_period;
+	let server_config = app.server_config.clone();
+	System::new().block_on({
+		let mut server = HttpServer::new(move || {
 			ActixApp::new()
 				.wrap(Logger::default())
 				.wrap_fn(|req, srv| {
@@ -65,12 +100,45 @@ pub fn run(app: App) -> Result<(), std::io::Error> {
 				.wrap(Compress::default())
 				.configure(make_config(app.clone()))
 		})
-		.disable_signals()
-		.bind(address)
-		.map_err(|e| {
-			error!("Error starting HTTP server: {:?}", e);
-			e
-		})?
-		.run(),
-	)
+		// Stops accepting connections on SIGINT / SIGTERM and lets in-flight requests drain
+		.shutdown_timeout(shutdown_grace_period.as_secs());
+
+		if let Some(seconds) = server_config.keep_alive {
+			server = match seconds {
+				0 => server.keep_alive(KeepAlive::Disabled),
+				_ => server.keep_alive(Duration::from_secs(seconds)),
+			};
+		}
+		if let Some(seconds) = server_config.request_timeout {
+			server = server.client_request_timeout(Duration::from_secs(seconds));
+		}
+		if let Some(seconds) = server_config.disconnect_timeout {
+			server = server.client_disconnect_timeout(Duration::from_secs(seconds));
+		}
+		if let Some(max_connections) = server_config.max_connections {
+			// Connections are limited for each worker, so the limit is spread across all of them
+			let workers = std::thread::available_parallelism().map_or(2, |n| n.get());
+			server = server
+				.workers(workers)
+				.max_connections(max_connections.div_ceil(workers));
+		}
+
+		let server = match tls_config {
+			Some(tls_config) => {
+				let server_config = tls::make_server_config(tls_config).map_err(|e| {
+					error!("Error loading TLS certificate: {}", e);
+					std::io::Error::other(e)
+				})?;
+				server.bind_rustls_021(address, server_config)
+			}
+			None => server.bind(address),
+		};
+
+		server
+			.map_err(|e| {
+				error!("Error starting HTTP server: {:?}", e);
+				e
+			})?
+			.run()
+	})
 }
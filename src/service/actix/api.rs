@@ -1,38 +1,56 @@
 use actix_files::NamedFile;
-use actix_web::body::BoxBody;
-use actix_web::http::header::ContentEncoding;
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::header::{
+	self, ContentDisposition, ContentEncoding, ContentType, ETag, EntityTag, HeaderName, HttpDate,
+	IfModifiedSince, IfNoneMatch, LastModified,
+};
 use actix_web::{
 	delete,
 	dev::Payload,
 	error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
 	get,
 	http::StatusCode,
-	post, put,
+	post, put, route,
 	web::{self, Data, Json, JsonConfig, ServiceConfig},
-	FromRequest, HttpRequest, HttpResponse, Responder, ResponseError,
+	FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError,
 };
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use base64::prelude::*;
 use futures_util::future::err;
+use futures_util::{stream, StreamExt};
 use percent_encoding::percent_decode_str;
+use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
 use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::str;
+use std::task::{self, ready, Poll};
+use std::time::{Duration, SystemTime};
 
 use crate::app::{
-	config, ddns,
+	artist_info, audiobook, bandwidth, config, ddns, download, duplicate, events, favorite,
+	history, hls,
 	index::{self, Index},
-	lastfm, playlist, settings, thumbnail, user,
+	jukebox, lastfm, listenbrainz, peaks, playlist, podcast, proxy_auth, queue, radio, rating,
+	session, settings, share, smart_playlist, sync, throttle, thumbnail, transcode, user,
 	vfs::{self, MountDir},
 };
+use crate::db::DB;
 use crate::service::{dto, error::*};
+use crate::utils::{self, AudioFormat};
 
-pub fn make_config() -> impl FnOnce(&mut ServiceConfig) + Clone {
+pub fn make_config(max_body_size_mb: Option<usize>) -> impl FnOnce(&mut ServiceConfig) + Clone {
 	move |cfg: &mut ServiceConfig| {
 		let megabyte = 1024 * 1024;
-		cfg.app_data(JsonConfig::default().limit(4 * megabyte)) // 4MB
+		let json_limit = max_body_size_mb.unwrap_or(4) * megabyte; // 4MB
+		let payload_limit = max_body_size_mb.unwrap_or(16) * megabyte; // 16MB, for artwork uploads
+		cfg.app_data(JsonConfig::default().limit(json_limit))
+			.app_data(web::PayloadConfig::new(payload_limit))
 			.service(version)
+			.service(health)
 			.service(initial_setup)
 			.service(apply_config)
 			.service(get_settings)
@@ -45,29 +63,115 @@ pub fn make_config() -> impl FnOnce(&mut ServiceConfig) + Clone {
 			.service(create_user)
 			.service(update_user)
 			.service(delete_user)
+			.service(change_password)
 			.service(get_preferences)
 			.service(put_preferences)
+			.service(list_clients)
+			.service(put_client)
+			.service(delete_client)
+			.service(list_api_keys)
+			.service(create_api_key)
+			.service(delete_api_key)
 			.service(trigger_index)
+			.service(get_index_status)
+			.service(get_duplicates)
+			.service(get_events)
+			.service(get_missing_songs)
+			.service(purge_missing_songs)
+			.service(list_sessions)
+			.service(terminate_session)
+			.service(get_backup)
 			.service(login)
+			.service(logout)
 			.service(browse_root)
 			.service(browse)
+			.service(browse_view_root)
+			.service(browse_view)
 			.service(flatten_root)
 			.service(flatten)
 			.service(random)
 			.service(recent)
+			.service(list_favorites)
+			.service(add_favorite)
+			.service(remove_favorite)
+			.service(get_queue)
+			.service(clear_queue)
+			.service(append_to_queue)
+			.service(insert_next_in_queue)
+			.service(move_queue_track)
+			.service(remove_from_queue)
+			.service(set_queue_current_track)
+			.service(get_playback)
+			.service(get_changes)
+			.service(report_playback)
+			.service(get_jukebox)
+			.service(jukebox_play)
+			.service(jukebox_pause)
+			.service(jukebox_next)
+			.service(jukebox_previous)
+			.service(set_jukebox_volume)
+			.service(set_rating)
+			.service(clear_rating)
+			.service(record_listen)
+			.service(recently_played)
+			.service(most_played)
 			.service(search_root)
+			.service(search_suggestions)
 			.service(search)
 			.service(get_audio)
+			.service(get_hls_master_playlist)
+			.service(get_hls_playlist)
+			.service(get_hls_segment)
+			.service(get_download)
 			.service(get_thumbnail)
+			.service(get_peaks)
+			.service(get_artist_biography)
+			.service(get_artist_image)
+			.service(get_lyrics)
+			.service(get_audiobook)
+			.service(set_audiobook_position)
+			.service(list_audiobook_positions)
+			.service(put_tags)
+			.service(put_artwork)
 			.service(list_playlists)
 			.service(save_playlist)
 			.service(read_playlist)
+			.service(rename_playlist)
+			.service(export_playlist)
+			.service(import_playlists)
 			.service(delete_playlist)
+			.service(list_smart_playlists)
+			.service(save_smart_playlist)
+			.service(read_smart_playlist)
+			.service(delete_smart_playlist)
+			.service(list_podcasts)
+			.service(subscribe_to_podcast)
+			.service(unsubscribe_from_podcast)
+			.service(refresh_podcast)
+			.service(list_podcast_episodes)
+			.service(set_podcast_episode_position)
+			.service(download_podcast_episode)
+			.service(remove_podcast_episode_download)
+			.service(get_podcast_episode_audio)
+			.service(list_radio_stations)
+			.service(add_radio_station)
+			.service(remove_radio_station)
+			.service(get_radio_stream)
+			.service(list_shares)
+			.service(create_share)
+			.service(delete_share)
+			.service(get_share)
+			.service(get_share_audio)
+			.service(get_share_download)
 			.service(lastfm_now_playing)
 			.service(lastfm_scrobble)
 			.service(lastfm_link_token)
 			.service(lastfm_link)
-			.service(lastfm_unlink);
+			.service(lastfm_unlink)
+			.service(listenbrainz_link)
+			.service(listenbrainz_unlink)
+			.service(now_playing)
+			.service(scrobble);
 	}
 }
 
@@ -75,19 +179,51 @@ impl ResponseError for APIError {
 	fn status_code(&self) -> StatusCode {
 		match self {
 			APIError::AuthorizationTokenEncoding => StatusCode::INTERNAL_SERVER_ERROR,
-			APIError::AdminPermissionRequired => StatusCode::UNAUTHORIZED,
+			APIError::AdminPermissionRequired => StatusCode::FORBIDDEN,
+			APIError::ApiKeyAlreadyExists => StatusCode::CONFLICT,
+			APIError::ApiKeyNotFound => StatusCode::NOT_FOUND,
+			APIError::ArchiveTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+			APIError::ArtistBiographyNotFound => StatusCode::NOT_FOUND,
+			APIError::ArtistImageNotFound => StatusCode::NOT_FOUND,
+			APIError::ArtistInfoNotConfigured => StatusCode::NOT_IMPLEMENTED,
+			APIError::ArtistInfoRequestFailed => StatusCode::FAILED_DEPENDENCY,
+			APIError::ArtworkFormatUnsupported => StatusCode::UNSUPPORTED_MEDIA_TYPE,
 			APIError::AudioFileIOError => StatusCode::NOT_FOUND,
 			APIError::AuthenticationRequired => StatusCode::UNAUTHORIZED,
 			APIError::BrancaTokenEncoding => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::ChaptersReading(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::ClientNotFound => StatusCode::NOT_FOUND,
 			APIError::DdnsUpdateQueryFailed(s) => {
 				StatusCode::from_u16(*s).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
 			}
 			APIError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::DLNAAccessForbidden => StatusCode::FORBIDDEN,
+			APIError::DLNAInvalidRequest => StatusCode::BAD_REQUEST,
+			APIError::DLNANotEnabled => StatusCode::NOT_IMPLEMENTED,
 			APIError::DeletingOwnAccount => StatusCode::CONFLICT,
+			APIError::EditFormatUnsupported => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+			APIError::EditTags(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::EmbeddedArtworkNotFound => StatusCode::NOT_FOUND,
+			APIError::EmptyApiKeyName => StatusCode::BAD_REQUEST,
 			APIError::EmptyPassword => StatusCode::BAD_REQUEST,
+			APIError::EmptyRadioStationName => StatusCode::BAD_REQUEST,
 			APIError::EmptyUsername => StatusCode::BAD_REQUEST,
+			APIError::FavoriteNotFound => StatusCode::NOT_FOUND,
+			APIError::Fingerprint(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::FingerprintUnavailable => StatusCode::NOT_IMPLEMENTED,
 			APIError::IncorrectCredentials => StatusCode::UNAUTHORIZED,
+			APIError::InvalidIndexIgnorePattern(_) => StatusCode::BAD_REQUEST,
+			APIError::InvalidPasswordHash => StatusCode::BAD_REQUEST,
+			APIError::InvalidPodcastUrl => StatusCode::BAD_REQUEST,
+			APIError::InvalidRadioStreamUrl => StatusCode::BAD_REQUEST,
+			APIError::InvalidSyncToken => StatusCode::BAD_REQUEST,
+			APIError::InvalidRating => StatusCode::BAD_REQUEST,
+			APIError::InvalidShareTarget => StatusCode::BAD_REQUEST,
+			APIError::InvalidSmartPlaylistRule(_) => StatusCode::BAD_REQUEST,
+			APIError::InvalidVolume => StatusCode::BAD_REQUEST,
+			APIError::JukeboxNotEnabled => StatusCode::NOT_IMPLEMENTED,
+			APIError::JukeboxQueueEmpty => StatusCode::BAD_REQUEST,
+			APIError::JukeboxUnavailable => StatusCode::NOT_IMPLEMENTED,
 			APIError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::Io(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::LastFMAccountNotLinked => StatusCode::NO_CONTENT,
@@ -96,17 +232,49 @@ impl ResponseError for APIError {
 			APIError::LastFMNowPlaying(_) => StatusCode::FAILED_DEPENDENCY,
 			APIError::LastFMScrobble(_) => StatusCode::FAILED_DEPENDENCY,
 			APIError::LastFMScrobblerAuthentication(_) => StatusCode::FAILED_DEPENDENCY,
+			APIError::ListenBrainzAccountNotLinked => StatusCode::NO_CONTENT,
+			APIError::ListenBrainzInvalidToken => StatusCode::BAD_REQUEST,
+			APIError::ListenBrainzRequestFailed => StatusCode::FAILED_DEPENDENCY,
+			APIError::LyricsNotFound => StatusCode::NOT_FOUND,
+			APIError::NotAnAudiobook => StatusCode::BAD_REQUEST,
+			APIError::LyricsReading(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::OwnAdminPrivilegeRemoval => StatusCode::CONFLICT,
 			APIError::PasswordHashing => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::Peaks(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::PeaksUnavailable => StatusCode::NOT_IMPLEMENTED,
 			APIError::PlaylistNotFound => StatusCode::NOT_FOUND,
+			APIError::PlaylistAlreadyExists => StatusCode::CONFLICT,
+			APIError::PodcastAlreadySubscribed => StatusCode::CONFLICT,
+			APIError::PodcastEpisodeNotFound => StatusCode::NOT_FOUND,
+			APIError::PodcastEpisodeTooLarge => StatusCode::FAILED_DEPENDENCY,
+			APIError::PodcastFeedInvalid => StatusCode::FAILED_DEPENDENCY,
+			APIError::PodcastNotFound => StatusCode::NOT_FOUND,
+			APIError::PodcastRequestFailed => StatusCode::FAILED_DEPENDENCY,
+			APIError::QueueTrackNotFound(_) => StatusCode::NOT_FOUND,
+			APIError::RadioProxyNotEnabled => StatusCode::NOT_IMPLEMENTED,
+			APIError::RadioStationNotFound => StatusCode::NOT_FOUND,
+			APIError::RadioStreamFailed => StatusCode::FAILED_DEPENDENCY,
+			APIError::RatingNotFound => StatusCode::NOT_FOUND,
+			APIError::SessionNotFound => StatusCode::NOT_FOUND,
 			APIError::Settings(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::ShareDownloadNotAllowed => StatusCode::FORBIDDEN,
+			APIError::ShareExpired => StatusCode::GONE,
+			APIError::ShareNotFound => StatusCode::NOT_FOUND,
+			APIError::SharePlayLimitReached => StatusCode::GONE,
+			APIError::SmartPlaylistNotFound => StatusCode::NOT_FOUND,
 			APIError::SongMetadataNotFound => StatusCode::NOT_FOUND,
+			APIError::StreamSegmentNotFound(_) => StatusCode::NOT_FOUND,
 			APIError::ThumbnailFlacDecoding(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::ThumbnailFileIOError => StatusCode::NOT_FOUND,
 			APIError::ThumbnailId3Decoding(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::ThumbnailImageDecoding(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::ThumbnailMp4Decoding(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::TomlDeserialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::TooManyLoginAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
+			APIError::TooManyStreams(_) => StatusCode::TOO_MANY_REQUESTS,
+			APIError::Transcoding(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
+			APIError::TranscodingUnavailable => StatusCode::NOT_IMPLEMENTED,
+			APIError::UnsupportedTranscodingBitrate(_) => StatusCode::BAD_REQUEST,
 			APIError::UnsupportedThumbnailFormat(_) => StatusCode::INTERNAL_SERVER_ERROR,
 			APIError::UserNotFound => StatusCode::NOT_FOUND,
 			APIError::VFSPathNotFound => StatusCode::NOT_FOUND,
@@ -114,8 +282,59 @@ impl ResponseError for APIError {
 	}
 
 	fn error_response(&self) -> HttpResponse<BoxBody> {
-		HttpResponse::new(self.status_code())
+		let mut response = HttpResponse::build(self.status_code());
+		if let APIError::TooManyLoginAttempts(retry_after) = self {
+			response.insert_header((header::RETRY_AFTER, retry_after.as_secs().max(1)));
+		}
+		response.finish()
+	}
+}
+
+const AUTH_COOKIE_NAME: &str = "auth_token";
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+const EVENTS_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// Path prefix all routes are served under, empty when Polaris is served from the root
+pub struct UrlBase(pub String);
+
+// Cookies are only sent back over https when the request came in over https, either directly
+// or according to the `X-Forwarded-Proto` header of a reverse proxy
+fn make_auth_cookie(token: String, url_base: &UrlBase, request: &HttpRequest) -> Cookie<'static> {
+	let secure = request.connection_info().scheme() == "https";
+	Cookie::build(AUTH_COOKIE_NAME, token)
+		.path(format!("{}/api", url_base.0))
+		.http_only(true)
+		.same_site(SameSite::Strict)
+		.secure(secure)
+		.finish()
+}
+
+// Username set by a trusted authenticating reverse proxy, if any
+fn get_proxy_user(request: &HttpRequest) -> Option<String> {
+	let proxy_auth_manager = request.app_data::<Data<proxy_auth::Manager>>()?;
+	let header = proxy_auth_manager.header()?;
+	let peer_address = request.peer_addr()?.ip();
+	if !proxy_auth_manager.is_trusted_proxy(peer_address) {
+		return None;
 	}
+	let username = request.headers().get(header)?.to_str().ok()?.trim();
+	(!username.is_empty()).then(|| username.to_owned())
+}
+
+// Address of the client making a request. Forwarding headers are only honored when the request
+// comes from a trusted proxy.
+pub(super) fn get_client_address(request: &HttpRequest) -> Option<IpAddr> {
+	let peer_address = request.peer_addr()?.ip();
+	let Some(proxy_auth_manager) = request.app_data::<Data<proxy_auth::Manager>>() else {
+		return Some(peer_address);
+	};
+	let forwarded_for = request
+		.headers()
+		.get_all("x-forwarded-for")
+		.filter_map(|v| v.to_str().ok())
+		.collect::<Vec<_>>()
+		.join(",");
+	Some(proxy_auth_manager.get_client_address(peer_address, &forwarded_for))
 }
 
 #[derive(Debug)]
@@ -133,11 +352,20 @@ impl FromRequest for Auth {
 			None => return Box::pin(err(ErrorInternalServerError(APIError::Internal))),
 		};
 
+		let proxy_user = get_proxy_user(request);
 		let bearer_auth_future = BearerAuth::from_request(request, payload);
 		let query_params_future =
 			web::Query::<dto::AuthQueryParameters>::from_request(request, payload);
+		let auth_cookie = request.cookie(AUTH_COOKIE_NAME);
 
 		Box::pin(async move {
+			// Auth via header set by a trusted reverse proxy
+			if let Some(username) = proxy_user {
+				let username =
+					block(move || user_manager.provision(&username).map(|_| username)).await?;
+				return Ok(Auth { username });
+			}
+
 			// Auth via bearer token in query parameter
 			if let Ok(query) = query_params_future.await {
 				let auth_token = user::AuthToken(query.auth_token.clone());
@@ -162,6 +390,18 @@ impl FromRequest for Auth {
 				});
 			}
 
+			// Auth via bearer token in cookie
+			if let Some(cookie) = auth_cookie {
+				let auth_token = user::AuthToken(cookie.value().to_owned());
+				let authorization = block(move || {
+					user_manager.authenticate(&auth_token, user::AuthorizationScope::PolarisAuth)
+				})
+				.await?;
+				return Ok(Auth {
+					username: authorization.username,
+				});
+			}
+
 			Err(ErrorUnauthorized(APIError::AuthenticationRequired))
 		})
 	}
@@ -205,13 +445,68 @@ impl FromRequest for AdminRights {
 	}
 }
 
-struct MediaFile {
-	named_file: NamedFile,
+pub(super) struct MediaFile {
+	content: MediaContent,
+	validators: Option<(EntityTag, SystemTime)>,
+	bandwidth: Option<bandwidth::Stream>,
+	session: Option<session::Request>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum MediaContent {
+	File(NamedFile),
+	// Transcodes which are still being written, sent without a length or byte ranges
+	Live(transcode::LiveOutput, &'static str),
+	Memory(Vec<u8>, &'static str),
 }
 
 impl MediaFile {
-	fn new(named_file: NamedFile) -> Self {
-		Self { named_file }
+	pub(super) fn new(named_file: NamedFile) -> Self {
+		Self {
+			content: MediaContent::File(named_file),
+			validators: None,
+			bandwidth: None,
+			session: None,
+		}
+	}
+
+	// For files whose metadata does not reflect their content, like cached thumbnails
+	fn with_validators(named_file: NamedFile, etag: EntityTag, last_modified: SystemTime) -> Self {
+		Self {
+			validators: Some((etag, last_modified)),
+			..Self::new(named_file)
+		}
+	}
+
+	pub(super) fn from_transcode(
+		output: transcode::Output,
+		mime_type: &'static str,
+	) -> Result<Self, APIError> {
+		let content = match output {
+			transcode::Output::File(path) => {
+				let named_file = NamedFile::open(path).map_err(|_| APIError::AudioFileIOError)?;
+				MediaContent::File(named_file.set_content_type(mime_type.parse().unwrap()))
+			}
+			transcode::Output::Live(output) => MediaContent::Live(output, mime_type),
+			transcode::Output::Memory(content) => MediaContent::Memory(content, mime_type),
+		};
+		Ok(Self {
+			content,
+			validators: None,
+			bandwidth: None,
+			session: None,
+		})
+	}
+
+	fn throttled(self, bandwidth: Option<bandwidth::Stream>) -> Self {
+		Self { bandwidth, ..self }
+	}
+
+	fn in_session(self, session: session::Request) -> Self {
+		Self {
+			session: Some(session),
+			..self
+		}
 	}
 }
 
@@ -222,13 +517,190 @@ impl Responder for MediaFile {
 		// Intentionally turn off content encoding for media files because:
 		// 1. There is little value in compressing files that are already compressed (mp3, jpg, etc.)
 		// 2. The Content-Length header is incompatible with content encoding (other than identity), and can be valuable for clients
-		self.named_file
-			.set_content_encoding(ContentEncoding::Identity)
-			.into_response(req)
+		let named_file = match self.content {
+			MediaContent::File(named_file) => named_file,
+			MediaContent::Live(output, mime_type) => {
+				let response = HttpResponse::Ok()
+					.content_type(mime_type)
+					.insert_header(ContentEncoding::Identity)
+					.streaming(read_in_chunks(output, || APIError::AudioFileIOError));
+				return stream_body(response, self.bandwidth, self.session);
+			}
+			MediaContent::Memory(content, mime_type) => {
+				let response = HttpResponse::Ok()
+					.content_type(mime_type)
+					.insert_header(ContentEncoding::Identity)
+					.body(content);
+				return stream_body(response, self.bandwidth, self.session);
+			}
+		};
+		let named_file = named_file.set_content_encoding(ContentEncoding::Identity);
+
+		let Some((etag, last_modified)) = self.validators else {
+			let response = named_file.into_response(req);
+			return stream_body(response, self.bandwidth, self.session);
+		};
+
+		let last_modified = HttpDate::from(last_modified);
+		let not_modified = match req.get_header::<IfNoneMatch>() {
+			Some(IfNoneMatch::Any) => true,
+			Some(IfNoneMatch::Items(items)) => items.iter().any(|i| i.weak_eq(&etag)),
+			None => match req.get_header::<IfModifiedSince>() {
+				Some(IfModifiedSince(since)) => last_modified <= since,
+				None => false,
+			},
+		};
+
+		let mut response = if not_modified {
+			HttpResponse::NotModified().finish()
+		} else {
+			named_file
+				.use_etag(false)
+				.use_last_modified(false)
+				.into_response(req)
+		};
+		let headers = response.headers_mut();
+		headers.insert(header::ETAG, etag.to_string().parse().unwrap());
+		headers.insert(
+			header::LAST_MODIFIED,
+			LastModified(last_modified).to_string().parse().unwrap(),
+		);
+		stream_body(response, self.bandwidth, self.session)
+	}
+}
+
+// Response body which holds chunks back to stay within the bandwidth limits, and which is cut
+// off when its streaming session is terminated
+struct StreamBody {
+	body: BoxBody,
+	bandwidth: Option<bandwidth::Stream>,
+	session: Option<session::Request>,
+	pending: Option<(Pin<Box<actix_web::rt::time::Sleep>>, web::Bytes)>,
+}
+
+impl MessageBody for StreamBody {
+	type Error = Box<dyn std::error::Error>;
+
+	fn size(&self) -> BodySize {
+		self.body.size()
+	}
+
+	fn poll_next(
+		mut self: Pin<&mut Self>,
+		cx: &mut task::Context<'_>,
+	) -> Poll<Option<Result<web::Bytes, Self::Error>>> {
+		if self.session.as_ref().is_some_and(|s| s.is_terminated()) {
+			return Poll::Ready(Some(Err("Streaming session was terminated".into())));
+		}
+		if self.pending.is_none() {
+			let bytes = match ready!(Pin::new(&mut self.body).poll_next(cx)) {
+				Some(Ok(bytes)) => bytes,
+				other => return Poll::Ready(other),
+			};
+			let wait = match &self.bandwidth {
+				Some(bandwidth) => bandwidth.reserve(bytes.len()),
+				None => Duration::ZERO,
+			};
+			if wait.is_zero() {
+				return Poll::Ready(Some(Ok(bytes)));
+			}
+			self.pending = Some((Box::pin(actix_web::rt::time::sleep(wait)), bytes));
+		}
+		if let Some((delay, _)) = self.pending.as_mut() {
+			ready!(delay.as_mut().poll(cx));
+		}
+		Poll::Ready(self.pending.take().map(|(_, bytes)| Ok(bytes)))
+	}
+}
+
+fn stream_body(
+	response: HttpResponse,
+	bandwidth: Option<bandwidth::Stream>,
+	session: Option<session::Request>,
+) -> HttpResponse {
+	if bandwidth.is_none() && session.is_none() {
+		return response;
+	}
+	response.map_body(|_, body| {
+		BoxBody::new(StreamBody {
+			body,
+			bandwidth,
+			session,
+			pending: None,
+		})
+	})
+}
+
+// JSON response tagged with a hash of its content, so clients polling the collection can
+// revalidate it with `If-None-Match` and get an empty 304 when nothing changed
+struct ConditionalJson<T>(T);
+
+impl<T: serde::Serialize> Responder for ConditionalJson<T> {
+	type Body = BoxBody;
+
+	fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+		let body = match serde_json::to_vec(&self.0) {
+			Ok(body) => body,
+			Err(e) => return HttpResponse::from_error(ErrorInternalServerError(e)),
+		};
+
+		let mut hasher = DefaultHasher::new();
+		body.hash(&mut hasher);
+		// Weak because compression middleware may change the representation
+		let etag = EntityTag::new_weak(format!("{:016x}", hasher.finish()));
+
+		let not_modified = match req.get_header::<IfNoneMatch>() {
+			Some(IfNoneMatch::Any) => true,
+			Some(IfNoneMatch::Items(items)) => items.iter().any(|i| i.weak_eq(&etag)),
+			None => false,
+		};
+		if not_modified {
+			return HttpResponse::NotModified()
+				.insert_header(ETag(etag))
+				.finish();
+		}
+
+		HttpResponse::Ok()
+			.content_type(ContentType::json())
+			.insert_header(ETag(etag))
+			.body(body)
+	}
+}
+
+// Page of a longer listing, whose full length is sent in the `X-Total-Count` header
+struct Paginated<R>(R, usize);
+
+impl<R: Responder> Responder for Paginated<R> {
+	type Body = R::Body;
+
+	fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
+		let mut response = self.0.respond_to(req);
+		response
+			.headers_mut()
+			.insert(HeaderName::from_static(TOTAL_COUNT_HEADER), self.1.into());
+		response
 	}
 }
 
-async fn block<F, I, E>(f: F) -> Result<I, APIError>
+// Page of a listing as sent to a user, with their favorites and ratings
+fn paginate_for_user<T, D>(
+	favorite_manager: &favorite::Manager,
+	rating_manager: &rating::Manager,
+	username: &str,
+	items: Vec<T>,
+	total: usize,
+	to_dto: fn(T, &HashSet<String>, &HashMap<String, u8>) -> D,
+) -> Result<Paginated<Vec<D>>, APIError> {
+	let favorites = favorite_manager.get_favorite_paths(username)?;
+	let ratings = rating_manager.get_ratings(username)?;
+	let items = items
+		.into_iter()
+		.map(|i| to_dto(i, &favorites, &ratings))
+		.collect();
+	Ok(Paginated(items, total))
+}
+
+pub(super) async fn block<F, I, E>(f: F) -> Result<I, APIError>
 where
 	F: FnOnce() -> Result<I, E> + Send + 'static,
 	I: Send + 'static,
@@ -249,6 +721,31 @@ async fn version() -> Json<dto::Version> {
 	Json(current_version)
 }
 
+// Unauthenticated so it can be used by liveness and readiness probes, which is why it does
+// not tell which mounts are missing
+#[get("/health")]
+async fn health(vfs_manager: Data<vfs::Manager>) -> Result<HttpResponse, APIError> {
+	let health = block(move || -> Result<dto::Health, APIError> {
+		let (database, mounts_available) = match vfs_manager.get_vfs() {
+			Ok(vfs) => (true, vfs.mounts().iter().all(|m| m.source.is_dir())),
+			Err(_) => (false, false),
+		};
+		Ok(dto::Health {
+			healthy: database && mounts_available,
+			database,
+			mounts_available,
+		})
+	})
+	.await?;
+
+	let status = if health.healthy {
+		StatusCode::OK
+	} else {
+		StatusCode::SERVICE_UNAVAILABLE
+	};
+	Ok(HttpResponse::build(status).json(health))
+}
+
 #[get("/initial_setup")]
 async fn initial_setup(
 	user_manager: Data<user::Manager>,
@@ -395,6 +892,32 @@ async fn delete_user(
 	Ok(HttpResponse::new(StatusCode::OK))
 }
 
+#[post("/user/{name}/password")]
+async fn change_password(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+	password_change: Json<dto::PasswordChange>,
+) -> Result<HttpResponse, APIError> {
+	block(move || -> Result<(), APIError> {
+		if auth.username == name.as_str() {
+			let old_password = password_change.old_password.as_deref().unwrap_or_default();
+			user_manager.check_password(&name, old_password)?;
+		} else {
+			if !user_manager.is_admin(&auth.username)? {
+				return Err(APIError::AdminPermissionRequired);
+			}
+			if !user_manager.exists(&name)? {
+				return Err(APIError::UserNotFound);
+			}
+		}
+		user_manager.set_password(&name, &password_change.new_password)?;
+		Ok(())
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
 #[get("/preferences")]
 async fn get_preferences(
 	user_manager: Data<user::Manager>,
@@ -414,6 +937,71 @@ async fn put_preferences(
 	Ok(HttpResponse::new(StatusCode::OK))
 }
 
+#[get("/clients")]
+async fn list_clients(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<user::Client>>, APIError> {
+	let clients = block(move || user_manager.list_clients(&auth.username)).await?;
+	Ok(Json(clients))
+}
+
+#[put("/client/{name}")]
+async fn put_client(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+	transcoding: Json<transcode::Profile>,
+) -> Result<HttpResponse, APIError> {
+	let client = user::Client {
+		name: name.into_inner(),
+		transcoding: transcoding.into_inner(),
+	};
+	block(move || user_manager.save_client(&auth.username, &client)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[delete("/client/{name}")]
+async fn delete_client(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	block(move || user_manager.delete_client(&auth.username, &name)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/api_keys")]
+async fn list_api_keys(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<user::ApiKey>>, APIError> {
+	let api_keys = block(move || user_manager.list_api_keys(&auth.username)).await?;
+	Ok(Json(api_keys))
+}
+
+#[post("/api_key/{name}")]
+async fn create_api_key(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+) -> Result<Json<dto::CreatedApiKey>, APIError> {
+	let name = name.into_inner();
+	let key_name = name.clone();
+	let key = block(move || user_manager.create_api_key(&auth.username, &key_name)).await?;
+	Ok(Json(dto::CreatedApiKey { name, key }))
+}
+
+#[delete("/api_key/{name}")]
+async fn delete_api_key(
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	block(move || user_manager.delete_api_key(&auth.username, &name)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
 #[post("/trigger_index")]
 async fn trigger_index(
 	index: Data<Index>,
@@ -423,144 +1011,1236 @@ async fn trigger_index(
 	Ok(HttpResponse::new(StatusCode::OK))
 }
 
+#[get("/index/status")]
+async fn get_index_status(index: Data<Index>, _auth: Auth) -> Json<dto::IndexStatus> {
+	Json(index.get_status().into())
+}
+
+// Server-sent events, with comments in between so idle connections are not closed by proxies
+#[get("/events")]
+async fn get_events(events_manager: Data<events::Manager>, auth: Auth) -> HttpResponse {
+	let receiver = events_manager.subscribe(&auth.username);
+	let messages = stream::unfold(receiver, |mut receiver| async move {
+		let message =
+			match actix_web::rt::time::timeout(EVENTS_KEEP_ALIVE_INTERVAL, receiver.next()).await {
+				Ok(Some(event)) => {
+					let event = dto::Event::from(event);
+					format!("data: {}\n\n", serde_json::to_string(&event).ok()?)
+				}
+				Ok(None) => return None,
+				Err(_) => ":\n\n".to_owned(),
+			};
+		Some((Ok::<_, APIError>(web::Bytes::from(message)), receiver))
+	});
+	HttpResponse::Ok()
+		.content_type("text/event-stream")
+		.insert_header((header::CACHE_CONTROL, "no-cache"))
+		.streaming(messages)
+}
+
+#[get("/duplicates")]
+async fn get_duplicates(
+	duplicate_manager: Data<duplicate::Manager>,
+	_admin_rights: AdminRights,
+) -> Result<Json<Vec<Vec<dto::Duplicate>>>, APIError> {
+	let duplicates = block(move || duplicate_manager.find_duplicates()).await?;
+	Ok(Json(
+		duplicates
+			.into_iter()
+			.map(|group| group.into_iter().map(|d| d.into()).collect())
+			.collect(),
+	))
+}
+
+#[get("/missing_songs")]
+async fn get_missing_songs(
+	index: Data<Index>,
+	_admin_rights: AdminRights,
+) -> Result<Json<Vec<dto::MissingSong>>, APIError> {
+	let songs = block(move || index.get_missing_songs()).await?;
+	Ok(Json(songs.into_iter().map(|s| s.into()).collect()))
+}
+
+#[delete("/missing_songs")]
+async fn purge_missing_songs(
+	index: Data<Index>,
+	_admin_rights: AdminRights,
+) -> Result<HttpResponse, APIError> {
+	block(move || index.purge_missing_songs()).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/sessions")]
+async fn list_sessions(
+	session_manager: Data<session::Manager>,
+	_admin_rights: AdminRights,
+) -> Json<Vec<dto::StreamSession>> {
+	let sessions = session_manager.list_sessions();
+	Json(sessions.into_iter().map(|s| s.into()).collect())
+}
+
+#[delete("/sessions/{id}")]
+async fn terminate_session(
+	session_manager: Data<session::Manager>,
+	_admin_rights: AdminRights,
+	id: web::Path<u64>,
+) -> Result<HttpResponse, APIError> {
+	session_manager.terminate(id.into_inner())?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/backup")]
+async fn get_backup(db: Data<DB>, _admin_rights: AdminRights) -> Result<HttpResponse, APIError> {
+	let snapshot = block(move || db.snapshot()).await?;
+	Ok(HttpResponse::Ok()
+		.content_type("application/vnd.sqlite3")
+		.insert_header(ContentDisposition::attachment("polaris.sqlite"))
+		.body(snapshot))
+}
+
 #[post("/auth")]
 async fn login(
 	user_manager: Data<user::Manager>,
+	throttle_manager: Data<throttle::Manager>,
+	url_base: Data<UrlBase>,
+	request: HttpRequest,
 	credentials: Json<dto::Credentials>,
 ) -> Result<HttpResponse, APIError> {
-	let username = credentials.username.clone();
-	let (user::AuthToken(token), is_admin) =
-		block(move || -> Result<(user::AuthToken, bool), APIError> {
-			let auth_token = user_manager.login(&credentials.username, &credentials.password)?;
-			let is_admin = user_manager.is_admin(&credentials.username)?;
+	// Users authenticated by a trusted reverse proxy get a session without a password check
+	if let Some(username) = get_proxy_user(&request) {
+		let proxy_username = username.clone();
+		let (user::AuthToken(token), is_admin) = block(move || -> Result<_, APIError> {
+			let auth_token = user_manager.login_trusted(&proxy_username)?;
+			let is_admin = user_manager.is_admin(&proxy_username)?;
 			Ok((auth_token, is_admin))
 		})
 		.await?;
+		return Ok(make_login_response(
+			username, token, is_admin, &url_base, &request,
+		));
+	}
+
+	let username = credentials.username.clone();
+	let address = get_client_address(&request)
+		.map(|a| a.to_string())
+		.unwrap_or_default();
+	throttle_manager.begin_attempt(&address, &username)?;
+
+	let login_result = block(move || -> Result<(user::AuthToken, bool), APIError> {
+		let auth_token = user_manager.login(&credentials.username, &credentials.password)?;
+		let is_admin = user_manager.is_admin(&credentials.username)?;
+		Ok((auth_token, is_admin))
+	})
+	.await;
+	match login_result {
+		Ok(_) => throttle_manager.record_success(&address, &username),
+		Err(APIError::IncorrectCredentials) => throttle_manager.record_failure(&address, &username),
+		Err(_) => (),
+	};
+	let (user::AuthToken(token), is_admin) = login_result?;
+	Ok(make_login_response(
+		username, token, is_admin, &url_base, &request,
+	))
+}
+
+fn make_login_response(
+	username: String,
+	token: String,
+	is_admin: bool,
+	url_base: &UrlBase,
+	request: &HttpRequest,
+) -> HttpResponse {
 	let authorization = dto::Authorization {
-		username: username.clone(),
-		token,
+		username,
+		token: token.clone(),
 		is_admin,
 	};
-	let response = HttpResponse::Ok().json(authorization);
-	Ok(response)
+	HttpResponse::Ok()
+		.cookie(make_auth_cookie(token, url_base, request))
+		.json(authorization)
+}
+
+#[delete("/auth")]
+async fn logout(url_base: Data<UrlBase>, request: HttpRequest) -> HttpResponse {
+	let mut cookie = make_auth_cookie(String::new(), &url_base, &request);
+	cookie.make_removal();
+	HttpResponse::Ok().cookie(cookie).finish()
 }
 
 #[get("/browse")]
 async fn browse_root(
 	index: Data<Index>,
-	_auth: Auth,
-) -> Result<Json<Vec<index::CollectionFile>>, APIError> {
-	let result = block(move || index.browse(Path::new(""))).await?;
-	Ok(Json(result))
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<ConditionalJson<Vec<dto::CollectionFile>>>, APIError> {
+	let Paginated(files, total) = block(move || {
+		let files = index.browse(&auth.username, Path::new(""))?;
+		let total = files.len();
+		let files = index::Page::from(page.into_inner()).apply(files);
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			files,
+			total,
+			dto::CollectionFile::new,
+		)
+	})
+	.await?;
+	Ok(Paginated(ConditionalJson(files), total))
 }
 
 #[get("/browse/{path:.*}")]
 async fn browse(
 	index: Data<Index>,
-	_auth: Auth,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
 	path: web::Path<String>,
-) -> Result<Json<Vec<index::CollectionFile>>, APIError> {
-	let result = block(move || {
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<ConditionalJson<Vec<dto::CollectionFile>>>, APIError> {
+	let Paginated(files, total) = block(move || {
 		let path = percent_decode_str(&path).decode_utf8_lossy();
-		index.browse(Path::new(path.as_ref()))
+		let files = index.browse(&auth.username, Path::new(path.as_ref()))?;
+		let total = files.len();
+		let files = index::Page::from(page.into_inner()).apply(files);
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			files,
+			total,
+			dto::CollectionFile::new,
+		)
 	})
 	.await?;
-	Ok(Json(result))
+	Ok(Paginated(ConditionalJson(files), total))
+}
+
+#[get("/views")]
+async fn browse_view_root(
+	index: Data<Index>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<ConditionalJson<Vec<dto::CollectionFile>>>, APIError> {
+	let Paginated(files, total) = block(move || {
+		let files = index.browse_view(&auth.username, "")?;
+		let total = files.len();
+		let files = index::Page::from(page.into_inner()).apply(files);
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			files,
+			total,
+			dto::CollectionFile::new,
+		)
+	})
+	.await?;
+	Ok(Paginated(ConditionalJson(files), total))
+}
+
+#[get("/views/{path:.*}")]
+async fn browse_view(
+	index: Data<Index>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<ConditionalJson<Vec<dto::CollectionFile>>>, APIError> {
+	let Paginated(files, total) = block(move || {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let files = index.browse_view(&auth.username, path.as_ref())?;
+		let total = files.len();
+		let files = index::Page::from(page.into_inner()).apply(files);
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			files,
+			total,
+			dto::CollectionFile::new,
+		)
+	})
+	.await?;
+	Ok(Paginated(ConditionalJson(files), total))
 }
 
 #[get("/flatten")]
-async fn flatten_root(index: Data<Index>, _auth: Auth) -> Result<Json<Vec<index::Song>>, APIError> {
-	let songs = block(move || index.flatten(Path::new(""))).await?;
-	Ok(Json(songs))
+async fn flatten_root(
+	index: Data<Index>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<ConditionalJson<Vec<dto::Song>>>, APIError> {
+	let Paginated(songs, total) = block(move || {
+		let (songs, total) =
+			index.flatten(&auth.username, Path::new(""), page.into_inner().into())?;
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			songs,
+			total,
+			dto::Song::new,
+		)
+	})
+	.await?;
+	Ok(Paginated(ConditionalJson(songs), total))
 }
 
 #[get("/flatten/{path:.*}")]
 async fn flatten(
 	index: Data<Index>,
-	_auth: Auth,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
 	path: web::Path<String>,
-) -> Result<Json<Vec<index::Song>>, APIError> {
-	let songs = block(move || {
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<ConditionalJson<Vec<dto::Song>>>, APIError> {
+	let Paginated(songs, total) = block(move || {
 		let path = percent_decode_str(&path).decode_utf8_lossy();
-		index.flatten(Path::new(path.as_ref()))
+		let (songs, total) = index.flatten(
+			&auth.username,
+			Path::new(path.as_ref()),
+			page.into_inner().into(),
+		)?;
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			songs,
+			total,
+			dto::Song::new,
+		)
 	})
 	.await?;
-	Ok(Json(songs))
+	Ok(Paginated(ConditionalJson(songs), total))
 }
 
 #[get("/random")]
-async fn random(index: Data<Index>, _auth: Auth) -> Result<Json<Vec<index::Directory>>, APIError> {
-	let result = block(move || index.get_random_albums(20)).await?;
+async fn random(index: Data<Index>, auth: Auth) -> Result<Json<Vec<index::Directory>>, APIError> {
+	let result = block(move || index.get_random_albums(&auth.username, 20)).await?;
 	Ok(Json(result))
 }
 
 #[get("/recent")]
-async fn recent(index: Data<Index>, _auth: Auth) -> Result<Json<Vec<index::Directory>>, APIError> {
-	let result = block(move || index.get_recent_albums(20)).await?;
-	Ok(Json(result))
-}
-
-#[get("/search")]
-async fn search_root(
-	index: Data<Index>,
-	_auth: Auth,
-) -> Result<Json<Vec<index::CollectionFile>>, APIError> {
-	let result = block(move || index.search("")).await?;
-	Ok(Json(result))
-}
-
-#[get("/search/{query:.*}")]
-async fn search(
+async fn recent(
 	index: Data<Index>,
-	_auth: Auth,
-	query: web::Path<String>,
-) -> Result<Json<Vec<index::CollectionFile>>, APIError> {
-	let result = block(move || index.search(&query)).await?;
-	Ok(Json(result))
+	auth: Auth,
+) -> Result<ConditionalJson<Vec<index::Directory>>, APIError> {
+	let result = block(move || index.get_recent_albums(&auth.username, 20)).await?;
+	Ok(ConditionalJson(result))
 }
 
-#[get("/audio/{path:.*}")]
-async fn get_audio(
-	vfs_manager: Data<vfs::Manager>,
-	_auth: Auth,
+#[put("/ratings/{path:.*}")]
+async fn set_rating(
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
 	path: web::Path<String>,
-) -> Result<MediaFile, APIError> {
-	let audio_path = block(move || {
-		let vfs = vfs_manager.get_vfs()?;
+	rating: Json<dto::Rating>,
+) -> Result<HttpResponse, APIError> {
+	block(move || {
 		let path = percent_decode_str(&path).decode_utf8_lossy();
-		vfs.virtual_to_real(Path::new(path.as_ref()))
+		rating_manager.set_rating(&auth.username, Path::new(path.as_ref()), rating.rating)
 	})
 	.await?;
-
-	let named_file = NamedFile::open(audio_path).map_err(|_| APIError::AudioFileIOError)?;
-	Ok(MediaFile::new(named_file))
+	Ok(HttpResponse::new(StatusCode::OK))
 }
 
-#[get("/thumbnail/{path:.*}")]
-async fn get_thumbnail(
-	vfs_manager: Data<vfs::Manager>,
-	thumbnails_manager: Data<thumbnail::Manager>,
-	_auth: Auth,
+#[delete("/ratings/{path:.*}")]
+async fn clear_rating(
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
 	path: web::Path<String>,
-	options_input: web::Query<dto::ThumbnailOptions>,
-) -> Result<MediaFile, APIError> {
-	let options = thumbnail::Options::from(options_input.0);
-
-	let thumbnail_path = block(move || -> Result<PathBuf, APIError> {
-		let vfs = vfs_manager.get_vfs()?;
+) -> Result<HttpResponse, APIError> {
+	block(move || {
 		let path = percent_decode_str(&path).decode_utf8_lossy();
-		let image_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
-		thumbnails_manager
-			.get_thumbnail(&image_path, &options)
-			.map_err(|e| e.into())
+		rating_manager.clear_rating(&auth.username, Path::new(path.as_ref()))
 	})
 	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/listen/{path:.*}")]
+async fn record_listen(
+	history_manager: Data<history::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+	options: web::Query<dto::ListenOptions>,
+) -> Result<HttpResponse, APIError> {
+	block(move || {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		history_manager.record_listen(&auth.username, Path::new(path.as_ref()), options.completion)
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/recently_played")]
+async fn recently_played(
+	history_manager: Data<history::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<index::Song>>, APIError> {
+	let result = block(move || history_manager.get_recently_played(&auth.username, 50)).await?;
+	Ok(Json(result))
+}
+
+#[get("/most_played")]
+async fn most_played(
+	history_manager: Data<history::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<history::PlayCount>>, APIError> {
+	let result = block(move || history_manager.get_most_played(&auth.username, 50)).await?;
+	Ok(Json(result))
+}
+
+#[get("/search")]
+async fn search_root(
+	index: Data<Index>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<Json<Vec<dto::CollectionFile>>>, APIError> {
+	let Paginated(files, total) = block(move || {
+		let files = index.search(&auth.username, "")?;
+		let total = files.len();
+		let files = index::Page::from(page.into_inner()).apply(files);
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			files,
+			total,
+			dto::CollectionFile::new,
+		)
+	})
+	.await?;
+	Ok(Paginated(Json(files), total))
+}
+
+// Registered before `/search/{query}`, which would otherwise match it
+#[get("/search/suggest")]
+async fn search_suggestions(
+	index: Data<Index>,
+	auth: Auth,
+	options: web::Query<dto::SuggestionOptions>,
+) -> Result<Json<Vec<dto::Suggestion>>, APIError> {
+	let options = options.into_inner();
+	let suggestions =
+		block(move || index.suggest(&auth.username, &options.prefix, options.limit)).await?;
+	Ok(Json(suggestions.into_iter().map(|s| s.into()).collect()))
+}
+
+#[get("/search/{query:.*}")]
+async fn search(
+	index: Data<Index>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	query: web::Path<String>,
+	page: web::Query<dto::PageOptions>,
+) -> Result<Paginated<Json<Vec<dto::CollectionFile>>>, APIError> {
+	let Paginated(files, total) = block(move || {
+		let files = index.search(&auth.username, &query)?;
+		let total = files.len();
+		let files = index::Page::from(page.into_inner()).apply(files);
+		paginate_for_user(
+			&favorite_manager,
+			&rating_manager,
+			&auth.username,
+			files,
+			total,
+			dto::CollectionFile::new,
+		)
+	})
+	.await?;
+	Ok(Paginated(Json(files), total))
+}
+
+#[get("/favorites")]
+async fn list_favorites(
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<dto::CollectionFile>>, APIError> {
+	let result = block(move || -> Result<_, APIError> {
+		let files = favorite_manager.list_favorites(&auth.username)?;
+		let favorites = favorite_manager.get_favorite_paths(&auth.username)?;
+		let ratings = rating_manager.get_ratings(&auth.username)?;
+		Ok(files
+			.into_iter()
+			.map(|f| dto::CollectionFile::new(f, &favorites, &ratings))
+			.collect())
+	})
+	.await?;
+	Ok(Json(result))
+}
+
+#[put("/favorites/{path:.*}")]
+async fn add_favorite(
+	favorite_manager: Data<favorite::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	block(move || {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		favorite_manager.add_favorite(&auth.username, Path::new(path.as_ref()))
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[delete("/favorites/{path:.*}")]
+async fn remove_favorite(
+	favorite_manager: Data<favorite::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	block(move || {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		favorite_manager.remove_favorite(&auth.username, Path::new(path.as_ref()))
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/queue")]
+async fn get_queue(
+	queue_manager: Data<queue::Manager>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+) -> Result<Json<dto::PlayQueue>, APIError> {
+	let result = block(move || -> Result<_, APIError> {
+		let queue = queue_manager.get_queue(&auth.username)?;
+		let favorites = favorite_manager.get_favorite_paths(&auth.username)?;
+		let ratings = rating_manager.get_ratings(&auth.username)?;
+		Ok(dto::PlayQueue::new(queue, &favorites, &ratings))
+	})
+	.await?;
+	Ok(Json(result))
+}
+
+#[get("/playback")]
+async fn get_playback(
+	queue_manager: Data<queue::Manager>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+) -> Result<Json<dto::Playback>, APIError> {
+	let result = block(move || -> Result<_, APIError> {
+		let playback = queue_manager.get_playback(&auth.username)?;
+		let favorites = favorite_manager.get_favorite_paths(&auth.username)?;
+		let ratings = rating_manager.get_ratings(&auth.username)?;
+		Ok(dto::Playback::new(playback, &favorites, &ratings))
+	})
+	.await?;
+	Ok(Json(result))
+}
+
+#[get("/changes")]
+async fn get_changes(
+	sync_manager: Data<sync::Manager>,
+	favorite_manager: Data<favorite::Manager>,
+	rating_manager: Data<rating::Manager>,
+	auth: Auth,
+	options: web::Query<dto::ChangesOptions>,
+) -> Result<Json<dto::Changes>, APIError> {
+	let result = block(move || -> Result<_, APIError> {
+		let changes = sync_manager.get_changes(&auth.username, options.since.as_deref())?;
+		let favorites = favorite_manager.get_favorite_paths(&auth.username)?;
+		let ratings = rating_manager.get_ratings(&auth.username)?;
+		Ok(dto::Changes::new(changes, &favorites, &ratings))
+	})
+	.await?;
+	Ok(Json(result))
+}
+
+#[put("/playback")]
+async fn report_playback(
+	queue_manager: Data<queue::Manager>,
+	audiobook_manager: Data<audiobook::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	input: Json<dto::PlaybackInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || -> Result<_, APIError> {
+		let report = input.into_inner().into();
+		queue_manager.report_playback(&auth.username, &report)?;
+		audiobook_manager.record_queue_position(&auth.username)?;
+		Ok(())
+	})
+	.await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[delete("/queue")]
+async fn clear_queue(
+	queue_manager: Data<queue::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || queue_manager.clear(&auth.username)).await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
 
-	let named_file = NamedFile::open(thumbnail_path).map_err(|_| APIError::ThumbnailFileIOError)?;
+#[post("/queue/tracks")]
+async fn append_to_queue(
+	queue_manager: Data<queue::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	input: Json<dto::QueueTracksInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || {
+		let tracks = input.tracks.iter().map(PathBuf::from).collect::<Vec<_>>();
+		queue_manager.append(&auth.username, &tracks)
+	})
+	.await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/queue/next")]
+async fn insert_next_in_queue(
+	queue_manager: Data<queue::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	input: Json<dto::QueueTracksInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || {
+		let tracks = input.tracks.iter().map(PathBuf::from).collect::<Vec<_>>();
+		queue_manager.insert_next(&auth.username, &tracks)
+	})
+	.await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/queue/move")]
+async fn move_queue_track(
+	queue_manager: Data<queue::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	input: Json<dto::MoveQueueTrackInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || queue_manager.move_track(&auth.username, input.from, input.to)).await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[delete("/queue/tracks/{index}")]
+async fn remove_from_queue(
+	queue_manager: Data<queue::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	index: web::Path<usize>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	let index = index.into_inner();
+	block(move || queue_manager.remove(&auth.username, index)).await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[put("/queue/current")]
+async fn set_queue_current_track(
+	queue_manager: Data<queue::Manager>,
+	audiobook_manager: Data<audiobook::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	input: Json<dto::QueueCurrentTrackInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || -> Result<_, APIError> {
+		queue_manager.set_current_track(
+			&auth.username,
+			input.current_track,
+			input.position_seconds,
+		)?;
+		audiobook_manager.record_queue_position(&auth.username)?;
+		Ok(())
+	})
+	.await?;
+	events_manager.publish(events::Event::QueueChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
 
+#[get("/jukebox")]
+async fn get_jukebox(
+	jukebox_manager: Data<jukebox::Manager>,
+	_auth: Auth,
+) -> Result<Json<dto::JukeboxStatus>, APIError> {
+	let status = jukebox_manager.get_status()?;
+	Ok(Json(status.into()))
+}
+
+#[post("/jukebox/play")]
+async fn jukebox_play(
+	jukebox_manager: Data<jukebox::Manager>,
+	auth: Auth,
+) -> Result<HttpResponse, APIError> {
+	block(move || jukebox_manager.play(&auth.username)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/jukebox/pause")]
+async fn jukebox_pause(
+	jukebox_manager: Data<jukebox::Manager>,
+	_auth: Auth,
+) -> Result<HttpResponse, APIError> {
+	block(move || jukebox_manager.pause()).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/jukebox/next")]
+async fn jukebox_next(
+	jukebox_manager: Data<jukebox::Manager>,
+	auth: Auth,
+) -> Result<HttpResponse, APIError> {
+	block(move || jukebox_manager.next(&auth.username)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/jukebox/previous")]
+async fn jukebox_previous(
+	jukebox_manager: Data<jukebox::Manager>,
+	auth: Auth,
+) -> Result<HttpResponse, APIError> {
+	block(move || jukebox_manager.previous(&auth.username)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[put("/jukebox/volume")]
+async fn set_jukebox_volume(
+	jukebox_manager: Data<jukebox::Manager>,
+	_auth: Auth,
+	input: Json<dto::JukeboxVolumeInput>,
+) -> Result<HttpResponse, APIError> {
+	block(move || jukebox_manager.set_volume(input.volume)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[route("/audio/{path:.*}", method = "GET", method = "HEAD")]
+#[allow(clippy::too_many_arguments)]
+async fn get_audio(
+	index: Data<Index>,
+	bandwidth_manager: Data<bandwidth::Manager>,
+	vfs_manager: Data<vfs::Manager>,
+	transcode_manager: Data<transcode::Manager>,
+	user_manager: Data<user::Manager>,
+	session_manager: Data<session::Manager>,
+	auth: Auth,
+	request: HttpRequest,
+	path: web::Path<String>,
+	options_input: web::Query<dto::AudioOptions>,
+) -> Result<MediaFile, APIError> {
+	let options_input = options_input.into_inner();
+	let client = get_client_name(&request, options_input.client.as_deref());
+	let (audio_path, cue_segment, options, bandwidth, session) =
+		block(move || -> Result<_, APIError> {
+			let vfs = vfs_manager.get_vfs_for_user(&auth.username)?;
+			let path = percent_decode_str(&path).decode_utf8_lossy();
+			let audio_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+			let cue_segment = match audio_path.exists() {
+				true => None,
+				false => index.get_cue_segment(&audio_path)?,
+			};
+			index.on_stream(&audio_path);
+			let profile = user_manager
+				.get_transcode_profile(&auth.username, options_input.client.as_deref())?;
+			let normalization = options_input
+				.normalization
+				.map(transcode::Normalization::from)
+				.or(profile.normalization);
+			let mut options = if options_input.is_transcode() {
+				Some(transcode::Options::from(options_input))
+			} else {
+				profile.options()
+			};
+			if let (Some(options), Some(normalization)) = (options.as_mut(), normalization) {
+				options.volume = index.get_volume(&audio_path, normalization)?;
+			}
+			let session = session_manager.begin_request(
+				&auth.username,
+				&client,
+				Path::new(path.as_ref()),
+				options.as_ref().map(|o| o.bitrate),
+			)?;
+			let bandwidth = bandwidth_manager.open_stream(Some(&auth.username))?;
+			Ok((audio_path, cue_segment, options, bandwidth, session))
+		})
+		.await?;
+
+	if let Some((source_path, segment)) = cue_segment {
+		let mime_type = options
+			.as_ref()
+			.map_or("audio/flac", |o| o.format.mime_type());
+		let output = block(move || -> Result<_, APIError> {
+			transcode_manager
+				.get_segment(&source_path, &segment, options.as_ref())
+				.map_err(|e| e.into())
+		})
+		.await?;
+		return Ok(MediaFile::from_transcode(output, mime_type)?
+			.throttled(bandwidth)
+			.in_session(session));
+	}
+
+	if let Some(options) = options {
+		let mime_type = options.format.mime_type();
+		let output = block(move || -> Result<_, APIError> {
+			transcode_manager
+				.get_transcode(&audio_path, &options)
+				.map_err(|e| e.into())
+		})
+		.await?;
+		return Ok(MediaFile::from_transcode(output, mime_type)?
+			.throttled(bandwidth)
+			.in_session(session));
+	}
+
+	Ok(MediaFile::new(open_audio_file(&audio_path)?)
+		.throttled(bandwidth)
+		.in_session(session))
+}
+
+// Opens an audio file to serve as is
+fn open_audio_file(audio_path: &Path) -> Result<NamedFile, APIError> {
+	let named_file = NamedFile::open(audio_path).map_err(|_| APIError::AudioFileIOError)?;
+	Ok(match utils::get_audio_format(audio_path) {
+		// Browsers do not recognize the audio/m4a type guessed from file extensions
+		Some(AudioFormat::MP4) | Some(AudioFormat::M4B) => {
+			named_file.set_content_type("audio/mp4".parse().unwrap())
+		}
+		_ => named_file,
+	})
+}
+
+// Name under which streaming sessions list the client a request comes from
+fn get_client_name(request: &HttpRequest, client: Option<&str>) -> String {
+	let user_agent = request
+		.headers()
+		.get(header::USER_AGENT)
+		.and_then(|v| v.to_str().ok());
+	let peer_address = request.peer_addr().map(|a| a.ip().to_string());
+	client
+		.or(user_agent)
+		.map(String::from)
+		.or(peer_address)
+		.unwrap_or_default()
+}
+
+// Source of an HLS stream, which is either a file or the part of a file covered by a cue track
+struct HlsSource {
+	audio_path: PathBuf,
+	segment: Option<transcode::Segment>,
+	options: transcode::Options,
+	duration: Option<u32>, // Milliseconds
+}
+
+fn get_hls_source(
+	index: &Index,
+	vfs_manager: &vfs::Manager,
+	user_manager: &user::Manager,
+	username: &str,
+	path: &str,
+	bitrate: u32,
+	client: Option<&str>,
+) -> Result<HlsSource, APIError> {
+	let vfs = vfs_manager.get_vfs_for_user(username)?;
+	let path = percent_decode_str(path).decode_utf8_lossy();
+	let audio_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+	let mut options = transcode::Options {
+		bitrate,
+		..Default::default()
+	};
+	let profile = user_manager.get_transcode_profile(username, client)?;
+	if let Some(normalization) = profile.normalization {
+		options.volume = index.get_volume(&audio_path, normalization)?;
+	}
+	// Durations of songs are truncated to the second
+	let duration = index
+		.get_song(username, Path::new(path.as_ref()))
+		.ok()
+		.and_then(|s| s.duration)
+		.map(|d| d as u32 * 1000 + 999);
+	let source = HlsSource {
+		audio_path,
+		segment: None,
+		options,
+		duration,
+	};
+	if source.audio_path.exists() {
+		return Ok(source);
+	}
+	match index.get_cue_segment(&source.audio_path)? {
+		Some((source_path, segment)) => Ok(HlsSource {
+			audio_path: source_path,
+			segment: Some(segment),
+			duration: segment
+				.end
+				.map(|end| end.saturating_sub(segment.start))
+				.or(source.duration),
+			..source
+		}),
+		None => Ok(source),
+	}
+}
+
+#[get("/hls/{path:.*}/master.m3u8")]
+async fn get_hls_master_playlist(
+	vfs_manager: Data<vfs::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+	options: web::Query<dto::StreamOptions>,
+) -> Result<HttpResponse, APIError> {
+	block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&auth.username)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		Ok(vfs.virtual_to_real(Path::new(path.as_ref()))?)
+	})
+	.await?;
+	let query_string = options.query_string();
+	let content = hls::master_playlist(|bitrate| {
+		format!("{}/{}{}", bitrate, hls::PLAYLIST_FILE_NAME, query_string)
+	});
+	Ok(HttpResponse::Ok()
+		.content_type(hls::PLAYLIST_MIME_TYPE)
+		.body(content))
+}
+
+#[get("/hls/{path:.*}/{bitrate:\\d+}/index.m3u8")]
+async fn get_hls_playlist(
+	index: Data<Index>,
+	vfs_manager: Data<vfs::Manager>,
+	transcode_manager: Data<transcode::Manager>,
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	path: web::Path<(String, u32)>,
+	options: web::Query<dto::StreamOptions>,
+) -> Result<HttpResponse, APIError> {
+	let (path, bitrate) = path.into_inner();
+	let options = options.into_inner();
+	let query_string = options.query_string();
+	let stream = block(move || -> Result<_, APIError> {
+		let source = get_hls_source(
+			&index,
+			&vfs_manager,
+			&user_manager,
+			&auth.username,
+			&path,
+			bitrate,
+			options.client.as_deref(),
+		)?;
+		index.on_stream(&source.audio_path);
+		Ok(transcode_manager.get_hls_stream(
+			&source.audio_path,
+			source.segment.as_ref(),
+			&source.options,
+			source.duration,
+		)?)
+	})
+	.await?;
+	let content = stream.playlist(|i| format!("{}{}", hls::segment_file_name(i), query_string));
+	Ok(HttpResponse::Ok()
+		.content_type(hls::PLAYLIST_MIME_TYPE)
+		.body(content))
+}
+
+#[get("/hls/{path:.*}/{bitrate:\\d+}/{segment:\\d+}.ts")]
+#[allow(clippy::too_many_arguments)]
+async fn get_hls_segment(
+	index: Data<Index>,
+	bandwidth_manager: Data<bandwidth::Manager>,
+	vfs_manager: Data<vfs::Manager>,
+	transcode_manager: Data<transcode::Manager>,
+	user_manager: Data<user::Manager>,
+	session_manager: Data<session::Manager>,
+	auth: Auth,
+	request: HttpRequest,
+	path: web::Path<(String, u32, usize)>,
+	options: web::Query<dto::StreamOptions>,
+) -> Result<MediaFile, APIError> {
+	let (path, bitrate, segment_index) = path.into_inner();
+	let client = get_client_name(&request, options.client.as_deref());
+	let (output, bandwidth, session) = block(move || -> Result<_, APIError> {
+		let session = session_manager.begin_request(
+			&auth.username,
+			&client,
+			Path::new(percent_decode_str(&path).decode_utf8_lossy().as_ref()),
+			Some(bitrate),
+		)?;
+		let source = get_hls_source(
+			&index,
+			&vfs_manager,
+			&user_manager,
+			&auth.username,
+			&path,
+			bitrate,
+			options.client.as_deref(),
+		)?;
+		let output = transcode_manager.get_hls_segment(
+			&source.audio_path,
+			source.segment.as_ref(),
+			&source.options,
+			segment_index,
+		)?;
+		let bandwidth = bandwidth_manager.open_stream(Some(&auth.username))?;
+		Ok((output, bandwidth, session))
+	})
+	.await?;
+	Ok(MediaFile::from_transcode(output, hls::SEGMENT_MIME_TYPE)?
+		.throttled(bandwidth)
+		.in_session(session))
+}
+
+#[get("/peaks/{path:.*}")]
+async fn get_peaks(
+	index: Data<Index>,
+	vfs_manager: Data<vfs::Manager>,
+	peaks_manager: Data<peaks::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<Json<Vec<u8>>, APIError> {
+	let peaks = block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&auth.username)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let audio_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		if audio_path.exists() {
+			return Ok(peaks_manager.get_peaks(&audio_path, None)?);
+		}
+		match index.get_cue_segment(&audio_path)? {
+			Some((source_path, segment)) => {
+				Ok(peaks_manager.get_peaks(&source_path, Some(&segment))?)
+			}
+			None => Err(APIError::AudioFileIOError),
+		}
+	})
+	.await?;
+	Ok(Json(peaks))
+}
+
+#[get("/artist/{name}/bio")]
+async fn get_artist_biography(
+	artist_info_manager: Data<artist_info::Manager>,
+	_auth: Auth,
+	name: web::Path<String>,
+) -> Result<Json<dto::ArtistBiography>, APIError> {
+	let biography = block(move || {
+		let name = percent_decode_str(&name).decode_utf8_lossy();
+		artist_info_manager.get_biography(&name)
+	})
+	.await?;
+	Ok(Json(biography.into()))
+}
+
+#[get("/artist/{name}/image")]
+async fn get_artist_image(
+	artist_info_manager: Data<artist_info::Manager>,
+	_auth: Auth,
+	name: web::Path<String>,
+) -> Result<MediaFile, APIError> {
+	let image_path = block(move || {
+		let name = percent_decode_str(&name).decode_utf8_lossy();
+		artist_info_manager.get_image(&name)
+	})
+	.await?;
+	let named_file = NamedFile::open(image_path).map_err(|_| APIError::ArtistImageNotFound)?;
 	Ok(MediaFile::new(named_file))
 }
 
+#[get("/lyrics/{path:.*}")]
+async fn get_lyrics(
+	index: Data<Index>,
+	vfs_manager: Data<vfs::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<Json<dto::Lyrics>, APIError> {
+	let lyrics = block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&auth.username)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let real_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		Ok(index.get_lyrics(&real_path)?)
+	})
+	.await?;
+	Ok(Json(lyrics.into()))
+}
+
+#[get("/audiobook/{path:.*}")]
+async fn get_audiobook(
+	audiobook_manager: Data<audiobook::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<Json<dto::Audiobook>, APIError> {
+	let audiobook = block(move || {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		audiobook_manager.get_audiobook(&auth.username, Path::new(path.as_ref()))
+	})
+	.await?;
+	Ok(Json(audiobook.into()))
+}
+
+#[put("/audiobook_position/{path:.*}")]
+async fn set_audiobook_position(
+	audiobook_manager: Data<audiobook::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+	input: Json<dto::AudiobookPositionInput>,
+) -> Result<HttpResponse, APIError> {
+	block(move || {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		audiobook_manager.set_position(
+			&auth.username,
+			Path::new(path.as_ref()),
+			input.position_seconds,
+		)
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/audiobook_positions")]
+async fn list_audiobook_positions(
+	audiobook_manager: Data<audiobook::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<dto::AudiobookPosition>>, APIError> {
+	let positions = block(move || audiobook_manager.list_positions(&auth.username)).await?;
+	Ok(Json(positions.into_iter().map(|p| p.into()).collect()))
+}
+
+#[put("/tags/{path:.*}")]
+async fn put_tags(
+	index: Data<Index>,
+	vfs_manager: Data<vfs::Manager>,
+	_admin_rights: AdminRights,
+	path: web::Path<String>,
+	edit: Json<dto::SongEdit>,
+) -> Result<HttpResponse, APIError> {
+	block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs()?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let real_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		Ok(index.edit_song(&real_path, &edit.into_inner().into())?)
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[put("/artwork/{path:.*}")]
+async fn put_artwork(
+	index: Data<Index>,
+	vfs_manager: Data<vfs::Manager>,
+	_admin_rights: AdminRights,
+	path: web::Path<String>,
+	artwork: web::Bytes,
+) -> Result<HttpResponse, APIError> {
+	block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs()?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let real_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		let edit = index::SongEdit {
+			artwork: Some(artwork.to_vec()),
+			..Default::default()
+		};
+		Ok(index.edit_song(&real_path, &edit)?)
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/download/{path:.*}")]
+async fn get_download(
+	bandwidth_manager: Data<bandwidth::Manager>,
+	vfs_manager: Data<vfs::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	let (archive, bandwidth) = block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&auth.username)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let archive = download::Archive::new(&vfs, Path::new(path.as_ref()))?;
+		let bandwidth = bandwidth_manager.open_stream(Some(&auth.username))?;
+		Ok((archive, bandwidth))
+	})
+	.await?;
+
+	Ok(archive_response(archive, bandwidth))
+}
+
+fn archive_response(
+	archive: download::Archive,
+	bandwidth: Option<bandwidth::Stream>,
+) -> HttpResponse {
+	let file_name = archive.file_name();
+	// Reads files on the blocking thread pool, one chunk at a time
+	let chunks = stream::unfold(Some(archive), |archive| async move {
+		let mut archive = archive?;
+		let (chunk, archive) = actix_web::web::block(move || (archive.next(), archive))
+			.await
+			.ok()?;
+		match chunk? {
+			Ok(chunk) => Some((Ok(web::Bytes::from(chunk)), Some(archive))),
+			Err(e) => Some((Err(APIError::from(e)), None)),
+		}
+	});
+
+	let response = HttpResponse::Ok()
+		.content_type("application/zip")
+		.insert_header(ContentDisposition::attachment(file_name))
+		.streaming(chunks);
+	stream_body(response, bandwidth, None)
+}
+
+#[get("/thumbnail/{path:.*}")]
+async fn get_thumbnail(
+	vfs_manager: Data<vfs::Manager>,
+	thumbnails_manager: Data<thumbnail::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+	options_input: web::Query<dto::ThumbnailOptions>,
+) -> Result<MediaFile, APIError> {
+	let options = thumbnail::Options::from(options_input.0);
+
+	let (thumbnail_path, image_modified) = block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&auth.username)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let image_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		let thumbnail_path = thumbnails_manager.get_thumbnail(&image_path, &options)?;
+		let image_modified = std::fs::metadata(&image_path)
+			.and_then(|m| m.modified())
+			.map_err(|_| APIError::ThumbnailFileIOError)?;
+		Ok((thumbnail_path, image_modified))
+	})
+	.await?;
+
+	let named_file =
+		NamedFile::open(&thumbnail_path).map_err(|_| APIError::ThumbnailFileIOError)?;
+
+	// Cached thumbnails are touched whenever they are used, so they are validated against the
+	// source image instead
+	let mut hasher = DefaultHasher::new();
+	thumbnail_path.file_name().hash(&mut hasher);
+	image_modified.hash(&mut hasher);
+	let etag = EntityTag::new_strong(format!("{:016x}", hasher.finish()));
+
+	Ok(MediaFile::with_validators(named_file, etag, image_modified))
+}
+
 #[get("/playlists")]
 async fn list_playlists(
 	playlist_manager: Data<playlist::Manager>,
@@ -578,11 +2258,14 @@ async fn list_playlists(
 #[put("/playlist/{name}")]
 async fn save_playlist(
 	playlist_manager: Data<playlist::Manager>,
+	events_manager: Data<events::Manager>,
 	auth: Auth,
 	name: web::Path<String>,
 	playlist: Json<dto::SavePlaylistInput>,
 ) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
 	block(move || playlist_manager.save_playlist(&name, &auth.username, &playlist.tracks)).await?;
+	events_manager.publish(events::Event::PlaylistsChanged { username });
 	Ok(HttpResponse::new(StatusCode::OK))
 }
 
@@ -596,16 +2279,444 @@ async fn read_playlist(
 	Ok(Json(songs))
 }
 
+#[get("/playlist/{name}/export")]
+async fn export_playlist(
+	playlist_manager: Data<playlist::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+	options: web::Query<dto::ExportPlaylistOptions>,
+) -> Result<HttpResponse, APIError> {
+	let format = options.format.map_or(playlist::Format::M3U, Into::into);
+	let file_name = format!("{}.{}", name, format.extension());
+	let content =
+		block(move || playlist_manager.export_playlist(&name, &auth.username, format)).await?;
+	Ok(HttpResponse::Ok()
+		.content_type(ContentType(format.mime_type().parse().unwrap()))
+		.insert_header(ContentDisposition::attachment(file_name))
+		.body(content))
+}
+
+#[post("/playlists/import")]
+async fn import_playlists(
+	playlist_manager: Data<playlist::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+) -> Result<Json<dto::ImportedPlaylists>, APIError> {
+	let username = auth.username.clone();
+	let playlists = block(move || playlist_manager.import_playlists(&auth.username)).await?;
+	if !playlists.imported.is_empty() {
+		events_manager.publish(events::Event::PlaylistsChanged { username });
+	}
+	Ok(Json(playlists.into()))
+}
+
+#[post("/playlist/{name}/rename")]
+async fn rename_playlist(
+	playlist_manager: Data<playlist::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+	input: Json<dto::RenamePlaylistInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || playlist_manager.rename_playlist(&name, &input.new_name, &auth.username)).await?;
+	events_manager.publish(events::Event::PlaylistsChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
 #[delete("/playlist/{name}")]
 async fn delete_playlist(
 	playlist_manager: Data<playlist::Manager>,
+	events_manager: Data<events::Manager>,
 	auth: Auth,
 	name: web::Path<String>,
 ) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
 	block(move || playlist_manager.delete_playlist(&name, &auth.username)).await?;
+	events_manager.publish(events::Event::PlaylistsChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/smart_playlists")]
+async fn list_smart_playlists(
+	smart_playlist_manager: Data<smart_playlist::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<dto::SmartPlaylist>>, APIError> {
+	let playlists =
+		block(move || smart_playlist_manager.list_smart_playlists(&auth.username)).await?;
+	Ok(Json(playlists.into_iter().map(|p| p.into()).collect()))
+}
+
+#[put("/smart_playlist/{name}")]
+async fn save_smart_playlist(
+	smart_playlist_manager: Data<smart_playlist::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+	playlist: Json<dto::SaveSmartPlaylistInput>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || {
+		smart_playlist_manager.save_smart_playlist(&name, &auth.username, &playlist.rules)
+	})
+	.await?;
+	events_manager.publish(events::Event::PlaylistsChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/smart_playlist/{name}")]
+async fn read_smart_playlist(
+	smart_playlist_manager: Data<smart_playlist::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+) -> Result<Json<Vec<index::Song>>, APIError> {
+	let songs =
+		block(move || smart_playlist_manager.read_smart_playlist(&name, &auth.username)).await?;
+	Ok(Json(songs))
+}
+
+#[delete("/smart_playlist/{name}")]
+async fn delete_smart_playlist(
+	smart_playlist_manager: Data<smart_playlist::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	name: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	let username = auth.username.clone();
+	block(move || smart_playlist_manager.delete_smart_playlist(&name, &auth.username)).await?;
+	events_manager.publish(events::Event::PlaylistsChanged { username });
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[get("/podcasts")]
+async fn list_podcasts(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<podcast::Podcast>>, APIError> {
+	let podcasts = block(move || podcast_manager.list_podcasts(&auth.username)).await?;
+	Ok(Json(podcasts))
+}
+
+#[post("/podcasts")]
+async fn subscribe_to_podcast(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	subscription: Json<dto::PodcastSubscription>,
+) -> Result<Json<podcast::Podcast>, APIError> {
+	let podcast = block(move || {
+		podcast_manager.subscribe(
+			&auth.username,
+			&subscription.url,
+			subscription.auto_download,
+		)
+	})
+	.await?;
+	Ok(Json(podcast))
+}
+
+#[delete("/podcasts/{id}")]
+async fn unsubscribe_from_podcast(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<HttpResponse, APIError> {
+	block(move || podcast_manager.unsubscribe(&auth.username, *id)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/podcasts/{id}/refresh")]
+async fn refresh_podcast(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<Json<podcast::Podcast>, APIError> {
+	let podcast = block(move || podcast_manager.refresh(&auth.username, *id)).await?;
+	Ok(Json(podcast))
+}
+
+#[get("/podcasts/{id}/episodes")]
+async fn list_podcast_episodes(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<Json<Vec<podcast::Episode>>, APIError> {
+	let episodes = block(move || podcast_manager.list_episodes(&auth.username, *id)).await?;
+	Ok(Json(episodes))
+}
+
+#[put("/podcast_episodes/{id}/position")]
+async fn set_podcast_episode_position(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+	position: Json<dto::PodcastEpisodePosition>,
+) -> Result<HttpResponse, APIError> {
+	block(move || {
+		podcast_manager.set_position(
+			&auth.username,
+			*id,
+			position.position_seconds,
+			position.completed,
+		)
+	})
+	.await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[post("/podcast_episodes/{id}/download")]
+async fn download_podcast_episode(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<HttpResponse, APIError> {
+	block(move || podcast_manager.download(&auth.username, *id)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[delete("/podcast_episodes/{id}/download")]
+async fn remove_podcast_episode_download(
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<HttpResponse, APIError> {
+	block(move || podcast_manager.remove_download(&auth.username, *id)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[route("/podcast_episodes/{id}/audio", method = "GET", method = "HEAD")]
+async fn get_podcast_episode_audio(
+	bandwidth_manager: Data<bandwidth::Manager>,
+	podcast_manager: Data<podcast::Manager>,
+	auth: Auth,
+	request: HttpRequest,
+	id: web::Path<i32>,
+) -> Result<HttpResponse, APIError> {
+	let (audio, bandwidth) = block(move || -> Result<_, APIError> {
+		let audio = podcast_manager.get_audio(&auth.username, *id)?;
+		let bandwidth = bandwidth_manager.open_stream(Some(&auth.username))?;
+		Ok((audio, bandwidth))
+	})
+	.await?;
+	match audio {
+		podcast::Audio::Cached { path, mime_type } => {
+			let named_file = NamedFile::open(path).map_err(|_| APIError::AudioFileIOError)?;
+			let named_file = match mime_type.and_then(|m| m.parse().ok()) {
+				Some(mime_type) => named_file.set_content_type(mime_type),
+				None => named_file,
+			};
+			Ok(MediaFile::new(named_file)
+				.throttled(bandwidth)
+				.respond_to(&request))
+		}
+		// Episodes which were not downloaded are played from their host
+		podcast::Audio::Remote(url) => Ok(HttpResponse::TemporaryRedirect()
+			.insert_header((header::LOCATION, url))
+			.finish()),
+	}
+}
+
+#[get("/radio_stations")]
+async fn list_radio_stations(
+	radio_manager: Data<radio::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<radio::Station>>, APIError> {
+	let stations = block(move || radio_manager.list_stations(&auth.username)).await?;
+	Ok(Json(stations))
+}
+
+#[post("/radio_stations")]
+async fn add_radio_station(
+	radio_manager: Data<radio::Manager>,
+	auth: Auth,
+	station: Json<radio::NewStation>,
+) -> Result<Json<radio::Station>, APIError> {
+	let station = block(move || radio_manager.add_station(&auth.username, &station)).await?;
+	Ok(Json(station))
+}
+
+#[delete("/radio_stations/{id}")]
+async fn remove_radio_station(
+	radio_manager: Data<radio::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<HttpResponse, APIError> {
+	block(move || radio_manager.remove_station(&auth.username, *id)).await?;
 	Ok(HttpResponse::new(StatusCode::OK))
 }
 
+#[get("/radio_stations/{id}/stream")]
+async fn get_radio_stream(
+	radio_manager: Data<radio::Manager>,
+	auth: Auth,
+	id: web::Path<i32>,
+) -> Result<HttpResponse, APIError> {
+	let stream = block(move || radio_manager.open_stream(&auth.username, *id)).await?;
+	let content_type = stream
+		.content_type
+		.unwrap_or_else(|| "audio/mpeg".to_owned());
+
+	// Reads the upstream station for as long as the client listens
+	let chunks = read_in_chunks(stream.reader, || APIError::RadioStreamFailed);
+
+	Ok(HttpResponse::Ok()
+		.content_type(content_type)
+		.insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+		.streaming(chunks))
+}
+
+// Reads on the blocking thread pool, as readers may wait for data to come in
+fn read_in_chunks<R>(
+	reader: R,
+	error: fn() -> APIError,
+) -> impl futures_util::Stream<Item = Result<web::Bytes, APIError>>
+where
+	R: std::io::Read + Send + 'static,
+{
+	stream::unfold(Some(reader), move |reader| async move {
+		let mut reader = reader?;
+		let (chunk, reader) = actix_web::web::block(move || {
+			let mut buffer = vec![0; 16 * 1024];
+			let chunk = reader.read(&mut buffer).map(|length| {
+				buffer.truncate(length);
+				buffer
+			});
+			(chunk, reader)
+		})
+		.await
+		.ok()?;
+		match chunk {
+			Ok(chunk) if chunk.is_empty() => None,
+			Ok(chunk) => Some((Ok(web::Bytes::from(chunk)), Some(reader))),
+			Err(_) => Some((Err(error()), None)),
+		}
+	})
+}
+
+#[get("/shares")]
+async fn list_shares(
+	share_manager: Data<share::Manager>,
+	auth: Auth,
+) -> Result<Json<Vec<share::Share>>, APIError> {
+	let shares = block(move || share_manager.list(&auth.username)).await?;
+	Ok(Json(shares))
+}
+
+#[post("/shares")]
+async fn create_share(
+	share_manager: Data<share::Manager>,
+	auth: Auth,
+	new_share: Json<dto::NewShare>,
+) -> Result<Json<share::Share>, APIError> {
+	let new_share = new_share.into_inner().into();
+	let share = block(move || share_manager.create(&auth.username, new_share)).await?;
+	Ok(Json(share))
+}
+
+#[delete("/shares/{token}")]
+async fn delete_share(
+	share_manager: Data<share::Manager>,
+	auth: Auth,
+	token: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	block(move || share_manager.delete(&auth.username, &token)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+// Share links are meant for people without an account, so the endpoints below do not require
+// authentication
+#[get("/share/{token}")]
+async fn get_share(
+	share_manager: Data<share::Manager>,
+	token: web::Path<String>,
+) -> Result<Json<dto::SharedContent>, APIError> {
+	let content = block(move || share_manager.get_content(&token)).await?;
+	Ok(Json(content.into()))
+}
+
+#[route("/share/{token}/audio/{track}", method = "GET", method = "HEAD")]
+async fn get_share_audio(
+	index: Data<Index>,
+	bandwidth_manager: Data<bandwidth::Manager>,
+	share_manager: Data<share::Manager>,
+	transcode_manager: Data<transcode::Manager>,
+	request: HttpRequest,
+	path: web::Path<(String, usize)>,
+) -> Result<MediaFile, APIError> {
+	let (token, track) = path.into_inner();
+	// HEAD requests do not play anything, so they never count against the play limit
+	let listener = match request.method() == actix_web::http::Method::HEAD {
+		true => None,
+		false => Some(get_share_listener(&request)),
+	};
+	let (audio_path, cue_segment, bandwidth) = block(move || -> Result<_, APIError> {
+		let audio_path = share_manager.get_track(&token, track, listener.as_deref())?;
+		let cue_segment = match audio_path.exists() {
+			true => None,
+			false => index.get_cue_segment(&audio_path)?,
+		};
+		let bandwidth = bandwidth_manager.open_stream(None)?;
+		Ok((audio_path, cue_segment, bandwidth))
+	})
+	.await?;
+
+	if let Some((source_path, segment)) = cue_segment {
+		let output = block(move || -> Result<_, APIError> {
+			transcode_manager
+				.get_segment(&source_path, &segment, None)
+				.map_err(|e| e.into())
+		})
+		.await?;
+		return Ok(MediaFile::from_transcode(output, "audio/flac")?.throttled(bandwidth));
+	}
+
+	Ok(MediaFile::new(open_audio_file(&audio_path)?).throttled(bandwidth))
+}
+
+// Share links carry no credentials, so listeners are told apart by where their requests come from
+fn get_share_listener(request: &HttpRequest) -> String {
+	let peer_address = request.peer_addr().map(|a| a.ip().to_string());
+	let user_agent = request
+		.headers()
+		.get(header::USER_AGENT)
+		.and_then(|v| v.to_str().ok());
+	format!(
+		"{} {}",
+		peer_address.unwrap_or_default(),
+		user_agent.unwrap_or_default()
+	)
+}
+
+#[get("/share/{token}/download")]
+async fn get_share_download(
+	bandwidth_manager: Data<bandwidth::Manager>,
+	share_manager: Data<share::Manager>,
+	request: HttpRequest,
+	token: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	let (download, bandwidth) = block(move || -> Result<_, APIError> {
+		let download = share_manager.get_download(&token)?;
+		let bandwidth = bandwidth_manager.open_stream(None)?;
+		Ok((download, bandwidth))
+	})
+	.await?;
+	let archive = match download {
+		share::Download::Archive(archive) => archive,
+		share::Download::File(path) => {
+			let file_name = path
+				.file_name()
+				.map(|n| n.to_string_lossy().into_owned())
+				.unwrap_or_default();
+			let named_file = NamedFile::open(&path).map_err(|_| APIError::AudioFileIOError)?;
+			let named_file =
+				named_file.set_content_disposition(ContentDisposition::attachment(file_name));
+			let response = named_file.into_response(&request);
+			return Ok(stream_body(response, bandwidth, None));
+		}
+	};
+
+	Ok(archive_response(archive, bandwidth))
+}
+
 #[put("/lastfm/now_playing/{path:.*}")]
 async fn lastfm_now_playing(
 	lastfm_manager: Data<lastfm::Manager>,
@@ -695,3 +2806,102 @@ async fn lastfm_unlink(
 	block(move || lastfm_manager.unlink(&auth.username)).await?;
 	Ok(HttpResponse::new(StatusCode::OK))
 }
+
+#[put("/listenbrainz/link")]
+async fn listenbrainz_link(
+	listenbrainz_manager: Data<listenbrainz::Manager>,
+	auth: Auth,
+	payload: Json<dto::ListenBrainzLink>,
+) -> Result<HttpResponse, APIError> {
+	block(move || listenbrainz_manager.link(&auth.username, &payload.token)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+#[delete("/listenbrainz/link")]
+async fn listenbrainz_unlink(
+	listenbrainz_manager: Data<listenbrainz::Manager>,
+	auth: Auth,
+) -> Result<HttpResponse, APIError> {
+	block(move || listenbrainz_manager.unlink(&auth.username)).await?;
+	Ok(HttpResponse::new(StatusCode::OK))
+}
+
+// Submits to every scrobbling service linked by the user, or returns NO_CONTENT when there are none
+#[put("/now_playing/{path:.*}")]
+async fn now_playing(
+	lastfm_manager: Data<lastfm::Manager>,
+	listenbrainz_manager: Data<listenbrainz::Manager>,
+	user_manager: Data<user::Manager>,
+	events_manager: Data<events::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	let path = percent_decode_str(&path).decode_utf8_lossy().into_owned();
+	events_manager.publish(events::Event::NowPlaying {
+		username: auth.username.clone(),
+		path: path.clone(),
+	});
+	let submitted = block(move || -> Result<bool, APIError> {
+		let track = Path::new(&path);
+		let mut results = Vec::new();
+		if user_manager.is_lastfm_linked(&auth.username) {
+			results.push(
+				lastfm_manager
+					.now_playing(&auth.username, track)
+					.map_err(APIError::from),
+			);
+		}
+		if user_manager.is_listenbrainz_linked(&auth.username) {
+			results.push(
+				listenbrainz_manager
+					.now_playing(&auth.username, track)
+					.map_err(APIError::from),
+			);
+		}
+		let submitted = !results.is_empty();
+		results.into_iter().collect::<Result<(), _>>()?;
+		Ok(submitted)
+	})
+	.await?;
+	Ok(HttpResponse::new(match submitted {
+		true => StatusCode::OK,
+		false => StatusCode::NO_CONTENT,
+	}))
+}
+
+#[post("/scrobble/{path:.*}")]
+async fn scrobble(
+	lastfm_manager: Data<lastfm::Manager>,
+	listenbrainz_manager: Data<listenbrainz::Manager>,
+	user_manager: Data<user::Manager>,
+	auth: Auth,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	let submitted = block(move || -> Result<bool, APIError> {
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let track = Path::new(path.as_ref());
+		let mut results = Vec::new();
+		if user_manager.is_lastfm_linked(&auth.username) {
+			results.push(
+				lastfm_manager
+					.scrobble(&auth.username, track)
+					.map_err(APIError::from),
+			);
+		}
+		if user_manager.is_listenbrainz_linked(&auth.username) {
+			results.push(
+				listenbrainz_manager
+					.scrobble(&auth.username, track)
+					.map_err(APIError::from),
+			);
+		}
+		let submitted = !results.is_empty();
+		results.into_iter().collect::<Result<(), _>>()?;
+		Ok(submitted)
+	})
+	.await?;
+	Ok(HttpResponse::new(match submitted {
+		true => StatusCode::OK,
+		false => StatusCode::NO_CONTENT,
+	}))
+}
@@ -0,0 +1,240 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentType, HeaderName, HeaderValue};
+use actix_web::{
+	get, post, route,
+	web::{self, Bytes, Data, ServiceConfig},
+	HttpRequest, HttpResponse, Responder,
+};
+use percent_encoding::percent_decode_str;
+use std::path::Path;
+
+use super::api::{block, get_client_address, MediaFile, UrlBase};
+use crate::app::{
+	dlna::{self, ServiceType},
+	index::Index,
+	proxy_auth, thumbnail, transcode, vfs,
+};
+use crate::service::error::*;
+
+// Flags telling devices that files can be streamed and seeked by byte ranges
+const CONTENT_FEATURES: &str =
+	"DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+
+pub fn make_config() -> impl FnOnce(&mut ServiceConfig) + Clone {
+	move |cfg: &mut ServiceConfig| {
+		cfg.service(get_description)
+			.service(get_content_directory_description)
+			.service(get_connection_manager_description)
+			.service(control_content_directory)
+			.service(control_connection_manager)
+			.service(get_audio)
+			.service(get_artwork);
+	}
+}
+
+// Devices are not authenticated, so requests from outside the local network are turned away.
+// Requests relayed by a reverse proxy are refused unless the proxy is trusted, in which case the
+// address it received the request from is checked instead.
+fn check_local(request: &HttpRequest) -> Result<(), APIError> {
+	let peer_address = request.peer_addr().map(|a| a.ip());
+	let is_trusted_proxy = match (
+		request.app_data::<Data<proxy_auth::Manager>>(),
+		peer_address,
+	) {
+		(Some(manager), Some(address)) => manager.is_trusted_proxy(address),
+		_ => false,
+	};
+	let headers = request.headers();
+	let client_address = match (
+		headers.contains_key("forwarded"),
+		headers.contains_key("x-forwarded-for"),
+	) {
+		(false, false) => peer_address,
+		(false, true) if is_trusted_proxy => get_client_address(request),
+		_ => None,
+	};
+	match client_address.is_some_and(dlna::is_local_address) {
+		true => Ok(()),
+		false => Err(APIError::DLNAAccessForbidden),
+	}
+}
+
+fn xml_response(xml: impl Into<String>) -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type(ContentType::xml())
+		.body(xml.into())
+}
+
+#[get("/description.xml")]
+async fn get_description(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+) -> Result<HttpResponse, APIError> {
+	check_local(&request)?;
+	Ok(xml_response(dlna_manager.get_description()?))
+}
+
+#[get("/content_directory.xml")]
+async fn get_content_directory_description(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+) -> Result<HttpResponse, APIError> {
+	check_local(&request)?;
+	let xml = dlna_manager.get_service_description(ServiceType::ContentDirectory)?;
+	Ok(xml_response(xml))
+}
+
+#[get("/connection_manager.xml")]
+async fn get_connection_manager_description(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+) -> Result<HttpResponse, APIError> {
+	check_local(&request)?;
+	let xml = dlna_manager.get_service_description(ServiceType::ConnectionManager)?;
+	Ok(xml_response(xml))
+}
+
+#[post("/control/content_directory")]
+async fn control_content_directory(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+	url_base: Data<UrlBase>,
+	body: Bytes,
+) -> Result<HttpResponse, APIError> {
+	control(
+		request,
+		dlna_manager,
+		url_base,
+		ServiceType::ContentDirectory,
+		body,
+	)
+	.await
+}
+
+#[post("/control/connection_manager")]
+async fn control_connection_manager(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+	url_base: Data<UrlBase>,
+	body: Bytes,
+) -> Result<HttpResponse, APIError> {
+	control(
+		request,
+		dlna_manager,
+		url_base,
+		ServiceType::ConnectionManager,
+		body,
+	)
+	.await
+}
+
+async fn control(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+	url_base: Data<UrlBase>,
+	service: ServiceType,
+	body: Bytes,
+) -> Result<HttpResponse, APIError> {
+	check_local(&request)?;
+	dlna_manager.get_user()?;
+
+	// Formatted as `"urn:schemas-upnp-org:service:ContentDirectory:1#Browse"`
+	let action = request
+		.headers()
+		.get("soapaction")
+		.and_then(|h| h.to_str().ok())
+		.and_then(|h| h.trim_matches('"').split_once('#'))
+		.map(|(_, action)| action.to_owned())
+		.unwrap_or_default();
+	let base_url = {
+		let connection_info = request.connection_info();
+		format!(
+			"{}://{}{}",
+			connection_info.scheme(),
+			connection_info.host(),
+			url_base.0
+		)
+	};
+	let body = String::from_utf8_lossy(&body).into_owned();
+
+	let result = block(move || -> Result<_, APIError> {
+		Ok(dlna_manager.control(service, &action, &body, &base_url))
+	})
+	.await?;
+
+	Ok(match result {
+		Ok(xml) => xml_response(xml),
+		Err(e) => HttpResponse::InternalServerError()
+			.content_type(ContentType::xml())
+			.body(dlna::get_fault(&e)),
+	})
+}
+
+#[route("/audio/{path:.*}", method = "GET", method = "HEAD")]
+async fn get_audio(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+	index: Data<Index>,
+	vfs_manager: Data<vfs::Manager>,
+	transcode_manager: Data<transcode::Manager>,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	check_local(&request)?;
+	let user = dlna_manager.get_user()?.to_owned();
+
+	let media_file = block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&user)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let audio_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		index.on_stream(&audio_path);
+		if audio_path.exists() {
+			let mime_type = dlna::get_mime_type(&audio_path);
+			let named_file =
+				NamedFile::open(&audio_path).map_err(|_| APIError::AudioFileIOError)?;
+			let named_file = named_file.set_content_type(mime_type.parse().unwrap());
+			return Ok(MediaFile::new(named_file));
+		}
+		let (source_path, segment) = index
+			.get_cue_segment(&audio_path)?
+			.ok_or(APIError::AudioFileIOError)?;
+		let output = transcode_manager.get_segment(&source_path, &segment, None)?;
+		MediaFile::from_transcode(output, "audio/flac")
+	})
+	.await?;
+
+	let mut response = media_file.respond_to(&request);
+	let headers = response.headers_mut();
+	headers.insert(
+		HeaderName::from_static("transfermode.dlna.org"),
+		HeaderValue::from_static("Streaming"),
+	);
+	headers.insert(
+		HeaderName::from_static("contentfeatures.dlna.org"),
+		HeaderValue::from_static(CONTENT_FEATURES),
+	);
+	Ok(response)
+}
+
+#[get("/artwork/{path:.*}")]
+async fn get_artwork(
+	request: HttpRequest,
+	dlna_manager: Data<dlna::Manager>,
+	vfs_manager: Data<vfs::Manager>,
+	thumbnails_manager: Data<thumbnail::Manager>,
+	path: web::Path<String>,
+) -> Result<HttpResponse, APIError> {
+	check_local(&request)?;
+	let user = dlna_manager.get_user()?.to_owned();
+
+	let thumbnail_path = block(move || -> Result<_, APIError> {
+		let vfs = vfs_manager.get_vfs_for_user(&user)?;
+		let path = percent_decode_str(&path).decode_utf8_lossy();
+		let image_path = vfs.virtual_to_real(Path::new(path.as_ref()))?;
+		let options = thumbnail::Options::default();
+		Ok(thumbnails_manager.get_thumbnail(&image_path, &options)?)
+	})
+	.await?;
+
+	let named_file = NamedFile::open(thumbnail_path).map_err(|_| APIError::ThumbnailFileIOError)?;
+	Ok(MediaFile::new(named_file).respond_to(&request))
+}
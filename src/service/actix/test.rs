@@ -9,6 +9,7 @@ use http::{response::Builder, Method, Request, Response};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::ops::Deref;
+use std::path::PathBuf;
 
 use crate::app::App;
 use crate::paths::Paths;
@@ -26,6 +27,46 @@ pub struct ActixTestService {
 pub type ServiceType = ActixTestService;
 
 impl ActixTestService {
+	// Starts a service whose config file has the given content
+	pub fn new_with_config(test_name: &str, config_file_content: &str) -> Self {
+		let output_dir = prepare_test_directory(test_name);
+		let config_file_path = output_dir.join("polaris.toml");
+		std::fs::write(&config_file_path, config_file_content).unwrap();
+		Self::start(test_name, Some(config_file_path))
+	}
+
+	fn start(test_name: &str, config_file_path: Option<PathBuf>) -> Self {
+		let output_dir: PathBuf = ["test-output", test_name].iter().collect();
+
+		let paths = Paths {
+			cache_dir_path: ["test-output", test_name].iter().collect(),
+			config_file_path,
+			db_file_path: output_dir.join("db.sqlite"),
+			#[cfg(unix)]
+			pid_file_path: output_dir.join("polaris.pid"),
+			log_file_path: None,
+			swagger_dir_path: ["docs", "swagger"].iter().collect(),
+			web_dir_path: ["test-data", "web"].iter().collect(),
+		};
+
+		let app = App::new(5050, paths).unwrap();
+
+		let system_runner = System::new();
+		let server = actix_test::start(move || {
+			let config = make_config(app.clone());
+			ActixApp::new()
+				.wrap(Logger::default())
+				.wrap(Compress::default())
+				.configure(config)
+		});
+
+		ActixTestService {
+			authorization: None,
+			system_runner,
+			server,
+		}
+	}
+
 	fn process_internal<T: Serialize + Clone + 'static>(
 		&mut self,
 		request: &Request<T>,
@@ -38,6 +79,7 @@ impl ActixTestService {
 			Method::POST => self.server.post(url),
 			Method::PUT => self.server.put(url),
 			Method::DELETE => self.server.delete(url),
+			Method::HEAD => self.server.head(url),
 			_ => unimplemented!(),
 		}
 		.timeout(std::time::Duration::from_secs(30));
@@ -76,35 +118,8 @@ impl ActixTestService {
 
 impl TestService for ActixTestService {
 	fn new(test_name: &str) -> Self {
-		let output_dir = prepare_test_directory(test_name);
-
-		let paths = Paths {
-			cache_dir_path: ["test-output", test_name].iter().collect(),
-			config_file_path: None,
-			db_file_path: output_dir.join("db.sqlite"),
-			#[cfg(unix)]
-			pid_file_path: output_dir.join("polaris.pid"),
-			log_file_path: None,
-			swagger_dir_path: ["docs", "swagger"].iter().collect(),
-			web_dir_path: ["test-data", "web"].iter().collect(),
-		};
-
-		let app = App::new(5050, paths).unwrap();
-
-		let system_runner = System::new();
-		let server = actix_test::start(move || {
-			let config = make_config(app.clone());
-			ActixApp::new()
-				.wrap(Logger::default())
-				.wrap(Compress::default())
-				.configure(config)
-		});
-
-		ActixTestService {
-			authorization: None,
-			system_runner,
-			server,
-		}
+		prepare_test_directory(test_name);
+		Self::start(test_name, None)
 	}
 
 	fn fetch<T: Serialize + Clone + 'static>(&mut self, request: &Request<T>) -> Response<()> {
@@ -1,10 +1,16 @@
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
 
-use crate::app::{config, ddns, settings, thumbnail, user, vfs};
+use crate::app::{
+	artist_info, audiobook, config, ddns, duplicate, events, index, jukebox, playlist, queue,
+	session, settings, share, smart_playlist, sync, thumbnail, transcode, user, vfs,
+};
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
+use std::path::PathBuf;
 
 pub const API_MAJOR_VERSION: i32 = 7;
-pub const API_MINOR_VERSION: i32 = 0;
+pub const API_MINOR_VERSION: i32 = 1;
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Version {
@@ -12,11 +18,25 @@ pub struct Version {
 	pub minor: i32,
 }
 
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Health {
+	pub healthy: bool,
+	pub database: bool,
+	pub mounts_available: bool,
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct InitialSetup {
 	pub has_any_users: bool,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreatedApiKey {
+	pub name: String,
+	// Only revealed once, when the key is created
+	pub key: String,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Credentials {
 	pub username: String,
@@ -69,16 +89,686 @@ impl Into<Option<u32>> for ThumbnailSize {
 	}
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StreamOptions {
+	pub client: Option<String>, // Name of the client whose normalization preference applies
+	pub auth_token: Option<String>,
+}
+
+impl StreamOptions {
+	// Playlists refer to each other with relative URLs, which do not carry query parameters over
+	pub fn query_string(&self) -> String {
+		let parameters = [("client", &self.client), ("auth_token", &self.auth_token)]
+			.into_iter()
+			.filter_map(|(name, value)| {
+				let value = utf8_percent_encode(value.as_deref()?, NON_ALPHANUMERIC);
+				Some(format!("{}={}", name, value))
+			})
+			.collect::<Vec<_>>();
+		match parameters.is_empty() {
+			true => String::new(),
+			false => format!("?{}", parameters.join("&")),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AudioOptions {
+	pub format: Option<TranscodeFormat>,
+	pub bitrate: Option<u32>,
+	pub client: Option<String>, // Name of the client whose transcoding profile applies
+	pub normalization: Option<Normalization>,
+}
+
+impl AudioOptions {
+	pub fn is_transcode(&self) -> bool {
+		self.format.is_some() || self.bitrate.is_some() || self.normalization.is_some()
+	}
+}
+
+impl From<AudioOptions> for transcode::Options {
+	fn from(dto: AudioOptions) -> Self {
+		let mut options = transcode::Options::default();
+		options.format = dto.format.map_or(options.format, Into::into);
+		options.bitrate = dto.bitrate.unwrap_or(options.bitrate);
+		options
+	}
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscodeFormat {
+	Mp3,
+	Opus,
+}
+
+impl From<TranscodeFormat> for transcode::Format {
+	fn from(f: TranscodeFormat) -> Self {
+		match f {
+			TranscodeFormat::Mp3 => Self::MP3,
+			TranscodeFormat::Opus => Self::Opus,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+	Track,
+	Album,
+	Loudness,
+}
+
+impl From<Normalization> for transcode::Normalization {
+	fn from(n: Normalization) -> Self {
+		match n {
+			Normalization::Track => Self::Track,
+			Normalization::Album => Self::Album,
+			Normalization::Loudness => Self::Loudness,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+	IndexUpdateStarted,
+	IndexUpdateCompleted,
+	PlaylistsChanged,
+	QueueChanged,
+	NowPlaying { path: String },
+}
+
+impl From<events::Event> for Event {
+	fn from(e: events::Event) -> Self {
+		match e {
+			events::Event::IndexUpdateStarted => Self::IndexUpdateStarted,
+			events::Event::IndexUpdateCompleted => Self::IndexUpdateCompleted,
+			events::Event::PlaylistsChanged { .. } => Self::PlaylistsChanged,
+			events::Event::QueueChanged { .. } => Self::QueueChanged,
+			events::Event::NowPlaying { path, .. } => Self::NowPlaying { path },
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SuggestionOptions {
+	pub prefix: String,
+	pub limit: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+	Artist,
+	Album,
+	Track,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suggestion {
+	pub kind: SuggestionKind,
+	pub text: String,
+	pub artist: Option<String>,
+}
+
+impl From<index::Suggestion> for Suggestion {
+	fn from(s: index::Suggestion) -> Self {
+		Self {
+			kind: match s.kind {
+				index::SuggestionKind::Artist => SuggestionKind::Artist,
+				index::SuggestionKind::Album => SuggestionKind::Album,
+				index::SuggestionKind::Track => SuggestionKind::Track,
+			},
+			text: s.text,
+			artist: s.artist,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lyrics {
+	pub synced: bool,
+	pub lines: Vec<LyricsLine>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LyricsLine {
+	pub start: Option<u32>, // Milliseconds, for time-synced lyrics
+	pub text: String,
+}
+
+impl From<index::Lyrics> for Lyrics {
+	fn from(l: index::Lyrics) -> Self {
+		Self {
+			synced: l.synced,
+			lines: l
+				.lines
+				.into_iter()
+				.map(|l| LyricsLine {
+					start: l.start,
+					text: l.text,
+				})
+				.collect(),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chapter {
+	pub title: Option<String>,
+	pub start: u32, // Milliseconds
+	pub end: Option<u32>,
+}
+
+impl From<index::Chapter> for Chapter {
+	fn from(c: index::Chapter) -> Self {
+		Self {
+			title: c.title,
+			start: c.start,
+			end: c.end,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Audiobook {
+	pub path: String,
+	pub position_seconds: u32,
+	pub chapters: Vec<Chapter>,
+}
+
+impl From<audiobook::Audiobook> for Audiobook {
+	fn from(a: audiobook::Audiobook) -> Self {
+		Self {
+			path: a.path.to_string_lossy().into_owned(),
+			position_seconds: a.position_seconds,
+			chapters: a.chapters.into_iter().map(|c| c.into()).collect(),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudiobookPosition {
+	pub path: String,
+	pub position_seconds: u32,
+	pub update_time: i64,
+}
+
+impl From<audiobook::Position> for AudiobookPosition {
+	fn from(p: audiobook::Position) -> Self {
+		Self {
+			path: p.path.to_string_lossy().into_owned(),
+			position_seconds: p.position_seconds,
+			update_time: p.update_time,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudiobookPositionInput {
+	pub position_seconds: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Duplicate {
+	pub path: String,
+	pub duration: i32,
+	pub file_size: Option<i64>,
+	pub bitrate: Option<i64>, // Kilobits per second, averaged over the whole file
+}
+
+impl From<duplicate::Duplicate> for Duplicate {
+	fn from(d: duplicate::Duplicate) -> Self {
+		let bitrate = d
+			.file_size
+			.filter(|_| d.duration > 0)
+			.map(|size| size * 8 / 1000 / d.duration as i64);
+		Self {
+			path: d.path.to_string_lossy().into_owned(),
+			duration: d.duration,
+			file_size: d.file_size,
+			bitrate,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MissingSong {
+	pub path: String,
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub missing_since: i64,
+	pub play_count: i64,
+	pub rating: Option<f32>,
+}
+
+impl From<index::MissingSong> for MissingSong {
+	fn from(s: index::MissingSong) -> Self {
+		Self {
+			path: s.path.to_string_lossy().into_owned(),
+			title: s.title,
+			artist: s.artist,
+			album: s.album,
+			missing_since: s.missing_since,
+			play_count: s.play_count,
+			rating: s.rating,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtistBiography {
+	pub summary: String,
+	pub content: String,
+	pub url: Option<String>,
+}
+
+impl From<artist_info::Biography> for ArtistBiography {
+	fn from(b: artist_info::Biography) -> Self {
+		Self {
+			summary: b.summary,
+			content: b.content,
+			url: b.url,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamSession {
+	pub id: u64,
+	pub username: String,
+	pub client: String,
+	pub path: String,
+	pub bitrate: Option<u32>,
+	pub started_at: i64,
+}
+
+impl From<session::Session> for StreamSession {
+	fn from(s: session::Session) -> Self {
+		Self {
+			id: s.id,
+			username: s.username,
+			client: s.client,
+			path: s.path.to_string_lossy().into_owned(),
+			bitrate: s.bitrate,
+			started_at: s.started_at,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SongEdit {
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub album_artist: Option<String>,
+	pub track_number: Option<u32>,
+	pub disc_number: Option<u32>,
+	pub year: Option<i32>,
+}
+
+impl From<SongEdit> for index::SongEdit {
+	fn from(e: SongEdit) -> Self {
+		Self {
+			title: e.title,
+			artist: e.artist,
+			album: e.album,
+			album_artist: e.album_artist,
+			track_number: e.track_number,
+			disc_number: e.disc_number,
+			year: e.year,
+			artwork: None,
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rating {
+	pub rating: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ListenOptions {
+	pub completion: Option<u8>, // Percentage of the song which was listened to
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageOptions {
+	pub offset: Option<usize>,
+	pub limit: Option<usize>,
+}
+
+impl From<PageOptions> for index::Page {
+	fn from(p: PageOptions) -> Self {
+		Self {
+			offset: p.offset.unwrap_or_default(),
+			limit: p.limit,
+		}
+	}
+}
+
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ListPlaylistsEntry {
 	pub name: String,
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportedPlaylists {
+	pub imported: Vec<ListPlaylistsEntry>,
+	// Playlist files which were not imported because a playlist with the same name exists
+	pub skipped: Vec<String>,
+}
+
+impl From<playlist::ImportedPlaylists> for ImportedPlaylists {
+	fn from(p: playlist::ImportedPlaylists) -> Self {
+		Self {
+			imported: p
+				.imported
+				.into_iter()
+				.map(|name| ListPlaylistsEntry { name })
+				.collect(),
+			skipped: p
+				.skipped
+				.iter()
+				.map(|p| p.to_string_lossy().into_owned())
+				.collect(),
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayQueue {
+	pub songs: Vec<Song>,
+	pub current_track: Option<usize>,
+	pub position_seconds: u32,
+}
+
+impl PlayQueue {
+	pub fn new(
+		queue: queue::PlayQueue,
+		favorites: &HashSet<String>,
+		ratings: &HashMap<String, u8>,
+	) -> Self {
+		Self {
+			songs: queue
+				.songs
+				.into_iter()
+				.map(|s| Song::new(s, favorites, ratings))
+				.collect(),
+			current_track: queue.current_track,
+			position_seconds: queue.position_seconds,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Playback {
+	pub device: Option<String>,
+	pub playing: bool,
+	pub update_time: i64,
+	pub queue: PlayQueue,
+}
+
+impl Playback {
+	pub fn new(
+		playback: queue::Playback,
+		favorites: &HashSet<String>,
+		ratings: &HashMap<String, u8>,
+	) -> Self {
+		Self {
+			device: playback.device,
+			playing: playback.playing,
+			update_time: playback.update_time,
+			queue: PlayQueue::new(playback.queue, favorites, ratings),
+		}
+	}
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PlaybackInput {
+	#[serde(default)]
+	pub device: Option<String>,
+	#[serde(default)]
+	pub tracks: Option<Vec<String>>,
+	pub current_track: Option<usize>,
+	#[serde(default)]
+	pub position_seconds: u32,
+	#[serde(default)]
+	pub playing: bool,
+}
+
+impl From<PlaybackInput> for queue::PlaybackReport {
+	fn from(p: PlaybackInput) -> Self {
+		Self {
+			device: p.device,
+			tracks: p
+				.tracks
+				.map(|tracks| tracks.into_iter().map(PathBuf::from).collect()),
+			current_track: p.current_track,
+			position_seconds: p.position_seconds,
+			playing: p.playing,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangesOptions {
+	pub since: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SongChanges {
+	pub added: Vec<Song>,
+	pub modified: Vec<Song>,
+	pub removed: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistChanges {
+	pub added: Vec<String>,
+	pub modified: Vec<String>,
+	pub removed: Vec<String>,
+}
+
+impl From<sync::PlaylistChanges> for PlaylistChanges {
+	fn from(c: sync::PlaylistChanges) -> Self {
+		Self {
+			added: c.added,
+			modified: c.modified,
+			removed: c.removed,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Changes {
+	pub revision: String, // Token to send as `since` in the next request
+	pub songs: SongChanges,
+	pub playlists: PlaylistChanges,
+}
+
+impl Changes {
+	pub fn new(
+		changes: sync::Changes,
+		favorites: &HashSet<String>,
+		ratings: &HashMap<String, u8>,
+	) -> Self {
+		let to_dto = |songs: Vec<index::Song>| {
+			songs
+				.into_iter()
+				.map(|s| Song::new(s, favorites, ratings))
+				.collect()
+		};
+		Self {
+			revision: changes.revision.to_string(),
+			songs: SongChanges {
+				added: to_dto(changes.songs.added),
+				modified: to_dto(changes.songs.modified),
+				removed: changes.songs.removed,
+			},
+			playlists: changes.playlists.into(),
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QueueTracksInput {
+	pub tracks: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MoveQueueTrackInput {
+	pub from: usize,
+	pub to: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QueueCurrentTrackInput {
+	pub current_track: Option<usize>,
+	#[serde(default)]
+	pub position_seconds: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JukeboxStatus {
+	pub username: Option<String>,
+	pub is_playing: bool,
+	pub current_track: Option<usize>,
+	pub position_seconds: u32,
+	pub volume: u8,
+}
+
+impl From<jukebox::Status> for JukeboxStatus {
+	fn from(s: jukebox::Status) -> Self {
+		Self {
+			username: s.username,
+			is_playing: s.is_playing,
+			current_track: s.current_track,
+			position_seconds: s.position_seconds,
+			volume: s.volume,
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JukeboxVolumeInput {
+	pub volume: u8,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SavePlaylistInput {
 	pub tracks: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ExportPlaylistOptions {
+	pub format: Option<PlaylistFormat>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistFormat {
+	M3u,
+	Pls,
+}
+
+impl From<PlaylistFormat> for playlist::Format {
+	fn from(f: PlaylistFormat) -> Self {
+		match f {
+			PlaylistFormat::M3u => Self::M3U,
+			PlaylistFormat::Pls => Self::PLS,
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RenamePlaylistInput {
+	pub new_name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmartPlaylist {
+	pub name: String,
+	pub rules: Vec<smart_playlist::Rule>,
+}
+
+impl From<smart_playlist::SmartPlaylist> for SmartPlaylist {
+	fn from(p: smart_playlist::SmartPlaylist) -> Self {
+		Self {
+			name: p.name,
+			rules: p.rules,
+		}
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveSmartPlaylistInput {
+	pub rules: Vec<smart_playlist::Rule>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PodcastSubscription {
+	pub url: String,
+	#[serde(default)]
+	pub auto_download: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PodcastEpisodePosition {
+	pub position_seconds: u32,
+	#[serde(default)]
+	pub completed: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NewShare {
+	#[serde(default)]
+	pub path: Option<String>,
+	#[serde(default)]
+	pub playlist: Option<String>,
+	#[serde(default)]
+	pub expiration_time: Option<i64>, // seconds since epoch
+	#[serde(default)]
+	pub allow_download: bool,
+	#[serde(default)]
+	pub max_plays: Option<u32>,
+}
+
+impl From<NewShare> for share::NewShare {
+	fn from(s: NewShare) -> Self {
+		Self {
+			path: s.path.map(Into::into),
+			playlist: s.playlist,
+			expiration_time: s.expiration_time,
+			allow_download: s.allow_download,
+			max_plays: s.max_plays,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedContent {
+	pub name: String,
+	pub owner: String,
+	pub expiration_time: Option<i64>,
+	pub allow_download: bool,
+	pub songs: Vec<index::Song>,
+}
+
+impl From<share::SharedContent> for SharedContent {
+	fn from(c: share::SharedContent) -> Self {
+		Self {
+			name: c.name,
+			owner: c.owner,
+			expiration_time: c.share.expiration_time,
+			allow_download: c.share.allow_download,
+			songs: c.songs,
+		}
+	}
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct LastFMLink {
 	pub auth_token: String, // user::AuthToken emitted by Polaris, valid for LastFMLink scope
@@ -86,6 +776,11 @@ pub struct LastFMLink {
 	pub content: String,    // Payload to send back to client after successful link
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ListenBrainzLink {
+	pub token: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct LastFMLinkToken {
 	pub value: String,
@@ -110,6 +805,7 @@ impl From<user::User> for User {
 pub struct NewUser {
 	pub name: String,
 	pub password: String,
+	#[serde(default)]
 	pub admin: bool,
 }
 
@@ -118,7 +814,9 @@ impl From<NewUser> for user::NewUser {
 		Self {
 			name: u.name,
 			password: u.password,
+			password_hash: None,
 			admin: u.admin,
+			mounts: None,
 		}
 	}
 }
@@ -129,19 +827,95 @@ pub struct UserUpdate {
 	pub new_is_admin: Option<bool>,
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasswordChange {
+	pub old_password: Option<String>, // Required when changing your own password
+	pub new_password: String,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub struct DDNSConfig {
 	pub host: String,
 	pub username: String,
 	pub password: String,
+	#[serde(default)]
+	pub update_url: String,
+	#[serde(default)]
+	pub update_interval_seconds: Option<i32>,
+	#[serde(default)]
+	pub cloudflare: Option<CloudflareConfig>,
+	#[serde(default)]
+	pub ip_version: Option<IpVersion>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpVersion {
+	V4,
+	V6,
+	Both,
+}
+
+impl From<IpVersion> for ddns::IpVersion {
+	fn from(v: IpVersion) -> Self {
+		match v {
+			IpVersion::V4 => Self::V4,
+			IpVersion::V6 => Self::V6,
+			IpVersion::Both => Self::Both,
+		}
+	}
+}
+
+impl From<ddns::IpVersion> for IpVersion {
+	fn from(v: ddns::IpVersion) -> Self {
+		match v {
+			ddns::IpVersion::V4 => Self::V4,
+			ddns::IpVersion::V6 => Self::V6,
+			ddns::IpVersion::Both => Self::Both,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct CloudflareConfig {
+	pub api_token: String,
+	pub zone: String,
+	pub record: String,
+}
+
+impl From<CloudflareConfig> for ddns::cloudflare::Config {
+	fn from(c: CloudflareConfig) -> Self {
+		Self {
+			api_token: c.api_token,
+			zone: c.zone,
+			record: c.record,
+		}
+	}
+}
+
+impl From<ddns::cloudflare::Config> for CloudflareConfig {
+	fn from(c: ddns::cloudflare::Config) -> Self {
+		Self {
+			api_token: c.api_token,
+			zone: c.zone,
+			record: c.record,
+		}
+	}
 }
 
 impl From<DDNSConfig> for ddns::Config {
 	fn from(c: DDNSConfig) -> Self {
+		let default = ddns::Config::default();
 		Self {
 			host: c.host,
 			username: c.username,
 			password: c.password,
+			update_url: c.update_url,
+			update_interval_seconds: c
+				.update_interval_seconds
+				.unwrap_or(default.update_interval_seconds),
+			cloudflare: c.cloudflare.map(|c| c.into()),
+			ip_version: c.ip_version.map(|v| v.into()).unwrap_or_default(),
 		}
 	}
 }
@@ -152,6 +926,10 @@ impl From<ddns::Config> for DDNSConfig {
 			host: c.host,
 			username: c.username,
 			password: c.password,
+			update_url: c.update_url,
+			update_interval_seconds: Some(c.update_interval_seconds),
+			cloudflare: c.cloudflare.map(|c| c.into()),
+			ip_version: Some(c.ip_version.into()),
 		}
 	}
 }
@@ -160,6 +938,16 @@ impl From<ddns::Config> for DDNSConfig {
 pub struct MountDir {
 	pub source: String,
 	pub name: String,
+	#[serde(default)]
+	pub watch: bool,
+	#[serde(default)]
+	pub follow_symlinks: bool,
+	#[serde(default)]
+	pub content_type: vfs::ContentType,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub album_art_pattern: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub art_overlay: Option<String>,
 }
 
 impl From<MountDir> for vfs::MountDir {
@@ -167,6 +955,11 @@ impl From<MountDir> for vfs::MountDir {
 		Self {
 			name: m.name,
 			source: m.source,
+			watch: m.watch,
+			follow_symlinks: m.follow_symlinks,
+			content_type: m.content_type,
+			album_art_pattern: m.album_art_pattern,
+			art_overlay: m.art_overlay,
 		}
 	}
 }
@@ -176,6 +969,11 @@ impl From<vfs::MountDir> for MountDir {
 		Self {
 			name: m.name,
 			source: m.source,
+			watch: m.watch,
+			follow_symlinks: m.follow_symlinks,
+			content_type: m.content_type,
+			album_art_pattern: m.album_art_pattern,
+			art_overlay: m.art_overlay,
 		}
 	}
 }
@@ -197,6 +995,18 @@ impl From<Config> for config::Config {
 				.map(|v| v.into_iter().map(|m| m.into()).collect()),
 			users: s.users.map(|v| v.into_iter().map(|u| u.into()).collect()),
 			ydns: s.ydns.map(|c| c.into()),
+			ldap: None,
+			tls: None,
+			proxy_auth: None,
+			jukebox: None,
+			dlna: None,
+			mpd: None,
+			radio: None,
+			artist_info: None,
+			url_base: None,
+			logging: None,
+			shutdown_grace_period: None,
+			server: None,
 		}
 	}
 }
@@ -205,6 +1015,27 @@ impl From<Config> for config::Config {
 pub struct NewSettings {
 	pub album_art_pattern: Option<String>,
 	pub reindex_every_n_seconds: Option<i32>,
+	pub auth_token_lifetime_seconds: Option<i32>,
+	pub album_art_priority: Option<AlbumArtPriority>,
+	pub index_num_threads: Option<i32>,
+	pub ignore_patterns: Option<Vec<String>>,
+	pub thumbnail_cache_size_mb: Option<i32>,
+	pub group_compilations: Option<bool>,
+	pub collation_locale: Option<String>,
+	pub search_accent_folding: Option<bool>,
+	pub loudness_target: Option<i32>,
+	pub metadata_lookup: Option<bool>,
+	pub acoustid_api_key: Option<String>,
+	pub missing_grace_period_days: Option<i32>,
+	pub max_files_per_second: Option<i32>,
+	pub pause_while_streaming: Option<bool>,
+	pub search_fuzzy: Option<bool>,
+	pub bandwidth_limit_kbps: Option<i32>,
+	pub user_bandwidth_limit_kbps: Option<i32>,
+	pub stream_bandwidth_limit_kbps: Option<i32>,
+	pub max_streams_per_user: Option<i32>,
+	pub pregenerate_thumbnails: Option<bool>,
+	pub transcode_cache_size_mb: Option<i32>,
 }
 
 impl From<NewSettings> for settings::NewSettings {
@@ -212,6 +1043,43 @@ impl From<NewSettings> for settings::NewSettings {
 		Self {
 			album_art_pattern: s.album_art_pattern,
 			reindex_every_n_seconds: s.reindex_every_n_seconds,
+			auth_token_lifetime_seconds: s.auth_token_lifetime_seconds,
+			album_art_priority: s.album_art_priority.map(|p| p.into()),
+			index_num_threads: s.index_num_threads,
+			ignore_patterns: s.ignore_patterns,
+			thumbnail_cache_size_mb: s.thumbnail_cache_size_mb,
+			group_compilations: s.group_compilations,
+			collation_locale: s.collation_locale,
+			search_accent_folding: s.search_accent_folding,
+			loudness_target: s.loudness_target,
+			metadata_lookup: s.metadata_lookup,
+			acoustid_api_key: s.acoustid_api_key,
+			missing_grace_period_days: s.missing_grace_period_days,
+			max_files_per_second: s.max_files_per_second,
+			pause_while_streaming: s.pause_while_streaming,
+			search_fuzzy: s.search_fuzzy,
+			bandwidth_limit_kbps: s.bandwidth_limit_kbps,
+			user_bandwidth_limit_kbps: s.user_bandwidth_limit_kbps,
+			stream_bandwidth_limit_kbps: s.stream_bandwidth_limit_kbps,
+			max_streams_per_user: s.max_streams_per_user,
+			pregenerate_thumbnails: s.pregenerate_thumbnails,
+			transcode_cache_size_mb: s.transcode_cache_size_mb,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumArtPriority {
+	Embedded,
+	Folder,
+}
+
+impl From<AlbumArtPriority> for settings::AlbumArtPriority {
+	fn from(p: AlbumArtPriority) -> Self {
+		match p {
+			AlbumArtPriority::Embedded => Self::Embedded,
+			AlbumArtPriority::Folder => Self::Folder,
 		}
 	}
 }
@@ -220,16 +1088,143 @@ impl From<NewSettings> for settings::NewSettings {
 pub struct Settings {
 	pub album_art_pattern: String,
 	pub reindex_every_n_seconds: i32,
+	pub auth_token_lifetime_seconds: i32,
+	pub album_art_priority: String,
+	pub index_num_threads: i32,
+	pub ignore_patterns: Vec<String>,
+	pub thumbnail_cache_size_mb: i32,
+	pub group_compilations: bool,
+	pub collation_locale: String,
+	pub search_accent_folding: bool,
+	pub loudness_target: i32,
+	pub metadata_lookup: bool,
+	pub acoustid_api_key: String,
+	pub missing_grace_period_days: i32,
+	pub max_files_per_second: i32,
+	pub pause_while_streaming: bool,
+	pub search_fuzzy: bool,
+	pub bandwidth_limit_kbps: i32,
+	pub user_bandwidth_limit_kbps: i32,
+	pub stream_bandwidth_limit_kbps: i32,
+	pub max_streams_per_user: i32,
+	pub pregenerate_thumbnails: bool,
+	pub transcode_cache_size_mb: i32,
 }
 
 impl From<settings::Settings> for Settings {
 	fn from(s: settings::Settings) -> Self {
+		let ignore_patterns = s.ignore_patterns();
 		Self {
 			album_art_pattern: s.index_album_art_pattern,
 			reindex_every_n_seconds: s.index_sleep_duration_seconds,
+			auth_token_lifetime_seconds: s.auth_token_lifetime_seconds,
+			album_art_priority: s.index_album_art_priority,
+			index_num_threads: s.index_num_threads,
+			ignore_patterns,
+			thumbnail_cache_size_mb: s.thumbnail_cache_size_mb,
+			group_compilations: s.index_group_compilations,
+			collation_locale: s.collation_locale,
+			search_accent_folding: s.search_accent_folding,
+			loudness_target: s.transcode_loudness_target,
+			metadata_lookup: s.index_metadata_lookup,
+			acoustid_api_key: s.acoustid_api_key,
+			missing_grace_period_days: s.index_missing_grace_period_days,
+			max_files_per_second: s.index_max_files_per_second,
+			pause_while_streaming: s.index_pause_while_streaming,
+			search_fuzzy: s.search_fuzzy,
+			bandwidth_limit_kbps: s.bandwidth_limit_kbps,
+			user_bandwidth_limit_kbps: s.bandwidth_user_limit_kbps,
+			stream_bandwidth_limit_kbps: s.bandwidth_stream_limit_kbps,
+			max_streams_per_user: s.max_streams_per_user,
+			pregenerate_thumbnails: s.index_pregenerate_thumbnails,
+			transcode_cache_size_mb: s.transcode_cache_size_mb,
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexStatus {
+	pub is_running: bool,
+	pub directories_processed: usize,
+	pub songs_processed: usize,
+	pub errors: usize,
+	pub eta_seconds: Option<u64>,
+	pub unavailable_mounts: Vec<String>,
+}
+
+impl From<index::Status> for IndexStatus {
+	fn from(s: index::Status) -> Self {
+		Self {
+			is_running: s.is_running,
+			directories_processed: s.directories_processed,
+			songs_processed: s.songs_processed,
+			errors: s.errors,
+			eta_seconds: s.eta_seconds,
+			unavailable_mounts: s.unavailable_mounts,
+		}
+	}
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum CollectionFile {
+	Directory(Directory),
+	Song(Song),
+}
+
+impl CollectionFile {
+	pub fn new(
+		file: index::CollectionFile,
+		favorites: &HashSet<String>,
+		ratings: &HashMap<String, u8>,
+	) -> Self {
+		match file {
+			index::CollectionFile::Directory(d) => Self::Directory(Directory::new(d, favorites)),
+			index::CollectionFile::Song(s) => Self::Song(Song::new(s, favorites, ratings)),
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Directory {
+	#[serde(flatten)]
+	pub directory: index::Directory,
+	pub is_favorite: bool,
+}
+
+impl Directory {
+	pub fn new(directory: index::Directory, favorites: &HashSet<String>) -> Self {
+		let is_favorite = favorites.contains(&directory.path);
+		Self {
+			directory,
+			is_favorite,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Song {
+	#[serde(flatten)]
+	pub song: index::Song,
+	pub is_favorite: bool,
+	pub rating: Option<u8>,
+}
+
+impl Song {
+	pub fn new(
+		song: index::Song,
+		favorites: &HashSet<String>,
+		ratings: &HashMap<String, u8>,
+	) -> Self {
+		let is_favorite = favorites.contains(&song.path);
+		let rating = ratings.get(&song.path).copied();
+		Self {
+			song,
+			is_favorite,
+			rating,
 		}
 	}
 }
 
-// TODO: Preferences, CollectionFile, Song and Directory should have dto types
+// TODO: Preferences should have a dto type
 // TODO Song dto type should skip `None` values when serializing, to lower payload sizes by a lot
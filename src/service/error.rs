@@ -1,8 +1,13 @@
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
-use crate::app::index::QueryError;
-use crate::app::{config, ddns, lastfm, playlist, settings, thumbnail, user, vfs};
+use crate::app::index::{EditError, QueryError};
+use crate::app::{
+	artist_info, audiobook, config, ddns, dlna, download, duplicate, favorite, fingerprint,
+	history, jukebox, lastfm, listenbrainz, peaks, playlist, podcast, queue, radio, rating,
+	session, settings, share, smart_playlist, sync, throttle, thumbnail, transcode, user, vfs,
+};
 use crate::db;
 
 #[derive(Error, Debug)]
@@ -11,16 +16,44 @@ pub enum APIError {
 	AuthorizationTokenEncoding,
 	#[error("Administrator permission is required")]
 	AdminPermissionRequired,
+	#[error("An API key with this name already exists")]
+	ApiKeyAlreadyExists,
+	#[error("API key not found")]
+	ApiKeyNotFound,
+	#[error("No biography was found for this artist")]
+	ArtistBiographyNotFound,
+	#[error("No image was found for this artist")]
+	ArtistImageNotFound,
+	#[error("No API key is configured to look up artists")]
+	ArtistInfoNotConfigured,
+	#[error("Could not reach artist information service")]
+	ArtistInfoRequestFailed,
+	#[error("Artwork must be a jpeg or png image")]
+	ArtworkFormatUnsupported,
+	#[error("Archive is too large")]
+	ArchiveTooLarge,
 	#[error("Audio file could not be opened")]
 	AudioFileIOError,
 	#[error("Authentication is required")]
 	AuthenticationRequired,
 	#[error("Could not encode Branca token")]
 	BrancaTokenEncoding,
+	#[error("Could not read chapters from `{0}`:\n\n{1}")]
+	ChaptersReading(PathBuf, String),
+	#[error("Client not found")]
+	ClientNotFound,
 	#[error("Database error:\n\n{0}")]
 	Database(diesel::result::Error),
 	#[error("DDNS update query failed with HTTP status {0}")]
 	DdnsUpdateQueryFailed(u16),
+	#[error("DLNA is only available to devices on the local network")]
+	DLNAAccessForbidden,
+	#[error("Invalid DLNA request")]
+	DLNAInvalidRequest,
+	#[error("DLNA is not enabled")]
+	DLNANotEnabled,
+	#[error("Cannot use empty API key name")]
+	EmptyApiKeyName,
 	#[error("Cannot delete your own account")]
 	DeletingOwnAccount,
 	#[error("EmbeddedArtworkNotFound")]
@@ -29,8 +62,48 @@ pub enum APIError {
 	EmptyUsername,
 	#[error("EmptyPassword")]
 	EmptyPassword,
+	#[error("Radio station name cannot be empty")]
+	EmptyRadioStationName,
+	#[error("Tags of this file cannot be edited")]
+	EditFormatUnsupported,
+	#[error("Favorite not found")]
+	FavoriteNotFound,
+	#[error("Could not fingerprint `{0}`:\n\n{1}")]
+	Fingerprint(PathBuf, String),
+	#[error("Audio fingerprints are unavailable because fpcalc could not be run")]
+	FingerprintUnavailable,
+	#[error("Could not write tags to `{0}`:\n\n{1}")]
+	EditTags(PathBuf, String),
+	#[error("Index ignore pattern `{0}` is not a valid regex")]
+	InvalidIndexIgnorePattern(String),
+	#[error("Password hash does not have a supported format")]
+	InvalidPasswordHash,
+	#[error("Podcast feeds must be http or https URLs")]
+	InvalidPodcastUrl,
+	#[error("Radio streams must be http or https URLs")]
+	InvalidRadioStreamUrl,
+	#[error(
+		"Ratings must be between {} and {}",
+		rating::MIN_RATING,
+		rating::MAX_RATING
+	)]
+	InvalidRating,
+	#[error("Invalid sync token")]
+	InvalidSyncToken,
+	#[error("Shares must target either a path or a playlist")]
+	InvalidShareTarget,
+	#[error("Jukebox volume must be between 0 and {}", jukebox::MAX_VOLUME)]
+	InvalidVolume,
+	#[error("Invalid smart playlist rule:\n\n{0}")]
+	InvalidSmartPlaylistRule(String),
 	#[error("Incorrect Credentials")]
 	IncorrectCredentials,
+	#[error("Jukebox is not enabled")]
+	JukeboxNotEnabled,
+	#[error("Jukebox cannot play an empty queue")]
+	JukeboxQueueEmpty,
+	#[error("Jukebox is unavailable because ffmpeg could not be run")]
+	JukeboxUnavailable,
 	#[error("No last.fm account has been linked")]
 	LastFMAccountNotLinked,
 	#[error("Could not decode content as base64 after linking last.fm account")]
@@ -43,20 +116,74 @@ pub enum APIError {
 	LastFMScrobble(rustfm_scrobble::ScrobblerError),
 	#[error("Could authenticate with last.fm:\n\n{0}")]
 	LastFMScrobblerAuthentication(rustfm_scrobble::ScrobblerError),
+	#[error("No ListenBrainz account has been linked")]
+	ListenBrainzAccountNotLinked,
+	#[error("ListenBrainz rejected the user token")]
+	ListenBrainzInvalidToken,
+	#[error("ListenBrainz request failed")]
+	ListenBrainzRequestFailed,
 	#[error("Internal server error")]
 	Internal,
+	#[error("Song has no lyrics")]
+	LyricsNotFound,
+	#[error("Could not read lyrics from `{0}`:\n\n{1}")]
+	LyricsReading(PathBuf, String),
 	#[error("File I/O error for `{0}`:\n\n{1}")]
 	Io(PathBuf, std::io::Error),
+	#[error("Path is not in an audiobook mount")]
+	NotAnAudiobook,
 	#[error("Cannot remove your own admin privilege")]
 	OwnAdminPrivilegeRemoval,
 	#[error("Could not hash password")]
 	PasswordHashing,
+	#[error("Could not compute waveform of `{0}`:\n\n{1}")]
+	Peaks(PathBuf, String),
+	#[error("Waveforms are unavailable because ffmpeg could not be run")]
+	PeaksUnavailable,
 	#[error("Playlist not found")]
 	PlaylistNotFound,
+	#[error("A playlist with this name already exists")]
+	PlaylistAlreadyExists,
+	#[error("Already subscribed to this podcast")]
+	PodcastAlreadySubscribed,
+	#[error("Podcast episode not found")]
+	PodcastEpisodeNotFound,
+	#[error("Podcast episode is too large")]
+	PodcastEpisodeTooLarge,
+	#[error("Could not parse podcast feed")]
+	PodcastFeedInvalid,
+	#[error("Podcast not found")]
+	PodcastNotFound,
+	#[error("Could not reach podcast host")]
+	PodcastRequestFailed,
+	#[error("Play queue has no track at position {0}")]
+	QueueTrackNotFound(usize),
+	#[error("Radio proxy is not enabled")]
+	RadioProxyNotEnabled,
+	#[error("Radio station not found")]
+	RadioStationNotFound,
+	#[error("Could not reach radio stream")]
+	RadioStreamFailed,
+	#[error("Rating not found")]
+	RatingNotFound,
+	#[error("Session not found")]
+	SessionNotFound,
 	#[error("Settings error:\n\n{0}")]
 	Settings(settings::Error),
+	#[error("Share does not allow downloads")]
+	ShareDownloadNotAllowed,
+	#[error("Share has expired")]
+	ShareExpired,
+	#[error("Share not found")]
+	ShareNotFound,
+	#[error("Share has reached its maximum number of plays")]
+	SharePlayLimitReached,
+	#[error("Smart playlist not found")]
+	SmartPlaylistNotFound,
 	#[error("Song not found")]
 	SongMetadataNotFound,
+	#[error("Stream has no segment {0}")]
+	StreamSegmentNotFound(usize),
 	#[error("Could not decode thumbnail from flac file `{0}`:\n\n{1}")]
 	ThumbnailFlacDecoding(PathBuf, metaflac::Error),
 	#[error("Thumbnail file could not be opened")]
@@ -67,8 +194,18 @@ pub enum APIError {
 	ThumbnailImageDecoding(PathBuf, image::error::ImageError),
 	#[error("Could not decode thumbnail from mp4 file `{0}`:\n\n{1}")]
 	ThumbnailMp4Decoding(PathBuf, mp4ameta::Error),
+	#[error("Could not transcode `{0}`:\n\n{1}")]
+	Transcoding(PathBuf, String),
+	#[error("Transcoding is unavailable because ffmpeg could not be run")]
+	TranscodingUnavailable,
+	#[error("Unsupported transcoding bitrate: {0}kbps")]
+	UnsupportedTranscodingBitrate(u32),
 	#[error("Toml deserialization error:\n\n{0}")]
 	TomlDeserialization(toml::de::Error),
+	#[error("Too many login attempts, retry in {} seconds", .0.as_secs())]
+	TooManyLoginAttempts(Duration),
+	#[error("Users cannot play more than {0} streams at once")]
+	TooManyStreams(u32),
 	#[error("Unsupported thumbnail format: `{0}`")]
 	UnsupportedThumbnailFormat(&'static str),
 	#[error("User not found")]
@@ -82,37 +219,250 @@ impl From<config::Error> for APIError {
 		match error {
 			config::Error::Ddns(e) => e.into(),
 			config::Error::Io(p, e) => APIError::Io(p, e),
+			config::Error::Ldap(_) => APIError::Internal,
+			config::Error::MissingEnvironmentVariable(_) => APIError::Internal,
 			config::Error::Settings(e) => e.into(),
 			config::Error::Toml(e) => APIError::TomlDeserialization(e),
+			config::Error::TomlEdit(_) => APIError::Internal,
 			config::Error::User(e) => e.into(),
 			config::Error::Vfs(e) => e.into(),
 		}
 	}
 }
 
+impl From<favorite::Error> for APIError {
+	fn from(error: favorite::Error) -> APIError {
+		match error {
+			favorite::Error::Database(e) => APIError::Database(e),
+			favorite::Error::DatabaseConnection(e) => e.into(),
+			favorite::Error::FavoriteNotFound(_) => APIError::FavoriteNotFound,
+			favorite::Error::FileNotFound(_) => APIError::VFSPathNotFound,
+			favorite::Error::UserNotFound => APIError::UserNotFound,
+			favorite::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<history::Error> for APIError {
+	fn from(error: history::Error) -> APIError {
+		match error {
+			history::Error::Database(e) => APIError::Database(e),
+			history::Error::DatabaseConnection(e) => e.into(),
+			history::Error::SongNotFound(_) => APIError::SongMetadataNotFound,
+			history::Error::UserNotFound => APIError::UserNotFound,
+			history::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
 impl From<playlist::Error> for APIError {
 	fn from(error: playlist::Error) -> APIError {
 		match error {
 			playlist::Error::Database(e) => APIError::Database(e),
 			playlist::Error::DatabaseConnection(e) => e.into(),
 			playlist::Error::PlaylistNotFound => APIError::PlaylistNotFound,
+			playlist::Error::PlaylistAlreadyExists => APIError::PlaylistAlreadyExists,
 			playlist::Error::UserNotFound => APIError::UserNotFound,
 			playlist::Error::Vfs(e) => e.into(),
 		}
 	}
 }
 
+impl From<podcast::Error> for APIError {
+	fn from(error: podcast::Error) -> APIError {
+		match error {
+			podcast::Error::Database(e) => APIError::Database(e),
+			podcast::Error::DatabaseConnection(e) => e.into(),
+			podcast::Error::UserNotFound => APIError::UserNotFound,
+			podcast::Error::PodcastNotFound => APIError::PodcastNotFound,
+			podcast::Error::EpisodeNotFound => APIError::PodcastEpisodeNotFound,
+			podcast::Error::EpisodeTooLarge => APIError::PodcastEpisodeTooLarge,
+			podcast::Error::AlreadySubscribed => APIError::PodcastAlreadySubscribed,
+			podcast::Error::InvalidUrl => APIError::InvalidPodcastUrl,
+			podcast::Error::InvalidFeed => APIError::PodcastFeedInvalid,
+			podcast::Error::RequestFailed(_) => APIError::PodcastRequestFailed,
+			podcast::Error::RequestTransport => APIError::PodcastRequestFailed,
+			podcast::Error::Io(p, e) => APIError::Io(p, e),
+		}
+	}
+}
+
+impl From<queue::Error> for APIError {
+	fn from(error: queue::Error) -> APIError {
+		match error {
+			queue::Error::Database(e) => APIError::Database(e),
+			queue::Error::DatabaseConnection(e) => e.into(),
+			queue::Error::SongNotFound(_) => APIError::VFSPathNotFound,
+			queue::Error::TrackNotFound(i) => APIError::QueueTrackNotFound(i),
+			queue::Error::UserNotFound => APIError::UserNotFound,
+			queue::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<jukebox::Error> for APIError {
+	fn from(error: jukebox::Error) -> APIError {
+		match error {
+			jukebox::Error::NotEnabled => APIError::JukeboxNotEnabled,
+			jukebox::Error::EmptyQueue => APIError::JukeboxQueueEmpty,
+			jukebox::Error::InvalidVolume(_) => APIError::InvalidVolume,
+			jukebox::Error::FfmpegNotFound(_, _) => APIError::JukeboxUnavailable,
+			jukebox::Error::Queue(e) => e.into(),
+			jukebox::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<dlna::Error> for APIError {
+	fn from(error: dlna::Error) -> APIError {
+		match error {
+			dlna::Error::NotEnabled => APIError::DLNANotEnabled,
+			dlna::Error::ObjectNotFound(_) => APIError::VFSPathNotFound,
+			dlna::Error::InvalidAction(_) => APIError::DLNAInvalidRequest,
+			dlna::Error::InvalidArguments => APIError::DLNAInvalidRequest,
+			dlna::Error::Query(e) => e.into(),
+		}
+	}
+}
+
+impl From<rating::Error> for APIError {
+	fn from(error: rating::Error) -> APIError {
+		match error {
+			rating::Error::Database(e) => APIError::Database(e),
+			rating::Error::DatabaseConnection(e) => e.into(),
+			rating::Error::InvalidRating(_) => APIError::InvalidRating,
+			rating::Error::RatingNotFound(_) => APIError::RatingNotFound,
+			rating::Error::SongNotFound(_) => APIError::SongMetadataNotFound,
+			rating::Error::UserNotFound => APIError::UserNotFound,
+			rating::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<smart_playlist::Error> for APIError {
+	fn from(error: smart_playlist::Error) -> APIError {
+		match error {
+			smart_playlist::Error::Database(e) => APIError::Database(e),
+			smart_playlist::Error::DatabaseConnection(e) => e.into(),
+			smart_playlist::Error::InvalidRule(s) => APIError::InvalidSmartPlaylistRule(s),
+			smart_playlist::Error::RuleParsing(_) => APIError::Internal,
+			smart_playlist::Error::SmartPlaylistNotFound => APIError::SmartPlaylistNotFound,
+			smart_playlist::Error::UserNotFound => APIError::UserNotFound,
+			smart_playlist::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<audiobook::Error> for APIError {
+	fn from(error: audiobook::Error) -> APIError {
+		match error {
+			audiobook::Error::Database(e) => APIError::Database(e),
+			audiobook::Error::DatabaseConnection(e) => e.into(),
+			audiobook::Error::UserNotFound => APIError::UserNotFound,
+			audiobook::Error::NotAnAudiobook(_) => APIError::NotAnAudiobook,
+			audiobook::Error::Query(e) => e.into(),
+			audiobook::Error::Queue(e) => e.into(),
+			audiobook::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<sync::Error> for APIError {
+	fn from(error: sync::Error) -> APIError {
+		match error {
+			sync::Error::Database(e) => APIError::Database(e),
+			sync::Error::DatabaseConnection(e) => e.into(),
+			sync::Error::UserNotFound => APIError::UserNotFound,
+			sync::Error::InvalidToken => APIError::InvalidSyncToken,
+			sync::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<share::Error> for APIError {
+	fn from(error: share::Error) -> APIError {
+		match error {
+			share::Error::Database(e) => APIError::Database(e),
+			share::Error::DatabaseConnection(e) => e.into(),
+			share::Error::UserNotFound => APIError::UserNotFound,
+			share::Error::ShareNotFound => APIError::ShareNotFound,
+			share::Error::ShareExpired => APIError::ShareExpired,
+			share::Error::PlayLimitReached => APIError::SharePlayLimitReached,
+			share::Error::DownloadNotAllowed => APIError::ShareDownloadNotAllowed,
+			share::Error::InvalidTarget => APIError::InvalidShareTarget,
+			share::Error::NothingToShare(_) => APIError::VFSPathNotFound,
+			share::Error::TrackNotFound(_) => APIError::VFSPathNotFound,
+			share::Error::Download(e) => e.into(),
+			share::Error::Playlist(e) => e.into(),
+			share::Error::Query(e) => e.into(),
+			share::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<artist_info::Error> for APIError {
+	fn from(error: artist_info::Error) -> APIError {
+		match error {
+			artist_info::Error::BiographyNotFound => APIError::ArtistBiographyNotFound,
+			artist_info::Error::Database(e) => APIError::Database(e),
+			artist_info::Error::DatabaseConnection(e) => e.into(),
+			artist_info::Error::ImageNotFound => APIError::ArtistImageNotFound,
+			artist_info::Error::Io(p, e) => APIError::Io(p, e),
+			artist_info::Error::NotConfigured => APIError::ArtistInfoNotConfigured,
+			artist_info::Error::RequestFailed(_) => APIError::ArtistInfoRequestFailed,
+			artist_info::Error::RequestTransport => APIError::ArtistInfoRequestFailed,
+		}
+	}
+}
+
+impl From<radio::Error> for APIError {
+	fn from(error: radio::Error) -> APIError {
+		match error {
+			radio::Error::Database(e) => APIError::Database(e),
+			radio::Error::DatabaseConnection(e) => e.into(),
+			radio::Error::UserNotFound => APIError::UserNotFound,
+			radio::Error::StationNotFound => APIError::RadioStationNotFound,
+			radio::Error::EmptyName => APIError::EmptyRadioStationName,
+			radio::Error::InvalidUrl => APIError::InvalidRadioStreamUrl,
+			radio::Error::AdminPermissionRequired => APIError::AdminPermissionRequired,
+			radio::Error::ProxyNotEnabled => APIError::RadioProxyNotEnabled,
+			radio::Error::RequestFailed(_) => APIError::RadioStreamFailed,
+			radio::Error::RequestTransport => APIError::RadioStreamFailed,
+			radio::Error::User(e) => e.into(),
+		}
+	}
+}
+
 impl From<QueryError> for APIError {
 	fn from(error: QueryError) -> APIError {
 		match error {
 			QueryError::Database(e) => APIError::Database(e),
 			QueryError::DatabaseConnection(e) => e.into(),
+			QueryError::ChaptersReading(p, e) => APIError::ChaptersReading(p, e.to_string()),
+			QueryError::Io(p, e) => APIError::Io(p, e),
+			QueryError::LyricsNotFound(_) => APIError::LyricsNotFound,
+			QueryError::LyricsReading(p, e) => APIError::LyricsReading(p, e.to_string()),
 			QueryError::SongNotFound(_) => APIError::SongMetadataNotFound,
+			QueryError::ViewNotFound(_) => APIError::VFSPathNotFound,
 			QueryError::Vfs(e) => e.into(),
 		}
 	}
 }
 
+impl From<EditError> for APIError {
+	fn from(error: EditError) -> APIError {
+		match error {
+			EditError::ArtworkFormatUnsupported => APIError::ArtworkFormatUnsupported,
+			EditError::Database(e) => APIError::Database(e),
+			EditError::DatabaseConnection(e) => e.into(),
+			EditError::FormatUnsupported(_) => APIError::EditFormatUnsupported,
+			EditError::Io(p, e) => APIError::Io(p, e),
+			EditError::Metadata(p, e) => APIError::EditTags(p, e.to_string()),
+			EditError::SongNotFound(_) => APIError::SongMetadataNotFound,
+		}
+	}
+}
+
 impl From<settings::Error> for APIError {
 	fn from(error: settings::Error) -> APIError {
 		match error {
@@ -121,6 +471,8 @@ impl From<settings::Error> for APIError {
 			settings::Error::AuthenticationSecretInvalid => APIError::Settings(error),
 			settings::Error::MiscSettingsNotFound => APIError::Settings(error),
 			settings::Error::IndexAlbumArtPatternInvalid => APIError::Settings(error),
+			settings::Error::IndexAlbumArtPriorityInvalid => APIError::Settings(error),
+			settings::Error::IndexIgnorePatternInvalid(p) => APIError::InvalidIndexIgnorePattern(p),
 			settings::Error::Database(e) => APIError::Database(e),
 		}
 	}
@@ -129,18 +481,25 @@ impl From<settings::Error> for APIError {
 impl From<user::Error> for APIError {
 	fn from(error: user::Error) -> APIError {
 		match error {
+			user::Error::ApiKeyAlreadyExists => APIError::ApiKeyAlreadyExists,
+			user::Error::ApiKeyNotFound => APIError::ApiKeyNotFound,
 			user::Error::AuthorizationTokenEncoding => APIError::AuthorizationTokenEncoding,
 			user::Error::BrancaTokenEncoding => APIError::BrancaTokenEncoding,
+			user::Error::ClientNotFound => APIError::ClientNotFound,
 			user::Error::Database(e) => APIError::Database(e),
 			user::Error::DatabaseConnection(e) => e.into(),
+			user::Error::EmptyApiKeyName => APIError::EmptyApiKeyName,
 			user::Error::EmptyPassword => APIError::EmptyPassword,
 			user::Error::EmptyUsername => APIError::EmptyUsername,
 			user::Error::IncorrectAuthorizationScope => APIError::IncorrectCredentials,
 			user::Error::IncorrectPassword => APIError::IncorrectCredentials,
 			user::Error::IncorrectUsername => APIError::IncorrectCredentials,
 			user::Error::InvalidAuthToken => APIError::IncorrectCredentials,
+			user::Error::InvalidPasswordHash => APIError::InvalidPasswordHash,
 			user::Error::MissingLastFMSessionKey => APIError::IncorrectCredentials,
+			user::Error::MissingListenBrainzToken => APIError::ListenBrainzAccountNotLinked,
 			user::Error::PasswordHashing => APIError::PasswordHashing,
+			user::Error::Settings(e) => e.into(),
 		}
 	}
 }
@@ -163,6 +522,9 @@ impl From<ddns::Error> for APIError {
 			ddns::Error::DatabaseConnection(e) => e.into(),
 			ddns::Error::UpdateQueryFailed(s) => APIError::DdnsUpdateQueryFailed(s),
 			ddns::Error::UpdateQueryTransport => APIError::DdnsUpdateQueryFailed(0),
+			ddns::Error::UpdateRejected(_) => APIError::DdnsUpdateQueryFailed(0),
+			ddns::Error::PublicIpLookup => APIError::DdnsUpdateQueryFailed(0),
+			ddns::Error::UnknownIpVersion(_) => APIError::Internal,
 		}
 	}
 }
@@ -174,6 +536,21 @@ impl From<db::Error> for APIError {
 			db::Error::ConnectionPool => APIError::Internal,
 			db::Error::Io(p, e) => APIError::Io(p, e),
 			db::Error::Migration => APIError::Internal,
+			db::Error::Backup(_) => APIError::Internal,
+			db::Error::Restore(_) => APIError::Internal,
+			db::Error::Checkpoint => APIError::Internal,
+		}
+	}
+}
+
+impl From<listenbrainz::Error> for APIError {
+	fn from(error: listenbrainz::Error) -> APIError {
+		match error {
+			listenbrainz::Error::InvalidToken => APIError::ListenBrainzInvalidToken,
+			listenbrainz::Error::RequestFailed(_) => APIError::ListenBrainzRequestFailed,
+			listenbrainz::Error::RequestTransport => APIError::ListenBrainzRequestFailed,
+			listenbrainz::Error::Query(e) => e.into(),
+			listenbrainz::Error::User(e) => e.into(),
 		}
 	}
 }
@@ -190,6 +567,77 @@ impl From<lastfm::Error> for APIError {
 	}
 }
 
+impl From<session::Error> for APIError {
+	fn from(error: session::Error) -> APIError {
+		match error {
+			session::Error::SessionNotFound => APIError::SessionNotFound,
+			session::Error::Settings(e) => e.into(),
+			session::Error::TooManyStreams(n) => APIError::TooManyStreams(n),
+		}
+	}
+}
+
+impl From<throttle::Error> for APIError {
+	fn from(error: throttle::Error) -> APIError {
+		match error {
+			throttle::Error::TooManyAttempts(d) => APIError::TooManyLoginAttempts(d),
+		}
+	}
+}
+
+impl From<transcode::Error> for APIError {
+	fn from(error: transcode::Error) -> APIError {
+		match error {
+			transcode::Error::FfmpegNotFound(_, _) => APIError::TranscodingUnavailable,
+			transcode::Error::Ffmpeg(p, e) => APIError::Transcoding(p, e),
+			transcode::Error::InvalidBitrate(b) => APIError::UnsupportedTranscodingBitrate(b),
+			transcode::Error::Io(p, e) => APIError::Io(p, e),
+			transcode::Error::StreamSegmentNotFound(i) => APIError::StreamSegmentNotFound(i),
+		}
+	}
+}
+
+impl From<duplicate::Error> for APIError {
+	fn from(error: duplicate::Error) -> APIError {
+		match error {
+			duplicate::Error::Database(e) => APIError::Database(e),
+			duplicate::Error::DatabaseConnection(e) => e.into(),
+			duplicate::Error::Fingerprint(e) => e.into(),
+			duplicate::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
+impl From<fingerprint::Error> for APIError {
+	fn from(error: fingerprint::Error) -> APIError {
+		match error {
+			fingerprint::Error::FpcalcNotFound(_, _) => APIError::FingerprintUnavailable,
+			fingerprint::Error::Fpcalc(p, e) => APIError::Fingerprint(p, e),
+		}
+	}
+}
+
+impl From<peaks::Error> for APIError {
+	fn from(error: peaks::Error) -> APIError {
+		match error {
+			peaks::Error::FfmpegNotFound(_, _) => APIError::PeaksUnavailable,
+			peaks::Error::Ffmpeg(p, e) => APIError::Peaks(p, e),
+			peaks::Error::Io(p, e) => APIError::Io(p, e),
+		}
+	}
+}
+
+impl From<download::Error> for APIError {
+	fn from(error: download::Error) -> APIError {
+		match error {
+			download::Error::ArchiveTooLarge(_) => APIError::ArchiveTooLarge,
+			download::Error::Io(p, e) => APIError::Io(p, e),
+			download::Error::NotADirectory(_) => APIError::VFSPathNotFound,
+			download::Error::Vfs(e) => e.into(),
+		}
+	}
+}
+
 impl From<thumbnail::Error> for APIError {
 	fn from(error: thumbnail::Error) -> APIError {
 		match error {
@@ -8,14 +8,32 @@ pub mod constants;
 pub mod protocol;
 
 mod admin;
+mod artist_info;
+mod audiobook;
 mod auth;
 mod collection;
 mod ddns;
+mod dlna;
+mod events;
+mod favorite;
+mod health;
+mod history;
+mod jukebox;
 mod lastfm;
+mod listenbrainz;
 mod media;
 mod playlist;
+mod podcast;
+mod queue;
+mod radio;
+mod rating;
+mod scrobble;
+mod session;
 mod settings;
+mod share;
+mod smart_playlist;
 mod swagger;
+mod sync;
 mod user;
 mod web;
 
@@ -25,6 +43,43 @@ use crate::service::test::constants::*;
 
 pub use crate::service::actix::test::ServiceType;
 
+// Both artists of the test collection in separate mounts, with the regular user only allowed to
+// see `Tobokegao`
+pub fn restricted_mounts_config() -> String {
+	// Relative sources would be resolved against the test directory holding the config file
+	let collection_dir = std::env::current_dir()
+		.unwrap()
+		.join("test-data")
+		.join("small-collection");
+	format!(
+		r#"
+		[[users]]
+		name = "{}"
+		password = "{}"
+		admin = true
+
+		[[users]]
+		name = "{}"
+		password = "{}"
+		mounts = ["Tobokegao"]
+
+		[[mount_dirs]]
+		name = "Khemmis"
+		source = '{}'
+
+		[[mount_dirs]]
+		name = "Tobokegao"
+		source = '{}'
+		"#,
+		TEST_USERNAME_ADMIN,
+		TEST_PASSWORD_ADMIN,
+		TEST_USERNAME,
+		TEST_PASSWORD,
+		collection_dir.join("Khemmis").display(),
+		collection_dir.join("Tobokegao").display()
+	)
+}
+
 pub trait TestService {
 	fn new(test_name: &str) -> Self;
 	fn fetch<T: Serialize + Clone + 'static>(&mut self, request: &Request<T>) -> Response<()>;
@@ -54,6 +109,11 @@ pub trait TestService {
 			mount_dirs: Some(vec![dto::MountDir {
 				name: TEST_MOUNT_NAME.into(),
 				source: TEST_MOUNT_SOURCE.into(),
+				watch: false,
+				follow_symlinks: false,
+				content_type: Default::default(),
+				album_art_pattern: None,
+				art_overlay: None,
 			}]),
 			..Default::default()
 		};
@@ -1,8 +1,10 @@
 use http::StatusCode;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::app::index;
 use crate::service::dto;
-use crate::service::test::{protocol, ServiceType, TestService};
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
 use crate::test_name;
 
 #[test]
@@ -79,3 +81,186 @@ fn trigger_index_requires_admin() {
 	let response = service.fetch(&request);
 	assert_eq!(response.status(), StatusCode::FORBIDDEN);
 }
+
+#[test]
+fn index_status_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	let request = protocol::index_status();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn index_status_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let request = protocol::index_status();
+	loop {
+		let response = service.fetch_json::<_, dto::IndexStatus>(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+		let status = response.into_body();
+		if !status.is_running {
+			assert_eq!(status.directories_processed, 6);
+			assert_eq!(status.songs_processed, 13);
+			assert_eq!(status.errors, 0);
+			break;
+		}
+		std::thread::sleep(Duration::from_millis(100));
+	}
+}
+
+#[test]
+fn duplicates_requires_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+	let request = protocol::duplicates();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn duplicates_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let request = protocol::duplicates();
+
+	// Fingerprints rely on fpcalc, which may not be installed on the test machine
+	if service.fetch(&request).status() == StatusCode::NOT_IMPLEMENTED {
+		return;
+	}
+
+	let response = service.fetch_json::<_, Vec<Vec<dto::Duplicate>>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	// Songs of each test album share the same audio and only differ by their tags
+	let mut group_sizes = response.body().iter().map(|g| g.len()).collect::<Vec<_>>();
+	group_sizes.sort();
+	assert_eq!(group_sizes, vec![5, 8]);
+}
+
+#[test]
+fn backup_requires_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+	let request = protocol::backup();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn backup_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+
+	let request = protocol::backup();
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.body().starts_with(b"SQLite format 3\0"));
+}
+
+#[test]
+fn missing_songs_requires_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::missing_songs();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let request = protocol::purge_missing_songs();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn missing_songs_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let request = protocol::missing_songs();
+	let response = service.fetch_json::<_, Vec<dto::MissingSong>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.body().is_empty());
+
+	let request = protocol::purge_missing_songs();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn put_tags_requires_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let edit = dto::SongEdit {
+		title: Some("Candlelight (Remastered)".to_owned()),
+		..Default::default()
+	};
+	let request = protocol::put_tags(&path, edit);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn put_tags_unknown_song_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "99 - Missing.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::put_tags(&path, dto::SongEdit::default());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn put_artwork_requires_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::put_artwork(&path, vec![]);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn put_artwork_rejects_non_images() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::put_artwork(&path, b"not an image".to_vec());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
@@ -0,0 +1,33 @@
+use http::StatusCode;
+
+use crate::service::test::{protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn artist_info_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let request = protocol::artist_biography("Khemmis");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::artist_image("Khemmis");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn artist_info_requires_api_keys() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::artist_biography("Tobokegao");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+
+	let request = protocol::artist_image("Tobokegao");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+}
@@ -0,0 +1,109 @@
+use http::StatusCode;
+use std::path::{Path, PathBuf};
+
+use crate::app::{index, vfs};
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn book_path() -> PathBuf {
+	[TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect()
+}
+
+fn setup_audiobook_mount(service: &mut ServiceType) {
+	service.complete_initial_setup();
+	service.login_admin();
+	let configuration = dto::Config {
+		mount_dirs: Some(vec![dto::MountDir {
+			name: TEST_MOUNT_NAME.into(),
+			source: TEST_MOUNT_SOURCE.into(),
+			watch: false,
+			follow_symlinks: false,
+			content_type: vfs::ContentType::Audiobook,
+			album_art_pattern: None,
+			art_overlay: None,
+		}]),
+		..Default::default()
+	};
+	let response = service.fetch(&protocol::apply_config(configuration));
+	assert_eq!(response.status(), StatusCode::OK);
+	service.index();
+	service.login();
+}
+
+#[test]
+fn audiobook_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::audiobook(&book_path());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn music_is_not_an_audiobook() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::audiobook(&book_path());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+	let request = protocol::set_audiobook_position(&book_path(), 10);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn audiobook_position_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	setup_audiobook_mount(&mut service);
+
+	let request = protocol::set_audiobook_position(&book_path(), 42);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::audiobook(&book_path());
+	let response = service.fetch_json::<_, dto::Audiobook>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let audiobook = response.body();
+	assert_eq!(Path::new(&audiobook.path), book_path());
+	assert_eq!(audiobook.position_seconds, 42);
+	assert!(audiobook.chapters.is_empty());
+}
+
+#[test]
+fn queue_position_is_bookmarked() {
+	let mut service = ServiceType::new(&test_name!());
+	setup_audiobook_mount(&mut service);
+
+	let request = protocol::append_to_queue(&[&book_path()]);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let request = protocol::set_queue_current_track(Some(0), 75);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::audiobook_positions();
+	let response = service.fetch_json::<_, Vec<dto::AudiobookPosition>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let positions = response.body();
+	assert_eq!(positions.len(), 1);
+	assert_eq!(Path::new(&positions[0].path), book_path());
+	assert_eq!(positions[0].position_seconds, 75);
+}
+
+#[test]
+fn random_excludes_audiobooks() {
+	let mut service = ServiceType::new(&test_name!());
+	setup_audiobook_mount(&mut service);
+
+	let request = protocol::random();
+	let response = service.fetch_json::<_, Vec<index::Directory>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.body().is_empty());
+}
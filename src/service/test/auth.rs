@@ -1,5 +1,5 @@
 use headers::{self, HeaderMapExt};
-use http::StatusCode;
+use http::{header, StatusCode};
 
 use crate::service::dto;
 use crate::service::test::{constants::*, protocol, ServiceType, TestService};
@@ -25,6 +25,51 @@ fn login_rejects_bad_password() {
 	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[test]
+fn login_locks_out_account_after_repeated_failures() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	for _ in 0..5 {
+		let request = protocol::login(TEST_USERNAME, "garbage");
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	}
+
+	let request = protocol::login(TEST_USERNAME, TEST_PASSWORD);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+	assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+	let request = protocol::login(TEST_USERNAME_ADMIN, TEST_PASSWORD_ADMIN);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn login_throttling_ignores_forwarded_addresses_from_untrusted_peers() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	for i in 0..10 {
+		let mut request = protocol::login(&format!("garbage_{i}"), "garbage");
+		let forwarded_for = format!("203.0.113.{i}").parse().unwrap();
+		request
+			.headers_mut()
+			.insert("X-Forwarded-For", forwarded_for);
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	}
+
+	let mut request = protocol::login(TEST_USERNAME, TEST_PASSWORD);
+	let forwarded_for = "203.0.113.100".parse().unwrap();
+	request
+		.headers_mut()
+		.insert("X-Forwarded-For", forwarded_for);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
 #[test]
 fn login_golden_path() {
 	let mut service = ServiceType::new(&test_name!());
@@ -110,3 +155,162 @@ fn authentication_via_query_param_golden_path() {
 	let response = service.fetch(&request);
 	assert_eq!(response.status(), StatusCode::OK);
 }
+
+#[test]
+fn login_sets_auth_cookie() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let request = protocol::login(TEST_USERNAME, TEST_PASSWORD);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let cookie = response.headers().get(header::SET_COOKIE).unwrap();
+	let cookie = cookie.to_str().unwrap();
+	assert!(cookie.starts_with("auth_token="));
+	assert!(cookie.contains("HttpOnly"));
+	assert!(!cookie.contains("Secure"));
+}
+
+#[test]
+fn login_over_https_sets_secure_auth_cookie() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let mut request = protocol::login(TEST_USERNAME, TEST_PASSWORD);
+	request
+		.headers_mut()
+		.insert("x-forwarded-proto", "https".parse().unwrap());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let cookie = response.headers().get(header::SET_COOKIE).unwrap();
+	let cookie = cookie.to_str().unwrap();
+	assert!(cookie.contains("Secure"));
+}
+
+#[test]
+fn authentication_via_cookie_rejects_bad_token() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let mut request = protocol::random();
+	request
+		.headers_mut()
+		.insert(header::COOKIE, "auth_token=garbage".parse().unwrap());
+
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn authentication_via_cookie_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let authorization = {
+		let request = protocol::login(TEST_USERNAME, TEST_PASSWORD);
+		let response = service.fetch_json::<_, dto::Authorization>(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+		response.into_body()
+	};
+
+	service.logout();
+
+	let mut request = protocol::random();
+	let cookie = format!("auth_token={}", authorization.token);
+	request
+		.headers_mut()
+		.insert(header::COOKIE, cookie.parse().unwrap());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn logout_clears_auth_cookie() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let request = protocol::logout();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let cookie = response.headers().get(header::SET_COOKIE).unwrap();
+	let cookie = cookie.to_str().unwrap();
+	assert!(cookie.starts_with("auth_token=;"));
+	assert!(cookie.contains("Max-Age=0"));
+}
+
+#[test]
+fn authentication_via_trusted_proxy_header() {
+	let config = r#"
+		[proxy_auth]
+		trusted_proxies = ["127.0.0.1", "::1"]
+	"#;
+	let mut service = ServiceType::new_with_config(&test_name!(), config);
+
+	let mut request = protocol::random();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	request
+		.headers_mut()
+		.insert("X-Remote-User", "proxy_user".parse().unwrap());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let mut request = protocol::login("proxy_user", "not_checked");
+	request
+		.headers_mut()
+		.insert("X-Remote-User", "proxy_user".parse().unwrap());
+	let response = service.fetch_json::<_, dto::Authorization>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body().username, "proxy_user");
+	assert!(!response.body().is_admin);
+}
+
+#[test]
+fn applying_config_keeps_users_authenticated_by_proxy() {
+	let config = r#"
+		[proxy_auth]
+		trusted_proxies = ["127.0.0.1", "::1"]
+	"#;
+	let mut service = ServiceType::new_with_config(&test_name!(), config);
+	service.complete_initial_setup();
+
+	let playlist = dto::SavePlaylistInput { tracks: Vec::new() };
+	let mut request = protocol::save_playlist(TEST_PLAYLIST_NAME, playlist);
+	request
+		.headers_mut()
+		.insert("X-Remote-User", "proxy_user".parse().unwrap());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	service.login_admin();
+	service.complete_initial_setup();
+	service.logout();
+
+	let mut request = protocol::read_playlist(TEST_PLAYLIST_NAME);
+	request
+		.headers_mut()
+		.insert("X-Remote-User", "proxy_user".parse().unwrap());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn authentication_via_untrusted_proxy_header_is_rejected() {
+	let config = r#"
+		[proxy_auth]
+		header = "Remote-User"
+		trusted_proxies = ["10.0.0.0/8"]
+	"#;
+	let mut service = ServiceType::new_with_config(&test_name!(), config);
+
+	let mut request = protocol::random();
+	request
+		.headers_mut()
+		.insert("Remote-User", "proxy_user".parse().unwrap());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -1,7 +1,8 @@
-use http::StatusCode;
+use http::{header, HeaderValue, StatusCode};
 use std::path::{Path, PathBuf};
 
 use crate::app::index;
+use crate::service::dto;
 use crate::service::test::{add_trailing_slash, constants::*, protocol, ServiceType, TestService};
 use crate::test_name;
 
@@ -28,6 +29,37 @@ fn browse_root() {
 	assert_eq!(entries.len(), 1);
 }
 
+#[test]
+fn browse_supports_etag() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::browse(Path::new(TEST_MOUNT_NAME));
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+	let mut request = protocol::browse(Path::new(TEST_MOUNT_NAME));
+	request
+		.headers_mut()
+		.append(header::IF_NONE_MATCH, etag.clone());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis"].iter().collect();
+	let request = protocol::add_favorite(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let mut request = protocol::browse(Path::new(TEST_MOUNT_NAME));
+	request.headers_mut().append(header::IF_NONE_MATCH, etag);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
 #[test]
 fn browse_directory() {
 	let mut service = ServiceType::new(&test_name!());
@@ -56,6 +88,44 @@ fn browse_bad_directory() {
 	assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[test]
+fn browse_view_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::browse_view("Artists");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn browse_view_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::browse_view("Artists");
+	let response = service.fetch_json::<_, Vec<index::CollectionFile>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body().len(), 2);
+
+	let request = protocol::browse_view("Artists/Khemmis/Hunted");
+	let response = service.fetch_json::<_, Vec<index::CollectionFile>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body().len(), 5);
+}
+
+#[test]
+fn browse_bad_view() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::browse_view("Labels");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[test]
 fn flatten_requires_auth() {
 	let mut service = ServiceType::new(&test_name!());
@@ -77,6 +147,8 @@ fn flatten_root() {
 	assert_eq!(response.status(), StatusCode::OK);
 	let entries = response.body();
 	assert_eq!(entries.len(), 13);
+	// Lets clients tell whether their cached copy of a song is still current
+	assert!(entries.iter().all(|s| s.content_hash.is_some()));
 }
 
 #[test]
@@ -94,6 +166,51 @@ fn flatten_directory() {
 	assert_eq!(entries.len(), 13);
 }
 
+#[test]
+fn flatten_one_page() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::flatten_page(Path::new(TEST_MOUNT_NAME), 10, 5);
+	let response = service.fetch_json::<_, Vec<index::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.headers().get("x-total-count").unwrap(), "13");
+	let entries = response.body();
+	assert_eq!(entries.len(), 3);
+}
+
+#[test]
+fn flatten_encodes_content() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::flatten(&PathBuf::new());
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.headers().get(header::CONTENT_ENCODING), None);
+	let identity_size = response.body().len();
+
+	for encoding in ["gzip", "deflate"] {
+		let mut request = protocol::flatten(&PathBuf::new());
+		request
+			.headers_mut()
+			.append(header::ACCEPT_ENCODING, HeaderValue::from_static(encoding));
+		let response = service.fetch_bytes(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.headers().get(header::CONTENT_ENCODING).unwrap(),
+			encoding
+		);
+		assert!(response.body().len() < identity_size);
+	}
+}
+
 #[test]
 fn flatten_bad_directory() {
 	let mut service = ServiceType::new(&test_name!());
@@ -153,6 +270,25 @@ fn recent_requires_auth() {
 	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[test]
+fn recent_supports_etag() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::recent();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+	let mut request = protocol::recent();
+	request.headers_mut().append(header::IF_NONE_MATCH, etag);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
 #[test]
 fn recent_golden_path() {
 	let mut service = ServiceType::new(&test_name!());
@@ -203,6 +339,46 @@ fn search_without_query() {
 	assert_eq!(response.status(), StatusCode::OK);
 }
 
+#[test]
+fn search_suggestions_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::search_suggestions("k");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn search_suggestions_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::search_suggestions("khem");
+	let response = service.fetch_json::<_, Vec<dto::Suggestion>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.body()[0],
+		dto::Suggestion {
+			kind: dto::SuggestionKind::Artist,
+			text: "Khemmis".to_owned(),
+			artist: None,
+		}
+	);
+
+	let request = protocol::search_suggestions("door");
+	let response = service.fetch_json::<_, Vec<dto::Suggestion>>(&request);
+	assert_eq!(
+		response.body(),
+		&vec![dto::Suggestion {
+			kind: dto::SuggestionKind::Track,
+			text: "Beyond The Door".to_owned(),
+			artist: Some("Khemmis".to_owned()),
+		}]
+	);
+}
+
 #[test]
 fn search_with_query() {
 	let mut service = ServiceType::new(&test_name!());
@@ -36,6 +36,10 @@ fn put_ddns_config_requires_admin() {
 		host: "test".to_owned(),
 		username: "test".to_owned(),
 		password: "test".to_owned(),
+		update_url: String::new(),
+		update_interval_seconds: None,
+		cloudflare: None,
+		ip_version: None,
 	});
 	service.complete_initial_setup();
 
@@ -53,11 +57,25 @@ fn put_ddns_config_golden_path() {
 	service.complete_initial_setup();
 	service.login_admin();
 
-	let request = protocol::put_ddns_config(dto::DDNSConfig {
+	let ddns_config = dto::DDNSConfig {
 		host: "test".to_owned(),
 		username: "test".to_owned(),
 		password: "test".to_owned(),
-	});
+		update_url: "https://www.duckdns.org/update?domains={host}&token=abc&ip={ip}".to_owned(),
+		update_interval_seconds: Some(600),
+		cloudflare: Some(dto::CloudflareConfig {
+			api_token: "t0k3n".to_owned(),
+			zone: "example.com".to_owned(),
+			record: "music.example.com".to_owned(),
+		}),
+		ip_version: Some(dto::IpVersion::V6),
+	};
+	let request = protocol::put_ddns_config(ddns_config.clone());
 	let response = service.fetch(&request);
 	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::get_ddns_config();
+	let response = service.fetch_json::<_, dto::DDNSConfig>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.into_body(), ddns_config);
 }
@@ -0,0 +1,114 @@
+use http::StatusCode;
+use std::path::PathBuf;
+
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn dlna_config() -> String {
+	format!("[dlna]\nuser = \"{TEST_USERNAME}\"")
+}
+
+#[test]
+fn dlna_is_disabled_by_default() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::dlna_description();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+}
+
+#[test]
+fn dlna_describes_media_server() {
+	let mut service = ServiceType::new_with_config(&test_name!(), &dlna_config());
+	let request = protocol::dlna_description();
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let description = String::from_utf8_lossy(response.body());
+	assert!(description.contains("urn:schemas-upnp-org:device:MediaServer:1"));
+	assert!(description.contains("/dlna/control/content_directory"));
+}
+
+#[test]
+fn dlna_refuses_requests_forwarded_by_untrusted_proxies() {
+	let mut service = ServiceType::new_with_config(&test_name!(), &dlna_config());
+	let mut request = protocol::dlna_description();
+	let forwarded_for = "192.168.1.5".parse().unwrap();
+	request
+		.headers_mut()
+		.insert("X-Forwarded-For", forwarded_for);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn dlna_checks_address_forwarded_by_trusted_proxy() {
+	let config = format!(
+		"{}\n[proxy_auth]\ntrusted_proxies = [\"127.0.0.1\", \"::1\"]",
+		dlna_config()
+	);
+	let mut service = ServiceType::new_with_config(&test_name!(), &config);
+
+	let mut request = protocol::dlna_description();
+	let forwarded_for = "192.168.1.5".parse().unwrap();
+	request
+		.headers_mut()
+		.insert("X-Forwarded-For", forwarded_for);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	// Only the entry added by the proxy is trusted, not the ones sent by the client
+	let mut request = protocol::dlna_description();
+	let forwarded_for = "192.168.1.5, 203.0.113.9".parse().unwrap();
+	request
+		.headers_mut()
+		.insert("X-Forwarded-For", forwarded_for);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn dlna_browses_collection() {
+	let mut service = ServiceType::new_with_config(&test_name!(), &dlna_config());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let request = protocol::dlna_browse("0");
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let result = String::from_utf8_lossy(response.body());
+	assert!(result.contains("<NumberReturned>1</NumberReturned>"));
+	assert!(result.contains(&format!("id=&quot;{TEST_MOUNT_NAME}&quot;")));
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let request = protocol::dlna_browse(&path.to_string_lossy());
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let result = String::from_utf8_lossy(response.body());
+	assert!(result.contains("<NumberReturned>5</NumberReturned>"));
+	assert!(result.contains("object.item.audioItem.musicTrack"));
+	assert!(result.contains("Candlelight"));
+
+	let request = protocol::dlna_browse("not_a_directory");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[test]
+fn dlna_streams_audio() {
+	let mut service = ServiceType::new_with_config(&test_name!(), &dlna_config());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::dlna_audio(&path);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.headers().get("Content-Type").unwrap(),
+		"audio/mpeg"
+	);
+	assert!(response.headers().contains_key("contentFeatures.dlna.org"));
+}
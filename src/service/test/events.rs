@@ -0,0 +1,12 @@
+use http::StatusCode;
+
+use crate::service::test::{protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn events_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::events();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
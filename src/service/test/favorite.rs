@@ -0,0 +1,129 @@
+use http::StatusCode;
+use std::path::{Path, PathBuf};
+
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn favorites_require_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis"].iter().collect();
+
+	let request = protocol::favorites();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::add_favorite(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::remove_favorite(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn add_favorite_rejects_unknown_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "missing.mp3"].iter().collect();
+	let request = protocol::add_favorite(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn remove_favorite_rejects_non_favorite() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis"].iter().collect();
+	let request = protocol::remove_favorite(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn favorites_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let album: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let song: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	for path in [&album, &song] {
+		let request = protocol::add_favorite(path);
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	let request = protocol::favorites();
+	let response = service.fetch_json::<_, Vec<dto::CollectionFile>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let favorites = response.body();
+	assert_eq!(favorites.len(), 2);
+	assert!(matches!(
+		&favorites[0],
+		dto::CollectionFile::Directory(d) if album == Path::new(&d.directory.path) && d.is_favorite
+	));
+	assert!(matches!(
+		&favorites[1],
+		dto::CollectionFile::Song(s) if song == Path::new(&s.song.path) && s.is_favorite
+	));
+
+	let request = protocol::remove_favorite(&album);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::favorites();
+	let response = service.fetch_json::<_, Vec<dto::CollectionFile>>(&request);
+	assert_eq!(response.body().len(), 1);
+}
+
+#[test]
+fn browse_flags_favorites() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let song: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::add_favorite(&song);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let album: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let request = protocol::browse(&album);
+	let response = service.fetch_json::<_, Vec<dto::CollectionFile>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let favorites: Vec<PathBuf> = response
+		.body()
+		.iter()
+		.filter_map(|f| match f {
+			dto::CollectionFile::Song(s) if s.is_favorite => Some(PathBuf::from(&s.song.path)),
+			_ => None,
+		})
+		.collect();
+	assert_eq!(favorites, vec![song.clone()]);
+
+	let request = protocol::flatten(&album);
+	let response = service.fetch_json::<_, Vec<dto::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body().iter().filter(|s| s.is_favorite).count(), 1);
+}
@@ -0,0 +1,56 @@
+use http::StatusCode;
+
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn health_does_not_require_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::health();
+	let response = service.fetch_json::<_, dto::Health>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let health = response.body();
+	assert!(health.healthy);
+	assert!(health.database);
+	assert!(health.mounts_available);
+}
+
+#[test]
+fn health_reports_mounts() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+
+	let request = protocol::health();
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let health: dto::Health = serde_json::from_slice(response.body()).unwrap();
+	assert!(health.mounts_available);
+	// Mount names are not disclosed to unauthenticated callers
+	let body = String::from_utf8_lossy(response.body());
+	assert!(!body.contains(TEST_MOUNT_NAME));
+}
+
+#[test]
+fn health_reports_missing_mounts() {
+	let mut service = ServiceType::new(&test_name!());
+	let configuration = dto::Config {
+		mount_dirs: Some(vec![dto::MountDir {
+			name: TEST_MOUNT_NAME.to_owned(),
+			source: "test-data/missing-collection".to_owned(),
+			watch: false,
+			follow_symlinks: false,
+			content_type: Default::default(),
+			album_art_pattern: None,
+			art_overlay: None,
+		}]),
+		..Default::default()
+	};
+	let request = protocol::apply_config(configuration);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::health();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
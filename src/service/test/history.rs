@@ -0,0 +1,83 @@
+use http::StatusCode;
+use std::path::PathBuf;
+
+use crate::app::{history, index};
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn song_path(name: &str) -> PathBuf {
+	[TEST_MOUNT_NAME, "Khemmis", "Hunted", name]
+		.iter()
+		.collect()
+}
+
+#[test]
+fn record_listen_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::record_listen(&song_path("02 - Candlelight.mp3"), None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn record_listen_rejects_unknown_song() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::record_listen(&song_path("missing.mp3"), None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn recently_played_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::recently_played();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn most_played_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::most_played();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn history_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let first = song_path("01 - Above The Water.mp3");
+	let second = song_path("02 - Candlelight.mp3");
+	for (path, completion) in [(&second, Some(100)), (&second, Some(40)), (&first, None)] {
+		let request = protocol::record_listen(path, completion);
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	let request = protocol::recently_played();
+	let response = service.fetch_json::<_, Vec<index::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let songs = response.body();
+	assert_eq!(songs.len(), 2);
+	assert_eq!(PathBuf::from(&songs[0].path), first);
+	assert_eq!(PathBuf::from(&songs[1].path), second);
+
+	let request = protocol::most_played();
+	let response = service.fetch_json::<_, Vec<history::PlayCount>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let play_counts = response.body();
+	assert_eq!(play_counts.len(), 2);
+	assert_eq!(PathBuf::from(&play_counts[0].song.path), second);
+	assert_eq!(play_counts[0].play_count, 2);
+	assert_eq!(play_counts[1].play_count, 1);
+}
@@ -0,0 +1,32 @@
+use http::StatusCode;
+
+use crate::service::test::{protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn jukebox_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::jukebox();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::jukebox_play();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn jukebox_is_disabled_by_default() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::jukebox();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+
+	let request = protocol::jukebox_play();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+}
@@ -1,8 +1,11 @@
-use http::{header, HeaderValue, StatusCode};
+use http::{header, HeaderValue, Method, StatusCode};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use crate::service::dto::ThumbnailSize;
-use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::service::dto::{self, ThumbnailSize, TranscodeFormat};
+use crate::service::test::{
+	constants::*, protocol, restricted_mounts_config, ServiceType, TestService,
+};
 use crate::test_name;
 
 #[test]
@@ -40,6 +43,35 @@ fn audio_golden_path() {
 	);
 }
 
+#[test]
+fn audio_is_throttled_per_user() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	let request = protocol::put_settings(dto::NewSettings {
+		user_bandwidth_limit_kbps: Some(40),
+		..Default::default()
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	// The first song fits in the burst allowance, the second waits for it to be paid off
+	let start = Instant::now();
+	for _ in 0..2 {
+		let request = protocol::audio(&path);
+		let response = service.fetch_bytes(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(response.body().len(), 24_142);
+	}
+	assert!(start.elapsed() >= Duration::from_secs(2));
+}
+
 #[test]
 fn audio_does_not_encode_content() {
 	let mut service = ServiceType::new(&test_name!());
@@ -95,6 +127,206 @@ fn audio_partial_content() {
 		response.headers().get(header::CONTENT_LENGTH).unwrap(),
 		"200"
 	);
+	assert_eq!(
+		response.headers().get(header::CONTENT_RANGE).unwrap(),
+		"bytes 100-299/24142"
+	);
+}
+
+#[test]
+fn audio_advertises_range_support() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::audio(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.headers().get(header::ACCEPT_RANGES).unwrap(),
+		"bytes"
+	);
+}
+
+#[test]
+fn audio_head_request() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let mut request = protocol::audio(&path);
+	*request.method_mut() = Method::HEAD;
+
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.body().is_empty());
+	assert_eq!(
+		response.headers().get(header::CONTENT_LENGTH).unwrap(),
+		"24142"
+	);
+}
+
+#[test]
+fn transcoded_audio_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::transcoded_audio(&path, Some(TranscodeFormat::Opus), Some(64));
+
+	// Transcoding relies on ffmpeg, which may not be installed on the test machine
+	if service.fetch(&request).status() == StatusCode::NOT_IMPLEMENTED {
+		return;
+	}
+
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(!response.body().is_empty());
+	assert_eq!(
+		response.headers().get(header::CONTENT_TYPE).unwrap(),
+		"audio/ogg"
+	);
+}
+
+#[test]
+fn transcoded_audio_rejects_bad_bitrate() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::transcoded_audio(&path, Some(TranscodeFormat::Mp3), Some(4000));
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn hls_master_playlist_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::hls_master_playlist(&path, None);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.headers().get(header::CONTENT_TYPE).unwrap(),
+		"application/vnd.apple.mpegurl"
+	);
+	let playlist = String::from_utf8(response.into_body()).unwrap();
+	assert!(playlist.starts_with("#EXTM3U\n"));
+	assert_eq!(playlist.matches("#EXT-X-STREAM-INF").count(), 3);
+	assert!(playlist.contains("\n64/index.m3u8\n"));
+}
+
+#[test]
+fn hls_playlists_carry_auth_token() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	let request = protocol::login(TEST_USERNAME, TEST_PASSWORD);
+	let authorization = service
+		.fetch_json::<_, dto::Authorization>(&request)
+		.into_body();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	// Players fetching HLS streams cannot set headers, so the token is passed along
+	let request = protocol::hls_master_playlist(&path, Some(&authorization.token));
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let playlist = String::from_utf8(response.into_body()).unwrap();
+	assert!(playlist.contains("\n128/index.m3u8?auth_token="));
+
+	let request = protocol::hls_master_playlist(&path, None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn hls_stream_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::hls_playlist(&path, 64);
+
+	// Transcoding relies on ffmpeg, which may not be installed on the test machine
+	if service.fetch(&request).status() == StatusCode::NOT_IMPLEMENTED {
+		return;
+	}
+
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let playlist = String::from_utf8(response.into_body()).unwrap();
+	assert!(playlist.contains("\n0.ts\n"));
+	assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+
+	let request = protocol::hls_segment(&path, 64, 0);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(!response.body().is_empty());
+	assert_eq!(
+		response.headers().get(header::CONTENT_TYPE).unwrap(),
+		"video/mp2t"
+	);
+
+	let request = protocol::hls_segment(&path, 64, 9999);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn hls_stream_rejects_bad_bitrate() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::hls_playlist(&path, 4000);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
 
 #[test]
@@ -110,6 +342,60 @@ fn audio_bad_path_returns_not_found() {
 	assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[test]
+fn peaks_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::peaks(&path);
+
+	// Waveforms rely on ffmpeg, which may not be installed on the test machine
+	if service.fetch(&request).status() == StatusCode::NOT_IMPLEMENTED {
+		return;
+	}
+
+	let response = service.fetch_json::<_, Vec<u8>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(!response.body().is_empty());
+}
+
+#[test]
+fn peaks_bad_path_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let path: PathBuf = ["not_my_collection"].iter().collect();
+
+	let request = protocol::peaks(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn lyrics_missing_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::lyrics(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
 #[test]
 fn thumbnail_requires_auth() {
 	let mut service = ServiceType::new(&test_name!());
@@ -125,6 +411,41 @@ fn thumbnail_requires_auth() {
 	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[test]
+fn thumbnail_supports_conditional_requests() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "Folder.jpg"]
+		.iter()
+		.collect();
+
+	let request = protocol::thumbnail(&path, None, None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let etag = response.headers().get(header::ETAG).unwrap().clone();
+	let last_modified = response
+		.headers()
+		.get(header::LAST_MODIFIED)
+		.unwrap()
+		.clone();
+
+	let mut request = protocol::thumbnail(&path, None, None);
+	request.headers_mut().append(header::IF_NONE_MATCH, etag);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+	let mut request = protocol::thumbnail(&path, None, None);
+	request
+		.headers_mut()
+		.append(header::IF_MODIFIED_SINCE, last_modified);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
 #[test]
 fn thumbnail_golden_path() {
 	let mut service = ServiceType::new(&test_name!());
@@ -199,3 +520,73 @@ fn thumbnail_size(name: &str, size: Option<ThumbnailSize>, pad: Option<bool>, ex
 	assert_eq!(thumbnail.width(), expected);
 	assert_eq!(thumbnail.height(), expected);
 }
+
+#[test]
+fn audio_is_only_served_from_visible_mounts() {
+	let config = restricted_mounts_config();
+	let mut service = ServiceType::new_with_config(&test_name!(), &config);
+	service.login();
+
+	let path: PathBuf = ["Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let response = service.fetch(&protocol::audio(&path));
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+	let path: PathBuf = ["Tobokegao", "Picnic", "01 - ピクニック (Picnic).mp3"]
+		.iter()
+		.collect();
+	let response = service.fetch_bytes(&protocol::audio(&path));
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn download_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let request = protocol::download(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn download_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let request = protocol::download(&path);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.headers().get(header::CONTENT_TYPE).unwrap(),
+		"application/zip"
+	);
+	assert_eq!(
+		response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+		"attachment; filename=\"Hunted.zip\""
+	);
+	let body = response.body();
+	assert!(body.starts_with(b"PK\x03\x04"));
+	assert!(body[body.len() - 22..].starts_with(b"PK\x05\x06"));
+}
+
+#[test]
+fn download_bad_directory() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::download(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+	let path: PathBuf = ["not_my_collection"].iter().collect();
+	let request = protocol::download(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -1,8 +1,10 @@
-use http::StatusCode;
+use http::{header, StatusCode};
 
 use crate::app::index;
 use crate::service::dto;
-use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::service::test::{
+	constants::*, protocol, restricted_mounts_config, ServiceType, TestService,
+};
 use crate::test_name;
 
 #[test]
@@ -96,6 +98,146 @@ fn get_playlist_bad_name_returns_not_found() {
 	assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
 
+#[test]
+fn export_playlist_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::export_playlist(TEST_PLAYLIST_NAME, None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn export_playlist_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	{
+		let my_playlist = dto::SavePlaylistInput { tracks: Vec::new() };
+		let request = protocol::save_playlist(TEST_PLAYLIST_NAME, my_playlist);
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	let request = protocol::export_playlist(TEST_PLAYLIST_NAME, Some(dto::PlaylistFormat::Pls));
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.headers().get(header::CONTENT_TYPE).unwrap(),
+		"audio/x-scpls"
+	);
+	assert_eq!(
+		response.body(),
+		b"[playlist]\nNumberOfEntries=0\nVersion=2\n"
+	);
+}
+
+#[test]
+fn export_playlist_bad_name_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::export_playlist(TEST_PLAYLIST_NAME, None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn import_playlists_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::import_playlists();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn import_playlists_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::import_playlists();
+	let response = service.fetch_json::<_, dto::ImportedPlaylists>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn rename_playlist_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let input = dto::RenamePlaylistInput {
+		new_name: "new_name".to_owned(),
+	};
+	let request = protocol::rename_playlist(TEST_PLAYLIST_NAME, input);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn rename_playlist_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	{
+		let my_playlist = dto::SavePlaylistInput { tracks: Vec::new() };
+		let request = protocol::save_playlist(TEST_PLAYLIST_NAME, my_playlist);
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	let input = dto::RenamePlaylistInput {
+		new_name: "new_name".to_owned(),
+	};
+	let request = protocol::rename_playlist(TEST_PLAYLIST_NAME, input);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::playlists();
+	let response = service.fetch_json::<_, Vec<dto::ListPlaylistsEntry>>(&request);
+	assert_eq!(
+		response.body(),
+		&vec![dto::ListPlaylistsEntry {
+			name: "new_name".to_owned()
+		}]
+	);
+}
+
+#[test]
+fn rename_playlist_bad_name_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let input = dto::RenamePlaylistInput {
+		new_name: "new_name".to_owned(),
+	};
+	let request = protocol::rename_playlist(TEST_PLAYLIST_NAME, input);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn rename_playlist_to_existing_name_returns_conflict() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	for name in [TEST_PLAYLIST_NAME, "other_playlist"] {
+		let my_playlist = dto::SavePlaylistInput { tracks: Vec::new() };
+		let request = protocol::save_playlist(name, my_playlist);
+		let response = service.fetch(&request);
+		assert_eq!(response.status(), StatusCode::OK);
+	}
+
+	let input = dto::RenamePlaylistInput {
+		new_name: "other_playlist".to_owned(),
+	};
+	let request = protocol::rename_playlist(TEST_PLAYLIST_NAME, input);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
 #[test]
 fn delete_playlist_requires_auth() {
 	let mut service = ServiceType::new(&test_name!());
@@ -132,3 +274,29 @@ fn delete_playlist_bad_name_returns_not_found() {
 	let response = service.fetch(&request);
 	assert_eq!(response.status(), StatusCode::NOT_FOUND);
 }
+
+#[test]
+fn playlists_only_contain_songs_from_visible_mounts() {
+	let config = restricted_mounts_config();
+	let mut service = ServiceType::new_with_config(&test_name!(), &config);
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let hidden_song = "Khemmis/Hunted/02 - Candlelight.mp3";
+	let visible_song = "Tobokegao/Picnic/01 - ピクニック (Picnic).mp3";
+	let request = protocol::save_playlist(
+		TEST_PLAYLIST_NAME,
+		dto::SavePlaylistInput {
+			tracks: vec![hidden_song.to_owned(), visible_song.to_owned()],
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::read_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch_json::<_, Vec<index::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let paths: Vec<String> = response.body().iter().map(|s| s.path.clone()).collect();
+	assert_eq!(paths, vec![visible_song.to_owned()]);
+}
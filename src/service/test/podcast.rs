@@ -0,0 +1,56 @@
+use http::StatusCode;
+
+use crate::app::podcast;
+use crate::service::test::{protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn podcasts_require_auth() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::list_podcasts();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::subscribe_to_podcast("https://example.com/feed.xml");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn list_podcasts_starts_empty() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::list_podcasts();
+	let response = service.fetch_json::<_, Vec<podcast::Podcast>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.body().is_empty());
+}
+
+#[test]
+fn subscribe_rejects_non_http_urls() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::subscribe_to_podcast("file:///etc/passwd");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn bad_podcast_id_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::list_podcast_episodes(1);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+	let request = protocol::set_podcast_episode_position(1, 30);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
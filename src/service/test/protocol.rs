@@ -3,7 +3,10 @@ use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
 use std::path::Path;
 
 use crate::service::dto;
-use crate::{app::user, service::dto::ThumbnailSize};
+use crate::{
+	app::{radio, transcode, user},
+	service::dto::{ThumbnailSize, TranscodeFormat},
+};
 
 pub fn web_index() -> Request<()> {
 	Request::builder()
@@ -29,6 +32,14 @@ pub fn version() -> Request<()> {
 		.unwrap()
 }
 
+pub fn health() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/health")
+		.body(())
+		.unwrap()
+}
+
 pub fn initial_setup() -> Request<()> {
 	Request::builder()
 		.method(Method::GET)
@@ -49,6 +60,14 @@ pub fn login(username: &str, password: &str) -> Request<dto::Credentials> {
 		.unwrap()
 }
 
+pub fn logout() -> Request<()> {
+	Request::builder()
+		.method(Method::DELETE)
+		.uri("/api/auth")
+		.body(())
+		.unwrap()
+}
+
 pub fn apply_config(config: dto::Config) -> Request<dto::Config> {
 	Request::builder()
 		.method(Method::PUT)
@@ -121,6 +140,17 @@ pub fn delete_user(username: &str) -> Request<()> {
 		.unwrap()
 }
 
+pub fn change_password(
+	username: &str,
+	password_change: dto::PasswordChange,
+) -> Request<dto::PasswordChange> {
+	Request::builder()
+		.method(Method::POST)
+		.uri(format!("/api/user/{}/password", username))
+		.body(password_change)
+		.unwrap()
+}
+
 pub fn get_preferences() -> Request<()> {
 	Request::builder()
 		.method(Method::GET)
@@ -137,6 +167,115 @@ pub fn put_preferences(preferences: user::Preferences) -> Request<user::Preferen
 		.unwrap()
 }
 
+pub fn list_clients() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/clients")
+		.body(())
+		.unwrap()
+}
+
+pub fn put_client(name: &str, transcoding: transcode::Profile) -> Request<transcode::Profile> {
+	let endpoint = format!("/api/client/{}", url_encode(name));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(transcoding)
+		.unwrap()
+}
+
+pub fn delete_client(name: &str) -> Request<()> {
+	let endpoint = format!("/api/client/{}", url_encode(name));
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn list_api_keys() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/api_keys")
+		.body(())
+		.unwrap()
+}
+
+pub fn create_api_key(name: &str) -> Request<()> {
+	let endpoint = format!("/api/api_key/{}", url_encode(name));
+	Request::builder()
+		.method(Method::POST)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn delete_api_key(name: &str) -> Request<()> {
+	let endpoint = format!("/api/api_key/{}", url_encode(name));
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn index_status() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/index/status")
+		.body(())
+		.unwrap()
+}
+
+pub fn duplicates() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/duplicates")
+		.body(())
+		.unwrap()
+}
+
+pub fn backup() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/backup")
+		.body(())
+		.unwrap()
+}
+
+pub fn missing_songs() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/missing_songs")
+		.body(())
+		.unwrap()
+}
+
+pub fn purge_missing_songs() -> Request<()> {
+	Request::builder()
+		.method(Method::DELETE)
+		.uri("/api/missing_songs")
+		.body(())
+		.unwrap()
+}
+
+pub fn sessions() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/sessions")
+		.body(())
+		.unwrap()
+}
+
+pub fn terminate_session(id: u64) -> Request<()> {
+	let endpoint = format!("/api/sessions/{}", id);
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
 pub fn trigger_index() -> Request<()> {
 	Request::builder()
 		.method(Method::POST)
@@ -145,6 +284,15 @@ pub fn trigger_index() -> Request<()> {
 		.unwrap()
 }
 
+pub fn browse_view(path: &str) -> Request<()> {
+	let endpoint = format!("/api/views/{}", url_encode(path));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
 pub fn browse(path: &Path) -> Request<()> {
 	let path = path.to_string_lossy();
 	let endpoint = format!("/api/browse/{}", url_encode(path.as_ref()));
@@ -165,6 +313,21 @@ pub fn flatten(path: &Path) -> Request<()> {
 		.unwrap()
 }
 
+pub fn flatten_page(path: &Path, offset: usize, limit: usize) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!(
+		"/api/flatten/{}?offset={}&limit={}",
+		url_encode(path.as_ref()),
+		offset,
+		limit
+	);
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
 pub fn random() -> Request<()> {
 	Request::builder()
 		.method(Method::GET)
@@ -190,6 +353,41 @@ pub fn search(query: &str) -> Request<()> {
 		.unwrap()
 }
 
+pub fn search_suggestions(prefix: &str) -> Request<()> {
+	let endpoint = format!("/api/search/suggest?prefix={}", url_encode(prefix));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn events() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/events")
+		.body(())
+		.unwrap()
+}
+
+pub fn artist_biography(name: &str) -> Request<()> {
+	let endpoint = format!("/api/artist/{}/bio", url_encode(name));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn artist_image(name: &str) -> Request<()> {
+	let endpoint = format!("/api/artist/{}/image", url_encode(name));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
 pub fn audio(path: &Path) -> Request<()> {
 	let path = path.to_string_lossy();
 	let endpoint = format!("/api/audio/{}", url_encode(path.as_ref()));
@@ -200,6 +398,88 @@ pub fn audio(path: &Path) -> Request<()> {
 		.unwrap()
 }
 
+pub fn hls_master_playlist(path: &Path, auth_token: Option<&str>) -> Request<()> {
+	let path = path.to_string_lossy();
+	let mut endpoint = format!("/api/hls/{}/master.m3u8", url_encode(path.as_ref()));
+	if let Some(auth_token) = auth_token {
+		endpoint.push_str(&format!("?auth_token={}", url_encode(auth_token)));
+	}
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn hls_playlist(path: &Path, bitrate: u32) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!(
+		"/api/hls/{}/{}/index.m3u8",
+		url_encode(path.as_ref()),
+		bitrate
+	);
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn hls_segment(path: &Path, bitrate: u32, segment: usize) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!(
+		"/api/hls/{}/{}/{}.ts",
+		url_encode(path.as_ref()),
+		bitrate,
+		segment
+	);
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn download(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/download/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn transcoded_audio(
+	path: &Path,
+	format: Option<TranscodeFormat>,
+	bitrate: Option<u32>,
+) -> Request<()> {
+	let path = path.to_string_lossy();
+	let mut params = Vec::new();
+	if let Some(f) = format {
+		match f {
+			TranscodeFormat::Mp3 => params.push("format=mp3".to_owned()),
+			TranscodeFormat::Opus => params.push("format=opus".to_owned()),
+		};
+	}
+	if let Some(b) = bitrate {
+		params.push(format!("bitrate={}", b));
+	}
+
+	let endpoint = format!(
+		"/api/audio/{}?{}",
+		url_encode(path.as_ref()),
+		params.join("&")
+	);
+
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
 pub fn thumbnail(path: &Path, size: Option<ThumbnailSize>, pad: Option<bool>) -> Request<()> {
 	let path = path.to_string_lossy();
 	let mut params = String::new();
@@ -233,6 +513,46 @@ pub fn thumbnail(path: &Path, size: Option<ThumbnailSize>, pad: Option<bool>) ->
 		.unwrap()
 }
 
+pub fn lyrics(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/lyrics/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn put_tags(path: &Path, edit: dto::SongEdit) -> Request<dto::SongEdit> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/tags/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(edit)
+		.unwrap()
+}
+
+pub fn put_artwork(path: &Path, artwork: Vec<u8>) -> Request<Vec<u8>> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/artwork/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(artwork)
+		.unwrap()
+}
+
+pub fn peaks(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/peaks/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
 pub fn playlists() -> Request<()> {
 	Request::builder()
 		.method(Method::GET)
@@ -262,6 +582,40 @@ pub fn read_playlist(name: &str) -> Request<()> {
 		.unwrap()
 }
 
+pub fn export_playlist(name: &str, format: Option<dto::PlaylistFormat>) -> Request<()> {
+	let mut endpoint = format!("/api/playlist/{}/export", url_encode(name));
+	match format {
+		Some(dto::PlaylistFormat::M3u) => endpoint.push_str("?format=m3u"),
+		Some(dto::PlaylistFormat::Pls) => endpoint.push_str("?format=pls"),
+		None => (),
+	};
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn import_playlists() -> Request<()> {
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/playlists/import")
+		.body(())
+		.unwrap()
+}
+
+pub fn rename_playlist(
+	name: &str,
+	input: dto::RenamePlaylistInput,
+) -> Request<dto::RenamePlaylistInput> {
+	let endpoint = format!("/api/playlist/{}/rename", url_encode(name));
+	Request::builder()
+		.method(Method::POST)
+		.uri(&endpoint)
+		.body(input)
+		.unwrap()
+}
+
 pub fn delete_playlist(name: &str) -> Request<()> {
 	let endpoint = format!("/api/playlist/{}", url_encode(name));
 	Request::builder()
@@ -299,6 +653,469 @@ pub fn lastfm_scrobble(path: &Path) -> Request<()> {
 		.unwrap()
 }
 
-fn url_encode(input: &str) -> String {
-	percent_encode(input.as_bytes(), NON_ALPHANUMERIC).to_string()
+pub fn listenbrainz_link(link: dto::ListenBrainzLink) -> Request<dto::ListenBrainzLink> {
+	Request::builder()
+		.method(Method::PUT)
+		.uri("/api/listenbrainz/link")
+		.body(link)
+		.unwrap()
+}
+
+pub fn listenbrainz_unlink() -> Request<()> {
+	Request::builder()
+		.method(Method::DELETE)
+		.uri("/api/listenbrainz/link")
+		.body(())
+		.unwrap()
+}
+
+pub fn now_playing(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/now_playing/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn scrobble(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/scrobble/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::POST)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn favorites() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/favorites")
+		.body(())
+		.unwrap()
+}
+
+pub fn add_favorite(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/favorites/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn remove_favorite(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/favorites/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn queue() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/queue")
+		.body(())
+		.unwrap()
+}
+
+pub fn clear_queue() -> Request<()> {
+	Request::builder()
+		.method(Method::DELETE)
+		.uri("/api/queue")
+		.body(())
+		.unwrap()
+}
+
+pub fn append_to_queue(tracks: &[&Path]) -> Request<dto::QueueTracksInput> {
+	let input = dto::QueueTracksInput {
+		tracks: tracks
+			.iter()
+			.map(|t| t.to_string_lossy().into_owned())
+			.collect(),
+	};
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/queue/tracks")
+		.body(input)
+		.unwrap()
+}
+
+pub fn insert_next_in_queue(tracks: &[&Path]) -> Request<dto::QueueTracksInput> {
+	let input = dto::QueueTracksInput {
+		tracks: tracks
+			.iter()
+			.map(|t| t.to_string_lossy().into_owned())
+			.collect(),
+	};
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/queue/next")
+		.body(input)
+		.unwrap()
+}
+
+pub fn move_queue_track(from: usize, to: usize) -> Request<dto::MoveQueueTrackInput> {
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/queue/move")
+		.body(dto::MoveQueueTrackInput { from, to })
+		.unwrap()
+}
+
+pub fn remove_from_queue(index: usize) -> Request<()> {
+	let endpoint = format!("/api/queue/tracks/{}", index);
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn set_queue_current_track(
+	current_track: Option<usize>,
+	position_seconds: u32,
+) -> Request<dto::QueueCurrentTrackInput> {
+	Request::builder()
+		.method(Method::PUT)
+		.uri("/api/queue/current")
+		.body(dto::QueueCurrentTrackInput {
+			current_track,
+			position_seconds,
+		})
+		.unwrap()
+}
+
+pub fn jukebox() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/jukebox")
+		.body(())
+		.unwrap()
+}
+
+pub fn jukebox_play() -> Request<()> {
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/jukebox/play")
+		.body(())
+		.unwrap()
+}
+
+pub fn list_smart_playlists() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/smart_playlists")
+		.body(())
+		.unwrap()
+}
+
+pub fn save_smart_playlist(
+	name: &str,
+	playlist: dto::SaveSmartPlaylistInput,
+) -> Request<dto::SaveSmartPlaylistInput> {
+	let endpoint = format!("/api/smart_playlist/{}", url_encode(name));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(playlist)
+		.unwrap()
+}
+
+pub fn read_smart_playlist(name: &str) -> Request<()> {
+	let endpoint = format!("/api/smart_playlist/{}", url_encode(name));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn delete_smart_playlist(name: &str) -> Request<()> {
+	let endpoint = format!("/api/smart_playlist/{}", url_encode(name));
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn set_rating(path: &Path, rating: u8) -> Request<dto::Rating> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/ratings/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(dto::Rating { rating })
+		.unwrap()
+}
+
+pub fn clear_rating(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/ratings/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn record_listen(path: &Path, completion: Option<u8>) -> Request<()> {
+	let path = path.to_string_lossy();
+	let mut endpoint = format!("/api/listen/{}", url_encode(path.as_ref()));
+	if let Some(completion) = completion {
+		endpoint.push_str(&format!("?completion={}", completion));
+	}
+	Request::builder()
+		.method(Method::POST)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn recently_played() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/recently_played")
+		.body(())
+		.unwrap()
+}
+
+pub fn most_played() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/most_played")
+		.body(())
+		.unwrap()
+}
+
+fn url_encode(input: &str) -> String {
+	percent_encode(input.as_bytes(), NON_ALPHANUMERIC).to_string()
+}
+
+pub fn dlna_description() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/dlna/description.xml")
+		.body(())
+		.unwrap()
+}
+
+pub fn dlna_browse(object_id: &str) -> Request<String> {
+	let body = format!(
+		r#"<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body><u:Browse xmlns:u="urn:schemas-upnp-org:service:ContentDirectory:1"><ObjectID>{object_id}</ObjectID><BrowseFlag>BrowseDirectChildren</BrowseFlag><Filter>*</Filter><StartingIndex>0</StartingIndex><RequestedCount>0</RequestedCount><SortCriteria></SortCriteria></u:Browse></s:Body></s:Envelope>"#
+	);
+	Request::builder()
+		.method(Method::POST)
+		.uri("/dlna/control/content_directory")
+		.header(
+			"SOAPACTION",
+			r#""urn:schemas-upnp-org:service:ContentDirectory:1#Browse""#,
+		)
+		.body(body)
+		.unwrap()
+}
+
+pub fn dlna_audio(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/dlna/audio/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn list_shares() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/shares")
+		.body(())
+		.unwrap()
+}
+
+pub fn create_share(share: dto::NewShare) -> Request<dto::NewShare> {
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/shares")
+		.body(share)
+		.unwrap()
+}
+
+pub fn delete_share(token: &str) -> Request<()> {
+	let endpoint = format!("/api/shares/{}", url_encode(token));
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn get_share(token: &str) -> Request<()> {
+	let endpoint = format!("/api/share/{}", url_encode(token));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn share_audio(token: &str, track: usize) -> Request<()> {
+	let endpoint = format!("/api/share/{}/audio/{}", url_encode(token), track);
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn share_download(token: &str) -> Request<()> {
+	let endpoint = format!("/api/share/{}/download", url_encode(token));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn list_podcasts() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/podcasts")
+		.body(())
+		.unwrap()
+}
+
+pub fn subscribe_to_podcast(url: &str) -> Request<dto::PodcastSubscription> {
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/podcasts")
+		.body(dto::PodcastSubscription {
+			url: url.to_owned(),
+			auto_download: false,
+		})
+		.unwrap()
+}
+
+pub fn list_podcast_episodes(id: i32) -> Request<()> {
+	let endpoint = format!("/api/podcasts/{id}/episodes");
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn set_podcast_episode_position(
+	id: i32,
+	position_seconds: u32,
+) -> Request<dto::PodcastEpisodePosition> {
+	let endpoint = format!("/api/podcast_episodes/{id}/position");
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(dto::PodcastEpisodePosition {
+			position_seconds,
+			completed: false,
+		})
+		.unwrap()
+}
+
+pub fn list_radio_stations() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/radio_stations")
+		.body(())
+		.unwrap()
+}
+
+pub fn add_radio_station(station: radio::NewStation) -> Request<radio::NewStation> {
+	Request::builder()
+		.method(Method::POST)
+		.uri("/api/radio_stations")
+		.body(station)
+		.unwrap()
+}
+
+pub fn remove_radio_station(id: i32) -> Request<()> {
+	let endpoint = format!("/api/radio_stations/{id}");
+	Request::builder()
+		.method(Method::DELETE)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn radio_stream(id: i32) -> Request<()> {
+	let endpoint = format!("/api/radio_stations/{id}/stream");
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn audiobook(path: &Path) -> Request<()> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/audiobook/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
+}
+
+pub fn set_audiobook_position(
+	path: &Path,
+	position_seconds: u32,
+) -> Request<dto::AudiobookPositionInput> {
+	let path = path.to_string_lossy();
+	let endpoint = format!("/api/audiobook_position/{}", url_encode(path.as_ref()));
+	Request::builder()
+		.method(Method::PUT)
+		.uri(&endpoint)
+		.body(dto::AudiobookPositionInput { position_seconds })
+		.unwrap()
+}
+
+pub fn audiobook_positions() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/audiobook_positions")
+		.body(())
+		.unwrap()
+}
+
+pub fn playback() -> Request<()> {
+	Request::builder()
+		.method(Method::GET)
+		.uri("/api/playback")
+		.body(())
+		.unwrap()
+}
+
+pub fn report_playback(input: dto::PlaybackInput) -> Request<dto::PlaybackInput> {
+	Request::builder()
+		.method(Method::PUT)
+		.uri("/api/playback")
+		.body(input)
+		.unwrap()
+}
+
+pub fn changes(since: Option<&str>) -> Request<()> {
+	let endpoint = match since {
+		Some(token) => format!("/api/changes?since={}", url_encode(token)),
+		None => "/api/changes".to_owned(),
+	};
+	Request::builder()
+		.method(Method::GET)
+		.uri(&endpoint)
+		.body(())
+		.unwrap()
 }
@@ -0,0 +1,192 @@
+use http::StatusCode;
+use std::path::PathBuf;
+
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn song_path(file_name: &str) -> PathBuf {
+	[TEST_MOUNT_NAME, "Khemmis", "Hunted", file_name]
+		.iter()
+		.collect()
+}
+
+fn queued_paths(queue: &dto::PlayQueue) -> Vec<PathBuf> {
+	queue
+		.songs
+		.iter()
+		.map(|s| PathBuf::from(&s.song.path))
+		.collect()
+}
+
+#[test]
+fn queue_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let song = song_path("02 - Candlelight.mp3");
+
+	let request = protocol::queue();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::append_to_queue(&[&song]);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::clear_queue();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn queue_rejects_unknown_songs_and_positions() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::append_to_queue(&[&song_path("missing.mp3")]);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+	let request = protocol::remove_from_queue(0);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+	let request = protocol::set_queue_current_track(Some(3), 0);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn queue_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let (a, b, c) = (
+		song_path("01 - Above The Water.mp3"),
+		song_path("02 - Candlelight.mp3"),
+		song_path("03 - Three Gates.mp3"),
+	);
+
+	let request = protocol::append_to_queue(&[&a, &b]);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::set_queue_current_track(Some(0), 30);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::insert_next_in_queue(&[&c]);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::move_queue_track(0, 2);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::queue();
+	let response = service.fetch_json::<_, dto::PlayQueue>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let queue = response.body();
+	assert_eq!(queued_paths(queue), vec![c, b.clone(), a]);
+	assert_eq!(queue.current_track, Some(2));
+	assert_eq!(queue.position_seconds, 30);
+
+	let request = protocol::remove_from_queue(0);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::queue();
+	let response = service.fetch_json::<_, dto::PlayQueue>(&request);
+	let queue = response.body();
+	assert_eq!(queued_paths(queue)[0], b);
+	assert_eq!(queue.current_track, Some(1));
+
+	let request = protocol::clear_queue();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::queue();
+	let response = service.fetch_json::<_, dto::PlayQueue>(&request);
+	let queue = response.body();
+	assert!(queue.songs.is_empty());
+	assert_eq!(queue.current_track, None);
+}
+
+#[test]
+fn playback_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::playback();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::report_playback(dto::PlaybackInput::default());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn playback_continues_on_another_device() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let (a, b) = (
+		song_path("01 - Above The Water.mp3"),
+		song_path("02 - Candlelight.mp3"),
+	);
+
+	let request = protocol::report_playback(dto::PlaybackInput {
+		device: Some("Desktop".to_owned()),
+		tracks: Some(vec![
+			a.to_string_lossy().into_owned(),
+			b.to_string_lossy().into_owned(),
+		]),
+		current_track: Some(1),
+		position_seconds: 95,
+		playing: false,
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::playback();
+	let response = service.fetch_json::<_, dto::Playback>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let playback = response.body();
+	assert_eq!(playback.device.as_deref(), Some("Desktop"));
+	assert!(!playback.playing);
+	assert_eq!(queued_paths(&playback.queue), vec![a, b]);
+	assert_eq!(playback.queue.current_track, Some(1));
+	assert_eq!(playback.queue.position_seconds, 95);
+
+	let request = protocol::report_playback(dto::PlaybackInput {
+		device: Some("Phone".to_owned()),
+		current_track: Some(1),
+		position_seconds: 95,
+		playing: true,
+		..Default::default()
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::playback();
+	let response = service.fetch_json::<_, dto::Playback>(&request);
+	let playback = response.body();
+	assert_eq!(playback.device.as_deref(), Some("Phone"));
+	assert!(playback.playing);
+	assert_eq!(playback.queue.songs.len(), 2);
+	assert!(playback.queue.position_seconds >= 95);
+
+	let request = protocol::report_playback(dto::PlaybackInput {
+		current_track: Some(2),
+		..Default::default()
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,81 @@
+use http::StatusCode;
+
+use crate::app::radio;
+use crate::service::test::{protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn new_station(shared: bool) -> radio::NewStation {
+	radio::NewStation {
+		name: "Metal FM".to_owned(),
+		stream_url: "http://example.com/stream".to_owned(),
+		homepage_url: None,
+		shared,
+	}
+}
+
+#[test]
+fn radio_stations_require_auth() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::list_radio_stations();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::add_radio_station(new_station(false));
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn shared_radio_stations_require_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::add_radio_station(new_station(true));
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn radio_proxy_is_disabled_by_default() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::add_radio_station(new_station(false));
+	let response = service.fetch_json::<_, radio::Station>(&request);
+	let id = response.body().id;
+
+	let request = protocol::radio_stream(id);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+}
+
+#[test]
+fn radio_stations_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+
+	let request = protocol::add_radio_station(new_station(true));
+	let response = service.fetch_json::<_, radio::Station>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let station = response.body().clone();
+	assert!(station.shared);
+
+	service.login();
+	let request = protocol::list_radio_stations();
+	let response = service.fetch_json::<_, Vec<radio::Station>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body(), &vec![station.clone()]);
+
+	service.login_admin();
+	let request = protocol::remove_radio_station(station.id);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::list_radio_stations();
+	let response = service.fetch_json::<_, Vec<radio::Station>>(&request);
+	assert!(response.body().is_empty());
+}
@@ -0,0 +1,87 @@
+use http::StatusCode;
+use std::path::PathBuf;
+
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn song_path() -> PathBuf {
+	[TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect()
+}
+
+#[test]
+fn ratings_require_auth() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::set_rating(&song_path(), 4);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::clear_rating(&song_path());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn set_rating_rejects_out_of_range_values() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::set_rating(&song_path(), 6);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn clear_rating_rejects_unrated_song() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::clear_rating(&song_path());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn ratings_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let song = song_path();
+	let request = protocol::set_rating(&song, 4);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let album: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	let request = protocol::flatten(&album);
+	let response = service.fetch_json::<_, Vec<dto::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let rated: Vec<(PathBuf, u8)> = response
+		.body()
+		.iter()
+		.filter_map(|s| Some((PathBuf::from(&s.song.path), s.rating?)))
+		.collect();
+	assert_eq!(rated, vec![(song.clone(), 4)]);
+
+	let request = protocol::clear_rating(&song);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::browse(&album);
+	let response = service.fetch_json::<_, Vec<dto::CollectionFile>>(&request);
+	assert!(response.body().iter().all(|f| match f {
+		dto::CollectionFile::Song(s) => s.rating.is_none(),
+		dto::CollectionFile::Directory(_) => true,
+	}));
+}
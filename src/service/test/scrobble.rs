@@ -0,0 +1,61 @@
+use http::StatusCode;
+use std::path::PathBuf;
+
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn now_playing_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::now_playing(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn now_playing_ignores_unlinked_user() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::now_playing(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+#[test]
+fn scrobble_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+	let request = protocol::scrobble(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn scrobble_ignores_unlinked_user() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect();
+
+	let request = protocol::scrobble(&path);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
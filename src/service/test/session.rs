@@ -0,0 +1,98 @@
+use http::{header, HeaderValue, StatusCode};
+use std::path::{Path, PathBuf};
+
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn song_path() -> PathBuf {
+	[TEST_MOUNT_NAME, "Khemmis", "Hunted", "02 - Candlelight.mp3"]
+		.iter()
+		.collect()
+}
+
+fn stream_from(service: &mut ServiceType, path: &Path, client: &str) -> StatusCode {
+	let mut request = protocol::audio(path);
+	request
+		.headers_mut()
+		.insert(header::USER_AGENT, HeaderValue::from_str(client).unwrap());
+	service.fetch(&request).status()
+}
+
+#[test]
+fn sessions_require_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::sessions();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let request = protocol::terminate_session(1);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn lists_streaming_sessions() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+
+	let path = song_path();
+	assert_eq!(stream_from(&mut service, &path, "phone"), StatusCode::OK);
+	assert_eq!(stream_from(&mut service, &path, "phone"), StatusCode::OK);
+
+	let request = protocol::sessions();
+	let response = service.fetch_json::<_, Vec<dto::StreamSession>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let sessions = response.body();
+	assert_eq!(sessions.len(), 1);
+	assert_eq!(sessions[0].username, TEST_USERNAME_ADMIN);
+	assert_eq!(sessions[0].client, "phone");
+	assert_eq!(Path::new(&sessions[0].path), path);
+	assert_eq!(sessions[0].bitrate, None);
+}
+
+#[test]
+fn concurrent_streams_are_limited() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	let request = protocol::put_settings(dto::NewSettings {
+		max_streams_per_user: Some(1),
+		..Default::default()
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	service.index();
+
+	let path = song_path();
+	assert_eq!(stream_from(&mut service, &path, "phone"), StatusCode::OK);
+	assert_eq!(
+		stream_from(&mut service, &path, "laptop"),
+		StatusCode::TOO_MANY_REQUESTS
+	);
+
+	// Terminating a session lets another client start streaming
+	let request = protocol::sessions();
+	let response = service.fetch_json::<_, Vec<dto::StreamSession>>(&request);
+	let id = response.body()[0].id;
+	let request = protocol::terminate_session(id);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(stream_from(&mut service, &path, "laptop"), StatusCode::OK);
+}
+
+#[test]
+fn terminating_unknown_session_fails() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+
+	let request = protocol::terminate_session(42);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -55,6 +55,27 @@ fn put_settings_requires_admin() {
 	assert_eq!(response.status(), StatusCode::FORBIDDEN);
 }
 
+#[test]
+fn put_settings_rejects_invalid_ignore_patterns() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+
+	let request = protocol::put_settings(dto::NewSettings {
+		reindex_every_n_seconds: Some(31),
+		ignore_patterns: Some(vec!["^@eaDir$".to_owned(), "(unclosed".to_owned()]),
+		..Default::default()
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+	let request = protocol::get_settings();
+	let response = service.fetch_json::<_, dto::Settings>(&request);
+	let settings = response.body();
+	assert_ne!(settings.reindex_every_n_seconds, 31);
+	assert!(settings.ignore_patterns.is_empty());
+}
+
 #[test]
 fn put_settings_golden_path() {
 	let mut service = ServiceType::new(&test_name!());
@@ -64,6 +85,27 @@ fn put_settings_golden_path() {
 	let request = protocol::put_settings(dto::NewSettings {
 		album_art_pattern: Some("test_pattern".to_owned()),
 		reindex_every_n_seconds: Some(31),
+		auth_token_lifetime_seconds: Some(3600),
+		album_art_priority: Some(dto::AlbumArtPriority::Folder),
+		index_num_threads: Some(2),
+		ignore_patterns: Some(vec!["^@eaDir$".to_owned()]),
+		thumbnail_cache_size_mb: Some(256),
+		group_compilations: Some(false),
+		collation_locale: Some("sv-SE".to_owned()),
+		search_accent_folding: Some(false),
+		loudness_target: Some(-23),
+		metadata_lookup: Some(true),
+		acoustid_api_key: Some("test_key".to_owned()),
+		missing_grace_period_days: Some(7),
+		max_files_per_second: Some(50),
+		pause_while_streaming: Some(true),
+		search_fuzzy: Some(true),
+		bandwidth_limit_kbps: Some(20_000),
+		user_bandwidth_limit_kbps: Some(5_000),
+		stream_bandwidth_limit_kbps: Some(1_000),
+		max_streams_per_user: Some(2),
+		pregenerate_thumbnails: Some(true),
+		transcode_cache_size_mb: Some(512),
 	});
 	let response = service.fetch(&request);
 	assert_eq!(response.status(), StatusCode::OK);
@@ -76,6 +118,27 @@ fn put_settings_golden_path() {
 		&Settings {
 			album_art_pattern: "test_pattern".to_owned(),
 			reindex_every_n_seconds: 31,
+			auth_token_lifetime_seconds: 3600,
+			album_art_priority: "folder".to_owned(),
+			index_num_threads: 2,
+			ignore_patterns: vec!["^@eaDir$".to_owned()],
+			thumbnail_cache_size_mb: 256,
+			group_compilations: false,
+			collation_locale: "sv-SE".to_owned(),
+			search_accent_folding: false,
+			loudness_target: -23,
+			metadata_lookup: true,
+			acoustid_api_key: "test_key".to_owned(),
+			missing_grace_period_days: 7,
+			max_files_per_second: 50,
+			pause_while_streaming: true,
+			search_fuzzy: true,
+			bandwidth_limit_kbps: 20_000,
+			user_bandwidth_limit_kbps: 5_000,
+			stream_bandwidth_limit_kbps: 1_000,
+			max_streams_per_user: 2,
+			pregenerate_thumbnails: true,
+			transcode_cache_size_mb: 512,
 		},
 	);
 }
@@ -0,0 +1,160 @@
+use http::{header, HeaderValue, StatusCode};
+use std::path::PathBuf;
+
+use crate::app::share;
+use crate::service::dto;
+use crate::service::test::{constants::*, protocol, ServiceType, TestService};
+use crate::test_name;
+
+fn album_path() -> String {
+	let path: PathBuf = [TEST_MOUNT_NAME, "Khemmis", "Hunted"].iter().collect();
+	path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn managing_shares_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::list_shares();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::create_share(dto::NewShare {
+		path: Some(album_path()),
+		..Default::default()
+	});
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn create_share_requires_single_target() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::create_share(dto::NewShare::default());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn bad_share_token_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::get_share("not_a_share");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn share_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::create_share(dto::NewShare {
+		path: Some(album_path()),
+		max_plays: Some(1),
+		..Default::default()
+	});
+	let response = service.fetch_json::<_, share::Share>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let token = response.body().token.clone();
+
+	let request = protocol::list_shares();
+	let response = service.fetch_json::<_, Vec<share::Share>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body().len(), 1);
+
+	// Share links work without an account
+	service.logout();
+
+	let request = protocol::get_share(&token);
+	let response = service.fetch_json::<_, dto::SharedContent>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(response.body().name, "Hunted");
+	assert_eq!(response.body().songs.len(), 5);
+
+	let request = protocol::share_audio(&token, 0);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	// Requesting the same track again is part of the same play
+	let request = protocol::share_audio(&token, 0);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::share_audio(&token, 1);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::GONE);
+
+	let request = protocol::share_download(&token);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	service.login();
+	let request = protocol::delete_share(&token);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::get_share(&token);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn share_plays_count_regardless_of_range() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::create_share(dto::NewShare {
+		path: Some(album_path()),
+		max_plays: Some(1),
+		..Default::default()
+	});
+	let response = service.fetch_json::<_, share::Share>(&request);
+	let token = response.body().token.clone();
+	service.logout();
+
+	for track in [0, 1] {
+		let mut request = protocol::share_audio(&token, track);
+		request
+			.headers_mut()
+			.append(header::RANGE, HeaderValue::from_str("bytes=1-").unwrap());
+		let response = service.fetch(&request);
+		let expected_status = match track {
+			0 => StatusCode::PARTIAL_CONTENT,
+			_ => StatusCode::GONE,
+		};
+		assert_eq!(response.status(), expected_status);
+	}
+}
+
+#[test]
+fn share_download_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::create_share(dto::NewShare {
+		path: Some(album_path()),
+		allow_download: true,
+		..Default::default()
+	});
+	let response = service.fetch_json::<_, share::Share>(&request);
+	let token = response.body().token.clone();
+	service.logout();
+
+	let request = protocol::share_download(&token);
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert!(response.body().starts_with(b"PK"));
+}
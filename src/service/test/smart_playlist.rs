@@ -0,0 +1,124 @@
+use http::StatusCode;
+
+use crate::app::{index, smart_playlist::Rule};
+use crate::service::dto;
+use crate::service::test::{protocol, restricted_mounts_config, ServiceType, TestService};
+use crate::test_name;
+
+const TEST_PLAYLIST_NAME: &str = "Recent Metal";
+
+#[test]
+fn smart_playlists_require_auth() {
+	let mut service = ServiceType::new(&test_name!());
+
+	let request = protocol::list_smart_playlists();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = protocol::read_smart_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn save_smart_playlist_rejects_invalid_rules() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::save_smart_playlist(
+		TEST_PLAYLIST_NAME,
+		dto::SaveSmartPlaylistInput {
+			rules: vec![Rule::MinRating { rating: 9 }],
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn read_smart_playlist_bad_name_returns_not_found() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::read_smart_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn smart_playlist_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let rules = vec![
+		Rule::YearBetween {
+			from: 2016,
+			to: 2016,
+		},
+		Rule::NotPlayedInDays { days: 30 },
+	];
+	let request = protocol::save_smart_playlist(
+		TEST_PLAYLIST_NAME,
+		dto::SaveSmartPlaylistInput {
+			rules: rules.clone(),
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::list_smart_playlists();
+	let response = service.fetch_json::<_, Vec<dto::SmartPlaylist>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.body(),
+		&vec![dto::SmartPlaylist {
+			name: TEST_PLAYLIST_NAME.to_owned(),
+			rules
+		}]
+	);
+
+	let request = protocol::read_smart_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch_json::<_, Vec<index::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let songs = response.body();
+	assert!(!songs.is_empty());
+	assert!(songs.iter().all(|s| s.year == Some(2016)));
+
+	let request = protocol::delete_smart_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::read_smart_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn smart_playlists_only_contain_songs_from_visible_mounts() {
+	let config = restricted_mounts_config();
+	let mut service = ServiceType::new_with_config(&test_name!(), &config);
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::save_smart_playlist(
+		TEST_PLAYLIST_NAME,
+		dto::SaveSmartPlaylistInput {
+			rules: vec![Rule::NotPlayedInDays { days: 30 }],
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::read_smart_playlist(TEST_PLAYLIST_NAME);
+	let response = service.fetch_json::<_, Vec<index::Song>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let songs = response.body();
+	assert!(!songs.is_empty());
+	assert!(songs.iter().all(|s| s.path.starts_with("Tobokegao")));
+}
@@ -0,0 +1,68 @@
+use http::StatusCode;
+
+use crate::service::dto;
+use crate::service::test::{protocol, ServiceType, TestService};
+use crate::test_name;
+
+#[test]
+fn changes_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::changes(None);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn changes_rejects_invalid_token() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::changes(Some("not-a-revision"));
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn changes_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login_admin();
+	service.index();
+	service.login();
+
+	let request = protocol::changes(None);
+	let response = service.fetch_json::<_, dto::Changes>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let changes = response.into_body();
+	assert_eq!(changes.songs.added.len(), 13);
+	assert!(changes.songs.modified.is_empty());
+	assert!(changes.songs.removed.is_empty());
+	let song_path = changes.songs.added[0].song.path.clone();
+
+	let request = protocol::save_playlist(
+		"my_playlist",
+		dto::SavePlaylistInput {
+			tracks: vec![song_path],
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::changes(Some(&changes.revision));
+	let response = service.fetch_json::<_, dto::Changes>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let changes = response.into_body();
+	assert!(changes.songs.added.is_empty());
+	assert_eq!(changes.playlists.added, vec!["my_playlist".to_owned()]);
+
+	let request = protocol::delete_playlist("my_playlist");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::changes(Some(&changes.revision));
+	let response = service.fetch_json::<_, dto::Changes>(&request);
+	let changes = response.into_body();
+	assert!(changes.playlists.added.is_empty());
+	assert_eq!(changes.playlists.removed, vec!["my_playlist".to_owned()]);
+}
@@ -1,7 +1,8 @@
+use headers::{self, HeaderMapExt};
 use http::StatusCode;
 use std::default::Default;
 
-use crate::app::user;
+use crate::app::{transcode, user};
 use crate::service::dto;
 use crate::service::test::{constants::*, protocol, ServiceType, TestService};
 use crate::test_name;
@@ -141,6 +142,81 @@ fn delete_user_cannot_delete_self() {
 	assert_eq!(response.status(), StatusCode::CONFLICT);
 }
 
+#[test]
+fn change_password_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	let request = protocol::change_password(TEST_USERNAME, dto::PasswordChange::default());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn change_password_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::change_password(
+		TEST_USERNAME,
+		dto::PasswordChange {
+			old_password: Some(TEST_PASSWORD.into()),
+			new_password: "new_password".into(),
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::login(TEST_USERNAME, "new_password");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[test]
+fn change_password_requires_old_password() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::change_password(
+		TEST_USERNAME,
+		dto::PasswordChange {
+			old_password: Some("garbage".into()),
+			new_password: "new_password".into(),
+		},
+	);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn change_password_of_other_user_requires_admin() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	let request = protocol::change_password(
+		TEST_USERNAME_ADMIN,
+		dto::PasswordChange {
+			old_password: None,
+			new_password: "new_password".into(),
+		},
+	);
+
+	service.login();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+	let request = protocol::change_password(
+		TEST_USERNAME,
+		dto::PasswordChange {
+			old_password: None,
+			new_password: "new_password".into(),
+		},
+	);
+	service.login_admin();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
+
 #[test]
 fn get_preferences_requires_auth() {
 	let mut service = ServiceType::new(&test_name!());
@@ -178,3 +254,100 @@ fn put_preferences_golden_path() {
 	let response = service.fetch(&request);
 	assert_eq!(response.status(), StatusCode::OK);
 }
+
+#[test]
+fn list_clients_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::list_clients();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn put_client_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::put_client("phone", transcode::Profile::default());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn clients_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let transcoding = transcode::Profile {
+		format: Some(transcode::Format::Opus),
+		max_bitrate: Some(96),
+		normalization: Some(transcode::Normalization::Album),
+	};
+	let request = protocol::put_client("phone", transcoding.clone());
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::list_clients();
+	let response = service.fetch_json::<_, Vec<user::Client>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	assert_eq!(
+		response.into_body(),
+		vec![user::Client {
+			name: "phone".to_owned(),
+			transcoding,
+		}]
+	);
+
+	let request = protocol::delete_client("phone");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = protocol::delete_client("phone");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn create_api_key_requires_auth() {
+	let mut service = ServiceType::new(&test_name!());
+	let request = protocol::create_api_key("backup");
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[test]
+fn api_keys_golden_path() {
+	let mut service = ServiceType::new(&test_name!());
+	service.complete_initial_setup();
+	service.login();
+
+	let request = protocol::create_api_key("backup");
+	let response = service.fetch_json::<_, dto::CreatedApiKey>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let created = response.into_body();
+	assert_eq!(created.name, "backup");
+
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::CONFLICT);
+
+	let request = protocol::list_api_keys();
+	let response = service.fetch_json::<_, Vec<user::ApiKey>>(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+	let api_keys = response.into_body();
+	assert_eq!(api_keys.len(), 1);
+	assert_eq!(api_keys[0].name, "backup");
+
+	service.logout();
+	let mut request = protocol::random();
+	let bearer = headers::Authorization::bearer(&created.key).unwrap();
+	request.headers_mut().typed_insert(bearer);
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	service.login();
+	let response = service.fetch(&protocol::delete_api_key("backup"));
+	assert_eq!(response.status(), StatusCode::OK);
+	service.logout();
+
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -1,4 +1,4 @@
-use http::StatusCode;
+use http::{Method, Request, StatusCode};
 
 use crate::service::test::{protocol, ServiceType, TestService};
 use crate::test_name;
@@ -10,3 +10,28 @@ fn serves_web_client() {
 	let response = service.fetch_bytes(&request);
 	assert_eq!(response.status(), StatusCode::OK);
 }
+
+#[test]
+fn serves_everything_under_url_base() {
+	let mut service = ServiceType::new_with_config(&test_name!(), "url_base = '/polaris/'");
+
+	let request = protocol::version();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+	let request = Request::builder()
+		.method(Method::GET)
+		.uri("/polaris/api/version")
+		.body(())
+		.unwrap();
+	let response = service.fetch(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let request = Request::builder()
+		.method(Method::GET)
+		.uri("/polaris/")
+		.body(())
+		.unwrap();
+	let response = service.fetch_bytes(&request);
+	assert_eq!(response.status(), StatusCode::OK);
+}
@@ -1,10 +1,9 @@
-use log::info;
-use std::thread;
-use std::time;
+use log::{error, info};
+use std::thread::JoinHandle;
 
-pub fn run() {
+pub fn run(server: JoinHandle<()>) {
 	info!("Starting up UI (headless)");
-	loop {
-		thread::sleep(time::Duration::from_secs(10));
+	if let Err(e) = server.join() {
+		error!("Error joining on server thread: {:?}", e);
 	}
 }
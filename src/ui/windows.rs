@@ -36,7 +36,7 @@ impl SystemTray {
 	}
 }
 
-pub fn run() {
+pub fn run(_server: std::thread::JoinHandle<()>) {
 	info!("Starting up UI (Windows system tray)");
 	nwg::init().expect("Failed to init Native Windows GUI");
 	let _ui = SystemTray::build_ui(Default::default()).expect("Failed to build tray UI");
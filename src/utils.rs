@@ -1,4 +1,10 @@
+use std::fs::{self, File};
+use std::io;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 #[macro_export]
 macro_rules! match_ignore_case {
@@ -44,6 +50,7 @@ pub fn get_audio_format(path: &Path) -> Option<AudioFormat> {
 		"mp3" => Some(AudioFormat::MP3),
 		"m4a" => Some(AudioFormat::MP4),
 		"mpc" => Some(AudioFormat::MPC),
+		"oga" => Some(AudioFormat::OGG),
 		"ogg" => Some(AudioFormat::OGG),
 		"opus" => Some(AudioFormat::OPUS),
 		"wav" => Some(AudioFormat::WAVE),
@@ -52,6 +59,171 @@ pub fn get_audio_format(path: &Path) -> Option<AudioFormat> {
 	}
 }
 
+/// Text compared by searches: composed, lowercase and optionally without accents, so that
+/// `bjork` finds `Björk`.
+pub fn get_search_key(text: &str, fold_accents: bool) -> String {
+	if !fold_accents {
+		return text.nfc().collect::<String>().to_lowercase();
+	}
+	let mut key = String::with_capacity(text.len());
+	for c in text.nfd().filter(|c| !is_combining_mark(*c)) {
+		for c in c.to_lowercase() {
+			// Letters which are not made of a base letter and an accent
+			match c {
+				'æ' => key.push_str("ae"),
+				'œ' => key.push_str("oe"),
+				'ß' => key.push_str("ss"),
+				'þ' => key.push_str("th"),
+				'ø' => key.push('o'),
+				'đ' | 'ð' => key.push('d'),
+				'ł' => key.push('l'),
+				'ı' => key.push('i'),
+				c => key.push(c),
+			}
+		}
+	}
+	key
+}
+
+// Modification dates track when cached files were last used, so eviction can drop the stalest ones
+pub fn mark_as_used(path: &Path) {
+	if let Ok(file) = File::options().write(true).open(path) {
+		file.set_modified(SystemTime::now()).ok();
+	}
+}
+
+/// Keeps a cache directory within a maximum size. The size of the directory is tracked as entries
+/// are added to it, so that it is only scanned again once it goes over the limit.
+#[derive(Clone, Default)]
+pub struct CacheLimit {
+	size: Arc<Mutex<Option<u64>>>,
+}
+
+impl CacheLimit {
+	/// Accounts for `entry` having been added to the cache, evicting other entries if needed.
+	pub fn add(&self, dir_path: &Path, max_size: u64, entry: &Path) -> io::Result<()> {
+		let mut size = self.size.lock().unwrap();
+		if let Some(size) = size.as_mut() {
+			*size += read_cache_entry(entry)?.map_or(0, |(_, s)| s);
+			if *size <= max_size {
+				return Ok(());
+			}
+		}
+		*size = None;
+		*size = Some(evict_least_recently_used(dir_path, max_size, entry)?);
+		Ok(())
+	}
+}
+
+/// Deletes least recently used entries of a cache directory until it fits within `max_size` bytes,
+/// and returns the size of what is left.
+/// Directories count as a single entry, last used when the most recent of their files was.
+/// Temporary `.part` entries which are still being written and `keep` are never deleted.
+pub fn evict_least_recently_used(dir_path: &Path, max_size: u64, keep: &Path) -> io::Result<u64> {
+	let mut entries = Vec::new();
+	for entry in fs::read_dir(dir_path)? {
+		let path = entry?.path();
+		if path.extension().is_some_and(|e| e == "part") {
+			continue;
+		}
+		if let Some((modified, size)) = read_cache_entry(&path)? {
+			entries.push((modified, size, path));
+		}
+	}
+
+	let mut total_size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+	entries.sort();
+	for (_, size, path) in entries {
+		if total_size <= max_size {
+			break;
+		}
+		if path == keep {
+			continue;
+		}
+		let removed = match path.is_dir() {
+			true => fs::remove_dir_all(&path),
+			false => fs::remove_file(&path),
+		};
+		// Entries may be evicted concurrently by another process
+		skip_not_found(removed)?;
+		total_size -= size;
+	}
+
+	Ok(total_size)
+}
+
+// Last use and size of a cache entry, or nothing if it was deleted while being read
+fn read_cache_entry(path: &Path) -> io::Result<Option<(SystemTime, u64)>> {
+	let Some(metadata) = skip_not_found(fs::symlink_metadata(path))? else {
+		return Ok(None);
+	};
+	if metadata.is_file() {
+		return Ok(Some((metadata.modified()?, metadata.len())));
+	}
+	if !metadata.is_dir() {
+		return Ok(None);
+	}
+	let Some(files) = skip_not_found(fs::read_dir(path))? else {
+		return Ok(None);
+	};
+	let mut modified = metadata.modified()?;
+	let mut size = 0;
+	for file in files {
+		if let Some(metadata) = skip_not_found(file.and_then(|f| f.metadata()))? {
+			modified = modified.max(metadata.modified()?);
+			size += metadata.len();
+		}
+	}
+	Ok(Some((modified, size)))
+}
+
+fn skip_not_found<T>(result: io::Result<T>) -> io::Result<Option<T>> {
+	match result {
+		Ok(value) => Ok(Some(value)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+#[test]
+fn cache_limit_only_evicts_once_over_limit() {
+	let dir_path = crate::test::prepare_test_directory(crate::test_name!());
+	let write = |name: &str| {
+		let path = dir_path.join(name);
+		fs::write(&path, [0; 100]).unwrap();
+		path
+	};
+
+	let cache_limit = CacheLimit::default();
+	let first = write("first.jpg");
+	cache_limit.add(&dir_path, 250, &first).unwrap();
+	let second = write("second.jpg");
+	cache_limit.add(&dir_path, 250, &second).unwrap();
+	assert!(first.exists());
+	assert!(second.exists());
+
+	let third = write("third.jpg");
+	cache_limit.add(&dir_path, 250, &third).unwrap();
+	assert_eq!(fs::read_dir(&dir_path).unwrap().count(), 2);
+	assert!(third.exists());
+}
+
+#[test]
+fn cache_entries_can_disappear() {
+	let path = crate::test::prepare_test_directory(crate::test_name!()).join("deleted.jpg");
+	assert!(read_cache_entry(&path).unwrap().is_none());
+}
+
+#[test]
+fn can_compute_search_keys() {
+	assert_eq!(get_search_key("Björk", true), "bjork");
+	assert_eq!(get_search_key("Bjo\u{308}rk", true), "bjork");
+	assert_eq!(get_search_key("Sigur Rós", true), "sigur ros");
+	assert_eq!(get_search_key("Mø", true), "mo");
+	assert_eq!(get_search_key("Bjo\u{308}rk", false), "björk");
+	assert_eq!(get_search_key("BJÖRK", false), "björk");
+}
+
 #[test]
 fn can_guess_audio_format() {
 	assert_eq!(get_audio_format(Path::new("animals/🐷/my🐖file.jpg")), None);
@@ -67,6 +239,10 @@ fn can_guess_audio_format() {
 		get_audio_format(Path::new("animals/🐷/my🐖file.flac")),
 		Some(AudioFormat::FLAC)
 	);
+	assert_eq!(
+		get_audio_format(Path::new("animals/🐷/my🐖file.oga")),
+		Some(AudioFormat::OGG)
+	);
 	assert_eq!(
 		get_audio_format(Path::new("animals/🐷/my🐖file.wav")),
 		Some(AudioFormat::WAVE)
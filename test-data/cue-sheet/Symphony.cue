@@ -0,0 +1,15 @@
+REM GENRE Classical
+REM DATE 1963
+PERFORMER "Berliner Philharmoniker"
+TITLE "Symphony No. 5"
+FILE "Symphony.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "Allegro con brio"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Andante con moto"
+    INDEX 00 07:30:00
+    INDEX 01 07:31:50
+  TRACK 03 AUDIO
+    TITLE "Allegro"
+    INDEX 01 17:20:00